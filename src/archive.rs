@@ -0,0 +1,724 @@
+// fs_archive and fs_extract: zip and tar archives, written and unpacked a file at a time
+
+use anyhow::{Context as _, Result};
+use chrono::{Datelike, Timelike};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::error::PolyError;
+use crate::modules::filesystem::tree_size;
+use crate::progress;
+
+/// Archive formats fs_archive writes and fs_extract reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "zip" => Ok(Self::Zip),
+            "tar" => Ok(Self::Tar),
+            "tar.gz" | "tgz" => Ok(Self::TarGz),
+            "tar.zst" | "tzst" => Ok(Self::TarZst),
+            other => Err(PolyError::InvalidArguments(format!(
+                "Invalid format '{}' (expected zip, tar, tar.gz or tar.zst)",
+                other
+            ))
+            .into()),
+        }
+    }
+
+    /// The format an archive's name says it has
+    pub fn from_name(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        [
+            (".zip", Self::Zip),
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+        ]
+        .into_iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map(|(_, format)| format)
+    }
+
+    /// The format an archive's first bytes say it has
+    pub fn sniff(path: &Path) -> Result<Option<Self>> {
+        let mut head = [0u8; 512];
+        let mut file = fs::File::open(path).with_context(|| format!("Failed to open archive: {}", path.display()))?;
+        let mut read = 0;
+        while read < head.len() {
+            match file.read(&mut head[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        let head = &head[..read];
+        Ok(if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::TarZst)
+        } else if head.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            None
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// Which paths inside an archive to take, from include and exclude globs. A
+/// pattern with a '/' matches the whole path, one without matches any single
+/// name in it, so "node_modules" leaves out every node_modules directory.
+pub struct Filter {
+    include: Vec<(globset::GlobMatcher, bool)>,
+    exclude: Vec<(globset::GlobMatcher, bool)>,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<(globset::GlobMatcher, bool)>> {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    let glob = globset::GlobBuilder::new(pattern.trim_end_matches('/'))
+                        .literal_separator(true)
+                        .build()
+                        .map_err(|e| PolyError::InvalidArguments(format!("Invalid pattern '{}': {}", pattern, e)))?;
+                    Ok((glob.compile_matcher(), pattern.contains('/')))
+                })
+                .collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Whether `path` (relative, '/'-separated) is left out by an exclude pattern
+    pub fn excludes(&self, path: &str) -> bool {
+        matches(&self.exclude, path)
+    }
+
+    /// Whether only some files are taken, so directories come in only as their parents
+    pub fn is_selective(&self) -> bool {
+        !self.include.is_empty()
+    }
+
+    /// Whether the file at `path` is taken: no include patterns, or one matches
+    pub fn takes(&self, path: &str) -> bool {
+        !self.excludes(path) && (self.include.is_empty() || matches(&self.include, path))
+    }
+}
+
+/// What went into an archive, or came out of one
+#[derive(Debug, Default, Serialize)]
+pub struct Tally {
+    pub files: usize,
+    pub directories: usize,
+    pub symlinks: usize,
+    /// Uncompressed size of the files
+    pub total_size: u64,
+    /// Entries left out by the include and exclude patterns
+    pub excluded: usize,
+}
+
+/// One entry of an archive, as fs_extract lists it with `dry_run`
+#[derive(Debug, Serialize)]
+pub struct Listed {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub size: u64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+}
+
+/// What fs_extract did, or with `dry_run` would do
+#[derive(Debug, Default, Serialize)]
+pub struct Extracted {
+    #[serde(flatten)]
+    pub tally: Tally,
+    /// Files not written because something is already there (without `overwrite`)
+    pub skipped: Vec<String>,
+    /// Entries that would land outside the destination, and other entries refused
+    pub rejected: Vec<Rejected>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entries: Option<Vec<Listed>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Rejected {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Archive `source`, a file or a directory, as `archive`. Entries are named from
+/// the source's own name down, and a directory's files are streamed in one at a
+/// time. The archive is written next to its final name and renamed into place, so
+/// a failure leaves no partial archive behind.
+pub fn create(source: &Path, archive: &Path, format: Format, filter: &Filter, password: Option<&str>) -> Result<Tally> {
+    if password.is_some() && format != Format::Zip {
+        anyhow::bail!("Only zip archives can be password-protected");
+    }
+    let root = fs::canonicalize(source)
+        .ok()
+        .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "archive".to_string());
+    let temp = archive.with_file_name(format!(
+        ".{}.partial-{}",
+        archive.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+    let file = fs::File::create(&temp).with_context(|| format!("Failed to create archive: {}", archive.display()))?;
+
+    let written = (|| -> Result<Tally> {
+        let mut sources = Sources::new(source, &root, filter);
+        match format {
+            Format::Zip => write_zip(file, &mut sources, password),
+            Format::Tar => write_tar(file, &mut sources).map(|(_, tally)| tally),
+            Format::TarGz => {
+                let (encoder, tally) = write_tar(flate2::write::GzEncoder::new(file, flate2::Compression::default()), &mut sources)?;
+                encoder.finish()?;
+                Ok(tally)
+            }
+            Format::TarZst => {
+                let (encoder, tally) = write_tar(zstd::Encoder::new(file, 0)?, &mut sources)?;
+                encoder.finish()?;
+                Ok(tally)
+            }
+        }
+    })();
+    match written {
+        Ok(tally) => {
+            fs::rename(&temp, archive).with_context(|| format!("Failed to create archive: {}", archive.display()))?;
+            Ok(tally)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Unpack `archive` into `destination`. Entries that would land outside it (absolute
+/// paths, `..`, a path through a symlink, or a symlink pointing out of it) are
+/// rejected rather than written. Existing files are left alone unless `overwrite`.
+pub fn extract(
+    archive: &Path,
+    destination: &Path,
+    format: Format,
+    filter: &Filter,
+    password: Option<&str>,
+    overwrite: bool,
+    dry_run: bool,
+) -> Result<Extracted> {
+    let mut unpack = Unpack {
+        destination,
+        filter,
+        overwrite,
+        dry_run,
+        extracted: Extracted {
+            entries: dry_run.then(Vec::new),
+            ..Default::default()
+        },
+    };
+    if !dry_run {
+        fs::create_dir_all(destination).with_context(|| format!("Failed to create destination: {}", destination.display()))?;
+    }
+    let file = fs::File::open(archive).with_context(|| format!("Failed to open archive: {}", archive.display()))?;
+    match format {
+        Format::Zip => unpack.zip(file, password)?,
+        _ if password.is_some() => anyhow::bail!("Only zip archives can be password-protected"),
+        Format::Tar => unpack.tar(file)?,
+        Format::TarGz => unpack.tar(flate2::read::GzDecoder::new(file))?,
+        Format::TarZst => unpack.tar(zstd::Decoder::new(file)?)?,
+    }
+    Ok(unpack.extracted)
+}
+
+// ── Creating ────────────────────────────────────────────────────────────
+
+/// The entries to archive, in name order, with excluded directories not entered
+struct Sources<'a> {
+    source: &'a Path,
+    root: &'a str,
+    filter: &'a Filter,
+    tally: Tally,
+    // Bytes written so far out of the total, when the client is listening for progress
+    progress: Option<(u64, u64)>,
+}
+
+/// An entry to archive: its name in the archive and where it is on disk
+struct Source {
+    name: String,
+    path: PathBuf,
+    metadata: fs::Metadata,
+}
+
+impl<'a> Sources<'a> {
+    fn new(source: &'a Path, root: &'a str, filter: &'a Filter) -> Self {
+        Self {
+            source,
+            root,
+            filter,
+            tally: Tally::default(),
+            progress: progress::is_streaming().then(|| (0, tree_size(source))),
+        }
+    }
+
+    /// Call `add` for each entry to archive
+    fn each(&mut self, mut add: impl FnMut(&Source) -> Result<()>) -> Result<()> {
+        let filter = self.filter;
+        let mut walk = WalkDir::new(self.source).follow_links(false).sort_by_file_name().into_iter();
+        while let Some(entry) = walk.next() {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(self.source)?.to_string_lossy().replace('\\', "/");
+            let metadata = entry.path().symlink_metadata()?;
+            let is_dir = metadata.is_dir();
+            let path = if relative.is_empty() { entry.file_name().to_string_lossy().into_owned() } else { relative };
+            let skip = if is_dir && entry.depth() > 0 { filter.excludes(&path) } else if is_dir { false } else { !filter.takes(&path) };
+            if skip {
+                self.tally.excluded += 1;
+                if is_dir {
+                    walk.skip_current_dir();
+                }
+                continue;
+            }
+            if is_dir && filter.is_selective() {
+                continue;
+            }
+
+            let name = match entry.depth() {
+                0 if !is_dir => path,
+                0 => self.root.to_string(),
+                _ => format!("{}/{}", self.root, path),
+            };
+            let source = Source { name, path: entry.path().to_path_buf(), metadata };
+            add(&source)?;
+
+            if source.metadata.file_type().is_symlink() {
+                self.tally.symlinks += 1;
+            } else if is_dir {
+                self.tally.directories += 1;
+            } else {
+                self.tally.files += 1;
+                self.tally.total_size += source.metadata.len();
+                if let Some((done, total)) = &mut self.progress {
+                    *done += source.metadata.len();
+                    progress::report(*done, Some((*total).max(*done)), &format!("Archived {}", source.path.display()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_zip(file: fs::File, sources: &mut Sources, password: Option<&str>) -> Result<Tally> {
+    let mut zip = zip::ZipWriter::new(file);
+    let mut base = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    if let Some(password) = password {
+        base = base.with_aes_encryption(zip::AesMode::Aes256, password);
+    }
+
+    sources.each(|source| {
+        let mut options = base.large_file(source.metadata.len() >= u32::MAX as u64);
+        if let Some(mode) = unix_mode(&source.metadata) {
+            options = options.unix_permissions(mode);
+        }
+        if let Some(modified) = source.metadata.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(modified);
+        }
+
+        if source.metadata.file_type().is_symlink() {
+            let target = fs::read_link(&source.path)?;
+            zip.add_symlink(&source.name, target.to_string_lossy(), options)?;
+        } else if source.metadata.is_dir() {
+            zip.add_directory(&source.name, options)?;
+        } else {
+            zip.start_file(&source.name, options)?;
+            let mut file = fs::File::open(&source.path).with_context(|| format!("Failed to read {}", source.path.display()))?;
+            io::copy(&mut file, &mut zip)?;
+        }
+        Ok(())
+    })?;
+    zip.finish()?;
+    Ok(std::mem::take(&mut sources.tally))
+}
+
+/// Write a tar stream into `writer`, handing the writer back so a compressor can be finished
+fn write_tar<W: Write>(writer: W, sources: &mut Sources) -> Result<(W, Tally)> {
+    let mut tar = tar::Builder::new(writer);
+    tar.follow_symlinks(false);
+    sources.each(|source| {
+        tar.append_path_with_name(&source.path, &source.name)
+            .with_context(|| format!("Failed to archive {}", source.path.display()))
+    })?;
+    Ok((tar.into_inner()?, std::mem::take(&mut sources.tally)))
+}
+
+// ── Extracting ──────────────────────────────────────────────────────────
+
+struct Unpack<'a> {
+    destination: &'a Path,
+    filter: &'a Filter,
+    overwrite: bool,
+    dry_run: bool,
+    extracted: Extracted,
+}
+
+/// What an entry is, for the checks shared by both formats
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+    Symlink,
+    HardLink,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "dir",
+            Kind::Symlink => "symlink",
+            Kind::HardLink => "hardlink",
+        }
+    }
+}
+
+impl Unpack<'_> {
+    fn zip(&mut self, file: fs::File, password: Option<&str>) -> Result<()> {
+        let mut zip = zip::ZipArchive::new(io::BufReader::new(file)).context("Not a readable zip archive")?;
+        let mut progress = progress::is_streaming().then(|| {
+            let total = (0..zip.len()).filter_map(|i| zip.by_index_raw(i).ok().map(|entry| entry.size())).sum::<u64>();
+            (0, total)
+        });
+
+        if password.is_none() && !self.dry_run {
+            for i in 0..zip.len() {
+                let entry = zip.by_index_raw(i)?;
+                if entry.encrypted() {
+                    anyhow::bail!("{} is password-protected; pass 'password'", entry.name());
+                }
+            }
+        }
+
+        for i in 0..zip.len() {
+            let (name, kind, size, encrypted) = {
+                let entry = zip.by_index_raw(i)?;
+                let kind = if entry.is_symlink() {
+                    Kind::Symlink
+                } else if entry.is_dir() {
+                    Kind::Dir
+                } else {
+                    Kind::File
+                };
+                (entry.name().to_string(), kind, entry.size(), entry.encrypted())
+            };
+            let Some(relative) = self.admit(&name, kind, size, encrypted) else {
+                continue;
+            };
+
+            let mut entry = match password.filter(|_| encrypted) {
+                Some(password) => zip.by_index_decrypt(i, password.as_bytes()),
+                None => zip.by_index(i),
+            }
+            .map_err(|e| match e {
+                zip::result::ZipError::InvalidPassword => anyhow::anyhow!("Wrong password for {}", name),
+                e => anyhow::Error::new(e).context(format!("Failed to read {}", name)),
+            })?;
+            let out = self.destination.join(&relative);
+            match kind {
+                Kind::Dir => fs::create_dir_all(&out)?,
+                Kind::Symlink => {
+                    let mut target = String::new();
+                    entry.read_to_string(&mut target)?;
+                    if let Err(reason) = self.check_link_target(&relative, Path::new(&target)) {
+                        self.reject(&name, reason);
+                        continue;
+                    }
+                    create_parent(&out)?;
+                    replace_existing(&out)?;
+                    make_symlink(Path::new(&target), &out)?;
+                }
+                _ => {
+                    create_parent(&out)?;
+                    replace_existing(&out)?;
+                    let mut file = fs::File::create(&out).with_context(|| format!("Failed to create {}", out.display()))?;
+                    io::copy(&mut entry, &mut file).with_context(|| format!("Failed to extract {}", name))?;
+                    if let Some(mode) = entry.unix_mode() {
+                        set_mode(&out, mode);
+                    }
+                }
+            }
+            self.count(kind, size);
+            if let Some((done, total)) = &mut progress {
+                *done += size;
+                progress::report(*done, Some((*total).max(*done)), &format!("Extracted {}", name));
+            }
+        }
+        Ok(())
+    }
+
+    fn tar<R: Read>(&mut self, reader: R) -> Result<()> {
+        let mut tar = tar::Archive::new(reader);
+        tar.set_preserve_permissions(false);
+        let streaming = progress::is_streaming();
+        let mut done = 0;
+        for entry in tar.entries().context("Not a readable tar archive")? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let size = entry.size();
+            let entry_type = entry.header().entry_type();
+            let kind = match entry_type {
+                tar::EntryType::Regular | tar::EntryType::Continuous => Kind::File,
+                tar::EntryType::Directory => Kind::Dir,
+                tar::EntryType::Symlink => Kind::Symlink,
+                tar::EntryType::Link => Kind::HardLink,
+                // The tar crate reads these as part of the entries they describe
+                tar::EntryType::XGlobalHeader => continue,
+                other => {
+                    self.reject(&name, format!("Unsupported entry type {:?}", other));
+                    continue;
+                }
+            };
+            let Some(relative) = self.admit(&name, kind, size, false) else {
+                continue;
+            };
+
+            if matches!(kind, Kind::Symlink | Kind::HardLink) {
+                let target = entry.link_name()?.map(|target| target.into_owned()).unwrap_or_default();
+                let checked = match kind {
+                    Kind::Symlink => self.check_link_target(&relative, &target),
+                    _ => safe_path(&target.to_string_lossy())
+                        .and_then(|target| self.check_through_symlink(&target).map(|_| ())),
+                };
+                if let Err(reason) = checked {
+                    self.reject(&name, reason);
+                    continue;
+                }
+            }
+            let out = self.destination.join(&relative);
+            if kind != Kind::Dir {
+                create_parent(&out)?;
+                replace_existing(&out)?;
+            }
+            // unpack_in checks once more that nothing lands outside the destination
+            if !entry.unpack_in(self.destination).with_context(|| format!("Failed to extract {}", name))? {
+                self.reject(&name, "Path leaves the destination".to_string());
+                continue;
+            }
+            if kind == Kind::File {
+                if let Ok(mode) = entry.header().mode() {
+                    set_mode(&out, mode);
+                }
+            }
+            self.count(kind, size);
+            if streaming {
+                done += size;
+                progress::report(done, None, &format!("Extracted {}", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where an entry goes, relative to the destination, or `None` when it is
+    /// left out, skipped, rejected, or only listed
+    fn admit(&mut self, name: &str, kind: Kind, size: u64, encrypted: bool) -> Option<PathBuf> {
+        let relative = match safe_path(name).and_then(|relative| self.check_through_symlink(&relative)) {
+            Ok(relative) => relative,
+            Err(reason) => {
+                self.reject(name, reason);
+                return None;
+            }
+        };
+        let path = name.trim_end_matches('/');
+        let taken = match kind {
+            Kind::Dir => !self.filter.excludes(path),
+            _ => self.filter.takes(path),
+        };
+        if !taken {
+            self.extracted.tally.excluded += 1;
+            return None;
+        }
+        if let Some(entries) = &mut self.extracted.entries {
+            entries.push(Listed { path: path.to_string(), kind: kind.name(), size, encrypted });
+            self.count(kind, size);
+            return None;
+        }
+        let out = self.destination.join(&relative);
+        if kind != Kind::Dir && !self.overwrite && out.symlink_metadata().is_ok() {
+            self.extracted.skipped.push(path.to_string());
+            return None;
+        }
+        Some(relative)
+    }
+
+    /// An entry must not be written through a directory that is really a symlink,
+    /// which an earlier entry (or anything else) could have pointed anywhere
+    fn check_through_symlink(&self, relative: &Path) -> Result<PathBuf, String> {
+        if self.dry_run {
+            return Ok(relative.to_path_buf());
+        }
+        let mut path = self.destination.to_path_buf();
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            path.push(component);
+            if path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(format!("Path goes through the symlink {}", path.display()));
+            }
+        }
+        Ok(relative.to_path_buf())
+    }
+
+    /// A symlink at `relative` may only point somewhere inside the destination
+    fn check_link_target(&self, relative: &Path, target: &Path) -> Result<(), String> {
+        if target.has_root() || target.components().any(|c| matches!(c, Component::Prefix(_))) {
+            return Err(format!("Symlink points to the absolute path {}", target.display()));
+        }
+        let mut depth = relative.components().count() as isize - 1;
+        for component in target.components() {
+            match component {
+                Component::ParentDir => depth -= 1,
+                Component::Normal(_) => depth += 1,
+                _ => {}
+            }
+            if depth < 0 {
+                return Err(format!("Symlink points outside the destination ({})", target.display()));
+            }
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, name: &str, reason: String) {
+        self.extracted.rejected.push(Rejected { path: name.to_string(), reason });
+    }
+
+    fn count(&mut self, kind: Kind, size: u64) {
+        let tally = &mut self.extracted.tally;
+        match kind {
+            Kind::Dir => tally.directories += 1,
+            Kind::Symlink => tally.symlinks += 1,
+            Kind::File | Kind::HardLink => {
+                tally.files += 1;
+                tally.total_size += size;
+            }
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn matches(patterns: &[(globset::GlobMatcher, bool)], path: &str) -> bool {
+    patterns.iter().any(|(matcher, whole_path)| {
+        if *whole_path {
+            matcher.is_match(path)
+        } else {
+            path.split('/').any(|name| matcher.is_match(name))
+        }
+    })
+}
+
+/// An entry's name as a path relative to the destination, or why it cannot be one
+fn safe_path(name: &str) -> Result<PathBuf, String> {
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') {
+        return Err("Absolute path".to_string());
+    }
+    let mut relative = PathBuf::new();
+    for (i, part) in name.split('/').enumerate() {
+        match part {
+            "" | "." => {}
+            ".." => return Err("Path leaves the destination (..)".to_string()),
+            // A Windows drive such as "C:"
+            _ if i == 0 && part.ends_with(':') => return Err("Absolute path".to_string()),
+            _ => relative.push(part),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Err("Empty path".to_string());
+    }
+    Ok(relative)
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// Remove a file or symlink in the way, so writing never follows an old symlink
+fn replace_existing(path: &Path) -> Result<()> {
+    match path.symlink_metadata() {
+        Ok(metadata) if !metadata.is_dir() => {
+            fs::remove_file(path).with_context(|| format!("Failed to replace {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link).with_context(|| format!("Failed to create symlink {}", link.display()))
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &Path, link: &Path) -> Result<()> {
+    anyhow::bail!("Symlinks are only extracted on Unix: {}", link.display())
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Permission bits from an archive, without setuid, setgid or sticky
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777));
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) {}
+
+/// A modification time as zip stores it: local time, to the even second, 1980 to 2107
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let local = chrono::DateTime::<chrono::Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(local.year()).ok()?,
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .ok()
+}
@@ -0,0 +1,835 @@
+// Audit log of tool calls, appended as JSON lines, to a SQLite database, or to [storage]
+
+use crate::config::AuditConfig;
+use crate::storage::{keyspaces, Storage};
+use anyhow::{Context as _, Result};
+use hmac::{Hmac, Mac};
+use rusqlite::OptionalExtension;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// Argument names redacted in every audit entry, on top of `[audit] redact`.
+/// `tool.arg` limits a rule to one tool.
+pub const DEFAULT_REDACT: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "authorization",
+    "cookie",
+    "credential",
+    "credentials",
+    "private_key",
+    "crypto_hmac.key",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// Longer arrays are cut down to this many items in audit entries
+const MAX_ARRAY_ITEMS: usize = 50;
+
+/// Signing keys shorter than this are refused
+const MIN_KEY_BYTES: usize = 32;
+
+/// audit_verify describes this many problems and only counts the rest
+const MAX_PROBLEMS: usize = 20;
+
+tokio::task_local! {
+    static CALLER: Caller;
+}
+
+/// Who is making the tool calls on the current task
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Authenticated principal, when the transport has one
+    pub name: Option<String>,
+    /// "stdio", "http" or "ws"; "library" for direct [`crate::PolyMcp::call_tool`] use
+    pub transport: &'static str,
+    /// HTTP session id
+    pub session: Option<String>,
+    /// The caller's tenant, on servers with `[tenants]`
+    pub tenant: Option<String>,
+}
+
+impl Caller {
+    pub fn new(transport: &'static str) -> Self {
+        Self {
+            transport,
+            ..Self::default()
+        }
+    }
+}
+
+/// Run `fut` with tool calls attributed to `caller`
+pub async fn with_caller<F: Future>(caller: Caller, fut: F) -> F::Output {
+    CALLER.scope(caller, fut).await
+}
+
+/// The [`Caller`] of the current task
+pub(crate) fn current_caller() -> Caller {
+    CALLER
+        .try_with(Caller::clone)
+        .unwrap_or_else(|_| Caller::new("library"))
+}
+
+/// How a tool call ended
+pub enum Outcome<'a> {
+    Ok,
+    Error(&'a str),
+    TimedOut,
+    Cancelled,
+}
+
+impl Outcome<'_> {
+    /// The entry's `status`: "ok", "error", "timeout" or "cancelled"
+    pub fn label(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Error(_) => "error",
+            Outcome::TimedOut => "timeout",
+            Outcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Where `[audit]` sends entries
+enum Location {
+    Jsonl(PathBuf),
+    Sqlite(PathBuf),
+    Storage,
+}
+
+impl Location {
+    fn of(config: &AuditConfig) -> Result<Option<Self>> {
+        match (config.format.as_deref(), &config.path) {
+            (Some("storage"), _) => Ok(Some(Location::Storage)),
+            (_, None) => Ok(None),
+            (Some("sqlite"), Some(path)) => Ok(Some(Location::Sqlite(path.clone()))),
+            (Some("jsonl"), Some(path)) => Ok(Some(Location::Jsonl(path.clone()))),
+            (Some(other), _) => {
+                anyhow::bail!("Unknown audit log format '{}' (expected jsonl, sqlite or storage)", other)
+            }
+            (None, Some(path)) => match path.extension().and_then(|e| e.to_str()) {
+                Some("db" | "sqlite" | "sqlite3") => Ok(Some(Location::Sqlite(path.clone()))),
+                _ => Ok(Some(Location::Jsonl(path.clone()))),
+            },
+        }
+    }
+
+    /// Shown where a file path would be
+    fn path(&self) -> PathBuf {
+        match self {
+            Location::Jsonl(path) | Location::Sqlite(path) => path.clone(),
+            Location::Storage => PathBuf::from(format!("[storage] {}", keyspaces::AUDIT)),
+        }
+    }
+}
+
+fn storage_backend(storage: Option<&Arc<dyn Storage>>) -> Result<Arc<dyn Storage>> {
+    storage
+        .cloned()
+        .context("[audit] format = \"storage\" needs a persistent [storage] backend (sqlite or sled)")
+}
+
+enum Sink {
+    // `len` is the file's length after the last write here; when it differs, another
+    // server has appended since, and the chain continues from its last entry. The
+    // file is locked from reading that entry until the new one is appended.
+    Jsonl { file: std::fs::File, path: PathBuf, len: u64 },
+    // Each insert reads the last hash in the same transaction
+    Sqlite(rusqlite::Connection),
+    // Keyed by timestamp and a counter, so entries scan in order
+    Storage(Arc<dyn Storage>, u64),
+}
+
+/// Appends one entry per tool call. Writing happens on a background thread, so
+/// recording never blocks a tool call; a failing write is logged and skipped.
+/// Each entry carries the hash of the one before it (`prev_hash`) and its own
+/// (`hash`), and with a signing key an HMAC-SHA256 `signature` of that hash, so
+/// [`verify`] can tell when entries were changed, removed or inserted.
+pub struct AuditLog {
+    path: PathBuf,
+    sender: mpsc::Sender<Value>,
+    redact: Vec<String>,
+    max_value_chars: usize,
+}
+
+impl AuditLog {
+    /// Open the log configured in `[audit]`, or `None` when auditing is off.
+    /// `format = "storage"` appends to `storage` instead of a file.
+    pub fn open(config: &AuditConfig, storage: Option<&Arc<dyn Storage>>) -> Result<Option<Self>> {
+        let Some(location) = Location::of(config)? else {
+            return Ok(None);
+        };
+        let path = location.path();
+        let mut sink = match &location {
+            Location::Jsonl(path) => open_jsonl(path)?,
+            Location::Sqlite(path) => open_sqlite(path)?,
+            Location::Storage => Sink::Storage(storage_backend(storage)?, 0),
+        };
+        let key = signing_key(config)?;
+        let mut last = last_hash(&sink)?;
+
+        let (sender, receiver) = mpsc::channel::<Value>();
+        let log_path = path.clone();
+        std::thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || {
+                for mut entry in receiver {
+                    match write_entry(&mut sink, &mut entry, last.as_deref(), key.as_deref()) {
+                        Ok(()) => last = entry["hash"].as_str().map(str::to_string),
+                        Err(e) => tracing::error!("Failed to write audit log {}: {:#}", log_path.display(), e),
+                    }
+                }
+            })
+            .context("Failed to start audit log writer")?;
+
+        let redact = DEFAULT_REDACT
+            .iter()
+            .map(|rule| rule.to_string())
+            .chain(config.redact.iter().cloned())
+            .map(|rule| normalize_key(&rule))
+            .collect();
+
+        Ok(Some(Self {
+            path,
+            sender,
+            redact,
+            max_value_chars: config.max_value_chars,
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a finished tool call made by the current task's [`Caller`]
+    pub fn record(
+        &self,
+        tool: &str,
+        module: Option<&str>,
+        args: &Value,
+        request_id: Option<&Value>,
+        duration: Duration,
+        outcome: Outcome,
+    ) {
+        let caller = current_caller();
+        let status = outcome.label();
+        let error = match outcome {
+            Outcome::Error(message) => Some(message),
+            _ => None,
+        };
+
+        let entry = json!({
+            "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "tool": tool,
+            "module": module,
+            "caller": caller.name,
+            "transport": caller.transport,
+            "session": caller.session,
+            "tenant": caller.tenant,
+            "request_id": request_id,
+            "args": self.summarize(tool, args),
+            "duration_ms": duration.as_millis() as u64,
+            "status": status,
+            "error": error,
+        });
+        // The writer thread only stops when the log is dropped
+        let _ = self.sender.send(entry);
+    }
+
+    /// Arguments with sensitive values redacted and long values shortened
+    fn summarize(&self, tool: &str, args: &Value) -> Value {
+        let tool = normalize_key(tool);
+        self.summarize_value(&tool, args)
+    }
+
+    fn summarize_value(&self, tool: &str, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut summary = Map::new();
+                for (key, value) in map {
+                    let value = if self.is_sensitive(tool, key) {
+                        json!(REDACTED)
+                    } else {
+                        self.summarize_value(tool, value)
+                    };
+                    summary.insert(key.clone(), value);
+                }
+                Value::Object(summary)
+            }
+            Value::Array(items) if items.len() > MAX_ARRAY_ITEMS => {
+                let mut summary: Vec<Value> = items
+                    .iter()
+                    .take(MAX_ARRAY_ITEMS)
+                    .map(|item| self.summarize_value(tool, item))
+                    .collect();
+                summary.push(json!(format!("... ({} items)", items.len())));
+                Value::Array(summary)
+            }
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.summarize_value(tool, item)).collect()),
+            Value::String(s) if s.chars().count() > self.max_value_chars => {
+                let head: String = s.chars().take(self.max_value_chars).collect();
+                json!(format!("{}... ({} chars)", head, s.chars().count()))
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// A rule matches whole words of the argument name, so "token" catches
+    /// "access_token" but not "max_tokens"
+    fn is_sensitive(&self, tool: &str, key: &str) -> bool {
+        let key = format!("_{}_", normalize_key(key));
+        self.redact.iter().any(|rule| match rule.split_once('.') {
+            Some((rule_tool, rule_key)) => rule_tool == tool && key.contains(&format!("_{}_", rule_key)),
+            None => key.contains(&format!("_{}_", rule)),
+        })
+    }
+}
+
+/// Check the hash chain (and, with `[audit] signing_key_file`, the signatures) of
+/// the log configured in `[audit]`. Reports the first problems found: entries
+/// whose contents no longer match their hash, entries that do not follow the one
+/// before them because some were removed, inserted or reordered, and entries
+/// with a missing or wrong signature. Entries written before chaining existed
+/// are counted as `unchained`. Removing entries from the end leaves a valid
+/// chain, so compare `last_hash` with a copy kept elsewhere to catch that.
+pub fn verify(config: &AuditConfig, storage: Option<&Arc<dyn Storage>>) -> Result<Value> {
+    let location = Location::of(config)?.context("No audit log is configured ([audit] path or format)")?;
+    let key = signing_key(config)?;
+    let mut verifier = Verifier::new(key.as_deref());
+
+    match &location {
+        Location::Jsonl(path) => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+            for line in std::io::BufReader::new(file).lines() {
+                let line = line.with_context(|| format!("Failed to read audit log {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                verifier.check(serde_json::from_str(&line).map_err(|e| format!("is not valid JSON ({})", e)));
+            }
+        }
+        Location::Sqlite(path) => {
+            let connection = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("Failed to open audit database {}", path.display()))?;
+            let has_hash = has_column(&connection, "hash")?;
+            let mut statement = connection.prepare(&format!(
+                "SELECT timestamp, tool, module, caller, transport, session, tenant, request_id, args,
+                    duration_ms, status, error, {} FROM audit_log ORDER BY id",
+                if has_hash { "prev_hash, hash, signature" } else { "NULL, NULL, NULL" }
+            ))?;
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                verifier.check(sqlite_entry(row));
+            }
+        }
+        Location::Storage => {
+            for (_, bytes) in storage_backend(storage)?.scan(keyspaces::AUDIT, "")? {
+                verifier.check(serde_json::from_slice(&bytes).map_err(|e| format!("is not valid JSON ({})", e)));
+            }
+        }
+    }
+
+    Ok(verifier.report(&location.path()))
+}
+
+/// Walks a log's entries in order for [`verify`]
+struct Verifier<'a> {
+    key: Option<&'a [u8]>,
+    entries: u64,
+    chained: u64,
+    unchained: u64,
+    signed: u64,
+    // Stored hash of the previous entry, which the next one's prev_hash must match
+    last: Option<String>,
+    // After an unreadable or unchained entry the next link cannot be checked
+    lost: bool,
+    signing: bool,
+    problems: Vec<Value>,
+    problem_count: u64,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(key: Option<&'a [u8]>) -> Self {
+        Self {
+            key,
+            entries: 0,
+            chained: 0,
+            unchained: 0,
+            signed: 0,
+            last: None,
+            lost: false,
+            signing: false,
+            problems: Vec::new(),
+            problem_count: 0,
+        }
+    }
+
+    fn check(&mut self, entry: std::result::Result<Value, String>) {
+        self.entries += 1;
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(problem) => {
+                self.problem(&Value::Null, &problem);
+                self.lost = true;
+                return;
+            }
+        };
+        let Some(hash) = entry["hash"].as_str() else {
+            if self.chained > 0 {
+                self.problem(&entry, "has no hash, unlike the entries before it: its chain fields were removed");
+                self.lost = true;
+            } else {
+                self.unchained += 1;
+            }
+            return;
+        };
+
+        if entry_hash(&entry) != hash {
+            self.problem(&entry, "does not match its hash: the entry was modified");
+        }
+        let prev = entry["prev_hash"].as_str();
+        if !self.lost && prev != self.last.as_deref() {
+            let problem = if self.chained > 0 {
+                "does not follow the entry before it: entries were removed, inserted or reordered"
+            } else {
+                "follows an entry that is not in the log: earlier entries were removed"
+            };
+            self.problem(&entry, problem);
+        }
+        match (self.key, entry["signature"].as_str()) {
+            (Some(key), Some(signature)) => {
+                self.signed += 1;
+                self.signing = true;
+                if sign(key, hash) != signature {
+                    self.problem(&entry, "has a wrong signature: it was modified, or signed with another key");
+                }
+            }
+            (Some(_), None) if self.signing => self.problem(&entry, "is not signed, unlike the entries before it"),
+            (None, Some(_)) => self.signed += 1,
+            _ => {}
+        }
+
+        self.chained += 1;
+        self.last = Some(hash.to_string());
+        self.lost = false;
+    }
+
+    fn problem(&mut self, entry: &Value, problem: &str) {
+        self.problem_count += 1;
+        if self.problems.len() < MAX_PROBLEMS {
+            self.problems.push(json!({
+                "entry": self.entries,
+                "timestamp": entry["timestamp"],
+                "tool": entry["tool"],
+                "problem": format!("Entry {} {}", self.entries, problem),
+            }));
+        }
+    }
+
+    fn report(self, path: &Path) -> Value {
+        let mut report = json!({
+            "path": path.display().to_string(),
+            "valid": self.problem_count == 0,
+            "entries": self.entries,
+            "chained": self.chained,
+            "unchained": self.unchained,
+            "signed": self.signed,
+            "signatures_checked": self.key.is_some(),
+            "last_hash": self.last,
+            "problems": self.problems,
+        });
+        if self.problem_count > MAX_PROBLEMS as u64 {
+            report["more_problems"] = json!(self.problem_count - MAX_PROBLEMS as u64);
+        }
+        report
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// Lowercase with `-` as `_`, and camelCase split into words
+fn normalize_key(key: &str) -> String {
+    let mut normalized = String::with_capacity(key.len() + 4);
+    let mut previous_lower = false;
+    for c in key.chars() {
+        if c.is_uppercase() && previous_lower {
+            normalized.push('_');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        normalized.push(if c == '-' { '_' } else { c.to_ascii_lowercase() });
+    }
+    normalized
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+fn open_jsonl(path: &Path) -> Result<Sink> {
+    create_parent(path)?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+    Ok(Sink::Jsonl {
+        file,
+        path: path.to_path_buf(),
+        len: 0,
+    })
+}
+
+fn open_sqlite(path: &Path) -> Result<Sink> {
+    create_parent(path)?;
+    let connection = rusqlite::Connection::open(path)
+        .with_context(|| format!("Failed to open audit database {}", path.display()))?;
+    connection
+        .execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                tool TEXT NOT NULL,
+                module TEXT,
+                caller TEXT,
+                transport TEXT NOT NULL,
+                session TEXT,
+                tenant TEXT,
+                request_id TEXT,
+                args TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS audit_log_timestamp ON audit_log (timestamp);
+            CREATE INDEX IF NOT EXISTS audit_log_tool ON audit_log (tool);",
+        )
+        .context("Failed to create audit_log table")?;
+    // Databases created before tenants or hash chaining existed lack these columns
+    for column in ["tenant", "prev_hash", "hash", "signature"] {
+        if !has_column(&connection, column)? {
+            connection
+                .execute(&format!("ALTER TABLE audit_log ADD COLUMN {} TEXT", column), [])
+                .with_context(|| format!("Failed to add {} column to audit_log", column))?;
+        }
+    }
+    Ok(Sink::Sqlite(connection))
+}
+
+fn has_column(connection: &rusqlite::Connection, column: &str) -> Result<bool> {
+    connection
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('audit_log') WHERE name = ?1",
+            [column],
+            |row| row.get(0),
+        )
+        .context("Failed to inspect audit_log table")
+}
+
+/// An audit_log row as the entry that was written
+fn sqlite_entry(row: &rusqlite::Row) -> std::result::Result<Value, String> {
+    let read = || -> rusqlite::Result<Value> {
+        let json_column = |index: usize| -> rusqlite::Result<Value> {
+            Ok(row
+                .get::<_, Option<String>>(index)?
+                .map(|text| serde_json::from_str(&text).unwrap_or(Value::String(text)))
+                .unwrap_or(Value::Null))
+        };
+        let mut entry = json!({
+            "timestamp": row.get::<_, String>(0)?,
+            "tool": row.get::<_, String>(1)?,
+            "module": row.get::<_, Option<String>>(2)?,
+            "caller": row.get::<_, Option<String>>(3)?,
+            "transport": row.get::<_, String>(4)?,
+            "session": row.get::<_, Option<String>>(5)?,
+            "tenant": row.get::<_, Option<String>>(6)?,
+            "request_id": json_column(7)?,
+            "args": json_column(8)?,
+            "duration_ms": row.get::<_, i64>(9)?,
+            "status": row.get::<_, String>(10)?,
+            "error": row.get::<_, Option<String>>(11)?,
+        });
+        // Rows from before hash chaining stay without the fields, as they were written
+        if let Some(hash) = row.get::<_, Option<String>>(13)? {
+            entry["prev_hash"] = json!(row.get::<_, Option<String>>(12)?);
+            entry["hash"] = json!(hash);
+            if let Some(signature) = row.get::<_, Option<String>>(14)? {
+                entry["signature"] = json!(signature);
+            }
+        }
+        Ok(entry)
+    };
+    read().map_err(|e| format!("cannot be read ({})", e))
+}
+
+/// The hash of the last entry already in the log, which the first new one follows.
+/// JSON-lines and SQLite logs look it up when writing.
+fn last_hash(sink: &Sink) -> Result<Option<String>> {
+    match sink {
+        Sink::Storage(storage, _) => {
+            let entries = storage.scan(keyspaces::AUDIT, "")?;
+            Ok(entries
+                .last()
+                .and_then(|(_, bytes)| serde_json::from_slice::<Value>(bytes).ok())
+                .and_then(|entry| entry["hash"].as_str().map(str::to_string)))
+        }
+        Sink::Jsonl { .. } | Sink::Sqlite(_) => Ok(None),
+    }
+}
+
+/// The hash of the last line of a JSON-lines log, read from the end of the file
+fn last_jsonl_hash(path: &Path) -> Result<Option<String>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut tail: Vec<u8> = Vec::new();
+    loop {
+        // The last complete line, once a newline before it is in the tail
+        let trimmed = tail.strip_suffix(b"\n").unwrap_or(&tail);
+        if let Some(start) = trimmed.iter().rposition(|&b| b == b'\n') {
+            return Ok(line_hash(&trimmed[start + 1..]));
+        }
+        if end == 0 {
+            return Ok(line_hash(trimmed));
+        }
+        let chunk = end.min(64 * 1024);
+        end -= chunk;
+        file.seek(SeekFrom::Start(end))?;
+        let mut buffer = vec![0; chunk as usize];
+        file.read_exact(&mut buffer)?;
+        buffer.extend_from_slice(&tail);
+        tail = buffer;
+    }
+}
+
+fn line_hash(line: &[u8]) -> Option<String> {
+    let entry: Value = serde_json::from_slice(line).ok()?;
+    entry["hash"].as_str().map(str::to_string)
+}
+
+/// Read `[audit] signing_key_file`: the key is the file's contents, without
+/// surrounding whitespace
+fn signing_key(config: &AuditConfig) -> Result<Option<Vec<u8>>> {
+    let Some(path) = &config.signing_key_file else {
+        return Ok(None);
+    };
+    let contents = std::fs::read(path)
+        .with_context(|| format!("Failed to read [audit] signing_key_file {}", path.display()))?;
+    let key = contents.trim_ascii().to_vec();
+    if key.len() < MIN_KEY_BYTES {
+        anyhow::bail!(
+            "[audit] signing_key_file {} holds {} bytes; the key needs at least {} (e.g. `openssl rand -hex 32`)",
+            path.display(),
+            key.len(),
+            MIN_KEY_BYTES
+        );
+    }
+    Ok(Some(key))
+}
+
+/// Link `entry` to the entry with hash `prev_hash`, and sign it when there is a key
+fn seal(entry: &mut Value, prev_hash: Option<&str>, key: Option<&[u8]>) {
+    entry["prev_hash"] = json!(prev_hash);
+    let hash = entry_hash(entry);
+    if let Some(key) = key {
+        entry["signature"] = json!(sign(key, &hash));
+    }
+    entry["hash"] = json!(hash);
+}
+
+/// SHA-256 of the entry without its `hash` and `signature`, as JSON with sorted keys
+fn entry_hash(entry: &Value) -> String {
+    let mut entry = entry.clone();
+    if let Some(map) = entry.as_object_mut() {
+        map.remove("hash");
+        map.remove("signature");
+    }
+    let mut text = String::new();
+    canonical_json(&entry, &mut text);
+    hex(&Sha256::digest(text.as_bytes()))
+}
+
+/// JSON with object keys sorted at every level, so the hash does not depend on key order
+fn canonical_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                canonical_json(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonical_json(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// HMAC-SHA256 of an entry's hash
+fn sign(key: &[u8], hash: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(hash.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Chain `entry` to the last line of a JSON-lines log and append it, with the file locked
+fn append_jsonl(file: &mut std::fs::File, path: &Path, len: &mut u64, entry: &mut Value, last: Option<&str>, key: Option<&[u8]>) -> Result<()> {
+    let appended = file.metadata()?.len() != *len;
+    let prev = if appended { last_jsonl_hash(path)? } else { last.map(str::to_string) };
+    seal(entry, prev.as_deref(), key);
+    // One write per line so concurrent servers appending to the same file do not interleave
+    let mut line = entry.to_string();
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    *len = file.metadata()?.len();
+    Ok(())
+}
+
+/// Chain `entry` to the one written before it (`last`, unless the log says
+/// otherwise), then append it
+fn write_entry(sink: &mut Sink, entry: &mut Value, last: Option<&str>, key: Option<&[u8]>) -> Result<()> {
+    match sink {
+        Sink::Jsonl { file, path, len } => {
+            // Otherwise two servers could both chain to the same last entry
+            file.lock().with_context(|| format!("Failed to lock audit log {}", path.display()))?;
+            let appended = append_jsonl(file, path, len, entry, last, key);
+            file.unlock()?;
+            appended?;
+        }
+        Sink::Sqlite(connection) => {
+            let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let prev: Option<String> = transaction
+                .query_row("SELECT hash FROM audit_log ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+                .optional()?
+                .flatten();
+            seal(entry, prev.as_deref(), key);
+            transaction.execute(
+                "INSERT INTO audit_log
+                    (timestamp, tool, module, caller, transport, session, tenant, request_id, args, duration_ms, status, error,
+                     prev_hash, hash, signature)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                rusqlite::params![
+                    entry["timestamp"].as_str(),
+                    entry["tool"].as_str(),
+                    entry["module"].as_str(),
+                    entry["caller"].as_str(),
+                    entry["transport"].as_str(),
+                    entry["session"].as_str(),
+                    entry["tenant"].as_str(),
+                    (!entry["request_id"].is_null()).then(|| entry["request_id"].to_string()),
+                    entry["args"].to_string(),
+                    entry["duration_ms"].as_u64(),
+                    entry["status"].as_str(),
+                    entry["error"].as_str(),
+                    entry["prev_hash"].as_str(),
+                    entry["hash"].as_str(),
+                    entry["signature"].as_str(),
+                ],
+            )?;
+            transaction.commit()?;
+        }
+        Sink::Storage(storage, counter) => {
+            seal(entry, last, key);
+            *counter += 1;
+            let key = format!("{}-{:08}", entry["timestamp"].as_str().unwrap_or_default(), counter);
+            storage.put_json(keyspaces::AUDIT, &key, entry)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tool: &str) -> Value {
+        json!({ "timestamp": chrono::Utc::now().to_rfc3339(), "tool": tool, "args": {}, "status": "ok" })
+    }
+
+    /// Append `count` entries through a sink of its own, as one server would
+    fn write(path: &Path, count: usize) {
+        let mut sink = open_jsonl(path).unwrap();
+        let mut last = last_hash(&sink).unwrap();
+        for i in 0..count {
+            let mut entry = entry(&format!("tool_{}", i));
+            write_entry(&mut sink, &mut entry, last.as_deref(), None).unwrap();
+            last = entry["hash"].as_str().map(str::to_string);
+        }
+    }
+
+    fn config(path: &Path) -> AuditConfig {
+        AuditConfig { path: Some(path.to_path_buf()), ..AuditConfig::default() }
+    }
+
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("poly-audit-{}.jsonl", uuid::Uuid::new_v4().simple()))
+    }
+
+    #[test]
+    fn verify_finds_a_tampered_entry() {
+        let path = temp_log();
+        write(&path, 5);
+        let report = verify(&config(&path), None).unwrap();
+        assert_eq!(report["valid"], true);
+        assert_eq!(report["chained"], 5);
+
+        let text = std::fs::read_to_string(&path).unwrap().replacen("tool_2", "tool_x", 1);
+        std::fs::write(&path, text).unwrap();
+        let report = verify(&config(&path), None).unwrap();
+        assert_eq!(report["valid"], false);
+        assert!(report["problems"][0]["problem"].as_str().unwrap().contains("was modified"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn servers_appending_to_one_log_keep_a_valid_chain() {
+        let path = temp_log();
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || write(&path, 50))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let report = verify(&config(&path), None).unwrap();
+        assert_eq!(report["entries"], 200);
+        assert_eq!(report["valid"], true, "{}", report);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
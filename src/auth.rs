@@ -0,0 +1,352 @@
+// Authentication for HTTP mode: static API keys and OAuth token introspection
+
+use crate::config::{AuthConfig, IntrospectionConfig};
+#[cfg(feature = "introspection")]
+use crate::storage::keyspaces;
+use crate::storage::{MemoryStorage, Storage};
+use anyhow::Result;
+#[cfg(feature = "introspection")]
+use anyhow::Context as _;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+#[cfg(feature = "introspection")]
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+#[cfg(feature = "introspection")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+
+/// Header carrying a static API key, as an alternative to `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Who made a request, attached to authenticated requests as an extension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Principal {
+    /// API key name from the config, or the token's subject/client id
+    pub name: String,
+    pub method: AuthMethod,
+    /// Scopes granted to an introspected token
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthMethod {
+    ApiKey,
+    OAuth,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing credentials: send 'Authorization: Bearer <token>' or '{API_KEY_HEADER}: <key>'")]
+    Missing,
+    #[error("Invalid or expired credentials")]
+    Invalid,
+    #[error("Token lacks the required scope '{0}'")]
+    InsufficientScope(String),
+    #[error("Token introspection failed: {0}")]
+    Introspection(String),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, challenge) = match &self {
+            AuthError::Missing => (StatusCode::UNAUTHORIZED, "Bearer realm=\"poly-mcp\"".to_string()),
+            AuthError::Invalid => (
+                StatusCode::UNAUTHORIZED,
+                "Bearer realm=\"poly-mcp\", error=\"invalid_token\"".to_string(),
+            ),
+            AuthError::InsufficientScope(scope) => (
+                StatusCode::FORBIDDEN,
+                format!("Bearer realm=\"poly-mcp\", error=\"insufficient_scope\", scope=\"{}\"", scope),
+            ),
+            AuthError::Introspection(_) => (StatusCode::SERVICE_UNAVAILABLE, "Bearer realm=\"poly-mcp\"".to_string()),
+        };
+
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32000, "message": self.to_string() }
+        });
+        let mut response = (status, Json(body)).into_response();
+        if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
+
+/// Checks request credentials against the `[auth]` config
+pub struct Authenticator {
+    // Keys are kept as digests so comparisons take the same time whatever the input
+    keys: Vec<(String, [u8; 32])>,
+    introspection: Option<Introspector>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        Self::with_storage(config, Arc::new(MemoryStorage::new()))
+    }
+
+    /// Cache introspected tokens in `storage`, so a restart does not send every
+    /// client's token to the introspection endpoint again
+    pub fn with_storage(config: &AuthConfig, storage: Arc<dyn Storage>) -> Result<Self> {
+        let keys = config
+            .api_keys
+            .iter()
+            .map(|(name, key)| {
+                if key.is_empty() {
+                    anyhow::bail!("API key '{}' is empty", name);
+                }
+                Ok((name.clone(), digest(key)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let introspection = config
+            .introspection
+            .as_ref()
+            .map(|introspection| Introspector::new(introspection, storage))
+            .transpose()?;
+        Ok(Self { keys, introspection })
+    }
+
+    /// Whether any credentials are configured. Without them HTTP mode is open.
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.introspection.is_some()
+    }
+
+    /// Authenticate a request from its headers, or from an `access_token` query
+    /// parameter for clients that cannot set headers (browser WebSocket and EventSource).
+    pub async fn authenticate(&self, headers: &HeaderMap, query: Option<&str>) -> Result<Principal, AuthError> {
+        let token = credentials(headers, query).ok_or(AuthError::Missing)?;
+
+        let presented = digest(&token);
+        let mut matched = None;
+        // Check every key so timing does not reveal which one matched
+        for (name, key) in &self.keys {
+            if bool::from(key.ct_eq(&presented)) {
+                matched = Some(name);
+            }
+        }
+        if let Some(name) = matched {
+            return Ok(Principal {
+                name: name.clone(),
+                method: AuthMethod::ApiKey,
+                scopes: Vec::new(),
+            });
+        }
+
+        match &self.introspection {
+            Some(introspection) => introspection.check(&token, presented).await,
+            None => Err(AuthError::Invalid),
+        }
+    }
+}
+
+/// axum middleware rejecting requests without valid credentials. The caller's
+/// [`Principal`] is added to the request extensions for handlers further down.
+pub async fn require_auth(
+    State(auth): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match auth.authenticate(request.headers(), request.uri().query()).await {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(e) => {
+            tracing::warn!("Rejected {} {}: {}", request.method(), request.uri().path(), e);
+            e.into_response()
+        }
+    }
+}
+
+/// RFC 7662 token introspection, with active tokens cached for a short time
+#[cfg(feature = "introspection")]
+struct Introspector {
+    config: IntrospectionConfig,
+    client: reqwest::Client,
+    // Principals by token digest, with their expiry in Unix seconds
+    cache: Arc<dyn Storage>,
+}
+
+/// A cached introspection result
+#[cfg(feature = "introspection")]
+#[derive(Serialize, Deserialize)]
+struct CachedToken {
+    principal: Principal,
+    expires: u64,
+}
+
+#[cfg(feature = "introspection")]
+impl Introspector {
+    fn new(config: &IntrospectionConfig, cache: Arc<dyn Storage>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create introspection client")?;
+        Ok(Self {
+            config: config.clone(),
+            client,
+            cache,
+        })
+    }
+
+    async fn check(&self, token: &str, key: [u8; 32]) -> Result<Principal, AuthError> {
+        let key: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        if let Some(principal) = self.cached(&key) {
+            return Ok(principal);
+        }
+
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .form(&[("token", token), ("token_type_hint", "access_token")]);
+        if let Some(client_id) = &self.config.client_id {
+            request = request.basic_auth(client_id, self.config.client_secret.as_deref());
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AuthError::Introspection(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(AuthError::Introspection(format!("endpoint answered {}", response.status())));
+        }
+        let info: Value = response
+            .json()
+            .await
+            .map_err(|e| AuthError::Introspection(e.to_string()))?;
+
+        if info["active"].as_bool() != Some(true) {
+            return Err(AuthError::Invalid);
+        }
+        let scopes: Vec<String> = info["scope"]
+            .as_str()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        if let Some(required) = &self.config.required_scope {
+            if !scopes.iter().any(|s| s == required) {
+                return Err(AuthError::InsufficientScope(required.clone()));
+            }
+        }
+
+        let name = ["sub", "username", "client_id"]
+            .iter()
+            .find_map(|field| info[*field].as_str())
+            .unwrap_or("oauth")
+            .to_string();
+        let principal = Principal {
+            name,
+            method: AuthMethod::OAuth,
+            scopes,
+        };
+
+        // Never cache past the token's own expiry
+        let now = unix_now();
+        let mut expires = now + self.config.cache_secs;
+        if let Some(exp) = info["exp"].as_u64() {
+            expires = expires.min(exp);
+        }
+        if expires > now {
+            self.cache_token(&key, &principal, expires);
+        }
+
+        Ok(principal)
+    }
+
+    fn cached(&self, key: &str) -> Option<Principal> {
+        let cached: CachedToken = match self.cache.get_json(keyspaces::TOKEN_CACHE, key) {
+            Ok(cached) => cached?,
+            Err(e) => {
+                tracing::warn!("Failed to read the token cache: {:#}", e);
+                return None;
+            }
+        };
+        if cached.expires > unix_now() {
+            return Some(cached.principal);
+        }
+        let _ = self.cache.delete(keyspaces::TOKEN_CACHE, key);
+        None
+    }
+
+    /// A failure to cache only costs another introspection request, so it is logged, not returned
+    fn cache_token(&self, key: &str, principal: &Principal, expires: u64) {
+        let cached = CachedToken {
+            principal: principal.clone(),
+            expires,
+        };
+        let result = self.cache.put_json(keyspaces::TOKEN_CACHE, key, &cached).and_then(|()| {
+            // Drop expired tokens so the cache does not grow without bound
+            let now = unix_now();
+            for (key, cached) in self.cache.scan_json::<CachedToken>(keyspaces::TOKEN_CACHE, "")? {
+                if cached.expires <= now {
+                    self.cache.delete(keyspaces::TOKEN_CACHE, &key)?;
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            tracing::warn!("Failed to update the token cache: {:#}", e);
+        }
+    }
+}
+
+/// Builds without the `introspection` feature have no HTTP client to ask the
+/// endpoint with, so an `[auth.introspection]` table is refused at startup
+#[cfg(not(feature = "introspection"))]
+enum Introspector {}
+
+#[cfg(not(feature = "introspection"))]
+impl Introspector {
+    fn new(_config: &IntrospectionConfig, _cache: Arc<dyn Storage>) -> Result<Self> {
+        anyhow::bail!("This build has no token introspection; rebuild with --features introspection, or remove [auth.introspection]")
+    }
+
+    async fn check(&self, _token: &str, _key: [u8; 32]) -> Result<Principal, AuthError> {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "introspection")]
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn digest(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+/// The presented token: a bearer token, an API key header, or an `access_token` query parameter
+fn credentials(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            let (scheme, token) = v.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim().to_string())
+        });
+    let api_key = || {
+        headers
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+    let query_token = || {
+        query?.split('&').find_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            (name == "access_token").then(|| urlencoding::decode(value).ok().map(|v| v.into_owned()))?
+        })
+    };
+
+    bearer
+        .or_else(api_key)
+        .or_else(query_token)
+        .filter(|token| !token.is_empty())
+}
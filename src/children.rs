@@ -0,0 +1,356 @@
+// Supervision of the external processes tool calls start: limits, a lifetime watchdog, and cleanup when a call ends
+
+use crate::config::ChildrenConfig;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, Once};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Longest command line kept for listing
+const MAX_COMMAND_CHARS: usize = 200;
+
+static SUPERVISOR: LazyLock<Supervisor> = LazyLock::new(|| Supervisor::new(&ChildrenConfig::default()));
+
+tokio::task_local! {
+    // Id and tool name of the call the current task runs
+    static CALL: (u64, String);
+}
+
+/// Every running child process, by id
+struct Supervisor {
+    children: Mutex<BTreeMap<u64, Child>>,
+    next_id: AtomicU64,
+    next_call: AtomicU64,
+    max_concurrent: AtomicUsize,
+    max_lifetime_secs: AtomicU64,
+    watchdog: Once,
+}
+
+struct Child {
+    // None until the process has been spawned
+    pid: Option<u32>,
+    program: String,
+    command: String,
+    call: Option<(u64, String)>,
+    started: Instant,
+    started_at: SystemTime,
+    killed: Option<Kill>,
+}
+
+/// Why the supervisor killed a child
+#[derive(Debug, Clone, Copy)]
+enum Kill {
+    Lifetime(u64),
+    Requested,
+    CallEnded,
+    Shutdown,
+}
+
+impl Supervisor {
+    fn new(config: &ChildrenConfig) -> Self {
+        Self {
+            children: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            next_call: AtomicU64::new(1),
+            max_concurrent: AtomicUsize::new(config.max_concurrent),
+            max_lifetime_secs: AtomicU64::new(config.max_lifetime_secs),
+            watchdog: Once::new(),
+        }
+    }
+
+    /// Kill the children `select` picks that are not being killed already; returns how many
+    fn kill_where(&self, reason: Kill, select: impl Fn(u64, &Child) -> bool) -> usize {
+        let mut children = self.children.lock().unwrap();
+        let mut killed = 0;
+        for (&id, child) in children.iter_mut() {
+            if child.killed.is_none() && select(id, child) {
+                child.killed = Some(reason);
+                if let Some(pid) = child.pid {
+                    kill_tree(pid);
+                }
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    /// Kill children that have outlived `max_lifetime_secs`, once a second
+    fn start_watchdog(&'static self) {
+        self.watchdog.call_once(|| {
+            let spawned = std::thread::Builder::new().name("children-watchdog".to_string()).spawn(move || loop {
+                std::thread::sleep(Duration::from_secs(1));
+                let limit = self.max_lifetime_secs.load(Ordering::Relaxed);
+                if limit == 0 {
+                    continue;
+                }
+                self.kill_where(Kill::Lifetime(limit), |id, child| {
+                    let expired = child.pid.is_some() && child.started.elapsed() > Duration::from_secs(limit);
+                    if expired {
+                        tracing::warn!(
+                            "Killed child process {} ({}) after {}s: {}",
+                            id,
+                            child.program,
+                            limit,
+                            child.command
+                        );
+                    }
+                    expired
+                });
+            });
+            if let Err(e) = spawned {
+                tracing::warn!("Could not start the child process watchdog: {}", e);
+            }
+        });
+    }
+}
+
+/// A child process the supervisor lists. It is killed, with the processes it started,
+/// if this is dropped before [`Tracked::finish`], e.g. because its call was cancelled.
+pub struct Tracked {
+    id: u64,
+    finished: bool,
+}
+
+impl Tracked {
+    /// Register a child about to be started from `command`, unless `max_concurrent`
+    /// children are running already
+    fn reserve(command: &std::process::Command) -> io::Result<Self> {
+        let supervisor = &*SUPERVISOR;
+        supervisor.start_watchdog();
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut line = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|word| {
+                let word = word.to_string_lossy();
+                match word.contains(char::is_whitespace) {
+                    true => format!("{:?}", word),
+                    false => word.into_owned(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some((cut, _)) = line.char_indices().nth(MAX_COMMAND_CHARS) {
+            line.truncate(cut);
+            line.push('…');
+        }
+
+        let mut children = supervisor.children.lock().unwrap();
+        let max = supervisor.max_concurrent.load(Ordering::Relaxed);
+        if max > 0 && children.len() >= max {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!(
+                    "Not starting {}: {} child processes are running, the most [children] max_concurrent allows",
+                    program,
+                    children.len()
+                ),
+            ));
+        }
+        crate::provenance::ran(&line);
+        let id = supervisor.next_id.fetch_add(1, Ordering::Relaxed);
+        children.insert(
+            id,
+            Child {
+                pid: None,
+                program,
+                command: line,
+                call: CALL.try_with(Clone::clone).ok(),
+                started: Instant::now(),
+                started_at: SystemTime::now(),
+                killed: None,
+            },
+        );
+        Ok(Self { id, finished: false })
+    }
+
+    /// Note the process id once the child is running
+    fn started(&self, pid: u32) {
+        let mut children = SUPERVISOR.children.lock().unwrap();
+        if let Some(child) = children.get_mut(&self.id) {
+            child.pid = Some(pid);
+            // Killed while it was being spawned
+            if child.killed.is_some() {
+                kill_tree(pid);
+            }
+        }
+    }
+
+    /// Stop tracking the child once `result`, what waiting for it gave, is in. A child
+    /// the supervisor killed gives an error saying why instead.
+    pub fn finish<T>(mut self, result: io::Result<T>) -> io::Result<T> {
+        self.finished = true;
+        let child = SUPERVISOR.children.lock().unwrap().remove(&self.id);
+        let Some(Child { program, killed: Some(kill), .. }) = child else {
+            return result;
+        };
+        let error = match kill {
+            Kill::Lifetime(secs) => io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} was killed after running for more than {}s ([children] max_lifetime_secs)", program, secs),
+            ),
+            Kill::Requested => io::Error::new(io::ErrorKind::Interrupted, format!("{} was killed with server_children", program)),
+            Kill::CallEnded => io::Error::new(io::ErrorKind::Interrupted, format!("{} was killed when its tool call ended", program)),
+            Kill::Shutdown => io::Error::new(io::ErrorKind::Interrupted, format!("{} was killed at server shutdown", program)),
+        };
+        Err(error)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let child = SUPERVISOR.children.lock().unwrap().remove(&self.id);
+        if let (false, Some(Child { pid: Some(pid), .. })) = (self.finished, child) {
+            kill_tree(pid);
+        }
+    }
+}
+
+/// The tool call a task runs for. Child processes started inside [`Call::scope`] are
+/// listed under it, and the ones still running are killed when this is dropped.
+pub struct Call {
+    id: u64,
+    tool: String,
+}
+
+impl Call {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            id: SUPERVISOR.next_call.fetch_add(1, Ordering::Relaxed),
+            tool: tool.to_string(),
+        }
+    }
+
+    /// Run `future` as part of this call
+    pub fn scope<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        CALL.scope((self.id, self.tool.clone()), future)
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        let id = self.id;
+        SUPERVISOR.kill_where(Kill::CallEnded, |_, child| child.call.as_ref().is_some_and(|(call, _)| *call == id));
+    }
+}
+
+/// Apply the `[children]` limits to children started from now on
+pub fn configure(config: &ChildrenConfig) {
+    SUPERVISOR.max_concurrent.store(config.max_concurrent, Ordering::Relaxed);
+    SUPERVISOR.max_lifetime_secs.store(config.max_lifetime_secs, Ordering::Relaxed);
+}
+
+/// Start `command` as a supervised child, in a process group of its own on Unix
+pub fn spawn(command: &mut std::process::Command) -> io::Result<(std::process::Child, Tracked)> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    let tracked = Tracked::reserve(command)?;
+    let child = command.spawn()?;
+    tracked.started(child.id());
+    Ok((child, tracked))
+}
+
+/// [`spawn`] for commands run on the async runtime. The child is also killed if the
+/// returned `Child` is dropped while it runs.
+pub fn spawn_async(command: &mut tokio::process::Command) -> io::Result<(tokio::process::Child, Tracked)> {
+    #[cfg(unix)]
+    command.process_group(0);
+    command.kill_on_drop(true);
+    let tracked = Tracked::reserve(command.as_std())?;
+    let child = command.spawn()?;
+    if let Some(pid) = child.id() {
+        tracked.started(pid);
+    }
+    Ok((child, tracked))
+}
+
+/// Run `command` to completion as a supervised child, collecting its output like
+/// `Command::output` does (stdin is null)
+pub fn run(command: &mut std::process::Command) -> io::Result<Output> {
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let (child, tracked) = spawn(command)?;
+    // Waiting blocks this thread; on a runtime worker, its other tasks (the call's
+    // timeout among them) move to another thread meanwhile
+    let multi_thread = tokio::runtime::Handle::try_current()
+        .is_ok_and(|runtime| runtime.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread);
+    let output = match multi_thread {
+        true => tokio::task::block_in_place(|| child.wait_with_output()),
+        false => child.wait_with_output(),
+    };
+    tracked.finish(output)
+}
+
+/// [`run`] for commands run on the async runtime
+pub async fn run_async(command: &mut tokio::process::Command) -> io::Result<Output> {
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let (child, tracked) = spawn_async(command)?;
+    tracked.finish(child.wait_with_output().await)
+}
+
+/// The running children, oldest first
+pub fn list() -> Vec<Value> {
+    let children = SUPERVISOR.children.lock().unwrap();
+    children
+        .iter()
+        .filter(|(_, child)| child.pid.is_some())
+        .map(|(id, child)| describe(*id, child))
+        .collect()
+}
+
+/// The `[children]` limits in effect
+pub fn limits() -> Value {
+    json!({
+        "max_concurrent": SUPERVISOR.max_concurrent.load(Ordering::Relaxed),
+        "max_lifetime_secs": SUPERVISOR.max_lifetime_secs.load(Ordering::Relaxed),
+    })
+}
+
+/// Kill child `id` and the processes it started; returns what it was, or `None` if
+/// no such child is running
+pub fn kill(id: u64) -> Option<Value> {
+    let described = {
+        let children = SUPERVISOR.children.lock().unwrap();
+        children.get(&id).filter(|child| child.pid.is_some()).map(|child| describe(id, child))?
+    };
+    SUPERVISOR.kill_where(Kill::Requested, |child_id, _| child_id == id);
+    tracing::info!("Killed child process {} on request", id);
+    Some(described)
+}
+
+/// Kill every running child, at shutdown; returns how many there were
+pub fn kill_all() -> usize {
+    SUPERVISOR.kill_where(Kill::Shutdown, |_, _| true)
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn describe(id: u64, child: &Child) -> Value {
+    json!({
+        "id": id,
+        "pid": child.pid,
+        "program": child.program,
+        "command": child.command,
+        "tool": child.call.as_ref().map(|(_, tool)| tool),
+        "started": child.started_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        "running_secs": child.started.elapsed().as_secs(),
+        "killed": child.killed.is_some(),
+    })
+}
+
+/// Kill the process `pid` and everything in its process group (its process tree on Windows)
+fn kill_tree(pid: u32) {
+    #[cfg(unix)]
+    // SAFETY: killpg only sends a signal; supervised children lead a process group of their own
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+    #[cfg(windows)]
+    let _ = std::process::Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
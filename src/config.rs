@@ -0,0 +1,761 @@
+// Server configuration loaded from poly-mcp.toml (or YAML), with CLI flags layered on top
+
+use crate::error::PolyError;
+use anyhow::{Context as _, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default config file looked up in the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_FILE: &str = "poly-mcp.toml";
+
+/// The YAML names looked for when there is no [`DEFAULT_CONFIG_FILE`]
+const DEFAULT_YAML_CONFIG_FILES: &[&str] = &["poly-mcp.yaml", "poly-mcp.yml"];
+
+/// Every module that can be enabled or disabled, in display order
+pub const MODULE_NAMES: &[&str] = &[
+    "filesystem",
+    "diagnostics",
+    "silent",
+    "time",
+    "network",
+    "context",
+    "git",
+    "input",
+    "gitent",
+    "clipboard",
+    "transform",
+    "settings",
+    "calc",
+    "crypto",
+    "regex",
+    "text",
+    "xlsx",
+    "md",
+    "media",
+    "browser",
+    "llm",
+    "power",
+    "apps",
+    "txn",
+    "session",
+    "fixtures",
+    "varp",
+];
+
+/// Whether this build includes the built-in module `name`. Every module is a cargo
+/// feature; names of modules left out are still accepted in `[modules]`.
+pub fn module_built(name: &str) -> bool {
+    match name {
+        "filesystem" => cfg!(feature = "filesystem"),
+        "diagnostics" => cfg!(feature = "diagnostics"),
+        "silent" => cfg!(feature = "silent"),
+        "time" => cfg!(feature = "time"),
+        "network" => cfg!(feature = "network"),
+        "context" => cfg!(feature = "context"),
+        "git" => cfg!(feature = "git"),
+        "input" => cfg!(feature = "input"),
+        "gitent" => cfg!(feature = "gitent"),
+        "clipboard" => cfg!(feature = "clipboard"),
+        "transform" => cfg!(feature = "transform"),
+        "settings" => cfg!(feature = "settings"),
+        "calc" => cfg!(feature = "calc"),
+        "crypto" => cfg!(feature = "crypto"),
+        "regex" => cfg!(feature = "regex"),
+        "text" => cfg!(feature = "text"),
+        "xlsx" => cfg!(feature = "xlsx"),
+        "md" => cfg!(feature = "md"),
+        "media" => cfg!(feature = "whisper"),
+        "browser" => cfg!(feature = "browser"),
+        "llm" => cfg!(feature = "llm"),
+        "power" => cfg!(feature = "power"),
+        "apps" => cfg!(feature = "apps"),
+        "txn" => cfg!(feature = "txn"),
+        "session" => true,
+        "fixtures" => cfg!(feature = "testing"),
+        "varp" => cfg!(feature = "premium"),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub modules: ModulesConfig,
+    pub filesystem: FilesystemConfig,
+    pub network: NetworkConfig,
+    pub git: GitConfig,
+    pub input: InputConfig,
+    pub gitent: GitentConfig,
+    pub media: MediaConfig,
+    pub browser: BrowserConfig,
+    pub llm: LlmConfig,
+    pub power: PowerConfig,
+    pub apps: AppsConfig,
+    pub audit: AuditConfig,
+    pub storage: StorageConfig,
+    pub telemetry: TelemetryConfig,
+    pub usage_stats: UsageStatsConfig,
+    pub plugins: PluginsConfig,
+    pub policy: PolicyConfig,
+    pub timeouts: TimeoutsConfig,
+    pub children: ChildrenConfig,
+    pub tenants: BTreeMap<String, TenantConfig>,
+}
+
+/// `[server]` table: bind address for HTTP mode. `cors_origins` limits which
+/// browser origins may call the server; empty allows any origin. Client sessions
+/// end after `session_idle_secs` without requests, and at most `max_sessions`
+/// are kept at once. `cwd` is the working directory each session starts in.
+/// `tools_page_size` splits tools/list into pages of that many tools (0: one page).
+/// Results of calls made with an `idempotency_key` are kept for `idempotency_ttl_secs`,
+/// for every session of the same caller.
+/// `tool_prefix` is put in front of every tool name clients see, and `[server.tool_names]`
+/// gives single tools another name instead (see [`crate::naming::ToolNames`]).
+/// Modules build HTTP clients and similar state on their first call unless `preload` is set.
+/// Tool results carry a `_provenance` field (see [`crate::provenance`]) unless `provenance` is false.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub cors_origins: Vec<String>,
+    pub session_idle_secs: u64,
+    pub max_sessions: usize,
+    pub cwd: Option<PathBuf>,
+    pub tools_page_size: usize,
+    pub idempotency_ttl_secs: u64,
+    pub tool_prefix: String,
+    pub tool_names: BTreeMap<String, String>,
+    pub preload: bool,
+    pub provenance: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 3000,
+            cors_origins: Vec::new(),
+            session_idle_secs: 1800,
+            max_sessions: 100,
+            cwd: None,
+            tools_page_size: 0,
+            idempotency_ttl_secs: 86400,
+            tool_prefix: String::new(),
+            tool_names: BTreeMap::new(),
+            preload: false,
+            provenance: true,
+        }
+    }
+}
+
+/// `[auth]` table: credentials required in HTTP mode. `api_keys` maps a name
+/// (reported as the caller) to a key; with no keys and no introspection
+/// endpoint, HTTP mode accepts every request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub api_keys: BTreeMap<String, String>,
+    pub introspection: Option<IntrospectionConfig>,
+}
+
+/// `[auth.introspection]` table: OAuth 2.0 token introspection endpoint (RFC 7662)
+/// used to validate bearer tokens that are not static API keys
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntrospectionConfig {
+    pub url: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub required_scope: Option<String>,
+    #[serde(default = "default_introspection_cache_secs")]
+    pub cache_secs: u64,
+}
+
+fn default_introspection_cache_secs() -> u64 {
+    60
+}
+
+/// `[modules]` table: `enable` restricts the server to the listed modules,
+/// `disable` removes modules from whatever is enabled.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulesConfig {
+    pub enable: Vec<String>,
+    pub disable: Vec<String>,
+}
+
+/// `[filesystem]` table: when `allowed_roots` is non-empty, filesystem tools
+/// refuse paths outside those directories. `delete_mode` is how fs_delete
+/// deletes when the call does not say.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilesystemConfig {
+    pub allowed_roots: Vec<PathBuf>,
+    pub delete_mode: DeleteMode,
+}
+
+/// How fs_delete deletes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    #[default]
+    Permanent,
+    /// Into the OS trash, from where fs_trash_restore can bring it back
+    Trash,
+}
+
+/// `[network]` table: HTTP client settings for net_* tools. crates.io, npm and
+/// PyPI responses are kept in `registry_cache_dir` (default: `poly-mcp/registry` in the
+/// user's cache directory) for `registry_cache_ttl_secs`, shared by every server using
+/// the directory; expired ones are deleted as new ones are stored. 0 turns the cache off.
+///
+/// Connections are pooled and reused: up to `pool_max_idle_per_host` idle ones per
+/// host (default: no limit) are kept for `pool_idle_timeout_secs`. `tcp_keepalive_secs`
+/// and `http2_keep_alive_secs` keep them from being dropped by idle timeouts along the
+/// way (default: off). With `dns_cache_ttl_secs`, host lookups are reused for that
+/// long instead of being made for each new connection (default: 0, off).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub timeout_secs: u64,
+    pub user_agent: Option<String>,
+    pub registry_cache_dir: Option<PathBuf>,
+    pub registry_cache_ttl_secs: u64,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout_secs: u64,
+    pub tcp_keepalive_secs: Option<u64>,
+    pub http2_keep_alive_secs: Option<u64>,
+    pub dns_cache_ttl_secs: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            user_agent: None,
+            registry_cache_dir: None,
+            registry_cache_ttl_secs: 3600,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: 90,
+            tcp_keepalive_secs: None,
+            http2_keep_alive_secs: None,
+            dns_cache_ttl_secs: 0,
+        }
+    }
+}
+
+/// `[git]` table: author used for commits and annotated tags when the call
+/// does not specify one (falls back to the repository's user.name/user.email)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+}
+
+/// `[gitent]` table: session database location. With `encryption_key_file` (or
+/// the GITENT_ENCRYPTION_KEY environment variable), file contents captured in the
+/// database are encrypted with that key.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitentConfig {
+    pub db_path: Option<PathBuf>,
+    pub encryption_key_file: Option<PathBuf>,
+}
+
+/// `[input]` table: where input_clipboard_read and input_clipboard_write keep the
+/// clipboard, see [`ClipboardBackend`]. With `osc52`, writes are also sent to the
+/// server's terminal as an OSC 52 escape sequence, which sets the clipboard of the
+/// terminal emulator on the other end, even over SSH.
+///
+/// input_notify holds notifications back during `quiet_hours` (critical ones
+/// too, unless `quiet_hours_allow_critical`) and past `max_notifications_per_minute`,
+/// and the next one delivered says how many were.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub clipboard: ClipboardBackend,
+    pub osc52: bool,
+    pub quiet_hours: Option<QuietHours>,
+    pub quiet_hours_allow_critical: bool,
+    pub max_notifications_per_minute: Option<u32>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            clipboard: ClipboardBackend::default(),
+            osc52: false,
+            quiet_hours: None,
+            quiet_hours_allow_critical: true,
+            max_notifications_per_minute: None,
+        }
+    }
+}
+
+/// A daily span of local time written "22:00-07:00", which may wrap past midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    pub start: chrono::NaiveTime,
+    pub end: chrono::NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: chrono::NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(span: String) -> Result<Self, Self::Error> {
+        let parse = |time: &str| chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M").ok();
+        span.split_once('-')
+            .and_then(|(start, end)| Some(Self { start: parse(start)?, end: parse(end)? }))
+            .ok_or_else(|| format!("invalid quiet_hours '{}' (expected HH:MM-HH:MM, e.g. \"22:00-07:00\")", span))
+    }
+}
+
+/// Clipboard the input tools use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardBackend {
+    /// The system clipboard, or the virtual one where there is no system clipboard
+    /// (headless and SSH hosts)
+    #[default]
+    Auto,
+    System,
+    /// A buffer kept by the server, shared by all its clients
+    Virtual,
+}
+
+/// `[media]` table: whisper model used by media_transcribe (whisper builds only)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub whisper_model: Option<PathBuf>,
+    pub threads: Option<u32>,
+}
+
+/// `[browser]` table: Chrome/Chromium used by the browser tools (browser builds
+/// only). The executable is auto-detected when `chrome_path` is unset; `no_sandbox`
+/// is needed when running as root or in most containers. Downloads go to
+/// `download_dir` (default: the user's Downloads folder), and at most `max_pages`
+/// pages are kept open.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrowserConfig {
+    pub chrome_path: Option<PathBuf>,
+    pub headless: bool,
+    pub no_sandbox: bool,
+    pub download_dir: Option<PathBuf>,
+    pub max_pages: usize,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        Self {
+            chrome_path: None,
+            headless: true,
+            no_sandbox: false,
+            download_dir: None,
+            max_pages: 10,
+        }
+    }
+}
+
+/// Wire protocol of the `[llm]` endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmApi {
+    /// Ollama's native `/api` endpoints
+    #[default]
+    Ollama,
+    /// `/chat/completions`, `/embeddings` and `/models` as served by OpenAI,
+    /// llama.cpp, vLLM, LM Studio and Ollama's `/v1`
+    OpenAi,
+}
+
+/// `[llm]` table: local model server used by the llm tools. `base_url` defaults
+/// to Ollama on localhost (with `/v1` for `api = "openai"`). `model` and
+/// `embed_model` are used when a call does not name one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    pub api: LlmApi,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+    pub embed_model: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            api: LlmApi::default(),
+            base_url: None,
+            api_key: None,
+            model: None,
+            embed_model: None,
+            timeout_secs: 300,
+        }
+    }
+}
+
+/// `[power]` table: whether power_shutdown may shut down or restart the machine
+/// (each call still needs the user's confirmation)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub allow_shutdown: bool,
+}
+
+/// `[apps]` table: when `allowed_apps` is non-empty, apps tools only focus,
+/// launch, quit or open with the listed applications
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppsConfig {
+    pub allowed_apps: Vec<String>,
+}
+
+/// `[audit]` table: when `path` is set, every tool call is appended to it as a
+/// JSON line, or to a SQLite database for `.db`/`.sqlite` paths or `format = "sqlite"`.
+/// `redact` names more arguments to hide (`tool.arg` for a single tool), and
+/// string values longer than `max_value_chars` are shortened. Entries are
+/// hash-chained; with `signing_key_file` each one is also signed with HMAC-SHA256.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    pub path: Option<PathBuf>,
+    pub format: Option<String>,
+    pub redact: Vec<String>,
+    pub max_value_chars: usize,
+    pub signing_key_file: Option<PathBuf>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: None,
+            redact: Vec::new(),
+            max_value_chars: 200,
+            signing_key_file: None,
+        }
+    }
+}
+
+/// `[storage]` table: where persistent state goes — the fs_snapshot history,
+/// time_schedule tasks, ctx_memory values, the OAuth token cache, and the audit
+/// log with `[audit] format = "storage"`. `backend` is "memory" (the default;
+/// every session keeps its own state until the process exits), "sqlite" or "sled"
+/// (one database shared by every session); `path` defaults to
+/// `.poly-mcp/state.db` or `.poly-mcp/state.sled`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: String,
+    pub path: Option<PathBuf>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: "memory".to_string(),
+            path: None,
+        }
+    }
+}
+
+/// `[plugins]` table: directories searched at startup for plugins — `*.toml`
+/// manifests of subprocess plugins, and shared libraries with the native plugin
+/// ABI (see [`crate::plugins`]). Their tools are served next to the built-in ones.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginsConfig {
+    pub dirs: Vec<PathBuf>,
+}
+
+/// `[telemetry]` table: OTLP export of tool-call and external-command traces and
+/// metrics (needs the `otel` feature). Export also starts when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set, unless `OTEL_SDK_DISABLED=true`. `protocol`
+/// is "grpc" or "http/protobuf" (default: `OTEL_EXPORTER_OTLP_PROTOCOL`, then grpc);
+/// `endpoint` is the collector's base URL when the environment names none, and
+/// `service_name` overrides `OTEL_SERVICE_NAME` (default: poly-mcp).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    pub endpoint: Option<String>,
+    pub protocol: Option<String>,
+    pub service_name: Option<String>,
+    pub metrics_interval_secs: Option<u64>,
+}
+
+/// `[usage_stats]` table: anonymous usage statistics, off unless `enabled`. Only
+/// per-tool call counts, outcomes and durations, the server version and the
+/// platform are kept (see [`crate::usage`]), in the JSON report at `path`
+/// (default: `.poly-mcp/usage-stats.json`). With `report_url` the report is also
+/// POSTed there every `report_interval_secs`, unless `DO_NOT_TRACK` is set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsageStatsConfig {
+    pub enabled: bool,
+    pub path: Option<PathBuf>,
+    pub report_url: Option<String>,
+    pub report_interval_secs: u64,
+}
+
+impl Default for UsageStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            report_url: None,
+            report_interval_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+/// `[policy]` table: limits on what agents may do, for deployments with
+/// untrusted agents. Tool patterns are globs over tool names (`fs_*`) or module
+/// names. `allow_tools`, when non-empty, lists the only tools that may run, and
+/// `deny_tools` always wins. `allowed_paths` confines the path arguments of every
+/// tool, including working directories that default to the current one, and
+/// `allowed_urls` the URL arguments of network and browser tools (full-URL globs,
+/// or host globs such as `*.example.com`). Calls to tools in
+/// `require_approval` wait for the user to allow them through an input dialog
+/// (`approval_mode = "dialog"`), the server's terminal (`"terminal"`), or a desktop
+/// notification with Allow and Deny buttons (`"notification"`). At most
+/// `max_calls_per_minute` tool calls run per minute (0: no limit), and `rate_limits`
+/// limits the tools matching each key further. `profile` fills in a built-in preset
+/// ("safe", "standard" or "yolo"; see [`crate::profiles`]). `read_only` offers only
+/// the built-in tools that inspect, and refuses their calls that would make changes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub profile: Option<String>,
+    pub allow_tools: Vec<String>,
+    pub deny_tools: Vec<String>,
+    pub allowed_paths: Vec<PathBuf>,
+    pub allowed_urls: Vec<String>,
+    pub require_approval: Vec<String>,
+    pub approval_mode: String,
+    pub approval_timeout_secs: u64,
+    pub max_calls_per_minute: u64,
+    pub rate_limits: BTreeMap<String, RateLimitConfig>,
+    pub read_only: bool,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            allow_tools: Vec::new(),
+            deny_tools: Vec::new(),
+            allowed_paths: Vec::new(),
+            allowed_urls: Vec::new(),
+            require_approval: Vec::new(),
+            approval_mode: "dialog".to_string(),
+            approval_timeout_secs: 120,
+            max_calls_per_minute: 0,
+            rate_limits: BTreeMap::new(),
+            read_only: false,
+        }
+    }
+}
+
+/// `[policy.rate_limits."<pattern>"]`: a limit on the tools matching the pattern (a tool
+/// name glob or module name). Calls are taken from a token bucket that holds `burst`
+/// calls (default: `per_second` rounded up) and refills at `per_second` (0: no rate
+/// limit); at most `concurrent` run at once (0: no limit). With `per_client`, every
+/// client has a bucket and count of its own.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_second: f64,
+    pub burst: Option<u32>,
+    pub concurrent: u32,
+    pub per_client: bool,
+}
+
+/// `[timeouts]` table: how long a tool call may run before it is aborted and an
+/// error returned. `tools` overrides the default for individual tools; 0 disables the limit.
+/// On SIGINT/SIGTERM, in-flight calls get `shutdown_grace_secs` to finish before they are aborted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    pub default_secs: u64,
+    pub tools: HashMap<String, u64>,
+    pub shutdown_grace_secs: u64,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            default_secs: 600,
+            tools: HashMap::new(),
+            shutdown_grace_secs: 30,
+        }
+    }
+}
+
+impl TimeoutsConfig {
+    pub fn for_tool(&self, tool: &str) -> Option<Duration> {
+        let secs = self.tools.get(tool).copied().unwrap_or(self.default_secs);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+}
+
+/// `[children]` table: limits on the external programs tools run (compilers, package
+/// managers, git, scripts). At most `max_concurrent` run at once and further ones are
+/// refused; one still running after `max_lifetime_secs` is killed. 0 disables a limit.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChildrenConfig {
+    pub max_concurrent: usize,
+    pub max_lifetime_secs: u64,
+}
+
+impl Default for ChildrenConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 32,
+            max_lifetime_secs: 3600,
+        }
+    }
+}
+
+/// `[tenants.<name>]` tables, for one HTTP server hosting several teams. `members`
+/// are the API key names and token subjects that belong to the tenant. Its sessions
+/// are confined to `allowed_roots`, both in the filesystem tools and for the path
+/// arguments of built-in tools (replacing `[policy] allowed_paths`), start in `cwd`
+/// (default: the first root) and keep gitent data in `gitent_db` (default:
+/// `.gitent/gitent.db` under the first root). `deny_tools` adds to `[policy] deny_tools`.
+/// Shell commands can reach past the roots, so its sessions may not run them
+/// (silent_script, txn_commit's `command`) unless `allow_shell` is set.
+/// `max_sessions` and `max_calls_per_day` cap its use, 0 meaning no limit. With
+/// `audit_path`, its calls are audited there instead of in the `[audit]` log.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantConfig {
+    pub members: Vec<String>,
+    pub allowed_roots: Vec<PathBuf>,
+    pub cwd: Option<PathBuf>,
+    pub gitent_db: Option<PathBuf>,
+    pub deny_tools: Vec<String>,
+    pub allow_shell: bool,
+    pub max_sessions: usize,
+    pub max_calls_per_day: u64,
+    pub audit_path: Option<PathBuf>,
+}
+
+impl Config {
+    /// Load the config from an explicit path, or from ./poly-mcp.toml (else
+    /// ./poly-mcp.yaml or ./poly-mcp.yml) if it exists. Files named `*.yaml` or
+    /// `*.yml` are read as YAML, with the same keys as the TOML tables, and any
+    /// other as TOML. A missing default file is not an error; a missing explicit file is.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(p) => p.to_path_buf(),
+            None => {
+                let default = std::iter::once(DEFAULT_CONFIG_FILE)
+                    .chain(DEFAULT_YAML_CONFIG_FILES.iter().copied())
+                    .map(PathBuf::from)
+                    .find(|default| default.exists());
+                match default {
+                    Some(default) => default,
+                    None => return Ok(Self::default()),
+                }
+            }
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let yaml = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        if yaml {
+            serde_yaml::from_str(&content).with_context(|| format!("Invalid config file: {}", path.display()))
+        } else {
+            toml::from_str(&content).with_context(|| format!("Invalid config file: {}", path.display()))
+        }
+    }
+
+    /// Resolve which modules are active: everything in `enable` (or all modules
+    /// when it is empty), minus everything in `disable`.
+    pub fn module_selection(&self) -> Result<ModuleSelection> {
+        ModuleSelection::new(&self.modules.enable, &self.modules.disable, MODULE_NAMES)
+    }
+}
+
+/// The set of modules whose tools are listed and callable
+#[derive(Debug, Clone, Default)]
+pub struct ModuleSelection {
+    // None means every module is enabled
+    enable: Option<BTreeSet<String>>,
+    disable: BTreeSet<String>,
+}
+
+impl ModuleSelection {
+    /// Build a selection from `enable`/`disable` lists, rejecting names that are
+    /// not in `known`. An empty `enable` list enables every module.
+    pub fn new(enable: &[String], disable: &[String], known: &[&str]) -> Result<Self> {
+        let enable = if enable.is_empty() {
+            None
+        } else {
+            Some(enable.iter().map(|m| canonical_module(m, known)).collect::<Result<_>>()?)
+        };
+        let disable = disable.iter().map(|m| canonical_module(m, known)).collect::<Result<_>>()?;
+
+        Ok(Self { enable, disable })
+    }
+
+    pub fn is_enabled(&self, module: &str) -> bool {
+        let enabled = self.enable.as_ref().is_none_or(|enable| enable.contains(module));
+        enabled && !self.disable.contains(module)
+    }
+
+    /// Fails with a descriptive error if `module`, which provides `tool`, is disabled
+    pub fn check_tool(&self, tool: &str, module: &str) -> Result<()> {
+        if self.is_enabled(module) {
+            Ok(())
+        } else {
+            Err(PolyError::Unavailable(format!(
+                "Tool '{}' is unavailable: module '{}' is disabled on this server (enable it with --enable {} or in {})",
+                tool, module, module, DEFAULT_CONFIG_FILE
+            ))
+            .into())
+        }
+    }
+}
+
+fn canonical_module(name: &str, known: &[&str]) -> Result<String> {
+    let name = name.trim().to_lowercase();
+    let name = match name.as_str() {
+        "fs" => "filesystem",
+        "net" => "network",
+        "ctx" => "context",
+        "clip" => "clipboard",
+        other => other,
+    };
+
+    known
+        .iter()
+        .find(|m| **m == name)
+        .map(|m| m.to_string())
+        .with_context(|| format!("Unknown module '{}'. Valid modules: {}", name, known.join(", ")))
+}
@@ -0,0 +1,208 @@
+// Categories of tool failures, with the JSON-RPC error codes they are reported under
+
+use serde_json::Value;
+use std::io;
+
+/// What kind of failure a tool call ran into. A tool can return one (wrapped in
+/// `anyhow::Error`) to pick its category; other errors are categorized by
+/// [`PolyError::of`]. The server reports the category's [`code`](PolyError::code) as the
+/// JSON-RPC error code and its [`name`](PolyError::name) as `code` in the error's `data`,
+/// so clients can tell failures apart without reading the message.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum PolyError {
+    /// Arguments missing, of the wrong type, or out of range
+    #[error("{0}")]
+    InvalidArguments(String),
+    /// No tool by that name on this server
+    #[error("{0}")]
+    UnknownTool(String),
+    /// A file, package, record or other thing the call refers to does not exist
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    AlreadyExists(String),
+    /// The operating system refused access
+    #[error("{0}")]
+    PermissionDenied(String),
+    /// The server's policy refused the call, or the user did not approve it
+    #[error("{0}")]
+    PolicyDenied(String),
+    /// A call rate limit or quota is used up; retrying later can succeed
+    #[error("{0}")]
+    RateLimited(String),
+    /// The state the call would change was changed by someone else, or is locked
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Timeout(String),
+    /// A program the tool runs is not installed or not on the PATH
+    #[error("{0}")]
+    ExternalToolMissing(String),
+    /// A remote host could not be reached
+    #[error("{0}")]
+    Network(String),
+    /// The tool's module is disabled, or not in this build
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Cancelled(String),
+    /// Anything else
+    #[error("{0}")]
+    Other(String),
+}
+
+impl PolyError {
+    /// JSON-RPC error code, from the range the spec reserves for servers
+    pub fn code(&self) -> i32 {
+        match self {
+            PolyError::Other(_) => -32000,
+            PolyError::Timeout(_) => -32001,
+            PolyError::NotFound(_) => -32002,
+            PolyError::UnknownTool(_) => -32003,
+            PolyError::PermissionDenied(_) => -32004,
+            PolyError::AlreadyExists(_) => -32005,
+            PolyError::Conflict(_) => -32006,
+            PolyError::ExternalToolMissing(_) => -32007,
+            PolyError::Network(_) => -32008,
+            PolyError::PolicyDenied(_) => -32009,
+            PolyError::RateLimited(_) => -32010,
+            PolyError::Unavailable(_) => -32011,
+            // JSON-RPC's Invalid params
+            PolyError::InvalidArguments(_) => -32602,
+            // LSP's RequestCancelled
+            PolyError::Cancelled(_) => -32800,
+        }
+    }
+
+    /// Name of the category, e.g. "not_found", as it appears in error data
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolyError::InvalidArguments(_) => "invalid_arguments",
+            PolyError::UnknownTool(_) => "unknown_tool",
+            PolyError::NotFound(_) => "not_found",
+            PolyError::AlreadyExists(_) => "already_exists",
+            PolyError::PermissionDenied(_) => "permission_denied",
+            PolyError::PolicyDenied(_) => "policy_denied",
+            PolyError::RateLimited(_) => "rate_limited",
+            PolyError::Conflict(_) => "conflict",
+            PolyError::Timeout(_) => "timeout",
+            PolyError::ExternalToolMissing(_) => "external_tool_missing",
+            PolyError::Network(_) => "network",
+            PolyError::Unavailable(_) => "unavailable",
+            PolyError::Cancelled(_) => "cancelled",
+            PolyError::Other(_) => "error",
+        }
+    }
+
+    /// This error with fields to add to the JSON-RPC error's `data`, such as the
+    /// current state a conflict was found against
+    pub fn with_data(self, data: Value) -> anyhow::Error {
+        Detailed { error: self, data }.into()
+    }
+
+    /// The category of any error a tool returned: the `PolyError` in its chain if there
+    /// is one, otherwise a guess from the I/O, git or HTTP error underneath, and failing
+    /// that from the message. The result carries the error's top-level message.
+    pub fn of(error: &anyhow::Error) -> PolyError {
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<PolyError>()) {
+            return e.clone();
+        }
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<Detailed>()) {
+            return e.error.clone();
+        }
+        let message = error.to_string();
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
+            if let Some(category) = io_category(e, &message) {
+                return category(message);
+            }
+        }
+        #[cfg(feature = "git")]
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<git2::Error>()) {
+            if let Some(category) = git_category(e) {
+                return category(message);
+            }
+        }
+        #[cfg(any(feature = "network", feature = "calc", feature = "llm"))]
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) {
+            if e.is_timeout() {
+                return PolyError::Timeout(message);
+            }
+            if e.is_connect() {
+                return PolyError::Network(message);
+            }
+        }
+        message_category(&message)(message)
+    }
+}
+
+/// A [`PolyError`] and the fields it adds to the error data, see [`PolyError::with_data`]
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct Detailed {
+    pub error: PolyError,
+    pub data: Value,
+}
+
+impl Detailed {
+    /// The fields attached anywhere in `error`'s chain
+    pub fn data_of(error: &anyhow::Error) -> Option<&Value> {
+        error.chain().find_map(|e| e.downcast_ref::<Detailed>()).map(|e| &e.data)
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn io_category(e: &io::Error, message: &str) -> Option<fn(String) -> PolyError> {
+    let category = match e.kind() {
+        // Modules add "Failed to run <program>" to errors from spawning commands
+        io::ErrorKind::NotFound if message.starts_with("Failed to run") => PolyError::ExternalToolMissing,
+        io::ErrorKind::NotFound => PolyError::NotFound,
+        io::ErrorKind::PermissionDenied => PolyError::PermissionDenied,
+        io::ErrorKind::AlreadyExists => PolyError::AlreadyExists,
+        io::ErrorKind::TimedOut => PolyError::Timeout,
+        io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected
+        | io::ErrorKind::AddrNotAvailable => PolyError::Network,
+        io::ErrorKind::InvalidInput => PolyError::InvalidArguments,
+        // A child process refused over [children] max_concurrent, or killed by the supervisor
+        io::ErrorKind::ResourceBusy => PolyError::RateLimited,
+        io::ErrorKind::Interrupted => PolyError::Cancelled,
+        _ => return None,
+    };
+    Some(category)
+}
+
+#[cfg(feature = "git")]
+fn git_category(e: &git2::Error) -> Option<fn(String) -> PolyError> {
+    let category = match e.code() {
+        git2::ErrorCode::NotFound => PolyError::NotFound,
+        git2::ErrorCode::Exists => PolyError::AlreadyExists,
+        git2::ErrorCode::Conflict
+        | git2::ErrorCode::MergeConflict
+        | git2::ErrorCode::Locked
+        | git2::ErrorCode::Modified
+        | git2::ErrorCode::Uncommitted
+        | git2::ErrorCode::NotFastForward => PolyError::Conflict,
+        git2::ErrorCode::Auth | git2::ErrorCode::Certificate => PolyError::PermissionDenied,
+        _ => return None,
+    };
+    Some(category)
+}
+
+/// Errors raised with plain messages, in the wording the modules use for them
+fn message_category(message: &str) -> fn(String) -> PolyError {
+    let lower = message.to_lowercase();
+    if message.starts_with("Missing '") || message.starts_with("Invalid ") || message.starts_with("Unknown action") {
+        PolyError::InvalidArguments
+    } else if message.starts_with("Unknown tool") {
+        PolyError::UnknownTool
+    } else if lower.contains("not found") || lower.contains("does not exist") || lower.contains("no such") {
+        PolyError::NotFound
+    } else if lower.contains("already exists") {
+        PolyError::AlreadyExists
+    } else {
+        PolyError::Other
+    }
+}
@@ -0,0 +1,222 @@
+// File type detection from a file's first bytes and its name: MIME type, text or binary, encoding, language and line endings
+
+use std::path::Path;
+
+/// How much of a file [`detect`] needs to see
+pub const SNIFF_BYTES: usize = 64 * 1024;
+
+/// What a file's content and name say about it
+#[derive(Debug, Clone)]
+pub struct FileType {
+    pub mime: String,
+    /// Text the tools can read and edit, rather than binary data
+    pub text: bool,
+    /// For text: "ascii", "utf-8", "utf-8-bom", "utf-16le", "utf-16be", or "non-utf8"
+    /// for 8-bit text in some other encoding
+    pub encoding: Option<&'static str>,
+    /// Programming, markup or data language, from the name or a `#!` line
+    pub language: Option<&'static str>,
+    /// For text: "lf", "crlf", "cr", "mixed", or "none" for a single line
+    pub line_endings: Option<&'static str>,
+}
+
+/// Work out the type of the file at `path` from its first [`SNIFF_BYTES`] bytes.
+/// Magic numbers win (so a PNG named `notes.txt` is a PNG); text without one is
+/// typed by its extension or name.
+pub fn detect(path: &Path, head: &[u8]) -> FileType {
+    let by_name = known_name(path);
+    let encoding = text_encoding(head);
+
+    if let Some(kind) = infer::get(head) {
+        if kind.matcher_type() != infer::MatcherType::Text || encoding.is_none() {
+            return FileType {
+                mime: kind.mime_type().to_string(),
+                text: false,
+                encoding: None,
+                language: None,
+                line_endings: None,
+            };
+        }
+    }
+
+    let Some(encoding) = encoding else {
+        return FileType {
+            mime: "application/octet-stream".to_string(),
+            text: false,
+            encoding: None,
+            language: None,
+            line_endings: None,
+        };
+    };
+    let text = decode(head, encoding);
+    let known = by_name.or_else(|| shebang(&text));
+    let mime = known
+        .map(|(mime, _)| mime)
+        .or_else(|| infer::get(head).map(|kind| kind.mime_type()))
+        .unwrap_or("text/plain");
+    FileType {
+        mime: mime.to_string(),
+        text: true,
+        encoding: Some(encoding),
+        language: known.map(|(_, language)| language),
+        line_endings: Some(line_endings(&text)),
+    }
+}
+
+/// The line-ending style of `text`
+pub fn line_endings(text: &str) -> &'static str {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+    match (lf > 0, crlf > 0, cr > 0) {
+        (false, false, false) => "none",
+        (true, false, false) => "lf",
+        (false, true, false) => "crlf",
+        (false, false, true) => "cr",
+        _ => "mixed",
+    }
+}
+
+/// How comments are written in `language` (as [`detect`] names it): the prefixes of line
+/// comments, and the delimiters of block comments
+pub fn comment_syntax(language: &str) -> (&'static [&'static str], Option<(&'static str, &'static str)>) {
+    const C_STYLE: (&[&str], Option<(&str, &str)>) = (&["//"], Some(("/*", "*/")));
+    const HASH: (&[&str], Option<(&str, &str)>) = (&["#"], None);
+    const MARKUP: (&[&str], Option<(&str, &str)>) = (&[], Some(("<!--", "-->")));
+    match language {
+        "rust" | "c" | "cpp" | "csharp" | "go" | "java" | "kotlin" | "swift" | "javascript" | "typescript"
+        | "scss" | "php" => C_STYLE,
+        "css" => (&[], Some(("/*", "*/"))),
+        "python" => (&["#"], Some(("\"\"\"", "\"\"\""))),
+        "shell" | "fish" | "ruby" | "perl" | "make" | "dockerfile" | "cmake" | "yaml" | "toml" => HASH,
+        "ini" => (&["#", ";"], None),
+        "powershell" => (&["#"], Some(("<#", "#>"))),
+        "batch" => (&["::", "REM ", "rem "], None),
+        "lua" => (&["--"], Some(("--[[", "]]"))),
+        "sql" => (&["--"], Some(("/*", "*/"))),
+        "html" | "xml" | "markdown" | "vue" => MARKUP,
+        "latex" => (&["%"], None),
+        _ => (&[], None),
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// The encoding of `head` if it looks like text: a byte order mark, valid UTF-8
+/// (allowing a character cut off at the end of the window), or 8-bit text with no
+/// NUL bytes and few control characters
+fn text_encoding(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some("utf-8-bom");
+    }
+    if head.starts_with(&[0xFF, 0xFE]) {
+        return Some("utf-16le");
+    }
+    if head.starts_with(&[0xFE, 0xFF]) {
+        return Some("utf-16be");
+    }
+    if head.contains(&0) {
+        return None;
+    }
+    let utf8 = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() >= SNIFF_BYTES,
+    };
+    if utf8 {
+        return Some(if head.is_ascii() { "ascii" } else { "utf-8" });
+    }
+    let control = head
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0C))
+        .count();
+    (control * 100 <= head.len()).then_some("non-utf8")
+}
+
+fn decode(head: &[u8], encoding: &str) -> String {
+    let units = |bytes: &[u8], from: fn([u8; 2]) -> u16| -> Vec<u16> {
+        bytes.chunks_exact(2).map(|pair| from([pair[0], pair[1]])).collect()
+    };
+    match encoding {
+        "utf-16le" => String::from_utf16_lossy(&units(&head[2..], u16::from_le_bytes)),
+        "utf-16be" => String::from_utf16_lossy(&units(&head[2..], u16::from_be_bytes)),
+        _ => String::from_utf8_lossy(head).into_owned(),
+    }
+}
+
+/// MIME type and language of files known by their extension or whole name
+fn known_name(path: &Path) -> Option<(&'static str, &'static str)> {
+    let name = path.file_name()?.to_str()?;
+    let by_name = match name {
+        "Makefile" | "makefile" | "GNUmakefile" => Some(("text/x-makefile", "make")),
+        "Dockerfile" | "Containerfile" => Some(("text/x-dockerfile", "dockerfile")),
+        "CMakeLists.txt" => Some(("text/x-cmake", "cmake")),
+        "Cargo.lock" => Some(("application/toml", "toml")),
+        _ => None,
+    };
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let known = match extension.as_str() {
+        "rs" => ("text/x-rust", "rust"),
+        "py" | "pyi" => ("text/x-python", "python"),
+        "js" | "mjs" | "cjs" | "jsx" => ("text/javascript", "javascript"),
+        "ts" | "mts" | "cts" | "tsx" => ("text/x-typescript", "typescript"),
+        "json" => ("application/json", "json"),
+        "toml" => ("application/toml", "toml"),
+        "yaml" | "yml" => ("application/yaml", "yaml"),
+        "md" | "markdown" => ("text/markdown", "markdown"),
+        "html" | "htm" => ("text/html", "html"),
+        "css" => ("text/css", "css"),
+        "scss" => ("text/x-scss", "scss"),
+        "xml" => ("application/xml", "xml"),
+        "svg" => ("image/svg+xml", "xml"),
+        "csv" => ("text/csv", "csv"),
+        "tsv" => ("text/tab-separated-values", "tsv"),
+        "sh" | "bash" | "zsh" => ("text/x-shellscript", "shell"),
+        "fish" => ("text/x-shellscript", "fish"),
+        "ps1" => ("text/x-powershell", "powershell"),
+        "bat" | "cmd" => ("text/x-batch", "batch"),
+        "c" | "h" => ("text/x-c", "c"),
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => ("text/x-c++", "cpp"),
+        "cs" => ("text/x-csharp", "csharp"),
+        "go" => ("text/x-go", "go"),
+        "java" => ("text/x-java", "java"),
+        "kt" | "kts" => ("text/x-kotlin", "kotlin"),
+        "swift" => ("text/x-swift", "swift"),
+        "rb" => ("text/x-ruby", "ruby"),
+        "php" => ("application/x-httpd-php", "php"),
+        "pl" | "pm" => ("text/x-perl", "perl"),
+        "lua" => ("text/x-lua", "lua"),
+        "sql" => ("application/sql", "sql"),
+        "ini" | "cfg" | "conf" => ("text/plain", "ini"),
+        "tex" => ("text/x-tex", "latex"),
+        "vue" => ("text/x-vue", "vue"),
+        "txt" | "log" => ("text/plain", "text"),
+        _ => return None,
+    };
+    Some(known)
+}
+
+/// MIME type and language of a script from its `#!` line, e.g. `#!/usr/bin/env python3`
+fn shebang(text: &str) -> Option<(&'static str, &'static str)> {
+    let line = text.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let known = match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => ("text/x-shellscript", "shell"),
+        "fish" => ("text/x-shellscript", "fish"),
+        "python" => ("text/x-python", "python"),
+        "node" | "deno" | "bun" => ("text/javascript", "javascript"),
+        "ruby" => ("text/x-ruby", "ruby"),
+        "perl" => ("text/x-perl", "perl"),
+        "php" => ("application/x-httpd-php", "php"),
+        "lua" => ("text/x-lua", "lua"),
+        _ => return None,
+    };
+    Some(known)
+}
@@ -0,0 +1,362 @@
+// Remediation hints for failed tool calls, attached to the JSON-RPC error's data
+
+use serde_json::{json, Value};
+use std::io;
+use std::path::Path;
+
+/// Tools that write to the path in their `path` or `destination` argument, and fail
+/// with "not found" when its parent directory is missing
+const WRITERS: &[(&str, &str)] = &[
+    ("fs_write", "path"),
+    ("fs_create", "path"),
+    ("fs_move", "destination"),
+    ("fs_copy", "destination"),
+    ("fs_link", "path"),
+    ("clip_paste_file", "path"),
+    ("xlsx_write", "path"),
+    ("md_render", "output_path"),
+];
+
+/// A call the client could make to get past an error
+struct Suggestion {
+    tool: &'static str,
+    arguments: Value,
+}
+
+/// What went wrong, in terms a client can act on
+struct Hint {
+    kind: &'static str,
+    hint: String,
+    suggestions: Vec<Suggestion>,
+}
+
+impl Hint {
+    fn new(kind: &'static str, hint: impl Into<String>) -> Self {
+        Self {
+            kind,
+            hint: hint.into(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    fn suggest(mut self, tool: &'static str, arguments: Value) -> Self {
+        self.suggestions.push(Suggestion { tool, arguments });
+        self
+    }
+}
+
+/// Error data for a failed call to `tool`, or `None` when the error is not one we
+/// know how to explain. `args` are the call's arguments after path resolution;
+/// `available` says whether the caller may use a tool, so only usable tools are suggested.
+///
+/// The result has the error `kind` (e.g. "not_found", "merge_conflict"), a `hint`
+/// in plain words, `suggestions` of `{tool, arguments}` calls to try next, and the
+/// full error chain as `cause` when it says more than the message.
+pub fn enrich(tool: &str, args: &Value, error: &anyhow::Error, available: impl Fn(&str) -> bool) -> Option<Value> {
+    let hint = classify(tool, args, error)?;
+    let suggestions: Vec<Value> = hint
+        .suggestions
+        .into_iter()
+        .filter(|s| available(s.tool))
+        .map(|s| json!({ "tool": s.tool, "arguments": s.arguments }))
+        .collect();
+
+    let mut data = json!({
+        "kind": hint.kind,
+        "hint": hint.hint,
+        "suggestions": suggestions,
+    });
+    let cause = format!("{:#}", error);
+    if cause != error.to_string() {
+        data["cause"] = json!(cause);
+    }
+    Some(data)
+}
+
+/// Error data for a call to `tool` that ran past its time limit
+pub fn timed_out(tool: &str) -> Value {
+    json!({
+        "kind": "timed_out",
+        "hint": format!(
+            "The call was abandoned. Retry with less work (a narrower path or smaller input), or raise the limit for '{}' under [timeouts.tools] in the server config",
+            tool
+        ),
+        "suggestions": [],
+    })
+}
+
+/// Error data for a call to `tool`, which does not exist: `closest` are the real
+/// tools it was likely meant to be (see [`closest`]), suggested with the same arguments
+pub fn unknown_tool(tool: &str, args: &Value, closest: &[String]) -> Value {
+    let hint = match closest {
+        [] => "No tool has this name or one like it. List the tools with tools/list".to_string(),
+        _ => format!("'{}' is not a tool; the name is likely misspelled. Retry with the closest match", tool),
+    };
+    json!({
+        "kind": "unknown_tool",
+        "hint": hint,
+        "did_you_mean": closest,
+        "suggestions": closest.iter().map(|name| json!({ "tool": name, "arguments": args })).collect::<Vec<_>>(),
+    })
+}
+
+/// Up to three of `names` that `name` is likely a misspelling of, closest first: those
+/// within an edit distance of half the longer name, ignoring case and separators (see
+/// [`normalize`]), and no more than one edit further than the closest (so `fs_list`
+/// finds `fs_ld`, and `fs.read` finds `fs_read`)
+pub fn closest<'a>(name: &str, names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let name = normalize(name);
+    let mut matches: Vec<(usize, &str)> = names
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &normalize(candidate)), candidate))
+        .filter(|(distance, candidate)| {
+            *distance <= (name.chars().count().max(candidate.chars().count()) / 2).max(2)
+        })
+        .collect();
+    matches.sort();
+    // A much closer match makes the others noise
+    let best = matches.first().map_or(0, |(distance, _)| *distance);
+    matches
+        .into_iter()
+        .take_while(|(distance, _)| *distance <= best + 1)
+        .take(3)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// The parameters among `params` that `key`, an argument the tool does not take, was
+/// likely meant to be, best first: one spelled the same but for case and separators
+/// (see [`normalize`]), then misspellings within an edit for every three characters,
+/// then those named by one of the key's words (`filePath` for `path`). Stricter than
+/// [`closest`], since clients may pass arguments a tool ignores.
+pub fn closest_params<'a>(key: &str, params: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let key = normalize(key);
+    let mut matches: Vec<(usize, &str)> = params
+        .into_iter()
+        .filter_map(|param| {
+            let normalized = normalize(param);
+            let distance = edit_distance(&key, &normalized);
+            let allowed = (key.chars().count().min(normalized.chars().count()) / 3).max(1);
+            if distance <= allowed {
+                Some((distance, param))
+            } else if key.split('_').any(|word| word == normalized) {
+                Some((usize::MAX, param))
+            } else {
+                None
+            }
+        })
+        .collect();
+    matches.sort();
+    matches.into_iter().take(3).map(|(_, param)| param.to_string()).collect()
+}
+
+/// `name` in lower snake case, so that spellings differing only in case and
+/// separators compare equal: `filePath`, `file-path` and `FILE_PATH` all become `file_path`
+pub fn normalize(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if matches!(c, '.' | '-' | ' ' | '_') {
+            normalized.push('_');
+        } else if c.is_uppercase() {
+            if previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+                normalized.push('_');
+            }
+            normalized.extend(c.to_lowercase());
+        } else {
+            normalized.push(c);
+        }
+        previous = Some(c);
+    }
+    normalized
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn classify(tool: &str, args: &Value, error: &anyhow::Error) -> Option<Hint> {
+    #[cfg(feature = "git")]
+    if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<git2::Error>()) {
+        if let Some(hint) = git_hint(args, e) {
+            return Some(hint);
+        }
+    }
+    if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
+        if let Some(hint) = io_hint(tool, args, e) {
+            return Some(hint);
+        }
+    }
+    // Only the modules that make HTTP requests can fail with these
+    #[cfg(any(feature = "network", feature = "calc", feature = "llm"))]
+    if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) {
+        if e.is_timeout() || e.is_connect() {
+            return Some(Hint::new(
+                "network_unreachable",
+                "The remote host did not answer. Check the URL and that the service is running, then retry",
+            ));
+        }
+    }
+    message_hint(tool, args, &format!("{:#}", error))
+}
+
+#[cfg(feature = "git")]
+fn git_hint(args: &Value, e: &git2::Error) -> Option<Hint> {
+    let path = args["path"].as_str().unwrap_or(".");
+    let hint = match (e.class(), e.code()) {
+        (git2::ErrorClass::Repository, git2::ErrorCode::NotFound) => Hint::new(
+            "not_a_repository",
+            format!("{} is not the root of a git repository. Find the enclosing repository, or change the session's working directory to it", path),
+        )
+        .suggest("git_discover", json!({ "path": path }))
+        .suggest("session_cwd", json!({})),
+        (_, git2::ErrorCode::Conflict | git2::ErrorCode::MergeConflict) => Hint::new(
+            "merge_conflict",
+            "Local changes conflict with the operation. Review them and commit them first, or resolve the conflicts, then retry",
+        )
+        .suggest("git_status", json!({ "path": path }))
+        .suggest("git_diff", json!({ "path": path })),
+        (_, git2::ErrorCode::Uncommitted | git2::ErrorCode::Modified) => Hint::new(
+            "uncommitted_changes",
+            "The working tree has uncommitted changes. Commit them first, then retry",
+        )
+        .suggest("git_status", json!({ "path": path }))
+        .suggest("git_diff", json!({ "path": path })),
+        (_, git2::ErrorCode::UnbornBranch) => Hint::new(
+            "empty_repository",
+            "The repository has no commits yet. Make the first commit, then retry",
+        )
+        .suggest("git_commit", json!({ "path": path, "message": "Initial commit" })),
+        (_, git2::ErrorCode::Locked) => Hint::new(
+            "locked",
+            "Another git process holds the repository lock. Wait for it to finish; if none is running, remove the stale .git/index.lock",
+        ),
+        _ => return None,
+    };
+    Some(hint)
+}
+
+fn io_hint(tool: &str, args: &Value, e: &io::Error) -> Option<Hint> {
+    let hint = match e.kind() {
+        io::ErrorKind::NotFound => {
+            // A missing source is the likelier culprit than a missing destination
+            let missing = ["source", "path"]
+                .iter()
+                .filter_map(|key| args[*key].as_str())
+                .map(Path::new)
+                .find(|path| !path.exists());
+            let target = WRITERS
+                .iter()
+                .find(|(writer, _)| *writer == tool)
+                .and_then(|(_, key)| args[*key].as_str())
+                .map(Path::new);
+            let missing_parent = target
+                .and_then(Path::parent)
+                .filter(|parent| !parent.as_os_str().is_empty() && !parent.exists());
+
+            match (missing, missing_parent) {
+                (Some(path), _) if Some(path) != target => not_found(path),
+                (_, Some(parent)) => Hint::new(
+                    "not_found",
+                    format!("The directory {} does not exist. Create it, then retry", parent.display()),
+                )
+                .suggest("fs_create", json!({ "path": parent.display().to_string(), "type": "dir" })),
+                (Some(path), None) => not_found(path),
+                (None, None) => return None,
+            }
+        }
+        io::ErrorKind::PermissionDenied => {
+            let path = args["path"].as_str().or(args["source"].as_str())?;
+            Hint::new(
+                "permission_denied",
+                format!("The server may not access {}. Check its permissions and owner", path),
+            )
+            .suggest("fs_permissions", json!({ "path": path }))
+        }
+        io::ErrorKind::AlreadyExists => {
+            let path = args["path"].as_str().or(args["destination"].as_str())?;
+            Hint::new("already_exists", format!("{} already exists. Inspect it, or pick another name", path))
+                .suggest("fs_stat", json!({ "path": path }))
+        }
+        _ => return None,
+    };
+    Some(hint)
+}
+
+fn not_found(path: &Path) -> Hint {
+    let hint = Hint::new(
+        "not_found",
+        format!("{} does not exist. Check the spelling, or search for it", path.display()),
+    );
+    match search_from(path) {
+        Some((dir, name)) => hint.suggest("fs_find", json!({ "path": dir, "pattern": name })),
+        None => hint,
+    }
+}
+
+/// Errors only recognizable by their message: ones this crate raises itself, and
+/// compiler output passed through by tools that run builds
+fn message_hint(tool: &str, args: &Value, message: &str) -> Option<Hint> {
+    let hint = if message.contains("The commit was rolled back") {
+        let txn = args["txn"].as_str().unwrap_or_default();
+        let mut hint = Hint::new(
+            "check_failed",
+            "The changes did not pass the commit's check and were undone; the transaction is still open. Fix the staged files and commit again, or abort",
+        )
+        .suggest("txn_status", json!({ "txn": txn, "diff": true }));
+        if let Some(path) = args["diagnostics"].as_str() {
+            hint = hint.suggest("diagnostics_get", json!({ "path": path }));
+        }
+        hint
+    } else if message.contains("error[E") || message.contains("could not compile") {
+        let path = args["path"].as_str().or(args["cwd"].as_str()).unwrap_or(".");
+        Hint::new("compile_error", "The code does not compile. Get the structured diagnostics to find the errors")
+            .suggest("diagnostics_get", json!({ "path": path }))
+    } else if message.starts_with("Policy denied") {
+        Hint::new(
+            "policy_denied",
+            "The server's policy forbids this call. Retrying will not help; ask the user to change [policy] if it is needed",
+        )
+    } else if message.contains("module '") && message.contains("is disabled") {
+        Hint::new(
+            "module_disabled",
+            "The tool's module is not enabled on this server. Use another tool, or ask the user to enable the module",
+        )
+    } else if message.starts_with("Unknown argument '") {
+        Hint::new(
+            "unknown_argument",
+            format!("'{}' has no parameter by that name; the name is likely misspelled. Retry with the suggested names", tool),
+        )
+    } else if message.starts_with("Missing '") || message.starts_with("Invalid ") {
+        Hint::new(
+            "invalid_arguments",
+            format!("The arguments do not match what '{}' expects. Get its parameters and an example call from tool_help", tool),
+        )
+        .suggest("tool_help", json!({ "name": tool }))
+    } else {
+        return None;
+    };
+    Some(hint)
+}
+
+/// The closest existing ancestor of a missing `path`, and a pattern for its file name
+fn search_from(path: &Path) -> Option<(String, String)> {
+    let name = path.file_name()?.to_str()?;
+    let dir = path.ancestors().skip(1).find(|dir| dir.is_dir())?;
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    Some((dir.display().to_string(), name.to_string()))
+}
+
+/// Levenshtein distance: insertions, deletions and substitutions of one character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,88 @@
+// Idempotency keys: a retried tool call with the same key gets the first call's result
+
+use crate::registry::ToolOutput;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The argument the dispatch layer takes out of every tools/call
+pub const ARGUMENT: &str = "idempotency_key";
+
+/// Most keys kept at once; past it the oldest are forgotten before they expire
+const MAX_ENTRIES: usize = 10_000;
+
+struct Entry {
+    tool: String,
+    args: Value,
+    created: Instant,
+    // Held while the first call runs, so a retry that arrives meanwhile waits for it
+    result: Arc<tokio::sync::Mutex<Option<ToolOutput>>>,
+}
+
+/// Results of calls made with an idempotency key, kept for `ttl`. Only successful
+/// results are stored; after a failure the next call with the key runs the tool again.
+/// Keys belong to an owner (the caller and tenant), not a session, so a client that
+/// retries on a new session after a dropped connection still gets the first result.
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<(String, String), Entry>>,
+    ttl: Duration,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Remove the idempotency key from `args`, if the call has one
+    pub fn take_key(args: &mut Value) -> Result<Option<String>> {
+        match args.as_object_mut().and_then(|map| map.remove(ARGUMENT)) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(key)) if !key.is_empty() => Ok(Some(key)),
+            Some(_) => anyhow::bail!("'{}' must be a non-empty string", ARGUMENT),
+        }
+    }
+
+    /// The result slot for `owner`'s `key`. Fails if the key was already used for a
+    /// different tool or different arguments, which is a client bug rather than a retry.
+    pub fn slot(&self, owner: &str, key: &str, tool: &str, args: &Value) -> Result<Arc<tokio::sync::Mutex<Option<ToolOutput>>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+
+        let id = (owner.to_string(), key.to_string());
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&id) {
+            let oldest = entries.iter().min_by_key(|(_, entry)| entry.created).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let entry = entries.entry(id).or_insert_with(|| Entry {
+            tool: tool.to_string(),
+            args: args.clone(),
+            created: now,
+            result: Arc::default(),
+        });
+        if entry.tool != tool || entry.args != *args {
+            anyhow::bail!(
+                "Idempotency key '{}' was already used for a different call to '{}'; use a new key for new calls",
+                key,
+                entry.tool
+            );
+        }
+        Ok(entry.result.clone())
+    }
+
+    /// Release `owner`'s `key` after its call failed, so the retry may fix the arguments
+    pub fn forget(&self, owner: &str, key: &str, slot: &Arc<tokio::sync::Mutex<Option<ToolOutput>>>) {
+        let mut entries = self.entries.lock().unwrap();
+        let id = (owner.to_string(), key.to_string());
+        if entries.get(&id).is_some_and(|entry| Arc::ptr_eq(&entry.result, slot)) {
+            entries.remove(&id);
+        }
+    }
+}
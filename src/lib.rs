@@ -1,21 +1,100 @@
 // Poly MCP Library
 // This crate provides MCP (Model Context Protocol) modules that can be integrated into other applications
 
+#[cfg(feature = "filesystem")]
+pub mod archive;
+pub mod audit;
+pub mod auth;
+pub mod children;
+pub mod config;
+pub mod error;
+#[cfg(feature = "filesystem")]
+pub mod filetype;
+pub mod hints;
+pub mod idempotency;
+pub mod locale;
+pub mod logging;
+pub mod middleware;
 pub mod modules;
+pub mod naming;
+pub mod paths;
+#[cfg(any(feature = "text", feature = "filesystem"))]
+pub mod patch;
+pub mod pagination;
+pub mod plugins;
+pub mod policy;
+pub mod profiles;
+pub mod progress;
+pub mod provenance;
+pub mod registry;
+pub mod sampling;
+pub mod server;
+pub mod session;
+#[cfg(feature = "filesystem")]
+pub mod snapshots;
+pub mod storage;
+pub mod telemetry;
+pub mod tenants;
+pub mod usage;
+#[cfg(feature = "filesystem")]
+pub mod watches;
 
 // Re-export commonly used items
-pub use modules::{
-    clipboard::ClipboardModule,
-    filesystem::FilesystemModule,
-    diagnostics::DiagnosticsModule,
-    silent::SilentModule,
-    time::TimeModule,
-    network::NetworkModule,
-    context::ContextModule,
-    git::GitModule,
-    input::InputModule,
-    transform::TransformModule,
-};
+pub use error::PolyError;
+pub use registry::{Lazy, ToolModule, ToolOutput, ToolRegistry};
+pub use middleware::Middleware;
+pub use server::{PolyMcp, PolyMcpBuilder};
+pub use modules::{prompts::PromptsModule, session::{SessionCwd, SessionModule}};
+#[cfg(feature = "apps")]
+pub use modules::apps::AppsModule;
+#[cfg(feature = "calc")]
+pub use modules::calc::CalcModule;
+#[cfg(feature = "clipboard")]
+pub use modules::clipboard::ClipboardModule;
+#[cfg(feature = "filesystem")]
+pub use modules::filesystem::FilesystemModule;
+#[cfg(feature = "diagnostics")]
+pub use modules::diagnostics::DiagnosticsModule;
+#[cfg(feature = "silent")]
+pub use modules::silent::SilentModule;
+#[cfg(feature = "text")]
+pub use modules::text::TextModule;
+#[cfg(feature = "time")]
+pub use modules::time::TimeModule;
+#[cfg(feature = "llm")]
+pub use modules::llm::{LlmClient, LlmModule};
+#[cfg(feature = "network")]
+pub use modules::network::NetworkModule;
+#[cfg(feature = "power")]
+pub use modules::power::PowerModule;
+#[cfg(feature = "regex")]
+pub use modules::regex_lab::RegexModule;
+#[cfg(feature = "settings")]
+pub use modules::settings::SettingsModule;
+#[cfg(feature = "context")]
+pub use modules::context::ContextModule;
+#[cfg(feature = "crypto")]
+pub use modules::crypto::CryptoModule;
+#[cfg(feature = "git")]
+pub use modules::git::GitModule;
+#[cfg(feature = "input")]
+pub use modules::input::InputModule;
+#[cfg(feature = "md")]
+pub use modules::markdown::MarkdownModule;
+#[cfg(feature = "transform")]
+pub use modules::transform::TransformModule;
+#[cfg(feature = "txn")]
+pub use modules::txn::{Transactions, TxnModule};
+#[cfg(feature = "xlsx")]
+pub use modules::xlsx::XlsxModule;
+
+/// Local speech-to-text with whisper.cpp. Needs cmake and a C++ compiler to build.
+#[cfg(feature = "whisper")]
+pub use modules::media::MediaModule;
+
+/// Headless Chrome automation over CDP. Needs Chrome or Chromium at runtime.
+#[cfg(feature = "browser")]
+pub use modules::browser::BrowserModule;
 
 /// VARP premium integration — spawns `varp-bridge` binary at runtime.
 /// No VARP source dependency. Requires: varp-bridge in PATH + VARP_LICENSE_KEY env.
@@ -0,0 +1,45 @@
+// External commands run in the C locale, and number parsing that tolerates localized output
+
+use std::process::Command;
+
+/// Make `command` print untranslated messages and '.' decimal points, whatever
+/// the user's locale: LC_ALL overrides LANG and every LC_* category. Only use it
+/// for commands whose output is parsed rather than shown to the user.
+pub fn untranslated(command: &mut Command) -> &mut Command {
+    command.env("LC_ALL", "C").env_remove("LANGUAGE")
+}
+
+/// A decimal number written with either '.' or ',' as the decimal separator,
+/// for output that is localized even in the C locale (Windows commands)
+pub fn parse_decimal(text: &str) -> Option<f64> {
+    text.trim().replace(',', ".").parse().ok()
+}
+
+/// The numbers in `text`, in order, wherever they appear between words
+pub fn numbers(text: &str) -> Vec<f64> {
+    text.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .map(|part| part.trim_matches(|c| c == '.' || c == ','))
+        .filter(|part| !part.is_empty())
+        .filter_map(parse_decimal)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_either_decimal_separator() {
+        assert_eq!(parse_decimal("0.045"), Some(0.045));
+        assert_eq!(parse_decimal(" 11,175 "), Some(11.175));
+        assert_eq!(parse_decimal("ms"), None);
+    }
+
+    #[test]
+    fn finds_numbers_between_words() {
+        assert_eq!(numbers("Packets: Sent = 4, Received = 3, Lost = 1 (25% loss),"), vec![4.0, 3.0, 1.0, 25.0]);
+        assert_eq!(numbers("3 Pakete übertragen, 3 empfangen, 0% Paketverlust, Zeit 2003ms"), vec![3.0, 3.0, 0.0, 2003.0]);
+        assert_eq!(numbers("Zeit=11,2 ms"), vec![11.2]);
+        assert!(numbers("no numbers here.").is_empty());
+    }
+}
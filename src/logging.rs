@@ -0,0 +1,200 @@
+// MCP logging: tracing events forwarded to clients as notifications/message
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// MCP log levels (RFC 5424 severities), least severe first
+pub const LEVELS: &[&str] = &["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"];
+
+/// Level a client gets before it sends logging/setLevel
+pub const DEFAULT_LEVEL: &str = "warning";
+
+/// Debug and info events are only forwarded from this crate, not from its dependencies
+const CRATE_TARGET: &str = "poly_mcp";
+
+/// Events logged outside a tool call reach every client, but only from "warning" up
+const UNSCOPED_MIN_RANK: u8 = 3;
+
+// Every server instance's sink, for events that happen outside a tool call
+static SINKS: Mutex<Vec<Weak<LogSink>>> = Mutex::new(Vec::new());
+
+tokio::task_local! {
+    static CURRENT: Arc<LogSink>;
+}
+
+/// Where one server instance's log messages go, and the least severe level its
+/// client asked for
+pub struct LogSink {
+    level: AtomicU8,
+    notifier: broadcast::Sender<Value>,
+}
+
+impl LogSink {
+    /// A sink sending to `notifier` at [`DEFAULT_LEVEL`], registered to receive
+    /// events that happen outside tool calls
+    pub fn register(notifier: broadcast::Sender<Value>) -> Arc<Self> {
+        let sink = Arc::new(Self {
+            level: AtomicU8::new(rank(DEFAULT_LEVEL).unwrap_or_default()),
+            notifier,
+        });
+        let mut sinks = SINKS.lock().unwrap();
+        sinks.retain(|sink| sink.strong_count() > 0);
+        sinks.push(Arc::downgrade(&sink));
+        sink
+    }
+
+    /// Handle logging/setLevel
+    pub fn set_level(&self, level: &str) -> Result<()> {
+        let rank = rank(level)
+            .ok_or_else(|| anyhow::anyhow!("Unknown log level '{}' (expected one of: {})", level, LEVELS.join(", ")))?;
+        self.level.store(rank, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether the message was at or above the client's level, and so sent
+    fn send(&self, rank: u8, logger: &str, data: &Value) -> bool {
+        if rank < self.level.load(Ordering::SeqCst) {
+            return false;
+        }
+        // No subscribers is not an error
+        let _ = self.notifier.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {
+                "level": LEVELS[rank as usize],
+                "logger": logger,
+                "data": data
+            }
+        }));
+        true
+    }
+
+    fn level(&self) -> &'static str {
+        LEVELS[self.level.load(Ordering::SeqCst) as usize]
+    }
+}
+
+/// Run a tool call so the events it logs go only to its own server instance.
+/// Warnings and errors logged anywhere else go to every instance.
+pub async fn scope<F: Future>(sink: Arc<LogSink>, call: F) -> F::Output {
+    CURRENT.scope(sink, call).await
+}
+
+/// Send `data` to the current tool call's client as a notifications/message at
+/// `level`, for tools that deliver messages to the user through the client. Fails
+/// outside a tool call, and when the client asked only for more severe messages.
+pub fn message(level: &str, logger: &str, data: &Value) -> Result<()> {
+    let rank = rank(level)
+        .ok_or_else(|| anyhow::anyhow!("Unknown log level '{}' (expected one of: {})", level, LEVELS.join(", ")))?;
+    let sink = CURRENT
+        .try_with(Arc::clone)
+        .map_err(|_| anyhow::anyhow!("No client to send the message to outside a tool call"))?;
+    if !sink.send(rank, logger, data) {
+        anyhow::bail!(
+            "The client only takes messages from level '{}' up (logging/setLevel); this one is '{}'",
+            sink.level(),
+            level
+        );
+    }
+    Ok(())
+}
+
+/// A [`tracing_subscriber`] layer that forwards events to MCP clients. Install it
+/// next to the usual formatting layer:
+///
+/// ```no_run
+/// use tracing_subscriber::prelude::*;
+///
+/// tracing_subscriber::registry()
+///     .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+///     .with(poly_mcp::logging::McpLogLayer)
+///     .init();
+/// ```
+pub struct McpLogLayer;
+
+impl<S: tracing::Subscriber> Layer<S> for McpLogLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let rank = match *metadata.level() {
+            tracing::Level::ERROR => 4,
+            tracing::Level::WARN => 3,
+            tracing::Level::INFO => 1,
+            _ => 0,
+        };
+        if rank < 3 && !metadata.target().starts_with(CRATE_TARGET) {
+            return;
+        }
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let data = if fields.other.is_empty() {
+            json!(fields.message)
+        } else {
+            let mut data = fields.other;
+            data.insert("message".to_string(), json!(fields.message));
+            Value::Object(data)
+        };
+
+        let scoped = CURRENT.try_with(|sink| {
+            sink.send(rank, metadata.target(), &data);
+        });
+        // Other clients' session starts and ends are none of a client's business
+        if scoped.is_err() && rank >= UNSCOPED_MIN_RANK {
+            let sinks: Vec<Arc<LogSink>> = SINKS.lock().unwrap().iter().filter_map(Weak::upgrade).collect();
+            for sink in sinks {
+                sink.send(rank, metadata.target(), &data);
+            }
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn rank(level: &str) -> Option<u8> {
+    LEVELS.iter().position(|l| *l == level).map(|rank| rank as u8)
+}
+
+/// An event's message and its other fields
+#[derive(Default)]
+struct Fields {
+    message: String,
+    other: Map<String, Value>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.other.insert(field.name().to_string(), json!(value));
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.other.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.other.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.other.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.other.insert(field.name().to_string(), json!(format!("{:?}", value)));
+        }
+    }
+}
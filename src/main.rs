@@ -1,38 +1,19 @@
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::io::{self, BufRead, Write};
-use std::sync::Arc;
+use std::io;
+use std::path::PathBuf;
 use anyhow::Result;
 use clap::Parser;
 use is_terminal::IsTerminal;
-use tokio::sync::Mutex;
-use axum::{
-    extract::State,
-    response::{IntoResponse, Response},
-    routing::post,
-    Json, Router,
-};
-use tower_http::cors::CorsLayer;
-
-mod modules;
-use modules::{
-    clipboard::ClipboardModule,
-    filesystem::FilesystemModule,
-    diagnostics::DiagnosticsModule,
-    silent::SilentModule,
-    time::TimeModule,
-    network::NetworkModule,
-    context::ContextModule,
-    git::GitModule,
-    input::InputModule,
-    transform::TransformModule,
-};
-#[cfg(feature = "gitent")]
-use modules::gitent::GitentModule;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+
+use poly_mcp::config::{module_built, Config, ModuleSelection, DEFAULT_CONFIG_FILE};
+use poly_mcp::logging::McpLogLayer;
+use poly_mcp::telemetry::Telemetry;
+use poly_mcp::{PolyMcp, PolyMcpBuilder};
 
 /// Poly MCP - A comprehensive Model Context Protocol server
 ///
-/// Provides 11 powerful modules for AI assistants:
+/// Provides 21 powerful modules for AI assistants:
 /// • Filesystem - File operations, snapshots, permissions
 /// • Diagnostics - Multi-language error detection
 /// • Silent - Bash scripting & resource monitoring
@@ -44,10 +25,25 @@ use modules::gitent::GitentModule;
 /// • Gitent - Agent-centric version control tracking
 /// • Clipboard - Session copy/paste with tags
 /// • Transform - Diff, encode, hash, regex, JSON, text, archive
-#[derive(Parser, Debug)]
+/// • Settings - Project settings shared between agents
+/// • Calc - Arithmetic, unit conversion, statistics
+/// • Crypto - UUIDs, secure random values, hashing, HMAC, encoding
+/// • Regex - Test, replace and explain regular expressions
+/// • Text - In-memory diff, patch and three-way merge
+/// • Xlsx - Spreadsheet reading and writing
+/// • Md - Markdown TOC, link checking, linting, rendering, front matter
+/// • Media - Local audio transcription with whisper (whisper feature)
+/// • Browser - Headless Chrome navigation, extraction, screenshots (browser feature)
+/// • Llm - Local model generation, embeddings and model listing (Ollama/OpenAI-compatible)
+/// • Power - Screen lock, sleep, keep-awake, approved shutdown/restart
+/// • Apps - Window listing, focus, launch, quit, open URLs and files
+/// • Txn - Atomic multi-file edits with validated commit and abort
+/// • Session - Per-session working directory, the active execution profile, running child processes, audit log verification and per-tool help
+/// • Fixtures - Throwaway sample workspaces from built-in templates (testing feature)
+#[derive(Parser, Debug, Clone)]
 #[command(name = "poly-mcp")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
-#[command(about = "A comprehensive MCP server with 11 powerful modules", long_about = None)]
+#[command(about = "A comprehensive MCP server with 26 powerful modules", long_about = None)]
 struct Cli {
     /// List all available modules and their tools
     #[arg(short, long)]
@@ -62,555 +58,523 @@ struct Cli {
     server: bool,
 
     /// Port to bind HTTP server to (default: 3000)
-    #[arg(short, long, default_value = "3000")]
-    port: u16,
+    #[arg(short, long)]
+    port: Option<u16>,
 
     /// Host to bind HTTP server to (default: 127.0.0.1)
-    #[arg(long, default_value = "127.0.0.1")]
-    host: String,
-}
+    #[arg(long)]
+    host: Option<String>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcRequest {
-    jsonrpc: String,
-    id: Option<Value>,
-    method: String,
-    params: Option<Value>,
-}
+    /// Require this API key in HTTP mode (repeatable; also read from POLY_MCP_API_KEY)
+    #[arg(long = "api-key", value_name = "KEY")]
+    api_keys: Vec<String>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    id: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    result: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<JsonRpcError>,
-}
+    /// Only accept browser requests from this origin in HTTP mode (repeatable)
+    #[arg(long = "cors-origin", value_name = "ORIGIN")]
+    cors_origins: Vec<String>,
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcError {
-    code: i32,
-    message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    data: Option<Value>,
-}
+    /// Directory relative tool paths resolve against until a session changes it with session_cwd
+    #[arg(long, value_name = "DIR")]
+    cwd: Option<PathBuf>,
 
-struct PolyMcp {
-    filesystem: FilesystemModule,
-    diagnostics: DiagnosticsModule,
-    silent: SilentModule,
-    time: TimeModule,
-    network: NetworkModule,
-    context: ContextModule,
-    git: GitModule,
-    input: InputModule,
-    #[cfg(feature = "gitent")]
-    gitent: GitentModule,
-    clipboard: ClipboardModule,
-    transform: TransformModule,
-    #[cfg(feature = "premium")]
-    varp: Option<modules::varp_bridge::VarpModule>,
-}
+    /// Return tools/list in pages of this many tools (default: 0, all at once)
+    #[arg(long, value_name = "N")]
+    tools_page_size: Option<usize>,
 
-impl PolyMcp {
-    fn new() -> Self {
-        #[cfg(feature = "premium")]
-        let varp = match modules::varp_bridge::VarpModule::new() {
-            Ok(Some(v)) => {
-                eprintln!("  VARP premium tools enabled ({} tools)", v.get_tools().len());
-                Some(v)
-            }
-            Ok(None) => {
-                eprintln!("  VARP premium: no license key (set VARP_LICENSE_KEY to enable)");
-                None
-            }
-            Err(e) => {
-                eprintln!("  VARP premium: {}", e);
-                None
-            }
-        };
-
-        Self {
-            filesystem: FilesystemModule::new(),
-            diagnostics: DiagnosticsModule::new(),
-            silent: SilentModule::new(),
-            time: TimeModule::new(),
-            network: NetworkModule::new(),
-            context: ContextModule::new(),
-            git: GitModule::new(),
-            input: InputModule::new(),
-            #[cfg(feature = "gitent")]
-            gitent: GitentModule::new(),
-            clipboard: ClipboardModule::new(),
-            transform: TransformModule::new(),
-            #[cfg(feature = "premium")]
-            varp,
-        }
-    }
+    /// Put this in front of every tool name, e.g. "poly." for poly.fs_read
+    #[arg(long, value_name = "PREFIX")]
+    tool_prefix: Option<String>,
 
-    fn get_server_info(&self) -> Value {
-        json!({
-            "protocolVersion": "2024-11-05",
-            "capabilities": {
-                "tools": {}
-            },
-            "serverInfo": {
-                "name": "poly-mcp",
-                "version": env!("CARGO_PKG_VERSION")
-            }
-        })
-    }
+    /// Build every module's HTTP clients and system scans at startup instead of on first use
+    #[arg(long)]
+    preload: bool,
 
-    fn list_tools(&self) -> Value {
-        let mut tools = Vec::new();
+    /// Only load these modules (comma-separated, e.g. filesystem,git)
+    #[arg(long, value_delimiter = ',', value_name = "MODULES")]
+    enable: Vec<String>,
 
-        // Filesystem tools
-        tools.extend(self.filesystem.get_tools());
+    /// Do not load these modules (comma-separated, e.g. network,input)
+    #[arg(long, value_delimiter = ',', value_name = "MODULES")]
+    disable: Vec<String>,
 
-        // Diagnostics tools
-        tools.extend(self.diagnostics.get_tools());
+    /// Path to a TOML or YAML (*.yaml, *.yml) config file (default: ./poly-mcp.toml, .yaml or .yml if present)
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
 
-        // Silent tools
-        tools.extend(self.silent.get_tools());
+    /// Restrict filesystem tools to this directory (repeatable)
+    #[arg(long = "allow-root", value_name = "PATH")]
+    allowed_roots: Vec<PathBuf>,
 
-        // Time tools
-        tools.extend(self.time.get_tools());
+    /// Timeout for network requests in seconds (default: 30)
+    #[arg(long, value_name = "SECS")]
+    network_timeout: Option<u64>,
 
-        // Network tools
-        tools.extend(self.network.get_tools());
+    /// Default author name for git commits and tags
+    #[arg(long, value_name = "NAME")]
+    git_author_name: Option<String>,
 
-        // Context tools
-        tools.extend(self.context.get_tools());
+    /// Default author email for git commits and tags
+    #[arg(long, value_name = "EMAIL")]
+    git_author_email: Option<String>,
 
-        // Git tools
-        tools.extend(self.git.get_tools());
+    /// Gitent database path (default: .gitent/gitent.db)
+    #[arg(long, value_name = "PATH")]
+    gitent_db: Option<PathBuf>,
 
-        // Input tools
-        tools.extend(self.input.get_tools());
+    /// Whisper model (ggml .bin) for media_transcribe
+    #[arg(long, value_name = "PATH")]
+    whisper_model: Option<PathBuf>,
 
-        // Gitent tools
-        #[cfg(feature = "gitent")]
-        tools.extend(self.gitent.get_tools());
+    /// Chrome or Chromium executable for the browser tools (default: auto-detect)
+    #[arg(long, value_name = "PATH")]
+    chrome_path: Option<PathBuf>,
 
-        // Clipboard tools
-        tools.extend(self.clipboard.get_tools());
+    /// Base URL of the Ollama or OpenAI-compatible server for llm tools
+    #[arg(long, value_name = "URL")]
+    llm_url: Option<String>,
 
-        // Transform tools
-        tools.extend(self.transform.get_tools());
+    /// Default model for llm_generate
+    #[arg(long, value_name = "NAME")]
+    llm_model: Option<String>,
 
-        // VARP premium tools (plan, task, iteration, vaca, workspace)
-        #[cfg(feature = "premium")]
-        if let Some(ref v) = self.varp {
-            tools.extend(v.get_tools());
-        }
+    /// Let power_shutdown shut down or restart the machine (each call still needs approval)
+    #[arg(long)]
+    allow_shutdown: bool,
 
-        json!({ "tools": tools })
-    }
+    /// Refuse calls to tools matching this pattern, e.g. "fs_delete" or "silent" (repeatable)
+    #[arg(long = "deny-tool", value_name = "PATTERN")]
+    deny_tools: Vec<String>,
 
-    async fn call_tool(&mut self, name: &str, arguments: Option<Value>) -> Result<Value> {
-        let args = arguments.unwrap_or(json!({}));
-
-        // Route to appropriate module
-        match name {
-            // Filesystem
-            "fs_read" => self.filesystem.read(args).await,
-            "fs_write" => self.filesystem.write(args).await,
-            "fs_move" => self.filesystem.move_file(args).await,
-            "fs_copy" => self.filesystem.copy(args).await,
-            "fs_create" => self.filesystem.create(args).await,
-            "fs_delete" => self.filesystem.delete(args).await,
-            "fs_move_desktop" => self.filesystem.move_desktop(args).await,
-            "fs_find" => self.filesystem.find(args).await,
-            "fs_ld" => self.filesystem.ld(args).await,
-            "fs_stat" => self.filesystem.stat(args).await,
-            "fs_permissions" => self.filesystem.permissions(args).await,
-            "fs_watch" => self.filesystem.watch(args).await,
-            "fs_snapshot" => self.filesystem.snapshot(args).await,
-            "fs_tree" => self.filesystem.tree(args).await,
-            "fs_grep" => self.filesystem.grep(args).await,
-            "fs_tail" => self.filesystem.tail(args).await,
-            "fs_replace" => self.filesystem.replace(args).await,
-
-            // Diagnostics
-            "diagnostics_get" => self.diagnostics.get(args).await,
-
-            // Silent
-            "silent_script" => self.silent.script(args).await,
-            "silent_resources" => self.silent.resources(args).await,
-
-            // Time
-            "time_now" => self.time.now(args).await,
-            "time_sleep" => self.time.sleep(args).await,
-            "time_schedule" => self.time.schedule(args).await,
-            "time_timezone" => self.time.timezone(args).await,
-            "time_stopwatch" => self.time.stopwatch(args).await,
-            "time_timer" => self.time.timer(args).await,
-            "time_alarm" => self.time.alarm(args).await,
-
-            // Network
-            "net_fetch" => self.network.fetch(args).await,
-            "net_cargo" => self.network.cargo(args).await,
-            "net_node" => self.network.node(args).await,
-            "net_python" => self.network.python(args).await,
-            "net_apt" => self.network.apt(args).await,
-            "net_ping" => self.network.ping(args).await,
-
-            // Context
-            "ctx_context" => self.context.context(args).await,
-            "ctx_compact" => self.context.compact_context(args).await,
-            "ctx_remove" => self.context.remove_context(args).await,
-            "ctx_token_count" => self.context.token_count(args).await,
-            "ctx_memory_store" => self.context.memory_store(args).await,
-            "ctx_memory_recall" => self.context.memory_recall(args).await,
-            "ctx_estimate_cost" => self.context.estimate_cost(args).await,
-
-            // Git
-            "git_status" => self.git.status(args).await,
-            "git_diff" => self.git.diff(args).await,
-            "git_commit" => self.git.commit(args).await,
-            "git_branch" => self.git.branch(args).await,
-            "git_checkout" => self.git.checkout(args).await,
-            "git_blame" => self.git.blame(args).await,
-            "git_log" => self.git.log(args).await,
-            "git_tag" => self.git.tag(args).await,
-
-            // Input
-            "input_notify" => self.input.notify(args).await,
-            "input_prompt" => self.input.prompt_user(args).await,
-            "input_select" => self.input.select(args).await,
-            "input_progress" => self.input.progress(args).await,
-            "input_clipboard_read" => self.input.clipboard_read(args).await,
-            "input_clipboard_write" => self.input.clipboard_write(args).await,
-
-            // Gitent
-            #[cfg(feature = "gitent")]
-            "gitent_init" => self.gitent.init(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_status" => self.gitent.status(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_track" => self.gitent.track(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_commit" => self.gitent.commit(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_log" => self.gitent.log(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_diff" => self.gitent.diff(args).await,
-            #[cfg(feature = "gitent")]
-            "gitent_rollback" => self.gitent.rollback(args).await,
-
-            // Clipboard
-            "clip_copy_file" => self.clipboard.copy_file(args).await,
-            "clip_copy" => self.clipboard.copy(args).await,
-            "clip_paste_file" => self.clipboard.paste_file(args).await,
-            "clip_paste" => self.clipboard.paste(args).await,
-            "clip_clear" => self.clipboard.clear(args).await,
-
-            // Transform
-            "transform_diff" => self.transform.diff(args).await,
-            "transform_encode" => self.transform.encode(args).await,
-            "transform_hash" => self.transform.hash(args).await,
-            "transform_regex" => self.transform.regex_op(args).await,
-            "transform_json" => self.transform.json_op(args).await,
-            "transform_text" => self.transform.text(args).await,
-            "transform_archive" => self.transform.archive(args).await,
-
-            // VARP premium tools (plan, task, iteration, vaca, workspace)
-            #[cfg(feature = "premium")]
-            "plan" | "task" | "iteration" | "vaca" | "workspace"
-                if self.varp.is_some() =>
-            {
-                self.varp.as_ref().unwrap().call_tool(name, args).await
-            }
+    /// Ask the user before running tools matching this pattern (repeatable)
+    #[arg(long = "require-approval", value_name = "PATTERN")]
+    require_approval: Vec<String>,
 
-            _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
-        }
-    }
+    /// Load plugins from this directory (repeatable, added to the file's list)
+    #[arg(long = "plugin-dir", value_name = "PATH")]
+    plugin_dirs: Vec<PathBuf>,
 
-    fn print_banner(&self, verbose: bool) {
-        eprintln!("\n╭────────────────────────────────────────────────────╮");
-        eprintln!("│         🔧 Poly MCP Server v{}              │", env!("CARGO_PKG_VERSION"));
-        eprintln!("╰────────────────────────────────────────────────────╯\n");
-
-        eprintln!("📡 Protocol: Model Context Protocol (MCP)");
-        eprintln!("🔗 Transport: stdio (stdin/stdout) - no network port");
-        eprintln!("📋 Format: JSON-RPC 2.0");
-        eprintln!("📦 Modules: 11 active modules loaded\n");
-
-        if verbose {
-            eprintln!("Available Modules:");
-            eprintln!("  • Filesystem    - 17 tools for file operations");
-            eprintln!("  • Diagnostics   - 1 tool for error detection");
-            eprintln!("  • Silent        - 2 tools for scripting & monitoring");
-            eprintln!("  • Time          - 7 tools for scheduling & timekeeping");
-            eprintln!("  • Network       - 6 tools for HTTP & packages");
-            eprintln!("  • Context       - 7 tools for token management");
-            eprintln!("  • Git           - 8 tools for version control");
-            eprintln!("  • Input         - 6 tools for user interaction");
-            eprintln!("  • Gitent        - 7 tools for agent tracking");
-            eprintln!("  • Clipboard     - 5 tools for session copy/paste");
-            eprintln!("  • Transform     - 7 tools for text/data processing\n");
-        }
+    /// Only offer tools that inspect, and refuse calls that would change files, repositories or the system
+    #[arg(long)]
+    read_only: bool,
 
-        eprintln!("✓ Server ready and listening for JSON-RPC requests...");
-        eprintln!("ℹ Use --help for more information\n");
-    }
+    /// Built-in policy preset: safe, standard or yolo
+    #[arg(long, value_name = "LEVEL")]
+    profile_level: Option<String>,
 
-    fn list_all_modules(&self) {
-        println!("\n╭────────────────────────────────────────────────────╮");
-        println!("│         🔧 Poly MCP - Available Modules           │");
-        println!("╰────────────────────────────────────────────────────╯\n");
-
-        let modules = vec![
-            ("Filesystem", "File and directory operations", vec![
-                "fs_read", "fs_write", "fs_move", "fs_copy", "fs_create", "fs_delete",
-                "fs_move_desktop", "fs_find", "fs_ld", "fs_stat", "fs_permissions",
-                "fs_watch", "fs_snapshot", "fs_tree", "fs_grep", "fs_tail", "fs_replace"
-            ]),
-            ("Diagnostics", "Language-agnostic error detection", vec![
-                "diagnostics_get"
-            ]),
-            ("Silent", "Bash scripting and resource monitoring", vec![
-                "silent_script", "silent_resources"
-            ]),
-            ("Time", "Time management, scheduling & timekeeping", vec![
-                "time_now", "time_sleep", "time_schedule",
-                "time_timezone", "time_stopwatch", "time_timer", "time_alarm"
-            ]),
-            ("Network", "HTTP requests and package queries", vec![
-                "net_fetch", "net_cargo", "net_node", "net_python", "net_apt", "net_ping"
-            ]),
-            ("Context", "Token counting and cost estimation", vec![
-                "ctx_context", "ctx_compact", "ctx_remove", "ctx_token_count",
-                "ctx_memory_store", "ctx_memory_recall", "ctx_estimate_cost"
-            ]),
-            ("Git", "Complete git operations", vec![
-                "git_status", "git_diff", "git_commit", "git_branch",
-                "git_checkout", "git_blame", "git_log", "git_tag"
-            ]),
-            ("Input", "User interaction and notifications", vec![
-                "input_notify", "input_prompt", "input_select", "input_progress",
-                "input_clipboard_read", "input_clipboard_write"
-            ]),
-            ("Gitent", "Agent-centric version control tracking", vec![
-                "gitent_init", "gitent_status", "gitent_track", "gitent_commit",
-                "gitent_log", "gitent_diff", "gitent_rollback"
-            ]),
-            ("Clipboard", "Session copy/paste with tags", vec![
-                "clip_copy_file", "clip_copy", "clip_paste_file", "clip_paste", "clip_clear"
-            ]),
-            ("Transform", "Text & data processing", vec![
-                "transform_diff", "transform_encode", "transform_hash", "transform_regex",
-                "transform_json", "transform_text", "transform_archive"
-            ]),
-        ];
-
-        for (name, description, tools) in modules {
-            println!("📦 {} - {}", name, description);
-            println!("   {} tools: {}", tools.len(), tools.join(", "));
-            println!();
-        }
+    /// Append every tool call to this file (JSON lines, or SQLite for .db/.sqlite)
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
 
-        println!("Total: 73 tools across 11 modules\n");
-    }
+    /// Where persistent state goes: memory, sqlite or sled (default: memory)
+    #[arg(long, value_name = "BACKEND")]
+    storage: Option<String>,
+
+    /// Database for the sqlite or sled storage backend (default: .poly-mcp/state.db or .poly-mcp/state.sled)
+    #[arg(long, value_name = "PATH")]
+    storage_path: Option<PathBuf>,
+
+    /// Abort tool calls that run longer than this many seconds, 0 for no limit (default: 600)
+    #[arg(long, value_name = "SECS")]
+    tool_timeout: Option<u64>,
+}
+
+impl Cli {
+    /// Load the config file and layer command-line flags on top of it
+    fn load_config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
 
-    async fn handle_request(&mut self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let id = request.id.clone();
-
-        match request.method.as_str() {
-            "initialize" => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: Some(self.get_server_info()),
-                error: None,
-            },
-            "tools/list" => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: Some(self.list_tools()),
-                error: None,
-            },
-            "tools/call" => {
-                let params = request.params.unwrap_or(json!({}));
-                let name = params["name"].as_str().unwrap_or("");
-                let arguments = params.get("arguments").cloned();
-
-                match self.call_tool(name, arguments).await {
-                    Ok(result) => JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: Some(json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": result.to_string()
-                                }
-                            ]
-                        })),
-                        error: None,
-                    },
-                    Err(e) => JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(JsonRpcError {
-                            code: -32000,
-                            message: e.to_string(),
-                            data: None,
-                        }),
-                    },
-                }
+        if let Some(host) = &self.host {
+            config.server.host = host.clone();
+        }
+        if let Some(port) = self.port {
+            config.server.port = port;
+        }
+        // Keys from the command line and environment are added to the file's keys
+        for (i, key) in self.api_keys.iter().enumerate() {
+            config.auth.api_keys.insert(format!("cli-{}", i + 1), key.clone());
+        }
+        if let Ok(key) = std::env::var("POLY_MCP_API_KEY") {
+            if !key.is_empty() {
+                config.auth.api_keys.insert("env".to_string(), key);
             }
-            _ => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32601,
-                    message: format!("Method not found: {}", request.method),
-                    data: None,
-                }),
-            },
         }
-    }
-}
+        if !self.cors_origins.is_empty() {
+            config.server.cors_origins = self.cors_origins.clone();
+        }
+        if let Some(cwd) = &self.cwd {
+            config.server.cwd = Some(cwd.clone());
+        }
+        if let Some(size) = self.tools_page_size {
+            config.server.tools_page_size = size;
+        }
+        if let Some(prefix) = &self.tool_prefix {
+            config.server.tool_prefix = prefix.clone();
+        }
+        if self.preload {
+            config.server.preload = true;
+        }
+        // --enable replaces the file's list, --disable adds to it
+        if !self.enable.is_empty() {
+            config.modules.enable = self.enable.clone();
+        }
+        config.modules.disable.extend(self.disable.iter().cloned());
+        if !self.allowed_roots.is_empty() {
+            config.filesystem.allowed_roots = self.allowed_roots.clone();
+        }
+        if let Some(timeout) = self.network_timeout {
+            config.network.timeout_secs = timeout;
+        }
+        if let Some(name) = &self.git_author_name {
+            config.git.author_name = Some(name.clone());
+        }
+        if let Some(email) = &self.git_author_email {
+            config.git.author_email = Some(email.clone());
+        }
+        if let Some(db) = &self.gitent_db {
+            config.gitent.db_path = Some(db.clone());
+        }
+        if let Some(model) = &self.whisper_model {
+            config.media.whisper_model = Some(model.clone());
+        }
+        if let Some(path) = &self.chrome_path {
+            config.browser.chrome_path = Some(path.clone());
+        }
+        if let Some(url) = &self.llm_url {
+            config.llm.base_url = Some(url.clone());
+        }
+        if let Some(model) = &self.llm_model {
+            config.llm.model = Some(model.clone());
+        }
+        if let Some(path) = &self.audit_log {
+            config.audit.path = Some(path.clone());
+        }
+        if let Some(backend) = &self.storage {
+            config.storage.backend = backend.clone();
+        }
+        if let Some(path) = &self.storage_path {
+            config.storage.path = Some(path.clone());
+        }
+        if self.allow_shutdown {
+            config.power.allow_shutdown = true;
+        }
+        config.policy.deny_tools.extend(self.deny_tools.iter().cloned());
+        config.policy.require_approval.extend(self.require_approval.iter().cloned());
+        config.plugins.dirs.extend(self.plugin_dirs.iter().cloned());
+        if self.read_only {
+            config.policy.read_only = true;
+        }
+        if let Some(profile) = &self.profile_level {
+            config.policy.profile = Some(profile.clone());
+        }
+        if let Some(timeout) = self.tool_timeout {
+            config.timeouts.default_secs = timeout;
+        }
 
-// Shared state type for HTTP server
-type SharedState = Arc<Mutex<PolyMcp>>;
-
-// HTTP handler for JSON-RPC requests
-async fn handle_jsonrpc(
-    State(state): State<SharedState>,
-    Json(request): Json<JsonRpcRequest>,
-) -> Response {
-    let mut server = state.lock().await;
-    let response = server.handle_request(request).await;
-    Json(response).into_response()
+        Ok(config)
+    }
 }
 
-// HTTP handler for health check
-async fn health_check() -> Response {
-    Json(json!({
-        "status": "healthy",
-        "service": "poly-mcp",
-        "version": env!("CARGO_PKG_VERSION")
-    }))
-    .into_response()
-}
+/// One-line module summaries shown in the startup banner, keyed by module name
+const MODULE_SUMMARIES: &[(&str, &str)] = &[
+    ("filesystem", "Filesystem    - 38 tools for file operations"),
+    ("diagnostics", "Diagnostics   - 1 tool for error detection"),
+    ("silent", "Silent        - 2 tools for scripting & monitoring"),
+    ("time", "Time          - 7 tools for scheduling & timekeeping"),
+    ("network", "Network       - 7 tools for HTTP & packages"),
+    ("context", "Context       - 7 tools for token management"),
+    ("git", "Git           - 13 tools for version control"),
+    ("input", "Input         - 6 tools for user interaction"),
+    ("gitent", "Gitent        - 7 tools for agent tracking"),
+    ("clipboard", "Clipboard     - 5 tools for session copy/paste"),
+    ("transform", "Transform     - 7 tools for text/data processing"),
+    ("settings", "Settings      - 4 tools for shared project settings"),
+    ("calc", "Calc          - 3 tools for math, units & statistics"),
+    ("crypto", "Crypto        - 5 tools for ids, randomness & hashing"),
+    ("regex", "Regex         - 3 tools for testing & explaining patterns"),
+    ("text", "Text          - 3 tools for in-memory diff, patch & merge"),
+    ("xlsx", "Xlsx          - 2 tools for reading & writing spreadsheets"),
+    ("md", "Md            - 5 tools for markdown toc, links, lint & render"),
+    ("media", "Media         - 1 tool for local audio transcription"),
+    ("browser", "Browser       - 8 tools for headless Chrome automation"),
+    ("llm", "Llm           - 3 tools for local model generation & embeddings"),
+    ("power", "Power         - 4 tools for lock, sleep, keep-awake & shutdown"),
+    ("apps", "Apps          - 5 tools for windows, launching & default handlers"),
+    ("txn", "Txn           - 4 tools for atomic multi-file edits"),
+    ("session", "Session       - 5 tools for the working directory, execution profile, child processes, audit log checks and tool help"),
+    ("fixtures", "Fixtures      - 1 tool for sample test workspaces"),
+];
+
+fn print_banner(server: &PolyMcp, verbose: bool) {
+    let enabled_modules = server.enabled_modules();
+    eprintln!("\n╭────────────────────────────────────────────────────╮");
+    eprintln!("│         🔧 Poly MCP Server v{}              │", env!("CARGO_PKG_VERSION"));
+    eprintln!("╰────────────────────────────────────────────────────╯\n");
 
-// Run server in stdio mode (original behavior)
-async fn run_stdio_mode(cli: &Cli) -> Result<()> {
-    let mut server = PolyMcp::new();
+    eprintln!("📡 Protocol: Model Context Protocol (MCP)");
+    eprintln!("🔗 Transport: stdio (stdin/stdout) - no network port");
+    eprintln!("📋 Format: JSON-RPC 2.0");
+    eprintln!("📦 Modules: {} active modules loaded", active_module_count(&enabled_modules));
+    if let Some(path) = server.audit_log_path() {
+        eprintln!("📝 Audit log: {}", path.display());
+    }
+    eprintln!();
 
-    // Only print startup banner if stdin is a terminal (interactive mode)
-    if io::stdin().is_terminal() {
-        server.print_banner(cli.verbose);
+    if verbose {
+        print_module_summaries(&enabled_modules);
+        print_init_times(server, &enabled_modules);
     }
 
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
+    eprintln!("✓ Server ready and listening for JSON-RPC requests...");
+    eprintln!("ℹ Use --help for more information\n");
+}
 
-    for line in stdin.lock().lines() {
-        let line = line?;
+fn print_init_times(server: &PolyMcp, enabled_modules: &ModuleSelection) {
+    eprintln!("Module Init Times:");
+    let mut total = std::time::Duration::ZERO;
+    for (module, elapsed) in server.module_init_times() {
+        if enabled_modules.is_enabled(module) {
+            eprintln!("  • {:<12} {:>8.2} ms", module, elapsed.as_secs_f64() * 1000.0);
+            total += *elapsed;
+        }
+    }
+    eprintln!("  Total: {:.2} ms\n", total.as_secs_f64() * 1000.0);
+}
+
+fn active_module_count(enabled_modules: &ModuleSelection) -> usize {
+    MODULE_SUMMARIES
+        .iter()
+        .filter(|(module, _)| module_built(module) && enabled_modules.is_enabled(module))
+        .count()
+}
 
-        if line.trim().is_empty() {
-            continue;
+fn print_module_summaries(enabled_modules: &ModuleSelection) {
+    eprintln!("Available Modules:");
+    for (module, summary) in MODULE_SUMMARIES {
+        if module_built(module) && enabled_modules.is_enabled(module) {
+            eprintln!("  • {}", summary);
         }
+    }
+    eprintln!();
+}
 
-        match serde_json::from_str::<JsonRpcRequest>(&line) {
-            Ok(request) => {
-                let response = server.handle_request(request).await;
-                let response_json = serde_json::to_string(&response)?;
-                writeln!(stdout, "{}", response_json)?;
-                stdout.flush()?;
-            }
-            Err(e) => {
-                let error_response = JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: None,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32700,
-                        message: format!("Parse error: {}", e),
-                        data: None,
-                    }),
-                };
-                let response_json = serde_json::to_string(&error_response)?;
-                writeln!(stdout, "{}", response_json)?;
-                stdout.flush()?;
-            }
+fn list_all_modules(enabled_modules: &ModuleSelection) {
+    println!("\n╭────────────────────────────────────────────────────╮");
+    println!("│         🔧 Poly MCP - Available Modules           │");
+    println!("╰────────────────────────────────────────────────────╯\n");
+
+    let modules = vec![
+        ("Filesystem", "File and directory operations", vec![
+            "fs_read", "fs_read_many", "fs_write", "fs_move", "fs_copy", "fs_link", "fs_create", "fs_delete",
+            "fs_move_desktop", "fs_find", "fs_ld", "fs_stat", "fs_permissions",
+            "fs_watch", "fs_watch_start", "fs_watch_poll", "fs_watch_stop", "fs_snapshot", "fs_snapshot_list", "fs_snapshot_restore", "fs_snapshot_diff", "fs_tree", "fs_grep", "fs_tail", "fs_du", "fs_replace",
+            "fs_filetype", "fs_normalize", "fs_loc", "fs_recent", "fs_largest", "fs_edit",
+            "fs_hash", "fs_diff", "fs_archive", "fs_extract", "fs_trash_list", "fs_trash_restore"
+        ]),
+        ("Diagnostics", "Language-agnostic error detection", vec![
+            "diagnostics_get"
+        ]),
+        ("Silent", "Bash scripting and resource monitoring", vec![
+            "silent_script", "silent_resources"
+        ]),
+        ("Time", "Time management, scheduling & timekeeping", vec![
+            "time_now", "time_sleep", "time_schedule",
+            "time_timezone", "time_stopwatch", "time_timer", "time_alarm"
+        ]),
+        ("Network", "HTTP requests and package queries", vec![
+            "net_fetch", "net_cargo", "net_node", "net_python", "net_apt", "net_ping",
+            "net_session_close"
+        ]),
+        ("Context", "Token counting and cost estimation", vec![
+            "ctx_context", "ctx_compact", "ctx_remove", "ctx_token_count",
+            "ctx_memory_store", "ctx_memory_recall", "ctx_estimate_cost"
+        ]),
+        ("Git", "Complete git operations", vec![
+            "git_status", "git_diff", "git_commit", "git_branch",
+            "git_checkout", "git_blame", "git_log", "git_tag", "git_stats",
+            "git_changelog", "git_apply", "git_ignore", "git_discover"
+        ]),
+        ("Input", "User interaction and notifications", vec![
+            "input_notify", "input_prompt", "input_select", "input_progress",
+            "input_clipboard_read", "input_clipboard_write"
+        ]),
+        ("Gitent", "Agent-centric version control tracking", vec![
+            "gitent_init", "gitent_status", "gitent_track", "gitent_commit",
+            "gitent_log", "gitent_diff", "gitent_rollback"
+        ]),
+        ("Clipboard", "Session copy/paste with tags", vec![
+            "clip_copy_file", "clip_copy", "clip_paste_file", "clip_paste", "clip_clear"
+        ]),
+        ("Transform", "Text & data processing", vec![
+            "transform_diff", "transform_encode", "transform_hash", "transform_regex",
+            "transform_json", "transform_text", "transform_archive"
+        ]),
+        ("Settings", "Project settings shared between agents", vec![
+            "settings_get", "settings_set", "settings_list", "settings_watch"
+        ]),
+        ("Calc", "Arithmetic, unit conversion & statistics", vec![
+            "calc_eval", "calc_convert", "calc_stats"
+        ]),
+        ("Crypto", "UUIDs, secure random values, hashing & encoding", vec![
+            "crypto_uuid", "crypto_random", "crypto_hash", "crypto_hmac", "crypto_encode"
+        ]),
+        ("Regex", "Regular expression testing, replacement & validation", vec![
+            "regex_test", "regex_replace", "regex_validate"
+        ]),
+        ("Text", "In-memory diff, patch & three-way merge", vec![
+            "text_diff", "text_patch", "text_merge3"
+        ]),
+        ("Xlsx", "Spreadsheet reading & writing", vec![
+            "xlsx_read", "xlsx_write"
+        ]),
+        ("Md", "Markdown TOC, link checking, linting, rendering & front matter", vec![
+            "md_toc", "md_links", "md_lint", "md_render", "md_frontmatter"
+        ]),
+        ("Media", "Local audio transcription with whisper (whisper feature)", vec![
+            "media_transcribe"
+        ]),
+        ("Browser", "Headless Chrome navigation, text extraction, clicks, screenshots & downloads (browser feature)", vec![
+            "browser_navigate", "browser_wait", "browser_text", "browser_click",
+            "browser_screenshot", "browser_download", "browser_pages", "browser_close"
+        ]),
+        ("Llm", "Local model generation, embeddings & model listing (Ollama/OpenAI-compatible)", vec![
+            "llm_generate", "llm_embed", "llm_models"
+        ]),
+        ("Power", "Screen lock, sleep, keep-awake & approved shutdown", vec![
+            "power_lock", "power_sleep", "power_shutdown", "power_keep_awake"
+        ]),
+        ("Apps", "Window listing, focus, launch, quit & open with default handler", vec![
+            "apps_list", "apps_focus", "apps_launch", "apps_quit", "apps_open"
+        ]),
+        ("Txn", "Atomic multi-file edits: stage fs changes, validate, then commit or abort", vec![
+            "txn_begin", "txn_status", "txn_commit", "txn_abort"
+        ]),
+        ("Session", "Per-session working directory that relative paths resolve against, the active profile, child processes, audit log checks, and help on any tool", vec![
+            "session_cwd", "session_profile", "server_children", "audit_verify", "tool_help"
+        ]),
+        ("Fixtures", "Throwaway sample workspaces from built-in templates (testing feature)", vec![
+            "fixture_create"
+        ]),
+    ];
+
+    let (mut built_modules, mut built_tools) = (0, 0);
+    for (name, description, tools) in modules {
+        let module = name.to_lowercase();
+        if !module_built(&module) {
+            println!("📦 {} - {} (not in this build)", name, description);
+        } else if enabled_modules.is_enabled(&module) {
+            println!("📦 {} - {}", name, description);
+        } else {
+            println!("📦 {} - {} (disabled)", name, description);
+        }
+        println!("   {} tools: {}", tools.len(), tools.join(", "));
+        println!();
+        if module_built(&module) {
+            built_modules += 1;
+            built_tools += tools.len();
         }
     }
 
-    Ok(())
+    println!("Total: {} tools across {} modules in this build\n", built_tools, built_modules);
+}
+
+// Run server in stdio mode (original behavior)
+async fn run_stdio_mode(cli: &Cli, server: PolyMcp) -> Result<()> {
+    // Only print startup banner if stdin is a terminal (interactive mode)
+    if io::stdin().is_terminal() {
+        print_banner(&server, cli.verbose);
+    }
+
+    server.run_stdio().await
 }
 
 // Run server in HTTP mode
-async fn run_http_mode(cli: &Cli) -> Result<()> {
-    let server = PolyMcp::new();
-    let state = Arc::new(Mutex::new(server));
-
-    // Build HTTP router
-    let app = Router::new()
-        .route("/", post(handle_jsonrpc))
-        .route("/jsonrpc", post(handle_jsonrpc))
-        .route("/health", axum::routing::get(health_check))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
-
-    let addr = format!("{}:{}", cli.host, cli.port);
+async fn run_http_mode(cli: &Cli, config: &Config, server: PolyMcp) -> Result<()> {
+    let enabled_modules = server.enabled_modules();
+    let requires_auth = server.requires_auth();
+    let audit_log = server.audit_log_path().map(|path| path.to_path_buf());
+
+    let addr = format!("{}:{}", config.server.host, config.server.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
     eprintln!("\n╭────────────────────────────────────────────────────╮");
     eprintln!("│         🔧 Poly MCP Server v{}              │", env!("CARGO_PKG_VERSION"));
     eprintln!("╰────────────────────────────────────────────────────╯\n");
     eprintln!("📡 Protocol: Model Context Protocol (MCP)");
-    eprintln!("🔗 Transport: HTTP (JSON-RPC 2.0)");
+    eprintln!("🔗 Transport: HTTP + WebSocket (JSON-RPC 2.0)");
     eprintln!("🌐 Address: http://{}", addr);
-    eprintln!("📦 Modules: 11 active modules loaded");
-    eprintln!("💚 Health: http://{}/health\n", addr);
+    eprintln!("🔌 WebSocket: ws://{}/ws", addr);
+    eprintln!("📣 Notifications (SSE): http://{}/events", addr);
+    eprintln!("📦 Modules: {} active modules loaded", active_module_count(&enabled_modules));
+    eprintln!("💚 Health: http://{}/health", addr);
+    if let Some(path) = &audit_log {
+        eprintln!("📝 Audit log: {}", path.display());
+    }
+    if requires_auth {
+        eprintln!("🔒 Auth: required (Authorization: Bearer or X-API-Key)\n");
+    } else {
+        eprintln!("🔓 Auth: none\n");
+        if !is_loopback(&config.server.host) {
+            eprintln!("⚠ Listening on {} without authentication: anyone who can reach this port can run tools.", config.server.host);
+            eprintln!("  Set --api-key or an [auth] table in {}\n", DEFAULT_CONFIG_FILE);
+        }
+    }
 
     if cli.verbose {
-        eprintln!("Available Modules:");
-        eprintln!("  • Filesystem    - 17 tools for file operations");
-        eprintln!("  • Diagnostics   - 1 tool for error detection");
-        eprintln!("  • Silent        - 2 tools for scripting & monitoring");
-        eprintln!("  • Time          - 7 tools for scheduling & timekeeping");
-        eprintln!("  • Network       - 6 tools for HTTP & packages");
-        eprintln!("  • Context       - 7 tools for token management");
-        eprintln!("  • Git           - 8 tools for version control");
-        eprintln!("  • Input         - 6 tools for user interaction");
-        eprintln!("  • Gitent        - 7 tools for agent tracking");
-        eprintln!("  • Clipboard     - 5 tools for session copy/paste");
-        eprintln!("  • Transform     - 7 tools for text/data processing\n");
+        print_module_summaries(&enabled_modules);
+        print_init_times(&server, &enabled_modules);
     }
 
     eprintln!("✓ Server ready and listening for HTTP requests...");
     eprintln!("ℹ Press Ctrl+C to stop\n");
 
-    axum::serve(listener, app).await?;
+    server.serve(listener).await
+}
 
-    Ok(())
+fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Settings from poly-mcp.toml, overridden by command-line flags; read again
+    // the same way on config/reload and SIGHUP
+    let config = cli.load_config()?;
+
+    // Initialize logging; stdout is reserved for JSON-RPC in stdio mode, and
+    // clients get events as notifications/message at the level they choose.
+    // Spans also go to an OTLP collector when [telemetry] asks for it.
+    let telemetry = Telemetry::init(&config.telemetry)?;
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(io::stderr)
+                .with_filter(LevelFilter::INFO),
+        )
+        .with(McpLogLayer)
+        .with(telemetry.as_ref().map(|telemetry| telemetry.layer()))
+        .init();
+    let reload_cli = cli.clone();
+    let server = PolyMcpBuilder::new()
+        .config(config.clone())
+        .config_loader(move || reload_cli.load_config())
+        .build()?;
 
     // Handle --list-modules flag
     if cli.list_modules {
-        let server = PolyMcp::new();
-        server.list_all_modules();
+        list_all_modules(&server.enabled_modules());
         return Ok(());
     }
 
     // Choose mode based on CLI flags
     if cli.server {
-        run_http_mode(&cli).await
+        run_http_mode(&cli, &config, server).await
     } else {
-        run_stdio_mode(&cli).await
+        run_stdio_mode(&cli, server).await
     }
 }
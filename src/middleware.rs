@@ -0,0 +1,277 @@
+// Hooks around every tool call, for embedders to log, rewrite, cache or limit calls
+
+use crate::registry::ToolModule;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The tool call a middleware hook runs for
+pub struct Call<'a> {
+    /// Built-in tool name, whatever name the client used
+    pub tool: &'a str,
+    /// The module that provides the tool, or `None` for an unknown tool
+    pub module: Option<&'a dyn ToolModule>,
+    /// The tenant whose session made the call, on servers with `[tenants]`
+    pub tenant: Option<&'a str>,
+    pub started: Instant,
+}
+
+impl Call<'_> {
+    pub fn module_name(&self) -> Option<&str> {
+        self.module.map(|module| module.name())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Code that runs around every tool call, added with [`crate::PolyMcpBuilder::middleware`].
+///
+/// `before_call` hooks run in the order the middlewares were added, before the policy
+/// check; `after_call` and `on_error` run in reverse order, and only for middlewares
+/// whose `before_call` let the call through. Calls made with `call_tool` and
+/// over JSON-RPC go through the same hooks.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Inspect or rewrite the arguments. Returning a result answers the call without
+    /// running the tool (or the middlewares after this one); an error rejects it.
+    async fn before_call(&self, _call: &Call<'_>, _args: &mut Value) -> Result<Option<Value>> {
+        Ok(None)
+    }
+
+    /// Inspect or rewrite the result of a call that succeeded. An error fails the call.
+    async fn after_call(&self, _call: &Call<'_>, _args: &Value, _result: &mut Value) -> Result<()> {
+        Ok(())
+    }
+
+    /// Observe a call that failed, timed out or was cancelled
+    async fn on_error(&self, _call: &Call<'_>, _args: &Value, _error: &anyhow::Error) {}
+}
+
+/// Lets the embedder keep a handle to a middleware it added, e.g. to read [`Timing::report`]
+#[async_trait]
+impl<M: Middleware + ?Sized> Middleware for std::sync::Arc<M> {
+    async fn before_call(&self, call: &Call<'_>, args: &mut Value) -> Result<Option<Value>> {
+        (**self).before_call(call, args).await
+    }
+
+    async fn after_call(&self, call: &Call<'_>, args: &Value, result: &mut Value) -> Result<()> {
+        (**self).after_call(call, args, result).await
+    }
+
+    async fn on_error(&self, call: &Call<'_>, args: &Value, error: &anyhow::Error) {
+        (**self).on_error(call, args, error).await
+    }
+}
+
+/// Per-tool call counts and durations, logged at debug level as calls finish,
+/// with a warning for calls slower than the threshold if one is set
+#[derive(Default)]
+pub struct Timing {
+    slow: Option<Duration>,
+    stats: Mutex<HashMap<String, ToolTiming>>,
+}
+
+/// What [`Timing`] has seen of one tool
+#[derive(Debug, Clone, Default)]
+pub struct ToolTiming {
+    pub calls: u64,
+    pub errors: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Timing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Warn about calls that take longer than `threshold`
+    pub fn slow_threshold(mut self, threshold: Duration) -> Self {
+        self.slow = Some(threshold);
+        self
+    }
+
+    /// Timings of every tool called so far, by tool name
+    pub fn stats(&self) -> HashMap<String, ToolTiming> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// The timings as JSON, slowest total first
+    pub fn report(&self) -> Value {
+        let mut stats: Vec<(String, ToolTiming)> = self.stats().into_iter().collect();
+        stats.sort_by_key(|(_, timing)| std::cmp::Reverse(timing.total));
+        let tools: Vec<Value> = stats
+            .into_iter()
+            .map(|(tool, timing)| {
+                json!({
+                    "tool": tool,
+                    "calls": timing.calls,
+                    "errors": timing.errors,
+                    "total_ms": timing.total.as_millis() as u64,
+                    "mean_ms": (timing.total / timing.calls.max(1) as u32).as_millis() as u64,
+                    "max_ms": timing.max.as_millis() as u64,
+                })
+            })
+            .collect();
+        json!({ "tools": tools })
+    }
+
+    fn record(&self, call: &Call<'_>, failed: bool) {
+        let elapsed = call.elapsed();
+        tracing::debug!("{} took {:.1} ms", call.tool, elapsed.as_secs_f64() * 1000.0);
+        if self.slow.is_some_and(|slow| elapsed > slow) {
+            tracing::warn!("{} was slow: {:.1} s", call.tool, elapsed.as_secs_f64());
+        }
+        let mut stats = self.stats.lock().unwrap();
+        let timing = stats.entry(call.tool.to_string()).or_default();
+        timing.calls += 1;
+        if failed {
+            timing.errors += 1;
+        }
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+    }
+}
+
+#[async_trait]
+impl Middleware for Timing {
+    async fn after_call(&self, call: &Call<'_>, _args: &Value, _result: &mut Value) -> Result<()> {
+        self.record(call, false);
+        Ok(())
+    }
+
+    async fn on_error(&self, call: &Call<'_>, _args: &Value, _error: &anyhow::Error) {
+        self.record(call, true);
+    }
+}
+
+/// Rejects calls whose arguments do not match the tool's `inputSchema` before the
+/// tool runs: missing required arguments, wrong JSON types and values outside an `enum`.
+/// Arguments the schema does not describe are let through.
+#[derive(Default)]
+pub struct ValidateArguments {
+    // inputSchema by tool name, looked up on a tool's first call
+    schemas: Mutex<HashMap<String, Value>>,
+}
+
+impl ValidateArguments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn schema(&self, call: &Call<'_>) -> Option<Value> {
+        if let Some(schema) = self.schemas.lock().unwrap().get(call.tool) {
+            return Some(schema.clone());
+        }
+        let schema = call
+            .module?
+            .tools()
+            .into_iter()
+            .find(|tool| tool["name"] == call.tool)?
+            .get("inputSchema")?
+            .clone();
+        self.schemas.lock().unwrap().insert(call.tool.to_string(), schema.clone());
+        Some(schema)
+    }
+}
+
+#[async_trait]
+impl Middleware for ValidateArguments {
+    async fn before_call(&self, call: &Call<'_>, args: &mut Value) -> Result<Option<Value>> {
+        let Some(schema) = self.schema(call) else {
+            return Ok(None);
+        };
+        let mut problems = Vec::new();
+        check_value(&schema, args, "arguments", &mut problems);
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid arguments for {}: {}", call.tool, problems.join("; "));
+        }
+        Ok(None)
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn check_value(schema: &Value, value: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            problems.push(format!("{} must be {}, not {}", path, types.join(" or "), type_name(value)));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            problems.push(format!("{} must be one of {}", path, allowed.join(", ")));
+        }
+    }
+    match value {
+        Value::Object(map) => check_object(schema, map, path, problems),
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_value(item_schema, item, &format!("{}[{}]", path, i), problems);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_object(schema: &Value, map: &Map<String, Value>, path: &str, problems: &mut Vec<String>) {
+    let field = |name: &str| match path {
+        "arguments" => format!("'{}'", name),
+        _ => format!("{}.{}", path, name),
+    };
+    for name in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+        if let Some(name) = name.as_str() {
+            if map.get(name).is_none_or(Value::is_null) {
+                problems.push(format!("{} is required", field(name)));
+            }
+        }
+    }
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+    for (name, value) in map {
+        // null stands for an omitted optional argument
+        if let (Some(property), false) = (properties.get(name), value.is_null()) {
+            check_value(property, value, &field(name), problems);
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "string" => value.is_string(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
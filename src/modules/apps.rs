@@ -0,0 +1,501 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::config::AppsConfig;
+use crate::paths;
+use crate::registry::ToolModule;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+pub struct AppsModule {
+    // Lowercased [apps] allowed_apps; empty means any application
+    allowed_apps: Vec<String>,
+}
+
+impl Default for AppsModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppsModule {
+    pub fn new() -> Self {
+        Self::with_config(&AppsConfig::default())
+    }
+
+    pub fn with_config(config: &AppsConfig) -> Self {
+        Self {
+            allowed_apps: config.allowed_apps.iter().map(|app| app.to_lowercase()).collect(),
+        }
+    }
+
+    /// Reject applications missing from the configured allow list
+    fn check_allowed(&self, app: &str) -> Result<()> {
+        if self.allowed_apps.is_empty() {
+            return Ok(());
+        }
+
+        let name = Path::new(app)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(app)
+            .trim_end_matches(".app")
+            .trim_end_matches(".exe")
+            .to_lowercase();
+        if self.allowed_apps.contains(&name) {
+            Ok(())
+        } else {
+            anyhow::bail!(
+                "Application '{}' is not in the allowed apps ({})",
+                app,
+                self.allowed_apps.join(", ")
+            )
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "apps_list",
+                "description": "List open windows and the applications that own them. Uses wmctrl on Linux (X11), System Events on macOS, and window-owning processes on Windows",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "apps_focus",
+                "description": "Bring an application or window to the front",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "app": {
+                            "type": "string",
+                            "description": "Application name (macOS/Windows) or window class (Linux), as reported by apps_list"
+                        },
+                        "window_id": {
+                            "type": "string",
+                            "description": "Window id from apps_list (Linux only)"
+                        },
+                        "title": {
+                            "type": "string",
+                            "description": "Focus the first window whose title contains this text (Linux only)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "apps_launch",
+                "description": "Start an application without waiting for it to exit",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "app": {
+                            "type": "string",
+                            "description": "Application name or path, e.g. 'firefox', 'Safari', 'notepad.exe'. On Linux a name ending in .desktop is started with gtk-launch"
+                        },
+                        "args": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Arguments passed to the application"
+                        }
+                    },
+                    "required": ["app"]
+                }
+            }),
+            json!({
+                "name": "apps_quit",
+                "description": "Ask an application to quit, or kill it with force",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "app": {
+                            "type": "string",
+                            "description": "Application or process name"
+                        },
+                        "force": {
+                            "type": "boolean",
+                            "description": "Kill instead of asking to quit; unsaved work is lost (default: false)"
+                        }
+                    },
+                    "required": ["app"]
+                }
+            }),
+            json!({
+                "name": "apps_open",
+                "description": "Open a URL or file with its default handler (xdg-open, open, Start-Process), or with a given application",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "target": {
+                            "type": "string",
+                            "description": "URL (https://, mailto:, ...) or path to a file or directory"
+                        },
+                        "app": {
+                            "type": "string",
+                            "description": "Open with this application instead of the default handler"
+                        }
+                    },
+                    "required": ["target"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn list(&self, _args: Value) -> Result<Value> {
+        let windows = if cfg!(target_os = "macos") {
+            list_windows_macos().await?
+        } else if cfg!(windows) {
+            list_windows_windows().await?
+        } else {
+            list_windows_linux().await?
+        };
+
+        // Group windows by owning application
+        let mut apps: BTreeMap<String, Value> = BTreeMap::new();
+        for window in &windows {
+            let name = window["app"].as_str().unwrap_or("unknown").to_string();
+            let entry = apps.entry(name.clone()).or_insert_with(|| {
+                json!({ "name": name, "pids": [], "window_count": 0 })
+            });
+            if let Some(pid) = window["pid"].as_u64() {
+                let pids = entry["pids"].as_array_mut().unwrap();
+                if !pids.contains(&json!(pid)) {
+                    pids.push(json!(pid));
+                }
+            }
+            entry["window_count"] = json!(entry["window_count"].as_u64().unwrap_or(0) + 1);
+            if window["frontmost"].as_bool() == Some(true) {
+                entry["frontmost"] = json!(true);
+            }
+        }
+
+        Ok(json!({
+            "apps": apps.into_values().collect::<Vec<_>>(),
+            "windows": windows,
+            "window_count": windows.len()
+        }))
+    }
+
+    pub async fn focus(&self, args: Value) -> Result<Value> {
+        let app = args["app"].as_str();
+        let window_id = args["window_id"].as_str();
+        let title = args["title"].as_str();
+        if let Some(app) = app {
+            self.check_allowed(app)?;
+        }
+
+        if cfg!(target_os = "macos") {
+            let app = app.context("Missing 'app' parameter")?;
+            run("osascript", &["-e", "on run argv", "-e", "tell application (item 1 of argv) to activate", "-e", "end run", app]).await?;
+        } else if cfg!(windows) {
+            let app = app.context("Missing 'app' parameter")?;
+            let output = run_powershell(
+                "(New-Object -ComObject WScript.Shell).AppActivate($env:POLY_MCP_APP)",
+                &[("POLY_MCP_APP", app)],
+            )
+            .await?;
+            if output.trim() != "True" {
+                anyhow::bail!("No window found for '{}'", app);
+            }
+        } else {
+            // wmctrl: -i takes a window id, -x matches the window class, otherwise the title
+            match (window_id, app, title) {
+                (Some(id), _, _) => run("wmctrl", &["-ia", id]).await,
+                (None, Some(app), _) => run("wmctrl", &["-xa", app]).await,
+                (None, None, Some(title)) => run("wmctrl", &["-a", title]).await,
+                (None, None, None) => anyhow::bail!("Pass 'app', 'window_id' or 'title'"),
+            }
+            .context("No matching window found")?;
+        }
+
+        Ok(json!({
+            "focused": true,
+            "app": app,
+            "window_id": window_id,
+            "title": title
+        }))
+    }
+
+    pub async fn launch(&self, args: Value) -> Result<Value> {
+        let app = args["app"].as_str().context("Missing 'app' parameter")?;
+        let app_args: Vec<String> = args["args"]
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        self.check_allowed(app)?;
+
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = Command::new("open");
+            command.args(["-n", "-a", app]);
+            if !app_args.is_empty() {
+                command.arg("--args").args(&app_args);
+            }
+            command
+        } else if cfg!(windows) || !app.ends_with(".desktop") {
+            let mut command = Command::new(app);
+            command.args(&app_args);
+            command
+        } else {
+            let mut command = Command::new("gtk-launch");
+            command.arg(app.trim_end_matches(".desktop")).args(&app_args);
+            command
+        };
+
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to launch {}", app))?;
+
+        Ok(json!({
+            "launched": true,
+            "app": app,
+            "args": app_args,
+            "pid": child.id()
+        }))
+    }
+
+    pub async fn quit(&self, args: Value) -> Result<Value> {
+        let app = args["app"].as_str().context("Missing 'app' parameter")?;
+        let force = args["force"].as_bool().unwrap_or(false);
+        self.check_allowed(app)?;
+
+        if cfg!(target_os = "macos") && !force {
+            run("osascript", &["-e", "on run argv", "-e", "tell application (item 1 of argv) to quit", "-e", "end run", app]).await?;
+        } else if cfg!(windows) {
+            let image = if app.to_lowercase().ends_with(".exe") {
+                app.to_string()
+            } else {
+                format!("{}.exe", app)
+            };
+            let mut taskkill_args = vec!["/IM", image.as_str()];
+            if force {
+                taskkill_args.push("/F");
+            }
+            run("taskkill", &taskkill_args).await?;
+        } else {
+            let signal = if force { "-KILL" } else { "-TERM" };
+            run("pkill", &[signal, "-x", app])
+                .await
+                .with_context(|| format!("No running process named '{}'", app))?;
+        }
+
+        Ok(json!({
+            "quit": true,
+            "app": app,
+            "force": force
+        }))
+    }
+
+    pub async fn open(&self, args: Value) -> Result<Value> {
+        let target = args["target"].as_str().context("Missing 'target' parameter")?;
+        let app = args["app"].as_str();
+        if let Some(app) = app {
+            self.check_allowed(app)?;
+        }
+
+        // Paths are resolved so the handler does not depend on the server's working directory
+        let is_url = is_url(target);
+        let target = if is_url {
+            target.to_string()
+        } else {
+            let canonical = std::fs::canonicalize(target)
+                .with_context(|| format!("No such file or directory: {}", target))?;
+            // Windows handlers do not understand \\?\ paths
+            paths::simplify(&canonical).to_string_lossy().into_owned()
+        };
+
+        if cfg!(target_os = "macos") {
+            match app {
+                Some(app) => run("open", &["-a", app, &target]).await?,
+                None => run("open", &[&target]).await?,
+            };
+        } else if cfg!(windows) {
+            // Values go through the environment so they are never parsed as PowerShell
+            match app {
+                Some(app) => run_powershell(
+                    "Start-Process -FilePath $env:POLY_MCP_APP -ArgumentList $env:POLY_MCP_TARGET",
+                    &[("POLY_MCP_APP", app), ("POLY_MCP_TARGET", &target)],
+                )
+                .await?,
+                None => run_powershell("Start-Process $env:POLY_MCP_TARGET", &[("POLY_MCP_TARGET", &target)]).await?,
+            };
+        } else {
+            let (program, program_args) = match app {
+                Some(app) => (app, vec![target.as_str()]),
+                None => ("xdg-open", vec![target.as_str()]),
+            };
+            Command::new(program)
+                .args(program_args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .with_context(|| format!("Failed to run {}", program))?;
+        }
+
+        Ok(json!({
+            "opened": true,
+            "target": target,
+            "kind": if is_url { "url" } else { "path" },
+            "app": app
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for AppsModule {
+    fn name(&self) -> &str {
+        "apps"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "apps_list" => self.list(args).await,
+            "apps_focus" => self.focus(args).await,
+            "apps_launch" => self.launch(args).await,
+            "apps_quit" => self.quit(args).await,
+            "apps_open" => self.open(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// Run a command to completion, returning its stdout
+async fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow::anyhow!("{} is not installed", program),
+            _ => anyhow::anyhow!("Failed to run {}: {}", program, e),
+        })?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn run_powershell(script: &str, env: &[(&str, &str)]) -> Result<String> {
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .stdin(Stdio::null());
+    for (name, value) in env {
+        command.env(name, value);
+    }
+    let output = command.output().await.context("Failed to run powershell")?;
+    if !output.status.success() {
+        anyhow::bail!("powershell failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `wmctrl -lpx` lines: id, desktop, pid, instance.class, host, title
+async fn list_windows_linux() -> Result<Vec<Value>> {
+    let output = run("wmctrl", &["-lpx"])
+        .await
+        .context("Listing windows needs wmctrl and an X11 (or XWayland) session")?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let (id, rest) = next_field(line)?;
+            let (desktop, rest) = next_field(rest)?;
+            let (pid, rest) = next_field(rest)?;
+            let (class, rest) = next_field(rest)?;
+            let (_host, title) = next_field(rest)?;
+            let desktop = desktop.parse::<i64>().ok()?;
+            let pid = pid.parse::<u64>().ok()?;
+            let title = title.trim();
+            let app = class.rsplit('.').next().unwrap_or(class);
+            Some(json!({
+                "id": id,
+                "title": title,
+                "app": app,
+                "class": class,
+                "pid": (pid > 0).then_some(pid),
+                // -1 means the window is shown on every desktop
+                "desktop": desktop
+            }))
+        })
+        .collect())
+}
+
+/// Whether `target` starts with a URL scheme such as `https:` or `mailto:`.
+/// Single letters are Windows drive letters, not schemes.
+fn is_url(target: &str) -> bool {
+    match target.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Split off the first whitespace-separated field
+fn next_field(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    (end > 0).then(|| (&s[..end], &s[end..]))
+}
+
+async fn list_windows_macos() -> Result<Vec<Value>> {
+    let script = r#"
+        var se = Application("System Events");
+        var windows = [];
+        se.applicationProcesses.whose({ backgroundOnly: false })().forEach(function (p) {
+            var names = [];
+            try { names = p.windows.name(); } catch (e) {}
+            var frontmost = p.frontmost();
+            if (names.length === 0) {
+                windows.push({ app: p.name(), pid: p.unixId(), title: null, frontmost: frontmost });
+            }
+            names.forEach(function (name) {
+                windows.push({ app: p.name(), pid: p.unixId(), title: name, frontmost: frontmost });
+            });
+        });
+        JSON.stringify(windows);
+    "#;
+    let output = run("osascript", &["-l", "JavaScript", "-e", script])
+        .await
+        .context("Listing windows needs Accessibility permission for the terminal running poly-mcp")?;
+    let windows: Value = serde_json::from_str(output.trim()).context("Unexpected output from System Events")?;
+    Ok(windows.as_array().cloned().unwrap_or_default())
+}
+
+async fn list_windows_windows() -> Result<Vec<Value>> {
+    let script = "@(Get-Process | Where-Object { $_.MainWindowHandle -ne 0 } | \
+                  Select-Object @{n='id';e={[string][int64]$_.MainWindowHandle}}, @{n='title';e={$_.MainWindowTitle}}, \
+                  @{n='app';e={$_.ProcessName}}, @{n='pid';e={$_.Id}}) | ConvertTo-Json -Compress";
+    let output = run_powershell(script, &[]).await?;
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let windows: Value = serde_json::from_str(output.trim()).context("Unexpected output from Get-Process")?;
+    // ConvertTo-Json unwraps single-element arrays
+    Ok(match windows {
+        Value::Array(windows) => windows,
+        window => vec![window],
+    })
+}
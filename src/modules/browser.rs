@@ -0,0 +1,788 @@
+use serde_json::{json, Value};
+use anyhow::{anyhow, Result, Context as _};
+use async_trait::async_trait;
+use crate::config::BrowserConfig;
+use crate::paths;
+use crate::provenance;
+use crate::registry::ToolModule;
+use base64::Engine;
+use chromiumoxide::cdp::browser_protocol::browser::{SetDownloadBehaviorBehavior, SetDownloadBehaviorParams};
+use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
+use chromiumoxide::cdp::js_protocol::runtime::EvaluateParams;
+use chromiumoxide::page::ScreenshotParams;
+use chromiumoxide::{Browser, Page};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_DOWNLOAD_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_MAX_CHARS: usize = 100_000;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A running browser and the pages opened through the tools
+struct BrowserSession {
+    browser: Browser,
+    // Drives the CDP connection; the browser is unusable once it ends
+    handler: JoinHandle<()>,
+    // In the order they were opened; the last one is the default page
+    pages: Vec<(String, Page)>,
+    next_page: u64,
+    profile_dir: PathBuf,
+}
+
+impl Drop for BrowserSession {
+    fn drop(&mut self) {
+        self.handler.abort();
+        let _ = std::fs::remove_dir_all(&self.profile_dir);
+    }
+}
+
+pub struct BrowserModule {
+    config: BrowserConfig,
+    // Chrome is started on first use and kept running between calls
+    session: Mutex<Option<BrowserSession>>,
+    // The download directory is browser-wide, so downloads run one at a time
+    downloads: Mutex<()>,
+}
+
+impl Default for BrowserModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrowserModule {
+    pub fn new() -> Self {
+        Self::with_config(&BrowserConfig::default())
+    }
+
+    pub fn with_config(config: &BrowserConfig) -> Self {
+        Self {
+            config: config.clone(),
+            session: Mutex::new(None),
+            downloads: Mutex::new(()),
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "browser_navigate",
+                "description": "Load a URL in a headless Chrome page and wait for it to finish loading, running its JavaScript. Opens a new page unless page_id is given; the returned page_id is used by the other browser tools",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "url": {
+                            "type": "string",
+                            "description": "URL to load"
+                        },
+                        "page_id": {
+                            "type": "string",
+                            "description": "Existing page to navigate instead of opening a new one"
+                        },
+                        "timeout_secs": {
+                            "type": "integer",
+                            "description": "Seconds to wait for the page to load (default: 30)"
+                        }
+                    },
+                    "required": ["url"]
+                }
+            }),
+            json!({
+                "name": "browser_wait",
+                "description": "Wait until an element matching a CSS selector appears on a page, e.g. content rendered by JavaScript after load",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "selector": {
+                            "type": "string",
+                            "description": "CSS selector to wait for"
+                        },
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to watch (default: the most recently opened page)"
+                        },
+                        "visible": {
+                            "type": "boolean",
+                            "description": "Also wait until the element is visible (default: false)"
+                        },
+                        "timeout_secs": {
+                            "type": "integer",
+                            "description": "Seconds to wait before failing (default: 30)"
+                        }
+                    },
+                    "required": ["selector"]
+                }
+            }),
+            json!({
+                "name": "browser_text",
+                "description": "Extract the rendered text of a page, or of the elements matching a CSS selector. Use format 'html' for the markup instead",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to read (default: the most recently opened page)"
+                        },
+                        "selector": {
+                            "type": "string",
+                            "description": "CSS selector; only matching elements are read (default: the whole page)"
+                        },
+                        "all": {
+                            "type": "boolean",
+                            "description": "Return every match of selector instead of the first (default: false)"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["text", "html"],
+                            "description": "Visible text or outer HTML (default: text)"
+                        },
+                        "max_chars": {
+                            "type": "integer",
+                            "description": "Truncate the result to this many characters (default: 100000)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "browser_click",
+                "description": "Click the element matching a CSS selector, scrolling it into view first",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "selector": {
+                            "type": "string",
+                            "description": "CSS selector of the element to click"
+                        },
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to click in (default: the most recently opened page)"
+                        },
+                        "wait_for_navigation": {
+                            "type": "boolean",
+                            "description": "Wait for the page load the click starts, e.g. on a link (default: false)"
+                        },
+                        "timeout_secs": {
+                            "type": "integer",
+                            "description": "Seconds to wait for the navigation (default: 30)"
+                        }
+                    },
+                    "required": ["selector"]
+                }
+            }),
+            json!({
+                "name": "browser_screenshot",
+                "description": "Capture a page, the full scrollable page, or a single element as PNG or JPEG. Saves to path when given, otherwise returns the image as base64",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to capture (default: the most recently opened page)"
+                        },
+                        "selector": {
+                            "type": "string",
+                            "description": "Capture only the element matching this CSS selector"
+                        },
+                        "full_page": {
+                            "type": "boolean",
+                            "description": "Capture the whole scrollable page rather than the viewport (default: false)"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["png", "jpeg"],
+                            "description": "Image format (default: png)"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "File to write the image to"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "browser_download",
+                "description": "Download a file with the page's cookies and session, by clicking a link or button or by URL, and wait for it to finish. Returns the saved path",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to download from (default: the most recently opened page)"
+                        },
+                        "selector": {
+                            "type": "string",
+                            "description": "CSS selector of the link or button that starts the download"
+                        },
+                        "url": {
+                            "type": "string",
+                            "description": "URL to download, when there is nothing to click"
+                        },
+                        "dest_dir": {
+                            "type": "string",
+                            "description": "Directory to save into (default: [browser] download_dir, or the Downloads folder)"
+                        },
+                        "timeout_secs": {
+                            "type": "integer",
+                            "description": "Seconds to wait for the download to finish (default: 120)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "browser_pages",
+                "description": "List the open browser pages with their ids, URLs and titles",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "browser_close",
+                "description": "Close a page, or with all=true every page and the browser itself",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "page_id": {
+                            "type": "string",
+                            "description": "Page to close (default: the most recently opened page)"
+                        },
+                        "all": {
+                            "type": "boolean",
+                            "description": "Close all pages and stop the browser (default: false)"
+                        }
+                    }
+                }
+            }),
+        ]
+    }
+
+    pub async fn navigate(&self, args: Value) -> Result<Value> {
+        let url = args["url"].as_str().context("Missing 'url' parameter")?;
+        let timeout = timeout_arg(&args, DEFAULT_TIMEOUT_SECS);
+
+        let (page_id, page) = match args["page_id"].as_str() {
+            Some(id) => self.page(Some(id)).await?,
+            None => self.open_page().await?,
+        };
+
+        provenance::fetched(url);
+        tokio::time::timeout(timeout, page.goto(url))
+            .await
+            .map_err(|_| anyhow!("Page did not finish loading within {}s: {}", timeout.as_secs(), url))?
+            .map_err(|e| anyhow!("Failed to load {}: {}", url, e))?;
+
+        Ok(json!({
+            "page_id": page_id,
+            "url": page.url().await?,
+            "title": page.get_title().await?,
+        }))
+    }
+
+    pub async fn wait(&self, args: Value) -> Result<Value> {
+        let selector = args["selector"].as_str().context("Missing 'selector' parameter")?;
+        let visible = args["visible"].as_bool().unwrap_or(false);
+        let timeout = timeout_arg(&args, DEFAULT_TIMEOUT_SECS);
+        let (page_id, page) = self.page(args["page_id"].as_str()).await?;
+
+        let script = format!(
+            r#"(() => {{
+                const matches = [...document.querySelectorAll({selector})];
+                const shown = matches.filter(el => {{
+                    const rect = el.getBoundingClientRect();
+                    const style = getComputedStyle(el);
+                    return rect.width > 0 && rect.height > 0 && style.visibility !== 'hidden';
+                }});
+                return {{ count: matches.length, visible: shown.length }};
+            }})()"#,
+            selector = serde_json::to_string(selector)?
+        );
+
+        let started = Instant::now();
+        loop {
+            let counts = evaluate(&page, &script).await?;
+            let found = if visible { counts["visible"].as_u64() } else { counts["count"].as_u64() };
+            if found.unwrap_or(0) > 0 {
+                return Ok(json!({
+                    "page_id": page_id,
+                    "selector": selector,
+                    "count": counts["count"],
+                    "visible": counts["visible"],
+                    "elapsed_ms": started.elapsed().as_millis() as u64,
+                }));
+            }
+            if started.elapsed() >= timeout {
+                anyhow::bail!(
+                    "No {}element matching '{}' after {}s",
+                    if visible { "visible " } else { "" },
+                    selector,
+                    timeout.as_secs()
+                );
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn text(&self, args: Value) -> Result<Value> {
+        let selector = args["selector"].as_str();
+        let all = args["all"].as_bool().unwrap_or(false);
+        let html = match args["format"].as_str().unwrap_or("text") {
+            "text" => false,
+            "html" => true,
+            other => anyhow::bail!("Unknown format '{}' (expected text or html)", other),
+        };
+        let max_chars = args["max_chars"].as_u64().map(|n| n as usize).unwrap_or(DEFAULT_MAX_CHARS);
+        let (page_id, page) = self.page(args["page_id"].as_str()).await?;
+
+        let script = format!(
+            r#"(() => {{
+                const selector = {selector};
+                const read = el => {html} ? el.outerHTML : el.innerText;
+                if (selector === null) {{
+                    return [{html} ? document.documentElement.outerHTML : (document.body ? document.body.innerText : '')];
+                }}
+                const matches = {all} ? [...document.querySelectorAll(selector)] : [document.querySelector(selector)];
+                return matches.filter(el => el).map(read);
+            }})()"#,
+            selector = serde_json::to_string(&selector)?,
+        );
+        let texts: Vec<String> = serde_json::from_value(evaluate(&page, &script).await?)
+            .context("Unexpected result from the page")?;
+        if let (Some(selector), true) = (selector, texts.is_empty()) {
+            anyhow::bail!("No element matching '{}'", selector);
+        }
+
+        let mut truncated = false;
+        let mut budget = max_chars;
+        let texts: Vec<String> = texts
+            .into_iter()
+            .map(|text| {
+                let count = text.chars().count();
+                if count <= budget {
+                    budget -= count;
+                    text
+                } else {
+                    truncated = true;
+                    let head = text.chars().take(budget).collect();
+                    budget = 0;
+                    head
+                }
+            })
+            .collect();
+
+        let mut result = json!({
+            "page_id": page_id,
+            "url": page.url().await?,
+            "title": page.get_title().await?,
+            "truncated": truncated,
+        });
+        if all {
+            result["count"] = json!(texts.len());
+            result["matches"] = json!(texts);
+        } else {
+            result["text"] = json!(texts.into_iter().next().unwrap_or_default());
+        }
+        Ok(result)
+    }
+
+    pub async fn click(&self, args: Value) -> Result<Value> {
+        let selector = args["selector"].as_str().context("Missing 'selector' parameter")?;
+        let wait_for_navigation = args["wait_for_navigation"].as_bool().unwrap_or(false);
+        let timeout = timeout_arg(&args, DEFAULT_TIMEOUT_SECS);
+        let (page_id, page) = self.page(args["page_id"].as_str()).await?;
+
+        let element = page
+            .find_element(selector)
+            .await
+            .map_err(|_| anyhow!("No element matching '{}'", selector))?;
+        element
+            .click()
+            .await
+            .map_err(|e| anyhow!("Failed to click '{}': {}", selector, e))?;
+
+        if wait_for_navigation {
+            tokio::time::timeout(timeout, page.wait_for_navigation())
+                .await
+                .map_err(|_| anyhow!("No page load finished within {}s of the click", timeout.as_secs()))??;
+        }
+
+        Ok(json!({
+            "page_id": page_id,
+            "clicked": selector,
+            "url": page.url().await?,
+            "title": page.get_title().await?,
+        }))
+    }
+
+    pub async fn screenshot(&self, args: Value) -> Result<Value> {
+        let (format, mime_type) = match args["format"].as_str().unwrap_or("png") {
+            "png" => (CaptureScreenshotFormat::Png, "image/png"),
+            "jpeg" | "jpg" => (CaptureScreenshotFormat::Jpeg, "image/jpeg"),
+            other => anyhow::bail!("Unknown format '{}' (expected png or jpeg)", other),
+        };
+        let full_page = args["full_page"].as_bool().unwrap_or(false);
+        let (page_id, page) = self.page(args["page_id"].as_str()).await?;
+
+        let image = match args["selector"].as_str() {
+            Some(selector) => {
+                let element = page
+                    .find_element(selector)
+                    .await
+                    .map_err(|_| anyhow!("No element matching '{}'", selector))?;
+                element.screenshot(format).await?
+            }
+            None => {
+                let params = ScreenshotParams::builder().format(format).full_page(full_page).build();
+                page.screenshot(params).await?
+            }
+        };
+
+        let mut result = json!({
+            "page_id": page_id,
+            "mime_type": mime_type,
+            "bytes": image.len(),
+        });
+        match args["path"].as_str() {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&path, &image)
+                    .await
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                result["path"] = json!(path.display().to_string());
+            }
+            None => {
+                result["data"] = json!(base64::engine::general_purpose::STANDARD.encode(&image));
+            }
+        }
+        Ok(result)
+    }
+
+    pub async fn download(&self, args: Value) -> Result<Value> {
+        let selector = args["selector"].as_str();
+        let url = args["url"].as_str();
+        if selector.is_none() && url.is_none() {
+            anyhow::bail!("Pass 'selector' to click or 'url' to fetch");
+        }
+        let timeout = timeout_arg(&args, DEFAULT_DOWNLOAD_TIMEOUT_SECS);
+        let dest_dir = args["dest_dir"]
+            .as_str()
+            .map(PathBuf::from)
+            .or_else(|| self.config.download_dir.clone())
+            .or_else(dirs::download_dir)
+            .unwrap_or_else(|| std::env::temp_dir().join("poly-mcp-downloads"));
+        let (page_id, page) = self.page(args["page_id"].as_str()).await?;
+
+        let _one_at_a_time = self.downloads.lock().await;
+
+        // Chrome saves into a fresh staging directory so the finished file is easy to spot
+        let staging = dest_dir.join(format!(".poly-mcp-download-{}", uuid::Uuid::new_v4().simple()));
+        tokio::fs::create_dir_all(&staging)
+            .await
+            .with_context(|| format!("Failed to create {}", staging.display()))?;
+        let result = self.download_into(&page, selector, url, &staging, &dest_dir, timeout).await;
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+
+        let path = result?;
+        let size = tokio::fs::metadata(&path).await?.len();
+        Ok(json!({
+            "page_id": page_id,
+            "path": path.display().to_string(),
+            "file_name": path.file_name().map(|n| n.to_string_lossy().to_string()),
+            "size": size,
+        }))
+    }
+
+    async fn download_into(
+        &self,
+        page: &Page,
+        selector: Option<&str>,
+        url: Option<&str>,
+        staging: &Path,
+        dest_dir: &Path,
+        timeout: Duration,
+    ) -> Result<PathBuf> {
+        let behavior = SetDownloadBehaviorParams::builder()
+            .behavior(SetDownloadBehaviorBehavior::Allow)
+            .download_path(paths::simplify(&staging.canonicalize()?).display().to_string())
+            .build()
+            .map_err(|e| anyhow!(e))?;
+        self.session()
+            .await?
+            .as_ref()
+            .context("Browser is not running")?
+            .browser
+            .execute(behavior)
+            .await
+            .context("Failed to set the download directory")?;
+
+        match (selector, url) {
+            (Some(selector), _) => {
+                let element = page
+                    .find_element(selector)
+                    .await
+                    .map_err(|_| anyhow!("No element matching '{}'", selector))?;
+                element.click().await?;
+            }
+            (None, Some(url)) => {
+                // A download link keeps the page's cookies and referrer, unlike a direct fetch
+                let script = format!(
+                    r#"(() => {{
+                        const link = document.createElement('a');
+                        link.href = {url};
+                        link.download = '';
+                        document.body.appendChild(link);
+                        link.click();
+                        link.remove();
+                        return true;
+                    }})()"#,
+                    url = serde_json::to_string(url)?
+                );
+                evaluate(page, &script).await?;
+            }
+            (None, None) => unreachable!(),
+        }
+
+        let file = wait_for_download(staging, timeout).await?;
+        let name = file.file_name().context("Download has no file name")?.to_string_lossy().to_string();
+        let target = unique_path(dest_dir, &name);
+        tokio::fs::rename(&file, &target)
+            .await
+            .with_context(|| format!("Failed to move the download to {}", target.display()))?;
+        Ok(target)
+    }
+
+    pub async fn pages(&self, _args: Value) -> Result<Value> {
+        let (running, pages) = match self.session.lock().await.as_ref() {
+            Some(session) => (true, session.pages.clone()),
+            None => (false, Vec::new()),
+        };
+
+        let mut list = Vec::new();
+        for (id, page) in &pages {
+            list.push(json!({
+                "page_id": id,
+                "url": page.url().await.ok().flatten(),
+                "title": page.get_title().await.ok().flatten(),
+            }));
+        }
+        Ok(json!({
+            "running": running,
+            "count": list.len(),
+            "pages": list,
+        }))
+    }
+
+    pub async fn close(&self, args: Value) -> Result<Value> {
+        let mut guard = self.session.lock().await;
+
+        if args["all"].as_bool().unwrap_or(false) {
+            let Some(mut session) = guard.take() else {
+                return Ok(json!({ "closed": 0, "browser_stopped": false }));
+            };
+            let closed = session.pages.len();
+            let _ = session.browser.close().await;
+            let _ = session.browser.wait().await;
+            return Ok(json!({ "closed": closed, "browser_stopped": true }));
+        }
+
+        let session = guard.as_mut().context("No pages are open")?;
+        let index = match args["page_id"].as_str() {
+            Some(id) => session
+                .pages
+                .iter()
+                .position(|(page_id, _)| page_id == id)
+                .with_context(|| format!("Unknown page_id '{}'", id))?,
+            None => session.pages.len().checked_sub(1).context("No pages are open")?,
+        };
+        let (page_id, page) = session.pages.remove(index);
+        page.close().await?;
+        Ok(json!({
+            "closed": page_id,
+            "remaining": session.pages.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>(),
+        }))
+    }
+
+    /// The running browser, starting Chrome if it is not running or has exited
+    async fn session(&self) -> Result<MutexGuard<'_, Option<BrowserSession>>> {
+        let mut guard = self.session.lock().await;
+        if guard.as_ref().is_some_and(|s| s.handler.is_finished()) {
+            tracing::warn!("Browser connection closed; starting a new browser");
+            *guard = None;
+        }
+        if guard.is_none() {
+            *guard = Some(self.launch().await?);
+        }
+        Ok(guard)
+    }
+
+    async fn launch(&self) -> Result<BrowserSession> {
+        // A profile per browser so sessions and concurrent servers do not share cookies
+        let profile_dir = std::env::temp_dir().join(format!("poly-mcp-browser-{}", uuid::Uuid::new_v4().simple()));
+
+        let mut builder = chromiumoxide::BrowserConfig::builder()
+            .user_data_dir(&profile_dir)
+            .window_size(1280, 900)
+            .viewport(None)
+            .request_timeout(Duration::from_secs(300));
+        if let Some(path) = &self.config.chrome_path {
+            builder = builder.chrome_executable(path);
+        }
+        if !self.config.headless {
+            builder = builder.with_head();
+        }
+        if self.config.no_sandbox {
+            builder = builder.no_sandbox();
+        }
+        let launch_config = builder.build().map_err(|e| {
+            anyhow!("{}. Install Chrome or Chromium, or set chrome_path in the [browser] table of poly-mcp.toml", e)
+        })?;
+
+        let (browser, mut handler) = Browser::launch(launch_config).await.map_err(|e| {
+            anyhow!(
+                "Failed to start the browser: {}. When running as root or in a container, set no_sandbox = true in [browser]",
+                e
+            )
+        })?;
+        let handler = tokio::spawn(async move {
+            // Individual protocol errors are not fatal; the stream ends when the browser goes away
+            while let Some(event) = handler.next().await {
+                if let Err(e) = event {
+                    tracing::debug!("Browser protocol error: {}", e);
+                }
+            }
+        });
+
+        Ok(BrowserSession {
+            browser,
+            handler,
+            pages: Vec::new(),
+            next_page: 1,
+            profile_dir,
+        })
+    }
+
+    async fn open_page(&self) -> Result<(String, Page)> {
+        let mut guard = self.session().await?;
+        let session = guard.as_mut().context("Browser is not running")?;
+        if session.pages.len() >= self.config.max_pages {
+            anyhow::bail!(
+                "{} pages are already open (max_pages in [browser]); close one with browser_close or reuse it via page_id",
+                session.pages.len()
+            );
+        }
+
+        let page = session.browser.new_page("about:blank").await.context("Failed to open a page")?;
+        let id = format!("page-{}", session.next_page);
+        session.next_page += 1;
+        session.pages.push((id.clone(), page.clone()));
+        Ok((id, page))
+    }
+
+    /// The page with this id, or the most recently opened one
+    async fn page(&self, page_id: Option<&str>) -> Result<(String, Page)> {
+        let guard = self.session.lock().await;
+        let pages = guard.as_ref().map(|s| s.pages.as_slice()).unwrap_or_default();
+        let found = match page_id {
+            Some(id) => pages.iter().find(|(page_id, _)| page_id == id),
+            None => pages.last(),
+        };
+        match (found, page_id) {
+            (Some(page), _) => Ok(page.clone()),
+            (None, Some(id)) => Err(anyhow!("Unknown page_id '{}'; see browser_pages", id)),
+            (None, None) => Err(anyhow!("No pages are open; start with browser_navigate")),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolModule for BrowserModule {
+    fn name(&self) -> &str {
+        "browser"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "browser_navigate" => self.navigate(args).await,
+            "browser_wait" => self.wait(args).await,
+            "browser_text" => self.text(args).await,
+            "browser_click" => self.click(args).await,
+            "browser_screenshot" => self.screenshot(args).await,
+            "browser_download" => self.download(args).await,
+            "browser_pages" => self.pages(args).await,
+            "browser_close" => self.close(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.close(json!({ "all": true })).await?;
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn timeout_arg(args: &Value, default_secs: u64) -> Duration {
+    Duration::from_secs(args["timeout_secs"].as_u64().unwrap_or(default_secs))
+}
+
+/// Run a JavaScript expression in the page and return its value
+async fn evaluate(page: &Page, script: &str) -> Result<Value> {
+    let params = EvaluateParams::builder()
+        .expression(script)
+        .return_by_value(true)
+        .await_promise(true)
+        .build()
+        .map_err(|e| anyhow!(e))?;
+    let result = page.evaluate_expression(params).await.context("Script failed in the page")?;
+    Ok(result.value().cloned().unwrap_or(Value::Null))
+}
+
+/// Wait for a finished file in `dir`; Chrome writes to a .crdownload file until done
+async fn wait_for_download(dir: &Path, timeout: Duration) -> Result<PathBuf> {
+    let started = Instant::now();
+    let mut in_progress = false;
+    loop {
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "crdownload") {
+                in_progress = true;
+            } else if entry.file_type().await?.is_file() {
+                return Ok(path);
+            }
+        }
+        if started.elapsed() >= timeout {
+            if in_progress {
+                anyhow::bail!("Download did not finish within {}s", timeout.as_secs());
+            }
+            anyhow::bail!("No download started within {}s", timeout.as_secs());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// `dir/name`, or `dir/name (2).ext` and so on when that file exists
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let path = Path::new(name);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
@@ -0,0 +1,691 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::provenance;
+use crate::registry::{Lazy, ToolModule};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub struct CalcModule {
+    // Built on the first currency conversion
+    client: Lazy<reqwest::Client>,
+}
+
+impl Default for CalcModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalcModule {
+    pub fn new() -> Self {
+        let client = Lazy::new(|| {
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .user_agent(format!("poly-mcp/{}", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap()
+        });
+
+        Self { client }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "calc_eval",
+                "description": "Evaluate an arithmetic expression exactly instead of doing mental math. Supports + - * / % ^ (or **), parentheses, constants (pi, e), variables, and functions: sqrt, cbrt, abs, exp, ln, log10, log2, log(x, base), sin, cos, tan, asin, acos, atan, floor, ceil, round, trunc, min, max, pow, hypot",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "expression": {
+                            "type": "string",
+                            "description": "Expression to evaluate, e.g. '(1024 * 3.5) / 8 + sqrt(2)'"
+                        },
+                        "variables": {
+                            "type": "object",
+                            "description": "Named values usable in the expression, e.g. {\"rate\": 0.07}"
+                        },
+                        "precision": {
+                            "type": "integer",
+                            "description": "Round the result to this many decimal places"
+                        }
+                    },
+                    "required": ["expression"]
+                }
+            }),
+            json!({
+                "name": "calc_convert",
+                "description": "Convert a value between units: data size (B, KB, MB, GB, TB, KiB, MiB, GiB, TiB, bit), length, mass, time, temperature (C, F, K), speed, volume, and currency (ISO codes, using provided rates or live rates fetched on request)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "number",
+                            "description": "Value to convert"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Source unit, e.g. 'GiB', 'km', 'F', 'USD'"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Target unit, e.g. 'MB', 'mi', 'C', 'EUR'"
+                        },
+                        "rates": {
+                            "type": "object",
+                            "description": "Currency rates relative to a common base, e.g. {\"USD\": 1, \"EUR\": 0.92}"
+                        },
+                        "fetch_rates": {
+                            "type": "boolean",
+                            "description": "Fetch live currency rates when converting currencies without 'rates' (default: false)"
+                        },
+                        "precision": {
+                            "type": "integer",
+                            "description": "Round the result to this many decimal places"
+                        }
+                    },
+                    "required": ["value", "from", "to"]
+                }
+            }),
+            json!({
+                "name": "calc_stats",
+                "description": "Descriptive statistics over a list of numbers: count, sum, mean, median, mode, min, max, range, variance, standard deviation, and percentiles",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "numbers": {
+                            "type": "array",
+                            "items": {
+                                "type": "number"
+                            },
+                            "description": "Numbers to summarize"
+                        },
+                        "percentiles": {
+                            "type": "array",
+                            "items": {
+                                "type": "number"
+                            },
+                            "description": "Percentiles to compute, 0-100 (default: [25, 50, 75, 90, 95, 99])"
+                        },
+                        "sample": {
+                            "type": "boolean",
+                            "description": "Use sample (n-1) instead of population variance (default: false)"
+                        }
+                    },
+                    "required": ["numbers"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn eval(&self, args: Value) -> Result<Value> {
+        let expression = args["expression"].as_str().context("Missing 'expression' parameter")?;
+
+        let mut variables = HashMap::new();
+        if let Some(vars) = args["variables"].as_object() {
+            for (name, value) in vars {
+                let n = value.as_f64()
+                    .with_context(|| format!("Variable '{}' must be a number", name))?;
+                variables.insert(name.to_lowercase(), n);
+            }
+        }
+
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0, variables: &variables };
+        let result = parser.expr()?;
+        if parser.pos < tokens.len() {
+            anyhow::bail!("Unexpected '{}' at end of expression", tokens[parser.pos]);
+        }
+        if !result.is_finite() {
+            anyhow::bail!("Result is not a finite number ({})", result);
+        }
+
+        let result = round_to(result, args["precision"].as_u64());
+
+        Ok(json!({
+            "expression": expression,
+            "result": result,
+            "formatted": format_number(result)
+        }))
+    }
+
+    pub async fn convert(&self, args: Value) -> Result<Value> {
+        let value = args["value"].as_f64().context("Missing 'value' parameter")?;
+        let from = args["from"].as_str().context("Missing 'from' parameter")?;
+        let to = args["to"].as_str().context("Missing 'to' parameter")?;
+
+        let (result, category, rates_source) = if let (Some(f), Some(t)) = (find_unit(from), find_unit(to)) {
+            if f.category != t.category {
+                anyhow::bail!("Cannot convert {} ({}) to {} ({})", from, f.category, to, t.category);
+            }
+            (f.to_base(value) / t.factor - t.offset, f.category, None)
+        } else if is_currency_code(from) && is_currency_code(to) {
+            let (rates, source) = match args["rates"].as_object() {
+                Some(rates) => (rates.clone(), "provided".to_string()),
+                None if args["fetch_rates"].as_bool().unwrap_or(false) => self.fetch_rates(from).await?,
+                None => anyhow::bail!(
+                    "Currency conversion needs exchange rates: pass 'rates' or set 'fetch_rates' to true"
+                ),
+            };
+            let rate = |code: &str| -> Result<f64> {
+                rates.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(code))
+                    .and_then(|(_, v)| v.as_f64())
+                    .with_context(|| format!("No exchange rate for {}", code.to_uppercase()))
+            };
+            (value / rate(from)? * rate(to)?, "currency", Some(source))
+        } else {
+            let unknown = if find_unit(from).is_none() { from } else { to };
+            anyhow::bail!("Unknown unit: {}", unknown);
+        };
+
+        let result = round_to(result, args["precision"].as_u64());
+
+        Ok(json!({
+            "value": value,
+            "from": from,
+            "to": to,
+            "category": category,
+            "result": result,
+            "formatted": format!("{} {}", format_number(result), to),
+            "rates_source": rates_source
+        }))
+    }
+
+    pub async fn stats(&self, args: Value) -> Result<Value> {
+        let numbers: Vec<f64> = args["numbers"].as_array()
+            .context("Missing 'numbers' parameter")?
+            .iter()
+            .map(|v| v.as_f64().context("'numbers' must contain only numbers"))
+            .collect::<Result<_>>()?;
+
+        if numbers.is_empty() {
+            anyhow::bail!("'numbers' must not be empty");
+        }
+
+        let sample = args["sample"].as_bool().unwrap_or(false);
+        let percentiles: Vec<f64> = match args["percentiles"].as_array() {
+            Some(p) => p.iter().filter_map(|v| v.as_f64()).collect(),
+            None => vec![25.0, 50.0, 75.0, 90.0, 95.0, 99.0],
+        };
+
+        let mut sorted = numbers.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let count = numbers.len();
+        let sum: f64 = numbers.iter().sum();
+        let mean = sum / count as f64;
+        let min = sorted[0];
+        let max = sorted[count - 1];
+
+        let squared_diffs: f64 = numbers.iter().map(|x| (x - mean).powi(2)).sum();
+        let variance = if sample {
+            if count < 2 { None } else { Some(squared_diffs / (count - 1) as f64) }
+        } else {
+            Some(squared_diffs / count as f64)
+        };
+
+        // Mode: most frequent value(s); empty when every value is unique
+        let mut frequencies: Vec<(f64, usize)> = Vec::new();
+        for &x in &sorted {
+            match frequencies.last_mut() {
+                Some((v, n)) if *v == x => *n += 1,
+                _ => frequencies.push((x, 1)),
+            }
+        }
+        let top = frequencies.iter().map(|(_, n)| *n).max().unwrap_or(0);
+        let mode: Vec<f64> = if top > 1 {
+            frequencies.iter().filter(|(_, n)| *n == top).map(|(v, _)| *v).collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut percentile_values = serde_json::Map::new();
+        for p in &percentiles {
+            if !(0.0..=100.0).contains(p) {
+                anyhow::bail!("Percentile {} is outside 0-100", p);
+            }
+            percentile_values.insert(format!("p{}", format_number(*p)), json!(percentile(&sorted, *p)));
+        }
+
+        Ok(json!({
+            "count": count,
+            "sum": sum,
+            "mean": mean,
+            "median": percentile(&sorted, 50.0),
+            "mode": mode,
+            "min": min,
+            "max": max,
+            "range": max - min,
+            "variance": variance,
+            "std_dev": variance.map(f64::sqrt),
+            "variance_kind": if sample { "sample" } else { "population" },
+            "percentiles": percentile_values
+        }))
+    }
+
+    async fn fetch_rates(&self, base: &str) -> Result<(serde_json::Map<String, Value>, String)> {
+        let url = format!("https://open.er-api.com/v6/latest/{}", base.to_uppercase());
+        provenance::fetched(&url);
+        let response: Value = self.client.get().get(&url).send().await
+            .context("Failed to fetch exchange rates")?
+            .json().await
+            .context("Invalid exchange rate response")?;
+
+        let rates = response["rates"].as_object()
+            .with_context(|| format!("Exchange rate service returned no rates for {}", base.to_uppercase()))?
+            .clone();
+        let updated = response["time_last_update_utc"].as_str().unwrap_or("unknown time");
+
+        Ok((rates, format!("open.er-api.com ({})", updated)))
+    }
+}
+
+#[async_trait]
+impl ToolModule for CalcModule {
+    fn name(&self) -> &str {
+        "calc"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "calc_eval" => self.eval(args).await,
+            "calc_convert" => self.convert(args).await,
+            "calc_stats" => self.stats(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    fn warm_up(&self) {
+        self.client.get();
+    }
+}
+
+// --- Expression evaluation ---
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::Op(c) => write!(f, "{}", c),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' => i += 1,
+            '0'..='9' | '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '_') {
+                    i += 1;
+                }
+                // Exponent: 1e6, 2.5E-3
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+                let n = text.parse::<f64>().with_context(|| format!("Invalid number: {}", text))?;
+                tokens.push(Token::Number(n));
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect::<String>().to_lowercase()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                tokens.push(Token::Op('^'));
+                i += 2;
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+            '×' => {
+                tokens.push(Token::Op('*'));
+                i += 1;
+            }
+            '÷' => {
+                tokens.push(Token::Op('/'));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            _ => anyhow::bail!("Unexpected character '{}' at position {}", c, i + 1),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent parser; precedence from lowest: + -, * / %, unary -, ^ (right-associative)
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    variables: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            Some(t) => anyhow::bail!("Expected '{}' but found '{}'", expected, t),
+            None => anyhow::bail!("Expected '{}' but the expression ended", expected),
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = self.peek().cloned() {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match op {
+                '*' => value * rhs,
+                _ if rhs == 0.0 => anyhow::bail!("Division by zero"),
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<f64> {
+        let base = self.primary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<f64> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(Token::LParen) => {
+                let value = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.pos += 1;
+                    let mut args = Vec::new();
+                    if self.peek() != Some(&Token::RParen) {
+                        args.push(self.expr()?);
+                        while self.peek() == Some(&Token::Comma) {
+                            self.pos += 1;
+                            args.push(self.expr()?);
+                        }
+                    }
+                    self.expect(Token::RParen)?;
+                    return call_function(&name, &args);
+                }
+
+                match name.as_str() {
+                    "pi" => Ok(std::f64::consts::PI),
+                    "e" => Ok(std::f64::consts::E),
+                    "tau" => Ok(std::f64::consts::TAU),
+                    _ => self.variables.get(&name).copied()
+                        .with_context(|| format!("Unknown variable '{}'", name)),
+                }
+            }
+            Some(t) => anyhow::bail!("Unexpected '{}'", t),
+            None => anyhow::bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+fn call_function(name: &str, args: &[f64]) -> Result<f64> {
+    let arity = |n: usize| -> Result<()> {
+        if args.len() != n {
+            anyhow::bail!("{}() takes {} argument(s), got {}", name, n, args.len());
+        }
+        Ok(())
+    };
+
+    match name {
+        "min" | "max" => {
+            if args.is_empty() {
+                anyhow::bail!("{}() needs at least one argument", name);
+            }
+            let fold = if name == "min" { f64::min } else { f64::max };
+            Ok(args[1..].iter().fold(args[0], |acc, x| fold(acc, *x)))
+        }
+        "log" if args.len() == 2 => Ok(args[0].log(args[1])),
+        "pow" => {
+            arity(2)?;
+            Ok(args[0].powf(args[1]))
+        }
+        "hypot" => {
+            arity(2)?;
+            Ok(args[0].hypot(args[1]))
+        }
+        "round" if args.len() == 2 => Ok(round_to(args[0], Some(args[1].max(0.0) as u64))),
+        _ => {
+            arity(1)?;
+            let x = args[0];
+            Ok(match name {
+                "sqrt" => x.sqrt(),
+                "cbrt" => x.cbrt(),
+                "abs" => x.abs(),
+                "exp" => x.exp(),
+                "ln" => x.ln(),
+                "log" | "log10" => x.log10(),
+                "log2" => x.log2(),
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                "tan" => x.tan(),
+                "asin" => x.asin(),
+                "acos" => x.acos(),
+                "atan" => x.atan(),
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                "round" => x.round(),
+                "trunc" => x.trunc(),
+                _ => anyhow::bail!("Unknown function '{}'", name),
+            })
+        }
+    }
+}
+
+// --- Units ---
+
+/// A unit expressed as `base = value * factor + offset` within its category
+struct Unit {
+    names: &'static [&'static str],
+    category: &'static str,
+    factor: f64,
+    offset: f64,
+}
+
+impl Unit {
+    fn to_base(&self, value: f64) -> f64 {
+        (value + self.offset) * self.factor
+    }
+}
+
+const fn unit(names: &'static [&'static str], category: &'static str, factor: f64) -> Unit {
+    Unit { names, category, factor, offset: 0.0 }
+}
+
+const UNITS: &[Unit] = &[
+    // Data size (base: byte)
+    unit(&["b", "byte", "bytes"], "data", 1.0),
+    unit(&["bit", "bits"], "data", 0.125),
+    unit(&["kb", "kilobyte", "kilobytes"], "data", 1e3),
+    unit(&["mb", "megabyte", "megabytes"], "data", 1e6),
+    unit(&["gb", "gigabyte", "gigabytes"], "data", 1e9),
+    unit(&["tb", "terabyte", "terabytes"], "data", 1e12),
+    unit(&["pb", "petabyte", "petabytes"], "data", 1e15),
+    unit(&["kib", "kibibyte", "kibibytes"], "data", 1024.0),
+    unit(&["mib", "mebibyte", "mebibytes"], "data", 1_048_576.0),
+    unit(&["gib", "gibibyte", "gibibytes"], "data", 1_073_741_824.0),
+    unit(&["tib", "tebibyte", "tebibytes"], "data", 1_099_511_627_776.0),
+    unit(&["pib", "pebibyte", "pebibytes"], "data", 1_125_899_906_842_624.0),
+    // Length (base: meter)
+    unit(&["mm", "millimeter", "millimeters"], "length", 0.001),
+    unit(&["cm", "centimeter", "centimeters"], "length", 0.01),
+    unit(&["m", "meter", "meters"], "length", 1.0),
+    unit(&["km", "kilometer", "kilometers"], "length", 1000.0),
+    unit(&["in", "inch", "inches"], "length", 0.0254),
+    unit(&["ft", "foot", "feet"], "length", 0.3048),
+    unit(&["yd", "yard", "yards"], "length", 0.9144),
+    unit(&["mi", "mile", "miles"], "length", 1609.344),
+    unit(&["nmi", "nautical_mile", "nautical_miles"], "length", 1852.0),
+    // Mass (base: kilogram)
+    unit(&["mg", "milligram", "milligrams"], "mass", 1e-6),
+    unit(&["g", "gram", "grams"], "mass", 0.001),
+    unit(&["kg", "kilogram", "kilograms"], "mass", 1.0),
+    unit(&["t", "tonne", "tonnes"], "mass", 1000.0),
+    unit(&["oz", "ounce", "ounces"], "mass", 0.028_349_523_125),
+    unit(&["lb", "lbs", "pound", "pounds"], "mass", 0.453_592_37),
+    // Time (base: second)
+    unit(&["ns", "nanosecond", "nanoseconds"], "time", 1e-9),
+    unit(&["us", "µs", "microsecond", "microseconds"], "time", 1e-6),
+    unit(&["ms", "millisecond", "milliseconds"], "time", 0.001),
+    unit(&["s", "sec", "second", "seconds"], "time", 1.0),
+    unit(&["min", "minute", "minutes"], "time", 60.0),
+    unit(&["h", "hr", "hour", "hours"], "time", 3600.0),
+    unit(&["d", "day", "days"], "time", 86400.0),
+    unit(&["wk", "week", "weeks"], "time", 604_800.0),
+    // Speed (base: meter per second)
+    unit(&["m/s", "mps"], "speed", 1.0),
+    unit(&["km/h", "kph", "kmh"], "speed", 1000.0 / 3600.0),
+    unit(&["mph"], "speed", 0.447_04),
+    unit(&["kn", "knot", "knots"], "speed", 1852.0 / 3600.0),
+    // Volume (base: liter)
+    unit(&["ml", "milliliter", "milliliters"], "volume", 0.001),
+    unit(&["l", "liter", "liters", "litre", "litres"], "volume", 1.0),
+    unit(&["m3", "cubic_meter", "cubic_meters"], "volume", 1000.0),
+    unit(&["gal", "gallon", "gallons"], "volume", 3.785_411_784),
+    unit(&["qt", "quart", "quarts"], "volume", 0.946_352_946),
+    unit(&["cup", "cups"], "volume", 0.236_588_236_5),
+    unit(&["floz", "fl_oz"], "volume", 0.029_573_529_562_5),
+    // Temperature (base: kelvin)
+    Unit { names: &["k", "kelvin"], category: "temperature", factor: 1.0, offset: 0.0 },
+    Unit { names: &["c", "celsius"], category: "temperature", factor: 1.0, offset: 273.15 },
+    Unit { names: &["f", "fahrenheit"], category: "temperature", factor: 5.0 / 9.0, offset: 459.67 },
+];
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    // Unit names are case-insensitive, so bits must be spelled out ("bit"); "b"/"B" is a byte
+    let name = name.trim().trim_start_matches('°').to_lowercase();
+    UNITS.iter().find(|u| u.names.contains(&name.as_str()))
+}
+
+fn is_currency_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+// --- Helpers ---
+
+/// Linear-interpolated percentile over sorted data
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.len() == 1 {
+        return sorted[0];
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Round to `precision` decimals, or by default strip binary float noise
+/// (0.1 + 0.2 gives 0.3 rather than 0.30000000000000004)
+fn round_to(value: f64, precision: Option<u64>) -> f64 {
+    match precision {
+        Some(p) => {
+            let factor = 10f64.powi(p.min(15) as i32);
+            (value * factor).round() / factor
+        }
+        None => format_number(value).parse().unwrap_or(value),
+    }
+}
+
+/// Human-readable number without float noise such as 0.30000000000000004,
+/// by rounding to 15 significant digits
+fn format_number(value: f64) -> String {
+    let rounded: f64 = format!("{:.14e}", value).parse().unwrap_or(value);
+    format!("{}", rounded)
+}
@@ -1,5 +1,8 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::provenance;
+use crate::registry::ToolModule;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::fs;
@@ -156,6 +159,7 @@ impl ClipboardModule {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
         let tag = args["tag"].as_str().context("Missing 'tag' parameter")?;
 
+        provenance::read(path);
         let full_content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read file: {}", path))?;
 
@@ -396,3 +400,25 @@ impl ClipboardModule {
         }
     }
 }
+
+#[async_trait]
+impl ToolModule for ClipboardModule {
+    fn name(&self) -> &str {
+        "clipboard"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "clip_copy_file" => self.copy_file(args).await,
+            "clip_copy" => self.copy(args).await,
+            "clip_paste_file" => self.paste_file(args).await,
+            "clip_paste" => self.paste(args).await,
+            "clip_clear" => self.clear(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
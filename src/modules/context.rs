@@ -1,6 +1,8 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
-use std::collections::HashMap;
+use async_trait::async_trait;
+use crate::registry::ToolModule;
+use crate::storage::{keyspaces, MemoryStorage, Storage};
 use std::sync::{Arc, Mutex};
 use tiktoken_rs::{cl100k_base, o200k_base};
 use flate2::write::{ZlibEncoder, GzEncoder};
@@ -8,7 +10,8 @@ use flate2::Compression;
 use std::io::Write as _;
 
 pub struct ContextModule {
-    memory_store: Arc<Mutex<HashMap<String, Value>>>,
+    // ctx_memory_* values
+    storage: Arc<dyn Storage>,
     context_usage: Arc<Mutex<ContextUsage>>,
 }
 
@@ -26,8 +29,13 @@ impl Default for ContextModule {
 
 impl ContextModule {
     pub fn new() -> Self {
+        Self::with_storage(Arc::new(MemoryStorage::new()))
+    }
+
+    /// Keep ctx_memory_* values in `storage`
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
         Self {
-            memory_store: Arc::new(Mutex::new(HashMap::new())),
+            storage,
             context_usage: Arc::new(Mutex::new(ContextUsage::default())),
         }
     }
@@ -104,7 +112,7 @@ impl ContextModule {
             }),
             json!({
                 "name": "ctx_memory_store",
-                "description": "Store data in memory (process lifetime)",
+                "description": "Store data in memory (process lifetime, or kept across restarts with a persistent [storage] backend)",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -228,14 +236,10 @@ impl ContextModule {
     pub async fn remove_context(&self, args: Value) -> Result<Value> {
         let reset_memory = args["reset_memory"].as_bool().unwrap_or(false);
 
-        let mut usage = self.context_usage.lock().unwrap();
-        usage.used_tokens = 0;
+        self.context_usage.lock().unwrap().used_tokens = 0;
 
         let memory_cleared = if reset_memory {
-            let mut store = self.memory_store.lock().unwrap();
-            let count = store.len();
-            store.clear();
-            count
+            self.storage.clear(keyspaces::MEMORY)?
         } else {
             0
         };
@@ -286,22 +290,20 @@ impl ContextModule {
         let key = args["key"].as_str().context("Missing 'key' parameter")?;
         let value = args.get("value").context("Missing 'value' parameter")?;
 
-        let mut store = self.memory_store.lock().unwrap();
-        store.insert(key.to_string(), value.clone());
+        self.storage.put_json(keyspaces::MEMORY, key, value)?;
+        let total_keys = self.storage.scan(keyspaces::MEMORY, "")?.len();
 
         Ok(json!({
             "success": true,
             "key": key,
             "stored": true,
-            "total_keys": store.len()
+            "total_keys": total_keys
         }))
     }
 
     pub async fn memory_recall(&self, args: Value) -> Result<Value> {
-        let store = self.memory_store.lock().unwrap();
-
         if let Some(key) = args["key"].as_str() {
-            if let Some(value) = store.get(key) {
+            if let Some(value) = self.storage.get_json::<Value>(keyspaces::MEMORY, key)? {
                 Ok(json!({
                     "key": key,
                     "value": value,
@@ -316,7 +318,12 @@ impl ContextModule {
             }
         } else {
             // List all keys
-            let keys: Vec<String> = store.keys().cloned().collect();
+            let keys: Vec<String> = self
+                .storage
+                .scan(keyspaces::MEMORY, "")?
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
 
             Ok(json!({
                 "keys": keys,
@@ -379,3 +386,27 @@ impl ContextModule {
         }))
     }
 }
+
+#[async_trait]
+impl ToolModule for ContextModule {
+    fn name(&self) -> &str {
+        "context"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "ctx_context" => self.context(args).await,
+            "ctx_compact" => self.compact_context(args).await,
+            "ctx_remove" => self.remove_context(args).await,
+            "ctx_token_count" => self.token_count(args).await,
+            "ctx_memory_store" => self.memory_store(args).await,
+            "ctx_memory_recall" => self.memory_recall(args).await,
+            "ctx_estimate_cost" => self.estimate_cost(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
@@ -0,0 +1,558 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use sha2::Digest;
+
+const MAX_COUNT: u64 = 1000;
+const MAX_LENGTH: u64 = 65536;
+
+/// Crockford base32, as used by ULIDs
+const CROCKFORD: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+pub struct CryptoModule;
+
+impl Default for CryptoModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CryptoModule {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "crypto_uuid",
+                "description": "Generate unique identifiers: random UUIDv4, time-ordered UUIDv7, or ULID (26-char, lexicographically sortable)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "kind": {
+                            "type": "string",
+                            "enum": ["uuid4", "uuid7", "ulid"],
+                            "description": "Identifier type (default: uuid4)"
+                        },
+                        "count": {
+                            "type": "integer",
+                            "description": "Number of identifiers to generate (default: 1, max: 1000)"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["hyphenated", "simple", "urn", "braced"],
+                            "description": "UUID text format (default: hyphenated). Ignored for ULIDs"
+                        },
+                        "uppercase": {
+                            "type": "boolean",
+                            "description": "Uppercase hex digits in UUIDs (default: false). ULIDs are always uppercase"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "crypto_random",
+                "description": "Generate cryptographically secure random values from the OS random source: strings over a charset (tokens, passwords), raw bytes, or integers in a range. Strings are sampled uniformly and report their entropy; pass min_entropy_bits to size or check them.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "kind": {
+                            "type": "string",
+                            "enum": ["string", "bytes", "integer"],
+                            "description": "What to generate (default: string)"
+                        },
+                        "length": {
+                            "type": "integer",
+                            "description": "Characters for strings, bytes for bytes (default: 32, or enough to reach min_entropy_bits)"
+                        },
+                        "charset": {
+                            "type": "string",
+                            "enum": ["alphanumeric", "alpha", "lower", "upper", "numeric", "hex", "base64url", "ascii"],
+                            "description": "Character set for strings (default: alphanumeric). 'ascii' is all printable non-space ASCII"
+                        },
+                        "alphabet": {
+                            "type": "string",
+                            "description": "Custom characters to draw from, overrides charset"
+                        },
+                        "min_entropy_bits": {
+                            "type": "integer",
+                            "description": "Minimum entropy for strings; fails if an explicit length falls short"
+                        },
+                        "encoding": {
+                            "type": "string",
+                            "enum": ["hex", "base64", "base64url"],
+                            "description": "Output encoding for bytes (default: hex)"
+                        },
+                        "min": {
+                            "type": "integer",
+                            "description": "Lower bound for integers, inclusive (default: 0)"
+                        },
+                        "max": {
+                            "type": "integer",
+                            "description": "Upper bound for integers, inclusive (default: 2^53 - 1)"
+                        },
+                        "count": {
+                            "type": "integer",
+                            "description": "Number of values to generate (default: 1, max: 1000)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "crypto_hash",
+                "description": "Hash a string with SHA-256, SHA-512 or BLAKE3. Input can be UTF-8 text, hex or base64; output as hex or base64.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "input": {
+                            "type": "string",
+                            "description": "Data to hash"
+                        },
+                        "algorithm": {
+                            "type": "string",
+                            "enum": ["sha256", "sha512", "blake3"],
+                            "description": "Hash algorithm (default: sha256)"
+                        },
+                        "input_encoding": {
+                            "type": "string",
+                            "enum": ["utf8", "hex", "base64", "base64url"],
+                            "description": "How to interpret input (default: utf8)"
+                        },
+                        "output_encoding": {
+                            "type": "string",
+                            "enum": ["hex", "base64", "base64url"],
+                            "description": "Digest encoding (default: hex)"
+                        }
+                    },
+                    "required": ["input"]
+                }
+            }),
+            json!({
+                "name": "crypto_hmac",
+                "description": "Compute an HMAC (SHA-256 or SHA-512) of a message, or verify one in constant time by passing 'expected'. Useful for signing webhooks and checking signatures.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "key": {
+                            "type": "string",
+                            "description": "Secret key"
+                        },
+                        "message": {
+                            "type": "string",
+                            "description": "Message to authenticate"
+                        },
+                        "algorithm": {
+                            "type": "string",
+                            "enum": ["sha256", "sha512"],
+                            "description": "Hash function (default: sha256)"
+                        },
+                        "key_encoding": {
+                            "type": "string",
+                            "enum": ["utf8", "hex", "base64", "base64url"],
+                            "description": "How to interpret key (default: utf8)"
+                        },
+                        "message_encoding": {
+                            "type": "string",
+                            "enum": ["utf8", "hex", "base64", "base64url"],
+                            "description": "How to interpret message (default: utf8)"
+                        },
+                        "output_encoding": {
+                            "type": "string",
+                            "enum": ["hex", "base64", "base64url"],
+                            "description": "MAC encoding, also used to decode 'expected' (default: hex)"
+                        },
+                        "expected": {
+                            "type": "string",
+                            "description": "MAC to verify against; the result then includes 'valid'"
+                        }
+                    },
+                    "required": ["key", "message"]
+                }
+            }),
+            json!({
+                "name": "crypto_encode",
+                "description": "Convert data between UTF-8 text, hex, base64 and base64url. Decoded bytes that are not valid UTF-8 are returned as hex.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "input": {
+                            "type": "string",
+                            "description": "Data to convert"
+                        },
+                        "from": {
+                            "type": "string",
+                            "enum": ["utf8", "hex", "base64", "base64url"],
+                            "description": "Encoding of input (default: utf8)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "enum": ["utf8", "hex", "base64", "base64url"],
+                            "description": "Encoding of the result"
+                        }
+                    },
+                    "required": ["input", "to"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn uuid(&self, args: Value) -> Result<Value> {
+        let kind = args["kind"].as_str().unwrap_or("uuid4");
+        let count = count_arg(&args)?;
+        let format = args["format"].as_str().unwrap_or("hyphenated");
+        let uppercase = args["uppercase"].as_bool().unwrap_or(false);
+
+        let ids = (0..count)
+            .map(|_| match kind {
+                "uuid4" => format_uuid(uuid::Uuid::new_v4(), format, uppercase),
+                "uuid7" => format_uuid(uuid::Uuid::now_v7(), format, uppercase),
+                "ulid" => Ok(new_ulid()),
+                _ => Err(anyhow::anyhow!("Unsupported kind: {}. Use uuid4, uuid7 or ulid", kind)),
+            })
+            .collect::<Result<Vec<String>>>()?;
+
+        Ok(single_or_many(json!({ "kind": kind }), "id", ids))
+    }
+
+    pub async fn random(&self, args: Value) -> Result<Value> {
+        let kind = args["kind"].as_str().unwrap_or("string");
+        let count = count_arg(&args)?;
+
+        match kind {
+            "string" => {
+                let alphabet = match args["alphabet"].as_str() {
+                    Some(custom) => {
+                        let mut chars: Vec<char> = Vec::new();
+                        for c in custom.chars() {
+                            if !chars.contains(&c) {
+                                chars.push(c);
+                            }
+                        }
+                        chars
+                    }
+                    None => charset(args["charset"].as_str().unwrap_or("alphanumeric"))?,
+                };
+                if alphabet.len() < 2 {
+                    anyhow::bail!("Alphabet must contain at least 2 distinct characters");
+                }
+
+                let bits_per_char = (alphabet.len() as f64).log2();
+                let min_entropy = args["min_entropy_bits"].as_u64();
+                let length = match (args["length"].as_u64(), min_entropy) {
+                    (Some(length), _) => length,
+                    (None, Some(bits)) => (bits as f64 / bits_per_char).ceil() as u64,
+                    (None, None) => 32,
+                };
+                check_length(length)?;
+
+                let entropy_bits = length as f64 * bits_per_char;
+                if let Some(bits) = min_entropy {
+                    if entropy_bits < bits as f64 {
+                        anyhow::bail!(
+                            "{} characters from a {}-character alphabet give {:.1} bits of entropy, below the required {}; use at least {} characters",
+                            length,
+                            alphabet.len(),
+                            entropy_bits,
+                            bits,
+                            (bits as f64 / bits_per_char).ceil() as u64
+                        );
+                    }
+                }
+
+                let mut rng = OsRng;
+                let values: Vec<String> = (0..count)
+                    .map(|_| {
+                        (0..length)
+                            .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                            .collect()
+                    })
+                    .collect();
+
+                Ok(single_or_many(
+                    json!({
+                        "kind": "string",
+                        "length": length,
+                        "alphabet_size": alphabet.len(),
+                        "entropy_bits": (entropy_bits * 10.0).floor() / 10.0,
+                        "source": "os"
+                    }),
+                    "value",
+                    values,
+                ))
+            }
+            "bytes" => {
+                let length = args["length"].as_u64().unwrap_or(32);
+                check_length(length)?;
+                let encoding = args["encoding"].as_str().unwrap_or("hex");
+
+                let values = (0..count)
+                    .map(|_| {
+                        let mut bytes = vec![0u8; length as usize];
+                        OsRng.fill_bytes(&mut bytes);
+                        encode_bytes(&bytes, encoding)
+                    })
+                    .collect::<Result<Vec<String>>>()?;
+
+                Ok(single_or_many(
+                    json!({
+                        "kind": "bytes",
+                        "length": length,
+                        "encoding": encoding,
+                        "entropy_bits": length * 8,
+                        "source": "os"
+                    }),
+                    "value",
+                    values,
+                ))
+            }
+            "integer" => {
+                let min = args["min"].as_i64().unwrap_or(0);
+                let max = args["max"].as_i64().unwrap_or((1i64 << 53) - 1);
+                if min > max {
+                    anyhow::bail!("min ({}) must not be greater than max ({})", min, max);
+                }
+
+                let mut rng = OsRng;
+                let values: Vec<i64> = (0..count).map(|_| rng.gen_range(min..=max)).collect();
+
+                let mut result = json!({
+                    "kind": "integer",
+                    "min": min,
+                    "max": max,
+                    "source": "os"
+                });
+                if values.len() == 1 {
+                    result["value"] = json!(values[0]);
+                } else {
+                    result["values"] = json!(values);
+                }
+                Ok(result)
+            }
+            _ => Err(anyhow::anyhow!("Unsupported kind: {}. Use string, bytes or integer", kind)),
+        }
+    }
+
+    pub async fn hash(&self, args: Value) -> Result<Value> {
+        let input = args["input"].as_str().context("Missing 'input' parameter")?;
+        let algorithm = args["algorithm"].as_str().unwrap_or("sha256");
+        let input_encoding = args["input_encoding"].as_str().unwrap_or("utf8");
+        let output_encoding = args["output_encoding"].as_str().unwrap_or("hex");
+
+        let data = decode_bytes(input, input_encoding).context("Invalid input")?;
+        let digest = match algorithm {
+            "sha256" => sha2::Sha256::digest(&data).to_vec(),
+            "sha512" => sha2::Sha512::digest(&data).to_vec(),
+            "blake3" => blake3::hash(&data).as_bytes().to_vec(),
+            _ => return Err(anyhow::anyhow!("Unsupported algorithm: {}. Use sha256, sha512 or blake3", algorithm)),
+        };
+
+        Ok(json!({
+            "hash": encode_bytes(&digest, output_encoding)?,
+            "algorithm": algorithm,
+            "encoding": output_encoding,
+            "input_size": data.len()
+        }))
+    }
+
+    pub async fn hmac(&self, args: Value) -> Result<Value> {
+        let key = args["key"].as_str().context("Missing 'key' parameter")?;
+        let message = args["message"].as_str().context("Missing 'message' parameter")?;
+        let algorithm = args["algorithm"].as_str().unwrap_or("sha256");
+        let key_encoding = args["key_encoding"].as_str().unwrap_or("utf8");
+        let message_encoding = args["message_encoding"].as_str().unwrap_or("utf8");
+        let output_encoding = args["output_encoding"].as_str().unwrap_or("hex");
+
+        let key = decode_bytes(key, key_encoding).context("Invalid key")?;
+        let message = decode_bytes(message, message_encoding).context("Invalid message")?;
+        let expected = match args["expected"].as_str() {
+            Some(expected) => Some(
+                decode_bytes(expected.trim(), output_encoding)
+                    .with_context(|| format!("'expected' is not valid {}", output_encoding))?,
+            ),
+            None => None,
+        };
+
+        let (mac, valid) = match algorithm {
+            "sha256" => compute_hmac::<Hmac<sha2::Sha256>>(&key, &message, expected.as_deref())?,
+            "sha512" => compute_hmac::<Hmac<sha2::Sha512>>(&key, &message, expected.as_deref())?,
+            _ => return Err(anyhow::anyhow!("Unsupported algorithm: {}. Use sha256 or sha512", algorithm)),
+        };
+
+        let mut result = json!({
+            "mac": encode_bytes(&mac, output_encoding)?,
+            "algorithm": format!("hmac-{}", algorithm),
+            "encoding": output_encoding
+        });
+        if let Some(valid) = valid {
+            result["valid"] = json!(valid);
+        }
+        Ok(result)
+    }
+
+    pub async fn encode(&self, args: Value) -> Result<Value> {
+        let input = args["input"].as_str().context("Missing 'input' parameter")?;
+        let from = args["from"].as_str().unwrap_or("utf8");
+        let to = args["to"].as_str().context("Missing 'to' parameter")?;
+
+        let bytes = decode_bytes(input, from).with_context(|| format!("Input is not valid {}", from))?;
+
+        let (result, to) = if to == "utf8" {
+            match String::from_utf8(bytes.clone()) {
+                Ok(text) => (text, "utf8"),
+                Err(_) => (encode_bytes(&bytes, "hex")?, "hex"),
+            }
+        } else {
+            (encode_bytes(&bytes, to)?, to)
+        };
+
+        Ok(json!({
+            "result": result,
+            "from": from,
+            "to": to,
+            "bytes": bytes.len()
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for CryptoModule {
+    fn name(&self) -> &str {
+        "crypto"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "crypto_uuid" => self.uuid(args).await,
+            "crypto_random" => self.random(args).await,
+            "crypto_hash" => self.hash(args).await,
+            "crypto_hmac" => self.hmac(args).await,
+            "crypto_encode" => self.encode(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn count_arg(args: &Value) -> Result<u64> {
+    let count = args["count"].as_u64().unwrap_or(1);
+    if count == 0 || count > MAX_COUNT {
+        anyhow::bail!("count must be between 1 and {}", MAX_COUNT);
+    }
+    Ok(count)
+}
+
+fn check_length(length: u64) -> Result<()> {
+    if length == 0 || length > MAX_LENGTH {
+        anyhow::bail!("length must be between 1 and {}", MAX_LENGTH);
+    }
+    Ok(())
+}
+
+/// Put a lone value under `key`, or several under `{key}s`
+fn single_or_many(mut result: Value, key: &str, values: Vec<String>) -> Value {
+    if values.len() == 1 {
+        result[key] = json!(values[0]);
+    } else {
+        result["count"] = json!(values.len());
+        result[format!("{}s", key)] = json!(values);
+    }
+    result
+}
+
+fn format_uuid(id: uuid::Uuid, format: &str, uppercase: bool) -> Result<String> {
+    let text = match format {
+        "hyphenated" => id.hyphenated().to_string(),
+        "simple" => id.simple().to_string(),
+        "urn" => id.urn().to_string(),
+        "braced" => id.braced().to_string(),
+        _ => return Err(anyhow::anyhow!("Unsupported format: {}", format)),
+    };
+    Ok(if uppercase { text.to_uppercase() } else { text })
+}
+
+/// 48-bit millisecond timestamp followed by 80 random bits, in Crockford base32
+fn new_ulid() -> String {
+    let millis = chrono::Utc::now().timestamp_millis() as u128 & ((1 << 48) - 1);
+    let mut random = [0u8; 16];
+    OsRng.fill_bytes(&mut random[6..]);
+    let value = (millis << 80) | u128::from_be_bytes(random);
+
+    (0..26)
+        .map(|i| CROCKFORD[((value >> (125 - 5 * i)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+fn charset(name: &str) -> Result<Vec<char>> {
+    let chars: String = match name {
+        "alphanumeric" => ('a'..='z').chain('A'..='Z').chain('0'..='9').collect(),
+        "alpha" => ('a'..='z').chain('A'..='Z').collect(),
+        "lower" => ('a'..='z').collect(),
+        "upper" => ('A'..='Z').collect(),
+        "numeric" => ('0'..='9').collect(),
+        "hex" => ('0'..='9').chain('a'..='f').collect(),
+        "base64url" => ('A'..='Z').chain('a'..='z').chain('0'..='9').chain(['-', '_']).collect(),
+        "ascii" => ('!'..='~').collect(),
+        _ => return Err(anyhow::anyhow!("Unsupported charset: {}", name)),
+    };
+    Ok(chars.chars().collect())
+}
+
+fn decode_bytes(input: &str, encoding: &str) -> Result<Vec<u8>> {
+    match encoding {
+        "utf8" => Ok(input.as_bytes().to_vec()),
+        "hex" => {
+            let input = input.trim();
+            if !input.len().is_multiple_of(2) {
+                anyhow::bail!("Hex string must have even length, got {}", input.len());
+            }
+            (0..input.len())
+                .step_by(2)
+                .map(|i| {
+                    input
+                        .get(i..i + 2)
+                        .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                        .context("Invalid hex input (non-hex characters found)")
+                })
+                .collect()
+        }
+        "base64" => base64::engine::general_purpose::STANDARD
+            .decode(input.trim())
+            .context("Invalid base64 input"),
+        "base64url" => base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(input.trim().trim_end_matches('='))
+            .context("Invalid base64url input"),
+        _ => Err(anyhow::anyhow!("Unsupported encoding: {}", encoding)),
+    }
+}
+
+fn encode_bytes(bytes: &[u8], encoding: &str) -> Result<String> {
+    match encoding {
+        "hex" => Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        "base64" => Ok(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        "base64url" => Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)),
+        _ => Err(anyhow::anyhow!("Unsupported output encoding: {}", encoding)),
+    }
+}
+
+/// MAC of `message`, and whether it matches `expected` (compared in constant time)
+fn compute_hmac<M: Mac + hmac::digest::KeyInit + Clone>(
+    key: &[u8],
+    message: &[u8],
+    expected: Option<&[u8]>,
+) -> Result<(Vec<u8>, Option<bool>)> {
+    let mut mac = <M as Mac>::new_from_slice(key).context("Invalid HMAC key")?;
+    mac.update(message);
+    let valid = expected.map(|expected| mac.clone().verify_slice(expected).is_ok());
+    Ok((mac.finalize().into_bytes().to_vec(), valid))
+}
@@ -1,9 +1,24 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
-use std::process::Command;
+use async_trait::async_trait;
+use crate::children;
+use crate::locale;
+use crate::pagination::Pages;
+use crate::progress;
+use crate::provenance;
+use crate::registry::ToolModule;
+use crate::telemetry;
+use serde::Deserialize;
+use std::process::{Command, Stdio};
+use std::collections::BTreeMap;
 use std::path::Path;
+use tokio::io::AsyncBufReadExt;
+use tracing::Instrument;
 
-pub struct DiagnosticsModule;
+pub struct DiagnosticsModule {
+    // Long diagnostic lists, fetched a page at a time
+    pages: Pages,
+}
 
 impl Default for DiagnosticsModule {
     fn default() -> Self {
@@ -13,7 +28,7 @@ impl Default for DiagnosticsModule {
 
 impl DiagnosticsModule {
     pub fn new() -> Self {
-        Self
+        Self { pages: Pages::new() }
     }
 
     pub fn get_tools(&self) -> Vec<Value> {
@@ -36,6 +51,22 @@ impl DiagnosticsModule {
                             "type": "string",
                             "enum": ["json", "text"],
                             "description": "Output format (default: json)"
+                        },
+                        "max_diagnostics": {
+                            "type": "integer",
+                            "description": "Return at most this many diagnostics; cargo is stopped as soon as it has reported them (default: all)"
+                        },
+                        "stop_on_error": {
+                            "type": "boolean",
+                            "description": "Return as soon as the first error is reported, with the warnings before it; cargo is stopped there (default: false)"
+                        },
+                        "page_size": {
+                            "type": "integer",
+                            "description": "Return at most this many diagnostics, with counts per level and file and a next_cursor for the rest (default: all at once)"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "next_cursor from a previous diagnostics_get, to get the next page without re-running the checker; other arguments are then ignored"
                         }
                     }
                 }
@@ -44,7 +75,16 @@ impl DiagnosticsModule {
     }
 
     pub async fn get(&self, args: Value) -> Result<Value> {
+        if let Some(cursor) = args["cursor"].as_str() {
+            let (diagnostics, next_cursor) = self.pages.next(cursor)?;
+            return Ok(json!({
+                "diagnostics": diagnostics,
+                "next_cursor": next_cursor
+            }));
+        }
+
         let path = args["path"].as_str().unwrap_or(".");
+        provenance::read(path);
         let tool = args["tool"].as_str();
         let format = args["format"].as_str().unwrap_or("json");
 
@@ -57,23 +97,57 @@ impl DiagnosticsModule {
             self.detect_tool(path_obj)?
         };
 
-        let diagnostics = match detected_tool.as_str() {
-            "cargo" => self.run_cargo_diagnostics(path)?,
-            "rustc" => self.run_rustc_diagnostics(path)?,
-            "tsc" => self.run_tsc_diagnostics(path)?,
-            "eslint" => self.run_eslint_diagnostics(path)?,
-            "pylint" => self.run_pylint_diagnostics(path)?,
-            "mypy" => self.run_mypy_diagnostics(path)?,
-            "ruff" => self.run_ruff_diagnostics(path)?,
-            "gcc" | "g++" => self.run_gcc_diagnostics(path)?,
-            "clang" => self.run_clang_diagnostics(path)?,
+        let limit = Limit {
+            max: args["max_diagnostics"].as_u64().map(|n| n as usize),
+            stop_on_error: args["stop_on_error"].as_bool().unwrap_or(false),
+        };
+
+        // Other checkers only report once they are done, so their lists are cut afterwards
+        let (diagnostics, truncated) = match detected_tool.as_str() {
+            "cargo" => self.run_cargo_diagnostics(path, &limit).await?,
+            "rustc" => limit.apply(self.run_rustc_diagnostics(path)?),
+            "tsc" => limit.apply(self.run_tsc_diagnostics(path)?),
+            "eslint" => limit.apply(self.run_eslint_diagnostics(path)?),
+            "pylint" => limit.apply(self.run_pylint_diagnostics(path)?),
+            "mypy" => limit.apply(self.run_mypy_diagnostics(path)?),
+            "ruff" => limit.apply(self.run_ruff_diagnostics(path)?),
+            "gcc" | "g++" => limit.apply(self.run_gcc_diagnostics(path)?),
+            "clang" => limit.apply(self.run_clang_diagnostics(path)?),
             _ => anyhow::bail!("Unsupported diagnostic tool: {}", detected_tool),
         };
 
+        let Some(page_size) = args["page_size"].as_u64() else {
+            return Ok(json!({
+                "path": path,
+                "tool": detected_tool,
+                "diagnostics": diagnostics,
+                "truncated": truncated,
+                "format": format
+            }));
+        };
+
+        // Summary of the whole list, so the first page says what the rest holds
+        let mut by_level: BTreeMap<String, usize> = BTreeMap::new();
+        let mut by_file: BTreeMap<String, usize> = BTreeMap::new();
+        for diagnostic in &diagnostics {
+            let level = diagnostic["level"].as_str().unwrap_or("unknown");
+            *by_level.entry(level.to_string()).or_default() += 1;
+            if let Some(file) = diagnostic["file"].as_str() {
+                *by_file.entry(file.to_string()).or_default() += 1;
+            }
+        }
+        let total = diagnostics.len();
+        let (diagnostics, next_cursor) = self.pages.first(diagnostics, page_size as usize);
+
         Ok(json!({
             "path": path,
             "tool": detected_tool,
+            "total": total,
+            "by_level": by_level,
+            "by_file": by_file,
             "diagnostics": diagnostics,
+            "next_cursor": next_cursor,
+            "truncated": truncated,
             "format": format
         }))
     }
@@ -96,7 +170,7 @@ impl DiagnosticsModule {
         // Check for Python
         if path.extension().is_some_and(|e| e == "py") {
             // Prefer ruff if available, fallback to pylint
-            if Command::new("ruff").arg("--version").output().is_ok() {
+            if telemetry::run_command(Command::new("ruff").arg("--version")).is_ok() {
                 return Ok("ruff".to_string());
             }
             return Ok("pylint".to_string());
@@ -104,7 +178,7 @@ impl DiagnosticsModule {
 
         // Check for C/C++
         if path.extension().is_some_and(|e| e == "c" || e == "cpp" || e == "cc" || e == "cxx") {
-            if Command::new("clang").arg("--version").output().is_ok() {
+            if telemetry::run_command(Command::new("clang").arg("--version")).is_ok() {
                 return Ok("clang".to_string());
             }
             return Ok("gcc".to_string());
@@ -113,43 +187,73 @@ impl DiagnosticsModule {
         anyhow::bail!("Could not detect appropriate diagnostic tool for: {}", path.display())
     }
 
-    fn run_cargo_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("cargo")
+    /// Read `cargo check` output a line at a time as cargo writes it, streaming each
+    /// diagnostic to the client, and stop cargo once `limit` is reached. Large workspaces
+    /// print tens of megabytes of build messages, nearly all of them skipped unparsed.
+    async fn run_cargo_diagnostics(&self, path: &str, limit: &Limit) -> Result<(Vec<Value>, bool)> {
+        let mut command = tokio::process::Command::new("cargo");
+        command
             .arg("check")
             .arg("--message-format=json")
             .current_dir(path)
-            .output()
-            .context("Failed to run cargo check")?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut diagnostics = Vec::new();
-
-        for line in stdout.lines() {
-            if let Ok(msg) = serde_json::from_str::<Value>(line) {
-                if msg["reason"] == "compiler-message" {
-                    if let Some(message) = msg.get("message") {
-                        diagnostics.push(json!({
-                            "level": message["level"],
-                            "message": message["message"],
-                            "file": message["spans"][0]["file_name"],
-                            "line": message["spans"][0]["line_start"],
-                            "column": message["spans"][0]["column_start"],
-                            "code": message.get("code").and_then(|c| c.get("code"))
-                        }));
-                    }
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let span = telemetry::command_span("cargo");
+        let started = std::time::Instant::now();
+        let (mut child, tracked) = children::spawn_async(&mut command).context("Failed to run cargo check")?;
+        let mut stdout = tokio::io::BufReader::new(child.stdout.take().context("Failed to run cargo check")?);
+
+        let read = async {
+            let mut diagnostics = Vec::new();
+            let mut line = String::new();
+            loop {
+                if limit.reached(&diagnostics) {
+                    return Ok((diagnostics, true));
+                }
+                line.clear();
+                if stdout.read_line(&mut line).await? == 0 {
+                    return Ok::<_, std::io::Error>((diagnostics, false));
                 }
+                // Cheaper than parsing the build-script and artifact lines that make up most of it
+                if !line.contains("\"compiler-message\"") {
+                    continue;
+                }
+                let Some(message) = serde_json::from_str::<CargoMessage>(&line).ok().filter(|m| m.reason == "compiler-message").and_then(|m| m.message) else {
+                    continue;
+                };
+                let span = message.spans.first();
+                let diagnostic = json!({
+                    "level": message.level,
+                    "message": message.message,
+                    "file": span.map(|span| &span.file_name),
+                    "line": span.map(|span| span.line_start),
+                    "column": span.map(|span| span.column_start),
+                    "code": message.code.map(|code| code.code)
+                });
+                progress::output("diagnostics", &diagnostic.to_string());
+                diagnostics.push(diagnostic);
             }
         }
+        .instrument(span.clone())
+        .await;
 
-        Ok(diagnostics)
+        // Stopped early or failed: dropping the child kills cargo and the compilers it started
+        let status = match &read {
+            Ok((_, false)) => Some(tracked.finish(child.wait().await).context("Failed to run cargo check")?),
+            _ => None,
+        };
+        telemetry::record_command(&span, "cargo", status.as_ref(), started.elapsed());
+        read.context("Failed to read cargo check output")
     }
 
     fn run_rustc_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("rustc")
-            .arg("--error-format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run rustc")?;
+        let output = telemetry::run_command(
+            Command::new("rustc")
+                .arg("--error-format=json")
+                .arg(path),
+        )
+        .context("Failed to run rustc")?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -174,23 +278,25 @@ impl DiagnosticsModule {
     }
 
     fn run_tsc_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("tsc")
-            .arg("--noEmit")
-            .arg("--pretty")
-            .arg("false")
-            .current_dir(path)
-            .output()
-            .context("Failed to run tsc")?;
+        let output = telemetry::run_command(
+            Command::new("tsc")
+                .arg("--noEmit")
+                .arg("--pretty")
+                .arg("false")
+                .current_dir(path),
+        )
+        .context("Failed to run tsc")?;
 
         self.parse_generic_output(&output.stdout, &output.stderr)
     }
 
     fn run_eslint_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("eslint")
-            .arg("--format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run eslint")?;
+        let output = telemetry::run_command(
+            Command::new("eslint")
+                .arg("--format=json")
+                .arg(path),
+        )
+        .context("Failed to run eslint")?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
 
@@ -221,11 +327,12 @@ impl DiagnosticsModule {
     }
 
     fn run_pylint_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("pylint")
-            .arg("--output-format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run pylint")?;
+        let output = telemetry::run_command(
+            Command::new("pylint")
+                .arg("--output-format=json")
+                .arg(path),
+        )
+        .context("Failed to run pylint")?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
 
@@ -252,21 +359,23 @@ impl DiagnosticsModule {
     }
 
     fn run_mypy_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("mypy")
-            .arg(path)
-            .output()
-            .context("Failed to run mypy")?;
+        let output = telemetry::run_command(
+            Command::new("mypy")
+                .arg(path),
+        )
+        .context("Failed to run mypy")?;
 
         self.parse_generic_output(&output.stdout, &output.stderr)
     }
 
     fn run_ruff_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("ruff")
-            .arg("check")
-            .arg("--output-format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run ruff")?;
+        let output = telemetry::run_command(
+            Command::new("ruff")
+                .arg("check")
+                .arg("--output-format=json")
+                .arg(path),
+        )
+        .context("Failed to run ruff")?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
 
@@ -292,24 +401,27 @@ impl DiagnosticsModule {
         }
     }
 
+    // The text format's "warning"/"error" levels are translated, hence the C locale
     fn run_gcc_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("gcc")
-            .arg("-fsyntax-only")
-            .arg("-fdiagnostics-format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run gcc")?;
+        let output = telemetry::run_command(
+            locale::untranslated(&mut Command::new("gcc"))
+                .arg("-fsyntax-only")
+                .arg("-fdiagnostics-format=json")
+                .arg(path),
+        )
+        .context("Failed to run gcc")?;
 
         self.parse_generic_output(&output.stdout, &output.stderr)
     }
 
     fn run_clang_diagnostics(&self, path: &str) -> Result<Vec<Value>> {
-        let output = Command::new("clang")
-            .arg("-fsyntax-only")
-            .arg("-fdiagnostics-format=json")
-            .arg(path)
-            .output()
-            .context("Failed to run clang")?;
+        let output = telemetry::run_command(
+            locale::untranslated(&mut Command::new("clang"))
+                .arg("-fsyntax-only")
+                .arg("-fdiagnostics-format=json")
+                .arg(path),
+        )
+        .context("Failed to run clang")?;
 
         self.parse_generic_output(&output.stdout, &output.stderr)
     }
@@ -364,3 +476,77 @@ impl DiagnosticsModule {
         None
     }
 }
+
+/// When diagnostics_get stops taking diagnostics
+struct Limit {
+    max: Option<usize>,
+    stop_on_error: bool,
+}
+
+impl Limit {
+    /// Whether no diagnostics should be taken after `taken`
+    fn reached(&self, taken: &[Value]) -> bool {
+        let last_is_error = taken.last().is_some_and(|diagnostic| diagnostic["level"].as_str().is_some_and(|level| level.starts_with("error")));
+        self.max.is_some_and(|max| taken.len() >= max) || (self.stop_on_error && last_is_error)
+    }
+
+    /// `diagnostics` cut off where the limit is reached, and whether any were cut
+    fn apply(&self, diagnostics: Vec<Value>) -> (Vec<Value>, bool) {
+        let total = diagnostics.len();
+        let mut taken = Vec::new();
+        for diagnostic in diagnostics {
+            if self.reached(&taken) {
+                break;
+            }
+            taken.push(diagnostic);
+        }
+        let cut = taken.len() < total;
+        (taken, cut)
+    }
+}
+
+/// The parts of a `cargo check --message-format=json` line diagnostics_get reads
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CompilerMessage>,
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    level: String,
+    message: String,
+    #[serde(default)]
+    spans: Vec<CompilerSpan>,
+    code: Option<CompilerCode>,
+}
+
+#[derive(Deserialize)]
+struct CompilerSpan {
+    file_name: String,
+    line_start: u64,
+    column_start: u64,
+}
+
+#[derive(Deserialize)]
+struct CompilerCode {
+    code: String,
+}
+
+#[async_trait]
+impl ToolModule for DiagnosticsModule {
+    fn name(&self) -> &str {
+        "diagnostics"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "diagnostics_get" => self.get(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
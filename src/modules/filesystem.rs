@@ -1,24 +1,113 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::archive;
+use crate::progress;
+use crate::provenance;
+use crate::registry::{ToolModule, ToolOutput};
+use crate::snapshots;
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::collections::HashMap;
 use chrono::Local;
 use notify::{Watcher, RecursiveMode};
 use walkdir::WalkDir;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
 use regex::Regex;
+use tokio::sync::broadcast;
+use crate::config::{Config, DeleteMode, FilesystemConfig};
+use crate::error::PolyError;
+use crate::filetype;
+use crate::pagination::Pages;
+use crate::patch;
+#[cfg(feature = "txn")]
+use crate::modules::txn::Transactions;
+#[cfg(not(feature = "txn"))]
+use unavailable::Transactions;
+use crate::paths::{glob_match, resolve_path, within};
+use crate::storage::{keyspaces, MemoryStorage, Storage};
+use crate::watches::{self, Watches};
+use serde::{Deserialize, Serialize};
+
+/// Most of each file fs_read_many returns unless `max_bytes_per_file` says otherwise
+const READ_MANY_FILE_BYTES: u64 = 100_000;
+
+/// Most file content fs_read_many returns in all unless `max_total_bytes` says otherwise
+const READ_MANY_TOTAL_BYTES: u64 = 1_000_000;
+
+/// Most files fs_read_many reads unless `max_files` says otherwise
+const READ_MANY_MAX_FILES: u64 = 100;
+
+/// Most diff text fs_diff returns unless `max_bytes` says otherwise
+const DIFF_MAX_BYTES: usize = 100_000;
+
+/// fs_diff only reports that files this large differ, without diffing their lines
+const DIFF_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Newest snapshots fs_snapshot leaves uncompressed unless `keep_uncompressed` says otherwise
+const KEEP_UNCOMPRESSED: usize = 3;
+
+/// Most content, and appended content, fs_tail returns unless `max_bytes` says otherwise
+const TAIL_MAX_BYTES: usize = 1_000_000;
+
+/// How often a followed file is checked when no change event arrives, since network
+/// and some container file systems do not report appends
+const TAIL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
 pub struct FilesystemModule {
-    snapshots: Arc<Mutex<HashMap<String, Vec<SnapshotInfo>>>>,
+    // fs_snapshot history, by snapshotted path
+    storage: Arc<dyn Storage>,
+    notifier: Option<broadcast::Sender<Value>>,
+    // Canonicalized roots from [filesystem] allowed_roots; empty means unrestricted.
+    // Replaced when the config is reloaded.
+    allowed_roots: RwLock<Vec<PathBuf>>,
+    // [filesystem] delete_mode, for fs_delete calls without a mode
+    delete_mode: RwLock<DeleteMode>,
+    // Shared with the txn module; edits given a `txn` id are staged here instead of written
+    transactions: Option<Arc<Transactions>>,
+    // fs_find results too long for one page
+    pages: Pages,
+    // fs_watch_start subscriptions of this session
+    watches: Watches,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct SnapshotInfo {
-    #[allow(dead_code)]
     timestamp: String,
+    /// The snapshot's manifest, or for snapshots taken before they were incremental,
+    /// the full copy
     path: PathBuf,
     compressed: bool,
+    #[serde(default)]
+    incremental: bool,
+}
+
+impl SnapshotInfo {
+    /// The name fs_snapshot_list gives it: the manifest's name without `.json`
+    fn id(&self) -> String {
+        let name = self.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let id = name.strip_suffix(snapshots::ARCHIVE_EXTENSION).or_else(|| name.strip_suffix(".json")).unwrap_or(&name);
+        id.to_string()
+    }
+
+    /// The snapshot with its archive unpacked, for reading its contents
+    fn expanded(self, store: &snapshots::Store) -> Result<Self> {
+        if !self.compressed {
+            return Ok(self);
+        }
+        Ok(Self { path: store.expand(&self.path)?, compressed: false, ..self })
+    }
+
+    fn manifest(&self) -> Result<snapshots::Manifest> {
+        if !self.incremental {
+            anyhow::bail!(
+                "Snapshot {} was taken before snapshots were incremental; copy it back from {} with fs_copy",
+                self.id(),
+                self.path.display()
+            );
+        }
+        snapshots::load(&self.path)
+    }
 }
 
 impl Default for FilesystemModule {
@@ -29,8 +118,138 @@ impl Default for FilesystemModule {
 
 impl FilesystemModule {
     pub fn new() -> Self {
+        Self::with_config(&FilesystemConfig::default())
+    }
+
+    pub fn with_config(config: &FilesystemConfig) -> Self {
         Self {
-            snapshots: Arc::new(Mutex::new(HashMap::new())),
+            storage: Arc::new(MemoryStorage::new()),
+            notifier: None,
+            allowed_roots: RwLock::new(resolve_roots(config)),
+            delete_mode: RwLock::new(config.delete_mode),
+            transactions: None,
+            pages: Pages::new(),
+            watches: Watches::new(),
+        }
+    }
+
+    /// Push fs_watch and fs_watch_start events as JSON-RPC notifications to connected clients as they happen
+    pub fn set_notifier(&mut self, notifier: broadcast::Sender<Value>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Keep the fs_snapshot history in `storage`
+    pub fn set_storage(&mut self, storage: Arc<dyn Storage>) {
+        self.storage = storage;
+    }
+
+    /// Stage edits that name a transaction in `transactions` rather than writing them
+    #[cfg(feature = "txn")]
+    pub fn set_transactions(&mut self, transactions: Arc<Transactions>) {
+        self.transactions = Some(transactions);
+    }
+
+    /// The transaction named by the call's `txn` argument, if any
+    fn txn<'a>(&'a self, args: &'a Value) -> Result<Option<(&'a Transactions, &'a str)>> {
+        let Some(id) = args["txn"].as_str() else {
+            return Ok(None);
+        };
+        let transactions = self
+            .transactions
+            .as_deref()
+            .context("Transactions are not available on this server (enable the txn module)")?;
+        Ok(Some((transactions, id)))
+    }
+
+    /// File text as seen from inside the transaction, falling back to the disk;
+    /// `None` if the file does not exist there
+    fn read_text(&self, txn: Option<(&Transactions, &str)>, path: &str) -> Result<Option<String>> {
+        if let Some((transactions, id)) = txn {
+            if let Some(staged) = transactions.read(id, Path::new(path))? {
+                return Ok(staged);
+            }
+        }
+        provenance::read(path);
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read file: {}", path)),
+        }
+    }
+
+    /// Fail with a conflict if the file no longer has the `expected_hash` or `expected_mtime`
+    /// the caller read it with, so an edit made meanwhile (by a person, say) is not
+    /// silently overwritten. The error data has the file's current hash and mtime, and
+    /// the diff from its current content to `new_content`.
+    fn check_unchanged(&self, args: &Value, txn: Option<(&Transactions, &str)>, path: &str, new_content: &[u8]) -> Result<()> {
+        let expected_hash = args["expected_hash"].as_str();
+        let expected_mtime = args["expected_mtime"].as_u64();
+        if expected_hash.is_none() && expected_mtime.is_none() {
+            return Ok(());
+        }
+
+        let staged = match txn {
+            Some((transactions, id)) => transactions.read(id, Path::new(path))?,
+            None => None,
+        };
+        let current = match staged {
+            Some(staged) => staged.map(String::into_bytes),
+            None => match fs::read(path) {
+                Ok(content) => Some(content),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read file: {}", path)),
+            },
+        };
+        let Some(current) = current else {
+            return Err(PolyError::Conflict(format!("{} was deleted since it was read", path))
+                .with_data(json!({ "current_hash": null, "current_mtime": null })));
+        };
+        let hash = content_hash(&current);
+        let mtime = mtime_ms(Path::new(path));
+        let hash_changed = expected_hash.is_some_and(|expected| !expected.eq_ignore_ascii_case(&hash));
+        let mtime_changed = expected_mtime.is_some_and(|expected| Some(expected) != mtime);
+        if !hash_changed && !mtime_changed {
+            return Ok(());
+        }
+
+        // Binary content has no meaningful line diff
+        let diff = match (std::str::from_utf8(&current), std::str::from_utf8(new_content)) {
+            (Ok(current), Ok(new_content)) => Some(
+                similar::TextDiff::from_lines(current, new_content)
+                    .unified_diff()
+                    .header(&format!("{} (current)", path), &format!("{} (this write)", path))
+                    .to_string(),
+            ),
+            _ => None,
+        };
+        Err(PolyError::Conflict(format!(
+            "{} has changed since it was read; read it again and redo the edit on the current content",
+            path
+        ))
+        .with_data(json!({
+            "current_hash": hash,
+            "current_mtime": mtime,
+            "diff": diff
+        })))
+    }
+
+    /// Whether `path` is inside the configured allowed roots once `..` and symlinks
+    /// are resolved. Walks over a directory use it to pass over symlinks leading out.
+    fn is_allowed(&self, path: &Path) -> bool {
+        allowed_in(&self.allowed_roots.read().unwrap(), path)
+    }
+
+    /// Reject paths outside the configured allowed roots
+    fn check_allowed(&self, path: impl AsRef<Path>) -> Result<()> {
+        if self.is_allowed(path.as_ref()) {
+            Ok(())
+        } else {
+            let allowed_roots = self.allowed_roots.read().unwrap();
+            anyhow::bail!(
+                "Access denied: {} is outside the allowed roots ({})",
+                path.as_ref().display(),
+                allowed_roots.iter().map(|r| r.display().to_string()).collect::<Vec<_>>().join(", ")
+            )
         }
     }
 
@@ -38,7 +257,7 @@ impl FilesystemModule {
         vec![
             json!({
                 "name": "fs_read",
-                "description": "Read file contents, optionally reading specific line ranges",
+                "description": "Read file contents, optionally only some lines or bytes so large files can be read in pages. Returns the whole file's hash and mtime, to pass to fs_write as expected_hash/expected_mtime",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -57,11 +276,78 @@ impl FilesystemModule {
                                     "type": "integer"
                                 }
                             }
+                        },
+                        "start_line": {
+                            "type": "integer",
+                            "description": "First line to read, from 1 (instead of lines)"
+                        },
+                        "end_line": {
+                            "type": "integer",
+                            "description": "Last line to read (default: the last line of the file)"
+                        },
+                        "offset": {
+                            "type": "integer",
+                            "description": "Byte offset to start reading at (instead of line ranges)"
+                        },
+                        "length": {
+                            "type": "integer",
+                            "description": "Number of bytes to read from offset (default: to the end of the file)"
+                        },
+                        "max_bytes": {
+                            "type": "integer",
+                            "description": "Return at most this many bytes, cut at the end of a line where possible. The result says whether it was truncated and where to continue"
+                        },
+                        "encoding": {
+                            "type": "string",
+                            "enum": ["utf-8", "latin1", "base64", "hex"],
+                            "description": "How to return the content (default: utf-8 for UTF-8 text, latin1 for other text, base64 for binary files). The result names the encoding used"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; reads see the changes staged in it"
                         }
                     },
                     "required": ["path"]
                 }
             }),
+            json!({
+                "name": "fs_read_many",
+                "description": "Read several files in one call, named in 'paths' or matched by a glob 'pattern', as a map of path to content. Files that cannot be read are listed in 'errors' without failing the others",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "paths": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Files to read"
+                        },
+                        "pattern": {
+                            "type": "string",
+                            "description": "Glob of files to read under 'path' (*.rs, or src/**/*.rs to match the path under 'path'), in path order"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Directory 'pattern' is matched under (default: current directory)"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also match files .gitignore leaves out (default: false)"
+                        },
+                        "max_bytes_per_file": {
+                            "type": "integer",
+                            "description": "Most bytes of each file to return, cut after the last whole line that fits (default: 100000)"
+                        },
+                        "max_total_bytes": {
+                            "type": "integer",
+                            "description": "Most bytes to return in all; files that would go over are listed in 'not_read' (default: 1000000)"
+                        },
+                        "max_files": {
+                            "type": "integer",
+                            "description": "Most files to read (default: 100)"
+                        }
+                    }
+                }
+            }),
             json!({
                 "name": "fs_write",
                 "description": "Write content to a file, optionally writing to specific line ranges",
@@ -76,6 +362,11 @@ impl FilesystemModule {
                             "type": "string",
                             "description": "Content to write to the file"
                         },
+                        "encoding": {
+                            "type": "string",
+                            "enum": ["utf-8", "latin1", "base64", "hex"],
+                            "description": "How content is encoded; base64 or hex for binary files (default: utf-8)"
+                        },
                         "lines": {
                             "type": "array",
                             "description": "Optional array of line ranges to replace, e.g. [[1,10], [15,20]]. Content will be split and replace specified ranges.",
@@ -87,6 +378,18 @@ impl FilesystemModule {
                                     "type": "integer"
                                 }
                             }
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; the change is staged until txn_commit"
+                        },
+                        "expected_hash": {
+                            "type": "string",
+                            "description": "The file's hash from fs_read; the write fails with a conflict if the file has changed since"
+                        },
+                        "expected_mtime": {
+                            "type": "integer",
+                            "description": "The file's mtime from fs_read; the write fails with a conflict if the file was modified since"
                         }
                     },
                     "required": ["path", "content"]
@@ -105,6 +408,10 @@ impl FilesystemModule {
                         "destination": {
                             "type": "string",
                             "description": "Destination path"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; files only; the move is staged until txn_commit"
                         }
                     },
                     "required": ["source", "destination"]
@@ -123,11 +430,42 @@ impl FilesystemModule {
                         "destination": {
                             "type": "string",
                             "description": "Destination path"
+                        },
+                        "follow_symlinks": {
+                            "type": "boolean",
+                            "description": "Copy the files and directories symlinks point to instead of the links themselves (default: false, like cp -r)"
                         }
                     },
                     "required": ["source", "destination"]
                 }
             }),
+            json!({
+                "name": "fs_link",
+                "description": "Create a symbolic or hard link",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the new link"
+                        },
+                        "target": {
+                            "type": "string",
+                            "description": "What the link points to; a relative symlink target is relative to the link's directory"
+                        },
+                        "type": {
+                            "type": "string",
+                            "enum": ["symlink", "hard"],
+                            "description": "Link type (default: symlink); hard links need an existing file on the same filesystem"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace a file or link already at 'path' (default: false)"
+                        }
+                    },
+                    "required": ["path", "target"]
+                }
+            }),
             json!({
                 "name": "fs_create",
                 "description": "Create files or directories",
@@ -142,6 +480,10 @@ impl FilesystemModule {
                             "type": "string",
                             "enum": ["file", "dir"],
                             "description": "Type to create (file or dir)"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; files only; the new file is staged until txn_commit"
                         }
                     },
                     "required": ["path", "type"]
@@ -149,18 +491,61 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_delete",
-                "description": "Delete files or directories",
+                "description": "Delete files or directories, permanently or into the OS trash (restorable with fs_trash_restore)",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
                             "description": "Path to delete"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["permanent", "trash"],
+                            "description": "permanent removes the path, trash moves it to the OS trash (default: [filesystem] delete_mode, else permanent)"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; files only; the deletion is staged until txn_commit"
                         }
                     },
                     "required": ["path"]
                 }
             }),
+            json!({
+                "name": "fs_trash_list",
+                "description": "List what is in the OS trash, newest first, with each item's original path and the id fs_trash_restore takes",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Only items deleted from this file or directory, or from inside it"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Return at most this many items (default: 50)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "fs_trash_restore",
+                "description": "Put an item from the OS trash back where it was deleted from, by its id or its original path (the most recently deleted item from there)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Trash item id from fs_delete or fs_trash_list"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Original path of the item to restore, if no id is given"
+                        }
+                    }
+                }
+            }),
             json!({
                 "name": "fs_move_desktop",
                 "description": "Move and organize items within the Desktop directory",
@@ -181,7 +566,7 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_find",
-                "description": "Search for files and directories by name pattern",
+                "description": "Search for files and directories by substring, glob or regex pattern",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -191,12 +576,29 @@ impl FilesystemModule {
                         },
                         "pattern": {
                             "type": "string",
-                            "description": "Search pattern (substring match, or glob with wildcards like *.rs)"
+                            "description": "Name pattern: a substring, a glob (*.rs, or src/**/*.rs to match the path under 'path'), or a regex searched in the path under 'path'"
+                        },
+                        "match_mode": {
+                            "type": "string",
+                            "enum": ["auto", "substring", "glob", "regex"],
+                            "description": "How to read 'pattern' (default: auto, a glob if it has *, ?, [ or {, otherwise a substring)"
+                        },
+                        "case_insensitive": {
+                            "type": "boolean",
+                            "description": "Match regardless of case (default: false)"
                         },
                         "type": {
                             "type": "string",
-                            "enum": ["file", "dir", "all"],
-                            "description": "Type to search for (default: all)"
+                            "enum": ["file", "dir", "symlink", "all"],
+                            "description": "Type to search for (default: all); symlinks are only their own type when not followed"
+                        },
+                        "follow_symlinks": {
+                            "type": "boolean",
+                            "description": "Descend into symlinked directories and report links as what they point to (default: false, links are listed with their target)"
+                        },
+                        "min_depth": {
+                            "type": "number",
+                            "description": "Minimum directory depth of results; 1 leaves out the root itself (default: 0)"
                         },
                         "max_depth": {
                             "type": "number",
@@ -205,6 +607,39 @@ impl FilesystemModule {
                         "max_results": {
                             "type": "number",
                             "description": "Maximum number of results to return (default: 1000)"
+                        },
+                        "respect_gitignore": {
+                            "type": "boolean",
+                            "description": "Skip .git and what .gitignore, .ignore and git's exclude files leave out, such as node_modules or target (default: true)"
+                        },
+                        "extensions": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Only files with one of these extensions, e.g. [\"rs\", \"toml\"] (case-insensitive)"
+                        },
+                        "min_size": {
+                            "type": "integer",
+                            "description": "Only files of at least this many bytes"
+                        },
+                        "max_size": {
+                            "type": "integer",
+                            "description": "Only files of at most this many bytes"
+                        },
+                        "modified_after": {
+                            "type": ["string", "integer"],
+                            "description": "Only entries modified at or after this time: Unix seconds, RFC3339, or YYYY-MM-DD (UTC)"
+                        },
+                        "modified_before": {
+                            "type": ["string", "integer"],
+                            "description": "Only entries modified before this time: Unix seconds, RFC3339, or YYYY-MM-DD (UTC)"
+                        },
+                        "page_size": {
+                            "type": "integer",
+                            "description": "Return at most this many results, with a next_cursor for the rest (default: all at once)"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "next_cursor from a previous fs_find, to get the next page; other arguments are then ignored"
                         }
                     },
                     "required": ["path", "pattern"]
@@ -212,7 +647,7 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_ld",
-                "description": "List directory contents with details (like ls -la)",
+                "description": "List directory contents with details (like ls -la); symlinks are listed with their target",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -226,7 +661,7 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_stat",
-                "description": "Get file/directory metadata and statistics",
+                "description": "Get file/directory metadata and statistics; for a symlink, its target and the metadata of what it points to",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -258,7 +693,7 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_watch",
-                "description": "Watch a file or directory for changes",
+                "description": "Watch a file or directory for changes for a fixed time and return what happened. When the call carries a progress token, each event is also streamed as a progress notification as it happens. For watching in the background while doing other work, use fs_watch_start",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -274,9 +709,59 @@ impl FilesystemModule {
                     "required": ["path"]
                 }
             }),
+            json!({
+                "name": "fs_watch_start",
+                "description": "Start watching a file or directory in the background and return a watch id at once. Events are kept until fs_watch_poll takes them (and pushed as notifications/fs_watch to clients listening on SSE or WebSocket); end the watch with fs_watch_stop",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to watch"
+                        },
+                        "recursive": {
+                            "type": "boolean",
+                            "description": "Also watch everything under a directory (default: true)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_watch_poll",
+                "description": "Take the events a watch from fs_watch_start has seen since the last poll",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Watch id from fs_watch_start"
+                        },
+                        "wait": {
+                            "type": "number",
+                            "description": "Seconds to wait for an event when none has arrived yet (default: 0, at most 300)"
+                        }
+                    },
+                    "required": ["id"]
+                }
+            }),
+            json!({
+                "name": "fs_watch_stop",
+                "description": "Stop a watch from fs_watch_start, returning the events no poll has taken",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "id": {
+                            "type": "string",
+                            "description": "Watch id from fs_watch_start"
+                        }
+                    },
+                    "required": ["id"]
+                }
+            }),
             json!({
                 "name": "fs_snapshot",
-                "description": "Create lightweight timestamped backups with automatic management",
+                "description": "Create incremental timestamped backups: file contents are stored once and shared between snapshots, older snapshots are compressed, and the oldest are pruned automatically. Reports the disk space the snapshots take",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -287,6 +772,84 @@ impl FilesystemModule {
                         "max_snapshots": {
                             "type": "number",
                             "description": "Maximum number of snapshots to keep (default: 10)"
+                        },
+                        "keep_uncompressed": {
+                            "type": "integer",
+                            "description": "Newest snapshots left uncompressed; older ones are packed into tar.zst archives (default: 3)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_snapshot_list",
+                "description": "List the fs_snapshot snapshots of a path, oldest first, with the id, time and file count and size of each. Snapshots are found on disk, so those taken before a restart are listed too",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The snapshotted file or directory"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_snapshot_restore",
+                "description": "Put a file or directory back the way an fs_snapshot recorded it. Only files that differ from the snapshot are rewritten; files created since are kept unless delete_extra is set. Check what would change first with fs_snapshot_diff",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The snapshotted file or directory"
+                        },
+                        "snapshot": {
+                            "type": "string",
+                            "description": "Snapshot id or timestamp from fs_snapshot_list (default: the latest)"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Restore here instead of over the path itself"
+                        },
+                        "delete_extra": {
+                            "type": "boolean",
+                            "description": "Also remove files and directories the snapshot does not have (default: false)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_snapshot_diff",
+                "description": "Compare an fs_snapshot snapshot with the current files, or with another snapshot: added, removed and changed files with per-file line counts and optional unified diffs",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The snapshotted file or directory"
+                        },
+                        "snapshot": {
+                            "type": "string",
+                            "description": "Snapshot id or timestamp from fs_snapshot_list (default: the latest)"
+                        },
+                        "against": {
+                            "type": "string",
+                            "description": "A later snapshot to compare with instead of the current files"
+                        },
+                        "context_lines": {
+                            "type": "integer",
+                            "description": "Unchanged lines shown around each change (default: 3)"
+                        },
+                        "include_diffs": {
+                            "type": "boolean",
+                            "description": "Also return the unified diff of each changed text file (default: false)"
+                        },
+                        "max_bytes": {
+                            "type": "integer",
+                            "description": "Most diff text to return in total, cut after the last whole line that fits (default: 100000)"
                         }
                     },
                     "required": ["path"]
@@ -312,7 +875,7 @@ impl FilesystemModule {
                         },
                         "show_size": {
                             "type": "boolean",
-                            "description": "Show file sizes (default: false)"
+                            "description": "Show file sizes, and each directory's file count and total size (default: false)"
                         },
                         "dirs_only": {
                             "type": "boolean",
@@ -320,7 +883,16 @@ impl FilesystemModule {
                         },
                         "pattern": {
                             "type": "string",
-                            "description": "Only show entries matching this pattern (substring match)"
+                            "description": "Only show files matching this pattern (substring or glob)"
+                        },
+                        "respect_gitignore": {
+                            "type": "boolean",
+                            "description": "Leave out .git and what .gitignore, .ignore and git's exclude files ignore (default: false)"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["text", "json"],
+                            "description": "text: an indented tree drawing; json: nested nodes with each directory's file count and total size (default: text)"
                         }
                     },
                     "required": ["path"]
@@ -362,7 +934,7 @@ impl FilesystemModule {
             }),
             json!({
                 "name": "fs_tail",
-                "description": "Read the last N lines of a file. Essential for reading log files and build output.",
+                "description": "Read the last N lines or bytes of a file without reading the rest, and optionally follow it for a while to get what is appended. Essential for reading log files and build output. Pass next_offset back as offset to get only what was written since",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -373,6 +945,26 @@ impl FilesystemModule {
                         "lines": {
                             "type": "number",
                             "description": "Number of lines from the end (default: 20)"
+                        },
+                        "bytes": {
+                            "type": "number",
+                            "description": "Number of bytes from the end, instead of lines"
+                        },
+                        "offset": {
+                            "type": "number",
+                            "description": "Read from this byte offset to the end instead, e.g. next_offset of an earlier call; starts over from 0 if the file has since shrunk"
+                        },
+                        "follow": {
+                            "type": "number",
+                            "description": "Seconds to keep reading what is appended to the file, up to 300; rotated or truncated files are read again from the start. When the call carries a progress token, each appended line is streamed as it arrives (default: 0)"
+                        },
+                        "until": {
+                            "type": "string",
+                            "description": "Regex that stops following as soon as an appended line matches it, e.g. 'Finished|error'"
+                        },
+                        "max_bytes": {
+                            "type": "number",
+                            "description": "Most bytes of content, and of appended content, to return; the oldest are dropped past it (default: 1000000)"
                         }
                     },
                     "required": ["path"]
@@ -407,24 +999,462 @@ impl FilesystemModule {
                         "dry_run": {
                             "type": "boolean",
                             "description": "Preview changes without writing (default: false)"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; replacements are staged until txn_commit"
                         }
                     },
                     "required": ["path", "find", "replace"]
                 }
             }),
-        ]
-    }
-
-    pub async fn read(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let full_content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read file: {}", path))?;
-
-        let total_lines = full_content.lines().count();
-
-        // Check if lines parameter is provided
+            json!({
+                "name": "fs_edit",
+                "description": "Edit a file in place with search/replace blocks or a unified diff, instead of rewriting it through fs_write. Either every edit applies or nothing is written; preview returns the resulting diff without writing.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File to edit"
+                        },
+                        "edits": {
+                            "type": "array",
+                            "description": "Search/replace blocks, applied in order. Each search text must occur exactly once unless replace_all is set.",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "search": {
+                                        "type": "string",
+                                        "description": "Text to find, including enough surrounding lines to be unique"
+                                    },
+                                    "replace": {
+                                        "type": "string",
+                                        "description": "Text to put in its place"
+                                    },
+                                    "replace_all": {
+                                        "type": "boolean",
+                                        "description": "Replace every occurrence (default: false)"
+                                    }
+                                },
+                                "required": ["search", "replace"]
+                            }
+                        },
+                        "patch": {
+                            "type": "string",
+                            "description": "Unified diff for this file, instead of edits; hunks are located by their context, so drifted line numbers still apply"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["exact", "fuzzy"],
+                            "description": "exact (default): text must match character for character; fuzzy: lines may differ in indentation and other whitespace, and replacements are re-indented to fit"
+                        },
+                        "preview": {
+                            "type": "boolean",
+                            "description": "Return the diff the edit would make without writing (default: false)"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; the edit is staged until txn_commit"
+                        },
+                        "expected_hash": {
+                            "type": "string",
+                            "description": "The file's hash from fs_read; the edit fails with a conflict if the file has changed since"
+                        },
+                        "expected_mtime": {
+                            "type": "integer",
+                            "description": "The file's mtime from fs_read; the edit fails with a conflict if the file was modified since"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_filetype",
+                "description": "Detect a file's type from its content and name: MIME type, text or binary, encoding, language, and line-ending style",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File to inspect"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_normalize",
+                "description": "Normalize line endings, byte order marks and trailing newlines of a text file or every text file in a directory (skipping .gitignore'd files), with dry-run reporting",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to normalize"
+                        },
+                        "line_endings": {
+                            "type": "string",
+                            "enum": ["lf", "crlf", "keep"],
+                            "description": "Line endings to convert to (default: lf)"
+                        },
+                        "bom": {
+                            "type": "string",
+                            "enum": ["strip", "add", "keep"],
+                            "description": "Remove or add a UTF-8 byte order mark (default: strip)"
+                        },
+                        "final_newline": {
+                            "type": "boolean",
+                            "description": "End non-empty files with a line ending (default: true)"
+                        },
+                        "file_pattern": {
+                            "type": "string",
+                            "description": "Only process files matching this pattern (e.g. '*.rs')"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also process files excluded by .gitignore, .ignore and git's exclude files (default: false)"
+                        },
+                        "dry_run": {
+                            "type": "boolean",
+                            "description": "Report what would change without writing (default: false)"
+                        },
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin; changes are staged until txn_commit"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_loc",
+                "description": "Count lines of code per language (files, code, comment and blank lines) in a file or directory tree, skipping .gitignore'd files. A quick overview of an unfamiliar repository.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to count"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also count files excluded by .gitignore, .ignore and git's exclude files (default: false)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_hash",
+                "description": "Checksum a file, or every file in a directory tree plus one digest of the whole tree, with MD5, SHA-1, SHA-256, SHA-512 or BLAKE3. Files are streamed, not loaded into memory. Pass 'expected' to verify a download, or compare tree digests to tell whether anything changed.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to hash"
+                        },
+                        "algorithm": {
+                            "type": "string",
+                            "enum": ["md5", "sha1", "sha256", "sha512", "blake3"],
+                            "description": "Hash algorithm (default: sha256, or the one 'expected' names)"
+                        },
+                        "expected": {
+                            "type": "string",
+                            "description": "Checksum to compare against, in hex, optionally prefixed with the algorithm ('sha256:9f86d0...'); for a directory, the tree digest. The result reports whether it matches."
+                        },
+                        "file_pattern": {
+                            "type": "string",
+                            "description": "Only hash files matching this pattern (e.g. '*.rs'), for directories"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also hash files excluded by .gitignore, .ignore and git's exclude files, for directories (default: false)"
+                        },
+                        "page_size": {
+                            "type": "integer",
+                            "description": "List at most this many files' checksums, with a next_cursor for the rest (default: all at once)"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "next_cursor from a previous fs_hash, to get the next page of files; other arguments are then ignored"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_diff",
+                "description": "Compare two files as a unified diff, or two directory trees as a summary of added, removed and changed files with per-file line counts and optional diffs. Identical files are told apart by content without being read twice.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "old": {
+                            "type": "string",
+                            "description": "Original file or directory (lines shown with -)"
+                        },
+                        "new": {
+                            "type": "string",
+                            "description": "Changed file or directory (lines shown with +)"
+                        },
+                        "context_lines": {
+                            "type": "integer",
+                            "description": "Unchanged lines shown around each change (default: 3)"
+                        },
+                        "include_diffs": {
+                            "type": "boolean",
+                            "description": "For directories, also return the unified diff of each changed text file (default: false)"
+                        },
+                        "max_bytes": {
+                            "type": "integer",
+                            "description": "Most diff text to return in total, cut after the last whole line that fits (default: 100000)"
+                        },
+                        "file_pattern": {
+                            "type": "string",
+                            "description": "For directories, only compare files matching this pattern (e.g. '*.rs')"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "For directories, also compare files excluded by .gitignore, .ignore and git's exclude files (default: false)"
+                        }
+                    },
+                    "required": ["old", "new"]
+                }
+            }),
+            json!({
+                "name": "fs_archive",
+                "description": "Pack a file or directory into a zip, tar, tar.gz or tar.zst archive, streaming one file at a time. Include and exclude patterns pick what goes in; zips can be encrypted with a password (AES-256). Symlinks are stored as links.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "File or directory to archive; entries are named from its own name down"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Archive file to write"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["zip", "tar", "tar.gz", "tar.zst"],
+                            "description": "Archive format (default: from the destination's extension)"
+                        },
+                        "include": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Only archive files matching one of these patterns. A pattern with a '/' matches the path below the source, one without matches file names (e.g. '*.rs', 'src/**/*.toml')"
+                        },
+                        "exclude": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Leave out files and directories matching one of these patterns (e.g. 'target', '*.log')"
+                        },
+                        "password": {
+                            "type": "string",
+                            "description": "Encrypt every entry with this password (zip only)"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace the destination if it exists (default: false)"
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }
+            }),
+            json!({
+                "name": "fs_extract",
+                "description": "Unpack a zip, tar, tar.gz or tar.zst archive into a directory. Entries that would land outside it (absolute paths, '..', paths through symlinks, symlinks pointing out) are refused and listed in 'rejected'. Existing files are kept unless overwrite is set. dry_run lists the entries without writing anything.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "source": {
+                            "type": "string",
+                            "description": "Archive to unpack"
+                        },
+                        "destination": {
+                            "type": "string",
+                            "description": "Directory to unpack into, created if missing"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["zip", "tar", "tar.gz", "tar.zst"],
+                            "description": "Archive format (default: from the extension, or else the file's contents)"
+                        },
+                        "include": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Only extract files matching one of these patterns, as for fs_archive"
+                        },
+                        "exclude": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Skip files and directories matching one of these patterns"
+                        },
+                        "password": {
+                            "type": "string",
+                            "description": "Password of an encrypted zip"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Replace files that already exist (default: false, they are listed in 'skipped')"
+                        },
+                        "dry_run": {
+                            "type": "boolean",
+                            "description": "Only list the entries that would be extracted (default: false)"
+                        }
+                    },
+                    "required": ["source", "destination"]
+                }
+            }),
+            json!({
+                "name": "fs_recent",
+                "description": "List the files under a directory modified in the last N minutes, newest first, skipping .gitignore'd files. Finds what just changed without a find + stat loop.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to search"
+                        },
+                        "minutes": {
+                            "type": "number",
+                            "description": "How far back to look, in minutes (default: 60)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Return at most this many files (default: 50)"
+                        },
+                        "file_pattern": {
+                            "type": "string",
+                            "description": "Only files matching this pattern (e.g. '*.rs')"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also list files excluded by .gitignore, .ignore and git's exclude files (default: false)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_largest",
+                "description": "List the largest files under a directory, biggest first, skipping .gitignore'd files unless asked",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to search"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "How many files to return (default: 20)"
+                        },
+                        "file_pattern": {
+                            "type": "string",
+                            "description": "Only files matching this pattern (e.g. '*.log')"
+                        },
+                        "include_ignored": {
+                            "type": "boolean",
+                            "description": "Also consider files excluded by .gitignore, .ignore and git's exclude files, such as build output (default: false)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "fs_du",
+                "description": "Disk usage of a directory: its total size, the size of each entry in it, and the largest subdirectories and files anywhere below it. Counts everything, build output included, so it finds what to clean up",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to measure"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "How many entries, subdirectories and files to list in each ranking (default: 20)"
+                        },
+                        "depth": {
+                            "type": "integer",
+                            "description": "How many levels deep subdirectories are ranked; their sizes always include everything below them (default: 3)"
+                        },
+                        "by": {
+                            "type": "string",
+                            "enum": ["size", "disk_usage"],
+                            "description": "Rank by file size, or by the space allocated on disk, which is what frees up when deleting and differs for sparse and many small files (default: size)"
+                        },
+                        "exclude": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Leave out files and directories matching one of these patterns (e.g. '.git', '*.log', 'src/generated')"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn read(&self, args: Value) -> Result<ToolOutput> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let bytes = match self.txn(&args)? {
+            Some(txn) => self
+                .read_text(Some(txn), path)?
+                .with_context(|| format!("File does not exist in transaction {}: {}", txn.1, path))?
+                .into_bytes(),
+            None => fs::read(path)
+                .with_context(|| format!("Failed to read file: {}", path))?,
+        };
+
+        // Without an encoding, text comes back as is and binary files as base64
+        let encoding = match args["encoding"].as_str() {
+            Some(encoding @ ("utf-8" | "latin1" | "base64" | "hex")) => encoding,
+            Some(other) => anyhow::bail!("Invalid encoding '{}': expected utf-8, latin1, base64 or hex", other),
+            None if std::str::from_utf8(&bytes).is_ok() => "utf-8",
+            None if is_text(Path::new(path), &bytes) => "latin1",
+            None => "base64",
+        };
+        let text: Option<std::borrow::Cow<str>> = match encoding {
+            "utf-8" => Some(std::str::from_utf8(&bytes).map_err(|_| {
+                anyhow::anyhow!("Invalid encoding: {} is not UTF-8 text; read it with encoding latin1, base64 or hex", path)
+            })?.into()),
+            "latin1" => Some(bytes.iter().map(|&b| b as char).collect::<String>().into()),
+            _ => None,
+        };
+        let at_boundary = |i: usize| text.as_deref().is_none_or(|text| encoding != "utf-8" || text.is_char_boundary(i));
+
+        let max_bytes = args["max_bytes"].as_u64().map(|n| n as usize);
+        let by_line = !args["start_line"].is_null() || !args["end_line"].is_null();
+        let by_byte = !args["offset"].is_null() || !args["length"].is_null();
+        if by_byte && (by_line || !args["lines"].is_null()) {
+            anyhow::bail!("Invalid arguments: pass line ranges or offset/length, not both");
+        }
+        if by_line && !args["lines"].is_null() {
+            anyhow::bail!("Invalid arguments: pass either lines or start_line/end_line, not both");
+        }
+        let lines_text = || {
+            text.as_deref()
+                .context("Invalid arguments: line ranges need a text encoding (utf-8 or latin1); read binary files with offset/length")
+        };
+
+        let mut result = json!({
+            "path": path,
+            "encoding": encoding,
+            "total_lines": text.as_deref().map(|text| text.lines().count()),
+            "file_size": bytes.len(),
+            "hash": content_hash(&bytes),
+            "mtime": mtime_ms(Path::new(path))
+        });
+
+        // Check if lines parameter is provided
         let content = if let Some(lines_array) = args.get("lines").and_then(|v| v.as_array()) {
-            let all_lines: Vec<&str> = full_content.lines().collect();
+            let all_lines: Vec<&str> = lines_text()?.lines().collect();
             let mut selected_lines = Vec::new();
 
             for range in lines_array {
@@ -436,846 +1466,3458 @@ impl FilesystemModule {
                         let from_idx = from.saturating_sub(1);
                         let to_idx = to.min(all_lines.len());
 
-                        if from_idx < all_lines.len() && from_idx < to_idx {
-                            selected_lines.extend_from_slice(&all_lines[from_idx..to_idx]);
+                        if from_idx < all_lines.len() && from_idx < to_idx {
+                            selected_lines.extend_from_slice(&all_lines[from_idx..to_idx]);
+                        }
+                    }
+                }
+            }
+
+            let selected = selected_lines.join("\n");
+            let content = &selected[..fitting_len(selected.as_bytes(), max_bytes, |i| selected.is_char_boundary(i), true)];
+            result["truncated"] = json!(content.len() < selected.len());
+            content.to_string().into()
+        } else if by_line {
+            let all_lines: Vec<&str> = lines_text()?.lines().collect();
+            let start = (args["start_line"].as_u64().unwrap_or(1) as usize).max(1);
+            let end = args["end_line"].as_u64().map_or(all_lines.len(), |n| n as usize).min(all_lines.len());
+            let mut content = String::new();
+            let mut last = start - 1;
+            let mut truncated = false;
+            for (i, line) in all_lines.iter().enumerate().take(end).skip(start - 1) {
+                let separator = if i + 1 > start { 1 } else { 0 };
+                if max_bytes.is_some_and(|max| content.len() + separator + line.len() > max) {
+                    // A first line longer than max_bytes is cut; otherwise stop at a whole line
+                    if i + 1 == start {
+                        content = line[..fitting_len(line.as_bytes(), max_bytes, |i| line.is_char_boundary(i), true)].to_string();
+                        last = start;
+                    }
+                    truncated = true;
+                    break;
+                }
+                if separator == 1 {
+                    content.push('\n');
+                }
+                content.push_str(line);
+                last = i + 1;
+            }
+            result["start_line"] = json!(start);
+            result["end_line"] = json!(last);
+            result["next_line"] = json!((last < all_lines.len()).then_some(last + 1));
+            result["truncated"] = json!(truncated);
+            content.into()
+        } else if by_byte || max_bytes.is_some() {
+            let offset = floor_boundary((args["offset"].as_u64().unwrap_or(0) as usize).min(bytes.len()), at_boundary);
+            let end = match args["length"].as_u64() {
+                Some(length) => floor_boundary(offset.saturating_add(length as usize).min(bytes.len()), at_boundary),
+                None => bytes.len(),
+            };
+            let selected = &bytes[offset..end];
+            let taken = fitting_len(selected, max_bytes, |i| at_boundary(offset + i), text.is_some());
+            result["offset"] = json!(offset);
+            result["end_offset"] = json!(offset + taken);
+            result["next_offset"] = json!((offset + taken < bytes.len()).then_some(offset + taken));
+            result["truncated"] = json!(taken < selected.len());
+            result["size"] = json!(taken);
+            encode_content(&selected[..taken], encoding).into()
+        } else {
+            result["size"] = json!(bytes.len());
+            match text {
+                Some(text) => text,
+                None => encode_content(&bytes, encoding).into(),
+            }
+        };
+
+        if result["size"].is_null() {
+            // Line ranges are always text, with one character per byte in latin1
+            result["size"] = json!(if encoding == "latin1" { content.chars().count() } else { content.len() });
+        }
+        // Whole files can be large: the content goes straight into the JSON text
+        #[derive(Serialize)]
+        struct Read<'a> {
+            #[serde(flatten)]
+            fields: Value,
+            content: &'a str,
+        }
+        ToolOutput::serialize(&Read { fields: result, content: &content })
+    }
+
+    pub async fn read_many(&self, args: Value) -> Result<ToolOutput> {
+        let max_file_bytes = args["max_bytes_per_file"].as_u64().unwrap_or(READ_MANY_FILE_BYTES);
+        let max_total_bytes = args["max_total_bytes"].as_u64().unwrap_or(READ_MANY_TOTAL_BYTES);
+        let max_files = args["max_files"].as_u64().unwrap_or(READ_MANY_MAX_FILES) as usize;
+
+        let mut paths = string_list(&args["paths"]);
+        if let Some(pattern) = args["pattern"].as_str() {
+            let root = args["path"].as_str().unwrap_or(".");
+            self.check_allowed(root)?;
+            provenance::read(root);
+            let matcher = FindPattern::new(pattern, "glob", false)?;
+            let mut matched: Vec<PathBuf> = walk_files(Path::new(root), args["include_ignored"].as_bool().unwrap_or(false))
+                .filter(|path| {
+                    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+                    matcher.matches(&relative, &file_name(path), path)
+                })
+                .collect();
+            matched.sort();
+            paths.extend(matched.iter().map(|path| path.display().to_string()));
+        } else if args["paths"].is_null() {
+            anyhow::bail!("Missing 'paths' or 'pattern' parameter");
+        }
+        let more_files = paths.len() > max_files;
+        paths.truncate(max_files);
+
+        let mut files = BTreeMap::new();
+        let mut errors = BTreeMap::new();
+        let mut not_read = Vec::new();
+        let mut total_bytes = 0;
+        for path in paths {
+            if files.contains_key(&path) || errors.contains_key(&path) {
+                continue;
+            }
+            // Fill the budget with what fits, passing over files too big for what is left;
+            // denied and unreadable ones are reported in `errors` instead
+            let size = fs::metadata(&path)
+                .ok()
+                .filter(|_| self.is_allowed(Path::new(&path)))
+                .map(|metadata| metadata.len().min(max_file_bytes));
+            if size.is_some_and(|size| total_bytes + size > max_total_bytes) {
+                not_read.push(path);
+                continue;
+            }
+            match self.read_batch_file(&path, max_file_bytes) {
+                Ok(file) => {
+                    total_bytes += file.size;
+                    files.insert(path, file);
+                }
+                Err(e) => {
+                    errors.insert(path, format!("{:#}", e));
+                }
+            }
+        }
+
+        let truncated = more_files || !not_read.is_empty() || files.values().any(|file| file.truncated);
+        // Contents go straight into the JSON text, as fs_read's do
+        #[derive(Serialize)]
+        struct ReadMany {
+            files: BTreeMap<String, BatchFile>,
+            errors: BTreeMap<String, String>,
+            not_read: Vec<String>,
+            count: usize,
+            error_count: usize,
+            total_bytes: u64,
+            truncated: bool,
+        }
+        ToolOutput::serialize(&ReadMany {
+            count: files.len(),
+            error_count: errors.len(),
+            files,
+            errors,
+            not_read,
+            total_bytes,
+            truncated,
+        })
+    }
+
+    /// One file for fs_read_many, encoded as fs_read would and cut after the last whole
+    /// line within `max_bytes`. Only that much is read, so huge files cost no more.
+    fn read_batch_file(&self, path: &str, max_bytes: u64) -> Result<BatchFile> {
+        use std::io::Read as _;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let file = fs::File::open(path).with_context(|| format!("Failed to read file: {}", path))?;
+        let metadata = file.metadata().with_context(|| format!("Failed to read file: {}", path))?;
+        if metadata.is_dir() {
+            anyhow::bail!("Not a file: {}", path);
+        }
+        let mut bytes = Vec::new();
+        file.take(max_bytes + 1)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read file: {}", path))?;
+        let whole = bytes.len() as u64 <= max_bytes;
+
+        // A character split where the read stopped does not make the file binary
+        let utf8 = match std::str::from_utf8(&bytes) {
+            Ok(_) => true,
+            Err(e) => !whole && e.error_len().is_none(),
+        };
+        let encoding = if utf8 {
+            "utf-8"
+        } else if is_text(Path::new(path), &bytes) {
+            "latin1"
+        } else {
+            "base64"
+        };
+        let at_boundary = |i: usize| !utf8 || bytes.get(i).is_none_or(|&b| (b & 0xC0) != 0x80);
+        let taken = fitting_len(&bytes, Some(max_bytes as usize), at_boundary, encoding != "base64");
+
+        Ok(BatchFile {
+            content: encode_content(&bytes[..taken], encoding),
+            encoding,
+            size: taken as u64,
+            file_size: metadata.len(),
+            // Only a whole file's hash can guard a later fs_write
+            hash: whole.then(|| content_hash(&bytes)),
+            mtime: mtime_ms(Path::new(path)),
+            truncated: !whole,
+        })
+    }
+
+    pub async fn write(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let content = args["content"].as_str().context("Missing 'content' parameter")?;
+        let encoding = args["encoding"].as_str().unwrap_or("utf-8");
+        let txn = self.txn(&args)?;
+        if encoding != "utf-8" && !args["lines"].is_null() {
+            anyhow::bail!("Invalid arguments: line ranges can only be written as utf-8 text");
+        }
+
+        // Check if lines parameter is provided
+        let final_content = if let Some(lines_array) = args.get("lines").and_then(|v| v.as_array()) {
+            // Read existing file or create empty if not exists
+            let existing_content = self.read_text(txn, path).ok().flatten().unwrap_or_default();
+            let mut all_lines: Vec<String> = existing_content.lines().map(|s| s.to_string()).collect();
+            let new_lines: Vec<&str> = content.lines().collect();
+            let mut new_line_idx = 0;
+
+            for range in lines_array {
+                if let Some(range_arr) = range.as_array() {
+                    if range_arr.len() == 2 {
+                        let from = range_arr[0].as_i64().unwrap_or(1) as usize;
+                        let to = range_arr[1].as_i64().unwrap_or(all_lines.len() as i64) as usize;
+
+                        // Convert from 1-indexed to 0-indexed
+                        let from_idx = from.saturating_sub(1);
+                        let to_idx = to.min(all_lines.len());
+
+                        // Calculate how many lines to replace
+                        let range_size = to_idx.saturating_sub(from_idx);
+
+                        // Extend file if needed
+                        while all_lines.len() < to_idx {
+                            all_lines.push(String::new());
+                        }
+
+                        // Replace lines in this range with corresponding new lines
+                        for i in 0..range_size {
+                            if new_line_idx < new_lines.len() {
+                                all_lines[from_idx + i] = new_lines[new_line_idx].to_string();
+                                new_line_idx += 1;
+                            }
+                        }
+                    }
+                }
+            }
+
+            (all_lines.join("\n") + "\n").into_bytes()
+        } else {
+            decode_content(content, encoding)?
+        };
+        self.check_unchanged(&args, txn, path, &final_content)?;
+
+        if let Some((transactions, id)) = txn {
+            let bytes = final_content.len();
+            let text = String::from_utf8(final_content)
+                .map_err(|_| anyhow::anyhow!("Invalid content: transactions can only stage UTF-8 text"))?;
+            transactions.write(id, Path::new(path), text)?;
+            return Ok(json!({
+                "success": true,
+                "path": path,
+                "txn": id,
+                "bytes_staged": bytes
+            }));
+        }
+
+        fs::write(path, &final_content)
+            .with_context(|| format!("Failed to write file: {}", path))?;
+
+        Ok(json!({
+            "success": true,
+            "path": path,
+            "bytes_written": final_content.len()
+        }))
+    }
+
+    pub async fn move_file(&self, args: Value) -> Result<Value> {
+        let source = args["source"].as_str().context("Missing 'source' parameter")?;
+        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+        self.check_allowed(source)?;
+        self.check_allowed(destination)?;
+
+        if let Some((transactions, id)) = self.txn(&args)? {
+            let content = self
+                .read_text(Some((transactions, id)), source)?
+                .with_context(|| format!("Source path does not exist: {}", source))?;
+            transactions.write(id, Path::new(destination), content)?;
+            transactions.delete(id, Path::new(source))?;
+            return Ok(json!({
+                "success": true,
+                "source": source,
+                "destination": destination,
+                "txn": id
+            }));
+        }
+
+        fs::rename(source, destination)
+            .with_context(|| format!("Failed to move from {} to {}", source, destination))?;
+
+        Ok(json!({
+            "success": true,
+            "source": source,
+            "destination": destination
+        }))
+    }
+
+    pub async fn copy(&self, args: Value) -> Result<Value> {
+        let source = args["source"].as_str().context("Missing 'source' parameter")?;
+        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+        self.check_allowed(source)?;
+        self.check_allowed(destination)?;
+
+        let follow_symlinks = args["follow_symlinks"].as_bool().unwrap_or(false);
+
+        let metadata = if follow_symlinks { fs::metadata(source) } else { fs::symlink_metadata(source) }
+            .with_context(|| format!("Source path does not exist: {}", source))?;
+        let mut skipped = Vec::new();
+        if metadata.is_symlink() {
+            copy_symlink(Path::new(source), Path::new(destination))
+                .with_context(|| format!("Failed to copy symlink from {} to {}", source, destination))?;
+        } else if metadata.is_dir() {
+            skipped = copy_dir_all(source, destination, follow_symlinks, &|path| self.is_allowed(path))
+                .with_context(|| format!("Failed to copy directory from {} to {}", source, destination))?;
+        } else {
+            fs::copy(source, destination)
+                .with_context(|| format!("Failed to copy file from {} to {}", source, destination))?;
+        }
+
+        let mut result = json!({
+            "success": true,
+            "source": source,
+            "destination": destination
+        });
+        if !skipped.is_empty() {
+            // Symlinks whose targets are outside the allowed roots, broken when followed,
+            // or leading back into a directory being copied
+            result["skipped"] = json!(skipped.iter().map(|path| path.display().to_string()).collect::<Vec<_>>());
+        }
+        Ok(result)
+    }
+
+    pub async fn link(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        let target = args["target"].as_str().context("Missing 'target' parameter")?;
+        let link_type = args["type"].as_str().unwrap_or("symlink");
+        let overwrite = args["overwrite"].as_bool().unwrap_or(false);
+        self.check_allowed(path)?;
+        let link = Path::new(path);
+
+        // What the link will lead to, from the working directory
+        let resolved = match link_type {
+            "symlink" => link.parent().unwrap_or(Path::new("")).join(target),
+            "hard" => PathBuf::from(target),
+            other => anyhow::bail!("Unknown link type: {} (expected symlink or hard)", other),
+        };
+        self.check_allowed(&resolved)?;
+        if link_type == "hard" && !fs::metadata(&resolved).is_ok_and(|metadata| metadata.is_file()) {
+            anyhow::bail!("Hard link target is not an existing file: {}", target);
+        }
+
+        if let Ok(existing) = fs::symlink_metadata(link) {
+            if !overwrite {
+                anyhow::bail!("Path already exists: {} (pass overwrite: true to replace it)", path);
+            }
+            if existing.is_dir() {
+                anyhow::bail!("Path is a directory and is not replaced by a link: {}", path);
+            }
+            fs::remove_file(link).with_context(|| format!("Failed to replace {}", path))?;
+        }
+
+        let mut result = json!({
+            "success": true,
+            "path": path,
+            "target": target,
+            "type": link_type
+        });
+        if link_type == "symlink" {
+            make_symlink(Path::new(target), link).with_context(|| format!("Failed to create symlink {} -> {}", path, target))?;
+            result["broken"] = json!(!resolved.exists());
+        } else {
+            fs::hard_link(&resolved, link).with_context(|| format!("Failed to create hard link {} -> {}", path, target))?;
+            result["links"] = json!(fs::metadata(link).map(|metadata| file_links(&metadata)).unwrap_or(1));
+        }
+        Ok(result)
+    }
+
+    pub async fn create(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let type_str = args["type"].as_str().context("Missing 'type' parameter")?;
+
+        if let Some((transactions, id)) = self.txn(&args)? {
+            if type_str != "file" {
+                anyhow::bail!("Only files can be created in a transaction; directories are created on commit as needed");
+            }
+            transactions.write(id, Path::new(path), String::new())?;
+            return Ok(json!({
+                "success": true,
+                "path": path,
+                "type": type_str,
+                "txn": id
+            }));
+        }
+
+        match type_str {
+            "file" => {
+                fs::File::create(path)
+                    .with_context(|| format!("Failed to create file: {}", path))?;
+            }
+            "dir" => {
+                fs::create_dir_all(path)
+                    .with_context(|| format!("Failed to create directory: {}", path))?;
+            }
+            _ => anyhow::bail!("Invalid type: {}. Must be 'file' or 'dir'", type_str),
+        }
+
+        Ok(json!({
+            "success": true,
+            "path": path,
+            "type": type_str
+        }))
+    }
+
+    pub async fn delete(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let path_obj = Path::new(path);
+
+        let mode = match args["mode"].as_str() {
+            Some("permanent") => DeleteMode::Permanent,
+            Some("trash") => DeleteMode::Trash,
+            Some(other) => {
+                return Err(PolyError::InvalidArguments(format!("Invalid mode: {}. Must be 'permanent' or 'trash'", other)).into())
+            }
+            None => *self.delete_mode.read().unwrap(),
+        };
+
+        if let Some((transactions, id)) = self.txn(&args)? {
+            if args["mode"].as_str() == Some("trash") {
+                anyhow::bail!("Deletions staged in a transaction are permanent; leave out mode 'trash' or the txn");
+            }
+            if path_obj.is_dir() {
+                anyhow::bail!("Only files can be deleted in a transaction: {} is a directory", path);
+            }
+            if self.read_text(Some((transactions, id)), path)?.is_none() {
+                anyhow::bail!("Path does not exist: {}", path);
+            }
+            transactions.delete(id, path_obj)?;
+            return Ok(json!({
+                "success": true,
+                "path": path,
+                "txn": id
+            }));
+        }
+
+        if mode == DeleteMode::Trash {
+            if path_obj.symlink_metadata().is_err() {
+                anyhow::bail!("Path does not exist: {}", path);
+            }
+            // Where the trash records it came from: the real parent directory and the name
+            let original = path_obj
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .and_then(|parent| fs::canonicalize(parent).ok())
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default()
+                .join(path_obj.file_name().unwrap_or_default());
+            trash::delete(path_obj).map_err(|e| trash_error(e, path))?;
+            let mut result = json!({
+                "success": true,
+                "path": path,
+                "mode": "trash"
+            });
+            // The id to restore it by, where the platform lets the trash be listed
+            if let Some(item) = trash_items().ok().and_then(|items| {
+                items.into_iter().filter(|item| item.original_path() == original).max_by_key(|item| item.time_deleted)
+            }) {
+                result["trash_id"] = json!(item.id.to_string_lossy());
+            }
+            return Ok(result);
+        }
+
+        if path_obj.is_file() {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to delete file: {}", path))?;
+        } else if path_obj.is_dir() {
+            fs::remove_dir_all(path)
+                .with_context(|| format!("Failed to delete directory: {}", path))?;
+        } else {
+            anyhow::bail!("Path does not exist: {}", path);
+        }
+
+        Ok(json!({
+            "success": true,
+            "path": path,
+            "mode": "permanent"
+        }))
+    }
+
+    pub async fn trash_list(&self, args: Value) -> Result<Value> {
+        let within_path = args["path"].as_str().map(|path| resolve_path(Path::new(path)));
+        let limit = args["limit"].as_u64().unwrap_or(50) as usize;
+
+        let mut items: Vec<trash::TrashItem> = trash_items()?
+            .into_iter()
+            .filter(|item| within_path.as_deref().is_none_or(|root| within(&item.original_path(), root)))
+            .filter(|item| self.is_allowed(&item.original_path()))
+            .collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.time_deleted));
+        let total = items.len();
+        let listed: Vec<Value> = items.iter().take(limit).map(trash_item_json).collect();
+
+        Ok(json!({
+            "count": listed.len(),
+            "total": total,
+            "items": listed
+        }))
+    }
+
+    pub async fn trash_restore(&self, args: Value) -> Result<Value> {
+        let items = trash_items()?;
+        let item = match (args["id"].as_str(), args["path"].as_str()) {
+            (Some(id), _) => items
+                .into_iter()
+                .find(|item| item.id.to_string_lossy() == id)
+                .with_context(|| format!("No item with id {} in the trash", id))?,
+            (None, Some(path)) => {
+                let original = resolve_path(Path::new(path));
+                items
+                    .into_iter()
+                    .filter(|item| item.original_path() == original)
+                    .max_by_key(|item| item.time_deleted)
+                    .with_context(|| format!("Nothing deleted from {} is in the trash", path))?
+            }
+            (None, None) => anyhow::bail!("Missing 'id' or 'path' parameter"),
+        };
+        let original = item.original_path();
+        let original_str = original.display().to_string();
+        self.check_allowed(&original_str)?;
+
+        let restored = trash_item_json(&item);
+        restore_trash_item(item).map_err(|e| trash_error(e, &original_str))?;
+        Ok(json!({
+            "success": true,
+            "path": original_str,
+            "restored": restored
+        }))
+    }
+
+    pub async fn move_desktop(&self, args: Value) -> Result<Value> {
+        let item = args["item"].as_str().context("Missing 'item' parameter")?;
+        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+
+        // Get Desktop path
+        let desktop = dirs::desktop_dir()
+            .context("Could not find Desktop directory")?;
+
+        let source_path = desktop.join(item);
+        let dest_path = desktop.join(destination).join(item);
+        self.check_allowed(&source_path)?;
+        self.check_allowed(&dest_path)?;
+
+        // Create destination directory if it doesn't exist
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::rename(&source_path, &dest_path)
+            .with_context(|| format!("Failed to move {} to {}", source_path.display(), dest_path.display()))?;
+
+        Ok(json!({
+            "success": true,
+            "item": item,
+            "from": source_path,
+            "to": dest_path
+        }))
+    }
+
+    pub async fn find(&self, args: Value) -> Result<Value> {
+        if let Some(cursor) = args["cursor"].as_str() {
+            let (results, next_cursor) = self.pages.next(cursor)?;
+            return Ok(json!({
+                "results": results,
+                "next_cursor": next_cursor
+            }));
+        }
+
+        let root_path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(root_path)?;
+        provenance::read(root_path);
+        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
+        let search_type = args["type"].as_str().unwrap_or("all");
+        let max_results = args["max_results"].as_u64().unwrap_or(1000) as usize;
+        let case_insensitive = args["case_insensitive"].as_bool().unwrap_or(false);
+        let matcher = FindPattern::new(pattern, args["match_mode"].as_str().unwrap_or("auto"), case_insensitive)?;
+        let respect_gitignore = args["respect_gitignore"].as_bool().unwrap_or(true);
+        let follow_symlinks = args["follow_symlinks"].as_bool().unwrap_or(false);
+        let min_depth = args["min_depth"].as_u64().unwrap_or(0) as usize;
+        let min_size = args["min_size"].as_u64();
+        let max_size = args["max_size"].as_u64();
+        let modified_after = time_arg(&args, "modified_after")?;
+        let modified_before = time_arg(&args, "modified_before")?;
+        let extensions: Option<Vec<String>> = args["extensions"].as_array().map(|list| {
+            list.iter()
+                .filter_map(Value::as_str)
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect()
+        });
+        // Only files have a size, extension and content age worth filtering on
+        let files_only = min_size.is_some() || max_size.is_some() || extensions.is_some();
+
+        let mut walker = ignore::WalkBuilder::new(root_path);
+        walker
+            .standard_filters(respect_gitignore)
+            .hidden(false)
+            .require_git(false)
+            .follow_links(follow_symlinks)
+            .sort_by_file_name(|a, b| a.cmp(b));
+        // Followed links must not lead the walk out of the allowed roots
+        let allowed_roots = self.allowed_roots.read().unwrap().clone();
+        walker.filter_entry(move |e| {
+            (!respect_gitignore || e.file_name() != ".git")
+                && (!follow_symlinks || !e.path_is_symlink() || allowed_in(&allowed_roots, e.path()))
+        });
+        if let Some(depth) = args["max_depth"].as_u64() {
+            walker.max_depth(Some(depth as usize));
+        }
+
+        let mut results = Vec::new();
+
+        for entry in walker.build().filter_map(|e| e.ok()) {
+            if results.len() >= max_results {
+                break;
+            }
+            if entry.depth() < min_depth {
+                continue;
+            }
+
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy();
+            let relative = path.strip_prefix(root_path).unwrap_or(path).to_string_lossy().replace('\\', "/");
+
+            if !matcher.matches(&relative, &file_name, path) {
+                continue;
+            }
+
+            // Type filtering, of what followed links point to
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let entry_type = entry_type(&metadata);
+            let is_file = entry_type == "file";
+            if search_type != "all" && search_type != entry_type {
+                continue;
+            }
+            if files_only && !is_file {
+                continue;
+            }
+            if let Some(extensions) = &extensions {
+                let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+                if !extensions.contains(&extension) {
+                    continue;
+                }
+            }
+
+            let size = metadata.len();
+            if min_size.is_some_and(|min| size < min) || max_size.is_some_and(|max| size > max) {
+                continue;
+            }
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|since| since.as_secs() as i64);
+            if modified_after.is_some_and(|after| modified.is_none_or(|m| m < after))
+                || modified_before.is_some_and(|before| modified.is_none_or(|m| m >= before))
+            {
+                continue;
+            }
+
+            let mut found = json!({
+                "path": path.to_string_lossy(),
+                "name": file_name,
+                "type": entry_type,
+                "size": size,
+                "modified": modified
+            });
+            if metadata.is_symlink() {
+                add_symlink_target(&mut found, path);
+            }
+            results.push(found);
+        }
+
+        let truncated = results.len() >= max_results;
+        let count = results.len();
+        let (results, next_cursor) = match args["page_size"].as_u64() {
+            Some(page_size) => self.pages.first(results, page_size as usize),
+            None => (results, None),
+        };
+
+        Ok(json!({
+            "results": results,
+            "count": count,
+            "match_mode": matcher.mode(),
+            "truncated": truncated,
+            "next_cursor": next_cursor
+        }))
+    }
+
+    pub async fn ld(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let path_obj = Path::new(path);
+
+        if !path_obj.exists() {
+            anyhow::bail!("Path does not exist: {}", path);
+        }
+
+        let mut entries = Vec::new();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            #[cfg(unix)]
+            use std::os::unix::fs::PermissionsExt;
+
+            #[cfg(unix)]
+            let permissions = format!("{:o}", metadata.permissions().mode() & 0o777);
+
+            #[cfg(not(unix))]
+            let permissions = if metadata.permissions().readonly() {
+                "r--".to_string()
+            } else {
+                "rw-".to_string()
+            };
+
+            let mut listed = json!({
+                "name": file_name,
+                "type": entry_type(&metadata),
+                "size": metadata.len(),
+                "permissions": permissions,
+                "modified": metadata.modified().ok().and_then(|t| {
+                    t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+                })
+            });
+            if metadata.is_symlink() {
+                add_symlink_target(&mut listed, &entry.path());
+            }
+            entries.push(listed);
+        }
+
+        Ok(json!({
+            "path": path,
+            "entries": entries,
+            "count": entries.len()
+        }))
+    }
+
+    pub async fn stat(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let own = fs::symlink_metadata(path)
+            .with_context(|| format!("Failed to get metadata for: {}", path))?;
+        // A symlink is described by what it points to, unless that is missing
+        let pointed = own.is_symlink().then(|| fs::metadata(path).ok()).flatten();
+        let metadata = pointed.as_ref().unwrap_or(&own);
+
+        #[cfg(unix)]
+        use std::os::unix::fs::PermissionsExt;
+
+        #[cfg(unix)]
+        let permissions = format!("{:o}", metadata.permissions().mode() & 0o777);
+
+        #[cfg(not(unix))]
+        let permissions = if metadata.permissions().readonly() {
+            "readonly".to_string()
+        } else {
+            "read-write".to_string()
+        };
+
+        let mut result = json!({
+            "path": path,
+            "type": entry_type(&own),
+            "size": metadata.len(),
+            "permissions": permissions,
+            "readonly": metadata.permissions().readonly(),
+            "created": metadata.created().ok().and_then(|t| {
+                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+            }),
+            "modified": metadata.modified().ok().and_then(|t| {
+                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+            }),
+            "accessed": metadata.accessed().ok().and_then(|t| {
+                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+            })
+        });
+        if own.is_symlink() {
+            add_symlink_target(&mut result, Path::new(path));
+        }
+        Ok(result)
+    }
+
+    pub async fn permissions(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+
+        if let Some(mode_str) = args["mode"].as_str() {
+            // Set permissions
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = u32::from_str_radix(mode_str, 8)
+                    .with_context(|| format!("Invalid permission mode: {}", mode_str))?;
+                let permissions = std::fs::Permissions::from_mode(mode);
+                fs::set_permissions(path, permissions)
+                    .with_context(|| format!("Failed to set permissions for: {}", path))?;
+
+                Ok(json!({
+                    "success": true,
+                    "path": path,
+                    "mode": mode_str
+                }))
+            }
+
+            #[cfg(not(unix))]
+            {
+                anyhow::bail!("Setting permissions is only supported on Unix systems");
+            }
+        } else {
+            // Get permissions
+            let metadata = fs::metadata(path)?;
+
+            #[cfg(unix)]
+            use std::os::unix::fs::PermissionsExt;
+
+            #[cfg(unix)]
+            let mode = format!("{:o}", metadata.permissions().mode() & 0o777);
+
+            #[cfg(not(unix))]
+            let mode = if metadata.permissions().readonly() {
+                "readonly"
+            } else {
+                "read-write"
+            };
+
+            Ok(json!({
+                "path": path,
+                "mode": mode,
+                "readonly": metadata.permissions().readonly()
+            }))
+        }
+    }
+
+    pub async fn watch(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let duration = args["duration"].as_u64().unwrap_or(60);
+
+        // Events arrive on a tokio channel so waiting for them does not tie up a runtime thread
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+
+        watcher.watch(Path::new(path), RecursiveMode::Recursive)?;
+
+        let mut events = Vec::new();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(duration);
+
+        while let Ok(Some(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+            if let Ok(event) = event {
+                let event_json = json!({
+                    "kind": format!("{:?}", event.kind),
+                    "paths": event.paths.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>()
+                });
+
+                if let Some(ref notifier) = self.notifier {
+                    // No subscribers is not an error; the event is still returned below
+                    let _ = notifier.send(json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/fs_watch",
+                        "params": {
+                            "path": path,
+                            "event": event_json
+                        }
+                    }));
+                }
+
+                progress::output("events", &event_json.to_string());
+                events.push(event_json);
+            }
+        }
+
+        Ok(json!({
+            "path": path,
+            "duration": duration,
+            "events": events,
+            "event_count": events.len()
+        }))
+    }
+
+    pub async fn watch_start(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let recursive = args["recursive"].as_bool().unwrap_or(true);
+        self.watches.start(path, recursive, self.notifier.clone())
+    }
+
+    pub async fn watch_poll(&self, args: Value) -> Result<Value> {
+        let id = args["id"].as_str().context("Missing 'id' parameter")?;
+        let wait = args["wait"].as_f64().unwrap_or(0.0);
+        if !(0.0..=watches::MAX_WAIT.as_secs_f64()).contains(&wait) {
+            return Err(PolyError::InvalidArguments(format!(
+                "wait must be between 0 and {} seconds",
+                watches::MAX_WAIT.as_secs()
+            ))
+            .into());
+        }
+        self.watches.poll(id, std::time::Duration::from_secs_f64(wait)).await
+    }
+
+    pub async fn watch_stop(&self, args: Value) -> Result<Value> {
+        let id = args["id"].as_str().context("Missing 'id' parameter")?;
+        self.watches.stop(id)
+    }
+
+    pub async fn snapshot(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let max_snapshots = args["max_snapshots"].as_u64().unwrap_or(10) as usize;
+        let keep_uncompressed = args["keep_uncompressed"].as_u64().unwrap_or(KEEP_UNCOMPRESSED as u64) as usize;
+
+        let path_obj = Path::new(path);
+        if !path_obj.exists() {
+            anyhow::bail!("Path does not exist: {}", path);
+        }
+
+        let snapshot_dir = snapshot_dir(path_obj);
+        fs::create_dir_all(&snapshot_dir)?;
+
+        // Create timestamp
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let mut snapshot_name = format!("snapshot_{}", timestamp);
+        for n in 2.. {
+            if !snapshot_dir.join(format!("{}.json", snapshot_name)).exists() {
+                break;
+            }
+            snapshot_name = format!("snapshot_{}_{}", timestamp, n);
+        }
+
+        let key = path.to_string();
+        let mut snapshot_list = self.snapshot_history(path)?;
+
+        // Store only what changed since the last snapshot
+        let store = snapshots::Store::new(&snapshot_dir);
+        let previous = snapshot_list
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.incremental)
+            .and_then(|snapshot| snapshots::load(&snapshot.path).ok());
+        let (snapshot_path, dedup) = store.take(path_obj, &snapshot_name, &timestamp, previous.as_ref())?;
+
+        snapshot_list.push(SnapshotInfo {
+            timestamp: timestamp.clone(),
+            path: snapshot_path.clone(),
+            compressed: false,
+            incremental: true,
+        });
+
+        // Remove the oldest snapshots
+        let mut pruned = None;
+        if snapshot_list.len() > max_snapshots {
+            let mut removed = 0;
+            while snapshot_list.len() > max_snapshots {
+                if let Some(oldest) = snapshot_list.first() {
+                    if oldest.path.exists() {
+                        if oldest.path.is_file() {
+                            fs::remove_file(&oldest.path)?;
+                        } else {
+                            fs::remove_dir_all(&oldest.path)?;
+                        }
+                    }
+                }
+                snapshot_list.remove(0);
+                removed += 1;
+            }
+
+            // Then the contents no remaining snapshot refers to
+            let (blobs, bytes) = store.collect_garbage()?;
+            pruned = Some(json!({ "snapshots": removed, "blobs": blobs, "bytes": bytes }));
+        }
+
+        // Pack all but the newest into archives
+        let mut compressed = 0;
+        let older = snapshot_list.len().saturating_sub(keep_uncompressed);
+        for snapshot in snapshot_list.iter_mut().take(older) {
+            if snapshot.incremental && !snapshot.compressed {
+                snapshot.path = store.compress(&snapshot.path)?;
+                snapshot.compressed = true;
+                compressed += 1;
+            }
+        }
+        self.storage.put_json(keyspaces::SNAPSHOTS, &key, &snapshot_list)?;
+
+        let mut result = json!({
+            "success": true,
+            "path": path,
+            "snapshot": snapshot_path,
+            "timestamp": timestamp,
+            "total_snapshots": snapshot_list.len(),
+            "max_snapshots": max_snapshots,
+            "dedup": dedup,
+            "compressed": compressed,
+            "disk_usage": store.disk_usage()?
+        });
+        if let Some(pruned) = pruned {
+            result["pruned"] = pruned;
+        }
+        Ok(result)
+    }
+
+    pub async fn snapshot_list(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+
+        let history = self.snapshot_history(path)?;
+        let snapshots: Vec<Value> = history
+            .iter()
+            .map(|snapshot| {
+                let mut info = json!({
+                    "id": snapshot.id(),
+                    "timestamp": snapshot.timestamp,
+                    "path": snapshot.path,
+                    "incremental": snapshot.incremental,
+                    "compressed": snapshot.compressed,
+                });
+                if let Ok(manifest) = snapshot.manifest() {
+                    let (mut files, mut directories, mut symlinks, mut bytes) = (0, 0, 0, 0);
+                    for entry in &manifest.entries {
+                        match entry {
+                            snapshots::Entry::File { size, .. } => {
+                                files += 1;
+                                bytes += size;
+                            }
+                            snapshots::Entry::Dir { .. } => directories += 1,
+                            snapshots::Entry::Symlink { .. } => symlinks += 1,
                         }
                     }
+                    info["files"] = json!(files);
+                    info["directories"] = json!(directories);
+                    info["symlinks"] = json!(symlinks);
+                    info["bytes"] = json!(bytes);
                 }
-            }
+                info
+            })
+            .collect();
+        let store = snapshots::Store::new(snapshot_dir(Path::new(path)));
+        Ok(json!({
+            "path": path,
+            "store": snapshot_dir(Path::new(path)),
+            "count": snapshots.len(),
+            "snapshots": snapshots,
+            "disk_usage": store.disk_usage()?,
+        }))
+    }
 
-            selected_lines.join("\n")
-        } else {
-            full_content
+    pub async fn snapshot_restore(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        let destination = args["destination"].as_str().unwrap_or(path);
+        self.check_allowed(path)?;
+        self.check_allowed(destination)?;
+        let delete_extra = args["delete_extra"].as_bool().unwrap_or(false);
+
+        let store = snapshots::Store::new(snapshot_dir(Path::new(path)));
+        let snapshot = self.find_snapshot(path, args["snapshot"].as_str())?.expanded(&store)?;
+        let manifest = snapshot.manifest()?;
+        let restored = store.restore(&manifest, Path::new(destination), delete_extra)?;
+
+        let mut result = json!({
+            "success": true,
+            "path": path,
+            "destination": destination,
+            "snapshot": snapshot.id(),
+            "timestamp": snapshot.timestamp,
+        });
+        if let (Value::Object(result), Value::Object(restored)) = (&mut result, serde_json::to_value(restored)?) {
+            result.extend(restored);
+        }
+        Ok(result)
+    }
+
+    pub async fn snapshot_diff(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let context_lines = args["context_lines"].as_u64().unwrap_or(3) as usize;
+        let include_diffs = args["include_diffs"].as_bool().unwrap_or(false);
+        let mut budget = args["max_bytes"].as_u64().unwrap_or(DIFF_MAX_BYTES as u64) as usize;
+
+        let store = snapshots::Store::new(snapshot_dir(Path::new(path)));
+        let snapshot = self.find_snapshot(path, args["snapshot"].as_str())?.expanded(&store)?;
+        // Each file's content on disk, with its hash when a manifest gives it
+        let files_of = |manifest: &snapshots::Manifest| -> HashMap<String, (PathBuf, Option<String>)> {
+            manifest
+                .entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    snapshots::Entry::File { path, hash, .. } => Some((path.clone(), (store.blob(hash), Some(hash.clone())))),
+                    _ => None,
+                })
+                .collect()
+        };
+        let old_files = files_of(&snapshot.manifest()?);
+        let (against, new_files) = match args["against"].as_str() {
+            Some(against) => {
+                let against = self.find_snapshot(path, Some(against))?.expanded(&store)?;
+                (against.id(), files_of(&against.manifest()?))
+            }
+            None => {
+                provenance::read(path);
+                ("current".to_string(), self.current_files(Path::new(path)))
+            }
         };
 
+        let mut removed: Vec<&String> = old_files.keys().filter(|file| !new_files.contains_key(*file)).collect();
+        let mut added: Vec<&String> = new_files.keys().filter(|file| !old_files.contains_key(*file)).collect();
+        let mut both: Vec<&String> = old_files.keys().filter(|file| new_files.contains_key(*file)).collect();
+        removed.sort();
+        added.sort();
+        both.sort();
+
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for file in both {
+            let ((old, old_hash), (new, new_hash)) = (&old_files[file], &new_files[file]);
+            if old_hash.is_some() && old_hash == new_hash {
+                unchanged += 1;
+                continue;
+            }
+            let labels = (format!("a/{}", file), format!("b/{}", file));
+            let budget = include_diffs.then_some(&mut budget);
+            match diff_files(old, new, (&labels.0, &labels.1), context_lines, budget)? {
+                Some(mut changes) => {
+                    changes["path"] = json!(file);
+                    changed.push(changes);
+                }
+                None => unchanged += 1,
+            }
+        }
+
         Ok(json!({
             "path": path,
-            "content": content,
-            "size": content.len(),
-            "total_lines": total_lines
+            "snapshot": snapshot.id(),
+            "against": against,
+            "identical": added.is_empty() && removed.is_empty() && changed.is_empty(),
+            "added": added,
+            "removed": removed,
+            "changed": changed,
+            "summary": {
+                "added": added.len(),
+                "removed": removed.len(),
+                "changed": changed.len(),
+                "unchanged": unchanged
+            }
         }))
     }
 
-    pub async fn write(&self, args: Value) -> Result<Value> {
+    /// The snapshots of `path`, oldest first: the history kept in storage, plus the
+    /// manifests on disk it does not list, such as those taken before a restart
+    /// with in-memory storage or by another session
+    fn snapshot_history(&self, path: &str) -> Result<Vec<SnapshotInfo>> {
+        let mut history: Vec<SnapshotInfo> = self.storage.get_json(keyspaces::SNAPSHOTS, path)?.unwrap_or_default();
+        history.retain(|snapshot| snapshot.path.exists());
+        // Whether it is compressed is up to date on disk, not in the history
+        for snapshot in history.iter_mut().filter(|snapshot| snapshot.incremental) {
+            snapshot.compressed = snapshots::is_archive(&snapshot.path);
+        }
+        let store = snapshots::Store::new(snapshot_dir(Path::new(path)));
+        let on_disk = store.manifests()?;
+        for (manifest, timestamp) in &on_disk {
+            if !history.iter().any(|snapshot| snapshot.path == *manifest) {
+                history.push(SnapshotInfo {
+                    timestamp: timestamp.clone(),
+                    path: manifest.clone(),
+                    compressed: snapshots::is_archive(manifest),
+                    incremental: true,
+                });
+            }
+        }
+        // Stable, so snapshots taken in the same second keep the order the store gives them
+        let position = |snapshot: &SnapshotInfo| on_disk.iter().position(|(manifest, _)| *manifest == snapshot.path);
+        history.sort_by(|a, b| (&a.timestamp, position(a)).cmp(&(&b.timestamp, position(b))));
+        Ok(history)
+    }
+
+    /// The snapshot of `path` with id or timestamp `id`, or the latest
+    fn find_snapshot(&self, path: &str, id: Option<&str>) -> Result<SnapshotInfo> {
+        let history = self.snapshot_history(path)?;
+        let found = match id.filter(|id| *id != "latest") {
+            None => history.last(),
+            Some(id) => history.iter().rev().find(|snapshot| snapshot.id() == id || snapshot.timestamp == id),
+        };
+        if let Some(snapshot) = found {
+            return Ok(snapshot.clone());
+        }
+        if history.is_empty() {
+            anyhow::bail!("No snapshots of {}; take one with fs_snapshot", path);
+        }
+        let ids: Vec<String> = history.iter().map(SnapshotInfo::id).collect();
+        Err(PolyError::InvalidArguments(format!(
+            "Invalid snapshot '{}' for {}: expected one of {}",
+            id.unwrap_or_default(),
+            path,
+            ids.join(", ")
+        ))
+        .into())
+    }
+
+    /// The files under `path` by their path relative to it, as a snapshot names them
+    fn current_files(&self, path: &Path) -> HashMap<String, (PathBuf, Option<String>)> {
+        if path.is_file() {
+            return HashMap::from([(file_name(path).into_owned(), (path.to_path_buf(), None))]);
+        }
+        WalkDir::new(path)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file() && self.is_allowed(e.path()))
+            .map(|e| {
+                let relative = e.path().strip_prefix(path).unwrap_or(e.path()).to_string_lossy().replace('\\', "/");
+                (relative, (e.path().to_path_buf(), None))
+            })
+            .collect()
+    }
+
+    pub async fn tree(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let content = args["content"].as_str().context("Missing 'content' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let max_depth = args["max_depth"].as_u64().unwrap_or(4) as usize;
+        let show_size = args["show_size"].as_bool().unwrap_or(false);
+        let format = args["format"].as_str().unwrap_or("text");
+        if !matches!(format, "text" | "json") {
+            return Err(PolyError::InvalidArguments(format!("Invalid format: {}", format)).into());
+        }
+        let options = TreeOptions {
+            max_depth,
+            show_hidden: args["show_hidden"].as_bool().unwrap_or(false),
+            respect_gitignore: args["respect_gitignore"].as_bool().unwrap_or(false),
+            dirs_only: args["dirs_only"].as_bool().unwrap_or(false),
+            pattern: args["pattern"].as_str(),
+            // Totals need the whole tree; a plain text tree only what it shows
+            whole_tree: show_size || format == "json",
+        };
 
-        // Check if lines parameter is provided
-        let final_content = if let Some(lines_array) = args.get("lines").and_then(|v| v.as_array()) {
-            // Read existing file or create empty if not exists
-            let existing_content = fs::read_to_string(path).unwrap_or_default();
-            let mut all_lines: Vec<String> = existing_content.lines().map(|s| s.to_string()).collect();
-            let new_lines: Vec<&str> = content.lines().collect();
-            let mut new_line_idx = 0;
+        let root = Path::new(path);
+        if !root.exists() {
+            anyhow::bail!("Path does not exist: {}", path);
+        }
 
-            for range in lines_array {
-                if let Some(range_arr) = range.as_array() {
-                    if range_arr.len() == 2 {
-                        let from = range_arr[0].as_i64().unwrap_or(1) as usize;
-                        let to = range_arr[1].as_i64().unwrap_or(all_lines.len() as i64) as usize;
+        let tree = scan_tree(root, &options);
+        let (dir_count, file_count) = tree.shown();
+        let mut result = json!({
+            "path": path,
+            "format": format,
+            "directories": dir_count,
+            "files": file_count,
+            "max_depth": max_depth
+        });
+        result["tree"] = match format {
+            "json" => tree.to_json(0, max_depth),
+            _ => {
+                let mut output = format!("{}\n", root.display());
+                tree.render("", show_size, &mut output);
+                json!(output)
+            }
+        };
+        if options.whole_tree {
+            result["total_files"] = json!(tree.files);
+            result["total_directories"] = json!(tree.dirs);
+            result["total_size"] = json!(tree.size);
+        }
+        Ok(result)
+    }
 
-                        // Convert from 1-indexed to 0-indexed
-                        let from_idx = from.saturating_sub(1);
-                        let to_idx = to.min(all_lines.len());
+    pub async fn grep(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
+        let case_insensitive = args["case_insensitive"].as_bool().unwrap_or(false);
+        let max_results = args["max_results"].as_u64().unwrap_or(200) as usize;
+        let context_lines = args["context_lines"].as_u64().unwrap_or(0) as usize;
+        let file_pattern = args["file_pattern"].as_str();
 
-                        // Calculate how many lines to replace
-                        let range_size = to_idx.saturating_sub(from_idx);
+        let mut regex_pattern = String::new();
+        if case_insensitive {
+            regex_pattern.push_str("(?i)");
+        }
+        regex_pattern.push_str(pattern);
 
-                        // Extend file if needed
-                        while all_lines.len() < to_idx {
-                            all_lines.push(String::new());
-                        }
+        let re = Regex::new(&regex_pattern)
+            .with_context(|| format!("Invalid regex pattern: {}", pattern))?;
 
-                        // Replace lines in this range with corresponding new lines
-                        for i in 0..range_size {
-                            if new_line_idx < new_lines.len() {
-                                all_lines[from_idx + i] = new_lines[new_line_idx].to_string();
-                                new_line_idx += 1;
-                            }
+        let root = Path::new(path);
+        let mut matches = Vec::new();
+
+        let files: Vec<PathBuf> = if root.is_file() {
+            vec![root.to_path_buf()]
+        } else {
+            WalkDir::new(root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file() && self.is_allowed(e.path()))
+                .filter(|e| {
+                    if let Some(fp) = file_pattern {
+                        let name = e.file_name().to_string_lossy();
+                        glob_match(fp, &name)
+                    } else {
+                        true
+                    }
+                })
+                .map(|e| e.path().to_path_buf())
+                .collect()
+        };
+
+        'outer: for file_path in &files {
+            let content = match fs::read_to_string(file_path) {
+                Ok(c) => c,
+                Err(_) => continue, // skip binary/unreadable files
+            };
+
+            let lines: Vec<&str> = content.lines().collect();
+
+            for (line_num, line) in lines.iter().enumerate() {
+                if re.is_match(line) {
+                    if matches.len() >= max_results {
+                        break 'outer;
+                    }
+
+                    let mut context_before = Vec::new();
+                    let mut context_after = Vec::new();
+
+                    if context_lines > 0 {
+                        let start = line_num.saturating_sub(context_lines);
+                        for line in &lines[start..line_num] {
+                            context_before.push(*line);
+                        }
+                        let end = (line_num + 1 + context_lines).min(lines.len());
+                        for line in &lines[(line_num + 1)..end] {
+                            context_after.push(*line);
                         }
                     }
+
+                    let mut entry = json!({
+                        "file": file_path.to_string_lossy(),
+                        "line": line_num + 1,
+                        "content": line
+                    });
+
+                    if context_lines > 0 {
+                        entry["context_before"] = json!(context_before);
+                        entry["context_after"] = json!(context_after);
+                    }
+
+                    provenance::read(file_path);
+                    matches.push(entry);
                 }
             }
+        }
 
-            all_lines.join("\n") + "\n"
-        } else {
-            content.to_string()
-        };
-
-        fs::write(path, &final_content)
-            .with_context(|| format!("Failed to write file: {}", path))?;
+        let truncated = matches.len() >= max_results;
 
         Ok(json!({
-            "success": true,
-            "path": path,
-            "bytes_written": final_content.len()
+            "matches": matches,
+            "count": matches.len(),
+            "files_searched": files.len(),
+            "pattern": pattern,
+            "truncated": truncated
         }))
     }
 
-    pub async fn move_file(&self, args: Value) -> Result<Value> {
-        let source = args["source"].as_str().context("Missing 'source' parameter")?;
-        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+    pub async fn tail(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let max_bytes = args["max_bytes"].as_u64().unwrap_or(TAIL_MAX_BYTES as u64).max(1) as usize;
+        let follow = args["follow"].as_f64().unwrap_or(0.0);
+        if !(0.0..=watches::MAX_WAIT.as_secs_f64()).contains(&follow) {
+            return Err(PolyError::InvalidArguments(format!(
+                "follow must be between 0 and {} seconds",
+                watches::MAX_WAIT.as_secs()
+            ))
+            .into());
+        }
+        let until = args["until"]
+            .as_str()
+            .map(|pattern| Regex::new(pattern).with_context(|| format!("Invalid regex: {}", pattern)))
+            .transpose()?;
+        let given = ["lines", "bytes", "offset"].iter().filter(|key| !args[**key].is_null()).count();
+        if given > 1 {
+            return Err(PolyError::InvalidArguments("Pass only one of lines, bytes and offset".to_string()).into());
+        }
 
-        fs::rename(source, destination)
-            .with_context(|| format!("Failed to move from {} to {}", source, destination))?;
+        let mut file = fs::File::open(path).with_context(|| format!("Failed to read file: {}", path))?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let start = if let Some(offset) = args["offset"].as_u64() {
+            if offset > size { 0 } else { offset }
+        } else if let Some(bytes) = args["bytes"].as_u64() {
+            size.saturating_sub(bytes)
+        } else {
+            tail_start(&mut file, size, args["lines"].as_u64().unwrap_or(20) as usize)?
+        };
+        let (bytes, from) = read_span(&mut file, start, size, max_bytes)?;
+        let content = String::from_utf8_lossy(&bytes);
 
-        Ok(json!({
-            "success": true,
-            "source": source,
-            "destination": destination
-        }))
+        let mut result = json!({
+            "path": path,
+            "content": content,
+            "lines_returned": content.lines().count(),
+            "size": size,
+            "start_offset": from,
+            "next_offset": from + bytes.len() as u64,
+            "truncated": from > start,
+        });
+        if follow > 0.0 {
+            let followed = follow_file(path, &metadata, from + bytes.len() as u64, follow, until.as_ref(), max_bytes).await?;
+            let appended = String::from_utf8_lossy(&followed.appended);
+            result["appended"] = json!(appended);
+            result["appended_lines"] = json!(appended.lines().count());
+            result["appended_bytes"] = json!(followed.total);
+            result["appended_truncated"] = json!(followed.total > followed.appended.len() as u64);
+            result["matched"] = json!(followed.matched);
+            result["rotations"] = json!(followed.rotations);
+            result["followed_secs"] = json!((followed.elapsed * 10.0).round() / 10.0);
+            result["next_offset"] = json!(followed.offset);
+        }
+        Ok(result)
     }
 
-    pub async fn copy(&self, args: Value) -> Result<Value> {
-        let source = args["source"].as_str().context("Missing 'source' parameter")?;
-        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+    pub async fn replace(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let find = args["find"].as_str().context("Missing 'find' parameter")?;
+        let replace_with = args["replace"].as_str().context("Missing 'replace' parameter")?;
+        let use_regex = args["regex"].as_bool().unwrap_or(false);
+        let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+        let file_pattern = args["file_pattern"].as_str();
+        let txn = self.txn(&args)?;
 
-        let source_path = Path::new(source);
+        let root = Path::new(path);
+
+        let files: Vec<PathBuf> = if root.is_file() || (!root.is_dir() && txn.is_some()) {
+            vec![root.to_path_buf()]
+        } else if root.is_dir() {
+            let fp = file_pattern.context("'file_pattern' is required when path is a directory")?;
+            WalkDir::new(root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file() && self.is_allowed(e.path()))
+                .filter(|e| glob_match(fp, &e.file_name().to_string_lossy()))
+                .map(|e| e.path().to_path_buf())
+                .collect()
+        } else {
+            anyhow::bail!("Path does not exist: {}", path);
+        };
+
+        let re = if use_regex {
+            Some(Regex::new(find).with_context(|| format!("Invalid regex: {}", find))?)
+        } else {
+            None
+        };
+
+        let mut results = Vec::new();
+        let mut total_replacements = 0usize;
+
+        for file_path in &files {
+            let content = match self.read_text(txn, &file_path.to_string_lossy()) {
+                Ok(Some(c)) => c,
+                Ok(None) if files.len() == 1 => anyhow::bail!("Path does not exist: {}", path),
+                _ => continue,
+            };
+
+            let (new_content, count) = if let Some(ref re) = re {
+                let count = re.find_iter(&content).count();
+                let replaced = re.replace_all(&content, replace_with).to_string();
+                (replaced, count)
+            } else {
+                let count = content.matches(find).count();
+                let replaced = content.replace(find, replace_with);
+                (replaced, count)
+            };
 
-        if source_path.is_file() {
-            fs::copy(source, destination)
-                .with_context(|| format!("Failed to copy file from {} to {}", source, destination))?;
-        } else if source_path.is_dir() {
-            copy_dir_all(source, destination)
-                .with_context(|| format!("Failed to copy directory from {} to {}", source, destination))?;
-        } else {
-            anyhow::bail!("Source path does not exist: {}", source);
+            if count > 0 {
+                if !dry_run {
+                    match txn {
+                        Some((transactions, id)) => transactions.write(id, file_path, new_content)?,
+                        None => fs::write(file_path, &new_content)
+                            .with_context(|| format!("Failed to write: {}", file_path.display()))?,
+                    }
+                }
+                total_replacements += count;
+                results.push(json!({
+                    "file": file_path.to_string_lossy(),
+                    "replacements": count
+                }));
+            }
         }
 
-        Ok(json!({
+        let mut result = json!({
             "success": true,
-            "source": source,
-            "destination": destination
-        }))
+            "dry_run": dry_run,
+            "files_modified": results.len(),
+            "total_replacements": total_replacements,
+            "files_searched": files.len(),
+            "details": results
+        });
+        if let Some((_, id)) = txn {
+            result["txn"] = json!(id);
+        }
+        Ok(result)
     }
 
-    pub async fn create(&self, args: Value) -> Result<Value> {
+    pub async fn edit(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let type_str = args["type"].as_str().context("Missing 'type' parameter")?;
-
-        match type_str {
-            "file" => {
-                fs::File::create(path)
-                    .with_context(|| format!("Failed to create file: {}", path))?;
+        self.check_allowed(path)?;
+        let fuzzy = match args["mode"].as_str().unwrap_or("exact") {
+            "exact" => false,
+            "fuzzy" => true,
+            other => anyhow::bail!("Invalid mode '{}': expected exact or fuzzy", other),
+        };
+        let preview = args["preview"].as_bool().unwrap_or(false);
+        let txn = self.txn(&args)?;
+        let content = self
+            .read_text(txn, path)?
+            .with_context(|| format!("File not found: {}", path))?;
+
+        let (new_content, report_key, report) = match (args["edits"].as_array(), args["patch"].as_str()) {
+            (Some(edits), None) => {
+                let (new_content, report) = search_replace(&content, edits, fuzzy)?;
+                (new_content, "edits", report)
             }
-            "dir" => {
-                fs::create_dir_all(path)
-                    .with_context(|| format!("Failed to create directory: {}", path))?;
+            (None, Some(diff)) => {
+                let hunks = patch::parse(diff)?;
+                if hunks.is_empty() {
+                    anyhow::bail!("Patch contains no hunks (expected lines starting with @@)");
+                }
+                let patched = patch::apply(&content, &hunks, None, fuzzy);
+                if patched.failed > 0 {
+                    return Err(PolyError::Conflict(format!(
+                        "{} of {} hunks do not match {}; nothing was changed",
+                        patched.failed,
+                        hunks.len(),
+                        path
+                    ))
+                    .with_data(json!({ "hunks": patched.hunks })));
+                }
+                (patched.text, "hunks", patched.hunks)
             }
-            _ => anyhow::bail!("Invalid type: {}. Must be 'file' or 'dir'", type_str),
-        }
+            _ => anyhow::bail!("Invalid arguments: give either 'edits' or 'patch'"),
+        };
 
-        Ok(json!({
+        let changed = new_content != content;
+        let mut result = json!({
             "success": true,
             "path": path,
-            "type": type_str
-        }))
-    }
-
-    pub async fn delete(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let path_obj = Path::new(path);
-
-        if path_obj.is_file() {
-            fs::remove_file(path)
-                .with_context(|| format!("Failed to delete file: {}", path))?;
-        } else if path_obj.is_dir() {
-            fs::remove_dir_all(path)
-                .with_context(|| format!("Failed to delete directory: {}", path))?;
-        } else {
-            anyhow::bail!("Path does not exist: {}", path);
+            "mode": if fuzzy { "fuzzy" } else { "exact" },
+            "preview": preview,
+            "changed": changed,
+        });
+        result[report_key] = json!(report);
+        if preview {
+            result["diff"] = json!(similar::TextDiff::from_lines(&content, &new_content)
+                .unified_diff()
+                .header(path, path)
+                .to_string());
+            return Ok(result);
+        }
+        if !changed {
+            return Ok(result);
         }
 
-        Ok(json!({
-            "success": true,
-            "path": path
-        }))
+        self.check_unchanged(&args, txn, path, new_content.as_bytes())?;
+        match txn {
+            Some((transactions, id)) => {
+                transactions.write(id, Path::new(path), new_content)?;
+                result["txn"] = json!(id);
+            }
+            None => {
+                fs::write(path, &new_content).with_context(|| format!("Failed to write file: {}", path))?;
+                result["bytes_written"] = json!(new_content.len());
+            }
+        }
+        Ok(result)
     }
 
-    pub async fn move_desktop(&self, args: Value) -> Result<Value> {
-        let item = args["item"].as_str().context("Missing 'item' parameter")?;
-        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
-
-        // Get Desktop path
-        let desktop = dirs::desktop_dir()
-            .context("Could not find Desktop directory")?;
-
-        let source_path = desktop.join(item);
-        let dest_path = desktop.join(destination).join(item);
+    pub async fn filetype(&self, args: Value) -> Result<Value> {
+        use std::io::Read;
 
-        // Create destination directory if it doesn't exist
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent)?;
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for: {}", path))?;
+        if metadata.is_dir() {
+            anyhow::bail!("Invalid path: {} is a directory", path);
         }
 
-        fs::rename(&source_path, &dest_path)
-            .with_context(|| format!("Failed to move {} to {}", source_path.display(), dest_path.display()))?;
+        let mut head = Vec::new();
+        fs::File::open(path)
+            .and_then(|file| file.take(filetype::SNIFF_BYTES as u64).read_to_end(&mut head))
+            .with_context(|| format!("Failed to read file: {}", path))?;
+        let detected = filetype::detect(Path::new(path), &head);
 
         Ok(json!({
-            "success": true,
-            "item": item,
-            "from": source_path,
-            "to": dest_path
+            "path": path,
+            "mime": detected.mime,
+            "text": detected.text,
+            "binary": !detected.text,
+            "encoding": detected.encoding,
+            "language": detected.language,
+            "line_endings": detected.line_endings,
+            "extension": Path::new(path).extension().and_then(|e| e.to_str()),
+            "size": metadata.len()
         }))
     }
 
-    pub async fn find(&self, args: Value) -> Result<Value> {
-        let root_path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
-        let search_type = args["type"].as_str().unwrap_or("all");
-        let max_results = args["max_results"].as_u64().unwrap_or(1000) as usize;
-
-        let mut walker = WalkDir::new(root_path);
-        if let Some(depth) = args["max_depth"].as_u64() {
-            walker = walker.max_depth(depth as usize);
+    pub async fn normalize(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        let line_endings = match args["line_endings"].as_str().unwrap_or("lf") {
+            "keep" => None,
+            ending @ ("lf" | "crlf") => Some(ending),
+            other => anyhow::bail!("Invalid line_endings '{}': expected lf, crlf or keep", other),
+        };
+        let bom = args["bom"].as_str().unwrap_or("strip");
+        if !matches!(bom, "strip" | "add" | "keep") {
+            anyhow::bail!("Invalid bom '{}': expected strip, add or keep", bom);
         }
+        let final_newline = args["final_newline"].as_bool().unwrap_or(true);
+        let file_pattern = args["file_pattern"].as_str();
+        let include_ignored = args["include_ignored"].as_bool().unwrap_or(false);
+        let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+        let txn = self.txn(&args)?;
 
-        // Determine if pattern is a glob (contains * or ?)
-        let is_glob = pattern.contains('*') || pattern.contains('?');
-
-        let mut results = Vec::new();
-
-        for entry in walker.into_iter().filter_map(|e| e.ok()) {
-            if results.len() >= max_results {
-                break;
-            }
-
-            let path = entry.path();
-            let file_name = entry.file_name().to_string_lossy();
+        let root = Path::new(path);
+        let files: Vec<PathBuf> = if root.is_file() || (!root.is_dir() && txn.is_some()) {
+            vec![root.to_path_buf()]
+        } else if root.is_dir() {
+            walk_files(root, include_ignored)
+                .filter(|file| file_pattern.is_none_or(|fp| glob_match(fp, &file_name(file))))
+                .collect()
+        } else {
+            anyhow::bail!("Path does not exist: {}", path);
+        };
 
-            let matches = if is_glob {
-                glob_match(pattern, &file_name)
-            } else {
-                file_name.contains(pattern) || path.to_string_lossy().contains(pattern)
+        let mut changed = Vec::new();
+        let mut skipped = Vec::new();
+        for file_path in &files {
+            let name = file_path.to_string_lossy();
+            let content = match self.read_text(txn, &name) {
+                Ok(Some(content)) => content,
+                Ok(None) if files.len() == 1 => anyhow::bail!("Path does not exist: {}", path),
+                Ok(None) => continue,
+                Err(_) => {
+                    let reason = match fs::read(file_path) {
+                        Ok(bytes) if is_text(file_path, &bytes) => "not UTF-8 text",
+                        Ok(_) => "binary",
+                        Err(_) => "unreadable",
+                    };
+                    skipped.push(json!({ "file": name, "reason": reason }));
+                    continue;
+                }
             };
-
-            if !matches {
+            if !is_text(file_path, content.as_bytes()) {
+                skipped.push(json!({ "file": name, "reason": "binary" }));
                 continue;
             }
 
-            // Type filtering
-            match search_type {
-                "file" if !path.is_file() => continue,
-                "dir" if !path.is_dir() => continue,
-                _ => {}
+            let (normalized, changes) = normalize_text(&content, line_endings, bom, final_newline);
+            if changes.is_empty() {
+                continue;
             }
-
-            let size = path.metadata().map(|m| m.len()).unwrap_or(0);
-
-            results.push(json!({
-                "path": path.to_string_lossy(),
-                "name": file_name,
-                "type": if path.is_file() { "file" } else { "dir" },
-                "size": size
-            }));
+            if !dry_run {
+                match txn {
+                    Some((transactions, id)) => transactions.write(id, file_path, normalized)?,
+                    None => fs::write(file_path, &normalized)
+                        .with_context(|| format!("Failed to write: {}", file_path.display()))?,
+                }
+            }
+            changed.push(json!({ "file": name, "changes": changes }));
         }
 
-        let truncated = results.len() >= max_results;
-
-        Ok(json!({
-            "results": results,
-            "count": results.len(),
-            "truncated": truncated
-        }))
+        let mut result = json!({
+            "success": true,
+            "dry_run": dry_run,
+            "files_checked": files.len(),
+            "files_changed": changed.len(),
+            "changed": changed,
+            "skipped": skipped
+        });
+        if let Some((_, id)) = txn {
+            result["txn"] = json!(id);
+        }
+        Ok(result)
     }
 
-    pub async fn ld(&self, args: Value) -> Result<Value> {
+    pub async fn loc(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let path_obj = Path::new(path);
-
-        if !path_obj.exists() {
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let include_ignored = args["include_ignored"].as_bool().unwrap_or(false);
+        if !Path::new(path).exists() {
             anyhow::bail!("Path does not exist: {}", path);
         }
 
-        let mut entries = Vec::new();
-
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let metadata = entry.metadata()?;
-            let file_name = entry.file_name().to_string_lossy().to_string();
-
-            #[cfg(unix)]
-            use std::os::unix::fs::PermissionsExt;
-
-            #[cfg(unix)]
-            let permissions = format!("{:o}", metadata.permissions().mode() & 0o777);
-
-            #[cfg(not(unix))]
-            let permissions = if metadata.permissions().readonly() {
-                "r--".to_string()
-            } else {
-                "rw-".to_string()
+        let mut languages: HashMap<&str, LineCounts> = HashMap::new();
+        let mut unrecognized = 0;
+        for file in walk_files(Path::new(path), include_ignored) {
+            let Ok(content) = fs::read(&file) else {
+                continue;
+            };
+            let detected = filetype::detect(&file, &content[..content.len().min(filetype::SNIFF_BYTES)]);
+            let (true, Some(language)) = (detected.text, detected.language) else {
+                unrecognized += 1;
+                continue;
             };
+            let counts = languages.entry(language).or_default();
+            counts.files += 1;
+            count_lines(&String::from_utf8_lossy(&content), language, counts);
+        }
 
-            entries.push(json!({
-                "name": file_name,
-                "type": if metadata.is_file() { "file" } else if metadata.is_dir() { "dir" } else { "other" },
-                "size": metadata.len(),
-                "permissions": permissions,
-                "modified": metadata.modified().ok().and_then(|t| {
-                    t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+        let mut languages: Vec<(&str, LineCounts)> = languages.into_iter().collect();
+        languages.sort_by(|a, b| b.1.code.cmp(&a.1.code).then(a.0.cmp(b.0)));
+        let mut total = LineCounts::default();
+        let rows: Vec<Value> = languages
+            .iter()
+            .map(|(language, counts)| {
+                total.files += counts.files;
+                total.code += counts.code;
+                total.comments += counts.comments;
+                total.blanks += counts.blanks;
+                json!({
+                    "language": language,
+                    "files": counts.files,
+                    "lines": counts.code + counts.comments + counts.blanks,
+                    "code": counts.code,
+                    "comments": counts.comments,
+                    "blanks": counts.blanks
                 })
-            }));
-        }
+            })
+            .collect();
 
         Ok(json!({
             "path": path,
-            "entries": entries,
-            "count": entries.len()
+            "languages": rows,
+            "total": {
+                "files": total.files,
+                "lines": total.code + total.comments + total.blanks,
+                "code": total.code,
+                "comments": total.comments,
+                "blanks": total.blanks
+            },
+            "unrecognized_files": unrecognized
         }))
     }
 
-    pub async fn stat(&self, args: Value) -> Result<Value> {
+    pub async fn hash(&self, args: Value) -> Result<Value> {
+        if let Some(cursor) = args["cursor"].as_str() {
+            let (files, next_cursor) = self.pages.next(cursor)?;
+            return Ok(json!({
+                "files": files,
+                "next_cursor": next_cursor
+            }));
+        }
+
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let metadata = fs::metadata(path)
-            .with_context(|| format!("Failed to get metadata for: {}", path))?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let (named, expected) = match args["expected"].as_str().map(str::trim) {
+            Some(expected) => match expected.split_once(':') {
+                Some((algorithm, hex)) => (Some(algorithm.to_ascii_lowercase()), Some(hex.to_ascii_lowercase())),
+                None => (None, Some(expected.to_ascii_lowercase())),
+            },
+            None => (None, None),
+        };
+        let algorithm = match (args["algorithm"].as_str(), named.as_deref()) {
+            (Some(given), Some(named)) if given != named => {
+                return Err(PolyError::InvalidArguments(format!(
+                    "Invalid expected: it is a {} checksum, but algorithm is {}",
+                    named, given
+                ))
+                .into());
+            }
+            (Some(given), _) => given.to_string(),
+            (None, named) => named.unwrap_or("sha256").to_string(),
+        };
+        Checksum::new(&algorithm)?;
 
-        #[cfg(unix)]
-        use std::os::unix::fs::PermissionsExt;
+        let root = Path::new(path);
+        let metadata = fs::metadata(root).with_context(|| format!("Path does not exist: {}", path))?;
+        let total = progress::is_streaming().then(|| if metadata.is_dir() { tree_size(root) } else { metadata.len() });
+        let mut done = 0;
+        let mut on_read = |message: &Path, read: u64| {
+            done += read;
+            if let Some(total) = total {
+                progress::report(done, Some(total.max(done)), &format!("Hashing {}", message.display()));
+            }
+        };
 
-        #[cfg(unix)]
-        let permissions = format!("{:o}", metadata.permissions().mode() & 0o777);
+        if !metadata.is_dir() {
+            let hash = checksum_file(root, &algorithm, |read| on_read(root, read))?;
+            let mut result = json!({
+                "path": path,
+                "type": "file",
+                "algorithm": algorithm,
+                "hash": hash,
+                "size": metadata.len()
+            });
+            if let Some(expected) = expected {
+                result["matches"] = json!(expected == hash);
+            }
+            return Ok(result);
+        }
 
-        #[cfg(not(unix))]
-        let permissions = if metadata.permissions().readonly() {
-            "readonly".to_string()
-        } else {
-            "read-write".to_string()
-        };
+        let mut files: Vec<(String, PathBuf)> = matching_files(&args, path)
+            .map(|file| {
+                let relative = file.strip_prefix(root).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+                (relative, file)
+            })
+            .collect();
+        files.sort();
+
+        // The tree digest hashes lines like `sha256sum` prints them, so it changes
+        // when any file's content or name does
+        let mut digest = Checksum::new(&algorithm)?;
+        let mut listed = Vec::new();
+        let mut unreadable = Vec::new();
+        let mut total_size = 0;
+        for (relative, file) in files {
+            let size = fs::metadata(&file).map(|m| m.len()).unwrap_or(0);
+            match checksum_file(&file, &algorithm, |read| on_read(&file, read)) {
+                Ok(hash) => {
+                    digest.update(format!("{}  {}\n", hash, relative).as_bytes());
+                    total_size += size;
+                    listed.push(json!({
+                        "path": relative,
+                        "hash": hash,
+                        "size": size
+                    }));
+                }
+                Err(e) => unreadable.push(json!({
+                    "path": relative,
+                    "error": format!("{:#}", e)
+                })),
+            }
+        }
+        let hash = digest.finish();
 
-        Ok(json!({
+        let file_count = listed.len();
+        let (files, next_cursor) = match args["page_size"].as_u64() {
+            Some(page_size) => self.pages.first(listed, page_size as usize),
+            None => (listed, None),
+        };
+        let mut result = json!({
             "path": path,
-            "type": if metadata.is_file() { "file" } else if metadata.is_dir() { "dir" } else { "other" },
-            "size": metadata.len(),
-            "permissions": permissions,
-            "readonly": metadata.permissions().readonly(),
-            "created": metadata.created().ok().and_then(|t| {
-                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
-            }),
-            "modified": metadata.modified().ok().and_then(|t| {
-                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
-            }),
-            "accessed": metadata.accessed().ok().and_then(|t| {
-                t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
-            })
-        }))
+            "type": "directory",
+            "algorithm": algorithm,
+            "hash": hash,
+            "file_count": file_count,
+            "total_size": total_size,
+            "files": files,
+            "next_cursor": next_cursor
+        });
+        if !unreadable.is_empty() {
+            result["unreadable"] = json!(unreadable);
+        }
+        if let Some(expected) = expected {
+            result["matches"] = json!(expected == hash);
+        }
+        Ok(result)
     }
 
-    pub async fn permissions(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-
-        if let Some(mode_str) = args["mode"].as_str() {
-            // Set permissions
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mode = u32::from_str_radix(mode_str, 8)
-                    .with_context(|| format!("Invalid permission mode: {}", mode_str))?;
-                let permissions = std::fs::Permissions::from_mode(mode);
-                fs::set_permissions(path, permissions)
-                    .with_context(|| format!("Failed to set permissions for: {}", path))?;
-
-                Ok(json!({
-                    "success": true,
-                    "path": path,
-                    "mode": mode_str
-                }))
+    pub async fn diff(&self, args: Value) -> Result<Value> {
+        let old = args["old"].as_str().context("Missing 'old' parameter")?;
+        let new = args["new"].as_str().context("Missing 'new' parameter")?;
+        self.check_allowed(old)?;
+        self.check_allowed(new)?;
+        provenance::read(old);
+        provenance::read(new);
+        let context_lines = args["context_lines"].as_u64().unwrap_or(3) as usize;
+        let mut budget = args["max_bytes"].as_u64().unwrap_or(DIFF_MAX_BYTES as u64) as usize;
+
+        let old_metadata = fs::metadata(old).with_context(|| format!("Path does not exist: {}", old))?;
+        let new_metadata = fs::metadata(new).with_context(|| format!("Path does not exist: {}", new))?;
+        match (old_metadata.is_dir(), new_metadata.is_dir()) {
+            (false, false) => {
+                let mut result = json!({
+                    "old": old,
+                    "new": new,
+                    "type": "file"
+                });
+                match diff_files(Path::new(old), Path::new(new), (old, new), context_lines, Some(&mut budget))? {
+                    Some(changes) => {
+                        result["identical"] = json!(false);
+                        if let (Value::Object(result), Value::Object(changes)) = (&mut result, changes) {
+                            result.extend(changes);
+                        }
+                    }
+                    None => {
+                        result["identical"] = json!(true);
+                    }
+                }
+                return Ok(result);
             }
+            (true, true) => {}
+            _ => anyhow::bail!("Cannot compare a file with a directory: {} and {}", old, new),
+        }
 
-            #[cfg(not(unix))]
-            {
-                anyhow::bail!("Setting permissions is only supported on Unix systems");
+        let relative_files = |root: &str| -> HashMap<String, PathBuf> {
+            matching_files(&args, root)
+                .map(|file| {
+                    let relative = file.strip_prefix(root).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+                    (relative, file)
+                })
+                .collect()
+        };
+        let old_files = relative_files(old);
+        let new_files = relative_files(new);
+        let include_diffs = args["include_diffs"].as_bool().unwrap_or(false);
+
+        let mut removed: Vec<&String> = old_files.keys().filter(|path| !new_files.contains_key(*path)).collect();
+        let mut added: Vec<&String> = new_files.keys().filter(|path| !old_files.contains_key(*path)).collect();
+        let mut both: Vec<&String> = old_files.keys().filter(|path| new_files.contains_key(*path)).collect();
+        removed.sort();
+        added.sort();
+        both.sort();
+
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for path in both {
+            let labels = (format!("a/{}", path), format!("b/{}", path));
+            let budget = include_diffs.then_some(&mut budget);
+            match diff_files(&old_files[path], &new_files[path], (&labels.0, &labels.1), context_lines, budget)? {
+                Some(mut changes) => {
+                    changes["path"] = json!(path);
+                    changed.push(changes);
+                }
+                None => unchanged += 1,
+            }
+        }
+
+        Ok(json!({
+            "old": old,
+            "new": new,
+            "type": "directory",
+            "identical": added.is_empty() && removed.is_empty() && changed.is_empty(),
+            "added": added,
+            "removed": removed,
+            "changed": changed,
+            "summary": {
+                "added": added.len(),
+                "removed": removed.len(),
+                "changed": changed.len(),
+                "unchanged": unchanged
             }
-        } else {
-            // Get permissions
-            let metadata = fs::metadata(path)?;
+        }))
+    }
 
-            #[cfg(unix)]
-            use std::os::unix::fs::PermissionsExt;
+    pub async fn archive(&self, args: Value) -> Result<Value> {
+        let source = args["source"].as_str().context("Missing 'source' parameter")?;
+        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+        self.check_allowed(source)?;
+        self.check_allowed(destination)?;
+        let format = match args["format"].as_str() {
+            Some(format) => archive::Format::parse(format)?,
+            None => archive::Format::from_name(Path::new(destination)).ok_or_else(|| {
+                PolyError::InvalidArguments(format!(
+                    "Invalid destination '{}': pass 'format' or use a .zip, .tar, .tar.gz or .tar.zst name",
+                    destination
+                ))
+            })?,
+        };
+        let filter = archive::Filter::new(&string_list(&args["include"]), &string_list(&args["exclude"]))?;
+        let password = args["password"].as_str().filter(|password| !password.is_empty());
+        let overwrite = args["overwrite"].as_bool().unwrap_or(false);
 
-            #[cfg(unix)]
-            let mode = format!("{:o}", metadata.permissions().mode() & 0o777);
+        let source_path = Path::new(source);
+        if source_path.symlink_metadata().is_err() {
+            anyhow::bail!("Source path does not exist: {}", source);
+        }
+        let destination_path = Path::new(destination);
+        if destination_path.exists() && !overwrite {
+            anyhow::bail!("Destination already exists: {} (pass overwrite to replace it)", destination);
+        }
+        if source_path.is_dir() && within(&resolve_path(destination_path), &resolve_path(source_path)) {
+            anyhow::bail!("Destination {} is inside the source directory", destination);
+        }
+        provenance::read(source);
 
-            #[cfg(not(unix))]
-            let mode = if metadata.permissions().readonly() {
-                "readonly"
-            } else {
-                "read-write"
-            };
+        let tally = archive::create(source_path, destination_path, format, &filter, password)?;
+        let archive_size = fs::metadata(destination_path).map(|m| m.len()).unwrap_or(0);
+        Ok(json!({
+            "success": true,
+            "source": source,
+            "destination": destination,
+            "format": format.name(),
+            "files": tally.files,
+            "directories": tally.directories,
+            "symlinks": tally.symlinks,
+            "excluded": tally.excluded,
+            "total_size": tally.total_size,
+            "archive_size": archive_size,
+            "encrypted": password.is_some()
+        }))
+    }
 
-            Ok(json!({
-                "path": path,
-                "mode": mode,
-                "readonly": metadata.permissions().readonly()
-            }))
+    pub async fn extract(&self, args: Value) -> Result<Value> {
+        let source = args["source"].as_str().context("Missing 'source' parameter")?;
+        let destination = args["destination"].as_str().context("Missing 'destination' parameter")?;
+        self.check_allowed(source)?;
+        self.check_allowed(destination)?;
+        let source_path = Path::new(source);
+        if !source_path.is_file() {
+            anyhow::bail!("Archive does not exist: {}", source);
+        }
+        let format = match args["format"].as_str() {
+            Some(format) => archive::Format::parse(format)?,
+            None => match archive::Format::from_name(source_path) {
+                Some(format) => format,
+                None => archive::Format::sniff(source_path)?
+                    .with_context(|| format!("Cannot tell the format of {}; pass 'format'", source))?,
+            },
+        };
+        let filter = archive::Filter::new(&string_list(&args["include"]), &string_list(&args["exclude"]))?;
+        let password = args["password"].as_str().filter(|password| !password.is_empty());
+        let overwrite = args["overwrite"].as_bool().unwrap_or(false);
+        let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+        provenance::read(source);
+
+        let extracted = archive::extract(source_path, Path::new(destination), format, &filter, password, overwrite, dry_run)?;
+        let mut result = json!({
+            "success": true,
+            "source": source,
+            "destination": destination,
+            "format": format.name(),
+            "dry_run": dry_run
+        });
+        if let (Value::Object(result), Value::Object(extracted)) = (&mut result, serde_json::to_value(extracted)?) {
+            result.extend(extracted);
         }
+        Ok(result)
     }
 
-    pub async fn watch(&self, args: Value) -> Result<Value> {
+    pub async fn recent(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let duration = args["duration"].as_u64().unwrap_or(60);
-
-        let (tx, rx) = std::sync::mpsc::channel();
-        let mut watcher = notify::recommended_watcher(tx)?;
-
-        watcher.watch(Path::new(path), RecursiveMode::Recursive)?;
-
-        let mut events = Vec::new();
-        let start = std::time::Instant::now();
-
-        while start.elapsed().as_secs() < duration {
-            if let Ok(Ok(event)) = rx.recv_timeout(std::time::Duration::from_secs(1)) {
-                events.push(json!({
-                    "kind": format!("{:?}", event.kind),
-                    "paths": event.paths.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>()
-                }));
-            }
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let minutes = args["minutes"].as_f64().unwrap_or(60.0);
+        if !(minutes > 0.0 && minutes.is_finite()) {
+            anyhow::bail!("Invalid minutes: {}", minutes);
+        }
+        let limit = args["limit"].as_u64().unwrap_or(50) as usize;
+        if !Path::new(path).exists() {
+            anyhow::bail!("Path does not exist: {}", path);
         }
 
+        let now = std::time::SystemTime::now();
+        let window = std::time::Duration::from_secs_f64(minutes * 60.0);
+        let mut files: Vec<(PathBuf, fs::Metadata, std::time::Duration)> = matching_files(&args, path)
+            .filter_map(|file| {
+                let metadata = fs::metadata(&file).ok()?;
+                // Files stamped in the future count as just modified
+                let age = now.duration_since(metadata.modified().ok()?).unwrap_or_default();
+                (age <= window).then_some((file, metadata, age))
+            })
+            .collect();
+        let found = files.len();
+        files.sort_by_key(|(_, _, age)| *age);
+        files.truncate(limit);
+
+        let files: Vec<Value> = files
+            .into_iter()
+            .map(|(file, metadata, age)| {
+                json!({
+                    "path": file.to_string_lossy(),
+                    "size": metadata.len(),
+                    "modified": metadata.modified().ok().and_then(|t| {
+                        t.duration_since(std::time::UNIX_EPOCH).ok().map(|d| d.as_secs())
+                    }),
+                    "age_secs": age.as_secs()
+                })
+            })
+            .collect();
         Ok(json!({
             "path": path,
-            "duration": duration,
-            "events": events,
-            "event_count": events.len()
+            "minutes": minutes,
+            "files": files,
+            "count": files.len(),
+            "total_found": found
         }))
     }
 
-    pub async fn snapshot(&self, args: Value) -> Result<Value> {
+    pub async fn largest(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let max_snapshots = args["max_snapshots"].as_u64().unwrap_or(10) as usize;
-
-        let path_obj = Path::new(path);
-        if !path_obj.exists() {
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let limit = args["limit"].as_u64().unwrap_or(20) as usize;
+        if !Path::new(path).exists() {
             anyhow::bail!("Path does not exist: {}", path);
         }
 
-        // Create snapshot directory
-        let snapshot_dir = path_obj.parent()
-            .unwrap_or_else(|| Path::new("."))
-            .join(".snapshots")
-            .join(path_obj.file_name().unwrap_or(path_obj.as_os_str()));
-
-        fs::create_dir_all(&snapshot_dir)?;
-
-        // Create timestamp
-        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let snapshot_name = format!("snapshot_{}", timestamp);
-        let snapshot_path = snapshot_dir.join(&snapshot_name);
+        let mut scanned = 0;
+        let mut total_size = 0;
+        let mut files: Vec<(PathBuf, u64)> = matching_files(&args, path)
+            .filter_map(|file| {
+                let size = fs::metadata(&file).ok()?.len();
+                scanned += 1;
+                total_size += size;
+                Some((file, size))
+            })
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files.truncate(limit);
+
+        let files: Vec<Value> = files
+            .into_iter()
+            .map(|(file, size)| {
+                json!({
+                    "path": file.to_string_lossy(),
+                    "size": size,
+                    "size_human": format_size(size)
+                })
+            })
+            .collect();
+        Ok(json!({
+            "path": path,
+            "files": files,
+            "files_scanned": scanned,
+            "total_size": total_size,
+            "total_size_human": format_size(total_size)
+        }))
+    }
 
-        // Copy the file/directory
-        if path_obj.is_file() {
-            fs::copy(path, &snapshot_path)?;
-        } else {
-            copy_dir_all(path, &snapshot_path)?;
+    pub async fn du(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        self.check_allowed(path)?;
+        provenance::read(path);
+        let limit = args["limit"].as_u64().unwrap_or(20) as usize;
+        let depth = (args["depth"].as_u64().unwrap_or(3) as usize).max(1);
+        let by_disk = match args["by"].as_str() {
+            None | Some("size") => false,
+            Some("disk_usage") => true,
+            Some(other) => anyhow::bail!("Invalid by '{}': expected size or disk_usage", other),
+        };
+        let filter = archive::Filter::new(&[], &string_list(&args["exclude"]))?;
+        let root = Path::new(path);
+        if !fs::metadata(root).with_context(|| format!("Path does not exist: {}", path))?.is_dir() {
+            anyhow::bail!("Not a directory: {}", path);
         }
 
-        // Store snapshot info
-        let mut snapshots = self.snapshots.lock().unwrap();
-        let key = path.to_string();
-        let snapshot_list = snapshots.entry(key.clone()).or_default();
-
-        snapshot_list.push(SnapshotInfo {
-            timestamp: timestamp.clone(),
-            path: snapshot_path.clone(),
-            compressed: false,
+        let mut total = Usage::default();
+        // Subdirectories down to `depth`, by path relative to the root
+        let mut directories: HashMap<PathBuf, Usage> = HashMap::new();
+        // The root's own files; its subdirectories are added from `directories`
+        let mut children = Vec::new();
+        // The `limit` largest files anywhere, smallest on top
+        let mut largest = std::collections::BinaryHeap::new();
+        let mut hard_links = std::collections::HashSet::new();
+        let (mut directory_count, mut excluded, mut unreadable) = (0, 0, 0);
+
+        let walker = WalkDir::new(root).follow_links(false).into_iter().filter_entry(|entry| {
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+            let skip = entry.depth() > 0 && (filter.excludes(&relative) || !self.is_allowed(entry.path()));
+            excluded += usize::from(skip);
+            !skip
         });
-
-        // Manage snapshots (compress old ones, delete oldest)
-        if snapshot_list.len() > max_snapshots {
-            // Compress older snapshots
-            for snapshot in snapshot_list.iter_mut().rev().skip(3) {
-                if !snapshot.compressed {
-                    // TODO: Implement compression
-                    snapshot.compressed = true;
+        for entry in walker {
+            let Some((metadata, entry)) = entry.ok().and_then(|entry| Some((entry.metadata().ok()?, entry))) else {
+                unreadable += 1;
+                continue;
+            };
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path()).to_path_buf();
+            if metadata.is_dir() {
+                if entry.depth() > 0 {
+                    directory_count += 1;
+                    if entry.depth() <= depth {
+                        directories.entry(relative).or_default();
+                    }
                 }
+                continue;
+            }
+            // Like du, a file with several hard links is counted once
+            if metadata.is_file() && file_links(&metadata) > 1 && !hard_links.insert(file_identity(&metadata)) {
+                continue;
             }
 
-            // Remove oldest snapshots
-            while snapshot_list.len() > max_snapshots {
-                if let Some(oldest) = snapshot_list.first() {
-                    if oldest.path.exists() {
-                        if oldest.path.is_file() {
-                            fs::remove_file(&oldest.path)?;
-                        } else {
-                            fs::remove_dir_all(&oldest.path)?;
-                        }
-                    }
+            let usage = Usage { size: metadata.len(), disk: disk_usage(&metadata), files: 1 };
+            total.add(&usage);
+            for ancestor in relative.ancestors().skip(1).filter(|a| !a.as_os_str().is_empty()) {
+                if let Some(stats) = directories.get_mut(ancestor) {
+                    stats.add(&usage);
                 }
-                snapshot_list.remove(0);
+            }
+            let kind = if metadata.is_symlink() { "symlink" } else { "file" };
+            if entry.depth() == 1 {
+                children.push((relative.clone(), usage.clone(), kind));
+            }
+            largest.push(std::cmp::Reverse((usage.key(by_disk), relative, usage.size, usage.disk, kind)));
+            if largest.len() > limit {
+                largest.pop();
+            }
+            if total.files % 10_000 == 0 {
+                progress::report(total.files, None, &format!("Measured {} files", total.files));
             }
         }
 
+        let entry_json = |relative: &Path, usage: &Usage, kind: &str| {
+            let mut entry = json!({
+                "path": root.join(relative).to_string_lossy(),
+                "type": kind,
+                "size": usage.size,
+                "size_human": format_size(usage.size),
+                "disk_usage": usage.disk,
+                "percent": if total.size == 0 { 0.0 } else { (usage.size as f64 * 1000.0 / total.size as f64).round() / 10.0 },
+            });
+            if kind == "directory" {
+                entry["files"] = json!(usage.files);
+            }
+            entry
+        };
+
+        children.extend(
+            directories
+                .iter()
+                .filter(|(relative, _)| relative.components().count() == 1)
+                .map(|(relative, usage)| (relative.clone(), usage.clone(), "directory")),
+        );
+        children.sort_by(|a, b| b.1.key(by_disk).cmp(&a.1.key(by_disk)).then_with(|| a.0.cmp(&b.0)));
+        let entry_count = children.len();
+        children.truncate(limit);
+
+        let mut ranked: Vec<(&PathBuf, &Usage)> = directories.iter().collect();
+        ranked.sort_by(|a, b| b.1.key(by_disk).cmp(&a.1.key(by_disk)).then_with(|| a.0.cmp(b.0)));
+        ranked.truncate(limit);
+
         Ok(json!({
-            "success": true,
             "path": path,
-            "snapshot": snapshot_path,
-            "timestamp": timestamp,
-            "total_snapshots": snapshot_list.len(),
-            "max_snapshots": max_snapshots
+            "size": total.size,
+            "size_human": format_size(total.size),
+            "disk_usage": total.disk,
+            "disk_usage_human": format_size(total.disk),
+            "files": total.files,
+            "directories": directory_count,
+            "excluded": excluded,
+            "unreadable": unreadable,
+            "entry_count": entry_count,
+            "entries": children.iter().map(|(relative, usage, kind)| entry_json(relative, usage, kind)).collect::<Vec<_>>(),
+            "largest_directories": ranked.iter().map(|(relative, usage)| entry_json(relative, usage, "directory")).collect::<Vec<_>>(),
+            "largest_files": largest
+                .into_sorted_vec()
+                .iter()
+                .map(|std::cmp::Reverse((_, relative, size, disk, kind))| {
+                    json!({
+                        "path": root.join(relative).to_string_lossy(),
+                        "type": kind,
+                        "size": size,
+                        "size_human": format_size(*size),
+                        "disk_usage": disk,
+                    })
+                })
+                .collect::<Vec<_>>(),
         }))
     }
+}
 
-    pub async fn tree(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let max_depth = args["max_depth"].as_u64().unwrap_or(4) as usize;
-        let show_hidden = args["show_hidden"].as_bool().unwrap_or(false);
-        let show_size = args["show_size"].as_bool().unwrap_or(false);
-        let dirs_only = args["dirs_only"].as_bool().unwrap_or(false);
-        let pattern = args["pattern"].as_str();
+/// Bytes of the files under a directory, as fs_du counts them
+#[derive(Clone, Default)]
+struct Usage {
+    size: u64,
+    // Allocated blocks, which sparse files have fewer and small files more of
+    disk: u64,
+    files: u64,
+}
 
-        let root = Path::new(path);
-        if !root.exists() {
-            anyhow::bail!("Path does not exist: {}", path);
-        }
+impl Usage {
+    /// What fs_du ranks by
+    fn key(&self, by_disk: bool) -> u64 {
+        if by_disk { self.disk } else { self.size }
+    }
 
-        let mut output = String::new();
-        let mut file_count = 0usize;
-        let mut dir_count = 0usize;
+    fn add(&mut self, other: &Usage) {
+        self.size += other.size;
+        self.disk += other.disk;
+        self.files += other.files;
+    }
+}
 
-        output.push_str(&format!("{}\n", root.display()));
-        build_tree(root, "", max_depth, 0, show_hidden, show_size, dirs_only, pattern, &mut output, &mut file_count, &mut dir_count)?;
+#[async_trait]
+impl ToolModule for FilesystemModule {
+    fn name(&self) -> &str {
+        "filesystem"
+    }
 
-        Ok(json!({
-            "tree": output,
-            "path": path,
-            "directories": dir_count,
-            "files": file_count,
-            "max_depth": max_depth
-        }))
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
     }
 
-    pub async fn grep(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
-        let case_insensitive = args["case_insensitive"].as_bool().unwrap_or(false);
-        let max_results = args["max_results"].as_u64().unwrap_or(200) as usize;
-        let context_lines = args["context_lines"].as_u64().unwrap_or(0) as usize;
-        let file_pattern = args["file_pattern"].as_str();
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "fs_read" => self.read(args).await?.into_value(),
+            "fs_read_many" => self.read_many(args).await?.into_value(),
+            "fs_write" => self.write(args).await,
+            "fs_move" => self.move_file(args).await,
+            "fs_copy" => self.copy(args).await,
+            "fs_link" => self.link(args).await,
+            "fs_create" => self.create(args).await,
+            "fs_delete" => self.delete(args).await,
+            "fs_trash_list" => self.trash_list(args).await,
+            "fs_trash_restore" => self.trash_restore(args).await,
+            "fs_move_desktop" => self.move_desktop(args).await,
+            "fs_find" => self.find(args).await,
+            "fs_ld" => self.ld(args).await,
+            "fs_stat" => self.stat(args).await,
+            "fs_permissions" => self.permissions(args).await,
+            "fs_watch" => self.watch(args).await,
+            "fs_watch_start" => self.watch_start(args).await,
+            "fs_watch_poll" => self.watch_poll(args).await,
+            "fs_watch_stop" => self.watch_stop(args).await,
+            "fs_snapshot" => self.snapshot(args).await,
+            "fs_snapshot_list" => self.snapshot_list(args).await,
+            "fs_snapshot_restore" => self.snapshot_restore(args).await,
+            "fs_snapshot_diff" => self.snapshot_diff(args).await,
+            "fs_tree" => self.tree(args).await,
+            "fs_grep" => self.grep(args).await,
+            "fs_tail" => self.tail(args).await,
+            "fs_replace" => self.replace(args).await,
+            "fs_edit" => self.edit(args).await,
+            "fs_filetype" => self.filetype(args).await,
+            "fs_normalize" => self.normalize(args).await,
+            "fs_loc" => self.loc(args).await,
+            "fs_hash" => self.hash(args).await,
+            "fs_diff" => self.diff(args).await,
+            "fs_archive" => self.archive(args).await,
+            "fs_extract" => self.extract(args).await,
+            "fs_recent" => self.recent(args).await,
+            "fs_largest" => self.largest(args).await,
+            "fs_du" => self.du(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
 
-        let mut regex_pattern = String::new();
-        if case_insensitive {
-            regex_pattern.push_str("(?i)");
+    async fn call_output(&self, tool: &str, args: Value) -> Result<ToolOutput> {
+        match tool {
+            "fs_read" => self.read(args).await,
+            "fs_read_many" => self.read_many(args).await,
+            _ => Ok(self.call(tool, args).await?.into()),
         }
-        regex_pattern.push_str(pattern);
+    }
 
-        let re = Regex::new(&regex_pattern)
-            .with_context(|| format!("Invalid regex pattern: {}", pattern))?;
+    async fn reload(&self, config: &Config) -> Result<()> {
+        *self.allowed_roots.write().unwrap() = resolve_roots(&config.filesystem);
+        *self.delete_mode.write().unwrap() = config.filesystem.delete_mode;
+        Ok(())
+    }
+}
 
-        let root = Path::new(path);
-        let mut matches = Vec::new();
+/// Where fs_snapshot keeps the snapshots of `path`: `.snapshots/<name>` next to it
+fn snapshot_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(".snapshots")
+        .join(path.file_name().unwrap_or(path.as_os_str()))
+}
 
-        let files: Vec<PathBuf> = if root.is_file() {
-            vec![root.to_path_buf()]
-        } else {
-            WalkDir::new(root)
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().is_file())
-                .filter(|e| {
-                    if let Some(fp) = file_pattern {
-                        let name = e.file_name().to_string_lossy();
-                        glob_match(fp, &name)
-                    } else {
-                        true
-                    }
-                })
-                .map(|e| e.path().to_path_buf())
-                .collect()
-        };
+fn resolve_roots(config: &FilesystemConfig) -> Vec<PathBuf> {
+    config.allowed_roots.iter().map(|root| resolve_path(root)).collect()
+}
 
-        'outer: for file_path in &files {
-            let content = match fs::read_to_string(file_path) {
-                Ok(c) => c,
-                Err(_) => continue, // skip binary/unreadable files
+#[allow(clippy::too_many_arguments)]
+/// What fs_tree shows
+struct TreeOptions<'a> {
+    max_depth: usize,
+    show_hidden: bool,
+    respect_gitignore: bool,
+    dirs_only: bool,
+    pattern: Option<&'a str>,
+    /// Walk below `max_depth` too, so directories can report their totals
+    whole_tree: bool,
+}
+
+/// A file or directory in fs_tree, with the totals of everything under it
+struct TreeNode {
+    name: String,
+    is_dir: bool,
+    depth: usize,
+    /// Size of the file, or of all files under the directory
+    size: u64,
+    files: usize,
+    dirs: usize,
+    /// Empty below `max_depth`, where only the totals are kept
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn new(name: String, is_dir: bool, depth: usize, size: u64) -> Self {
+        Self { name, is_dir, depth, size, files: 0, dirs: 0, children: Vec::new() }
+    }
+
+    fn adopt(&mut self, child: TreeNode, options: &TreeOptions) {
+        self.size += child.size;
+        self.files += child.files + usize::from(!child.is_dir);
+        self.dirs += child.dirs + usize::from(child.is_dir);
+        if child.depth <= options.max_depth && (child.is_dir || !options.dirs_only) {
+            self.children.push(child);
+        }
+    }
+
+    /// Directories and files shown, as opposed to counted in the totals
+    fn shown(&self) -> (usize, usize) {
+        self.children.iter().fold((0, 0), |(dirs, files), child| {
+            let (child_dirs, child_files) = child.shown();
+            let (dir, file) = if child.is_dir { (1, 0) } else { (0, 1) };
+            (dirs + dir + child_dirs, files + file + child_files)
+        })
+    }
+
+    fn render(&self, prefix: &str, show_size: bool, output: &mut String) {
+        let count = self.children.len();
+        for (i, child) in self.children.iter().enumerate() {
+            let is_last = i == count - 1;
+            let connector = if is_last { "└── " } else { "├── " };
+            let details = match (show_size, child.is_dir) {
+                (false, _) => String::new(),
+                (true, true) => format!(" ({} files, {})", child.files, format_size(child.size)),
+                (true, false) => format!(" ({})", format_size(child.size)),
             };
+            let dir_marker = if child.is_dir { "/" } else { "" };
+            output.push_str(&format!("{}{}{}{}{}\n", prefix, connector, child.name, dir_marker, details));
+            if child.is_dir {
+                child.render(&format!("{}{}", prefix, if is_last { "    " } else { "│   " }), show_size, output);
+            }
+        }
+    }
 
-            let lines: Vec<&str> = content.lines().collect();
+    fn to_json(&self, depth: usize, max_depth: usize) -> Value {
+        if !self.is_dir {
+            return json!({ "name": self.name, "type": "file", "size": self.size });
+        }
+        let mut node = json!({
+            "name": self.name,
+            "type": "dir",
+            "size": self.size,
+            "files": self.files,
+            "dirs": self.dirs
+        });
+        if depth < max_depth {
+            node["children"] = self.children.iter().map(|child| child.to_json(depth + 1, max_depth)).collect();
+        } else if self.files + self.dirs > 0 {
+            node["truncated"] = json!(true);
+        }
+        node
+    }
+}
 
-            for (line_num, line) in lines.iter().enumerate() {
-                if re.is_match(line) {
-                    if matches.len() >= max_results {
-                        break 'outer;
-                    }
+/// The tree under `root`, as deep as `options` asks for; unreadable entries are left out
+fn scan_tree(root: &Path, options: &TreeOptions) -> TreeNode {
+    let mut walker = ignore::WalkBuilder::new(root);
+    walker
+        .standard_filters(options.respect_gitignore)
+        .hidden(!options.show_hidden)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if options.respect_gitignore {
+        walker.filter_entry(|e| e.file_name() != ".git");
+    }
+    if !options.whole_tree {
+        walker.max_depth(Some(options.max_depth));
+    }
 
-                    let mut context_before = Vec::new();
-                    let mut context_after = Vec::new();
+    // The directories from the root down to the current entry, each adopted by the
+    // one before it once the walk has left it
+    let mut open = vec![TreeNode::new(root.display().to_string(), true, 0, 0)];
+    for entry in walker.build().filter_map(|e| e.ok()) {
+        let depth = entry.depth();
+        if depth == 0 {
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_dir && options.pattern.is_some_and(|pat| !name.contains(pat) && !glob_match(pat, &name)) {
+            continue;
+        }
+        while open.len() > depth {
+            let done = open.pop().unwrap();
+            open.last_mut().unwrap().adopt(done, options);
+        }
+        let size = if is_dir { 0 } else { entry.metadata().map(|m| m.len()).unwrap_or(0) };
+        let node = TreeNode::new(name, is_dir, depth, size);
+        if is_dir {
+            open.push(node);
+        } else {
+            open.last_mut().unwrap().adopt(node, options);
+        }
+    }
+    while open.len() > 1 {
+        let done = open.pop().unwrap();
+        open.last_mut().unwrap().adopt(done, options);
+    }
+    open.pop().unwrap()
+}
 
-                    if context_lines > 0 {
-                        let start = line_num.saturating_sub(context_lines);
-                        for line in &lines[start..line_num] {
-                            context_before.push(*line);
-                        }
-                        let end = (line_num + 1 + context_lines).min(lines.len());
-                        for line in &lines[(line_num + 1)..end] {
-                            context_after.push(*line);
-                        }
-                    }
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1_073_741_824 {
+        format!("{:.1}G", bytes as f64 / 1_073_741_824.0)
+    } else if bytes >= 1_048_576 {
+        format!("{:.1}M", bytes as f64 / 1_048_576.0)
+    } else if bytes >= 1024 {
+        format!("{:.1}K", bytes as f64 / 1024.0)
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
+// Helper function to copy directories recursively
+/// Copy a directory tree. When the client is listening for progress, the tree's size
+/// is measured first so each copied file can be reported as a share of the total.
+/// Symlinks are recreated as links unless `follow_symlinks`; those it cannot copy
+/// (see [`DirCopy::skipped`]) are left out and returned.
+fn copy_dir_all(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    follow_symlinks: bool,
+    allowed: &dyn Fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>> {
+    let mut copy = DirCopy {
+        allowed,
+        follow_symlinks,
+        tally: progress::is_streaming().then(|| CopyTally {
+            copied: 0,
+            total: tree_size(src.as_ref()),
+        }),
+        skipped: Vec::new(),
+        copying: Vec::new(),
+    };
+    copy.copy_dir(src.as_ref(), dst.as_ref())?;
+    Ok(copy.skipped)
+}
+
+/// One file in fs_read_many's result; `hash` is left out when only part was read
+#[derive(Serialize)]
+struct BatchFile {
+    content: String,
+    encoding: &'static str,
+    size: u64,
+    file_size: u64,
+    hash: Option<String>,
+    mtime: Option<u64>,
+    truncated: bool,
+}
+
+/// Bytes copied so far out of the size of the whole tree
+struct CopyTally {
+    copied: u64,
+    total: u64,
+}
 
-                    let mut entry = json!({
-                        "file": file_path.to_string_lossy(),
-                        "line": line_num + 1,
-                        "content": line
-                    });
+/// One fs_copy of a directory tree
+struct DirCopy<'a> {
+    allowed: &'a dyn Fn(&Path) -> bool,
+    follow_symlinks: bool,
+    tally: Option<CopyTally>,
+    /// Symlinks whose targets `allowed` refuses, and when following them, broken ones
+    /// and those leading back into a directory being copied
+    skipped: Vec<PathBuf>,
+    /// Canonical paths of the directories being copied, outermost first
+    copying: Vec<PathBuf>,
+}
 
-                    if context_lines > 0 {
-                        entry["context_before"] = json!(context_before);
-                        entry["context_after"] = json!(context_after);
+impl DirCopy<'_> {
+    fn copy_dir(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        let canonical = fs::canonicalize(src)?;
+        if self.copying.contains(&canonical) {
+            self.skipped.push(src.to_path_buf());
+            return Ok(());
+        }
+        fs::create_dir_all(dst)?;
+        self.copying.push(canonical);
+
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let path = entry.path();
+            let destination = dst.join(entry.file_name());
+            let mut ty = entry.file_type()?;
+
+            if ty.is_symlink() {
+                if !(self.allowed)(&path) {
+                    self.skipped.push(path);
+                    continue;
+                }
+                if !self.follow_symlinks {
+                    copy_symlink(&path, &destination)?;
+                    continue;
+                }
+                match fs::metadata(&path) {
+                    Ok(metadata) => ty = metadata.file_type(),
+                    Err(_) => {
+                        self.skipped.push(path);
+                        continue;
                     }
+                }
+            }
 
-                    matches.push(entry);
+            if ty.is_dir() {
+                self.copy_dir(&path, &destination)?;
+            } else {
+                let bytes = fs::copy(&path, destination)?;
+                if let Some(tally) = &mut self.tally {
+                    tally.copied += bytes;
+                    progress::report(
+                        tally.copied,
+                        Some(tally.total.max(tally.copied)),
+                        &format!("Copied {}", path.display()),
+                    );
                 }
             }
         }
 
-        let truncated = matches.len() >= max_results;
+        self.copying.pop();
+        Ok(())
+    }
+}
 
-        Ok(json!({
-            "matches": matches,
-            "count": matches.len(),
-            "files_searched": files.len(),
-            "pattern": pattern,
-            "truncated": truncated
-        }))
+/// Create a symlink at `dst` with the same target as the one at `src`
+fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    make_symlink(&fs::read_link(src)?, dst)
+}
+
+/// Create a symlink at `link` pointing to `target`, which is relative to the link's
+/// directory unless absolute. Windows links files and directories differently, so
+/// there the kind is chosen by what `target` is now.
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    if link.parent().unwrap_or(Path::new("")).join(target).is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
     }
+}
 
-    pub async fn tail(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let n = args["lines"].as_u64().unwrap_or(20) as usize;
+#[cfg(not(any(unix, windows)))]
+fn make_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "symlinks are not supported on this platform"))
+}
 
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read file: {}", path))?;
+/// Total size of the files under `path`
+pub(crate) fn tree_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
 
-        let all_lines: Vec<&str> = content.lines().collect();
-        let total = all_lines.len();
-        let start = total.saturating_sub(n);
-        let tail_lines = &all_lines[start..];
+/// Hash of a file's content as fs_read reports it and fs_write's `expected_hash` takes it
+fn content_hash(content: impl AsRef<[u8]>) -> String {
+    blake3::hash(content.as_ref()).to_hex().to_string()
+}
 
-        Ok(json!({
-            "path": path,
-            "content": tail_lines.join("\n"),
-            "lines_returned": tail_lines.len(),
-            "total_lines": total,
-            "from_line": start + 1
-        }))
+/// A running checksum in one of fs_hash's algorithms
+enum Checksum {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Checksum {
+    fn new(algorithm: &str) -> Result<Self> {
+        use sha2::Digest;
+        Ok(match algorithm {
+            "md5" => Self::Md5(md5::Md5::new()),
+            "sha1" => Self::Sha1(sha1::Sha1::new()),
+            "sha256" => Self::Sha256(sha2::Sha256::new()),
+            "sha512" => Self::Sha512(sha2::Sha512::new()),
+            "blake3" => Self::Blake3(Box::default()),
+            other => {
+                return Err(PolyError::InvalidArguments(format!(
+                    "Invalid algorithm '{}' (expected md5, sha1, sha256, sha512 or blake3)",
+                    other
+                ))
+                .into())
+            }
+        })
     }
 
-    pub async fn replace(&self, args: Value) -> Result<Value> {
-        let path = args["path"].as_str().context("Missing 'path' parameter")?;
-        let find = args["find"].as_str().context("Missing 'find' parameter")?;
-        let replace_with = args["replace"].as_str().context("Missing 'replace' parameter")?;
-        let use_regex = args["regex"].as_bool().unwrap_or(false);
-        let dry_run = args["dry_run"].as_bool().unwrap_or(false);
-        let file_pattern = args["file_pattern"].as_str();
+    fn update(&mut self, data: &[u8]) {
+        use sha2::Digest;
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Sha512(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
 
-        let root = Path::new(path);
+    /// The checksum in lowercase hex
+    fn finish(self) -> String {
+        use sha2::Digest;
+        match self {
+            Self::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
 
-        let files: Vec<PathBuf> = if root.is_file() {
-            vec![root.to_path_buf()]
-        } else if root.is_dir() {
-            let fp = file_pattern.context("'file_pattern' is required when path is a directory")?;
-            WalkDir::new(root)
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().is_file())
-                .filter(|e| glob_match(fp, &e.file_name().to_string_lossy()))
-                .map(|e| e.path().to_path_buf())
-                .collect()
-        } else {
-            anyhow::bail!("Path does not exist: {}", path);
+/// Checksum a file a chunk at a time, so its size does not matter. `on_read` is
+/// told how many bytes each chunk had.
+fn checksum_file(path: &Path, algorithm: &str, mut on_read: impl FnMut(u64)) -> Result<String> {
+    use std::io::Read;
+
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut checksum = Checksum::new(algorithm)?;
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
         };
+        checksum.update(&buffer[..read]);
+        on_read(read as u64);
+    }
+    Ok(checksum.finish())
+}
 
-        let re = if use_regex {
-            Some(Regex::new(find).with_context(|| format!("Invalid regex: {}", find))?)
-        } else {
-            None
-        };
+/// How two files differ, or `None` when their contents are the same: lines added
+/// and removed, and with a `budget` the unified diff, cut to fit what is left of it.
+/// Binary files are only reported as differing.
+fn diff_files(old: &Path, new: &Path, labels: (&str, &str), context_lines: usize, budget: Option<&mut usize>) -> Result<Option<Value>> {
+    if same_content(old, new)? {
+        return Ok(None);
+    }
+    if fs::metadata(old)?.len().max(fs::metadata(new)?.len()) > DIFF_MAX_FILE_SIZE {
+        return Ok(Some(json!({ "binary": false, "too_large": true })));
+    }
+    let old_content = fs::read(old).with_context(|| format!("Failed to read {}", old.display()))?;
+    let new_content = fs::read(new).with_context(|| format!("Failed to read {}", new.display()))?;
+    fn text(content: &[u8]) -> Option<&str> {
+        std::str::from_utf8(content).ok().filter(|_| !content.contains(&0))
+    }
+    let (Some(old_text), Some(new_text)) = (text(&old_content), text(&new_content)) else {
+        return Ok(Some(json!({ "binary": true })));
+    };
+
+    let text_diff = similar::TextDiff::from_lines(old_text, new_text);
+    let (mut additions, mut deletions) = (0, 0);
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            similar::ChangeTag::Insert => additions += 1,
+            similar::ChangeTag::Delete => deletions += 1,
+            similar::ChangeTag::Equal => {}
+        }
+    }
+    let mut changes = json!({
+        "binary": false,
+        "additions": additions,
+        "deletions": deletions
+    });
+    if let Some(budget) = budget {
+        let mut unified = text_diff
+            .unified_diff()
+            .context_radius(context_lines)
+            .header(labels.0, labels.1)
+            .to_string();
+        let fits = fitting_len(unified.as_bytes(), Some(*budget), |i| unified.is_char_boundary(i), true);
+        changes["truncated"] = json!(fits < unified.len());
+        unified.truncate(fits);
+        *budget -= fits;
+        changes["diff"] = json!(unified);
+    }
+    Ok(Some(changes))
+}
 
-        let mut results = Vec::new();
-        let mut total_replacements = 0usize;
+/// Whether two files hold the same bytes, compared a chunk at a time
+fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    use std::io::Read;
 
-        for file_path in &files {
-            let content = match fs::read_to_string(file_path) {
-                Ok(c) => c,
-                Err(_) => continue,
-            };
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = std::io::BufReader::new(fs::File::open(a).with_context(|| format!("Failed to open {}", a.display()))?);
+    let mut b = std::io::BufReader::new(fs::File::open(b).with_context(|| format!("Failed to open {}", b.display()))?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let read = a.read(&mut chunk_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut chunk_b[..1])? == 0);
+        }
+        b.read_exact(&mut chunk_b[..read])?;
+        if chunk_a[..read] != chunk_b[..read] {
+            return Ok(false);
+        }
+    }
+}
 
-            let (new_content, count) = if let Some(ref re) = re {
-                let count = re.find_iter(&content).count();
-                let replaced = re.replace_all(&content, replace_with).to_string();
-                (replaced, count)
-            } else {
-                let count = content.matches(find).count();
-                let replaced = content.replace(find, replace_with);
-                (replaced, count)
-            };
+/// Modification time in milliseconds since the Unix epoch
+fn mtime_ms(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_millis() as u64)
+}
 
-            if count > 0 {
-                if !dry_run {
-                    fs::write(file_path, &new_content)
-                        .with_context(|| format!("Failed to write: {}", file_path.display()))?;
+/// The largest index up to `index` where `at_boundary` holds, e.g. between characters
+fn floor_boundary(index: usize, at_boundary: impl Fn(usize) -> bool) -> usize {
+    (0..=index).rev().find(|&i| at_boundary(i)).unwrap_or(0)
+}
+
+/// How much of the start of `bytes` fits in `max_bytes`: up to the end of its last whole
+/// line if `lines` and one fits, else up to the last boundary that fits
+fn fitting_len(bytes: &[u8], max_bytes: Option<usize>, at_boundary: impl Fn(usize) -> bool, lines: bool) -> usize {
+    let Some(max) = max_bytes.filter(|&max| bytes.len() > max) else {
+        return bytes.len();
+    };
+    let cut = floor_boundary(max, at_boundary);
+    match bytes[..cut].iter().rposition(|&b| b == b'\n').filter(|_| lines) {
+        Some(newline) => newline + 1,
+        None => cut,
+    }
+}
+
+/// Byte offset where the last `lines` lines of `file` start, found by reading back from
+/// its end a block at a time so large files are not read whole. A final newline does
+/// not start another line.
+fn tail_start(file: &mut fs::File, size: u64, lines: usize) -> Result<u64> {
+    use std::io::{Read, Seek, SeekFrom};
+    const BLOCK: u64 = 64 * 1024;
+    if lines == 0 {
+        return Ok(size);
+    }
+    let mut buffer = vec![0; BLOCK as usize];
+    let (mut end, mut newlines) = (size, 0);
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK);
+        let block = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        for (i, _) in block.iter().enumerate().rev().filter(|(_, b)| **b == b'\n') {
+            let at = start + i as u64;
+            if at + 1 == size {
+                continue;
+            }
+            newlines += 1;
+            if newlines == lines {
+                return Ok(at + 1);
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Read `file` from `start` up to `end`, keeping the last `max_bytes` of it. Returns the
+/// bytes and the offset they start at, moved past any partial UTF-8 character.
+fn read_span(file: &mut fs::File, start: u64, end: u64, max_bytes: usize) -> Result<(Vec<u8>, u64)> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut start = start.max(end.saturating_sub(max_bytes as u64));
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.take(end.saturating_sub(start)).read_to_end(&mut bytes)?;
+    let partial = bytes.iter().take(3).take_while(|b| **b & 0xC0 == 0x80).count();
+    if start > 0 && partial > 0 {
+        bytes.drain(..partial);
+        start += partial as u64;
+    }
+    Ok((bytes, start))
+}
+
+/// What following a file with fs_tail saw
+struct Followed {
+    // The last `max_bytes` of what was appended
+    appended: Vec<u8>,
+    total: u64,
+    matched: Option<String>,
+    rotations: u64,
+    offset: u64,
+    elapsed: f64,
+}
+
+/// Read what is appended to `path` after `offset` for `seconds`, or until a complete
+/// appended line matches `until`. Each line is streamed as progress output. A file that
+/// shrinks or is replaced, as log rotation does, is read again from its start.
+async fn follow_file(path: &str, metadata: &fs::Metadata, offset: u64, seconds: f64, until: Option<&Regex>, max_bytes: usize) -> Result<Followed> {
+    let started = std::time::Instant::now();
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs_f64(seconds);
+
+    // The parent directory is watched so a file that is recreated is still seen
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })?;
+    let parent = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    watcher.watch(parent, RecursiveMode::NonRecursive)?;
+
+    let mut followed = Followed { appended: Vec::new(), total: 0, matched: None, rotations: 0, offset, elapsed: 0.0 };
+    let mut identity = file_identity(metadata);
+    // The start of a line whose newline has not been written yet
+    let mut partial = Vec::new();
+    loop {
+        let wake = deadline.min(tokio::time::Instant::now() + TAIL_POLL_INTERVAL);
+        let _ = tokio::time::timeout_at(wake, rx.recv()).await;
+
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => Some(metadata),
+            // Rotated away; wait for it to be created again
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read file: {}", path)),
+        };
+        if let Some(metadata) = metadata {
+            if file_identity(&metadata) != identity || metadata.len() < followed.offset {
+                identity = file_identity(&metadata);
+                followed.offset = 0;
+                followed.rotations += 1;
+                partial.clear();
+            }
+            if metadata.len() > followed.offset {
+                let mut file = fs::File::open(path).with_context(|| format!("Failed to read file: {}", path))?;
+                let (bytes, _) = read_span(&mut file, followed.offset, metadata.len(), usize::MAX)?;
+                followed.offset += bytes.len() as u64;
+                followed.total += bytes.len() as u64;
+                followed.appended.extend_from_slice(&bytes);
+                let excess = followed.appended.len().saturating_sub(max_bytes);
+                followed.appended.drain(..excess);
+
+                partial.extend_from_slice(&bytes);
+                let complete = partial.iter().rposition(|&b| b == b'\n').map_or(0, |newline| newline + 1);
+                for line in String::from_utf8_lossy(&partial[..complete]).lines() {
+                    progress::output("lines", line);
+                    if followed.matched.is_none() && until.is_some_and(|until| until.is_match(line)) {
+                        followed.matched = Some(line.to_string());
+                    }
                 }
-                total_replacements += count;
-                results.push(json!({
-                    "file": file_path.to_string_lossy(),
-                    "replacements": count
-                }));
+                partial.drain(..complete);
             }
         }
 
-        Ok(json!({
-            "success": true,
-            "dry_run": dry_run,
-            "files_modified": results.len(),
-            "total_replacements": total_replacements,
-            "files_searched": files.len(),
-            "details": results
-        }))
+        if followed.matched.is_some() || tokio::time::Instant::now() >= deadline {
+            break;
+        }
     }
+    followed.elapsed = started.elapsed().as_secs_f64();
+    Ok(followed)
 }
 
-#[allow(clippy::too_many_arguments)]
-fn build_tree(
-    dir: &Path,
-    prefix: &str,
-    max_depth: usize,
-    current_depth: usize,
-    show_hidden: bool,
-    show_size: bool,
-    dirs_only: bool,
-    pattern: Option<&str>,
-    output: &mut String,
-    file_count: &mut usize,
-    dir_count: &mut usize,
-) -> Result<()> {
-    if current_depth >= max_depth {
-        return Ok(());
+/// Device and inode, which change when a log is rotated to a new file
+#[cfg(unix)]
+fn file_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Bytes allocated on disk for a file, the way du counts it
+#[cfg(unix)]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Whether `path` resolves to somewhere inside `allowed_roots`; anywhere is allowed when there are none
+fn allowed_in(allowed_roots: &[PathBuf], path: &Path) -> bool {
+    if allowed_roots.is_empty() {
+        return true;
     }
+    let resolved = resolve_path(path);
+    allowed_roots.iter().any(|root| within(&resolved, root))
+}
 
-    let mut entries: Vec<_> = fs::read_dir(dir)?
-        .filter_map(|e| e.ok())
-        .collect();
+/// The `type` fs_ld, fs_stat and fs_find report for an entry
+fn entry_type(metadata: &fs::Metadata) -> &'static str {
+    if metadata.is_symlink() {
+        "symlink"
+    } else if metadata.is_file() {
+        "file"
+    } else if metadata.is_dir() {
+        "dir"
+    } else {
+        "other"
+    }
+}
 
-    entries.sort_by_key(|a| a.file_name());
+/// Add where the symlink at `path` points to an entry: its `target` as written, the
+/// `target_type` of what that is, and whether the link is `broken`
+fn add_symlink_target(entry: &mut Value, path: &Path) {
+    let pointed = fs::metadata(path).ok();
+    entry["target"] = json!(fs::read_link(path).ok().map(|target| target.display().to_string()));
+    entry["target_type"] = json!(pointed.as_ref().map(entry_type));
+    entry["broken"] = json!(pointed.is_none());
+}
 
-    // Filter hidden files
-    if !show_hidden {
-        entries.retain(|e| {
-            !e.file_name().to_string_lossy().starts_with('.')
-        });
+#[cfg(unix)]
+fn file_links(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn file_links(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// File bytes as fs_read returns them in `encoding`
+fn encode_content(bytes: &[u8], encoding: &str) -> String {
+    use base64::Engine as _;
+    match encoding {
+        "base64" => base64::engine::general_purpose::STANDARD.encode(bytes),
+        "hex" => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        "latin1" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
     }
+}
 
-    // Filter by pattern
-    if let Some(pat) = pattern {
-        entries.retain(|e| {
-            let name = e.file_name().to_string_lossy().to_string();
-            let is_dir = e.path().is_dir();
-            is_dir || name.contains(pat) || glob_match(pat, &name)
-        });
+/// The bytes fs_write's `content` stands for in `encoding`
+fn decode_content(content: &str, encoding: &str) -> Result<Vec<u8>> {
+    use base64::Engine as _;
+    match encoding {
+        "utf-8" => Ok(content.as_bytes().to_vec()),
+        "base64" => base64::engine::general_purpose::STANDARD
+            .decode(content.trim())
+            .context("Invalid content: not valid base64"),
+        "hex" => {
+            let hex = content.trim();
+            if !hex.len().is_multiple_of(2) {
+                anyhow::bail!("Invalid content: hex must have an even number of digits");
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .context("Invalid content: not valid hex")
+        }
+        "latin1" => content
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| anyhow::anyhow!("Invalid content: '{}' is not a latin1 character", c)))
+            .collect(),
+        other => anyhow::bail!("Invalid encoding '{}': expected utf-8, latin1, base64 or hex", other),
     }
+}
 
-    // Filter dirs_only
-    if dirs_only {
-        entries.retain(|e| e.path().is_dir());
+/// The files under `root` (or `root` itself if it is a file), skipping `.git` and, unless
+/// `include_ignored`, whatever `.gitignore`, `.ignore` and git's exclude files leave out
+fn walk_files(root: &Path, include_ignored: bool) -> impl Iterator<Item = PathBuf> {
+    ignore::WalkBuilder::new(root)
+        .standard_filters(!include_ignored)
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|e| e.file_name() != ".git")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .map(|e| e.into_path())
+}
+
+fn file_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    path.file_name().unwrap_or_default().to_string_lossy()
+}
+
+/// A time argument as Unix seconds: a number of seconds, an RFC3339 timestamp, or a
+/// plain YYYY-MM-DD date (midnight UTC)
+fn time_arg(args: &Value, key: &str) -> Result<Option<i64>> {
+    let value = &args[key];
+    if let Some(seconds) = value.as_i64() {
+        return Ok(Some(seconds));
+    }
+    let Some(text) = value.as_str() else {
+        return Ok(None);
+    };
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Ok(Some(time.timestamp()));
     }
+    let date = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map_err(|_| PolyError::InvalidArguments(format!("Invalid {} (expected Unix seconds, RFC3339 or YYYY-MM-DD): {}", key, text)))?;
+    Ok(date.and_hms_opt(0, 0, 0).map(|midnight| midnight.and_utc().timestamp()))
+}
+
+/// A trash item as fs_trash_list reports it
+fn trash_item_json(item: &trash::TrashItem) -> Value {
+    let deleted_at = chrono::DateTime::from_timestamp(item.time_deleted, 0)
+        .map(|time| time.with_timezone(&Local).to_rfc3339());
+    json!({
+        "id": item.id.to_string_lossy(),
+        "name": item.name.to_string_lossy(),
+        "original_path": item.original_path().display().to_string(),
+        "deleted_at": deleted_at
+    })
+}
 
-    let count = entries.len();
+/// The trash's errors print as debug output; say what went wrong in words
+fn trash_error(error: trash::Error, path: &str) -> anyhow::Error {
+    match error {
+        trash::Error::RestoreCollision { path: existing, .. } => {
+            anyhow::anyhow!("Cannot restore {}: {} already exists", path, existing.display())
+        }
+        trash::Error::TargetedRoot => anyhow::anyhow!("Cannot move a root directory to the trash: {}", path),
+        trash::Error::CouldNotAccess { target } => anyhow::anyhow!("Cannot access {}", target),
+        other => anyhow::anyhow!("Trash operation failed for {}: {:?}", path, other),
+    }
+}
 
-    for (i, entry) in entries.iter().enumerate() {
-        let is_last = i == count - 1;
-        let connector = if is_last { "└── " } else { "├── " };
-        let name = entry.file_name().to_string_lossy().to_string();
-        let path = entry.path();
-        let is_dir = path.is_dir();
+/// Everything in the trash. Windows and freedesktop trashes (Linux, BSD) can be listed;
+/// macOS gives no access to what is in its trash.
+#[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))))]
+fn trash_items() -> Result<Vec<trash::TrashItem>> {
+    trash::os_limited::list().map_err(|e| trash_error(e, "the trash"))
+}
 
-        let size_str = if show_size && !is_dir {
-            let size = path.metadata().map(|m| m.len()).unwrap_or(0);
-            format!(" ({})", format_size(size))
-        } else {
-            String::new()
-        };
+#[cfg(not(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))))]
+fn trash_items() -> Result<Vec<trash::TrashItem>> {
+    anyhow::bail!("Listing and restoring the trash is only supported on Linux, BSD and Windows")
+}
 
-        let dir_marker = if is_dir { "/" } else { "" };
+#[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))))]
+fn restore_trash_item(item: trash::TrashItem) -> Result<(), trash::Error> {
+    trash::os_limited::restore_all([item])
+}
 
-        output.push_str(&format!("{}{}{}{}{}\n", prefix, connector, name, dir_marker, size_str));
+#[cfg(not(any(target_os = "windows", all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android")))))]
+fn restore_trash_item(_item: trash::TrashItem) -> Result<(), trash::Error> {
+    Err(trash::Error::Unknown { description: "restoring from the trash is not supported here".to_string() })
+}
 
-        if is_dir {
-            *dir_count += 1;
-            let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
-            build_tree(&path, &new_prefix, max_depth, current_depth + 1, show_hidden, show_size, dirs_only, pattern, output, file_count, dir_count)?;
+/// A list-of-strings argument; non-strings are ignored and a missing one is empty
+fn string_list(value: &Value) -> Vec<String> {
+    value.as_array().map(|items| items.iter().filter_map(Value::as_str).map(str::to_string).collect()).unwrap_or_default()
+}
+
+/// The files under `path` that fs_recent and fs_largest consider: those matching the
+/// call's `file_pattern`, and not ignored unless it sets `include_ignored`
+fn matching_files<'a>(args: &'a Value, path: &str) -> impl Iterator<Item = PathBuf> + 'a {
+    let file_pattern = args["file_pattern"].as_str();
+    walk_files(Path::new(path), args["include_ignored"].as_bool().unwrap_or(false))
+        .filter(move |file| file_pattern.is_none_or(|fp| glob_match(fp, &file_name(file))))
+}
+
+/// Files and lines of one language counted by fs_loc
+#[derive(Default)]
+struct LineCounts {
+    files: usize,
+    code: usize,
+    comments: usize,
+    blanks: usize,
+}
+
+/// Sort the lines of `text` into code, comment and blank lines. Lines with both code
+/// and a comment count as code; comment markers inside strings are not told apart.
+fn count_lines(text: &str, language: &str, counts: &mut LineCounts) {
+    let (line_comments, block) = filetype::comment_syntax(language);
+    let mut block_end = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            counts.blanks += 1;
+        } else if let Some(end) = block_end {
+            counts.comments += 1;
+            if line.contains(end) {
+                block_end = None;
+            }
+        } else if let Some((start, end)) = block.filter(|(start, _)| line.starts_with(start)) {
+            counts.comments += 1;
+            if !line[start.len()..].contains(end) {
+                block_end = Some(end);
+            }
+        } else if line_comments.iter().any(|prefix| line.starts_with(prefix)) {
+            counts.comments += 1;
         } else {
-            *file_count += 1;
+            counts.code += 1;
         }
     }
+}
 
-    Ok(())
+fn is_text(path: &Path, content: &[u8]) -> bool {
+    filetype::detect(path, &content[..content.len().min(filetype::SNIFF_BYTES)]).text
 }
 
-fn format_size(bytes: u64) -> String {
-    if bytes >= 1_073_741_824 {
-        format!("{:.1}G", bytes as f64 / 1_073_741_824.0)
-    } else if bytes >= 1_048_576 {
-        format!("{:.1}M", bytes as f64 / 1_048_576.0)
-    } else if bytes >= 1024 {
-        format!("{:.1}K", bytes as f64 / 1024.0)
-    } else {
-        format!("{}B", bytes)
+/// `content` with the line endings, BOM and final newline fs_normalize asked for,
+/// and a description of each change made
+fn normalize_text(content: &str, line_endings: Option<&str>, bom: &str, final_newline: bool) -> (String, Vec<String>) {
+    let mut changes = Vec::new();
+    let had_bom = content.starts_with('\u{FEFF}');
+    let mut body = content.trim_start_matches('\u{FEFF}').to_string();
+    let found = filetype::line_endings(&body);
+
+    let newline = match (line_endings, found) {
+        (Some("crlf"), _) | (None, "crlf") => "\r\n",
+        (None, "cr") => "\r",
+        _ => "\n",
+    };
+    if let Some(target) = line_endings {
+        if found != "none" && found != target {
+            body = body.replace("\r\n", "\n").replace('\r', "\n");
+            if target == "crlf" {
+                body = body.replace('\n', "\r\n");
+            }
+            changes.push(format!("line endings {} -> {}", found, target));
+        }
+    }
+    if final_newline && !body.is_empty() && !body.ends_with(['\n', '\r']) {
+        body.push_str(newline);
+        changes.push("added final newline".to_string());
     }
+    match bom {
+        "strip" if had_bom => changes.push("removed BOM".to_string()),
+        "add" if !had_bom => changes.push("added BOM".to_string()),
+        _ => {}
+    }
+    if bom == "add" || (bom == "keep" && had_bom) {
+        body.insert(0, '\u{FEFF}');
+    }
+    (body, changes)
 }
 
-/// Simple glob matching: supports * (any chars) and ? (single char)
-fn glob_match(pattern: &str, text: &str) -> bool {
-    glob_match_recursive(&pattern.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>(), 0, 0)
+/// Apply the search/replace blocks of fs_edit to `content` in order, each to the result
+/// of the ones before; the report of each edit, or an error for the first that does not match
+fn search_replace(content: &str, edits: &[Value], fuzzy: bool) -> Result<(String, Vec<Value>)> {
+    // Blocks written with \n still match a CRLF file, and keep it CRLF
+    let eol = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let with_eol = |text: &str| match eol {
+        "\r\n" => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+        _ => text.to_string(),
+    };
+
+    let mut text = content.to_string();
+    let mut report = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
+        let number = index + 1;
+        let search = edit["search"].as_str().with_context(|| format!("Missing 'search' in edit {}", number))?;
+        let replace = edit["replace"].as_str().with_context(|| format!("Missing 'replace' in edit {}", number))?;
+        let replace_all = edit["replace_all"].as_bool().unwrap_or(false);
+        if search.trim().is_empty() {
+            anyhow::bail!("Invalid edit {}: 'search' is empty", number);
+        }
+        let (search, replace) = (with_eol(search), with_eol(replace));
+
+        let spans: Vec<std::ops::Range<usize>> = match fuzzy {
+            true => fuzzy_spans(&text, &search),
+            false => text.match_indices(&search).map(|(start, found)| start..start + found.len()).collect(),
+        };
+        let line_of = |offset: usize| text[..offset].matches('\n').count() + 1;
+        if spans.is_empty() {
+            return Err(PolyError::Conflict(format!(
+                "Edit {} of {}: the search text does not occur in the file{}; nothing was changed",
+                number,
+                edits.len(),
+                if fuzzy { "" } else { " (mode fuzzy ignores whitespace differences)" }
+            ))
+            .into());
+        }
+        if spans.len() > 1 && !replace_all {
+            let lines: Vec<String> = spans.iter().map(|span| line_of(span.start).to_string()).collect();
+            return Err(PolyError::Conflict(format!(
+                "Edit {} of {}: the search text occurs {} times (lines {}); include more surrounding lines to pick one, or set replace_all. Nothing was changed",
+                number,
+                edits.len(),
+                spans.len(),
+                lines.join(", ")
+            ))
+            .into());
+        }
+
+        let line = line_of(spans[0].start);
+        // From the back, so the spans before each replacement stay where they are
+        for span in spans.iter().rev() {
+            let replacement = match fuzzy {
+                true => reindent(&text[span.clone()], &search, &replace, eol),
+                false => replace.clone(),
+            };
+            text.replace_range(span.clone(), &replacement);
+        }
+        report.push(json!({ "edit": number, "line": line, "replacements": spans.len() }));
+    }
+    Ok((text, report))
 }
 
-fn glob_match_recursive(pattern: &[char], text: &[char], pi: usize, ti: usize) -> bool {
-    if pi == pattern.len() && ti == text.len() {
-        return true;
+/// Byte ranges of the runs of whole lines in `text` that match the lines of `search`
+/// up to whitespace, ignoring blank lines around the search text
+fn fuzzy_spans(text: &str, search: &str) -> Vec<std::ops::Range<usize>> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let want: Vec<&str> = search.lines().collect();
+    let first = want.iter().position(|line| !line.trim().is_empty()).unwrap_or(0);
+    let last = want.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |last| last + 1);
+    let want = &want[first..last];
+
+    let mut offsets = vec![0];
+    for line in &lines {
+        offsets.push(offsets[offsets.len() - 1] + line.len());
     }
-    if pi == pattern.len() {
-        return false;
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while !want.is_empty() && start + want.len() <= lines.len() {
+        if want.iter().zip(&lines[start..]).all(|(want, have)| patch::same_line(have, want, true)) {
+            spans.push(offsets[start]..offsets[start + want.len()]);
+            start += want.len();
+        } else {
+            start += 1;
+        }
     }
+    spans
+}
 
-    match pattern[pi] {
-        '*' => {
-            // * matches zero or more characters
-            for i in ti..=text.len() {
-                if glob_match_recursive(pattern, text, pi + 1, i) {
-                    return true;
-                }
+/// `replace` as whole lines in place of `matched`, shifted by the difference between the
+/// indentation `matched` has in the file and the indentation `search` gave it
+fn reindent(matched: &str, search: &str, replace: &str, eol: &str) -> String {
+    let indent = |text: &str| -> String {
+        text.lines()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line[..line.len() - line.trim_start().len()].to_string())
+            .unwrap_or_default()
+    };
+    let (have, want) = (indent(matched), indent(search));
+
+    let mut out = String::new();
+    for line in replace.lines() {
+        if !line.trim().is_empty() {
+            if let Some(extra) = have.strip_prefix(want.as_str()) {
+                out.push_str(extra);
+                out.push_str(line);
+            } else if let Some(extra) = want.strip_prefix(have.as_str()) {
+                out.push_str(line.strip_prefix(extra).unwrap_or(line));
+            } else {
+                out.push_str(line);
             }
-            false
         }
-        '?' => {
-            if ti < text.len() {
-                glob_match_recursive(pattern, text, pi + 1, ti + 1)
-            } else {
-                false
+        out.push_str(eol);
+    }
+    // The last line of the file keeps having no line ending
+    if !matched.ends_with('\n') {
+        out.truncate(out.len() - eol.len().min(out.len()));
+    }
+    out
+}
+
+/// What fs_find matches names against
+enum FindPattern {
+    /// In the file name or anywhere in the full path
+    Substring { pattern: String, case_insensitive: bool },
+    /// Against the file name, or against the path under the root if the glob has a '/'
+    Glob { matcher: globset::GlobMatcher, whole_path: bool },
+    /// Searched for in the path under the root
+    Regex(Regex),
+}
+
+impl FindPattern {
+    fn new(pattern: &str, mode: &str, case_insensitive: bool) -> Result<Self> {
+        let mode = match mode {
+            "auto" if pattern.contains(['*', '?', '[', '{']) => "glob",
+            "auto" => "substring",
+            mode => mode,
+        };
+        let found = match mode {
+            "substring" => FindPattern::Substring {
+                pattern: if case_insensitive { pattern.to_lowercase() } else { pattern.to_string() },
+                case_insensitive,
+            },
+            "glob" => {
+                let glob = globset::GlobBuilder::new(pattern)
+                    .case_insensitive(case_insensitive)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| PolyError::InvalidArguments(format!("Invalid glob pattern: {}", e)))?;
+                FindPattern::Glob { matcher: glob.compile_matcher(), whole_path: pattern.contains('/') }
+            }
+            "regex" => {
+                let regex = regex::RegexBuilder::new(pattern)
+                    .case_insensitive(case_insensitive)
+                    .build()
+                    .map_err(|e| PolyError::InvalidArguments(format!("Invalid regex pattern: {}", e)))?;
+                FindPattern::Regex(regex)
             }
+            other => return Err(PolyError::InvalidArguments(format!("Invalid match_mode: {}", other)).into()),
+        };
+        Ok(found)
+    }
+
+    fn mode(&self) -> &'static str {
+        match self {
+            FindPattern::Substring { .. } => "substring",
+            FindPattern::Glob { .. } => "glob",
+            FindPattern::Regex(_) => "regex",
         }
-        c => {
-            if ti < text.len() && (c == text[ti] || c.to_lowercase().next() == text[ti].to_lowercase().next()) {
-                glob_match_recursive(pattern, text, pi + 1, ti + 1)
-            } else {
-                false
+    }
+
+    /// `relative` is the path under the root with '/' separators, `path` the full path
+    fn matches(&self, relative: &str, name: &str, path: &Path) -> bool {
+        match self {
+            FindPattern::Substring { pattern, case_insensitive: false } => {
+                name.contains(pattern.as_str()) || path.to_string_lossy().contains(pattern.as_str())
             }
+            FindPattern::Substring { pattern, case_insensitive: true } => {
+                name.to_lowercase().contains(pattern.as_str()) || path.to_string_lossy().to_lowercase().contains(pattern.as_str())
+            }
+            FindPattern::Glob { matcher, whole_path: true } => matcher.is_match(relative),
+            FindPattern::Glob { matcher, whole_path: false } => matcher.is_match(name),
+            FindPattern::Regex(regex) => regex.is_match(relative),
         }
     }
 }
 
-// Helper function to copy directories recursively
-fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
-    fs::create_dir_all(&dst)?;
+/// Stands in for the txn module's staging area in builds without it. It has no
+/// values, so edits that name a transaction fail as on a server without txn.
+#[cfg(not(feature = "txn"))]
+mod unavailable {
+    use anyhow::Result;
+    use std::path::Path;
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
+    pub enum Transactions {}
 
-        if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+    impl Transactions {
+        pub fn read(&self, _txn: &str, _path: &Path) -> Result<Option<Option<String>>> {
+            match *self {}
         }
-    }
 
-    Ok(())
+        pub fn write(&self, _txn: &str, _path: &Path, _content: String) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn delete(&self, _txn: &str, _path: &Path) -> Result<()> {
+            match *self {}
+        }
+    }
 }
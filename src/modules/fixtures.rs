@@ -0,0 +1,501 @@
+// Throwaway sample workspaces built from templates, as reproducible targets for agent evaluations and tests
+
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::error::PolyError;
+use crate::registry::ToolModule;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where fixtures go without a `path`, under the system's temp directory
+const TEMP_DIR: &str = "poly-mcp-fixtures";
+
+/// Every fixture commit is made by this author, an hour after the one before it,
+/// from this time on, so the same template always gets the same commit ids
+const AUTHOR: (&str, &str) = ("Fixture Author", "fixture@example.com");
+const EPOCH: i64 = 1_700_000_000;
+
+/// A file of a template; `{name}` in it becomes the fixture's name, and `{crate}`
+/// the name as a Rust path, with '_' for '-'
+type File = (&'static str, &'static str);
+
+/// A commit of a template's history: its message and the files it writes. Paths
+/// written with empty content are removed instead.
+struct Commit {
+    message: &'static str,
+    files: &'static [File],
+}
+
+struct Template {
+    name: &'static str,
+    description: &'static str,
+    files: &'static [File],
+    // On top of `files`, which the first commit adds
+    history: &'static [Commit],
+    // Made on a branch of this name off the last commit of `history`
+    branch: Option<(&'static str, &'static [Commit])>,
+    tag: Option<&'static str>,
+}
+
+const RUST_MANIFEST: File = (
+    "Cargo.toml",
+    r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+"#,
+);
+
+const RUST_GITIGNORE: File = (".gitignore", "/target\n");
+
+const RUST_MAIN: File = (
+    "src/main.rs",
+    r#"fn main() {
+    let numbers = [3, 1, 4, 1, 5, 9, 2, 6];
+    println!("sum = {}", {crate}::sum(&numbers));
+    println!("max = {:?}", {crate}::max(&numbers));
+}
+"#,
+);
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        name: "rust-crate",
+        description: "A Rust library and binary crate with unit tests that pass",
+        files: &[
+            RUST_MANIFEST,
+            RUST_GITIGNORE,
+            RUST_MAIN,
+            (
+                "src/lib.rs",
+                r#"//! Small helpers over slices of numbers
+
+/// The sum of `numbers`
+pub fn sum(numbers: &[i64]) -> i64 {
+    numbers.iter().sum()
+}
+
+/// The largest of `numbers`, or None when there are none
+pub fn max(numbers: &[i64]) -> Option<i64> {
+    numbers.iter().copied().max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums() {
+        assert_eq!(sum(&[1, 2, 3]), 6);
+        assert_eq!(sum(&[]), 0);
+    }
+
+    #[test]
+    fn finds_the_max() {
+        assert_eq!(max(&[3, 9, 2]), Some(9));
+        assert_eq!(max(&[]), None);
+    }
+}
+"#,
+            ),
+            ("README.md", "# {name}\n\nSmall helpers over slices of numbers.\n\n```sh\ncargo test\n```\n"),
+        ],
+        history: &[],
+        branch: None,
+        tag: None,
+    },
+    Template {
+        name: "rust-crate-errors",
+        description: "The rust-crate fixture with a type error and a failing test, for diagnostics and fix-it evaluations",
+        files: &[
+            RUST_MANIFEST,
+            RUST_GITIGNORE,
+            RUST_MAIN,
+            (
+                "src/lib.rs",
+                r#"//! Small helpers over slices of numbers
+
+/// The sum of `numbers`
+pub fn sum(numbers: &[i64]) -> i64 {
+    numbers.len()
+}
+
+/// The largest of `numbers`, or None when there are none
+pub fn max(numbers: &[i64]) -> Option<i64> {
+    numbers.iter().copied().min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums() {
+        assert_eq!(sum(&[1, 2, 3]), 6);
+    }
+
+    #[test]
+    fn finds_the_max() {
+        assert_eq!(max(&[3, 9, 2]), Some(9));
+    }
+}
+"#,
+            ),
+            ("README.md", "# {name}\n\nSmall helpers over slices of numbers. They do not build yet.\n"),
+        ],
+        history: &[],
+        branch: None,
+        tag: None,
+    },
+    Template {
+        name: "node-project",
+        description: "A Node.js package with a module and tests for the built-in test runner (node --test)",
+        files: &[
+            (
+                "package.json",
+                r#"{
+  "name": "{name}",
+  "version": "0.1.0",
+  "description": "Small string helpers",
+  "main": "src/index.js",
+  "scripts": {
+    "test": "node --test"
+  },
+  "license": "MIT"
+}
+"#,
+            ),
+            (".gitignore", "node_modules/\n"),
+            (
+                "src/index.js",
+                r#"'use strict';
+
+/** Turn "Hello World" into "hello-world" */
+function slugify(text) {
+  return text
+    .toLowerCase()
+    .trim()
+    .replace(/[^a-z0-9]+/g, '-')
+    .replace(/^-+|-+$/g, '');
+}
+
+/** Cut `text` to at most `length` characters, ending with an ellipsis when cut */
+function truncate(text, length) {
+  return text.length <= length ? text : text.slice(0, Math.max(0, length - 1)) + '…';
+}
+
+module.exports = { slugify, truncate };
+"#,
+            ),
+            (
+                "test/index.test.js",
+                r#"'use strict';
+
+const test = require('node:test');
+const assert = require('node:assert');
+const { slugify, truncate } = require('../src/index.js');
+
+test('slugify', () => {
+  assert.strictEqual(slugify('Hello World'), 'hello-world');
+  assert.strictEqual(slugify('  Already-slugged  '), 'already-slugged');
+});
+
+test('truncate', () => {
+  assert.strictEqual(truncate('short', 10), 'short');
+  assert.strictEqual(truncate('a longer sentence', 8), 'a longe…');
+});
+"#,
+            ),
+            ("README.md", "# {name}\n\nSmall string helpers.\n\n```sh\nnpm test\n```\n"),
+        ],
+        history: &[],
+        branch: None,
+        tag: None,
+    },
+    Template {
+        name: "git-repo",
+        description: "A git repository with five commits on main, a tag, and a feature branch with one more commit",
+        files: &[
+            ("README.md", "# {name}\n\nA sample repository.\n"),
+            ("notes/todo.md", "- write the greeting\n"),
+        ],
+        history: &[
+            Commit {
+                message: "Add greeting script",
+                files: &[("hello.sh", "#!/bin/sh\necho \"Helo, $1\"\n")],
+            },
+            Commit {
+                message: "Fix typo in greeting",
+                files: &[("hello.sh", "#!/bin/sh\necho \"Hello, $1\"\n")],
+            },
+            Commit {
+                message: "Default to greeting the world",
+                files: &[
+                    ("hello.sh", "#!/bin/sh\necho \"Hello, ${1:-world}\"\n"),
+                    ("notes/todo.md", "- write the greeting\n- add a farewell\n"),
+                ],
+            },
+            Commit {
+                message: "Remove the done notes",
+                files: &[("notes/todo.md", "")],
+            },
+        ],
+        branch: Some((
+            "feature/farewell",
+            &[Commit {
+                message: "Add farewell script",
+                files: &[("bye.sh", "#!/bin/sh\necho \"Goodbye, ${1:-world}\"\n")],
+            }],
+        )),
+        tag: Some("v0.1.0"),
+    },
+];
+
+pub struct FixturesModule {
+    // Fixtures made in the temp directory, removed when the module shuts down
+    created: Mutex<Vec<PathBuf>>,
+}
+
+impl Default for FixturesModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FixturesModule {
+    pub fn new() -> Self {
+        Self {
+            created: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        let templates: Vec<&str> = TEMPLATES.iter().map(|template| template.name).collect();
+        let descriptions: Vec<String> = TEMPLATES
+            .iter()
+            .map(|template| format!("{}: {}", template.name, template.description))
+            .collect();
+        vec![json!({
+            "name": "fixture_create",
+            "description": format!(
+                "Create a throwaway sample workspace from a built-in template, the same every time, as a target for trying tools out or for tests. Templates: {}",
+                descriptions.join("; ")
+            ),
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "template": {
+                        "type": "string",
+                        "enum": templates,
+                        "description": "Which workspace to create"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to create it in, which must not exist or be empty (default: a new directory under the system's temp directory, removed when the server stops)"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Crate, package or repository name: lowercase letters, digits, '-' and '_' (default: fixture)"
+                    },
+                    "git": {
+                        "type": "boolean",
+                        "description": "Also make the files a git repository with one commit; git-repo always is one (default: false)"
+                    }
+                },
+                "required": ["template"]
+            }
+        })]
+    }
+
+    pub async fn create(&self, args: Value) -> Result<Value> {
+        let template = args["template"].as_str().context("Missing 'template' parameter")?;
+        let template = TEMPLATES.iter().find(|t| t.name == template).ok_or_else(|| {
+            let names: Vec<&str> = TEMPLATES.iter().map(|t| t.name).collect();
+            PolyError::InvalidArguments(format!("Invalid template '{}': expected one of {}", template, names.join(", ")))
+        })?;
+        let name = args["name"].as_str().unwrap_or("fixture");
+        if name.is_empty()
+            || !name.starts_with(|c: char| c.is_ascii_lowercase())
+            || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(PolyError::InvalidArguments(format!(
+                "Invalid name '{}': use lowercase letters, digits, '-' and '_', starting with a letter",
+                name
+            ))
+            .into());
+        }
+        let git = args["git"].as_bool().unwrap_or(false) || !template.history.is_empty();
+
+        let (dir, temporary) = match args["path"].as_str() {
+            Some(path) => {
+                let dir = PathBuf::from(path);
+                if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+                    anyhow::bail!("{} already exists and is not empty", dir.display());
+                }
+                (dir, false)
+            }
+            None => {
+                let id = uuid::Uuid::new_v4().simple().to_string();
+                let dir = std::env::temp_dir().join(TEMP_DIR).join(format!("{}-{}", template.name, &id[..8]));
+                (dir, true)
+            }
+        };
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        if temporary {
+            self.created.lock().unwrap().push(dir.clone());
+        }
+
+        let made = build(template, &dir, name, git);
+        if made.is_err() && temporary {
+            let _ = fs::remove_dir_all(&dir);
+        }
+        let repository = made?;
+
+        let mut files: Vec<String> = walkdir::WalkDir::new(&dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().strip_prefix(&dir).unwrap_or(e.path()).to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+
+        let mut result = json!({
+            "template": template.name,
+            "path": dir.display().to_string(),
+            "name": name,
+            "temporary": temporary,
+            "file_count": files.len(),
+            "files": files,
+        });
+        if let Some(repository) = repository {
+            result["git"] = repository;
+        }
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl ToolModule for FixturesModule {
+    fn name(&self) -> &str {
+        "fixtures"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "fixture_create" => self.create(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        for dir in self.created.lock().unwrap().drain(..) {
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to remove fixture {}: {}", dir.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// Write `template` into `dir`, and with `git` commit its files and history.
+/// Returns what the repository holds, if one was made.
+fn build(template: &Template, dir: &Path, name: &str, git: bool) -> Result<Option<Value>> {
+    write_files(dir, template.files, name)?;
+    if !git {
+        return Ok(None);
+    }
+
+    let repo = git2::Repository::init_opts(dir, git2::RepositoryInitOptions::new().initial_head("main"))
+        .with_context(|| format!("Failed to create a git repository in {}", dir.display()))?;
+    let mut commits = 0;
+    let mut head = commit(&repo, "Initial commit", commits)?;
+    commits += 1;
+    for step in template.history {
+        write_files(dir, step.files, name)?;
+        head = commit(&repo, step.message, commits)?;
+        commits += 1;
+    }
+    if let Some(tag) = template.tag {
+        let target = repo.find_object(head, None)?;
+        repo.tag(tag, &target, &signature(commits)?, &format!("Release {}", tag), false)?;
+    }
+
+    let mut branches = vec!["main".to_string()];
+    if let Some((branch, steps)) = template.branch {
+        repo.branch(branch, &repo.find_commit(head)?, false)?;
+        repo.set_head(&format!("refs/heads/{}", branch))?;
+        for (i, step) in steps.iter().enumerate() {
+            write_files(dir, step.files, name)?;
+            commit(&repo, step.message, commits + i as i64)?;
+        }
+        // Back to main, with its files in the working tree
+        repo.set_head("refs/heads/main")?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force().remove_untracked(true)))?;
+        branches.push(branch.to_string());
+    }
+
+    Ok(Some(json!({
+        "branch": "main",
+        "head": head.to_string(),
+        // On main
+        "commits": commits,
+        "branches": branches,
+        "tags": template.tag.into_iter().collect::<Vec<_>>(),
+    })))
+}
+
+fn write_files(dir: &Path, files: &[File], name: &str) -> Result<()> {
+    for (path, content) in files {
+        let path = dir.join(path);
+        if content.is_empty() {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content.replace("{name}", name).replace("{crate}", &name.replace('-', "_")))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        if path.extension().is_some_and(|extension| extension == "sh") {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(())
+}
+
+/// Commit the whole working tree onto HEAD as the `n`th fixture commit
+fn commit(repo: &git2::Repository, message: &str, n: i64) -> Result<git2::Oid> {
+    let mut index = repo.index()?;
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+    // Picks up removed files too
+    index.update_all(["*"], None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    let signature = signature(n)?;
+    Ok(repo.commit(Some("HEAD"), &signature, &signature, message, &tree, parent.as_ref().into_iter().collect::<Vec<_>>().as_slice())?)
+}
+
+fn signature(n: i64) -> Result<git2::Signature<'static>> {
+    Ok(git2::Signature::new(AUTHOR.0, AUTHOR.1, &git2::Time::new(EPOCH + n * 3600, 0))?)
+}
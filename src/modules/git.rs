@@ -1,9 +1,26 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
-use git2::{Repository, StatusOptions, DiffOptions, BranchType, ObjectType};
+use async_trait::async_trait;
+use crate::children;
+use crate::provenance;
+use crate::registry::{ToolModule, ToolOutput};
+use crate::telemetry;
+use git2::{Repository, StatusOptions, DiffOptions, BranchType, ObjectType, Sort, Diff, ApplyLocation, ApplyOptions, BlameOptions};
+use chrono::{DateTime, NaiveDate};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-
-pub struct GitModule;
+use std::process::{Command, Stdio};
+use regex::Regex;
+use crate::config::GitConfig;
+use crate::pagination::Pages;
+use crate::paths::simplify;
+use serde::Serialize;
+
+pub struct GitModule {
+    config: GitConfig,
+    // Large git_diff results, fetched a page at a time
+    pages: Pages,
+}
 
 impl Default for GitModule {
     fn default() -> Self {
@@ -13,7 +30,23 @@ impl Default for GitModule {
 
 impl GitModule {
     pub fn new() -> Self {
-        Self
+        Self::with_config(&GitConfig::default())
+    }
+
+    pub fn with_config(config: &GitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            pages: Pages::new(),
+        }
+    }
+
+    /// Signature for new commits and tags: the configured default author if set,
+    /// otherwise the repository's user.name/user.email
+    fn default_signature(&self, repo: &Repository) -> Result<git2::Signature<'static>> {
+        match (&self.config.author_name, &self.config.author_email) {
+            (Some(name), Some(email)) => Ok(git2::Signature::now(name, email)?),
+            _ => Ok(repo.signature()?.to_owned()),
+        }
     }
 
     pub fn get_tools(&self) -> Vec<Value> {
@@ -33,7 +66,7 @@ impl GitModule {
             }),
             json!({
                 "name": "git_diff",
-                "description": "Get diff of changes in repository",
+                "description": "Get diff of changes in repository. Large diffs can be fetched in pages: pass page_size for a summary and the first page, then cursor for the next ones",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -48,6 +81,14 @@ impl GitModule {
                         "file": {
                             "type": "string",
                             "description": "Specific file to diff"
+                        },
+                        "page_size": {
+                            "type": "integer",
+                            "description": "Return at most this many patch lines, with a per-file summary and a next_cursor for the rest (default: all at once)"
+                        },
+                        "cursor": {
+                            "type": "string",
+                            "description": "next_cursor from a previous git_diff, to get the next page of patch lines; other arguments are then ignored"
                         }
                     }
                 }
@@ -135,6 +176,26 @@ impl GitModule {
                         "file": {
                             "type": "string",
                             "description": "File to blame"
+                        },
+                        "line_start": {
+                            "type": "number",
+                            "description": "First line to blame, 1-based (default: 1)"
+                        },
+                        "line_end": {
+                            "type": "number",
+                            "description": "Last line to blame, inclusive (default: end of file)"
+                        },
+                        "ignore_revs_file": {
+                            "type": "string",
+                            "description": "File listing commits to skip, e.g. formatting commits (default: .git-blame-ignore-revs if present and ignore_revs is true)"
+                        },
+                        "ignore_revs": {
+                            "type": "boolean",
+                            "description": "Skip commits listed in the ignore-revs file (default: true)"
+                        },
+                        "include_content": {
+                            "type": "boolean",
+                            "description": "Include the text of each blamed line (default: true)"
                         }
                     },
                     "required": ["file"]
@@ -187,6 +248,172 @@ impl GitModule {
                     }
                 }
             }),
+            json!({
+                "name": "git_stats",
+                "description": "Repository statistics: commits per author, churn per file/directory, hot files, and average commit size over a time range",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to git repository (default: current directory)"
+                        },
+                        "since": {
+                            "type": "string",
+                            "description": "Only include commits at or after this date (RFC3339 or YYYY-MM-DD)"
+                        },
+                        "until": {
+                            "type": "string",
+                            "description": "Only include commits before this date (RFC3339 or YYYY-MM-DD)"
+                        },
+                        "max_commits": {
+                            "type": "number",
+                            "description": "Maximum number of commits to analyze (default: 1000)"
+                        },
+                        "top": {
+                            "type": "number",
+                            "description": "Number of hot files/directories to return (default: 10)"
+                        },
+                        "dir_depth": {
+                            "type": "number",
+                            "description": "Directory depth used to aggregate churn (default: 1)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "git_changelog",
+                "description": "Generate a markdown changelog section from conventional commits between two refs, grouped by commit type",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to git repository (default: current directory)"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Starting ref, exclusive (default: most recent tag reachable from 'to', or repository root)"
+                        },
+                        "to": {
+                            "type": "string",
+                            "description": "Ending ref, inclusive (default: HEAD)"
+                        },
+                        "version": {
+                            "type": "string",
+                            "description": "Heading for the changelog section (default: Unreleased)"
+                        },
+                        "detect_breaking": {
+                            "type": "boolean",
+                            "description": "Collect '!' and BREAKING CHANGE footers into a dedicated section (default: true)"
+                        },
+                        "include_other": {
+                            "type": "boolean",
+                            "description": "Include commits that do not follow the conventional-commit format (default: true)"
+                        },
+                        "commit_url": {
+                            "type": "string",
+                            "description": "Link template for commits, e.g. 'https://github.com/org/repo/commit/{hash}'"
+                        },
+                        "compare_url": {
+                            "type": "string",
+                            "description": "Link template for the section heading, e.g. 'https://github.com/org/repo/compare/{from}...{to}'"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "git_apply",
+                "description": "Apply a unified diff to the worktree and/or index with a per-hunk report (applied, already applied, failed) and three-way fallback",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to git repository (default: current directory)"
+                        },
+                        "patch": {
+                            "type": "string",
+                            "description": "Unified diff text to apply"
+                        },
+                        "patch_file": {
+                            "type": "string",
+                            "description": "Path to a file containing the unified diff (alternative to 'patch')"
+                        },
+                        "location": {
+                            "type": "string",
+                            "enum": ["workdir", "index", "both"],
+                            "description": "Where to apply the patch (default: workdir)"
+                        },
+                        "check": {
+                            "type": "boolean",
+                            "description": "Only report which hunks would apply, without changing anything (default: false)"
+                        },
+                        "allow_partial": {
+                            "type": "boolean",
+                            "description": "Apply the hunks that apply cleanly even if others fail (default: false)"
+                        },
+                        "three_way": {
+                            "type": "boolean",
+                            "description": "Fall back to 'git apply --3way' when the patch does not apply cleanly (default: true)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "git_ignore",
+                "description": "Check whether paths are ignored (and by which rule), or list/add/remove patterns in .gitignore or .git/info/exclude",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to git repository (default: current directory)"
+                        },
+                        "action": {
+                            "type": "string",
+                            "enum": ["check", "list", "add", "remove"],
+                            "description": "Action to perform (default: check)"
+                        },
+                        "paths": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Repository-relative paths to check (for check)"
+                        },
+                        "patterns": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Ignore patterns to add or remove (for add/remove)"
+                        },
+                        "file": {
+                            "type": "string",
+                            "enum": ["gitignore", "exclude"],
+                            "description": "Ignore file to edit: top-level .gitignore or .git/info/exclude (default: gitignore)"
+                        },
+                        "comment": {
+                            "type": "string",
+                            "description": "Comment line written above newly added patterns (for add)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "git_discover",
+                "description": "Find the repository enclosing a path and report its toplevel, default branch, remotes, and whether it is bare, a linked worktree, or a submodule",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Any path inside the repository (default: current directory)"
+                        }
+                    }
+                }
+            }),
         ]
     }
 
@@ -239,10 +466,20 @@ impl GitModule {
         Ok(result)
     }
 
-    pub async fn diff(&self, args: Value) -> Result<Value> {
+    pub async fn diff(&self, args: Value) -> Result<ToolOutput> {
+        if let Some(cursor) = args["cursor"].as_str() {
+            let (patches, next_cursor) = self.pages.next(cursor)?;
+            return Ok(json!({
+                "patches": patches,
+                "next_cursor": next_cursor
+            })
+            .into());
+        }
+
         let path = args["path"].as_str().unwrap_or(".");
         let staged = args["staged"].as_bool().unwrap_or(false);
         let file_filter = args["file"].as_str();
+        let page_size = args["page_size"].as_u64().map(|n| n as usize);
 
         let repo = Repository::open(path)?;
 
@@ -263,24 +500,68 @@ impl GitModule {
         };
 
         let mut patches = Vec::new();
+        // Where each file's lines start in `patches`, for the paged summary
+        let mut file_offsets = Vec::new();
 
         diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-            patches.push(json!({
-                "origin": format!("{}", line.origin()),
-                "content": String::from_utf8_lossy(line.content())
-            }));
+            if line.origin() == 'F' {
+                file_offsets.push(patches.len());
+            }
+            patches.push(DiffLine {
+                origin: line.origin(),
+                content: String::from_utf8_lossy(line.content()).into_owned(),
+            });
             true
         })?;
 
         let stats = diff.stats()?;
 
-        Ok(json!({
+        let mut result = json!({
             "staged": staged,
             "files_changed": stats.files_changed(),
             "insertions": stats.insertions(),
-            "deletions": stats.deletions(),
-            "patches": patches
-        }))
+            "deletions": stats.deletions()
+        });
+
+        match page_size {
+            // Large diffs have a line per changed line: they go straight into the JSON text
+            None => {
+                #[derive(Serialize)]
+                struct Patched<'a> {
+                    #[serde(flatten)]
+                    fields: Value,
+                    patches: &'a [DiffLine],
+                }
+                return ToolOutput::serialize(&Patched { fields: result, patches: &patches });
+            }
+            Some(page_size) => {
+                let mut files = Vec::new();
+                for (idx, delta) in diff.deltas().enumerate() {
+                    let (_, insertions, deletions) = match git2::Patch::from_diff(&diff, idx)? {
+                        Some(patch) => patch.line_stats()?,
+                        None => (0, 0, 0),
+                    };
+                    let file = delta.new_file().path().or(delta.old_file().path());
+                    files.push(json!({
+                        "path": file.map(|p| p.display().to_string()),
+                        "status": format!("{:?}", delta.status()).to_lowercase(),
+                        "insertions": insertions,
+                        "deletions": deletions,
+                        "offset": file_offsets.get(idx)
+                    }));
+                }
+
+                let total_lines = patches.len();
+                let patches = patches.iter().map(|line| json!(line)).collect();
+                let (patches, next_cursor) = self.pages.first(patches, page_size);
+                result["files"] = json!(files);
+                result["total_lines"] = json!(total_lines);
+                result["patches"] = json!(patches);
+                result["next_cursor"] = json!(next_cursor);
+            }
+        }
+
+        Ok(result.into())
     }
 
     pub async fn commit(&self, args: Value) -> Result<Value> {
@@ -293,7 +574,7 @@ impl GitModule {
         let signature = if let (Some(name), Some(email)) = (args["author_name"].as_str(), args["author_email"].as_str()) {
             git2::Signature::now(name, email)?
         } else {
-            repo.signature()?
+            self.default_signature(&repo)?
         };
 
         // Get current index
@@ -411,30 +692,83 @@ impl GitModule {
     pub async fn blame(&self, args: Value) -> Result<Value> {
         let path = args["path"].as_str().unwrap_or(".");
         let file = args["file"].as_str().context("Missing 'file' parameter")?;
+        let line_start = args["line_start"].as_u64().map(|l| l.max(1) as usize);
+        let line_end = args["line_end"].as_u64().map(|l| l as usize);
+        let include_content = args["include_content"].as_bool().unwrap_or(true);
+        let use_ignore_revs = args["ignore_revs"].as_bool().unwrap_or(true);
+
+        if let (Some(start), Some(end)) = (line_start, line_end) {
+            if end < start {
+                anyhow::bail!("line_end ({}) is before line_start ({})", end, start);
+            }
+        }
 
         let repo = Repository::open(path)?;
-        let blame = repo.blame_file(Path::new(file), None)?;
+        let workdir = repo.workdir().context("Repository has no working directory")?.to_path_buf();
 
-        let mut lines = Vec::new();
+        let ignore_revs_file = match args["ignore_revs_file"].as_str() {
+            Some(f) => Some(workdir.join(f)),
+            None => Some(workdir.join(".git-blame-ignore-revs")).filter(|f| f.is_file()),
+        }
+        .filter(|_| use_ignore_revs);
 
-        for hunk in blame.iter() {
-            let commit = repo.find_commit(hunk.final_commit_id())?;
+        // libgit2 has no ignore-revs support, so that case goes through the git CLI
+        let lines = if let Some(ref revs_file) = ignore_revs_file {
+            blame_with_ignore_revs(&workdir, file, revs_file, line_start, line_end, include_content)?
+        } else {
+            let mut opts = BlameOptions::new();
+            if let Some(start) = line_start {
+                opts.min_line(start);
+            }
+            if let Some(end) = line_end {
+                opts.max_line(end);
+            }
+            let blame = repo.blame_file(Path::new(file), Some(&mut opts))?;
+
+            let file_lines: Vec<String> = if include_content {
+                let blob = repo.head()?.peel_to_tree()?.get_path(Path::new(file))?.to_object(&repo)?.peel_to_blob()?;
+                String::from_utf8_lossy(blob.content()).lines().map(|l| l.to_string()).collect()
+            } else {
+                Vec::new()
+            };
+
+            let mut lines = Vec::new();
+
+            for hunk in blame.iter() {
+                let commit = repo.find_commit(hunk.final_commit_id())?;
+                let start = hunk.final_start_line();
+                let count = hunk.lines_in_hunk();
+
+                let mut entry = json!({
+                    "line_start": start,
+                    "line_end": start + count.saturating_sub(1),
+                    "line_count": count,
+                    "commit": hunk.final_commit_id().to_string(),
+                    "author": commit.author().name().unwrap_or(""),
+                    "email": commit.author().email().unwrap_or(""),
+                    "timestamp": commit.time().seconds(),
+                    "message": commit.summary().unwrap_or("")
+                });
+
+                if include_content {
+                    let from = start.saturating_sub(1).min(file_lines.len());
+                    let to = (from + count).min(file_lines.len());
+                    entry["content"] = json!(file_lines[from..to]);
+                }
 
-            lines.push(json!({
-                "line_start": hunk.final_start_line(),
-                "line_count": hunk.lines_in_hunk(),
-                "commit": hunk.final_commit_id().to_string(),
-                "author": commit.author().name().unwrap_or(""),
-                "email": commit.author().email().unwrap_or(""),
-                "timestamp": commit.time().seconds(),
-                "message": commit.summary().unwrap_or("")
-            }));
-        }
+                lines.push(entry);
+            }
+
+            lines
+        };
 
         Ok(json!({
             "file": file,
             "lines": lines,
-            "total_hunks": lines.len()
+            "total_hunks": lines.len(),
+            "line_start": line_start,
+            "line_end": line_end,
+            "ignore_revs_file": ignore_revs_file
         }))
     }
 
@@ -514,7 +848,7 @@ impl GitModule {
 
                 if let Some(msg) = message {
                     // Create annotated tag
-                    let sig = repo.signature()?;
+                    let sig = self.default_signature(&repo)?;
                     repo.tag(name, &target, &sig, msg, false)?;
                 } else {
                     // Create lightweight tag
@@ -541,4 +875,1071 @@ impl GitModule {
             _ => Err(anyhow::anyhow!("Unknown action: {}", action)),
         }
     }
+
+    pub async fn stats(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+        let since = args["since"].as_str().map(parse_date_arg).transpose()?;
+        let until = args["until"].as_str().map(parse_date_arg).transpose()?;
+        let max_commits = args["max_commits"].as_u64().unwrap_or(1000) as usize;
+        let top = args["top"].as_u64().unwrap_or(10) as usize;
+        let dir_depth = args["dir_depth"].as_u64().unwrap_or(1).max(1) as usize;
+
+        let repo = Repository::open(path)?;
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        revwalk.push_head()?;
+
+        let mut authors: HashMap<String, ChurnStats> = HashMap::new();
+        let mut files: HashMap<String, ChurnStats> = HashMap::new();
+        let mut dirs: HashMap<String, ChurnStats> = HashMap::new();
+
+        let mut commit_count = 0usize;
+        let mut merge_count = 0usize;
+        let mut total_insertions = 0usize;
+        let mut total_deletions = 0usize;
+        let mut total_files_changed = 0usize;
+        let mut first_ts: Option<i64> = None;
+        let mut last_ts: Option<i64> = None;
+
+        for oid in revwalk {
+            if commit_count >= max_commits {
+                break;
+            }
+
+            let commit = repo.find_commit(oid?)?;
+            let ts = commit.time().seconds();
+
+            if until.is_some_and(|u| ts >= u) {
+                continue;
+            }
+            // Revwalk is time-sorted, so everything after this is older
+            if since.is_some_and(|s| ts < s) {
+                break;
+            }
+
+            commit_count += 1;
+            first_ts = Some(first_ts.map_or(ts, |t| t.min(ts)));
+            last_ts = Some(last_ts.map_or(ts, |t| t.max(ts)));
+
+            let author = commit.author();
+            let author_key = format!(
+                "{} <{}>",
+                author.name().unwrap_or(""),
+                author.email().unwrap_or("")
+            );
+            let author_stats = authors.entry(author_key).or_default();
+            author_stats.commits += 1;
+
+            // Merge commits are counted but their churn is attributed to the merged commits
+            if commit.parent_count() > 1 {
+                merge_count += 1;
+                continue;
+            }
+
+            let tree = commit.tree()?;
+            let parent_tree = if commit.parent_count() == 1 {
+                Some(commit.parent(0)?.tree()?)
+            } else {
+                None
+            };
+
+            let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+            let stats = diff.stats()?;
+
+            author_stats.insertions += stats.insertions();
+            author_stats.deletions += stats.deletions();
+            total_insertions += stats.insertions();
+            total_deletions += stats.deletions();
+            total_files_changed += stats.files_changed();
+
+            let mut touched_dirs = HashSet::new();
+
+            for idx in 0..diff.deltas().len() {
+                let delta = diff.get_delta(idx).context("Missing diff delta")?;
+                let file_path = delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                let (insertions, deletions) = match git2::Patch::from_diff(&diff, idx)? {
+                    Some(patch) => {
+                        let (_, ins, del) = patch.line_stats()?;
+                        (ins, del)
+                    }
+                    None => (0, 0),
+                };
+
+                let file_stats = files.entry(file_path.clone()).or_default();
+                file_stats.commits += 1;
+                file_stats.insertions += insertions;
+                file_stats.deletions += deletions;
+
+                let dir = dir_prefix(&file_path, dir_depth);
+                let dir_stats = dirs.entry(dir.clone()).or_default();
+                dir_stats.insertions += insertions;
+                dir_stats.deletions += deletions;
+                if touched_dirs.insert(dir) {
+                    dir_stats.commits += 1;
+                }
+            }
+        }
+
+        let non_merge = commit_count - merge_count;
+        let avg = |total: usize| {
+            if non_merge > 0 {
+                total as f64 / non_merge as f64
+            } else {
+                0.0
+            }
+        };
+
+        let mut author_list: Vec<(String, ChurnStats)> = authors.into_iter().collect();
+        author_list.sort_by(|a, b| b.1.commits.cmp(&a.1.commits).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(json!({
+            "path": path,
+            "commits": commit_count,
+            "merge_commits": merge_count,
+            "first_commit_timestamp": first_ts,
+            "last_commit_timestamp": last_ts,
+            "insertions": total_insertions,
+            "deletions": total_deletions,
+            "average_commit_size": {
+                "insertions": avg(total_insertions),
+                "deletions": avg(total_deletions),
+                "lines_changed": avg(total_insertions + total_deletions),
+                "files_changed": avg(total_files_changed)
+            },
+            "authors": author_list.iter().map(|(name, s)| s.to_json("author", name)).collect::<Vec<_>>(),
+            "hot_files": top_churn(files, top, "file"),
+            "hot_directories": top_churn(dirs, top, "directory"),
+            "truncated": commit_count >= max_commits
+        }))
+    }
+    pub async fn changelog(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+        let to = args["to"].as_str().unwrap_or("HEAD");
+        let version = args["version"].as_str().unwrap_or("Unreleased");
+        let detect_breaking = args["detect_breaking"].as_bool().unwrap_or(true);
+        let include_other = args["include_other"].as_bool().unwrap_or(true);
+        let commit_url = args["commit_url"].as_str();
+        let compare_url = args["compare_url"].as_str();
+
+        let repo = Repository::open(path)?;
+
+        let to_commit = repo.revparse_single(to)
+            .with_context(|| format!("Unknown ref: {}", to))?
+            .peel_to_commit()?;
+
+        let from = match args["from"].as_str() {
+            Some(f) => Some(f.to_string()),
+            None => latest_tag_before(&repo, to_commit.id())?,
+        };
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+        revwalk.push(to_commit.id())?;
+        if let Some(ref f) = from {
+            let from_commit = repo.revparse_single(f)
+                .with_context(|| format!("Unknown ref: {}", f))?
+                .peel_to_commit()?;
+            revwalk.hide(from_commit.id())?;
+        }
+
+        let mut groups: Vec<(&str, Vec<Value>)> = CHANGELOG_SECTIONS
+            .iter()
+            .map(|(_, title)| (*title, Vec::new()))
+            .collect();
+        let mut other = Vec::new();
+        let mut breaking = Vec::new();
+        let mut total = 0usize;
+
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+
+            // Merge commits only repeat what the merged commits already say
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            total += 1;
+
+            let message = commit.message().unwrap_or("");
+            let hash = commit.id().to_string();
+            let short = format!("{:.7}", commit.id());
+
+            let entry_line = |description: &str, scope: Option<&str>| {
+                let scope_str = scope.map(|s| format!("**{}:** ", s)).unwrap_or_default();
+                let link = match commit_url {
+                    Some(tpl) => format!("[{}]({})", short, tpl.replace("{hash}", &hash).replace("{short}", &short)),
+                    None => short.clone(),
+                };
+                format!("- {}{} ({})", scope_str, description, link)
+            };
+
+            match parse_conventional_commit(message) {
+                Some(cc) => {
+                    let line = entry_line(&cc.description, cc.scope.as_deref());
+                    let entry = json!({
+                        "hash": hash,
+                        "type": cc.kind,
+                        "scope": cc.scope,
+                        "description": cc.description,
+                        "breaking": cc.breaking,
+                        "line": line
+                    });
+
+                    if detect_breaking && cc.breaking {
+                        let note = cc.breaking_note.as_deref().unwrap_or(&cc.description);
+                        breaking.push(json!({
+                            "hash": hash,
+                            "line": entry_line(note, cc.scope.as_deref())
+                        }));
+                    }
+
+                    let section = CHANGELOG_SECTIONS
+                        .iter()
+                        .position(|(kind, _)| *kind == cc.kind);
+                    match section {
+                        Some(idx) => groups[idx].1.push(entry),
+                        None if include_other => other.push(entry),
+                        None => {}
+                    }
+                }
+                None if include_other => {
+                    let summary = commit.summary().unwrap_or("");
+                    other.push(json!({
+                        "hash": hash,
+                        "type": null,
+                        "scope": null,
+                        "description": summary,
+                        "breaking": false,
+                        "line": entry_line(summary, None)
+                    }));
+                }
+                None => {}
+            }
+        }
+
+        let from_label = from.clone().unwrap_or_default();
+        let heading = match compare_url {
+            Some(tpl) if from.is_some() => format!(
+                "## [{}]({})",
+                version,
+                tpl.replace("{from}", &from_label).replace("{to}", to)
+            ),
+            _ => format!("## {}", version),
+        };
+
+        let mut markdown = format!("{}\n", heading);
+        let mut push_section = |title: &str, entries: &[Value]| {
+            if entries.is_empty() {
+                return;
+            }
+            markdown.push_str(&format!("\n### {}\n\n", title));
+            for entry in entries {
+                markdown.push_str(entry["line"].as_str().unwrap_or(""));
+                markdown.push('\n');
+            }
+        };
+
+        push_section("⚠ BREAKING CHANGES", &breaking);
+        for (title, entries) in &groups {
+            push_section(title, entries);
+        }
+        push_section("Other Changes", &other);
+
+        let sections: serde_json::Map<String, Value> = groups
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(title, entries)| (title.to_string(), json!(entries)))
+            .collect();
+
+        Ok(json!({
+            "from": from,
+            "to": to,
+            "version": version,
+            "commits": total,
+            "breaking_changes": breaking,
+            "sections": sections,
+            "other": other,
+            "markdown": markdown
+        }))
+    }
+    pub async fn apply(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+        let location_str = args["location"].as_str().unwrap_or("workdir");
+        let check_only = args["check"].as_bool().unwrap_or(false);
+        let allow_partial = args["allow_partial"].as_bool().unwrap_or(false);
+        let three_way = args["three_way"].as_bool().unwrap_or(true);
+
+        let patch_text = if let Some(patch) = args["patch"].as_str() {
+            patch.to_string()
+        } else if let Some(patch_file) = args["patch_file"].as_str() {
+            std::fs::read_to_string(patch_file)
+                .with_context(|| format!("Failed to read patch file: {}", patch_file))?
+        } else {
+            anyhow::bail!("Must provide either 'patch' or 'patch_file'");
+        };
+
+        let location = match location_str {
+            "workdir" => ApplyLocation::WorkDir,
+            "index" => ApplyLocation::Index,
+            "both" => ApplyLocation::Both,
+            _ => anyhow::bail!("Invalid location: {}. Must be 'workdir', 'index' or 'both'", location_str),
+        };
+
+        let repo = Repository::open(path)?;
+        let diff = Diff::from_buffer(patch_text.as_bytes()).context("Failed to parse patch")?;
+        let reversed = Diff::from_buffer(reverse_patch(&patch_text).as_bytes()).ok();
+
+        // Classify every hunk on its own so the report says exactly what would happen
+        let hunks = collect_hunks(&diff)?;
+        let mut applicable = Vec::new();
+        let mut already_applied = Vec::new();
+        let mut failed = Vec::new();
+
+        for idx in 0..hunks.len() {
+            if hunk_applies(&repo, &diff, location, idx) {
+                applicable.push(idx);
+            } else if reversed.as_ref().is_some_and(|r| hunk_applies(&repo, r, location, idx)) {
+                already_applied.push(idx);
+            } else {
+                failed.push(idx);
+            }
+        }
+
+        let report = |indices: &[usize]| indices.iter().map(|i| hunks[*i].clone()).collect::<Vec<_>>();
+
+        let mut result = json!({
+            "location": location_str,
+            "check": check_only,
+            "total_hunks": hunks.len(),
+            "applied": report(&applicable),
+            "already_applied": report(&already_applied),
+            "failed": report(&failed),
+            "three_way": false
+        });
+
+        if check_only {
+            result["success"] = json!(failed.is_empty() && already_applied.is_empty());
+            return Ok(result);
+        }
+
+        if failed.is_empty() && already_applied.is_empty() {
+            repo.apply(&diff, location, None).context("Failed to apply patch")?;
+            result["success"] = json!(true);
+            return Ok(result);
+        }
+
+        if three_way && location_str != "workdir" {
+            let output = run_git_apply_3way(path, &patch_text, location_str == "index")?;
+            let conflicts = conflicted_paths(path)?;
+
+            result["three_way"] = json!(true);
+            result["success"] = json!(output.status.success() && conflicts.is_empty());
+            result["conflicts"] = json!(conflicts);
+            result["stderr"] = json!(String::from_utf8_lossy(&output.stderr).to_string());
+            return Ok(result);
+        }
+
+        if allow_partial && !applicable.is_empty() {
+            let mut hunk_idx = 0usize;
+            let mut opts = ApplyOptions::new();
+            opts.hunk_callback(|_| {
+                let keep = applicable.contains(&hunk_idx);
+                hunk_idx += 1;
+                keep
+            });
+            repo.apply(&diff, location, Some(&mut opts)).context("Failed to apply patch")?;
+            result["success"] = json!(false);
+            result["partial"] = json!(true);
+            return Ok(result);
+        }
+
+        // Nothing was written, so nothing counts as applied
+        result["applied"] = json!([]);
+        result["applicable"] = json!(report(&applicable));
+        result["success"] = json!(false);
+        if three_way {
+            result["message"] = json!("Three-way fallback needs the index; use location 'index' or 'both'");
+        }
+        Ok(result)
+    }
+
+    pub async fn ignore(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+        let action = args["action"].as_str().unwrap_or("check");
+        let file = args["file"].as_str().unwrap_or("gitignore");
+
+        let repo = Repository::open(path)?;
+        let workdir = repo.workdir().context("Repository has no working directory")?.to_path_buf();
+
+        let ignore_file = match file {
+            "gitignore" => workdir.join(".gitignore"),
+            "exclude" => repo.path().join("info").join("exclude"),
+            _ => anyhow::bail!("Invalid file: {}. Must be 'gitignore' or 'exclude'", file),
+        };
+
+        let string_list = |key: &str| -> Result<Vec<String>> {
+            let arr = args[key].as_array()
+                .with_context(|| format!("Missing '{}' parameter", key))?;
+            Ok(arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect())
+        };
+
+        match action {
+            "check" => {
+                let paths = string_list("paths")?;
+                let rules = check_ignore_rules(&workdir, &paths);
+                let mut results = Vec::new();
+
+                for p in &paths {
+                    let ignored = repo.is_path_ignored(p)?;
+                    let rule = rules.get(p).filter(|_| ignored);
+                    results.push(json!({
+                        "path": p,
+                        "ignored": ignored,
+                        "source": rule.map(|r| &r.0),
+                        "line": rule.map(|r| r.1),
+                        "pattern": rule.map(|r| &r.2)
+                    }));
+                }
+
+                Ok(json!({
+                    "results": results,
+                    "ignored_count": results.iter().filter(|r| r["ignored"] == true).count()
+                }))
+            }
+            "list" => {
+                let content = std::fs::read_to_string(&ignore_file).unwrap_or_default();
+                let patterns: Vec<&str> = content
+                    .lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .collect();
+
+                Ok(json!({
+                    "file": ignore_file,
+                    "exists": ignore_file.exists(),
+                    "patterns": patterns,
+                    "count": patterns.len()
+                }))
+            }
+            "add" => {
+                let patterns = string_list("patterns")?;
+                let mut content = std::fs::read_to_string(&ignore_file).unwrap_or_default();
+                let existing: HashSet<String> = content.lines().map(|l| l.trim().to_string()).collect();
+
+                let added: Vec<&String> = patterns.iter().filter(|p| !existing.contains(p.trim())).collect();
+                let skipped: Vec<&String> = patterns.iter().filter(|p| existing.contains(p.trim())).collect();
+
+                if !added.is_empty() {
+                    if !content.is_empty() && !content.ends_with('\n') {
+                        content.push('\n');
+                    }
+                    if let Some(comment) = args["comment"].as_str() {
+                        content.push_str(&format!("# {}\n", comment.trim_start_matches('#').trim()));
+                    }
+                    for pattern in &added {
+                        content.push_str(pattern.trim());
+                        content.push('\n');
+                    }
+
+                    if let Some(parent) = ignore_file.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&ignore_file, &content)
+                        .with_context(|| format!("Failed to write: {}", ignore_file.display()))?;
+                }
+
+                Ok(json!({
+                    "success": true,
+                    "file": ignore_file,
+                    "added": added,
+                    "already_present": skipped
+                }))
+            }
+            "remove" => {
+                let patterns = string_list("patterns")?;
+                let content = std::fs::read_to_string(&ignore_file)
+                    .with_context(|| format!("Failed to read: {}", ignore_file.display()))?;
+
+                let mut removed = Vec::new();
+                let kept: Vec<&str> = content
+                    .lines()
+                    .filter(|line| {
+                        let matched = patterns.iter().any(|p| p.trim() == line.trim());
+                        if matched {
+                            removed.push(line.trim().to_string());
+                        }
+                        !matched
+                    })
+                    .collect();
+
+                let mut new_content = kept.join("\n");
+                if !new_content.is_empty() {
+                    new_content.push('\n');
+                }
+                std::fs::write(&ignore_file, new_content)
+                    .with_context(|| format!("Failed to write: {}", ignore_file.display()))?;
+
+                let not_found: Vec<&String> = patterns.iter().filter(|p| !removed.contains(&p.trim().to_string())).collect();
+
+                Ok(json!({
+                    "success": true,
+                    "file": ignore_file,
+                    "removed": removed,
+                    "not_found": not_found
+                }))
+            }
+            _ => Err(anyhow::anyhow!("Unknown action: {}", action)),
+        }
+    }
+
+    pub async fn discover(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+
+        // Not being inside a repository is an answer, not an error
+        let repo = match Repository::discover(path) {
+            Ok(repo) => repo,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Ok(json!({
+                    "found": false,
+                    "path": path,
+                    "message": e.message()
+                }));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut remotes = Vec::new();
+        for name in repo.remotes()?.iter().flatten() {
+            let remote = repo.find_remote(name)?;
+            remotes.push(json!({
+                "name": name,
+                "url": remote.url(),
+                "push_url": remote.pushurl().or(remote.url())
+            }));
+        }
+
+        let (current_branch, is_detached) = match repo.head() {
+            Ok(head) => (head.shorthand().map(|s| s.to_string()), !head.is_branch()),
+            // Unborn HEAD: report the branch it points at
+            Err(_) => (
+                repo.find_reference("HEAD").ok()
+                    .and_then(|r| r.symbolic_target().map(|t| t.trim_start_matches("refs/heads/").to_string())),
+                false,
+            ),
+        };
+
+        let (default_branch, default_branch_source) = match default_branch(&repo) {
+            Some((branch, source)) => (Some(branch), Some(source)),
+            None => (None, None),
+        };
+
+        let superproject = superproject_of(&repo);
+
+        Ok(json!({
+            "found": true,
+            "toplevel": repo.workdir(),
+            "git_dir": repo.path(),
+            "is_bare": repo.is_bare(),
+            "is_worktree": repo.is_worktree(),
+            "is_shallow": repo.is_shallow(),
+            "is_empty": repo.is_empty().unwrap_or(false),
+            "is_submodule": superproject.is_some(),
+            "superproject": superproject,
+            "current_branch": current_branch,
+            "is_detached": is_detached,
+            "default_branch": default_branch,
+            "default_branch_source": default_branch_source,
+            "remotes": remotes,
+            "state": format!("{:?}", repo.state())
+        }))
+    }
+
+}
+
+#[async_trait]
+impl ToolModule for GitModule {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        // Every tool reads the repository at `path`
+        provenance::read(args["path"].as_str().unwrap_or("."));
+        match tool {
+            "git_status" => self.status(args).await,
+            "git_diff" => self.diff(args).await?.into_value(),
+            "git_commit" => self.commit(args).await,
+            "git_branch" => self.branch(args).await,
+            "git_checkout" => self.checkout(args).await,
+            "git_blame" => self.blame(args).await,
+            "git_log" => self.log(args).await,
+            "git_tag" => self.tag(args).await,
+            "git_stats" => self.stats(args).await,
+            "git_changelog" => self.changelog(args).await,
+            "git_apply" => self.apply(args).await,
+            "git_ignore" => self.ignore(args).await,
+            "git_discover" => self.discover(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn call_output(&self, tool: &str, args: Value) -> Result<ToolOutput> {
+        match tool {
+            "git_diff" => self.diff(args).await,
+            _ => Ok(self.call(tool, args).await?.into()),
+        }
+    }
+}
+
+/// One line of git_diff's `patches`
+#[derive(Serialize)]
+struct DiffLine {
+    origin: char,
+    content: String,
+}
+
+#[derive(Default)]
+struct ChurnStats {
+    commits: usize,
+    insertions: usize,
+    deletions: usize,
+}
+
+impl ChurnStats {
+    fn to_json(&self, key: &str, name: &str) -> Value {
+        json!({
+            key: name,
+            "commits": self.commits,
+            "insertions": self.insertions,
+            "deletions": self.deletions,
+            "churn": self.insertions + self.deletions
+        })
+    }
+}
+
+/// Sort entries by number of commits touching them (then churn) and keep the top N
+fn top_churn(entries: HashMap<String, ChurnStats>, top: usize, key: &str) -> Vec<Value> {
+    let mut list: Vec<(String, ChurnStats)> = entries.into_iter().collect();
+    list.sort_by(|a, b| {
+        b.1.commits
+            .cmp(&a.1.commits)
+            .then_with(|| (b.1.insertions + b.1.deletions).cmp(&(a.1.insertions + a.1.deletions)))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    list.truncate(top);
+    list.iter().map(|(name, s)| s.to_json(key, name)).collect()
+}
+
+/// First `depth` directory components of a repository-relative path ("." for top-level files)
+fn dir_prefix(file_path: &str, depth: usize) -> String {
+    let components: Vec<&str> = file_path.split('/').collect();
+    if components.len() <= 1 {
+        return ".".to_string();
+    }
+    let dir_components = &components[..components.len() - 1];
+    dir_components[..depth.min(dir_components.len())].join("/")
+}
+
+/// Parse an RFC3339 timestamp or a plain YYYY-MM-DD date (midnight UTC) into unix seconds
+fn parse_date_arg(s: &str) -> Result<i64> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.timestamp());
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date (expected RFC3339 or YYYY-MM-DD): {}", s))?;
+    Ok(date.and_hms_opt(0, 0, 0).context("Invalid date")?.and_utc().timestamp())
+}
+
+/// Conventional-commit types in the order their changelog sections are emitted
+const CHANGELOG_SECTIONS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("revert", "Reverts"),
+    ("docs", "Documentation"),
+    ("test", "Tests"),
+    ("build", "Build System"),
+    ("ci", "Continuous Integration"),
+    ("style", "Style"),
+    ("chore", "Chores"),
+];
+
+struct ConventionalCommit {
+    kind: String,
+    scope: Option<String>,
+    description: String,
+    breaking: bool,
+    breaking_note: Option<String>,
+}
+
+/// Parse `type(scope)!: description` plus a `BREAKING CHANGE:` footer
+fn parse_conventional_commit(message: &str) -> Option<ConventionalCommit> {
+    let header_re = Regex::new(r"^(\w+)(?:\(([^)]*)\))?(!)?: (.+)$").ok()?;
+    let header = message.lines().next()?.trim();
+    let caps = header_re.captures(header)?;
+
+    let breaking_note = message.lines().find_map(|line| {
+        line.strip_prefix("BREAKING CHANGE:")
+            .or_else(|| line.strip_prefix("BREAKING-CHANGE:"))
+            .map(|note| note.trim().to_string())
+    });
+
+    Some(ConventionalCommit {
+        kind: caps[1].to_lowercase(),
+        scope: caps.get(2).map(|m| m.as_str().to_string()).filter(|s| !s.is_empty()),
+        description: caps[4].trim().to_string(),
+        breaking: caps.get(3).is_some() || breaking_note.is_some(),
+        breaking_note,
+    })
+}
+
+/// Most recent tag whose commit is an ancestor of `target` (excluding `target` itself)
+fn latest_tag_before(repo: &Repository, target: git2::Oid) -> Result<Option<String>> {
+    let mut best: Option<(i64, String)> = None;
+
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let commit = match repo
+            .revparse_single(&format!("refs/tags/{}", name))
+            .and_then(|obj| obj.peel_to_commit())
+        {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        if commit.id() == target || !repo.graph_descendant_of(target, commit.id())? {
+            continue;
+        }
+
+        let ts = commit.time().seconds();
+        if best.as_ref().is_none_or(|(best_ts, _)| ts > *best_ts) {
+            best = Some((ts, name.to_string()));
+        }
+    }
+
+    Ok(best.map(|(_, name)| name))
+}
+
+/// File and header of every hunk in a parsed diff, in the order libgit2 applies them
+fn collect_hunks(diff: &Diff) -> Result<Vec<Value>> {
+    let mut hunks = Vec::new();
+
+    for idx in 0..diff.deltas().len() {
+        let Some(patch) = git2::Patch::from_diff(diff, idx)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let file = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        for hunk_idx in 0..patch.num_hunks() {
+            let (hunk, _) = patch.hunk(hunk_idx)?;
+            hunks.push(json!({
+                "file": file,
+                "header": String::from_utf8_lossy(hunk.header()).trim_end().to_string(),
+                "old_start": hunk.old_start(),
+                "old_lines": hunk.old_lines(),
+                "new_start": hunk.new_start(),
+                "new_lines": hunk.new_lines()
+            }));
+        }
+    }
+
+    Ok(hunks)
+}
+
+/// Dry-run a single hunk of `diff` against the repository
+fn hunk_applies(repo: &Repository, diff: &Diff, location: ApplyLocation, target: usize) -> bool {
+    let mut hunk_idx = 0usize;
+    let mut opts = ApplyOptions::new();
+    opts.check(true);
+    opts.hunk_callback(|_| {
+        let keep = hunk_idx == target;
+        hunk_idx += 1;
+        keep
+    });
+    repo.apply(diff, location, Some(&mut opts)).is_ok()
+}
+
+/// Swap the old and new sides of a unified diff so it can be checked in reverse
+fn reverse_patch(patch: &str) -> String {
+    let hunk_re = Regex::new(r"^@@ -(\S+) \+(\S+) @@(.*)$").expect("valid hunk regex");
+    let mut out = String::with_capacity(patch.len());
+    let mut lines = patch.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(old) = line.strip_prefix("--- ") {
+            if let Some(new) = lines.peek().and_then(|l| l.strip_prefix("+++ ")) {
+                out.push_str(&format!("--- {}\n+++ {}\n", swap_side(new, "b/", "a/"), swap_side(old, "a/", "b/")));
+                lines.next();
+                continue;
+            }
+        }
+
+        let reversed = if let Some(caps) = hunk_re.captures(line) {
+            format!("@@ -{} +{} @@{}", &caps[2], &caps[1], &caps[3])
+        } else if let Some(rest) = line.strip_prefix("new file mode") {
+            format!("deleted file mode{}", rest)
+        } else if let Some(rest) = line.strip_prefix("deleted file mode") {
+            format!("new file mode{}", rest)
+        } else if let Some(rest) = line.strip_prefix('+') {
+            format!("-{}", rest)
+        } else if let Some(rest) = line.strip_prefix('-') {
+            format!("+{}", rest)
+        } else {
+            line.to_string()
+        };
+
+        out.push_str(&reversed);
+        out.push('\n');
+    }
+
+    out
+}
+
+fn swap_side(path: &str, from: &str, to: &str) -> String {
+    match path.strip_prefix(from) {
+        Some(rest) => format!("{}{}", to, rest),
+        None => path.to_string(),
+    }
+}
+
+/// The git CLI, for what libgit2 cannot do. On Windows it is told to allow paths
+/// past MAX_PATH, as in deep node_modules trees, which Git for Windows refuses by default.
+fn git_command() -> Command {
+    let mut cmd = Command::new("git");
+    if cfg!(windows) {
+        cmd.arg("-c").arg("core.longpaths=true");
+    }
+    cmd
+}
+
+fn run_git_apply_3way(path: &str, patch: &str, cached: bool) -> Result<std::process::Output> {
+    use std::io::Write as _;
+
+    let mut cmd = git_command();
+    cmd.arg("apply").arg("--3way");
+    if cached {
+        cmd.arg("--cached");
+    }
+
+    cmd.current_dir(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let (mut child, tracked) = children::spawn(&mut cmd).context("Failed to run git apply --3way")?;
+
+    child
+        .stdin
+        .take()
+        .context("git apply stdin not available")?
+        .write_all(patch.as_bytes())?;
+
+    Ok(tracked.finish(child.wait_with_output())?)
+}
+
+fn conflicted_paths(path: &str) -> Result<Vec<String>> {
+    let repo = Repository::open(path)?;
+    let index = repo.index()?;
+    let mut paths = Vec::new();
+
+    if index.has_conflicts() {
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.push(String::from_utf8_lossy(&entry.path).to_string());
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Matching rule per path as reported by `git check-ignore -v` (source file, line, pattern).
+/// libgit2 can only answer yes/no, so the rule lookup is best-effort via the git CLI.
+fn check_ignore_rules(workdir: &Path, paths: &[String]) -> HashMap<String, (String, u64, String)> {
+    let mut rules = HashMap::new();
+
+    let output = match telemetry::run_command(
+        git_command()
+            .arg("check-ignore")
+            .arg("-v")
+            .arg("--no-index")
+            .arg("--")
+            .args(paths)
+            .current_dir(workdir),
+    ) {
+        Ok(output) => output,
+        Err(_) => return rules,
+    };
+
+    // Format: <source>:<linenum>:<pattern>\t<pathname>
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((rule, pathname)) = line.split_once('\t') else {
+            continue;
+        };
+        let mut parts = rule.splitn(3, ':');
+        if let (Some(source), Some(line_num), Some(pattern)) = (parts.next(), parts.next(), parts.next()) {
+            rules.insert(
+                pathname.to_string(),
+                (source.to_string(), line_num.parse().unwrap_or(0), pattern.to_string()),
+            );
+        }
+    }
+
+    rules
+}
+
+/// Run `git blame --porcelain --ignore-revs-file` and fold consecutive lines from the
+/// same commit into hunks shaped like the libgit2 output
+fn blame_with_ignore_revs(
+    workdir: &Path,
+    file: &str,
+    revs_file: &Path,
+    line_start: Option<usize>,
+    line_end: Option<usize>,
+    include_content: bool,
+) -> Result<Vec<Value>> {
+    let mut cmd = git_command();
+    cmd.arg("blame")
+        .arg("--porcelain")
+        .arg("--ignore-revs-file")
+        .arg(revs_file);
+    if line_start.is_some() || line_end.is_some() {
+        let range = format!(
+            "{},{}",
+            line_start.unwrap_or(1),
+            line_end.map(|e| e.to_string()).unwrap_or_default()
+        );
+        cmd.arg("-L").arg(range);
+    }
+    let output = telemetry::run_command(cmd.arg("HEAD").arg("--").arg(file).current_dir(workdir))
+        .context("Failed to run git blame")?;
+
+    if !output.status.success() {
+        anyhow::bail!("git blame failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Commit metadata is only printed the first time a commit appears
+    let mut commits: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut hunks: Vec<Value> = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+
+    for line in stdout.lines() {
+        if let Some(text) = line.strip_prefix('\t') {
+            let Some((sha, final_line)) = current.take() else {
+                continue;
+            };
+            let meta = commits.get(&sha).cloned().unwrap_or_default();
+
+            let extends_last = hunks.last().is_some_and(|h: &Value| {
+                h["commit"] == sha.as_str() && h["line_end"].as_u64() == Some(final_line as u64 - 1)
+            });
+
+            if extends_last {
+                let last = hunks.last_mut().expect("checked above");
+                last["line_end"] = json!(final_line);
+                last["line_count"] = json!(last["line_count"].as_u64().unwrap_or(0) + 1);
+                if include_content {
+                    if let Some(content) = last["content"].as_array_mut() {
+                        content.push(json!(text));
+                    }
+                }
+            } else {
+                let mut entry = json!({
+                    "line_start": final_line,
+                    "line_end": final_line,
+                    "line_count": 1,
+                    "commit": sha,
+                    "author": meta.get("author").cloned().unwrap_or_default(),
+                    "email": meta.get("author-mail").map(|m| m.trim_matches(|c| c == '<' || c == '>').to_string()).unwrap_or_default(),
+                    "timestamp": meta.get("author-time").and_then(|t| t.parse::<i64>().ok()).unwrap_or(0),
+                    "message": meta.get("summary").cloned().unwrap_or_default()
+                });
+                if include_content {
+                    entry["content"] = json!([text]);
+                }
+                hunks.push(entry);
+            }
+            continue;
+        }
+
+        let mut parts = line.split(' ');
+        let first = parts.next().unwrap_or("");
+        if first.len() == 40 && first.chars().all(|c| c.is_ascii_hexdigit()) {
+            let final_line = parts.nth(1).and_then(|l| l.parse().ok()).unwrap_or(0);
+            commits.entry(first.to_string()).or_default();
+            current = Some((first.to_string(), final_line));
+        } else if let Some((sha, _)) = &current {
+            if let Some((key, value)) = line.split_once(' ') {
+                commits.entry(sha.clone()).or_default().insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    Ok(hunks)
+}
+
+/// Best guess at the repository's default branch, with where the answer came from:
+/// the remote HEAD, a local main/master branch, init.defaultBranch, or the current branch.
+fn default_branch(repo: &Repository) -> Option<(String, &'static str)> {
+    let mut remote_names: Vec<String> = repo.remotes().ok()
+        .map(|r| r.iter().flatten().map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    // Prefer origin when several remotes exist
+    remote_names.sort_by_key(|name| name != "origin");
+
+    for remote in &remote_names {
+        let head_ref = format!("refs/remotes/{}/HEAD", remote);
+        if let Some(target) = repo.find_reference(&head_ref).ok().and_then(|r| r.symbolic_target().map(|t| t.to_string())) {
+            let prefix = format!("refs/remotes/{}/", remote);
+            return Some((target.trim_start_matches(&prefix).to_string(), "remote_head"));
+        }
+    }
+
+    for candidate in ["main", "master"] {
+        if repo.find_branch(candidate, BranchType::Local).is_ok() {
+            return Some((candidate.to_string(), "local_branch"));
+        }
+    }
+
+    if let Some(configured) = repo.config().ok().and_then(|c| c.get_string("init.defaultBranch").ok()) {
+        return Some((configured, "config"));
+    }
+
+    repo.head().ok()
+        .filter(|h| h.is_branch())
+        .and_then(|h| h.shorthand().map(|s| (s.to_string(), "current_branch")))
+}
+
+/// If the repository is checked out as a submodule, return the enclosing
+/// repository's toplevel and the submodule's path within it.
+fn superproject_of(repo: &Repository) -> Option<Value> {
+    let workdir = simplify(&repo.workdir()?.canonicalize().ok()?);
+    let parent = Repository::discover(workdir.parent()?).ok()?;
+    let parent_workdir = simplify(&parent.workdir()?.canonicalize().ok()?);
+
+    let submodules = parent.submodules().ok()?;
+    let submodule = submodules.iter().find(|sm| parent_workdir.join(sm.path()) == workdir)?;
+
+    Some(json!({
+        "toplevel": parent_workdir,
+        "submodule_path": submodule.path(),
+        "submodule_name": submodule.name()
+    }))
 }
@@ -1,24 +1,44 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use gitent_core::{Storage, Session, Change, ChangeType, Commit};
 use uuid::Uuid;
+use crate::config::GitentConfig;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+
+/// Start of every encrypted content blob, followed by the nonce and the ciphertext
+const BLOB_MAGIC: &[u8] = b"GTE1";
 
 pub struct GitentModule {
     state: Arc<Mutex<Option<GitentState>>>,
+    // Database path from [gitent] db_path, used when neither the call nor GITENT_DB_PATH sets one
+    default_db_path: Option<PathBuf>,
+    // [gitent] encryption_key_file, used when GITENT_ENCRYPTION_KEY is not set
+    encryption_key_file: Option<PathBuf>,
 }
 
 struct GitentState {
     storage: Storage,
     session: Session,
     db_path: PathBuf,
+    // Encrypts the file contents stored with changes; None stores them as they are
+    cipher: Option<BlobCipher>,
 }
 
 impl GitentModule {
     pub fn new() -> Self {
+        Self::with_config(&GitentConfig::default())
+    }
+
+    pub fn with_config(config: &GitentConfig) -> Self {
         Self {
             state: Arc::new(Mutex::new(None)),
+            default_db_path: config.db_path.clone(),
+            encryption_key_file: config.encryption_key_file.clone(),
         }
     }
 
@@ -36,7 +56,7 @@ impl GitentModule {
                         },
                         "db_path": {
                             "type": "string",
-                            "description": "Database path (defaults to .gitent/gitent.db or GITENT_DB_PATH env var)"
+                            "description": "Database path (defaults to GITENT_DB_PATH env var, then [gitent] db_path from the config file, then .gitent/gitent.db)"
                         },
                         "force_new": {
                             "type": "boolean",
@@ -180,7 +200,8 @@ impl GitentModule {
         let force_new = args["force_new"].as_bool().unwrap_or(false);
 
         let root_path = PathBuf::from(path);
-        let db_path = Self::get_db_path(db_path_arg);
+        let db_path = self.get_db_path(db_path_arg);
+        let cipher = self.load_cipher()?;
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = db_path.parent() {
@@ -212,10 +233,12 @@ impl GitentModule {
 
         // Update module state
         let mut state_guard = self.state.lock().unwrap();
+        let encrypted = cipher.is_some();
         *state_guard = Some(GitentState {
             storage,
             session: session.clone(),
             db_path: db_path.clone(),
+            cipher,
         });
 
         Ok(json!({
@@ -224,7 +247,8 @@ impl GitentModule {
             "root_path": session.root_path.to_string_lossy(),
             "started": session.started.to_rfc3339(),
             "db_path": db_path.to_string_lossy(),
-            "active": session.active
+            "active": session.active,
+            "encrypted": encrypted
         }))
     }
 
@@ -288,6 +312,10 @@ impl GitentModule {
                 change = change.with_content_after(content.as_bytes().to_vec());
             }
         }
+        // Sealed after the content hashes are taken, so they still identify the plaintext
+        if let Some(cipher) = &state.cipher {
+            cipher.seal_change(&mut change)?;
+        }
 
         // Handle rename
         if change_type == ChangeType::Rename {
@@ -423,6 +451,7 @@ impl GitentModule {
             // Get uncommitted changes
             state.storage.get_uncommitted_changes(&state.session.id)?
         };
+        let changes = Self::open_changes(state, changes)?;
 
         // Apply file filter if specified
         let filtered_changes: Vec<_> = if let Some(filter) = file_filter {
@@ -489,6 +518,7 @@ impl GitentModule {
         let changes: Vec<_> = commit.changes.iter()
             .filter_map(|id| state.storage.get_change(id).ok())
             .collect();
+        let changes = Self::open_changes(state, changes)?;
 
         if !execute {
             // Preview mode - show what would be restored
@@ -548,16 +578,39 @@ impl GitentModule {
 
     // Helper methods
 
-    fn get_db_path(custom_path: Option<&str>) -> PathBuf {
+    fn get_db_path(&self, custom_path: Option<&str>) -> PathBuf {
         if let Some(path) = custom_path {
             PathBuf::from(path)
         } else if let Ok(env_path) = std::env::var("GITENT_DB_PATH") {
             PathBuf::from(env_path)
+        } else if let Some(path) = &self.default_db_path {
+            path.clone()
         } else {
             PathBuf::from(".gitent/gitent.db")
         }
     }
 
+    /// The content cipher, from GITENT_ENCRYPTION_KEY or else [gitent] encryption_key_file
+    fn load_cipher(&self) -> Result<Option<BlobCipher>> {
+        let key = match std::env::var("GITENT_ENCRYPTION_KEY") {
+            Ok(key) => key,
+            Err(_) => match &self.encryption_key_file {
+                Some(path) => std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read gitent encryption key file {}", path.display()))?,
+                None => return Ok(None),
+            },
+        };
+        BlobCipher::new(key.trim()).map(Some)
+    }
+
+    /// Decrypt the contents of changes read from the database
+    fn open_changes(state: &GitentState, mut changes: Vec<Change>) -> Result<Vec<Change>> {
+        for change in &mut changes {
+            BlobCipher::open_change(state.cipher.as_ref(), change)?;
+        }
+        Ok(changes)
+    }
+
     fn ensure_session<'a>(state_guard: &'a std::sync::MutexGuard<'a, Option<GitentState>>) -> Result<&'a GitentState> {
         state_guard.as_ref().ok_or_else(|| {
             anyhow::anyhow!(
@@ -648,3 +701,119 @@ impl GitentModule {
         }
     }
 }
+
+/// ChaCha20-Poly1305 over the file contents stored with changes. Each blob gets a
+/// random nonce and is bound to its change and side (before/after), so blobs cannot
+/// be swapped between changes without failing to decrypt.
+///
+/// Not yet built: the gitent feature needs gitent-core, which is not a dependency
+/// yet (see Cargo.toml), so this is undocumented until it compiles and has tests.
+struct BlobCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl BlobCipher {
+    /// From a 32-byte key written as 64 hex digits or in base64
+    fn new(key: &str) -> Result<Self> {
+        let bytes = if key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            (0..64)
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&key[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()?
+        } else {
+            use base64::Engine;
+            base64::engine::general_purpose::STANDARD
+                .decode(key)
+                .unwrap_or_default()
+        };
+        if bytes.len() != 32 {
+            anyhow::bail!("The gitent encryption key must be 32 bytes, as 64 hex digits or base64 (e.g. from `openssl rand -hex 32`)");
+        }
+        Ok(Self { cipher: ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&bytes)) })
+    }
+
+    fn seal_change(&self, change: &mut Change) -> Result<()> {
+        let id = change.id;
+        for (side, content) in [("before", &mut change.content_before), ("after", &mut change.content_after)] {
+            if let Some(plain) = content.take() {
+                *content = Some(self.seal(&plain, &aad(&id, side))?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypt a change's contents in place. Contents stored without encryption,
+    /// before a key was set, are left as they are.
+    fn open_change(cipher: Option<&Self>, change: &mut Change) -> Result<()> {
+        let id = change.id;
+        for (side, content) in [("before", &mut change.content_before), ("after", &mut change.content_after)] {
+            let Some(blob) = content.as_deref().filter(|blob| blob.starts_with(BLOB_MAGIC)) else {
+                continue;
+            };
+            let cipher = cipher.with_context(|| {
+                format!("Contents of {} are encrypted; set GITENT_ENCRYPTION_KEY or [gitent] encryption_key_file", change.path.display())
+            })?;
+            *content = Some(cipher.open(blob, &aad(&id, side)).with_context(|| {
+                format!("Failed to decrypt the contents of {}", change.path.display())
+            })?);
+        }
+        Ok(())
+    }
+
+    fn seal(&self, plain: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: plain, aad })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt change contents"))?;
+        Ok([BLOB_MAGIC, nonce.as_slice(), &sealed].concat())
+    }
+
+    fn open(&self, blob: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let rest = &blob[BLOB_MAGIC.len()..];
+        if rest.len() < 12 {
+            anyhow::bail!("Encrypted blob is truncated");
+        }
+        let (nonce, sealed) = rest.split_at(12);
+        self.cipher
+            .decrypt(nonce.into(), Payload { msg: sealed, aad })
+            .map_err(|_| anyhow::anyhow!("Encrypted blob does not match its key or change"))
+    }
+}
+
+/// What a blob is bound to: its change and which side of it
+fn aad(id: &Uuid, side: &str) -> Vec<u8> {
+    [id.as_bytes().as_slice(), side.as_bytes()].concat()
+}
+
+#[async_trait]
+impl ToolModule for GitentModule {
+    fn name(&self) -> &str {
+        "gitent"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "gitent_init" => self.init(args).await,
+            "gitent_status" => self.status(args).await,
+            "gitent_track" => self.track(args).await,
+            "gitent_commit" => self.commit(args).await,
+            "gitent_log" => self.log(args).await,
+            "gitent_diff" => self.diff(args).await,
+            "gitent_rollback" => self.rollback(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        // Dropping the storage closes the database, writing out anything still pending
+        if let Some(state) = self.state.lock().unwrap().take() {
+            tracing::info!("Closed gitent storage {}", state.db_path.display());
+        }
+        Ok(())
+    }
+}
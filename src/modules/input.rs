@@ -1,11 +1,54 @@
 use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use crate::config::{ClipboardBackend, InputConfig, QuietHours};
+use crate::error::PolyError;
+use crate::logging;
+use crate::registry::ToolModule;
+use crate::sampling::{self, ClientBridge};
+use base64::Engine;
 use cli_clipboard::{ClipboardContext, ClipboardProvider};
-use dialoguer::{Input, Select};
+use dialoguer::{Confirm, Input, Select};
 use indicatif::{ProgressBar, ProgressStyle};
+use is_terminal::IsTerminal;
 use notify_rust::Notification;
 use serde_json::{json, Value};
+use std::io::Write as _;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub struct InputModule;
+/// The virtual clipboard: what was last written to the clipboard through this server
+static VIRTUAL_CLIPBOARD: Mutex<Option<String>> = Mutex::new(None);
+
+/// input_notify's history, shared by every client like the desktop it goes to
+static NOTIFICATIONS: Mutex<NotificationLog> = Mutex::new(NotificationLog {
+    delivered: VecDeque::new(),
+    suppressed: BTreeMap::new(),
+});
+
+/// The span [input] max_notifications_per_minute counts over
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long input_notify waits for one of its buttons to be clicked
+const DEFAULT_ACTION_WAIT: Duration = Duration::from_secs(60);
+
+struct NotificationLog {
+    // When each notification of the last minute went out
+    delivered: VecDeque<Instant>,
+    // Notifications held back since the last one delivered, by title
+    suppressed: BTreeMap<String, usize>,
+}
+
+pub struct InputModule {
+    // [input] clipboard and osc52, the defaults for calls that do not choose
+    clipboard: ClipboardBackend,
+    osc52: bool,
+    // [input] do-not-disturb settings for input_notify
+    quiet_hours: Option<QuietHours>,
+    quiet_hours_allow_critical: bool,
+    max_notifications_per_minute: Option<u32>,
+}
 
 impl Default for InputModule {
     fn default() -> Self {
@@ -15,14 +58,24 @@ impl Default for InputModule {
 
 impl InputModule {
     pub fn new() -> Self {
-        Self
+        Self::with_config(&InputConfig::default())
+    }
+
+    pub fn with_config(config: &InputConfig) -> Self {
+        Self {
+            clipboard: config.clipboard,
+            osc52: config.osc52,
+            quiet_hours: config.quiet_hours,
+            quiet_hours_allow_critical: config.quiet_hours_allow_critical,
+            max_notifications_per_minute: config.max_notifications_per_minute,
+        }
     }
 
     pub fn get_tools(&self) -> Vec<Value> {
         vec![
             json!({
                 "name": "input_notify",
-                "description": "Send notifications (terminal and desktop)",
+                "description": "Send notifications (terminal, desktop, or to the MCP client as a log message), optionally read aloud with the platform's text-to-speech",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -36,17 +89,48 @@ impl InputModule {
                         },
                         "type": {
                             "type": "string",
-                            "enum": ["terminal", "desktop", "both"],
-                            "description": "Notification type (default: both)"
+                            "enum": ["terminal", "desktop", "both", "client", "all"],
+                            "description": "Where to notify: the server's terminal, a desktop notification, both of those, the MCP client (as a notifications/message log message), or all three (default: both)"
                         },
                         "urgency": {
                             "type": "string",
                             "enum": ["low", "normal", "critical"],
-                            "description": "Desktop notification urgency (default: normal)"
+                            "description": "Desktop notification urgency, and the client message's level unless 'level' is given: notice, warning or critical (default: normal)"
+                        },
+                        "level": {
+                            "type": "string",
+                            "enum": ["debug", "info", "notice", "warning", "error", "critical", "alert", "emergency"],
+                            "description": "Log level of the message sent to the client"
+                        },
+                        "style": {
+                            "type": "string",
+                            "enum": ["auto", "color", "box", "plain"],
+                            "description": "How the terminal notification is drawn: a box colored by urgency, a box without color, or plain lines for logs (default: auto, a colored box when stderr is a terminal and NO_COLOR is unset, plain lines when it is not)"
+                        },
+                        "width": {
+                            "type": "integer",
+                            "description": "Widest the terminal box may be, in columns; longer lines wrap (default: the terminal's width, else $COLUMNS, else 80)"
                         },
                         "timeout": {
                             "type": "number",
                             "description": "Notification timeout in milliseconds (desktop only)"
+                        },
+                        "actions": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Buttons to put on the desktop notification, e.g. ['Approve', 'Deny']. The call then waits for one to be clicked and returns its label as 'action' (Linux and BSD desktops)"
+                        },
+                        "wait": {
+                            "type": "number",
+                            "description": "With actions: seconds to wait for a click before closing the notification (default: 60)"
+                        },
+                        "speak": {
+                            "type": "boolean",
+                            "description": "Also read the notification aloud with say (macOS), spd-say/espeak-ng/espeak (Linux) or SAPI (Windows). Returns once speech has finished (default: false)"
+                        },
+                        "voice": {
+                            "type": "string",
+                            "description": "Text-to-speech voice name, e.g. 'Samantha' (macOS), 'en-us' (espeak), 'Microsoft Zira Desktop' (Windows)"
                         }
                     },
                     "required": ["message"]
@@ -54,7 +138,7 @@ impl InputModule {
             }),
             json!({
                 "name": "input_prompt",
-                "description": "Interactive user prompts (supports MCP and terminal)",
+                "description": "Interactive user prompts, on the server's terminal or through the MCP client (elicitation, or sampling when the client only supports that)",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -70,6 +154,10 @@ impl InputModule {
                             "type": "string",
                             "enum": ["terminal", "mcp"],
                             "description": "Input mode (default: terminal)"
+                        },
+                        "timeout": {
+                            "type": "number",
+                            "description": "Seconds to wait for the client's answer in mcp mode (default: 300)"
                         }
                     },
                     "required": ["prompt"]
@@ -77,7 +165,7 @@ impl InputModule {
             }),
             json!({
                 "name": "input_select",
-                "description": "Selection menus (supports MCP and terminal)",
+                "description": "Selection menus, on the server's terminal or through the MCP client (elicitation, or sampling when the client only supports that)",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -100,6 +188,10 @@ impl InputModule {
                             "type": "string",
                             "enum": ["terminal", "mcp"],
                             "description": "Input mode (default: terminal)"
+                        },
+                        "timeout": {
+                            "type": "number",
+                            "description": "Seconds to wait for the client's answer in mcp mode (default: 300)"
                         }
                     },
                     "required": ["prompt", "options"]
@@ -138,21 +230,36 @@ impl InputModule {
             }),
             json!({
                 "name": "input_clipboard_read",
-                "description": "Read from clipboard",
+                "description": "Read from the clipboard: the system clipboard, or on headless hosts the server's virtual clipboard",
                 "inputSchema": {
                     "type": "object",
-                    "properties": {}
+                    "properties": {
+                        "backend": {
+                            "type": "string",
+                            "enum": ["auto", "system", "virtual"],
+                            "description": "Clipboard to read (default: [input] clipboard, auto: the system clipboard if there is one)"
+                        }
+                    }
                 }
             }),
             json!({
                 "name": "input_clipboard_write",
-                "description": "Write to clipboard",
+                "description": "Write to the clipboard: the system clipboard, or on headless hosts the server's virtual clipboard, optionally also the terminal's via OSC 52",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
                         "content": {
                             "type": "string",
                             "description": "Content to write to clipboard"
+                        },
+                        "backend": {
+                            "type": "string",
+                            "enum": ["auto", "system", "virtual"],
+                            "description": "Clipboard to write (default: [input] clipboard, auto: the system clipboard if there is one)"
+                        },
+                        "osc52": {
+                            "type": "boolean",
+                            "description": "Also send the content to the server's terminal as an OSC 52 escape sequence (default: [input] osc52)"
                         }
                     },
                     "required": ["content"]
@@ -169,25 +276,96 @@ impl InputModule {
         let notification_type = args["type"].as_str().unwrap_or("both");
         let urgency = args["urgency"].as_str().unwrap_or("normal");
         let timeout = args["timeout"].as_u64().map(|t| t as i32);
+        let speak = args["speak"].as_bool().unwrap_or(false);
+        let voice = args["voice"].as_str();
+        let actions: Vec<String> = args["actions"]
+            .as_array()
+            .map(|labels| labels.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default();
+        let wait = args["wait"]
+            .as_f64()
+            .filter(|secs| *secs > 0.0)
+            .map(Duration::from_secs_f64)
+            .unwrap_or(DEFAULT_ACTION_WAIT);
 
         let mut results = json!({
             "title": title,
             "message": message
         });
 
+        // Quiet hours and the rate cap hold a notification back on every channel.
+        // The call is audited all the same.
+        let now = Instant::now();
+        let (similar, other) = {
+            let mut log = NOTIFICATIONS.lock().unwrap_or_else(|e| e.into_inner());
+            while log.delivered.front().is_some_and(|sent| now.duration_since(*sent) >= RATE_WINDOW) {
+                log.delivered.pop_front();
+            }
+            let quiet = self
+                .quiet_hours
+                .filter(|hours| hours.contains(chrono::Local::now().time()))
+                .filter(|_| !(urgency == "critical" && self.quiet_hours_allow_critical));
+            let limited = self
+                .max_notifications_per_minute
+                .is_some_and(|max| log.delivered.len() >= max as usize);
+            if quiet.is_some() || limited {
+                let oldest = log.delivered.front().copied();
+                let held = log.suppressed.entry(title.to_string()).or_default();
+                *held += 1;
+                results["delivered"] = json!(false);
+                results["similar_suppressed"] = json!(*held);
+                match quiet {
+                    Some(hours) => {
+                        results["suppressed"] = json!("quiet_hours");
+                        results["quiet_until"] = json!(hours.end.format("%H:%M").to_string());
+                    }
+                    None => {
+                        let wait = oldest.map(|sent| RATE_WINDOW.saturating_sub(now.duration_since(sent)));
+                        results["suppressed"] = json!("rate_limit");
+                        results["retry_after_secs"] = json!(wait.unwrap_or(RATE_WINDOW).as_secs_f64().ceil() as u64);
+                    }
+                }
+                return Ok(results);
+            }
+            log.delivered.push_back(now);
+            let similar = log.suppressed.remove(title).unwrap_or(0);
+            let other = log.suppressed.values().sum::<usize>();
+            log.suppressed.clear();
+            (similar, other)
+        };
+        results["delivered"] = json!(true);
+        let message = match suppressed_note(similar, other) {
+            Some(note) => {
+                results["coalesced"] = json!({"similar": similar, "other": other});
+                format!("{}\n{}", message, note)
+            }
+            None => message.to_string(),
+        };
+        let message = message.as_str();
+
         // Terminal notification
-        if notification_type == "terminal" || notification_type == "both" {
-            println!("\n┌─ {} ─┐", "─".repeat(title.len().max(message.len())));
-            println!("│ {} │", title);
-            println!("├─{}─┤", "─".repeat(title.len().max(message.len())));
-            println!("│ {} │", message);
-            println!("└─{}─┘\n", "─".repeat(title.len().max(message.len())));
+        if matches!(notification_type, "terminal" | "both" | "all") {
+            let style = TerminalStyle::parse(args["style"].as_str().unwrap_or("auto"))?;
+            let width = args["width"].as_u64().map(|w| w as usize).unwrap_or_else(terminal_width);
+            let rendered = render_notification(title, message, urgency, style, width);
 
-            results["terminal"] = json!(true);
+            // stderr, since stdout carries the MCP messages in stdio mode
+            let mut stderr = std::io::stderr().lock();
+            match stderr.write_all(rendered.as_bytes()).and_then(|_| stderr.flush()) {
+                Ok(()) => {
+                    results["terminal"] = json!(true);
+                }
+                Err(e) => {
+                    results["terminal"] = json!(false);
+                    results["terminal_error"] = json!(e.to_string());
+                }
+            }
+            results["terminal_style"] = json!(style.name());
         }
 
-        // Desktop notification
-        if notification_type == "desktop" || notification_type == "both" {
+        // Desktop notification; with buttons, it is waited on once everything else is sent
+        let mut buttons = None;
+        if matches!(notification_type, "desktop" | "both" | "all") {
             let mut notification = Notification::new();
             notification.summary(title);
             notification.body(message);
@@ -210,10 +388,17 @@ impl InputModule {
 
             if let Some(t) = timeout {
                 notification.timeout(t);
+            } else if !actions.is_empty() {
+                notification.timeout(wait.as_millis().min(i32::MAX as u128) as i32);
             }
 
-            match notification.show() {
-                Ok(_) => {
+            let shown = if actions.is_empty() {
+                notification.show().map(|_| ()).map_err(anyhow::Error::from)
+            } else {
+                Buttons::show(notification, &actions).map(|shown| buttons = Some(shown))
+            };
+            match shown {
+                Ok(()) => {
                     results["desktop"] = json!(true);
                 }
                 Err(e) => {
@@ -223,6 +408,58 @@ impl InputModule {
             }
         }
 
+        // Message to the MCP client, for hosts that show it in their own UI
+        if matches!(notification_type, "client" | "all") {
+            let level = args["level"].as_str().unwrap_or(match urgency {
+                "low" => "notice",
+                "critical" => "critical",
+                _ => "warning",
+            });
+            let data = json!({
+                "title": title,
+                "message": message,
+                "urgency": urgency
+            });
+            results["client_level"] = json!(level);
+            match logging::message(level, "input_notify", &data) {
+                Ok(()) => {
+                    results["client"] = json!(true);
+                }
+                Err(e) => {
+                    results["client"] = json!(false);
+                    results["client_error"] = json!(e.to_string());
+                }
+            }
+        }
+
+        // Spoken notification
+        if speak {
+            let text = match args["title"].as_str() {
+                Some(title) => format!("{}. {}", title, message),
+                None => message.to_string(),
+            };
+            match speak_text(&text, voice).await {
+                Ok(engine) => {
+                    results["speech"] = json!(true);
+                    results["speech_engine"] = json!(engine);
+                }
+                Err(e) => {
+                    results["speech"] = json!(false);
+                    results["speech_error"] = json!(e.to_string());
+                }
+            }
+        }
+
+        if let Some(buttons) = buttons {
+            let (action, status) = match tokio::time::timeout(wait, buttons.clicked()).await {
+                Ok(Some(label)) => (Some(label), "clicked"),
+                Ok(None) => (None, "dismissed"),
+                Err(_) => (None, "timeout"),
+            };
+            results["action"] = json!(action);
+            results["action_status"] = json!(status);
+        }
+
         Ok(results)
     }
 
@@ -252,14 +489,49 @@ impl InputModule {
                 }))
             }
             "mcp" => {
-                // For MCP mode, we would need to use MCP sampling
-                // For now, return a placeholder indicating MCP prompting is needed
-                Ok(json!({
-                    "prompt": prompt,
-                    "mode": "mcp",
-                    "message": "MCP sampling would be triggered here",
-                    "default": default_value
-                }))
+                let client = sampling::current()?;
+                let timeout = client_timeout(&args);
+
+                if client.supports("elicitation") {
+                    let mut field = json!({ "type": "string", "title": prompt });
+                    if let Some(default) = default_value {
+                        field["default"] = json!(default);
+                    }
+                    let schema = json!({
+                        "type": "object",
+                        "properties": { "response": field },
+                        "required": ["response"]
+                    });
+                    let (action, content) = sampling::elicit(&client, prompt, schema, timeout).await?;
+                    let response = content
+                        .as_ref()
+                        .and_then(|content| content["response"].as_str())
+                        .map(String::from);
+
+                    Ok(json!({
+                        "prompt": prompt,
+                        "response": response,
+                        "action": action,
+                        "mode": "mcp",
+                        "via": "elicitation"
+                    }))
+                } else {
+                    require_sampling(&client)?;
+                    let sampled = sampling::create_message(&client, prompt, None, 1000, timeout).await?;
+                    let response = match sampled.text.trim() {
+                        "" => default_value.unwrap_or_default().to_string(),
+                        text => text.to_string(),
+                    };
+
+                    Ok(json!({
+                        "prompt": prompt,
+                        "response": response,
+                        "action": "accept",
+                        "mode": "mcp",
+                        "via": "sampling",
+                        "model": sampled.model
+                    }))
+                }
             }
             _ => Err(anyhow::anyhow!("Unknown mode: {}", mode)),
         }
@@ -305,14 +577,66 @@ impl InputModule {
                 }))
             }
             "mcp" => {
-                // For MCP mode, we would need to use MCP sampling
-                Ok(json!({
-                    "prompt": prompt,
-                    "options": option_strs,
-                    "mode": "mcp",
-                    "message": "MCP sampling would be triggered here",
-                    "default": default_idx
-                }))
+                let client = sampling::current()?;
+                let timeout = client_timeout(&args);
+
+                if client.supports("elicitation") {
+                    let mut field = json!({ "type": "string", "title": prompt, "enum": option_strs });
+                    if let Some(default) = default_idx.and_then(|idx| option_strs.get(idx)) {
+                        field["default"] = json!(default);
+                    }
+                    let schema = json!({
+                        "type": "object",
+                        "properties": { "selected": field },
+                        "required": ["selected"]
+                    });
+                    let (action, content) = sampling::elicit(&client, prompt, schema, timeout).await?;
+                    let selected = content
+                        .as_ref()
+                        .and_then(|content| content["selected"].as_str())
+                        .map(String::from);
+                    let index = selected
+                        .as_ref()
+                        .and_then(|selected| option_strs.iter().position(|option| option == selected));
+
+                    Ok(json!({
+                        "prompt": prompt,
+                        "selected": selected,
+                        "index": index,
+                        "action": action,
+                        "mode": "mcp",
+                        "via": "elicitation"
+                    }))
+                } else {
+                    require_sampling(&client)?;
+                    let numbered: Vec<String> = option_strs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, option)| format!("{}. {}", i + 1, option))
+                        .collect();
+                    let question = format!("{}\n\n{}", prompt, numbered.join("\n"));
+                    let sampled = sampling::create_message(
+                        &client,
+                        &question,
+                        Some("Answer with exactly one of the listed options, and nothing else."),
+                        100,
+                        timeout,
+                    )
+                    .await?;
+                    let index = match_option(&sampled.text, &option_strs).with_context(|| {
+                        format!("The client's answer '{}' is not one of the options", sampled.text.trim())
+                    })?;
+
+                    Ok(json!({
+                        "prompt": prompt,
+                        "selected": option_strs[index],
+                        "index": index,
+                        "action": "accept",
+                        "mode": "mcp",
+                        "via": "sampling",
+                        "model": sampled.model
+                    }))
+                }
             }
             _ => Err(anyhow::anyhow!("Unknown mode: {}", mode)),
         }
@@ -379,34 +703,586 @@ impl InputModule {
         }
     }
 
-    pub async fn clipboard_read(&self, _args: Value) -> Result<Value> {
-        let mut ctx = ClipboardContext::new()
-            .map_err(|e| anyhow::anyhow!("Failed to access clipboard: {}", e))?;
-
-        let content = ctx
-            .get_contents()
-            .map_err(|e| anyhow::anyhow!("Failed to read clipboard: {}", e))?;
+    pub async fn clipboard_read(&self, args: Value) -> Result<Value> {
+        let backend = self.clipboard_backend(&args)?;
+        let mut fallback = None;
+        if backend != ClipboardBackend::Virtual {
+            let read = system_clipboard().and_then(|mut ctx| {
+                ctx.get_contents().map_err(|e| anyhow::anyhow!("Failed to read clipboard: {}", e))
+            });
+            match read {
+                Ok(content) => {
+                    return Ok(json!({
+                        "content": content,
+                        "length": content.len(),
+                        "backend": "system"
+                    }))
+                }
+                Err(e) if backend == ClipboardBackend::System => return Err(e),
+                Err(e) => fallback = Some(e.to_string()),
+            }
+        }
 
-        Ok(json!({
-            "content": content,
-            "length": content.len()
-        }))
+        let content = VIRTUAL_CLIPBOARD.lock().unwrap().clone();
+        let mut result = json!({
+            "content": content.as_deref().unwrap_or_default(),
+            "length": content.as_ref().map_or(0, String::len),
+            "backend": "virtual",
+            "empty": content.is_none()
+        });
+        if let Some(reason) = fallback {
+            result["fallback_reason"] = json!(reason);
+        }
+        Ok(result)
     }
 
     pub async fn clipboard_write(&self, args: Value) -> Result<Value> {
         let content = args["content"]
             .as_str()
             .context("Missing 'content' parameter")?;
+        let backend = self.clipboard_backend(&args)?;
 
-        let mut ctx = ClipboardContext::new()
-            .map_err(|e| anyhow::anyhow!("Failed to access clipboard: {}", e))?;
+        // Kept whichever clipboard is used, so reading the virtual one always gives
+        // back what this server last wrote
+        *VIRTUAL_CLIPBOARD.lock().unwrap() = Some(content.to_string());
 
-        ctx.set_contents(content.to_string())
-            .map_err(|e| anyhow::anyhow!("Failed to write to clipboard: {}", e))?;
+        let mut used = "virtual";
+        let mut fallback = None;
+        if backend != ClipboardBackend::Virtual {
+            let written = system_clipboard().and_then(|mut ctx| {
+                ctx.set_contents(content.to_string())
+                    .map_err(|e| anyhow::anyhow!("Failed to write to clipboard: {}", e))
+            });
+            match written {
+                Ok(()) => used = "system",
+                Err(e) if backend == ClipboardBackend::System => return Err(e),
+                Err(e) => fallback = Some(e.to_string()),
+            }
+        }
 
-        Ok(json!({
+        let mut result = json!({
             "success": true,
-            "content_length": content.len()
-        }))
+            "content_length": content.len(),
+            "backend": used
+        });
+        if let Some(reason) = fallback {
+            result["fallback_reason"] = json!(reason);
+        }
+        if args["osc52"].as_bool().unwrap_or(self.osc52) {
+            match send_osc52(content) {
+                Ok(()) => result["osc52"] = json!(true),
+                Err(e) => {
+                    result["osc52"] = json!(false);
+                    result["osc52_error"] = json!(e.to_string());
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn clipboard_backend(&self, args: &Value) -> Result<ClipboardBackend> {
+        match args["backend"].as_str() {
+            None => Ok(self.clipboard),
+            Some("auto") => Ok(ClipboardBackend::Auto),
+            Some("system") => Ok(ClipboardBackend::System),
+            Some("virtual") => Ok(ClipboardBackend::Virtual),
+            Some(other) => Err(PolyError::InvalidArguments(format!("Invalid backend: {}", other)).into()),
+        }
+    }
+}
+
+impl InputModule {
+    /// Ask the user a yes/no question, in a native dialog ("dialog"), on the server's
+    /// terminal ("terminal"), or in a desktop notification with Allow and Deny buttons
+    /// ("notification", the dialog where the desktop has no such notifications). No
+    /// answer within `timeout` counts as no.
+    pub async fn confirm(&self, title: &str, message: &str, mode: &str, timeout: Duration) -> Result<bool> {
+        let answer = async {
+            match mode {
+                "dialog" => confirm_dialog(title, message).await,
+                "notification" => {
+                    let mut notification = Notification::new();
+                    notification.summary(title).body(message);
+                    #[cfg(target_os = "linux")]
+                    notification.urgency(notify_rust::Urgency::Critical);
+                    notification.timeout(timeout.as_millis().min(i32::MAX as u128) as i32);
+                    match Buttons::show(notification, &["Allow".to_string(), "Deny".to_string()]) {
+                        Ok(buttons) => Ok(buttons.clicked().await.as_deref() == Some("Allow")),
+                        Err(_) => confirm_dialog(title, message).await,
+                    }
+                }
+                "terminal" => {
+                    let prompt = format!("{}: {}", title, message);
+                    // The prompt reads keys from the controlling terminal, not stdin
+                    tokio::task::spawn_blocking(move || {
+                        Confirm::new()
+                            .with_prompt(prompt)
+                            .default(false)
+                            .interact_on_opt(&dialoguer::console::Term::stderr())
+                            .map(|answer| answer.unwrap_or(false))
+                            .context("No terminal to ask on")
+                    })
+                    .await?
+                }
+                other => anyhow::bail!("Unknown confirmation mode '{}' (expected dialog, terminal or notification)", other),
+            }
+        };
+
+        match tokio::time::timeout(timeout, answer).await {
+            Ok(answer) => answer,
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolModule for InputModule {
+    fn name(&self) -> &str {
+        "input"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "input_notify" => self.notify(args).await,
+            "input_prompt" => self.prompt_user(args).await,
+            "input_select" => self.select(args).await,
+            "input_progress" => self.progress(args).await,
+            "input_clipboard_read" => self.clipboard_read(args).await,
+            "input_clipboard_write" => self.clipboard_write(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+/// A desktop notification with buttons, waiting for one to be clicked. It is
+/// closed if dropped unanswered, so an abandoned wait does not leave it on screen.
+struct Buttons {
+    labels: Vec<String>,
+    // The action the notification server reports: a button's index, "default"
+    // for a click on the body, or "__closed"
+    answer: Option<tokio::sync::oneshot::Receiver<String>>,
+    // The notification as shown, with its id, to close it by
+    shown: Option<Notification>,
+}
+
+impl Buttons {
+    /// Show `notification` with a button for each of `labels`. Only XDG desktops
+    /// (Linux and BSD) have notification buttons.
+    fn show(mut notification: Notification, labels: &[String]) -> Result<Self> {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            for (i, label) in labels.iter().enumerate() {
+                notification.action(&i.to_string(), label);
+            }
+            let handle = notification.show()?;
+            notification.id(handle.id());
+            let (sender, answer) = tokio::sync::oneshot::channel();
+            // Returns once a button is clicked or the notification is closed
+            tokio::task::spawn_blocking(move || {
+                handle.wait_for_action(|action| {
+                    let _ = sender.send(action.to_string());
+                })
+            });
+            Ok(Self {
+                labels: labels.to_vec(),
+                answer: Some(answer),
+                shown: Some(notification),
+            })
+        }
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        {
+            let _ = (&mut notification, labels);
+            anyhow::bail!("Notification buttons are only supported on Linux and BSD desktops")
+        }
+    }
+
+    /// The label of the button clicked, or `None` when the notification was
+    /// dismissed or closed instead
+    async fn clicked(mut self) -> Option<String> {
+        let action = self.answer.take()?.await.ok();
+        // Answered or closed, so there is nothing left to close
+        self.shown = None;
+        let index = action?.parse::<usize>().ok()?;
+        self.labels.get(index).cloned()
+    }
+}
+
+impl Drop for Buttons {
+    fn drop(&mut self) {
+        // Showing a notification with the same id replaces it, and the new handle closes it
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if let Some(handle) = self.shown.take().and_then(|notification| notification.show().ok()) {
+            handle.close();
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn client_timeout(args: &Value) -> Duration {
+    args["timeout"]
+        .as_f64()
+        .filter(|secs| *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(sampling::DEFAULT_TIMEOUT)
+}
+
+fn require_sampling(client: &ClientBridge) -> Result<()> {
+    if !client.supports("sampling") {
+        anyhow::bail!("The MCP client supports neither elicitation nor sampling; use mode 'terminal' instead");
+    }
+    Ok(())
+}
+
+/// Which of `options` a model's free-text answer names: the option itself
+/// (ignoring case and surrounding punctuation) or its 1-based number
+fn match_option(answer: &str, options: &[String]) -> Option<usize> {
+    let answer = answer.trim().trim_matches(|c: char| c == '"' || c == '\'' || c == '.' || c == '`');
+    if let Some(index) = options.iter().position(|option| option.eq_ignore_ascii_case(answer)) {
+        return Some(index);
+    }
+    let number = answer.split(|c: char| !c.is_ascii_digit()).next()?.parse::<usize>().ok()?;
+    (1..=options.len()).contains(&number).then(|| number - 1)
+}
+
+/// Read `text` aloud and wait for it to finish, returning the engine used
+async fn speak_text(text: &str, voice: Option<&str>) -> Result<&'static str> {
+    for (engine, mut command) in speech_commands(text, voice) {
+        match command.output().await {
+            Ok(output) if output.status.success() => return Ok(engine),
+            Ok(output) => anyhow::bail!(
+                "{} failed: {}",
+                engine,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            // Not installed; try the next engine
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", engine)),
+        }
+    }
+
+    anyhow::bail!("No text-to-speech engine found (install speech-dispatcher, espeak-ng or espeak)")
+}
+
+/// Show a native Allow/Deny dialog and wait for the answer
+async fn confirm_dialog(title: &str, message: &str) -> Result<bool> {
+    for (tool, mut command) in dialog_commands(title, message) {
+        // Close the dialog if the wait is abandoned
+        command.kill_on_drop(true);
+        match command.output().await {
+            Ok(output) => {
+                return match output.status.code() {
+                    Some(0) => Ok(String::from_utf8_lossy(&output.stdout).trim() != "No"),
+                    // Deny, cancel, or the dialog's own timeout
+                    Some(1) | Some(5) => Ok(false),
+                    _ => anyhow::bail!("{} failed: {}", tool, String::from_utf8_lossy(&output.stderr).trim()),
+                };
+            }
+            // Not installed; try the next one
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", tool)),
+        }
+    }
+
+    anyhow::bail!("No dialog program found (install zenity or kdialog)")
+}
+
+/// Candidate Allow/Deny dialog commands for this platform, in order of preference.
+/// Each exits 0 for Allow and 1 for Deny.
+fn dialog_commands(title: &str, message: &str) -> Vec<(&'static str, tokio::process::Command)> {
+    use tokio::process::Command;
+
+    let mut commands = Vec::new();
+
+    if cfg!(target_os = "macos") {
+        let mut osascript = Command::new("osascript");
+        osascript
+            .args([
+                "-e",
+                "on run argv",
+                "-e",
+                "display dialog (item 2 of argv) with title (item 1 of argv) buttons {\"Deny\", \"Allow\"} \
+                 default button \"Deny\" cancel button \"Deny\" with icon caution",
+                "-e",
+                "end run",
+            ])
+            .arg(title)
+            .arg(message);
+        commands.push(("osascript", osascript));
+    } else if cfg!(windows) {
+        // The text goes through the environment so it is never parsed as PowerShell;
+        // the answer is printed as Yes or No
+        let mut message_box = Command::new("powershell");
+        message_box
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                "Add-Type -AssemblyName PresentationFramework; \
+                 [System.Windows.MessageBox]::Show($env:POLY_MCP_DIALOG_TEXT, $env:POLY_MCP_DIALOG_TITLE, 'YesNo', 'Warning')",
+            ])
+            .env("POLY_MCP_DIALOG_TITLE", title)
+            .env("POLY_MCP_DIALOG_TEXT", message);
+        commands.push(("powershell", message_box));
+    } else {
+        let mut zenity = Command::new("zenity");
+        zenity
+            .args(["--question", "--ok-label=Allow", "--cancel-label=Deny", "--no-markup"])
+            .arg(format!("--title={}", title))
+            .arg(format!("--text={}", message));
+        commands.push(("zenity", zenity));
+
+        let mut kdialog = Command::new("kdialog");
+        kdialog
+            .args(["--yes-label", "Allow", "--no-label", "Deny", "--title"])
+            .arg(title)
+            .arg("--yesno")
+            .arg(message);
+        commands.push(("kdialog", kdialog));
+    }
+
+    commands
+}
+
+/// Candidate text-to-speech commands for this platform, in order of preference
+fn speech_commands(text: &str, voice: Option<&str>) -> Vec<(&'static str, tokio::process::Command)> {
+    use tokio::process::Command;
+
+    let mut commands = Vec::new();
+
+    if cfg!(target_os = "macos") {
+        let mut say = Command::new("say");
+        if let Some(voice) = voice {
+            say.args(["-v", voice]);
+        }
+        say.arg("--").arg(text);
+        commands.push(("say", say));
+    } else if cfg!(windows) {
+        // The text goes through the environment so it is never parsed as PowerShell
+        let mut script = String::from(
+            "Add-Type -AssemblyName System.Speech; \
+             $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; ",
+        );
+        if voice.is_some() {
+            script.push_str("$s.SelectVoice($env:POLY_MCP_TTS_VOICE); ");
+        }
+        script.push_str("$s.Speak($env:POLY_MCP_TTS_TEXT)");
+
+        let mut sapi = Command::new("powershell");
+        sapi.args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .env("POLY_MCP_TTS_TEXT", text);
+        if let Some(voice) = voice {
+            sapi.env("POLY_MCP_TTS_VOICE", voice);
+        }
+        commands.push(("sapi", sapi));
+    } else {
+        let mut spd_say = Command::new("spd-say");
+        // -w waits until the message has been spoken
+        spd_say.arg("-w");
+        if let Some(voice) = voice {
+            spd_say.args(["-l", voice]);
+        }
+        spd_say.arg("--").arg(text);
+        commands.push(("spd-say", spd_say));
+
+        for engine in ["espeak-ng", "espeak"] {
+            let mut espeak = Command::new(engine);
+            if let Some(voice) = voice {
+                espeak.args(["-v", voice]);
+            }
+            espeak.arg("--").arg(text);
+            commands.push((engine, espeak));
+        }
+    }
+
+    commands
+}
+
+/// The system clipboard, failing straight away on Linux and BSD hosts without a
+/// display rather than waiting on a clipboard that cannot exist
+fn system_clipboard() -> Result<ClipboardContext> {
+    if cfg!(all(unix, not(target_os = "macos")))
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        anyhow::bail!("No system clipboard: neither DISPLAY nor WAYLAND_DISPLAY is set");
+    }
+    ClipboardContext::new().map_err(|e| anyhow::anyhow!("Failed to access clipboard: {}", e))
+}
+
+/// Ask the terminal the server runs in to put `content` on its clipboard. The
+/// sequence goes to the controlling terminal rather than stdout, which carries
+/// the MCP messages in stdio mode.
+fn send_osc52(content: &str) -> Result<()> {
+    let terminal = if cfg!(windows) { "CONOUT$" } else { "/dev/tty" };
+    let mut tty = std::fs::OpenOptions::new()
+        .write(true)
+        .open(terminal)
+        .with_context(|| format!("No terminal to send OSC 52 to ({})", terminal))?;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+    write!(tty, "\x1b]52;c;{}\x07", encoded)?;
+    tty.flush()?;
+    Ok(())
+}
+
+/// What the first notification delivered after some were held back says about them,
+/// e.g. "(3 similar alerts suppressed)"; similar ones had the same title
+fn suppressed_note(similar: usize, other: usize) -> Option<String> {
+    let alerts = |count: usize| if count == 1 { "alert" } else { "alerts" };
+    match (similar, other) {
+        (0, 0) => None,
+        (similar, 0) => Some(format!("({} similar {} suppressed)", similar, alerts(similar))),
+        (0, other) => Some(format!("({} other {} suppressed)", other, alerts(other))),
+        (similar, other) => Some(format!("({} similar and {} other {} suppressed)", similar, other, alerts(other))),
+    }
+}
+
+/// How a terminal notification is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TerminalStyle {
+    Color,
+    Box,
+    Plain,
+}
+
+impl TerminalStyle {
+    fn parse(style: &str) -> Result<Self> {
+        match style {
+            "auto" if !std::io::stderr().is_terminal() => Ok(Self::Plain),
+            "auto" if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+                || std::env::var("TERM").is_ok_and(|term| term == "dumb") =>
+            {
+                Ok(Self::Box)
+            }
+            "auto" | "color" => Ok(Self::Color),
+            "box" => Ok(Self::Box),
+            "plain" => Ok(Self::Plain),
+            other => Err(PolyError::InvalidArguments(format!(
+                "Invalid style '{}' (expected auto, color, box or plain)",
+                other
+            ))
+            .into()),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Color => "color",
+            Self::Box => "box",
+            Self::Plain => "plain",
+        }
+    }
+}
+
+/// Columns of the terminal on stderr, else $COLUMNS, else 80
+fn terminal_width() -> usize {
+    dialoguer::console::Term::stderr()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// A notification as the terminal shows it: a box at most `width` columns wide,
+/// with the title above the message, or plain lines for logs
+fn render_notification(title: &str, message: &str, urgency: &str, style: TerminalStyle, width: usize) -> String {
+    let title = printable(title);
+    let message = printable(message);
+    if style == TerminalStyle::Plain {
+        let mut out = match urgency {
+            "normal" => format!("{}\n", title),
+            _ => format!("[{}] {}\n", urgency, title),
+        };
+        for line in message.lines() {
+            out.push_str(&format!("  {}\n", line));
+        }
+        return out;
+    }
+
+    // "│ " and " │" around every line
+    let inner = width.saturating_sub(4).max(10);
+    let title_lines = wrap(&title, inner);
+    let message_lines = wrap(&message, inner);
+    let content = title_lines.iter().chain(&message_lines).map(|line| line.width()).max().unwrap_or(0);
+
+    let paint = |code: &str, text: &str| match style {
+        TerminalStyle::Color => format!("\x1b[{}m{}\x1b[0m", code, text),
+        _ => text.to_string(),
+    };
+    let border = match urgency {
+        "low" => "2",
+        "critical" => "1;31",
+        _ => "36",
+    };
+    let rule = "─".repeat(content + 2);
+    let row = |line: &str, code: Option<&str>| {
+        let padded = format!("{}{}", line, " ".repeat(content - line.width()));
+        let text = match code {
+            Some(code) => paint(code, &padded),
+            None => padded,
+        };
+        format!("{} {} {}\n", paint(border, "│"), text, paint(border, "│"))
+    };
+
+    let mut out = format!("\n{}\n", paint(border, &format!("┌{}┐", rule)));
+    for line in &title_lines {
+        out.push_str(&row(line, Some("1")));
+    }
+    out.push_str(&format!("{}\n", paint(border, &format!("├{}┤", rule))));
+    for line in &message_lines {
+        out.push_str(&row(line, None));
+    }
+    out.push_str(&format!("{}\n\n", paint(border, &format!("└{}┘", rule))));
+    out
+}
+
+/// `text` with tabs expanded and other control characters (escape sequences
+/// included) dropped, so it cannot move the cursor or restyle the terminal
+fn printable(text: &str) -> String {
+    text.replace('\t', "    ").chars().filter(|&c| c == '\n' || !c.is_control()).collect()
+}
+
+/// Lines of `text` at most `width` columns wide, broken at spaces where it can
+/// be and between characters in words longer than a whole line
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for (i, word) in paragraph.split(' ').enumerate() {
+            let word_width = word.width();
+            let space = usize::from(i > 0);
+            if line_width + space + word_width <= width {
+                line.push_str(&" ".repeat(space));
+                line.push_str(word);
+                line_width += space + word_width;
+                continue;
+            }
+            if word_width <= width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+                line_width = word_width;
+                continue;
+            }
+            if line_width + space < width {
+                line.push_str(&" ".repeat(space));
+                line_width += space;
+            }
+            for c in word.chars() {
+                let char_width = c.width().unwrap_or(0);
+                if line_width + char_width > width && line_width > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push(c);
+                line_width += char_width;
+            }
+        }
+        lines.push(line);
     }
+    lines
 }
@@ -0,0 +1,535 @@
+use serde_json::{json, Value};
+use anyhow::{anyhow, Result, Context as _};
+use async_trait::async_trait;
+use crate::config::{LlmApi, LlmConfig};
+use crate::progress;
+use crate::provenance;
+use crate::registry::{Lazy, ToolModule};
+use std::time::{Duration, Instant};
+
+/// Talks to a local Ollama or OpenAI-compatible server. Cheap to clone, so
+/// other modules that need embeddings can hold their own copy.
+#[derive(Clone)]
+pub struct LlmClient {
+    // Built on the first request
+    http: Lazy<reqwest::Client>,
+    api: LlmApi,
+    base_url: String,
+    api_key: Option<String>,
+    model: Option<String>,
+    embed_model: Option<String>,
+}
+
+/// Text produced by [`LlmClient::generate`]
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub model: String,
+    pub text: String,
+    pub finish_reason: Option<String>,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+}
+
+impl LlmClient {
+    pub fn new(config: &LlmConfig) -> Self {
+        let base_url = config
+            .base_url
+            .clone()
+            .unwrap_or_else(|| match config.api {
+                LlmApi::Ollama => "http://localhost:11434".to_string(),
+                LlmApi::OpenAi => "http://localhost:11434/v1".to_string(),
+            })
+            .trim_end_matches('/')
+            .to_string();
+
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let http = Lazy::new(move || {
+            reqwest::Client::builder()
+                .timeout(timeout)
+                .user_agent(format!("poly-mcp/{}", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap()
+        });
+
+        Self {
+            http,
+            api: config.api,
+            base_url,
+            api_key: config.api_key.clone(),
+            model: config.model.clone(),
+            embed_model: config.embed_model.clone(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Models the server has available, as `{name, size, modified, details}`
+    pub async fn models(&self) -> Result<Vec<Value>> {
+        match self.api {
+            LlmApi::Ollama => {
+                let body = self.get("/api/tags").await?;
+                Ok(body["models"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|model| {
+                        json!({
+                            "name": model["name"],
+                            "size": model["size"],
+                            "modified": model["modified_at"],
+                            "details": model["details"],
+                        })
+                    })
+                    .collect())
+            }
+            LlmApi::OpenAi => {
+                let body = self.get("/models").await?;
+                Ok(body["data"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|model| json!({ "name": model["id"], "owned_by": model["owned_by"] }))
+                    .collect())
+            }
+        }
+    }
+
+    /// One embedding vector per input, in order. Uses `[llm] embed_model` when
+    /// `model` is `None`.
+    pub async fn embed(&self, inputs: &[String], model: Option<&str>) -> Result<(String, Vec<Vec<f64>>)> {
+        let model = model
+            .or(self.embed_model.as_deref())
+            .context("No embedding model given; pass 'model' or set embed_model in the [llm] table (see llm_models)")?
+            .to_string();
+
+        let (body, key) = match self.api {
+            LlmApi::Ollama => (self.post("/api/embed", &json!({ "model": model, "input": inputs })).await?, "embeddings"),
+            LlmApi::OpenAi => (self.post("/embeddings", &json!({ "model": model, "input": inputs })).await?, "data"),
+        };
+
+        let vectors: Vec<Vec<f64>> = body[key]
+            .as_array()
+            .context("The server returned no embeddings")?
+            .iter()
+            .map(|item| {
+                let vector = if self.api == LlmApi::OpenAi { &item["embedding"] } else { item };
+                serde_json::from_value(vector.clone()).context("Malformed embedding in the response")
+            })
+            .collect::<Result<_>>()?;
+        if vectors.len() != inputs.len() {
+            anyhow::bail!("Asked for {} embeddings but got {}", inputs.len(), vectors.len());
+        }
+        Ok((model, vectors))
+    }
+
+    /// Run a chat completion. When the tool call has a progress listener, the
+    /// text is streamed to it as it is generated.
+    pub async fn generate(&self, model: Option<&str>, messages: Vec<Value>, options: &Value) -> Result<Generation> {
+        let model = model
+            .or(self.model.as_deref())
+            .context("No model given; pass 'model' or set model in the [llm] table (see llm_models)")?
+            .to_string();
+        let stream = progress::is_streaming();
+
+        let mut request = json!({ "model": model, "messages": messages, "stream": stream });
+        match self.api {
+            LlmApi::Ollama => {
+                let mut model_options = serde_json::Map::new();
+                if let Some(temperature) = options["temperature"].as_f64() {
+                    model_options.insert("temperature".into(), json!(temperature));
+                }
+                if let Some(max_tokens) = options["max_tokens"].as_u64() {
+                    model_options.insert("num_predict".into(), json!(max_tokens));
+                }
+                if options["stop"].is_array() {
+                    model_options.insert("stop".into(), options["stop"].clone());
+                }
+                request["options"] = Value::Object(model_options);
+                if options["json"].as_bool().unwrap_or(false) {
+                    request["format"] = json!("json");
+                }
+            }
+            LlmApi::OpenAi => {
+                for key in ["temperature", "max_tokens", "stop"] {
+                    if !options[key].is_null() {
+                        request[key] = options[key].clone();
+                    }
+                }
+                if options["json"].as_bool().unwrap_or(false) {
+                    request["response_format"] = json!({ "type": "json_object" });
+                }
+                if stream {
+                    request["stream_options"] = json!({ "include_usage": true });
+                }
+            }
+        }
+
+        let path = match self.api {
+            LlmApi::Ollama => "/api/chat",
+            LlmApi::OpenAi => "/chat/completions",
+        };
+        let url = self.url(path);
+        provenance::fetched(&url);
+        let response = self.send(self.http.get().post(url).json(&request)).await?;
+
+        let mut generation = Generation {
+            model,
+            text: String::new(),
+            finish_reason: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+        };
+        if stream {
+            self.read_stream(response, &mut generation).await?;
+        } else {
+            let body: Value = response.json().await.context("Invalid JSON from the LLM server")?;
+            self.read_chunk(&body, &mut generation);
+        }
+        Ok(generation)
+    }
+
+    /// Streamed responses are one JSON object per line (Ollama) or per
+    /// `data:` line of server-sent events (OpenAI)
+    async fn read_stream(&self, mut response: reqwest::Response, generation: &mut Generation) -> Result<()> {
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                self.read_line(&String::from_utf8_lossy(&line), generation)?;
+            }
+        }
+        self.read_line(&String::from_utf8_lossy(&buffer), generation)
+    }
+
+    fn read_line(&self, line: &str, generation: &mut Generation) -> Result<()> {
+        let line = line.trim();
+        let data = match self.api {
+            LlmApi::Ollama => line,
+            LlmApi::OpenAi => line.strip_prefix("data:").map(str::trim).unwrap_or(""),
+        };
+        if data.is_empty() || data == "[DONE]" {
+            return Ok(());
+        }
+
+        let chunk: Value = serde_json::from_str(data).context("Invalid JSON in the streamed response")?;
+        if let Some(error) = error_message(&chunk) {
+            anyhow::bail!("LLM server error: {}", error);
+        }
+        let before = generation.text.len();
+        self.read_chunk(&chunk, generation);
+        if generation.text.len() > before {
+            progress::output("text", &generation.text[before..]);
+        }
+        Ok(())
+    }
+
+    /// Fold a complete response, or one streamed piece of it, into `generation`
+    fn read_chunk(&self, chunk: &Value, generation: &mut Generation) {
+        match self.api {
+            LlmApi::Ollama => {
+                if let Some(content) = chunk["message"]["content"].as_str() {
+                    generation.text.push_str(content);
+                }
+                if chunk["done"].as_bool().unwrap_or(false) {
+                    generation.finish_reason = chunk["done_reason"].as_str().map(String::from);
+                    generation.prompt_tokens = chunk["prompt_eval_count"].as_u64();
+                    generation.completion_tokens = chunk["eval_count"].as_u64();
+                }
+            }
+            LlmApi::OpenAi => {
+                let choice = &chunk["choices"][0];
+                // Whole responses carry a message, streamed pieces a delta
+                let content = choice["message"]["content"].as_str().or(choice["delta"]["content"].as_str());
+                if let Some(content) = content {
+                    generation.text.push_str(content);
+                }
+                if let Some(reason) = choice["finish_reason"].as_str() {
+                    generation.finish_reason = Some(reason.to_string());
+                }
+                if chunk["usage"].is_object() {
+                    generation.prompt_tokens = chunk["usage"]["prompt_tokens"].as_u64();
+                    generation.completion_tokens = chunk["usage"]["completion_tokens"].as_u64();
+                }
+            }
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn get(&self, path: &str) -> Result<Value> {
+        let url = self.url(path);
+        provenance::fetched(&url);
+        let response = self.send(self.http.get().get(url)).await?;
+        response.json().await.context("Invalid JSON from the LLM server")
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let url = self.url(path);
+        provenance::fetched(&url);
+        let response = self.send(self.http.get().post(url).json(body)).await?;
+        response.json().await.context("Invalid JSON from the LLM server")
+    }
+
+    /// Send a request, turning connection failures and error statuses into readable errors
+    async fn send(&self, mut request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await.map_err(|e| {
+            if e.is_connect() {
+                anyhow!(
+                    "Could not reach the LLM server at {} (is Ollama running? start it with `ollama serve`, or set base_url in [llm])",
+                    self.base_url
+                )
+            } else if e.is_timeout() {
+                anyhow!("The LLM server at {} did not answer in time", self.base_url)
+            } else {
+                anyhow!("Request to the LLM server failed: {}", e)
+            }
+        })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let text = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|body| error_message(&body))
+            .unwrap_or(text);
+        anyhow::bail!("LLM server returned {}: {}", status, message.trim())
+    }
+}
+
+pub struct LlmModule {
+    client: LlmClient,
+}
+
+impl Default for LlmModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LlmModule {
+    pub fn new() -> Self {
+        Self::with_config(&LlmConfig::default())
+    }
+
+    pub fn with_config(config: &LlmConfig) -> Self {
+        Self {
+            client: LlmClient::new(config),
+        }
+    }
+
+    /// The client behind the tools, for modules that need generation or embeddings
+    pub fn client(&self) -> &LlmClient {
+        &self.client
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "llm_generate",
+                "description": "Generate text with a local model (Ollama or an OpenAI-compatible server) from a prompt or a chat history. Nothing leaves the machine unless [llm] points elsewhere. Streams the text as progress notifications to clients that pass a progress token",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "prompt": {
+                            "type": "string",
+                            "description": "User message to answer"
+                        },
+                        "messages": {
+                            "type": "array",
+                            "description": "Chat history as {role, content} objects (roles: system, user, assistant), used instead of or before prompt",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "role": { "type": "string" },
+                                    "content": { "type": "string" }
+                                },
+                                "required": ["role", "content"]
+                            }
+                        },
+                        "system": {
+                            "type": "string",
+                            "description": "System prompt placed before the messages"
+                        },
+                        "model": {
+                            "type": "string",
+                            "description": "Model name, e.g. 'llama3.2' (default: [llm] model)"
+                        },
+                        "temperature": {
+                            "type": "number",
+                            "description": "Sampling temperature (default: the model's)"
+                        },
+                        "max_tokens": {
+                            "type": "integer",
+                            "description": "Maximum tokens to generate"
+                        },
+                        "stop": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Stop generating at any of these strings"
+                        },
+                        "json": {
+                            "type": "boolean",
+                            "description": "Constrain the output to valid JSON (default: false)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "llm_embed",
+                "description": "Compute embedding vectors for one or more texts with a local embedding model, for semantic search and clustering",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "input": {
+                            "description": "Text or array of texts to embed",
+                            "oneOf": [
+                                { "type": "string" },
+                                { "type": "array", "items": { "type": "string" } }
+                            ]
+                        },
+                        "model": {
+                            "type": "string",
+                            "description": "Embedding model, e.g. 'nomic-embed-text' (default: [llm] embed_model)"
+                        }
+                    },
+                    "required": ["input"]
+                }
+            }),
+            json!({
+                "name": "llm_models",
+                "description": "List the models available on the local LLM server, with the configured defaults",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+        ]
+    }
+
+    pub async fn generate(&self, args: Value) -> Result<Value> {
+        let mut messages = Vec::new();
+        if let Some(system) = args["system"].as_str() {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        if let Some(history) = args["messages"].as_array() {
+            for message in history {
+                let role = message["role"].as_str().context("Each message needs a 'role'")?;
+                if !matches!(role, "system" | "user" | "assistant") {
+                    anyhow::bail!("Unknown message role '{}' (expected system, user or assistant)", role);
+                }
+                let content = message["content"].as_str().context("Each message needs a 'content' string")?;
+                messages.push(json!({ "role": role, "content": content }));
+            }
+        }
+        if let Some(prompt) = args["prompt"].as_str() {
+            messages.push(json!({ "role": "user", "content": prompt }));
+        }
+        if !messages.iter().any(|m| m["role"] != "system") {
+            anyhow::bail!("Pass 'prompt' or 'messages'");
+        }
+
+        let started = Instant::now();
+        let generation = self.client.generate(args["model"].as_str(), messages, &args).await?;
+
+        Ok(json!({
+            "model": generation.model,
+            "text": generation.text,
+            "finish_reason": generation.finish_reason,
+            "usage": {
+                "prompt_tokens": generation.prompt_tokens,
+                "completion_tokens": generation.completion_tokens,
+            },
+            "duration_ms": started.elapsed().as_millis() as u64,
+        }))
+    }
+
+    pub async fn embed(&self, args: Value) -> Result<Value> {
+        let (inputs, single) = match &args["input"] {
+            Value::String(text) => (vec![text.clone()], true),
+            Value::Array(items) => (
+                items
+                    .iter()
+                    .map(|item| item.as_str().map(String::from).context("'input' must contain only strings"))
+                    .collect::<Result<Vec<_>>>()?,
+                false,
+            ),
+            _ => anyhow::bail!("Missing 'input' parameter (a string or an array of strings)"),
+        };
+        if inputs.is_empty() {
+            anyhow::bail!("'input' is empty");
+        }
+
+        let (model, vectors) = self.client.embed(&inputs, args["model"].as_str()).await?;
+        let dimensions = vectors.first().map(Vec::len).unwrap_or(0);
+        let mut result = json!({
+            "model": model,
+            "dimensions": dimensions,
+            "count": vectors.len(),
+        });
+        if single {
+            result["embedding"] = json!(vectors.into_iter().next());
+        } else {
+            result["embeddings"] = json!(vectors);
+        }
+        Ok(result)
+    }
+
+    pub async fn models(&self, _args: Value) -> Result<Value> {
+        let models = self.client.models().await?;
+        Ok(json!({
+            "server": self.client.base_url(),
+            "default_model": self.client.model,
+            "default_embed_model": self.client.embed_model,
+            "count": models.len(),
+            "models": models,
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for LlmModule {
+    fn name(&self) -> &str {
+        "llm"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "llm_generate" => self.generate(args).await,
+            "llm_embed" => self.embed(args).await,
+            "llm_models" => self.models(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    fn warm_up(&self) {
+        self.client.http.get();
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// The error message in an error body: `{"error": "..."}` from Ollama or
+/// `{"error": {"message": "..."}}` from OpenAI-compatible servers
+fn error_message(body: &Value) -> Option<String> {
+    match &body["error"] {
+        Value::String(message) => Some(message.clone()),
+        Value::Object(error) => error.get("message").and_then(Value::as_str).map(String::from),
+        _ => None,
+    }
+}
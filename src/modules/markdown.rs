@@ -0,0 +1,1029 @@
+use serde_json::{json, Map, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::config::NetworkConfig;
+use crate::modules::network::NetworkModule;
+use crate::provenance;
+use crate::registry::ToolModule;
+use pulldown_cmark::{BrokenLink, CodeBlockKind, Event, LinkType, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::task::JoinSet;
+
+const TOC_START: &str = "<!-- toc -->";
+const TOC_END: &str = "<!-- tocstop -->";
+
+/// External links checked at the same time
+const MAX_CONCURRENT_CHECKS: usize = 8;
+
+const LINT_RULES: &[&str] = &[
+    "heading-increment",
+    "single-h1",
+    "empty-heading",
+    "duplicate-heading",
+    "fenced-code-language",
+    "trailing-whitespace",
+    "broken-link",
+];
+
+pub struct MarkdownModule {
+    // Used for the optional liveness check of external links
+    network: NetworkModule,
+}
+
+impl Default for MarkdownModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownModule {
+    pub fn new() -> Self {
+        Self::with_config(&NetworkConfig::default())
+    }
+
+    pub fn with_config(config: &NetworkConfig) -> Self {
+        Self {
+            network: NetworkModule::with_config(config),
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "md_toc",
+                "description": "Generate a table of contents from a markdown document's headings, with GitHub-style anchors. Optionally insert it between <!-- toc --> and <!-- tocstop --> markers",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Markdown file (or pass 'content')"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Markdown text (alternative to 'path')"
+                        },
+                        "min_level": {
+                            "type": "integer",
+                            "description": "Shallowest heading level to include (default: 1)"
+                        },
+                        "max_level": {
+                            "type": "integer",
+                            "description": "Deepest heading level to include (default: 3)"
+                        },
+                        "insert": {
+                            "type": "boolean",
+                            "description": "Insert the TOC at the <!-- toc --> marker, replacing any previous one. Writes the file when 'path' is given, otherwise returns the updated content (default: false)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "md_links",
+                "description": "Extract the links and images of a markdown document with their line numbers, and check them: in-document anchors, relative files (and anchors in linked markdown files), undefined references, and optionally whether external URLs respond",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Markdown file (or pass 'content')"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Markdown text (alternative to 'path')"
+                        },
+                        "base_dir": {
+                            "type": "string",
+                            "description": "Directory relative links are resolved against (default: the file's directory, or the working directory for 'content')"
+                        },
+                        "check": {
+                            "type": "boolean",
+                            "description": "Check anchors, relative files and references (default: true)"
+                        },
+                        "check_external": {
+                            "type": "boolean",
+                            "description": "Also request http(s) links to see whether they respond (default: false)"
+                        },
+                        "only_broken": {
+                            "type": "boolean",
+                            "description": "Return only broken links (default: false)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "md_lint",
+                "description": "Check a markdown document for structural problems: skipped heading levels, multiple H1s, empty or duplicate headings, fenced code without a language, trailing whitespace, and broken local links",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Markdown file (or pass 'content')"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Markdown text (alternative to 'path')"
+                        },
+                        "base_dir": {
+                            "type": "string",
+                            "description": "Directory relative links are resolved against (default: the file's directory)"
+                        },
+                        "disable": {
+                            "type": "array",
+                            "items": {
+                                "type": "string",
+                                "enum": LINT_RULES
+                            },
+                            "description": "Rules to skip"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "md_render",
+                "description": "Convert markdown to HTML (CommonMark with tables, footnotes, strikethrough and task lists). Front matter is not rendered",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Markdown file (or pass 'content')"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Markdown text (alternative to 'path')"
+                        },
+                        "standalone": {
+                            "type": "boolean",
+                            "description": "Wrap the result in a complete HTML document, titled from the front matter 'title' or the first heading (default: false)"
+                        },
+                        "heading_ids": {
+                            "type": "boolean",
+                            "description": "Give headings GitHub-style id attributes so anchor links work (default: true)"
+                        },
+                        "output_path": {
+                            "type": "string",
+                            "description": "Write the HTML to this file instead of returning it"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "md_frontmatter",
+                "description": "Read or update the front matter of a markdown document (YAML between --- lines, or TOML between +++ lines) as JSON",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Markdown file (or pass 'content')"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "Markdown text (alternative to 'path')"
+                        },
+                        "action": {
+                            "type": "string",
+                            "enum": ["get", "set", "replace", "remove"],
+                            "description": "'get' reads, 'set' merges 'values' (null deletes a key), 'replace' swaps in 'values', 'remove' deletes the front matter (default: get)"
+                        },
+                        "values": {
+                            "type": "object",
+                            "description": "Keys to set or the new front matter"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["yaml", "toml"],
+                            "description": "Format for documents without front matter yet (default: yaml). Existing front matter keeps its format"
+                        }
+                    }
+                }
+            }),
+        ]
+    }
+
+    pub async fn toc(&self, args: Value) -> Result<Value> {
+        let (content, path) = load(&args)?;
+        let min_level = args["min_level"].as_u64().unwrap_or(1).clamp(1, 6) as u8;
+        let max_level = args["max_level"].as_u64().unwrap_or(3).clamp(1, 6) as u8;
+        if min_level > max_level {
+            anyhow::bail!("min_level ({}) is greater than max_level ({})", min_level, max_level);
+        }
+
+        let doc = Document::parse(&content);
+        let included: Vec<&Heading> = doc
+            .headings
+            .iter()
+            .filter(|h| (min_level..=max_level).contains(&h.level))
+            .collect();
+        let base = included.iter().map(|h| h.level).min().unwrap_or(min_level);
+        let toc = included
+            .iter()
+            .map(|h| {
+                let indent = "  ".repeat((h.level - base) as usize);
+                format!("{}- [{}](#{})", indent, escape_link_text(&h.text), h.anchor)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut result = json!({
+            "headings": doc.headings.iter().map(Heading::to_json).collect::<Vec<_>>(),
+            "toc": toc,
+        });
+
+        if args["insert"].as_bool().unwrap_or(false) {
+            let updated = insert_toc(&content, &toc)?;
+            let changed = updated != content;
+            match &path {
+                Some(path) => {
+                    if changed {
+                        std::fs::write(path, &updated)
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                    }
+                    result["path"] = json!(path);
+                }
+                None => result["content"] = json!(updated),
+            }
+            result["changed"] = json!(changed);
+        }
+
+        Ok(result)
+    }
+
+    pub async fn links(&self, args: Value) -> Result<Value> {
+        let (content, path) = load(&args)?;
+        let check = args["check"].as_bool().unwrap_or(true);
+        let check_external = args["check_external"].as_bool().unwrap_or(false);
+        let only_broken = args["only_broken"].as_bool().unwrap_or(false);
+        let base_dir = base_dir(&args, path.as_deref());
+
+        let doc = Document::parse(&content);
+        let mut links: Vec<LinkReport> = doc.links.iter().map(LinkReport::new).collect();
+
+        if check {
+            for report in &mut links {
+                check_local(report, &doc, &base_dir);
+            }
+        }
+        if check_external {
+            self.check_external(&mut links).await;
+        }
+
+        let count = |status: &str| links.iter().filter(|l| l.status == status).count();
+        let summary = json!({
+            "total": links.len(),
+            "ok": count("ok"),
+            "broken": count("broken"),
+            "unchecked": count("unchecked"),
+        });
+
+        let links: Vec<Value> = links
+            .iter()
+            .filter(|l| !only_broken || l.status == "broken")
+            .map(LinkReport::to_json)
+            .collect();
+
+        Ok(json!({
+            "path": path,
+            "links": links,
+            "summary": summary,
+        }))
+    }
+
+    pub async fn lint(&self, args: Value) -> Result<Value> {
+        let (content, path) = load(&args)?;
+        let base_dir = base_dir(&args, path.as_deref());
+        let disabled: Vec<&str> = args["disable"]
+            .as_array()
+            .map(|rules| rules.iter().filter_map(|r| r.as_str()).collect())
+            .unwrap_or_default();
+        for rule in &disabled {
+            if !LINT_RULES.contains(rule) {
+                anyhow::bail!("Unknown rule: {}. Valid rules: {}", rule, LINT_RULES.join(", "));
+            }
+        }
+        let enabled = |rule: &str| !disabled.contains(&rule);
+
+        let doc = Document::parse(&content);
+        let mut issues = Vec::new();
+        let mut issue = |line: usize, rule: &str, severity: &str, message: String| {
+            if enabled(rule) {
+                issues.push(json!({ "line": line, "rule": rule, "severity": severity, "message": message }));
+            }
+        };
+
+        let mut previous: Option<u8> = None;
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for heading in &doc.headings {
+            if let Some(previous) = previous {
+                if heading.level > previous + 1 {
+                    issue(heading.line, "heading-increment", "warning", format!(
+                        "Heading level jumps from H{} to H{}; use H{}",
+                        previous, heading.level, previous + 1
+                    ));
+                }
+            }
+            previous = Some(heading.level);
+
+            if heading.text.trim().is_empty() {
+                issue(heading.line, "empty-heading", "warning", "Heading has no text".to_string());
+            } else if let Some(first) = seen.get(&heading.text) {
+                issue(heading.line, "duplicate-heading", "warning", format!(
+                    "Duplicate heading '{}' (first on line {}); its anchor becomes #{}",
+                    heading.text, first, heading.anchor
+                ));
+            } else {
+                seen.insert(heading.text.clone(), heading.line);
+            }
+        }
+
+        let h1s: Vec<&Heading> = doc.headings.iter().filter(|h| h.level == 1).collect();
+        for extra in h1s.iter().skip(1) {
+            issue(extra.line, "single-h1", "warning", format!(
+                "Multiple top-level headings; the first is on line {}",
+                h1s[0].line
+            ));
+        }
+
+        for line in &doc.unlabelled_code_blocks {
+            issue(*line, "fenced-code-language", "info", "Fenced code block has no language".to_string());
+        }
+
+        for (i, text) in content.lines().enumerate() {
+            let trimmed = text.trim_end_matches([' ', '\t']);
+            let trailing = &text[trimmed.len()..];
+            // Exactly two spaces after text is a deliberate hard line break
+            let hard_break = trailing == "  " && !trimmed.is_empty();
+            if !trailing.is_empty() && !hard_break {
+                issue(i + 1, "trailing-whitespace", "info", format!(
+                    "{} trailing whitespace character(s)",
+                    trailing.len()
+                ));
+            }
+        }
+
+        if enabled("broken-link") {
+            for link in &doc.links {
+                let mut report = LinkReport::new(link);
+                check_local(&mut report, &doc, &base_dir);
+                if report.status == "broken" {
+                    issue(report.line, "broken-link", "error", format!(
+                        "{}: {}",
+                        report.url,
+                        report.reason.unwrap_or_default()
+                    ));
+                }
+            }
+        }
+
+        issues.sort_by_key(|i| i["line"].as_u64());
+        let errors = issues.iter().filter(|i| i["severity"] == "error").count();
+        let warnings = issues.iter().filter(|i| i["severity"] == "warning").count();
+
+        Ok(json!({
+            "path": path,
+            "issues": issues,
+            "errors": errors,
+            "warnings": warnings,
+            "clean": issues.is_empty(),
+        }))
+    }
+
+    pub async fn render(&self, args: Value) -> Result<Value> {
+        let (content, path) = load(&args)?;
+        let standalone = args["standalone"].as_bool().unwrap_or(false);
+        let heading_ids = args["heading_ids"].as_bool().unwrap_or(true);
+
+        let doc = Document::parse(&content);
+        let mut anchors = doc.headings.iter().map(|h| h.anchor.clone());
+        let events = Parser::new_ext(&content, parse_options()).map(|event| match event {
+            Event::Start(Tag::Heading { level, id: None, classes, attrs }) if heading_ids => {
+                let id = anchors.next().map(Into::into);
+                Event::Start(Tag::Heading { level, id, classes, attrs })
+            }
+            Event::Start(Tag::Heading { .. }) => {
+                anchors.next();
+                event
+            }
+            other => other,
+        });
+
+        let mut body = String::new();
+        pulldown_cmark::html::push_html(&mut body, events);
+
+        let html = if standalone {
+            let title = split_front_matter(&content)
+                .and_then(|fm| fm.parse().ok())
+                .and_then(|data| data["title"].as_str().map(String::from))
+                .or_else(|| doc.headings.first().map(|h| h.text.clone()))
+                .unwrap_or_default();
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+                escape_html(&title),
+                body
+            )
+        } else {
+            body
+        };
+
+        match args["output_path"].as_str() {
+            Some(output) => {
+                std::fs::write(output, &html).with_context(|| format!("Failed to write {}", output))?;
+                Ok(json!({
+                    "path": path,
+                    "output_path": output,
+                    "bytes": html.len(),
+                }))
+            }
+            None => Ok(json!({
+                "path": path,
+                "html": html,
+            })),
+        }
+    }
+
+    pub async fn frontmatter(&self, args: Value) -> Result<Value> {
+        let (content, path) = load(&args)?;
+        let action = args["action"].as_str().unwrap_or("get");
+
+        let existing = split_front_matter(&content);
+        let current = match &existing {
+            Some(fm) => fm.parse()?,
+            None => json!({}),
+        };
+        let format = match &existing {
+            Some(fm) => fm.format,
+            None => match args["format"].as_str().unwrap_or("yaml") {
+                "yaml" => FrontMatterFormat::Yaml,
+                "toml" => FrontMatterFormat::Toml,
+                other => anyhow::bail!("Unsupported format: {}. Use yaml or toml", other),
+            },
+        };
+        let body = existing.as_ref().map(|fm| fm.body).unwrap_or(&content);
+
+        let data = match action {
+            "get" => {
+                return Ok(json!({
+                    "path": path,
+                    "present": existing.is_some(),
+                    "format": existing.as_ref().map(|fm| fm.format.name()),
+                    "data": current,
+                }));
+            }
+            "set" => {
+                let values = args["values"].as_object().context("Missing 'values' parameter")?;
+                let mut data = current.as_object().cloned().unwrap_or_default();
+                for (key, value) in values {
+                    if value.is_null() {
+                        data.remove(key);
+                    } else {
+                        data.insert(key.clone(), value.clone());
+                    }
+                }
+                Some(data)
+            }
+            "replace" => Some(args["values"].as_object().cloned().context("Missing 'values' parameter")?),
+            "remove" => None,
+            _ => anyhow::bail!("Unsupported action: {}. Use get, set, replace or remove", action),
+        };
+
+        let updated = match &data {
+            Some(data) => format!("{}{}", format.render(data)?, body),
+            None => body.to_string(),
+        };
+        let changed = updated != content;
+
+        let mut result = json!({
+            "path": path,
+            "action": action,
+            "format": data.as_ref().map(|_| format.name()),
+            "data": data.map(Value::Object),
+            "changed": changed,
+        });
+        match &path {
+            Some(path) if changed => {
+                std::fs::write(path, &updated).with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+            Some(_) => {}
+            None => result["content"] = json!(updated),
+        }
+        Ok(result)
+    }
+
+    /// Request every http(s) link, a few at a time
+    async fn check_external(&self, links: &mut [LinkReport]) {
+        let mut pending = links
+            .iter()
+            .enumerate()
+            .filter(|(_, link)| link.kind == "external")
+            .map(|(index, link)| (index, link.url.clone()))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let mut checks = JoinSet::new();
+        loop {
+            while checks.len() < MAX_CONCURRENT_CHECKS {
+                let Some((index, url)) = pending.next() else {
+                    break;
+                };
+                let network = self.network.clone();
+                checks.spawn(async move { (index, network.check_url(&url).await) });
+            }
+
+            let Some(done) = checks.join_next().await else {
+                break;
+            };
+            let Ok((index, check)) = done else {
+                continue;
+            };
+            let report = &mut links[index];
+            report.status = if check.ok { "ok" } else { "broken" };
+            report.http_status = check.status;
+            report.redirected_to = check.final_url;
+            report.reason = match (check.error, check.status) {
+                (Some(error), _) => Some(error),
+                (None, Some(status)) if !check.ok => Some(format!("HTTP {}", status)),
+                _ => None,
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl ToolModule for MarkdownModule {
+    fn name(&self) -> &str {
+        "md"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "md_toc" => self.toc(args).await,
+            "md_links" => self.links(args).await,
+            "md_lint" => self.lint(args).await,
+            "md_render" => self.render(args).await,
+            "md_frontmatter" => self.frontmatter(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Document model ──────────────────────────────────────────────────────
+
+struct Heading {
+    level: u8,
+    text: String,
+    anchor: String,
+    line: usize,
+}
+
+impl Heading {
+    fn to_json(&self) -> Value {
+        json!({
+            "level": self.level,
+            "text": self.text,
+            "anchor": self.anchor,
+            "line": self.line,
+        })
+    }
+}
+
+struct Link {
+    url: String,
+    text: String,
+    line: usize,
+    image: bool,
+    // Set for `[text][label]` references with no matching definition
+    undefined_reference: bool,
+}
+
+/// The parts of a markdown document the tools work with, with 1-based line numbers
+struct Document {
+    headings: Vec<Heading>,
+    links: Vec<Link>,
+    unlabelled_code_blocks: Vec<usize>,
+}
+
+impl Document {
+    fn parse(content: &str) -> Self {
+        let lines = LineIndex::new(content);
+        let mut broken = Vec::new();
+        let mut headings = Vec::new();
+        let mut links = Vec::new();
+        let mut unlabelled_code_blocks = Vec::new();
+        let mut anchors: HashMap<String, usize> = HashMap::new();
+
+        // The heading and link currently open, with the text collected for each
+        let mut heading: Option<(u8, Option<String>, usize)> = None;
+        let mut link: Option<(String, bool, usize)> = None;
+        let mut heading_text = String::new();
+        let mut link_text = String::new();
+
+        let callback = |link: BrokenLink| {
+            // Shortcut references like [TODO] are usually just brackets in prose
+            if matches!(link.link_type, LinkType::Reference | LinkType::Collapsed) {
+                broken.push((link.reference.to_string(), link.span.start));
+            }
+            None
+        };
+        let parser = Parser::new_with_broken_link_callback(content, parse_options(), Some(callback));
+
+        for (event, range) in parser.into_offset_iter() {
+            match event {
+                Event::Start(Tag::Heading { level, id, .. }) => {
+                    heading = Some((level as u8, id.map(|id| id.to_string()), range.start));
+                    heading_text.clear();
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    if let Some((level, id, start)) = heading.take() {
+                        let title = heading_text.trim().to_string();
+                        let anchor = id.unwrap_or_else(|| unique_anchor(&mut anchors, slugify(&title)));
+                        headings.push(Heading { level, text: title, anchor, line: lines.line(start) });
+                    }
+                }
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    link = Some((dest_url.to_string(), false, range.start));
+                    link_text.clear();
+                }
+                Event::Start(Tag::Image { dest_url, .. }) => {
+                    link = Some((dest_url.to_string(), true, range.start));
+                    link_text.clear();
+                }
+                Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                    if let Some((url, image, start)) = link.take() {
+                        links.push(Link {
+                            url,
+                            text: link_text.trim().to_string(),
+                            line: lines.line(start),
+                            image,
+                            undefined_reference: false,
+                        });
+                    }
+                }
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if info.trim().is_empty() => {
+                    unlabelled_code_blocks.push(lines.line(range.start));
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if heading.is_some() {
+                        heading_text.push_str(&t);
+                    }
+                    if link.is_some() {
+                        link_text.push_str(&t);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        links.extend(broken.into_iter().map(|(reference, start)| Link {
+            url: format!("[{}]", reference),
+            text: reference,
+            line: lines.line(start),
+            image: false,
+            undefined_reference: true,
+        }));
+        links.sort_by_key(|l| l.line);
+
+        Self { headings, links, unlabelled_code_blocks }
+    }
+
+    fn has_anchor(&self, anchor: &str) -> bool {
+        self.headings.iter().any(|h| h.anchor == anchor)
+    }
+}
+
+/// Byte offset to line number
+struct LineIndex(Vec<usize>);
+
+impl LineIndex {
+    fn new(content: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self(starts)
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.0.partition_point(|&start| start <= offset)
+    }
+}
+
+// ── Link checking ───────────────────────────────────────────────────────
+
+struct LinkReport {
+    url: String,
+    text: String,
+    line: usize,
+    image: bool,
+    kind: &'static str,
+    status: &'static str,
+    reason: Option<String>,
+    http_status: Option<u16>,
+    redirected_to: Option<String>,
+}
+
+impl LinkReport {
+    fn new(link: &Link) -> Self {
+        let (kind, status, reason) = if link.undefined_reference {
+            ("reference", "broken", Some(format!("No definition for reference [{}]", link.text)))
+        } else {
+            (link_kind(&link.url), "unchecked", None)
+        };
+        Self {
+            url: link.url.clone(),
+            text: link.text.clone(),
+            line: link.line,
+            image: link.image,
+            kind,
+            status,
+            reason,
+            http_status: None,
+            redirected_to: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut value = json!({
+            "url": self.url,
+            "text": self.text,
+            "line": self.line,
+            "kind": self.kind,
+            "status": self.status,
+        });
+        if self.image {
+            value["image"] = json!(true);
+        }
+        if let Some(reason) = &self.reason {
+            value["reason"] = json!(reason);
+        }
+        if let Some(status) = self.http_status {
+            value["http_status"] = json!(status);
+        }
+        if let Some(redirected_to) = &self.redirected_to {
+            value["redirected_to"] = json!(redirected_to);
+        }
+        value
+    }
+}
+
+fn link_kind(url: &str) -> &'static str {
+    if url.starts_with('#') {
+        return "anchor";
+    }
+    match url.split_once(':') {
+        Some((scheme, _)) if is_scheme(scheme) => match scheme.to_ascii_lowercase().as_str() {
+            "http" | "https" => "external",
+            "mailto" => "email",
+            _ => "other",
+        },
+        _ => "file",
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    // Longer than one character, so Windows drive letters are not mistaken for schemes
+    s.len() > 1
+        && s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Check anchors and relative files; other kinds are left as they are
+fn check_local(report: &mut LinkReport, doc: &Document, base_dir: &Path) {
+    match report.kind {
+        "anchor" => {
+            let anchor = decode(&report.url[1..]);
+            if anchor.is_empty() || doc.has_anchor(&anchor) {
+                report.status = "ok";
+            } else {
+                report.status = "broken";
+                report.reason = Some(format!("No heading with anchor #{}", anchor));
+            }
+        }
+        "file" => {
+            let (target, fragment) = match report.url.split_once('#') {
+                Some((target, fragment)) => (target, Some(fragment)),
+                None => (report.url.as_str(), None),
+            };
+            let target = target.split('?').next().unwrap_or(target);
+            let file = base_dir.join(decode(target));
+            if !file.exists() {
+                report.status = "broken";
+                report.reason = Some(format!("File not found: {}", file.display()));
+                return;
+            }
+
+            report.status = "ok";
+            let is_markdown = file
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "md" | "markdown"));
+            if let (Some(fragment), true) = (fragment, is_markdown) {
+                let anchor = decode(fragment);
+                let found = std::fs::read_to_string(&file)
+                    .map(|linked| Document::parse(&linked).has_anchor(&anchor))
+                    .unwrap_or(false);
+                if !found {
+                    report.status = "broken";
+                    report.reason = Some(format!("No heading with anchor #{} in {}", anchor, file.display()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn decode(s: &str) -> String {
+    urlencoding::decode(s).map(|d| d.into_owned()).unwrap_or_else(|_| s.to_string())
+}
+
+// ── Front matter ────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy)]
+enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+impl FrontMatterFormat {
+    fn name(self) -> &'static str {
+        match self {
+            FrontMatterFormat::Yaml => "yaml",
+            FrontMatterFormat::Toml => "toml",
+        }
+    }
+
+    fn render(self, data: &Map<String, Value>) -> Result<String> {
+        match self {
+            FrontMatterFormat::Yaml => {
+                let yaml = if data.is_empty() {
+                    String::new()
+                } else {
+                    serde_yaml::to_string(data).context("Failed to serialize front matter as YAML")?
+                };
+                Ok(format!("---\n{}---\n", yaml))
+            }
+            FrontMatterFormat::Toml => {
+                let toml = toml::to_string(data).context("Failed to serialize front matter as TOML")?;
+                Ok(format!("+++\n{}+++\n", toml))
+            }
+        }
+    }
+}
+
+struct FrontMatter<'a> {
+    format: FrontMatterFormat,
+    raw: &'a str,
+    body: &'a str,
+}
+
+impl FrontMatter<'_> {
+    fn parse(&self) -> Result<Value> {
+        let value = match self.format {
+            FrontMatterFormat::Yaml => {
+                let value: Value = serde_yaml::from_str(self.raw).context("Invalid YAML front matter")?;
+                if value.is_null() { json!({}) } else { value }
+            }
+            FrontMatterFormat::Toml => {
+                let value: toml::Value = toml::from_str(self.raw).context("Invalid TOML front matter")?;
+                toml_to_json(value)
+            }
+        };
+        if !value.is_object() {
+            anyhow::bail!("Front matter is not a mapping of keys to values");
+        }
+        Ok(value)
+    }
+}
+
+/// Split `---` (YAML) or `+++` (TOML) front matter from the body
+fn split_front_matter(content: &str) -> Option<FrontMatter<'_>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let (format, fence) = if content.starts_with("---") {
+        (FrontMatterFormat::Yaml, "---")
+    } else if content.starts_with("+++") {
+        (FrontMatterFormat::Toml, "+++")
+    } else {
+        return None;
+    };
+
+    let first_line_end = content.find('\n')?;
+    if content[..first_line_end].trim_end() != fence {
+        return None;
+    }
+
+    let mut offset = first_line_end + 1;
+    for line in content[offset..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        let closes = trimmed == fence || (fence == "---" && trimmed == "...");
+        if closes {
+            return Some(FrontMatter {
+                format,
+                raw: &content[first_line_end + 1..offset],
+                body: &content[offset + line.len()..],
+            });
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => json!(s),
+        toml::Value::Integer(i) => json!(i),
+        toml::Value::Float(f) => json!(f),
+        toml::Value::Boolean(b) => json!(b),
+        toml::Value::Datetime(dt) => json!(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn parse_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
+}
+
+/// Read the document from 'path' or take it from 'content'
+fn load(args: &Value) -> Result<(String, Option<PathBuf>)> {
+    match (args["path"].as_str(), args["content"].as_str()) {
+        (Some(path), _) => {
+            provenance::read(path);
+            let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+            Ok((content, Some(PathBuf::from(path))))
+        }
+        (None, Some(content)) => Ok((content.to_string(), None)),
+        (None, None) => anyhow::bail!("Pass either 'path' or 'content'"),
+    }
+}
+
+fn base_dir(args: &Value, path: Option<&Path>) -> PathBuf {
+    match (args["base_dir"].as_str(), path) {
+        (Some(dir), _) => PathBuf::from(dir),
+        (None, Some(path)) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        (None, None) => PathBuf::from("."),
+    }
+}
+
+/// GitHub's heading anchors: lowercase, punctuation dropped, spaces to hyphens
+fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Repeated anchors get -1, -2, ... suffixes, as on GitHub
+fn unique_anchor(seen: &mut HashMap<String, usize>, anchor: String) -> String {
+    let count = seen.entry(anchor.clone()).or_insert(0);
+    let unique = if *count == 0 { anchor } else { format!("{}-{}", anchor, count) };
+    *count += 1;
+    unique
+}
+
+fn escape_link_text(text: &str) -> String {
+    text.replace('[', "\\[").replace(']', "\\]")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Put the TOC between the markers, adding the closing marker if it is missing
+fn insert_toc(content: &str, toc: &str) -> Result<String> {
+    let start = content.find(TOC_START).with_context(|| {
+        format!("No {} marker found; add {} and {} where the table of contents should go", TOC_START, TOC_START, TOC_END)
+    })?;
+    let after_start = start + TOC_START.len();
+    let end: Range<usize> = match content[after_start..].find(TOC_END) {
+        Some(offset) => after_start + offset..after_start + offset + TOC_END.len(),
+        None => after_start..after_start,
+    };
+
+    Ok(format!(
+        "{}{}\n\n{}\n\n{}{}",
+        &content[..start],
+        TOC_START,
+        toc,
+        TOC_END,
+        &content[end.end..]
+    ))
+}
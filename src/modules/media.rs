@@ -0,0 +1,300 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::config::MediaConfig;
+use crate::progress;
+use crate::provenance;
+use crate::registry::ToolModule;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+/// Whisper models expect 16 kHz mono audio
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+pub struct MediaModule {
+    config: MediaConfig,
+    // Loading a model takes seconds, so the last one used is kept around
+    model: Mutex<Option<(PathBuf, Arc<WhisperContext>)>>,
+}
+
+impl Default for MediaModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MediaModule {
+    pub fn new() -> Self {
+        Self::with_config(&MediaConfig::default())
+    }
+
+    pub fn with_config(config: &MediaConfig) -> Self {
+        Self {
+            config: config.clone(),
+            model: Mutex::new(None),
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "media_transcribe",
+                "description": "Transcribe an audio file (wav, mp3, m4a/aac, flac, ogg) with a local whisper model and return timestamped segments. Nothing leaves the machine. Reports percent complete to clients that pass a progress token",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Audio file to transcribe"
+                        },
+                        "model": {
+                            "type": "string",
+                            "description": "Path to a ggml whisper model, e.g. ggml-base.en.bin (default: [media] whisper_model from the config)"
+                        },
+                        "language": {
+                            "type": "string",
+                            "description": "Spoken language code such as 'en' or 'de', or 'auto' to detect it (default: auto)"
+                        },
+                        "translate": {
+                            "type": "boolean",
+                            "description": "Translate the speech to English (default: false)"
+                        },
+                        "prompt": {
+                            "type": "string",
+                            "description": "Initial prompt to guide spelling of names and terms"
+                        },
+                        "threads": {
+                            "type": "integer",
+                            "description": "CPU threads to use (default: [media] threads, or the number of cores up to 8)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn transcribe(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        provenance::read(path);
+        let language = args["language"].as_str().unwrap_or("auto").to_string();
+        let translate = args["translate"].as_bool().unwrap_or(false);
+        let prompt = args["prompt"].as_str().map(String::from);
+        let threads = args["threads"]
+            .as_u64()
+            .map(|t| t as u32)
+            .or(self.config.threads)
+            .unwrap_or_else(default_threads)
+            .max(1);
+
+        let model_path = args["model"]
+            .as_str()
+            .map(PathBuf::from)
+            .or_else(|| self.config.whisper_model.clone())
+            .context(
+                "No whisper model configured. Pass 'model', set whisper_model in the [media] table of poly-mcp.toml, \
+                 or start the server with --whisper-model (ggml models: https://huggingface.co/ggerganov/whisper.cpp)",
+            )?;
+        if !model_path.is_file() {
+            anyhow::bail!("Whisper model not found: {}", model_path.display());
+        }
+
+        let audio_path = PathBuf::from(path);
+        let cached = self.model.lock().unwrap().clone();
+        let reporter = progress::reporter();
+
+        // Whisper keeps running on its blocking thread if the call is cancelled or
+        // times out; this flag, flipped when the future is dropped, tells it to stop
+        let abort = Arc::new(AtomicBool::new(false));
+        let _abort_on_drop = AbortOnDrop(abort.clone());
+
+        let task = tokio::task::spawn_blocking(move || -> Result<(Arc<WhisperContext>, Value)> {
+            let context = match cached {
+                Some((cached_path, context)) if cached_path == model_path => context,
+                _ => Arc::new(
+                    WhisperContext::new_with_params(&model_path, WhisperContextParameters::default())
+                        .with_context(|| format!("Failed to load whisper model {}", model_path.display()))?,
+                ),
+            };
+
+            let (audio, duration) = decode_audio(&audio_path)?;
+            if audio.is_empty() {
+                anyhow::bail!("No audio samples in {}", audio_path.display());
+            }
+
+            let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+            params.set_language(Some(&language));
+            params.set_translate(translate);
+            params.set_n_threads(threads as i32);
+            params.set_print_progress(false);
+            params.set_print_realtime(false);
+            params.set_print_special(false);
+            params.set_print_timestamps(false);
+            if let Some(prompt) = &prompt {
+                params.set_initial_prompt(prompt);
+            }
+            if let Some(reporter) = reporter {
+                params.set_progress_callback_safe(move |percent: i32| {
+                    reporter.report(percent.max(0) as u64, Some(100), "Transcribing");
+                });
+            }
+            params.set_abort_callback_safe(move || abort.load(Ordering::SeqCst));
+
+            let mut state = context.create_state().context("Failed to create whisper state")?;
+            state.full(params, &audio).context("Transcription failed")?;
+
+            let mut segments = Vec::new();
+            let mut text = String::new();
+            for segment in state.as_iter() {
+                let segment_text = segment.to_str_lossy()?.trim().to_string();
+                if !text.is_empty() && !segment_text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(&segment_text);
+                segments.push(json!({
+                    // whisper timestamps are in centiseconds
+                    "start": segment.start_timestamp() as f64 / 100.0,
+                    "end": segment.end_timestamp() as f64 / 100.0,
+                    "text": segment_text,
+                    "no_speech_probability": segment.no_speech_probability(),
+                }));
+            }
+
+            let detected = whisper_rs::get_lang_str(state.full_lang_id_from_state()).unwrap_or(&language);
+            Ok((context, json!({
+                "path": audio_path,
+                "model": model_path,
+                "language": detected,
+                "translated": translate,
+                "duration_secs": duration,
+                "text": text,
+                "segments": segments,
+                "segment_count": segments.len(),
+            })))
+        });
+
+        let (context, result) = task.await.context("Transcription task failed")??;
+        let model_path = PathBuf::from(result["model"].as_str().unwrap_or_default());
+        *self.model.lock().unwrap() = Some((model_path, context));
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl ToolModule for MediaModule {
+    fn name(&self) -> &str {
+        "media"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "media_transcribe" => self.transcribe(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+struct AbortOnDrop(Arc<AtomicBool>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+fn default_threads() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(4)
+        .min(8)
+}
+
+/// Decode an audio file to 16 kHz mono samples, plus its duration in seconds
+fn decode_audio(path: &Path) -> Result<(Vec<f32>, f64)> {
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .with_context(|| format!("Unsupported or unrecognized audio format: {}", path.display()))?;
+    let mut format = probed.format;
+    let track = format.default_track().context("File has no audio track")?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate.context("Unknown sample rate")?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("Unsupported audio codec")?;
+
+    let mut mono = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(Error::ResetRequired) => break,
+            Err(e) => return Err(e).context("Failed to read audio"),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt frame is skipped rather than failing the whole file
+            Err(Error::DecodeError(_)) => continue,
+            Err(e) => return Err(e).context("Failed to decode audio"),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        mono.extend(
+            buffer
+                .samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+
+    let duration = mono.len() as f64 / sample_rate as f64;
+    Ok((resample(&mono, sample_rate, WHISPER_SAMPLE_RATE), duration))
+}
+
+/// Linear-interpolation resampling; good enough for speech recognition
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let length = (samples.len() as f64 / ratio) as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let current = samples[index];
+            let next = samples.get(index + 1).copied().unwrap_or(current);
+            current + (next - current) * fraction
+        })
+        .collect()
+}
@@ -1,16 +1,82 @@
+// Built-in tool modules, each behind the cargo feature named after it; session and prompts are always built
+
+pub mod prompts;
+pub mod session;
+
+#[cfg(feature = "apps")]
+pub mod apps;
+
+#[cfg(feature = "calc")]
+pub mod calc;
+
+#[cfg(feature = "clipboard")]
 pub mod clipboard;
+
+#[cfg(feature = "context")]
 pub mod context;
+
+#[cfg(feature = "crypto")]
+pub mod crypto;
+
+#[cfg(feature = "diagnostics")]
 pub mod diagnostics;
+
+#[cfg(feature = "filesystem")]
 pub mod filesystem;
+
+#[cfg(feature = "git")]
 pub mod git;
+
+#[cfg(feature = "input")]
 pub mod input;
+
+#[cfg(feature = "llm")]
+pub mod llm;
+
+#[cfg(feature = "md")]
+pub mod markdown;
+
+#[cfg(feature = "network")]
 pub mod network;
+
+#[cfg(feature = "power")]
+pub mod power;
+
+#[cfg(feature = "regex")]
+pub mod regex_lab;
+
+#[cfg(feature = "settings")]
+pub mod settings;
+
+#[cfg(feature = "silent")]
 pub mod silent;
+
+#[cfg(feature = "text")]
+pub mod text;
+
+#[cfg(feature = "time")]
 pub mod time;
+
+#[cfg(feature = "transform")]
 pub mod transform;
 
+#[cfg(feature = "txn")]
+pub mod txn;
+
+#[cfg(feature = "testing")]
+pub mod fixtures;
+
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
 #[cfg(feature = "gitent")]
 pub mod gitent;
 
+#[cfg(feature = "whisper")]
+pub mod media;
+
+#[cfg(feature = "browser")]
+pub mod browser;
+
 #[cfg(feature = "premium")]
 pub mod varp_bridge;
@@ -1,10 +1,53 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::progress;
+use crate::provenance;
+use crate::registry::{Lazy, ToolModule};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process::Command;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::config::NetworkConfig;
+use crate::locale;
+use crate::telemetry;
 
+/// Where the registry cache is kept without `[network] registry_cache_dir`: the
+/// user's cache directory, so every server the user runs shares it
+pub fn default_registry_cache_dir() -> PathBuf {
+    match dirs::cache_dir() {
+        Some(cache) => cache.join("poly-mcp").join("registry"),
+        None => PathBuf::from(".poly-mcp").join("registry-cache"),
+    }
+}
+
+/// Most named sessions net_fetch keeps cookies for at once; starting another
+/// drops the one used least recently
+const MAX_SESSIONS: usize = 64;
+
+/// Named sessions unused for this long are dropped with their cookies
+const SESSION_IDLE: Duration = Duration::from_secs(3600);
+
+#[derive(Clone)]
 pub struct NetworkModule {
+    config: NetworkConfig,
+    // Built on the first request
+    client: Lazy<reqwest::Client>,
+    // None when `registry_cache_ttl_secs` is 0
+    registry_cache: Option<Arc<RegistryCache>>,
+    // None when `dns_cache_ttl_secs` is 0; shared by every client
+    dns_cache: Option<Arc<DnsCache>>,
+    // net_fetch `session` clients, each with its own cookies and connections
+    sessions: Arc<Mutex<HashMap<String, FetchSession>>>,
+}
+
+/// A named net_fetch session: its client, which holds the cookie jar, and when it was last used
+struct FetchSession {
     client: reqwest::Client,
+    last_used: Instant,
 }
 
 impl Default for NetworkModule {
@@ -15,20 +58,65 @@ impl Default for NetworkModule {
 
 impl NetworkModule {
     pub fn new() -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent(format!("poly-mcp/{}", env!("CARGO_PKG_VERSION")))
-            .build()
-            .unwrap();
+        Self::with_config(&NetworkConfig::default())
+    }
+
+    pub fn with_config(config: &NetworkConfig) -> Self {
+        let dns_cache = (config.dns_cache_ttl_secs > 0).then(|| {
+            Arc::new(DnsCache {
+                ttl: Duration::from_secs(config.dns_cache_ttl_secs),
+                entries: Arc::default(),
+            })
+        });
+        let client = Lazy::new({
+            let config = config.clone();
+            let dns_cache = dns_cache.clone();
+            move || build_client(&config, dns_cache.as_ref(), None)
+        });
+        let registry_cache = (config.registry_cache_ttl_secs > 0).then(|| {
+            Arc::new(RegistryCache {
+                dir: config.registry_cache_dir.clone().unwrap_or_else(default_registry_cache_dir),
+                ttl: Duration::from_secs(config.registry_cache_ttl_secs),
+            })
+        });
 
-        Self { client }
+        Self {
+            config: config.clone(),
+            client,
+            registry_cache,
+            dns_cache,
+            sessions: Arc::default(),
+        }
+    }
+
+    /// The client for net_fetch's named `session`, made on first use with a cookie jar
+    /// of its own, so cookies a site sets are sent back on the session's later calls
+    fn session_client(&self, session: &str) -> reqwest::Client {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, open| now.duration_since(open.last_used) < SESSION_IDLE);
+        if let Some(open) = sessions.get_mut(session) {
+            open.last_used = now;
+            return open.client.clone();
+        }
+        if sessions.len() >= MAX_SESSIONS {
+            let oldest = sessions.iter().min_by_key(|(_, open)| open.last_used).map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                tracing::info!("Dropping network session '{}' to make room for '{}'", oldest, session);
+                sessions.remove(&oldest);
+            }
+        }
+        let jar = Arc::new(reqwest::cookie::Jar::default());
+        let client = build_client(&self.config, self.dns_cache.as_ref(), Some(jar));
+        sessions.insert(session.to_string(), FetchSession { client: client.clone(), last_used: now });
+        client
     }
 
     pub fn get_tools(&self) -> Vec<Value> {
         vec![
             json!({
                 "name": "net_fetch",
-                "description": "Fetch content from URLs with automatic HTML to Markdown conversion",
+                "description": "Fetch content from URLs with automatic HTML to Markdown conversion. When the call carries a progress token, the raw body is streamed as progress notifications while it downloads, with bytes received out of the total",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -52,6 +140,10 @@ impl NetworkModule {
                         "convert_to_markdown": {
                             "type": "boolean",
                             "description": "Convert HTML to Markdown (default: true)"
+                        },
+                        "session": {
+                            "type": "string",
+                            "description": "Name of a session to make the request in. Cookies set by responses in a session are sent on its later requests, and its connections are kept apart from other sessions'. Without one, no cookies are kept. End it with net_session_close"
                         }
                     },
                     "required": ["url"]
@@ -71,6 +163,10 @@ impl NetworkModule {
                             "type": "string",
                             "enum": ["info", "search", "latest"],
                             "description": "Action to perform (default: info)"
+                        },
+                        "refresh": {
+                            "type": "boolean",
+                            "description": "Query the registry even when a cached response is still fresh (default: false)"
                         }
                     },
                     "required": ["crate_name"]
@@ -90,6 +186,10 @@ impl NetworkModule {
                             "type": "string",
                             "enum": ["info", "search", "latest"],
                             "description": "Action to perform (default: info)"
+                        },
+                        "refresh": {
+                            "type": "boolean",
+                            "description": "Query the registry even when a cached response is still fresh (default: false)"
                         }
                     },
                     "required": ["package_name"]
@@ -109,6 +209,10 @@ impl NetworkModule {
                             "type": "string",
                             "enum": ["info", "search", "latest"],
                             "description": "Action to perform (default: info)"
+                        },
+                        "refresh": {
+                            "type": "boolean",
+                            "description": "Query the registry even when a cached response is still fresh (default: false)"
                         }
                     },
                     "required": ["package_name"]
@@ -155,6 +259,20 @@ impl NetworkModule {
                     "required": ["host"]
                 }
             }),
+            json!({
+                "name": "net_session_close",
+                "description": "End a named net_fetch session, forgetting its cookies and closing its connections. Sessions also end after an hour unused, and the least recently used one is dropped when more than 64 are open",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "session": {
+                            "type": "string",
+                            "description": "Name of the session to end"
+                        }
+                    },
+                    "required": ["session"]
+                }
+            }),
         ]
     }
 
@@ -163,12 +281,19 @@ impl NetworkModule {
         let method = args["method"].as_str().unwrap_or("GET");
         let convert_to_markdown = args["convert_to_markdown"].as_bool().unwrap_or(true);
 
+        let session = args["session"].as_str();
+
+        provenance::fetched(url);
+        let client = match session {
+            Some(session) => self.session_client(session),
+            None => self.client.get().clone(),
+        };
         let mut request = match method {
-            "GET" => self.client.get(url),
-            "POST" => self.client.post(url),
-            "PUT" => self.client.put(url),
-            "DELETE" => self.client.delete(url),
-            "PATCH" => self.client.patch(url),
+            "GET" => client.get(url),
+            "POST" => client.post(url),
+            "PUT" => client.put(url),
+            "DELETE" => client.delete(url),
+            "PATCH" => client.patch(url),
             _ => return Err(anyhow::anyhow!("Unsupported HTTP method: {}", method)),
         };
 
@@ -195,7 +320,11 @@ impl NetworkModule {
             .and_then(|v| v.to_str().ok())
             .unwrap_or("");
 
-        let body_text = response.text().await?;
+        let body_text = if progress::is_streaming() {
+            read_body_streaming(response).await?
+        } else {
+            response.text().await?
+        };
 
         let processed_content = if convert_to_markdown && content_type.contains("text/html") {
             html2md::parse_html(&body_text)
@@ -216,23 +345,20 @@ impl NetworkModule {
             "content_type": content_type,
             "body": processed_content,
             "raw_body": body_text,
-            "converted_to_markdown": convert_to_markdown && content_type.contains("text/html")
+            "converted_to_markdown": convert_to_markdown && content_type.contains("text/html"),
+            "session": session
         }))
     }
 
     pub async fn cargo(&self, args: Value) -> Result<Value> {
         let crate_name = args["crate_name"].as_str().context("Missing 'crate_name' parameter")?;
         let action = args["action"].as_str().unwrap_or("info");
+        let refresh = args["refresh"].as_bool().unwrap_or(false);
 
         match action {
             "latest" => {
                 // Use cargo search to get latest version
-                let output = Command::new("cargo")
-                    .arg("search")
-                    .arg(crate_name)
-                    .arg("--limit")
-                    .arg("1")
-                    .output()
+                let output = telemetry::run_command(Command::new("cargo").arg("search").arg(crate_name).arg("--limit").arg("1"))
                     .context("Failed to run cargo search")?;
 
                 let stdout = String::from_utf8_lossy(&output.stdout);
@@ -260,12 +386,9 @@ impl NetworkModule {
             "info" | "search" => {
                 // Query crates.io API
                 let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
-                let response = self.client.get(&url).send().await?;
-
-                if response.status().is_success() {
-                    let data: Value = response.json().await?;
 
-                    Ok(json!({
+                if let Some((data, age)) = self.registry_json(&url, refresh).await? {
+                    Ok(with_cache_age(json!({
                         "crate": crate_name,
                         "info": data["crate"],
                         "versions": data["versions"],
@@ -275,7 +398,7 @@ impl NetworkModule {
                         "documentation": data["crate"]["documentation"],
                         "repository": data["crate"]["repository"],
                         "homepage": data["crate"]["homepage"]
-                    }))
+                    }), age))
                 } else {
                     Err(anyhow::anyhow!("Crate not found: {}", crate_name))
                 }
@@ -287,15 +410,12 @@ impl NetworkModule {
     pub async fn node(&self, args: Value) -> Result<Value> {
         let package_name = args["package_name"].as_str().context("Missing 'package_name' parameter")?;
         let action = args["action"].as_str().unwrap_or("info");
+        let refresh = args["refresh"].as_bool().unwrap_or(false);
 
         match action {
             "latest" => {
                 // Use npm view to get latest version
-                let output = Command::new("npm")
-                    .arg("view")
-                    .arg(package_name)
-                    .arg("version")
-                    .output()
+                let output = telemetry::run_command(Command::new("npm").arg("view").arg(package_name).arg("version"))
                     .context("Failed to run npm view")?;
 
                 let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -313,14 +433,11 @@ impl NetworkModule {
             "info" | "search" => {
                 // Query npm registry API
                 let url = format!("https://registry.npmjs.org/{}", package_name);
-                let response = self.client.get(&url).send().await?;
-
-                if response.status().is_success() {
-                    let data: Value = response.json().await?;
 
+                if let Some((data, age)) = self.registry_json(&url, refresh).await? {
                     let latest_version = data["dist-tags"]["latest"].as_str().unwrap_or("unknown");
 
-                    Ok(json!({
+                    Ok(with_cache_age(json!({
                         "package": package_name,
                         "latest_version": latest_version,
                         "description": data["description"],
@@ -331,7 +448,7 @@ impl NetworkModule {
                         "versions": data["versions"].as_object().map(|v| v.keys().collect::<Vec<_>>()),
                         "keywords": data["keywords"],
                         "dependencies": data["versions"][latest_version]["dependencies"]
-                    }))
+                    }), age))
                 } else {
                     Err(anyhow::anyhow!("Package not found: {}", package_name))
                 }
@@ -343,50 +460,40 @@ impl NetworkModule {
     pub async fn python(&self, args: Value) -> Result<Value> {
         let package_name = args["package_name"].as_str().context("Missing 'package_name' parameter")?;
         let action = args["action"].as_str().unwrap_or("info");
+        let refresh = args["refresh"].as_bool().unwrap_or(false);
 
         match action {
             "latest" => {
-                // Use pip index to get latest version
-                let output = Command::new("pip3")
-                    .arg("index")
-                    .arg("versions")
-                    .arg(package_name)
-                    .output()
-                    .context("Failed to run pip3 index")?;
-
-                let stdout = String::from_utf8_lossy(&output.stdout);
+                // pip index prints "name (version)" first; the lines after it are translated
+                let output = telemetry::run_command(
+                    locale::untranslated(&mut Command::new("pip3"))
+                        .args(["index", "versions", "--disable-pip-version-check"])
+                        .arg(package_name),
+                );
 
-                // Parse output for "LATEST: version"
-                for line in stdout.lines() {
-                    if line.contains("LATEST:") || line.contains("Available versions:") {
-                        if let Some(version) = line.split_whitespace().nth(1) {
-                            return Ok(json!({
-                                "package": package_name,
-                                "latest_version": version.trim_end_matches(','),
-                                "source": "pip3 index"
-                            }));
-                        }
-                    }
+                if let Some(version) = output.ok().and_then(|output| parse_pip_latest(&String::from_utf8_lossy(&output.stdout))) {
+                    return Ok(json!({
+                        "package": package_name,
+                        "latest_version": version,
+                        "source": "pip3 index"
+                    }));
                 }
 
                 // Fallback: query PyPI API
-                self.query_pypi_api(package_name, action).await
+                self.query_pypi_api(package_name, refresh).await
             }
             "info" | "search" => {
-                self.query_pypi_api(package_name, action).await
+                self.query_pypi_api(package_name, refresh).await
             }
             _ => Err(anyhow::anyhow!("Unknown action: {}", action)),
         }
     }
 
-    async fn query_pypi_api(&self, package_name: &str, _action: &str) -> Result<Value> {
+    async fn query_pypi_api(&self, package_name: &str, refresh: bool) -> Result<Value> {
         let url = format!("https://pypi.org/pypi/{}/json", package_name);
-        let response = self.client.get(&url).send().await?;
-
-        if response.status().is_success() {
-            let data: Value = response.json().await?;
 
-            Ok(json!({
+        if let Some((data, age)) = self.registry_json(&url, refresh).await? {
+            Ok(with_cache_age(json!({
                 "package": package_name,
                 "latest_version": data["info"]["version"],
                 "description": data["info"]["summary"],
@@ -396,57 +503,72 @@ impl NetworkModule {
                 "project_urls": data["info"]["project_urls"],
                 "requires_python": data["info"]["requires_python"],
                 "classifiers": data["info"]["classifiers"]
-            }))
+            }), age))
         } else {
             Err(anyhow::anyhow!("Package not found: {}", package_name))
         }
     }
 
+    /// GET a package registry's JSON API, from the registry cache when it has a fresh copy
+    /// and `refresh` is not set. `None` when the registry answers with an error status,
+    /// as for an unknown package; otherwise the data and, if it came from the cache, its age.
+    async fn registry_json(&self, url: &str, refresh: bool) -> Result<Option<(Value, Option<Duration>)>> {
+        let cache = self.registry_cache.as_deref();
+        if let Some(cache) = cache.filter(|_| !refresh) {
+            if let Some((body, age)) = cache.get(url) {
+                if let Ok(data) = serde_json::from_slice(&body) {
+                    // The answer came from the cache file, not the registry
+                    provenance::read(cache.path(url));
+                    return Ok(Some((data, Some(age))));
+                }
+            }
+        }
+
+        provenance::fetched(url);
+        let response = self.client.get().get(url).send().await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let body = response.bytes().await?;
+        let data = serde_json::from_slice(&body).with_context(|| format!("Invalid JSON from {}", url))?;
+        if let Some(cache) = cache {
+            // Not being able to cache only costs the next lookup a request
+            if let Err(e) = cache.put(url, &body) {
+                tracing::warn!("Failed to cache {}: {:#}", url, e);
+            }
+        }
+        Ok(Some((data, None)))
+    }
+
     pub async fn apt(&self, args: Value) -> Result<Value> {
         let package_name = args["package_name"].as_str().context("Missing 'package_name' parameter")?;
         let action = args["action"].as_str().unwrap_or("info");
 
         match action {
             "info" | "show" => {
-                let output = Command::new("apt")
-                    .arg("show")
-                    .arg(package_name)
-                    .output()
-                    .context("Failed to run apt show")?;
-
-                let stdout = String::from_utf8_lossy(&output.stdout);
-
-                let mut info = serde_json::Map::new();
+                // apt-cache rather than apt, whose output is not meant for scripts
+                let output = telemetry::run_command(locale::untranslated(&mut Command::new("apt-cache")).arg("show").arg(package_name))
+                    .context("Failed to run apt-cache show")?;
 
-                for line in stdout.lines() {
-                    if let Some((key, value)) = line.split_once(':') {
-                        info.insert(
-                            key.trim().to_lowercase().replace('-', "_"),
-                            json!(value.trim())
-                        );
-                    }
+                if !output.status.success() {
+                    return Err(anyhow::anyhow!("Package not found: {}", package_name));
                 }
 
                 Ok(json!({
                     "package": package_name,
-                    "info": info
+                    "info": parse_deb822(&String::from_utf8_lossy(&output.stdout))
                 }))
             }
             "search" => {
-                let output = Command::new("apt")
-                    .arg("search")
-                    .arg(package_name)
-                    .output()
-                    .context("Failed to run apt search")?;
+                let output = telemetry::run_command(locale::untranslated(&mut Command::new("apt-cache")).arg("search").arg(package_name))
+                    .context("Failed to run apt-cache search")?;
 
                 let stdout = String::from_utf8_lossy(&output.stdout);
-                let mut results = Vec::new();
-
-                for line in stdout.lines() {
-                    if line.contains('/') {
-                        results.push(line.to_string());
-                    }
-                }
+                let results: Vec<Value> = stdout
+                    .lines()
+                    .filter_map(|line| line.split_once(" - "))
+                    .map(|(name, description)| json!({ "name": name, "description": description }))
+                    .collect();
 
                 Ok(json!({
                     "query": package_name,
@@ -463,47 +585,20 @@ impl NetworkModule {
         let count = args["count"].as_u64().unwrap_or(4);
         let timeout = args["timeout"].as_u64().unwrap_or(5);
 
-        let output = Command::new("ping")
-            .arg("-c")
-            .arg(count.to_string())
-            .arg("-W")
-            .arg(timeout.to_string())
-            .arg(host)
-            .output()
-            .context("Failed to run ping")?;
+        let mut command = Command::new("ping");
+        if cfg!(windows) {
+            command.arg("-n").arg(count.to_string()).arg("-w").arg((timeout * 1000).to_string());
+        } else {
+            command.arg("-c").arg(count.to_string()).arg("-W").arg(timeout.to_string());
+        }
+        let output = telemetry::run_command(locale::untranslated(&mut command).arg(host)).context("Failed to run ping")?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let success = output.status.success();
-
-        // Parse ping statistics
-        let mut sent = 0;
-        let mut received = 0;
-        let mut min = 0.0;
-        let mut avg = 0.0;
-        let mut max = 0.0;
-
-        for line in stdout.lines() {
-            if line.contains("packets transmitted") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    sent = parts[0].parse().unwrap_or(0);
-                    received = parts[3].parse().unwrap_or(0);
-                }
-            }
-            if line.contains("min/avg/max") || line.contains("rtt") {
-                if let Some(stats_part) = line.split('=').nth(1) {
-                    let stats: Vec<&str> = stats_part.trim().split('/').collect();
-                    if stats.len() >= 3 {
-                        min = stats[0].parse().unwrap_or(0.0);
-                        avg = stats[1].parse().unwrap_or(0.0);
-                        max = stats[2].split_whitespace().next().unwrap_or("0").parse().unwrap_or(0.0);
-                    }
-                }
-            }
-        }
+        let (sent, received, (min, avg, max)) = parse_ping_summary(&stdout);
 
         let packet_loss = if sent > 0 {
-            ((sent - received) as f64 / sent as f64) * 100.0
+            (sent.saturating_sub(received) as f64 / sent as f64) * 100.0
         } else {
             100.0
         };
@@ -520,4 +615,529 @@ impl NetworkModule {
             "raw_output": stdout.to_string()
         }))
     }
+
+    pub async fn session_close(&self, args: Value) -> Result<Value> {
+        let session = args["session"].as_str().context("Missing 'session' parameter")?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let closed = sessions.remove(session).is_some();
+        let mut open: Vec<&str> = sessions.keys().map(String::as_str).collect();
+        open.sort();
+
+        Ok(json!({
+            "session": session,
+            "closed": closed,
+            "open_sessions": open
+        }))
+    }
+
+    /// Check whether a URL answers, for link checkers in other modules. Tries HEAD
+    /// first and falls back to GET, since some servers reject or mishandle HEAD.
+    pub async fn check_url(&self, url: &str) -> UrlCheck {
+        provenance::fetched(url);
+        let head = self.client.get().head(url).send().await;
+        let response = match head {
+            Ok(response) if response.status().is_success() => Ok(response),
+            _ => self.client.get().get(url).send().await,
+        };
+
+        match response {
+            Ok(response) => UrlCheck {
+                ok: response.status().is_success(),
+                status: Some(response.status().as_u16()),
+                final_url: Some(response.url().to_string()).filter(|final_url| final_url != url),
+                error: None,
+            },
+            Err(e) => UrlCheck {
+                ok: false,
+                status: None,
+                final_url: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Outcome of [`NetworkModule::check_url`]
+#[derive(Debug, Clone)]
+pub struct UrlCheck {
+    pub ok: bool,
+    /// Final HTTP status, after redirects
+    pub status: Option<u16>,
+    /// Where redirects led, if somewhere other than the requested URL
+    pub final_url: Option<String>,
+    /// Connection or timeout error, when there was no response at all
+    pub error: Option<String>,
+}
+
+/// An HTTP client with the `[network]` timeout, user agent, pool and keep-alive
+/// settings, resolving through `dns_cache` and keeping cookies in `jar` when given
+fn build_client(
+    config: &NetworkConfig,
+    dns_cache: Option<&Arc<DnsCache>>,
+    jar: Option<Arc<reqwest::cookie::Jar>>,
+) -> reqwest::Client {
+    let user_agent = config
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| format!("poly-mcp/{}", env!("CARGO_PKG_VERSION")));
+
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .user_agent(user_agent)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .tcp_keepalive(config.tcp_keepalive_secs.map(Duration::from_secs));
+    if let Some(max) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(secs) = config.http2_keep_alive_secs {
+        builder = builder
+            .http2_keep_alive_interval(Duration::from_secs(secs))
+            .http2_keep_alive_while_idle(true);
+    }
+    if let Some(dns_cache) = dns_cache {
+        builder = builder.dns_resolver(dns_cache.clone());
+    }
+    if let Some(jar) = jar {
+        builder = builder.cookie_provider(jar);
+    }
+    builder.build().unwrap()
+}
+
+/// Each host looked up, with when and what it resolved to
+type DnsEntries = HashMap<String, (Instant, Vec<SocketAddr>)>;
+
+/// Host lookups kept for a TTL, so repeated requests to a host skip the resolver
+struct DnsCache {
+    ttl: Duration,
+    entries: Arc<Mutex<DnsEntries>>,
+}
+
+impl reqwest::dns::Resolve for DnsCache {
+    fn resolve(&self, name: hyper014::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        let ttl = self.ttl;
+        let entries = self.entries.clone();
+        Box::pin(async move {
+            let cached = entries
+                .lock()
+                .unwrap()
+                .get(&host)
+                .filter(|(resolved, _)| resolved.elapsed() < ttl)
+                .map(|(_, addrs)| addrs.clone());
+            let addrs = match cached {
+                Some(addrs) => addrs,
+                None => {
+                    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+                    let mut entries = entries.lock().unwrap();
+                    entries.retain(|_, (resolved, _)| resolved.elapsed() < ttl);
+                    entries.insert(host, (Instant::now(), addrs.clone()));
+                    addrs
+                }
+            };
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Registry responses on disk, one file per URL holding the body as received; the
+/// file's modification time is when it was fetched
+struct RegistryCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl RegistryCache {
+    fn path(&self, url: &str) -> PathBuf {
+        use sha2::{Digest, Sha256};
+        self.dir.join(format!("{:x}.json", Sha256::digest(url.as_bytes())))
+    }
+
+    /// The body cached for `url` and how old it is, unless older than the TTL
+    fn get(&self, url: &str) -> Option<(Vec<u8>, Duration)> {
+        let path = self.path(url);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().unwrap_or_default();
+        if age >= self.ttl {
+            return None;
+        }
+        Some((fs::read(&path).ok()?, age))
+    }
+
+    /// Replace the file for `url` whole, so other servers sharing the directory
+    /// never read one half written, and delete the files that have expired
+    fn put(&self, url: &str, body: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        self.prune();
+        let temp = self.dir.join(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+        fs::write(&temp, body).with_context(|| format!("Failed to write {}", temp.display()))?;
+        let path = self.path(url);
+        fs::rename(&temp, &path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+
+    /// Delete responses older than the TTL, and temporary files a crashed server left
+    /// behind. Files another server is writing or reading at the same time are young,
+    /// so they are left alone.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let ours = (name.starts_with('.') && name.ends_with(".tmp")) || name.ends_with(".json");
+            let expired = entry
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() >= self.ttl);
+            if ours && expired {
+                // Another server may have pruned it first
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+/// Say in a registry lookup's result whether it came from the cache, and how old it is if so
+fn with_cache_age(mut result: Value, age: Option<Duration>) -> Value {
+    result["cached"] = json!(age.is_some());
+    if let Some(age) = age {
+        result["cached_age_secs"] = json!(age.as_secs());
+    }
+    result
+}
+
+#[async_trait]
+impl ToolModule for NetworkModule {
+    fn name(&self) -> &str {
+        "network"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "net_fetch" => self.fetch(args).await,
+            "net_cargo" => self.cargo(args).await,
+            "net_node" => self.node(args).await,
+            "net_python" => self.python(args).await,
+            "net_apt" => self.apt(args).await,
+            "net_ping" => self.ping(args).await,
+            "net_session_close" => self.session_close(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    fn warm_up(&self) {
+        self.client.get();
+    }
+}
+
+/// Read a response body chunk by chunk, streaming the text to the client as it arrives
+/// along with the bytes received so far (out of Content-Length, when the server sent one).
+/// The body is decoded as UTF-8; a character split across chunks is held back until complete.
+async fn read_body_streaming(mut response: reqwest::Response) -> Result<String> {
+    let total = response.content_length();
+    let mut body = Vec::new();
+    let mut sent = 0;
+
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        let ready = match std::str::from_utf8(&body[sent..]) {
+            Ok(text) => text.len(),
+            // Only an incomplete character at the end; wait for the next chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => body.len() - sent,
+        };
+        if ready > 0 {
+            let text = String::from_utf8_lossy(&body[sent..sent + ready]);
+            progress::output_at("body", &text, body.len() as u64, total);
+            sent += ready;
+        }
+    }
+    if sent < body.len() {
+        progress::output_at("body", &String::from_utf8_lossy(&body[sent..]), body.len() as u64, total);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// The first line of `pip index versions`: "name (version)"
+fn parse_pip_latest(output: &str) -> Option<String> {
+    let first = output.lines().find(|line| !line.trim().is_empty())?;
+    let (_, rest) = first.split_once('(')?;
+    let version = rest.split(')').next()?.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// The first stanza of `apt-cache show`, with field names lowercased and
+/// continuation lines folded into their field
+fn parse_deb822(output: &str) -> serde_json::Map<String, Value> {
+    let mut info = serde_json::Map::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                // A lone "." stands for an empty line
+                let text = line.trim();
+                value.push('\n');
+                value.push_str(if text == "." { "" } else { text });
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            let mut key = key.trim().to_lowercase().replace('-', "_");
+            // Description-en and the like, for archives with translated descriptions
+            if key.starts_with("description_") && key != "description_md5" {
+                key = "description".to_string();
+            }
+            fields.push((key, value.trim().to_string()));
+        }
+    }
+
+    for (key, value) in fields {
+        info.insert(key, json!(value));
+    }
+    info
+}
+
+/// Packets sent and received, and round-trip min/avg/max in ms, from ping's summary.
+/// The summary is found by shape rather than wording, since Windows translates it
+/// whatever the environment says: the counts are the first two numbers on the last
+/// line with a '%', and the times come after it, either as "min/avg/max/mdev = a/b/c/d"
+/// or, on Windows, as three "= Nms" values in the order minimum, maximum, average.
+fn parse_ping_summary(output: &str) -> (u64, u64, (f64, f64, f64)) {
+    let lines: Vec<&str> = output.lines().collect();
+    let Some(counts_at) = lines.iter().rposition(|line| line.contains('%')) else {
+        return (0, 0, (0.0, 0.0, 0.0));
+    };
+    let mut counts = locale::numbers(lines[counts_at]);
+    if counts.len() < 3 && counts_at > 0 {
+        // Some translations wrap the loss percentage onto a line of its own
+        counts = locale::numbers(&format!("{} {}", lines[counts_at - 1], lines[counts_at]));
+    }
+    let sent = counts.first().copied().unwrap_or(0.0) as u64;
+    let received = counts.get(1).copied().unwrap_or(0.0) as u64;
+
+    let mut rtt = (0.0, 0.0, 0.0);
+    for line in &lines[counts_at + 1..] {
+        let Some((_, values)) = line.rsplit_once('=') else {
+            continue;
+        };
+        let slashed: Vec<f64> = values.split('/').filter_map(|value| locale::numbers(value).first().copied()).collect();
+        if slashed.len() >= 3 {
+            rtt = (slashed[0], slashed[1], slashed[2]);
+            break;
+        }
+        let windows = locale::numbers(line);
+        if line.matches('=').count() == 3 && windows.len() == 3 {
+            rtt = (windows[0], windows[2], windows[1]);
+            break;
+        }
+    }
+
+    (sent, received, rtt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured output of `ping -c 3`, `ping -n 4` and friends
+
+    const PING_LINUX: &str = "\
+PING 127.0.0.1 (127.0.0.1) 56(84) bytes of data.
+64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=0.045 ms
+64 bytes from 127.0.0.1: icmp_seq=2 ttl=64 time=0.061 ms
+64 bytes from 127.0.0.1: icmp_seq=3 ttl=64 time=0.058 ms
+
+--- 127.0.0.1 ping statistics ---
+3 packets transmitted, 3 received, 0% packet loss, time 2041ms
+rtt min/avg/max/mdev = 0.045/0.054/0.061/0.007 ms
+";
+
+    // iputils under LANG=de_DE.UTF-8, as it prints when LC_ALL is not honoured
+    const PING_LINUX_DE: &str = "\
+PING example.com (93.184.216.34) 56(84) Bytes an Daten.
+64 Bytes von 93.184.216.34: icmp_seq=1 ttl=56 Zeit=11,2 ms
+64 Bytes von 93.184.216.34: icmp_seq=2 ttl=56 Zeit=11,7 ms
+64 Bytes von 93.184.216.34: icmp_seq=3 ttl=56 Zeit=11,4 ms
+
+--- example.com Ping-Statistiken ---
+3 Pakete übertragen, 3 empfangen, 0% Paketverlust, Zeit 2003ms
+rtt min/avg/max/mdev = 11,175/11,412/11,703/0,217 ms
+";
+
+    const PING_LINUX_UNREACHABLE: &str = "\
+PING 10.255.255.1 (10.255.255.1) 56(84) bytes of data.
+
+--- 10.255.255.1 ping statistics ---
+3 packets transmitted, 0 received, 100% packet loss, time 2052ms
+
+";
+
+    const PING_MACOS: &str = "\
+PING 127.0.0.1 (127.0.0.1): 56 data bytes
+64 bytes from 127.0.0.1: icmp_seq=0 ttl=64 time=0.052 ms
+64 bytes from 127.0.0.1: icmp_seq=1 ttl=64 time=0.093 ms
+64 bytes from 127.0.0.1: icmp_seq=2 ttl=64 time=0.068 ms
+
+--- 127.0.0.1 ping statistics ---
+3 packets transmitted, 3 packets received, 0.0% packet loss
+round-trip min/avg/max/stddev = 0.052/0.071/0.093/0.017 ms
+";
+
+    const PING_WINDOWS: &str = "\r
+Pinging 192.168.1.1 with 32 bytes of data:\r
+Reply from 192.168.1.1: bytes=32 time=2ms TTL=64\r
+Reply from 192.168.1.1: bytes=32 time=1ms TTL=64\r
+Request timed out.\r
+Reply from 192.168.1.1: bytes=32 time=4ms TTL=64\r
+\r
+Ping statistics for 192.168.1.1:\r
+    Packets: Sent = 4, Received = 3, Lost = 1 (25% loss),\r
+Approximate round trip times in milli-seconds:\r
+    Minimum = 1ms, Maximum = 4ms, Average = 2ms\r
+";
+
+    // Windows translates the summary whatever LC_ALL says, and German wraps the loss
+    const PING_WINDOWS_DE: &str = "\r
+Ping wird ausgeführt für 192.168.1.1 mit 32 Bytes Daten:\r
+Antwort von 192.168.1.1: Bytes=32 Zeit=3ms TTL=64\r
+Antwort von 192.168.1.1: Bytes=32 Zeit=1ms TTL=64\r
+Antwort von 192.168.1.1: Bytes=32 Zeit=2ms TTL=64\r
+Antwort von 192.168.1.1: Bytes=32 Zeit=2ms TTL=64\r
+\r
+Ping-Statistik für 192.168.1.1:\r
+    Pakete: Gesendet = 4, Empfangen = 4, Verloren = 0\r
+    (0% Verlust),\r
+Ca. Zeitangaben in Millisek.:\r
+    Minimum = 1ms, Maximum = 3ms, Mittelwert = 2ms\r
+";
+
+    const PING_WINDOWS_FR: &str = "\r
+Envoi d’une requête 'Ping'  192.168.1.1 avec 32 octets de données :\r
+Réponse de 192.168.1.1 : octets=32 temps=5 ms TTL=64\r
+Réponse de 192.168.1.1 : octets=32 temps=2 ms TTL=64\r
+\r
+Statistiques Ping pour 192.168.1.1:\r
+    Paquets : envoyés = 2, reçus = 2, perdus = 0 (perte 0%),\r
+Durée approximative des boucles en millisecondes :\r
+    Minimum = 2ms, Maximum = 5ms, Moyenne = 3ms\r
+";
+
+    #[test]
+    fn parses_ping_on_linux() {
+        assert_eq!(parse_ping_summary(PING_LINUX), (3, 3, (0.045, 0.054, 0.061)));
+        assert_eq!(parse_ping_summary(PING_LINUX_UNREACHABLE), (3, 0, (0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn parses_ping_with_decimal_commas() {
+        assert_eq!(parse_ping_summary(PING_LINUX_DE), (3, 3, (11.175, 11.412, 11.703)));
+    }
+
+    #[test]
+    fn parses_ping_on_macos() {
+        assert_eq!(parse_ping_summary(PING_MACOS), (3, 3, (0.052, 0.071, 0.093)));
+    }
+
+    #[test]
+    fn parses_translated_ping_on_windows() {
+        // Windows lists minimum, maximum, average
+        assert_eq!(parse_ping_summary(PING_WINDOWS), (4, 3, (1.0, 2.0, 4.0)));
+        assert_eq!(parse_ping_summary(PING_WINDOWS_DE), (4, 4, (1.0, 2.0, 3.0)));
+        assert_eq!(parse_ping_summary(PING_WINDOWS_FR), (2, 2, (2.0, 3.0, 5.0)));
+    }
+
+    #[test]
+    fn parses_nothing_from_a_failed_ping() {
+        assert_eq!(parse_ping_summary("ping: unknown host nosuch.invalid\n"), (0, 0, (0.0, 0.0, 0.0)));
+    }
+
+    // `apt-cache show --no-all-versions`, the second with a translated description
+    const APT_BASH: &str = "\
+Package: bash
+Source: bash (5.2.15-2)
+Version: 5.2.15-2+b13
+Essential: yes
+Installed-Size: 7164
+Maintainer: Matthias Klose <doko@debian.org>
+Architecture: amd64
+Depends: base-files (>= 2.1.12), debianutils (>= 5.6-0.1)
+Pre-Depends: libc6 (>= 2.36), libtinfo6 (>= 6)
+Description: GNU Bourne Again SHell
+Description-md5: 3522aa7b4374048d6450e348a5bb45d9
+Multi-Arch: foreign
+Homepage: http://tiswww.case.edu/php/chet/bash/bashtop.html
+Tag: admin::TODO, devel::TODO, devel::interpreter, implemented-in::c,
+ interface::shell, interface::text-mode, role::program,
+ scope::application, suite::gnu, uitoolkit::ncurses
+Section: shells
+Priority: required
+
+Package: bash
+Version: 5.1-6ubuntu1
+";
+
+    const APT_CURL_EN: &str = "\
+Package: curl
+Architecture: amd64
+Version: 7.81.0-1ubuntu1.15
+Priority: optional
+Section: web
+Description-en: command line tool for transferring data with URL syntax
+ curl is a command line tool for transferring data with URL syntax, supporting
+ DICT, FILE, FTP, FTPS, GOPHER, HTTP, HTTPS, IMAP, IMAPS, LDAP, LDAPS, POP3, POP3S,
+ RTMP, RTSP, SCP, SFTP, SMTP, SMTPS, TELNET and TFTP.
+ .
+ curl supports SSL certificates, HTTP POST, HTTP PUT, FTP uploading, and more.
+Description-md5: 1e8ea2bd6d7c0ae1a33e2fc5ff8d7e1c
+
+";
+
+    #[test]
+    fn parses_the_first_apt_stanza() {
+        let info = parse_deb822(APT_BASH);
+        assert_eq!(info["package"], "bash");
+        assert_eq!(info["version"], "5.2.15-2+b13");
+        assert_eq!(info["installed_size"], "7164");
+        assert_eq!(info["pre_depends"], "libc6 (>= 2.36), libtinfo6 (>= 6)");
+        assert_eq!(info["description"], "GNU Bourne Again SHell");
+        assert_eq!(info["description_md5"], "3522aa7b4374048d6450e348a5bb45d9");
+        assert_eq!(
+            info["tag"],
+            "admin::TODO, devel::TODO, devel::interpreter, implemented-in::c,\n\
+             interface::shell, interface::text-mode, role::program,\n\
+             scope::application, suite::gnu, uitoolkit::ncurses"
+        );
+    }
+
+    #[test]
+    fn folds_translated_apt_descriptions() {
+        let info = parse_deb822(APT_CURL_EN);
+        let description = info["description"].as_str().unwrap();
+        assert!(description.starts_with("command line tool for transferring data with URL syntax\ncurl is"));
+        assert!(description.contains("RTMP, RTSP, SCP, SFTP, SMTP, SMTPS, TELNET and TFTP.\n\ncurl supports"));
+        assert_eq!(info["description_md5"], "1e8ea2bd6d7c0ae1a33e2fc5ff8d7e1c");
+    }
+
+    #[test]
+    fn parses_pip_index_versions() {
+        let output = "\
+requests (2.32.3)
+Available versions: 2.32.3, 2.32.2, 2.31.0, 2.30.0
+  INSTALLED: 2.31.0
+  LATEST:    2.32.3
+";
+        assert_eq!(parse_pip_latest(output).as_deref(), Some("2.32.3"));
+        assert_eq!(parse_pip_latest("\nnumpy (2.0.0rc1)\n").as_deref(), Some("2.0.0rc1"));
+        // No match goes to stderr, leaving stdout empty
+        assert_eq!(parse_pip_latest(""), None);
+        assert_eq!(parse_pip_latest("requests ()\n"), None);
+    }
 }
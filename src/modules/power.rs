@@ -0,0 +1,518 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::config::PowerConfig;
+use crate::registry::ToolModule;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+/// How long a shutdown/restart approval token stays valid
+const APPROVAL_TTL: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShutdownAction {
+    Shutdown,
+    Restart,
+}
+
+impl ShutdownAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            ShutdownAction::Shutdown => "shutdown",
+            ShutdownAction::Restart => "restart",
+        }
+    }
+}
+
+struct PendingApproval {
+    action: ShutdownAction,
+    delay_secs: u64,
+    expires: Instant,
+}
+
+struct ScheduledShutdown {
+    action: ShutdownAction,
+    at: DateTime<Utc>,
+    task: JoinHandle<()>,
+}
+
+// Dropping a JoinHandle leaves its task running, and a shutdown must not outlive its session
+impl Drop for ScheduledShutdown {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct KeepAwake {
+    // The inhibitor process; killing it lets the machine sleep again
+    child: Child,
+    started: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+    reason: String,
+}
+
+pub struct PowerModule {
+    config: PowerConfig,
+    approvals: Arc<Mutex<HashMap<String, PendingApproval>>>,
+    scheduled: Arc<Mutex<Option<ScheduledShutdown>>>,
+    keep_awake: Arc<tokio::sync::Mutex<Option<KeepAwake>>>,
+}
+
+impl Default for PowerModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerModule {
+    pub fn new() -> Self {
+        Self::with_config(&PowerConfig::default())
+    }
+
+    pub fn with_config(config: &PowerConfig) -> Self {
+        Self {
+            config: config.clone(),
+            approvals: Arc::new(Mutex::new(HashMap::new())),
+            scheduled: Arc::new(Mutex::new(None)),
+            keep_awake: Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "power_lock",
+                "description": "Lock the screen of the current desktop session",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "power_sleep",
+                "description": "Put the machine to sleep (suspend) now. Ends any power_keep_awake first",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "power_shutdown",
+                "description": "Shut down or restart the machine after a delay, or cancel a pending one. Requires approval: the first call returns an approval_token and what will happen; calling again with the token asks the user to confirm on this machine (a dialog or terminal prompt, per [policy] approval_mode) before anything is scheduled. Disabled unless the server allows it ([power] allow_shutdown or --allow-shutdown). A pending shutdown is dropped if the session ends or the server exits",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "action": {
+                            "type": "string",
+                            "enum": ["shutdown", "restart", "cancel", "status"],
+                            "description": "What to do (default: shutdown)"
+                        },
+                        "delay_secs": {
+                            "type": "integer",
+                            "description": "Seconds to wait before shutting down, leaving time to cancel (default: 60)"
+                        },
+                        "approval_token": {
+                            "type": "string",
+                            "description": "Token from the first call; the call then waits for the user to confirm"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "power_keep_awake",
+                "description": "Keep the machine from sleeping, e.g. until a long build finishes. Uses systemd-inhibit (Linux), caffeinate (macOS) or SetThreadExecutionState (Windows). Ends after duration_secs, on 'stop', or when the server exits",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "action": {
+                            "type": "string",
+                            "enum": ["start", "stop", "status"],
+                            "description": "Start, stop, or report keep-awake (default: start)"
+                        },
+                        "duration_secs": {
+                            "type": "integer",
+                            "description": "Stop keeping awake after this many seconds (default: until stopped)"
+                        },
+                        "display": {
+                            "type": "boolean",
+                            "description": "Also keep the display on (default: false)"
+                        },
+                        "reason": {
+                            "type": "string",
+                            "description": "Shown by the OS as the reason sleep is blocked (default: 'Agent task in progress')"
+                        }
+                    }
+                }
+            }),
+        ]
+    }
+
+    pub async fn lock(&self, _args: Value) -> Result<Value> {
+        let method = run_first(lock_commands())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to lock the screen: {}", e))?;
+        Ok(json!({
+            "locked": true,
+            "method": method
+        }))
+    }
+
+    pub async fn sleep(&self, _args: Value) -> Result<Value> {
+        // An active inhibitor would block the suspend
+        let keep_awake_stopped = self.stop_keep_awake().await?.is_some();
+
+        let method = run_first(sleep_commands())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to put the machine to sleep: {}", e))?;
+        Ok(json!({
+            "sleeping": true,
+            "method": method,
+            "keep_awake_stopped": keep_awake_stopped
+        }))
+    }
+
+    pub async fn shutdown(&self, args: Value) -> Result<Value> {
+        let action = match args["action"].as_str().unwrap_or("shutdown") {
+            "shutdown" => ShutdownAction::Shutdown,
+            "restart" => ShutdownAction::Restart,
+            "cancel" => return Ok(self.cancel_shutdown()),
+            "status" => return Ok(self.shutdown_status()),
+            other => anyhow::bail!("Unknown action: {} (expected shutdown, restart, cancel or status)", other),
+        };
+        let delay_secs = args["delay_secs"].as_u64().unwrap_or(60);
+
+        if !self.config.allow_shutdown {
+            anyhow::bail!(
+                "{} is disabled on this server. Allow it with allow_shutdown = true in the [power] table of poly-mcp.toml or --allow-shutdown",
+                action.as_str()
+            );
+        }
+
+        let Some(token) = args["approval_token"].as_str() else {
+            let token = uuid::Uuid::new_v4().simple().to_string();
+            let mut approvals = self.approvals.lock().unwrap();
+            let now = Instant::now();
+            approvals.retain(|_, pending| pending.expires > now);
+            approvals.insert(
+                token.clone(),
+                PendingApproval {
+                    action,
+                    delay_secs,
+                    expires: now + APPROVAL_TTL,
+                },
+            );
+            return Ok(json!({
+                "status": "approval_required",
+                "action": action.as_str(),
+                "delay_secs": delay_secs,
+                "approval_token": token,
+                "expires_in_secs": APPROVAL_TTL.as_secs(),
+                "message": format!(
+                    "This will {} the machine {} seconds after approval. Call power_shutdown again with this approval_token to ask the user to confirm.",
+                    action.as_str(), delay_secs
+                )
+            }));
+        };
+
+        // The user has confirmed this call by now (see Policy::needs_approval); tokens only
+        // keep it from being replayed, being single use and valid for one action
+        let pending = self
+            .approvals
+            .lock()
+            .unwrap()
+            .remove(token)
+            .filter(|pending| pending.expires > Instant::now())
+            .context("Approval token is invalid or expired; call power_shutdown without a token to request a new one")?;
+        if pending.action != action || pending.delay_secs != delay_secs {
+            anyhow::bail!(
+                "Approval token was issued for {} in {} seconds, not {} in {} seconds",
+                pending.action.as_str(), pending.delay_secs, action.as_str(), delay_secs
+            );
+        }
+
+        let at = Utc::now() + chrono::Duration::seconds(delay_secs as i64);
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(delay_secs)).await;
+            if let Err(e) = run_first(shutdown_commands(action)).await {
+                tracing::error!("Failed to {}: {:#}", action.as_str(), e);
+            }
+        });
+
+        let replaced = self
+            .scheduled
+            .lock()
+            .unwrap()
+            .replace(ScheduledShutdown { action, at, task });
+
+        Ok(json!({
+            "status": "scheduled",
+            "action": action.as_str(),
+            "at": at.to_rfc3339(),
+            "delay_secs": delay_secs,
+            "replaced_previous": replaced.is_some(),
+            "message": "Call power_shutdown with action 'cancel' to abort"
+        }))
+    }
+
+    pub async fn keep_awake(&self, args: Value) -> Result<Value> {
+        match args["action"].as_str().unwrap_or("start") {
+            "start" => {
+                let duration = args["duration_secs"].as_u64().filter(|&secs| secs > 0);
+                let display = args["display"].as_bool().unwrap_or(false);
+                let reason = args["reason"].as_str().unwrap_or("Agent task in progress").to_string();
+
+                let replaced = self.stop_keep_awake().await?.is_some();
+                let mut command = keep_awake_command(duration, display, &reason);
+                let mut child = command
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .context("Failed to start the keep-awake process")?;
+
+                // Inhibitors that cannot take effect (no logind session, etc.) exit right away
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                if let Some(status) = child.try_wait()? {
+                    let mut stderr = String::new();
+                    if let Some(mut pipe) = child.stderr.take() {
+                        let _ = pipe.read_to_string(&mut stderr).await;
+                    }
+                    anyhow::bail!("Keep-awake process exited immediately ({}): {}", status, stderr.trim());
+                }
+
+                let started = Utc::now();
+                let until = duration.map(|secs| started + chrono::Duration::seconds(secs as i64));
+                *self.keep_awake.lock().await = Some(KeepAwake {
+                    child,
+                    started,
+                    until,
+                    reason: reason.clone(),
+                });
+
+                Ok(json!({
+                    "active": true,
+                    "started": started.to_rfc3339(),
+                    "until": until.map(|t| t.to_rfc3339()),
+                    "display": display,
+                    "reason": reason,
+                    "replaced_previous": replaced
+                }))
+            }
+            "stop" => {
+                let stopped = self.stop_keep_awake().await?;
+                Ok(json!({
+                    "active": false,
+                    "stopped": stopped.is_some(),
+                    "started": stopped.map(|s| s.to_rfc3339())
+                }))
+            }
+            "status" => {
+                let mut guard = self.keep_awake.lock().await;
+                // The inhibitor exits by itself once its duration is up
+                if let Some(state) = guard.as_mut() {
+                    if state.child.try_wait()?.is_some() {
+                        *guard = None;
+                    }
+                }
+                Ok(match guard.as_ref() {
+                    Some(state) => json!({
+                        "active": true,
+                        "started": state.started.to_rfc3339(),
+                        "until": state.until.map(|t| t.to_rfc3339()),
+                        "reason": state.reason
+                    }),
+                    None => json!({ "active": false }),
+                })
+            }
+            other => anyhow::bail!("Unknown action: {} (expected start, stop or status)", other),
+        }
+    }
+
+    /// Kill the keep-awake process, returning when it was started
+    async fn stop_keep_awake(&self) -> Result<Option<DateTime<Utc>>> {
+        let Some(mut state) = self.keep_awake.lock().await.take() else {
+            return Ok(None);
+        };
+        if state.child.try_wait()?.is_none() {
+            state.child.kill().await.context("Failed to stop the keep-awake process")?;
+        }
+        Ok(Some(state.started))
+    }
+
+    fn cancel_shutdown(&self) -> Value {
+        match self.scheduled.lock().unwrap().take() {
+            Some(scheduled) if !scheduled.task.is_finished() => {
+                json!({
+                    "status": "cancelled",
+                    "action": scheduled.action.as_str(),
+                    "was_scheduled_for": scheduled.at.to_rfc3339()
+                })
+            }
+            _ => json!({ "status": "nothing_scheduled" }),
+        }
+    }
+
+    fn shutdown_status(&self) -> Value {
+        match self.scheduled.lock().unwrap().as_ref() {
+            Some(scheduled) if !scheduled.task.is_finished() => json!({
+                "status": "scheduled",
+                "action": scheduled.action.as_str(),
+                "at": scheduled.at.to_rfc3339(),
+                "remaining_secs": (scheduled.at - Utc::now()).num_seconds().max(0)
+            }),
+            _ => json!({
+                "status": "nothing_scheduled",
+                "allowed": self.config.allow_shutdown
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolModule for PowerModule {
+    fn name(&self) -> &str {
+        "power"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "power_lock" => self.lock(args).await,
+            "power_sleep" => self.sleep(args).await,
+            "power_shutdown" => self.shutdown(args).await,
+            "power_keep_awake" => self.keep_awake(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    /// Drop any pending shutdown and let the machine sleep again when the session ends
+    async fn shutdown(&self) -> Result<()> {
+        self.scheduled.lock().unwrap().take();
+        self.stop_keep_awake().await?;
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn command(program: &str, args: &[&str]) -> (String, Command) {
+    let mut command = Command::new(program);
+    command.args(args);
+    (format!("{} {}", program, args.join(" ")), command)
+}
+
+/// Run candidate commands in order until one succeeds, returning its description
+async fn run_first(commands: Vec<(String, Command)>) -> Result<String> {
+    let mut errors = Vec::new();
+    for (description, mut command) in commands {
+        match command.stdin(Stdio::null()).output().await {
+            Ok(output) if output.status.success() => return Ok(description),
+            Ok(output) => errors.push(format!(
+                "{}: {}",
+                description,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                errors.push(format!("{}: not installed", description))
+            }
+            Err(e) => errors.push(format!("{}: {}", description, e)),
+        }
+    }
+    anyhow::bail!("{}", errors.join("; "))
+}
+
+fn lock_commands() -> Vec<(String, Command)> {
+    if cfg!(target_os = "macos") {
+        // Locks when "require password after sleep" is set to immediately
+        vec![command("pmset", &["displaysleepnow"])]
+    } else if cfg!(windows) {
+        vec![command("rundll32.exe", &["user32.dll,LockWorkStation"])]
+    } else {
+        vec![
+            command("loginctl", &["lock-session"]),
+            command("xdg-screensaver", &["lock"]),
+        ]
+    }
+}
+
+fn sleep_commands() -> Vec<(String, Command)> {
+    if cfg!(target_os = "macos") {
+        vec![command("pmset", &["sleepnow"])]
+    } else if cfg!(windows) {
+        vec![command("rundll32.exe", &["powrprof.dll,SetSuspendState", "0,1,0"])]
+    } else {
+        vec![
+            command("systemctl", &["suspend"]),
+            command("loginctl", &["suspend"]),
+        ]
+    }
+}
+
+fn shutdown_commands(action: ShutdownAction) -> Vec<(String, Command)> {
+    let restart = action == ShutdownAction::Restart;
+    if cfg!(target_os = "macos") {
+        let script = if restart {
+            "tell application \"System Events\" to restart"
+        } else {
+            "tell application \"System Events\" to shut down"
+        };
+        vec![command("osascript", &["-e", script])]
+    } else if cfg!(windows) {
+        vec![command("shutdown", &[if restart { "/r" } else { "/s" }, "/t", "0"])]
+    } else {
+        vec![
+            command("systemctl", &[if restart { "reboot" } else { "poweroff" }]),
+            command("shutdown", &[if restart { "-r" } else { "-h" }, "now"]),
+        ]
+    }
+}
+
+/// A process that blocks sleep while it runs, and exits after `duration` seconds if given
+fn keep_awake_command(duration: Option<u64>, display: bool, reason: &str) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("caffeinate");
+        command.arg(if display { "-di" } else { "-i" });
+        if let Some(secs) = duration {
+            command.args(["-t", &secs.to_string()]);
+        }
+        command
+    } else if cfg!(windows) {
+        // ES_CONTINUOUS | ES_SYSTEM_REQUIRED, plus ES_DISPLAY_REQUIRED; held while the thread lives
+        let flags = if display { "0x80000003" } else { "0x80000001" };
+        let wait = match duration {
+            Some(secs) => format!("Start-Sleep -Seconds {}", secs),
+            None => "while ($true) { Start-Sleep -Seconds 3600 }".to_string(),
+        };
+        let script = format!(
+            "$t = Add-Type -Name Power -Namespace PolyMcp -PassThru -MemberDefinition \
+             '[DllImport(\"kernel32.dll\")] public static extern uint SetThreadExecutionState(uint f);'; \
+             [void]$t::SetThreadExecutionState({}); {}",
+            flags, wait
+        );
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        command
+    } else {
+        let what = if display { "idle:sleep" } else { "sleep" };
+        let mut command = Command::new("systemd-inhibit");
+        command.args([
+            &format!("--what={}", what),
+            "--who=poly-mcp",
+            &format!("--why={}", reason),
+            "--mode=block",
+            "sleep",
+        ]);
+        command.arg(duration.map(|secs| secs.to_string()).unwrap_or_else(|| "infinity".to_string()));
+        command
+    }
+}
@@ -0,0 +1,216 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+
+/// A reusable prompt template exposed through the MCP prompts capability.
+/// Templates use `{{name}}` placeholders; `{{#name}}...{{/name}}` blocks are
+/// only rendered when the argument is provided.
+struct PromptTemplate {
+    name: &'static str,
+    description: &'static str,
+    arguments: &'static [PromptArgument],
+    template: &'static str,
+}
+
+struct PromptArgument {
+    name: &'static str,
+    description: &'static str,
+    required: bool,
+}
+
+const PROMPTS: &[PromptTemplate] = &[
+    PromptTemplate {
+        name: "summarize_diff",
+        description: "Summarize a diff: what changed, why it likely changed, and what to double-check",
+        arguments: &[
+            PromptArgument {
+                name: "diff",
+                description: "Unified diff to summarize (e.g. output of git_diff)",
+                required: true,
+            },
+            PromptArgument {
+                name: "focus",
+                description: "Aspect to focus on, e.g. 'API changes' or 'risk'",
+                required: false,
+            },
+        ],
+        template: "Summarize the following diff for a code reviewer.\n\
+\n\
+List the files touched and describe each change in one sentence, then explain the overall intent of the change. \
+Call out anything that looks risky, incomplete, or inconsistent.\
+{{#focus}}\n\nPay particular attention to: {{focus}}{{/focus}}\n\
+\n\
+```diff\n{{diff}}\n```",
+    },
+    PromptTemplate {
+        name: "triage_diagnostics",
+        description: "Group compiler/linter diagnostics by root cause and propose a fix order",
+        arguments: &[
+            PromptArgument {
+                name: "diagnostics",
+                description: "Diagnostics to triage (e.g. output of diagnostics_get)",
+                required: true,
+            },
+            PromptArgument {
+                name: "path",
+                description: "Project or file the diagnostics belong to",
+                required: false,
+            },
+        ],
+        template: "Triage the following diagnostics{{#path}} for {{path}}{{/path}}.\n\
+\n\
+Group them by likely root cause, note which errors are probably cascading from others, \
+and propose the order in which to fix them. For each group, suggest a concrete fix.\n\
+\n\
+```\n{{diagnostics}}\n```",
+    },
+    PromptTemplate {
+        name: "commit_message",
+        description: "Write a commit message for a set of changes",
+        arguments: &[
+            PromptArgument {
+                name: "diff",
+                description: "Staged diff to describe",
+                required: true,
+            },
+            PromptArgument {
+                name: "style",
+                description: "Message style, e.g. 'conventional' (default: imperative summary line plus body)",
+                required: false,
+            },
+        ],
+        template: "Write a git commit message for the following changes.\n\
+\n\
+Use a summary line of at most 72 characters in the imperative mood, followed by a blank line and a short body \
+explaining what changed and why.\
+{{#style}}\n\nFollow this style: {{style}}{{/style}}\n\
+\n\
+```diff\n{{diff}}\n```",
+    },
+    PromptTemplate {
+        name: "review_code",
+        description: "Review a piece of code for bugs, edge cases, and readability",
+        arguments: &[
+            PromptArgument {
+                name: "code",
+                description: "Code to review",
+                required: true,
+            },
+            PromptArgument {
+                name: "language",
+                description: "Programming language of the code",
+                required: false,
+            },
+        ],
+        template: "Review the following {{#language}}{{language}} {{/language}}code.\n\
+\n\
+Look for bugs, unhandled edge cases, and error-handling gaps first, then readability issues. \
+Reference specific lines and suggest concrete changes.\n\
+\n\
+```{{language}}\n{{code}}\n```",
+    },
+    PromptTemplate {
+        name: "explain_error",
+        description: "Explain an error message and suggest how to fix it",
+        arguments: &[
+            PromptArgument {
+                name: "error",
+                description: "Error message or stack trace",
+                required: true,
+            },
+            PromptArgument {
+                name: "context",
+                description: "What was being done when the error occurred",
+                required: false,
+            },
+        ],
+        template: "Explain the following error in plain terms and suggest the most likely fixes, most probable first.\
+{{#context}}\n\nContext: {{context}}{{/context}}\n\
+\n\
+```\n{{error}}\n```",
+    },
+];
+
+pub struct PromptsModule;
+
+impl Default for PromptsModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PromptsModule {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn get_prompts(&self) -> Vec<Value> {
+        PROMPTS
+            .iter()
+            .map(|p| {
+                json!({
+                    "name": p.name,
+                    "description": p.description,
+                    "arguments": p.arguments.iter().map(|a| json!({
+                        "name": a.name,
+                        "description": a.description,
+                        "required": a.required
+                    })).collect::<Vec<_>>()
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_prompt(&self, name: &str, arguments: Option<Value>) -> Result<Value> {
+        let prompt = PROMPTS
+            .iter()
+            .find(|p| p.name == name)
+            .with_context(|| format!("Unknown prompt: {}", name))?;
+
+        let args = arguments.unwrap_or(json!({}));
+
+        for arg in prompt.arguments.iter().filter(|a| a.required) {
+            if args[arg.name].as_str().is_none_or(|v| v.is_empty()) {
+                anyhow::bail!("Missing required argument '{}' for prompt '{}'", arg.name, name);
+            }
+        }
+
+        let text = render_template(prompt.template, prompt.arguments, &args);
+
+        Ok(json!({
+            "description": prompt.description,
+            "messages": [
+                {
+                    "role": "user",
+                    "content": {
+                        "type": "text",
+                        "text": text
+                    }
+                }
+            ]
+        }))
+    }
+}
+
+fn render_template(template: &str, arguments: &[PromptArgument], args: &Value) -> String {
+    let mut text = template.to_string();
+
+    for arg in arguments {
+        let value = args[arg.name].as_str().unwrap_or("");
+        let open = format!("{{{{#{}}}}}", arg.name);
+        let close = format!("{{{{/{}}}}}", arg.name);
+
+        // Conditional blocks: keep the inner text when the argument is set, drop the block otherwise
+        while let Some(start) = text.find(&open) {
+            let Some(end) = text[start..].find(&close).map(|e| start + e) else {
+                break;
+            };
+            let inner = text[start + open.len()..end].to_string();
+            let replacement = if value.is_empty() { String::new() } else { inner };
+            text.replace_range(start..end + close.len(), &replacement);
+        }
+
+        text = text.replace(&format!("{{{{{}}}}}", arg.name), value);
+    }
+
+    text
+}
@@ -0,0 +1,516 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
+use regex::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, Ast};
+
+const DEFAULT_LIMIT: u64 = 100;
+
+/// Characters accepted in the `flags` argument
+const FLAGS: &str = "imsxU";
+
+pub struct RegexModule;
+
+impl Default for RegexModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegexModule {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        let flags_schema = json!({
+            "type": "string",
+            "description": "Flags: 'i' case-insensitive, 'm' multi-line (^/$ match at line breaks), 's' dot matches newline, 'x' ignore whitespace and allow # comments, 'U' swap greedy and lazy"
+        });
+
+        vec![
+            json!({
+                "name": "regex_test",
+                "description": "Test a regular expression (Rust regex syntax) against text. Returns every match with its byte span, line/column, and capture groups by index and name, so a pattern can be refined without writing a script.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression"
+                        },
+                        "text": {
+                            "type": "string",
+                            "description": "Text to search"
+                        },
+                        "flags": flags_schema,
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum matches to return (default: 100); match_count still counts all of them"
+                        }
+                    },
+                    "required": ["pattern", "text"]
+                }
+            }),
+            json!({
+                "name": "regex_replace",
+                "description": "Replace regex matches in text. The replacement can reference captures as $1, ${1}, $name or ${name} ($$ for a literal $). Returns the new text and each individual substitution.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression"
+                        },
+                        "text": {
+                            "type": "string",
+                            "description": "Text to operate on"
+                        },
+                        "replacement": {
+                            "type": "string",
+                            "description": "Replacement text with optional capture references"
+                        },
+                        "flags": flags_schema,
+                        "limit": {
+                            "type": "integer",
+                            "description": "Replace at most this many matches (default: 0 = all)"
+                        },
+                        "literal": {
+                            "type": "boolean",
+                            "description": "Insert the replacement as-is, without expanding $ references (default: false)"
+                        }
+                    },
+                    "required": ["pattern", "text", "replacement"]
+                }
+            }),
+            json!({
+                "name": "regex_validate",
+                "description": "Check a regular expression for syntax errors and explain it. Invalid patterns report the error with its position and a hint (e.g. look-around and backreferences are not supported); valid patterns get a piece-by-piece explanation and their capture groups.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "pattern": {
+                            "type": "string",
+                            "description": "Regular expression"
+                        },
+                        "flags": flags_schema
+                    },
+                    "required": ["pattern"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn test(&self, args: Value) -> Result<Value> {
+        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
+        let text = args["text"].as_str().context("Missing 'text' parameter")?;
+        let flags = args["flags"].as_str().unwrap_or("");
+        let limit = args["limit"].as_u64().unwrap_or(DEFAULT_LIMIT) as usize;
+
+        let re = compile(pattern, flags)?;
+        let mut positions = LineIndex::new(text);
+
+        let mut match_count = 0;
+        let mut matches = Vec::new();
+        for caps in re.captures_iter(text) {
+            match_count += 1;
+            if matches.len() >= limit {
+                continue;
+            }
+
+            let whole = caps.get(0).expect("group 0 always participates in a match");
+            let (line, column) = positions.locate(whole.start());
+
+            let groups: Vec<Value> = re
+                .capture_names()
+                .enumerate()
+                .skip(1)
+                .map(|(index, name)| match caps.get(index) {
+                    Some(m) => json!({
+                        "index": index,
+                        "name": name,
+                        "text": m.as_str(),
+                        "start": m.start(),
+                        "end": m.end()
+                    }),
+                    None => json!({
+                        "index": index,
+                        "name": name,
+                        "text": null
+                    }),
+                })
+                .collect();
+
+            matches.push(json!({
+                "text": whole.as_str(),
+                "start": whole.start(),
+                "end": whole.end(),
+                "line": line,
+                "column": column,
+                "groups": groups
+            }));
+        }
+
+        Ok(json!({
+            "pattern": pattern,
+            "is_match": match_count > 0,
+            "match_count": match_count,
+            "truncated": match_count > matches.len(),
+            "capture_groups": group_names(&re),
+            "matches": matches
+        }))
+    }
+
+    pub async fn replace(&self, args: Value) -> Result<Value> {
+        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
+        let text = args["text"].as_str().context("Missing 'text' parameter")?;
+        let replacement = args["replacement"].as_str().context("Missing 'replacement' parameter")?;
+        let flags = args["flags"].as_str().unwrap_or("");
+        let limit = args["limit"].as_u64().unwrap_or(0) as usize;
+        let literal = args["literal"].as_bool().unwrap_or(false);
+
+        let re = compile(pattern, flags)?;
+
+        let mut result = String::with_capacity(text.len());
+        let mut changes = Vec::new();
+        let mut last_end = 0;
+        for caps in re.captures_iter(text) {
+            if limit > 0 && changes.len() >= limit {
+                break;
+            }
+
+            let whole = caps.get(0).expect("group 0 always participates in a match");
+            let mut substituted = String::new();
+            if literal {
+                substituted.push_str(replacement);
+            } else {
+                caps.expand(replacement, &mut substituted);
+            }
+
+            result.push_str(&text[last_end..whole.start()]);
+            result.push_str(&substituted);
+            last_end = whole.end();
+
+            changes.push(json!({
+                "start": whole.start(),
+                "end": whole.end(),
+                "matched": whole.as_str(),
+                "replacement": substituted
+            }));
+        }
+        result.push_str(&text[last_end..]);
+
+        // $name references that are not capture groups silently expand to nothing
+        let unknown_refs: Vec<String> = if literal {
+            Vec::new()
+        } else {
+            capture_refs(replacement)
+                .into_iter()
+                .filter(|r| match r.parse::<usize>() {
+                    Ok(index) => index >= re.captures_len(),
+                    Err(_) => !re.capture_names().flatten().any(|name| name == r),
+                })
+                .collect()
+        };
+
+        let mut response = json!({
+            "pattern": pattern,
+            "result": result,
+            "replacements": changes.len(),
+            "changed": result != text,
+            "changes": changes
+        });
+        if !unknown_refs.is_empty() {
+            response["warning"] = json!(format!(
+                "Replacement references unknown capture group(s) {}; they expand to empty text. Use ${{name}} when a reference is followed by letters, digits or _",
+                unknown_refs.iter().map(|r| format!("${}", r)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        Ok(response)
+    }
+
+    pub async fn validate(&self, args: Value) -> Result<Value> {
+        let pattern = args["pattern"].as_str().context("Missing 'pattern' parameter")?;
+        let flags = args["flags"].as_str().unwrap_or("");
+        check_flags(flags)?;
+
+        let mut parser = regex_syntax::ParserBuilder::new()
+            .case_insensitive(flags.contains('i'))
+            .multi_line(flags.contains('m'))
+            .dot_matches_new_line(flags.contains('s'))
+            .ignore_whitespace(flags.contains('x'))
+            .swap_greed(flags.contains('U'))
+            .build();
+
+        if let Err(e) = parser.parse(pattern) {
+            let (message, span, hint) = match &e {
+                regex_syntax::Error::Parse(e) => (e.kind().to_string(), *e.span(), hint_for(e.kind())),
+                regex_syntax::Error::Translate(e) => (e.kind().to_string(), *e.span(), None),
+                _ => (e.to_string(), ast::Span::splat(ast::Position::new(0, 1, 1)), None),
+            };
+
+            return Ok(json!({
+                "pattern": pattern,
+                "valid": false,
+                "error": message,
+                "start": span.start.offset,
+                "end": span.end.offset,
+                "line": span.start.line,
+                "column": span.start.column,
+                "hint": hint,
+                "detail": e.to_string()
+            }));
+        }
+
+        // The syntax is fine, but compilation can still fail, e.g. on size limits
+        let re = compile(pattern, flags)?;
+
+        let ast = ast::parse::ParserBuilder::new()
+            .ignore_whitespace(flags.contains('x'))
+            .build()
+            .parse(pattern)
+            .context("Failed to parse pattern")?;
+        let mut explanation = Vec::new();
+        explain(&ast, pattern, 0, &mut explanation);
+
+        Ok(json!({
+            "pattern": pattern,
+            "valid": true,
+            "capture_groups": group_names(&re),
+            "explanation": explanation
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for RegexModule {
+    fn name(&self) -> &str {
+        "regex"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "regex_test" => self.test(args).await,
+            "regex_replace" => self.replace(args).await,
+            "regex_validate" => self.validate(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn check_flags(flags: &str) -> Result<()> {
+    match flags.chars().find(|c| !FLAGS.contains(*c)) {
+        Some(c) => Err(anyhow::anyhow!("Unknown flag '{}'. Supported flags: {}", c, FLAGS)),
+        None => Ok(()),
+    }
+}
+
+fn compile(pattern: &str, flags: &str) -> Result<Regex> {
+    check_flags(flags)?;
+    RegexBuilder::new(pattern)
+        .case_insensitive(flags.contains('i'))
+        .multi_line(flags.contains('m'))
+        .dot_matches_new_line(flags.contains('s'))
+        .ignore_whitespace(flags.contains('x'))
+        .swap_greed(flags.contains('U'))
+        .build()
+        .with_context(|| format!("Invalid regex pattern (use regex_validate for details): {}", pattern))
+}
+
+fn group_names(re: &Regex) -> Vec<Value> {
+    re.capture_names()
+        .enumerate()
+        .skip(1)
+        .map(|(index, name)| json!({ "index": index, "name": name }))
+        .collect()
+}
+
+/// Names and numbers referenced as $x or ${x} in a replacement string
+fn capture_refs(replacement: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    let mut rest = replacement;
+    while let Some(pos) = rest.find('$') {
+        rest = &rest[pos + 1..];
+        if let Some(stripped) = rest.strip_prefix('$') {
+            rest = stripped;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            if let Some(end) = braced.find('}') {
+                refs.push(braced[..end].to_string());
+                rest = &braced[end + 1..];
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if end > 0 {
+                refs.push(rest[..end].to_string());
+            }
+            rest = &rest[end..];
+        }
+    }
+    refs
+}
+
+fn hint_for(kind: &ast::ErrorKind) -> Option<&'static str> {
+    match kind {
+        ast::ErrorKind::UnsupportedLookAround => Some(
+            "Look-ahead and look-behind are not supported. Match the surrounding text with a capture group and use only the group, or split the check into two patterns",
+        ),
+        ast::ErrorKind::UnsupportedBackreference => Some(
+            "Backreferences like \\1 are not supported. Capture the candidate text and compare the groups afterwards",
+        ),
+        ast::ErrorKind::GroupUnclosed | ast::ErrorKind::GroupUnopened => {
+            Some("Parentheses are unbalanced; escape literal parentheses as \\( and \\)")
+        }
+        ast::ErrorKind::ClassUnclosed => Some("Unclosed character class; escape a literal [ as \\["),
+        ast::ErrorKind::RepetitionMissing => {
+            Some("A repetition operator has nothing to repeat; escape literal *, + and ? with a backslash")
+        }
+        ast::ErrorKind::EscapeUnrecognized => {
+            Some("Unknown escape sequence; when the pattern is embedded in JSON, backslashes must be doubled")
+        }
+        _ => None,
+    }
+}
+
+/// Flatten the AST into one entry per meaningful piece, indented by nesting depth
+fn explain(node: &Ast, pattern: &str, depth: usize, out: &mut Vec<Value>) {
+    let mut push = |span: &ast::Span, description: String| {
+        out.push(json!({
+            "depth": depth,
+            "text": &pattern[span.start.offset..span.end.offset],
+            "description": description
+        }));
+    };
+
+    match node {
+        Ast::Empty(_) => {}
+        Ast::Flags(flags) => push(&flags.span, "set flags for the rest of the group".to_string()),
+        Ast::Literal(literal) => push(&literal.span, format!("literal {:?}", literal.c)),
+        Ast::Dot(span) => push(span, "any character (except newline unless the s flag is set)".to_string()),
+        Ast::Assertion(assertion) => {
+            let description = match assertion.kind {
+                ast::AssertionKind::StartLine => "start of text (or line with the m flag)",
+                ast::AssertionKind::EndLine => "end of text (or line with the m flag)",
+                ast::AssertionKind::StartText => "start of text",
+                ast::AssertionKind::EndText => "end of text",
+                ast::AssertionKind::WordBoundary => "word boundary",
+                ast::AssertionKind::NotWordBoundary => "not a word boundary",
+                _ => "word boundary (start/end variant)",
+            };
+            push(&assertion.span, description.to_string());
+        }
+        Ast::ClassUnicode(class) => push(
+            &class.span,
+            format!("{} Unicode class", if class.negated { "not in" } else { "character in" }),
+        ),
+        Ast::ClassPerl(class) => {
+            let name = match class.kind {
+                ast::ClassPerlKind::Digit => "digit",
+                ast::ClassPerlKind::Space => "whitespace character",
+                ast::ClassPerlKind::Word => "word character (letter, digit or _)",
+            };
+            let prefix = if class.negated { "any character that is not a " } else { "" };
+            push(&class.span, format!("{}{}", prefix, name));
+        }
+        Ast::ClassBracketed(class) => push(
+            &class.span,
+            if class.negated {
+                "any character not in the set".to_string()
+            } else {
+                "one character from the set".to_string()
+            },
+        ),
+        Ast::Repetition(rep) => {
+            let count = match &rep.op.kind {
+                ast::RepetitionKind::ZeroOrOne => "optional (0 or 1 times)".to_string(),
+                ast::RepetitionKind::ZeroOrMore => "0 or more times".to_string(),
+                ast::RepetitionKind::OneOrMore => "1 or more times".to_string(),
+                ast::RepetitionKind::Range(ast::RepetitionRange::Exactly(n)) => format!("exactly {} times", n),
+                ast::RepetitionKind::Range(ast::RepetitionRange::AtLeast(n)) => format!("at least {} times", n),
+                ast::RepetitionKind::Range(ast::RepetitionRange::Bounded(m, n)) => {
+                    format!("between {} and {} times", m, n)
+                }
+            };
+            let greed = if rep.greedy { "as many as possible" } else { "as few as possible" };
+            push(&rep.span, format!("repeat {}, {}:", count, greed));
+            explain(&rep.ast, pattern, depth + 1, out);
+        }
+        Ast::Group(group) => {
+            let description = match &group.kind {
+                ast::GroupKind::CaptureIndex(index) => format!("capture group {}:", index),
+                ast::GroupKind::CaptureName { name, .. } => {
+                    format!("capture group {} named '{}':", name.index, name.name)
+                }
+                ast::GroupKind::NonCapturing(_) => "non-capturing group:".to_string(),
+            };
+            push(&group.span, description);
+            explain(&group.ast, pattern, depth + 1, out);
+        }
+        Ast::Alternation(alternation) => {
+            push(&alternation.span, format!("one of {} alternatives:", alternation.asts.len()));
+            for branch in &alternation.asts {
+                explain(branch, pattern, depth + 1, out);
+            }
+        }
+        Ast::Concat(concat) => {
+            // Runs of plain literals read better as a single string
+            let mut run: Vec<&ast::Literal> = Vec::new();
+            for item in &concat.asts {
+                if let Ast::Literal(literal) = item {
+                    run.push(literal);
+                    continue;
+                }
+                flush_literals(&run, pattern, depth, out);
+                run.clear();
+                explain(item, pattern, depth, out);
+            }
+            flush_literals(&run, pattern, depth, out);
+        }
+    }
+}
+
+fn flush_literals(run: &[&ast::Literal], pattern: &str, depth: usize, out: &mut Vec<Value>) {
+    let (Some(first), Some(last)) = (run.first(), run.last()) else {
+        return;
+    };
+    let text: String = run.iter().map(|literal| literal.c).collect();
+    out.push(json!({
+        "depth": depth,
+        "text": &pattern[first.span.start.offset..last.span.end.offset],
+        "description": format!("literal {:?}", text)
+    }));
+}
+
+/// Converts byte offsets into 1-based line/column for offsets visited in increasing order
+struct LineIndex<'a> {
+    text: &'a str,
+    offset: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, offset: 0, line: 1, line_start: 0 }
+    }
+
+    fn locate(&mut self, offset: usize) -> (usize, usize) {
+        for (i, c) in self.text[self.offset..offset].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.offset + i + 1;
+            }
+        }
+        self.offset = offset;
+        let column = self.text[self.line_start..offset].chars().count() + 1;
+        (self.line, column)
+    }
+}
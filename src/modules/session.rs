@@ -0,0 +1,456 @@
+use serde_json::{json, Map, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::audit;
+use crate::children;
+use crate::config::{AuditConfig, Config};
+use crate::error::PolyError;
+use crate::hints;
+use crate::paths::{path_args, resolve_path};
+use crate::profiles;
+use crate::registry::ToolModule;
+use crate::storage::Storage;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// The tool that describes the others; the server gives its calls the tool list
+pub const TOOL_HELP: &str = "tool_help";
+
+/// Most related tools tool_help lists
+const MAX_RELATED: usize = 10;
+
+tokio::task_local! {
+    static CATALOG: Option<Arc<Vec<ToolEntry>>>;
+}
+
+/// A tool as the client sees it in tools/list, with the module and built-in name behind it
+pub struct ToolEntry {
+    pub module: String,
+    pub name: String,
+    pub tool: Value,
+}
+
+/// Run a tool call that can describe the tools in `catalog`
+pub async fn catalog_scope<F: Future>(catalog: Option<Arc<Vec<ToolEntry>>>, call: F) -> F::Output {
+    CATALOG.scope(catalog, call).await
+}
+
+/// Working directory of one server instance (a stdio connection or an HTTP session).
+/// Once set, relative path arguments of every built-in tool resolve against it, and
+/// tools that default to "the current directory" use it, instead of the directory
+/// the server process happened to be started in.
+#[derive(Default)]
+pub struct SessionCwd {
+    // None until set by session_cwd or [server] cwd; the process's directory is used until then
+    dir: RwLock<Option<PathBuf>>,
+}
+
+impl SessionCwd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start in `dir` instead of the process's directory
+    pub fn with_dir(dir: Option<&Path>) -> Result<Self> {
+        let cwd = Self::new();
+        if let Some(dir) = dir {
+            cwd.set(dir).context("Invalid working directory in [server] cwd / --cwd")?;
+        }
+        Ok(cwd)
+    }
+
+    /// The session's working directory
+    pub fn get(&self) -> PathBuf {
+        match self.explicit() {
+            Some(dir) => dir,
+            None => std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    fn explicit(&self) -> Option<PathBuf> {
+        self.dir.read().unwrap().clone()
+    }
+
+    /// Change directory; a relative `dir` is taken from the current one. Returns the new directory.
+    pub fn set(&self, dir: &Path) -> Result<PathBuf> {
+        let dir = resolve_path(&self.get().join(dir));
+        if !dir.is_dir() {
+            anyhow::bail!("Not a directory: {}", dir.display());
+        }
+        *self.dir.write().unwrap() = Some(dir.clone());
+        Ok(dir)
+    }
+
+    /// Make the path arguments of a call absolute, and fill in the ones that
+    /// default to the current directory. Does nothing until a directory is set.
+    pub fn resolve_args(&self, tool: &str, module: &str, args: &mut Value) {
+        let Some(cwd) = self.explicit() else {
+            return;
+        };
+        let Some(map) = args.as_object_mut() else {
+            return;
+        };
+
+        let (keys, default) = path_args(tool, module, map);
+        let resolve = |path: &mut String| {
+            if !path.is_empty() && Path::new(path.as_str()).is_relative() {
+                *path = cwd.join(path.as_str()).display().to_string();
+            }
+        };
+        for key in keys {
+            match map.get_mut(*key) {
+                Some(Value::String(path)) => resolve(path),
+                // Lists of paths, such as fs_read_many's
+                Some(Value::Array(paths)) => paths.iter_mut().for_each(|path| {
+                    if let Value::String(path) = path {
+                        resolve(path);
+                    }
+                }),
+                _ => {}
+            }
+        }
+        if let Some(key) = default {
+            if map.get(key).is_none_or(Value::is_null) {
+                map.insert(key.to_string(), json!(cwd.display().to_string()));
+            }
+        }
+    }
+}
+
+pub struct SessionModule {
+    cwd: Arc<SessionCwd>,
+    // What session_profile reports, from the config this session was built with
+    profile: RwLock<Value>,
+    // The log audit_verify checks; [audit] only changes on restart
+    audit: AuditConfig,
+    // For logs kept with [audit] format = "storage"
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl Default for SessionModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionModule {
+    pub fn new() -> Self {
+        Self::with_cwd(Arc::new(SessionCwd::new()))
+    }
+
+    /// Share `cwd` with the server, which resolves every call's path arguments against it
+    pub fn with_cwd(cwd: Arc<SessionCwd>) -> Self {
+        Self::with_config(cwd, &Config::default())
+    }
+
+    /// Also report the profile and policy of `config` from session_profile
+    pub fn with_config(cwd: Arc<SessionCwd>, config: &Config) -> Self {
+        Self {
+            cwd,
+            profile: RwLock::new(profiles::describe(config)),
+            audit: config.audit.clone(),
+            storage: None,
+        }
+    }
+
+    /// Read audit logs kept in `storage` with `[audit] format = "storage"`
+    pub fn set_storage(&mut self, storage: Arc<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "session_cwd",
+                "description": "Get or set this session's working directory. Relative paths given to any tool, and tools that default to the current directory (git, diagnostics, settings, scripts), use it instead of the directory the server was started in",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Directory to change to, absolute or relative to the current one (omit to just get the current directory)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "session_profile",
+                "description": "Get the server's execution profile (safe, standard, yolo or custom) and what it allows: sandbox roots, URL allowlist, denied tools, tools that need the user's approval, and the call rate limits. Check it before planning work that may be refused",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": "server_children",
+                "description": "List the external processes tool calls are running (builds, linters, package managers, git, scripts) with the tool that started each and how long it has run, or kill one together with the processes it started",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "action": {
+                            "type": "string",
+                            "enum": ["list", "kill"],
+                            "description": "list (default) or kill"
+                        },
+                        "id": {
+                            "type": "integer",
+                            "description": "For kill: the child's id from list"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "audit_verify",
+                "description": "Check that the audit log has not been tampered with: every entry must match its hash and follow the entry before it, and with a signing key configured its signature must be valid. Reports the first problems found and the hash of the last entry, which should be compared with a copy kept elsewhere to detect entries cut from the end",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }),
+            json!({
+                "name": TOOL_HELP,
+                "description": "Get help on one tool before calling it: its full input schema, each parameter with its type and whether it is required, example arguments, the errors calls to it commonly fail with and how to avoid them, and related tools. Use it when unsure about a tool's parameters instead of guessing",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "The tool's name as tools/list gives it"
+                        }
+                    },
+                    "required": ["name"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn cwd(&self, args: Value) -> Result<Value> {
+        let previous = self.cwd.get();
+        let Some(path) = args["path"].as_str() else {
+            return Ok(json!({
+                "cwd": previous.display().to_string(),
+                "explicit": self.cwd.explicit().is_some(),
+            }));
+        };
+
+        let cwd = self.cwd.set(Path::new(path))?;
+        Ok(json!({
+            "cwd": cwd.display().to_string(),
+            "previous": previous.display().to_string(),
+        }))
+    }
+
+    pub async fn profile(&self, _args: Value) -> Result<Value> {
+        Ok(self.profile.read().unwrap().clone())
+    }
+
+    pub async fn audit_verify(&self, _args: Value) -> Result<Value> {
+        let config = self.audit.clone();
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || audit::verify(&config, storage.as_ref())).await?
+    }
+
+    pub async fn tool_help(&self, args: Value) -> Result<Value> {
+        let name = args["name"].as_str().context("Missing 'name' parameter")?;
+        let catalog = CATALOG
+            .try_with(|catalog| catalog.clone())
+            .ok()
+            .flatten()
+            .context("tool_help is only available through the server")?;
+        describe_tool(name, &catalog)
+    }
+
+    pub async fn children(&self, args: Value) -> Result<Value> {
+        match args["action"].as_str().unwrap_or("list") {
+            "list" => {
+                let running = children::list();
+                Ok(json!({
+                    "count": running.len(),
+                    "children": running,
+                    "limits": children::limits(),
+                }))
+            }
+            "kill" => {
+                let id = args["id"].as_u64().context("Missing 'id' parameter")?;
+                let child = children::kill(id).with_context(|| format!("Child process not found: {}", id))?;
+                Ok(json!({ "killed": true, "child": child }))
+            }
+            other => Err(anyhow::anyhow!("Unknown action: {}", other)),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolModule for SessionModule {
+    fn name(&self) -> &str {
+        "session"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "session_cwd" => self.cwd(args).await,
+            "session_profile" => self.profile(args).await,
+            "server_children" => self.children(args).await,
+            "audit_verify" => self.audit_verify(args).await,
+            TOOL_HELP => self.tool_help(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn reload(&self, config: &Config) -> Result<()> {
+        *self.profile.write().unwrap() = profiles::describe(config);
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// Everything an agent needs to call tool `name` of `catalog` right the first time:
+/// what tool_help and the tools/describe request return
+pub fn describe_tool(name: &str, catalog: &[ToolEntry]) -> Result<Value> {
+    // Hints name tools by their built-in names
+    let Some(entry) = catalog.iter().find(|entry| entry.tool["name"] == name || entry.name == name) else {
+        let message = match hints::closest(name, catalog.iter().filter_map(|entry| entry.tool["name"].as_str())).as_slice() {
+            [] => format!("Unknown tool: {}", name),
+            [only] => format!("Unknown tool: {}. Did you mean '{}'?", name, only),
+            several => format!("Unknown tool: {}. Did you mean one of '{}'?", name, several.join("', '")),
+        };
+        return Err(PolyError::UnknownTool(message).into());
+    };
+
+    let schema = &entry.tool["inputSchema"];
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let empty = Map::new();
+    let properties = schema["properties"].as_object().unwrap_or(&empty);
+    // Required ones first
+    let mut names: Vec<&String> = properties.keys().collect();
+    names.sort_by_key(|param| !required.contains(&param.as_str()));
+
+    let parameters: Vec<Value> = names
+        .iter()
+        .map(|param| {
+            let property = &properties[*param];
+            let mut parameter = json!({
+                "name": param,
+                "type": property.get("type").cloned().unwrap_or(json!("any")),
+                "required": required.contains(&param.as_str()),
+            });
+            for key in ["description", "enum", "default", "minimum", "maximum"] {
+                if let Some(value) = property.get(key) {
+                    parameter[key] = value.clone();
+                }
+            }
+            parameter
+        })
+        .collect();
+
+    let example: Map<String, Value> = required
+        .iter()
+        .map(|param| (param.to_string(), placeholder(param, properties.get(*param).unwrap_or(&Value::Null))))
+        .collect();
+
+    let mut common_errors = Vec::new();
+    if let Some(first) = required.first() {
+        common_errors.push(json!({
+            "error": format!("Missing '{}' parameter", first),
+            "fix": format!("Always pass the required parameters: {}", required.join(", ")),
+        }));
+    }
+    for param in &names {
+        if let Some(values) = properties[*param]["enum"].as_array() {
+            let values: Vec<String> = values.iter().map(|value| value.as_str().map_or(value.to_string(), str::to_string)).collect();
+            common_errors.push(json!({
+                "error": format!("Invalid or unknown {}", param),
+                "fix": format!("'{}' must be one of: {}", param, values.join(", ")),
+            }));
+        }
+    }
+    let (paths, _) = path_args(&entry.name, &entry.module, &empty);
+    let paths: Vec<&str> = paths.iter().copied().filter(|param| properties.contains_key(*param)).collect();
+    if !paths.is_empty() {
+        // The filesystem module has its own roots; [policy] allowed_paths covers every module
+        let error = if entry.module == "filesystem" {
+            "Access denied: ... is outside the allowed roots"
+        } else {
+            "Policy denied: ... is outside the allowed paths"
+        };
+        common_errors.push(json!({
+            "error": error,
+            "fix": format!(
+                "'{}' must be inside the sandbox roots session_profile reports; relative paths resolve against session_cwd",
+                paths.join("', '")
+            ),
+        }));
+    }
+
+    let name = entry.tool["name"].as_str().unwrap_or(name);
+    Ok(json!({
+        "name": name,
+        "module": entry.module,
+        "description": entry.tool["description"],
+        "inputSchema": schema,
+        "parameters": parameters,
+        "example": { "name": name, "arguments": example },
+        "common_errors": common_errors,
+        "related": related_tools(name, catalog),
+    }))
+}
+
+/// A value of the right type for parameter `param` in an example call
+fn placeholder(param: &str, property: &Value) -> Value {
+    if let Some(first) = property["enum"].as_array().and_then(|values| values.first()) {
+        return first.clone();
+    }
+    if let Some(default) = property.get("default") {
+        return default.clone();
+    }
+    match property["type"].as_str() {
+        Some("integer") | Some("number") => json!(1),
+        Some("boolean") => json!(true),
+        Some("array") => json!([]),
+        Some("object") => json!({}),
+        _ => json!(format!("<{}>", param)),
+    }
+}
+
+/// Tools worth knowing about next to `name`: those its description mentions, those
+/// whose descriptions mention it, then others with the same prefix (fs_, git_, ...)
+fn related_tools(name: &str, catalog: &[ToolEntry]) -> Vec<String> {
+    let mentions = |tool: &Value, other: &str| {
+        tool["description"]
+            .as_str()
+            .unwrap_or_default()
+            .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .any(|word| word.trim_end_matches('.') == other)
+    };
+    let Some(tool) = catalog.iter().find(|entry| entry.tool["name"] == name).map(|entry| &entry.tool) else {
+        return Vec::new();
+    };
+    let prefix = name.rsplit_once('_').map(|(prefix, _)| prefix).filter(|prefix| !prefix.is_empty());
+
+    let mut related: Vec<String> = Vec::new();
+    let others = || catalog.iter().filter_map(|entry| entry.tool["name"].as_str()).filter(|other| *other != name);
+    let mentioned = others().filter(|other| mentions(tool, other));
+    let mentioning = catalog
+        .iter()
+        .filter(|entry| entry.tool["name"] != name && mentions(&entry.tool, name))
+        .filter_map(|entry| entry.tool["name"].as_str());
+    let siblings = others().filter(|other| prefix.is_some_and(|prefix| other.starts_with(&format!("{}_", prefix))));
+    for other in mentioned.chain(mentioning).chain(siblings) {
+        if related.len() >= MAX_RELATED {
+            break;
+        }
+        if !related.iter().any(|known| known == other) {
+            related.push(other.to_string());
+        }
+    }
+    related
+}
@@ -0,0 +1,525 @@
+use serde_json::{json, Map, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use regex::Regex;
+use tokio::sync::broadcast;
+
+/// Settings file location relative to the project root
+const SETTINGS_DIR: &str = ".poly-mcp";
+const SETTINGS_FILE: &str = "settings.json";
+
+static KEY_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-z0-9_]+(\.[a-z0-9_]+)*$").unwrap());
+
+/// Keys with a known meaning. Values set for these keys are type-checked;
+/// any other well-formed key is accepted as-is.
+struct KnownSetting {
+    key: &'static str,
+    kind: SettingKind,
+    description: &'static str,
+}
+
+enum SettingKind {
+    String,
+    Integer,
+    Boolean,
+    StringArray,
+    OneOf(&'static [&'static str]),
+}
+
+impl SettingKind {
+    fn describe(&self) -> String {
+        match self {
+            SettingKind::String => "string".to_string(),
+            SettingKind::Integer => "integer".to_string(),
+            SettingKind::Boolean => "boolean".to_string(),
+            SettingKind::StringArray => "array of strings".to_string(),
+            SettingKind::OneOf(options) => format!("one of: {}", options.join(", ")),
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            SettingKind::String => value.is_string(),
+            SettingKind::Integer => value.is_i64() || value.is_u64(),
+            SettingKind::Boolean => value.is_boolean(),
+            SettingKind::StringArray => value.as_array().is_some_and(|a| a.iter().all(|v| v.is_string())),
+            SettingKind::OneOf(options) => value.as_str().is_some_and(|s| options.contains(&s)),
+        }
+    }
+}
+
+const KNOWN_SETTINGS: &[KnownSetting] = &[
+    KnownSetting { key: "project.name", kind: SettingKind::String, description: "Human-readable project name" },
+    KnownSetting { key: "project.language", kind: SettingKind::String, description: "Primary language of the project" },
+    KnownSetting { key: "build.command", kind: SettingKind::String, description: "Command that builds the project" },
+    KnownSetting { key: "test.command", kind: SettingKind::String, description: "Command that runs the test suite" },
+    KnownSetting { key: "lint.command", kind: SettingKind::String, description: "Command that runs linters" },
+    KnownSetting { key: "format.command", kind: SettingKind::String, description: "Command that formats the code" },
+    KnownSetting { key: "git.default_branch", kind: SettingKind::String, description: "Branch that changes are merged into" },
+    KnownSetting {
+        key: "git.commit_style",
+        kind: SettingKind::OneOf(&["conventional", "freeform"]),
+        description: "Commit message convention",
+    },
+    KnownSetting { key: "agent.read_only", kind: SettingKind::Boolean, description: "Agents should not modify files" },
+    KnownSetting { key: "agent.max_parallel_tasks", kind: SettingKind::Integer, description: "Upper bound on concurrent agent tasks" },
+    KnownSetting { key: "paths.ignore", kind: SettingKind::StringArray, description: "Paths agents should leave alone" },
+];
+
+pub struct SettingsModule {
+    notifier: Option<broadcast::Sender<Value>>,
+}
+
+impl Default for SettingsModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SettingsModule {
+    pub fn new() -> Self {
+        Self { notifier: None }
+    }
+
+    /// Push settings changes seen by settings_watch as JSON-RPC notifications
+    pub fn set_notifier(&mut self, notifier: broadcast::Sender<Value>) {
+        self.notifier = Some(notifier);
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "settings_get",
+                "description": "Read project settings shared between agents (stored in .poly-mcp/settings.json)",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "key": {
+                            "type": "string",
+                            "description": "Setting key, e.g. 'test.command'"
+                        },
+                        "keys": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Several keys to read at once"
+                        },
+                        "project": {
+                            "type": "string",
+                            "description": "Project root directory (default: current directory)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "settings_set",
+                "description": "Set or remove project settings. Known keys are type-checked; pass expected_version to avoid overwriting a concurrent change",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "key": {
+                            "type": "string",
+                            "description": "Setting key (dot-separated lowercase segments, e.g. 'build.command')"
+                        },
+                        "value": {
+                            "description": "New value; null removes the key"
+                        },
+                        "values": {
+                            "type": "object",
+                            "description": "Several key/value pairs to set in one write (null removes a key)"
+                        },
+                        "expected_version": {
+                            "type": "integer",
+                            "description": "Fail unless the settings file is still at this version"
+                        },
+                        "project": {
+                            "type": "string",
+                            "description": "Project root directory (default: current directory)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "settings_list",
+                "description": "List all project settings with the current version, plus the known keys and their expected types",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "prefix": {
+                            "type": "string",
+                            "description": "Only list keys starting with this prefix, e.g. 'git.'"
+                        },
+                        "project": {
+                            "type": "string",
+                            "description": "Project root directory (default: current directory)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "settings_watch",
+                "description": "Wait for project settings to change and report which keys changed",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "keys": {
+                            "type": "array",
+                            "items": {
+                                "type": "string"
+                            },
+                            "description": "Only report changes to these keys (default: all)"
+                        },
+                        "since_version": {
+                            "type": "integer",
+                            "description": "Return immediately if the settings are already newer than this version"
+                        },
+                        "timeout": {
+                            "type": "integer",
+                            "description": "Maximum seconds to wait for a change (default: 30)"
+                        },
+                        "project": {
+                            "type": "string",
+                            "description": "Project root directory (default: current directory)"
+                        }
+                    }
+                }
+            }),
+        ]
+    }
+
+    pub async fn get(&self, args: Value) -> Result<Value> {
+        let file = settings_path(&args);
+        let store = SettingsStore::load(&file)?;
+
+        let keys: Vec<String> = if let Some(key) = args["key"].as_str() {
+            vec![key.to_string()]
+        } else if let Some(keys) = args["keys"].as_array() {
+            keys.iter().filter_map(|k| k.as_str()).map(|k| k.to_string()).collect()
+        } else {
+            anyhow::bail!("Missing 'key' or 'keys' parameter");
+        };
+
+        let results: Vec<Value> = keys
+            .iter()
+            .map(|key| {
+                let value = store.settings.get(key);
+                json!({
+                    "key": key,
+                    "value": value,
+                    "set": value.is_some(),
+                    "known": known_setting(key).is_some()
+                })
+            })
+            .collect();
+
+        if args["key"].is_string() {
+            let mut result = results.into_iter().next().unwrap_or_default();
+            result["version"] = json!(store.version);
+            return Ok(result);
+        }
+
+        Ok(json!({
+            "settings": results,
+            "version": store.version
+        }))
+    }
+
+    pub async fn set(&self, args: Value) -> Result<Value> {
+        let file = settings_path(&args);
+
+        let updates: Map<String, Value> = if let Some(key) = args["key"].as_str() {
+            let value = args.get("value").context("Missing 'value' parameter")?;
+            Map::from_iter([(key.to_string(), value.clone())])
+        } else if let Some(values) = args["values"].as_object() {
+            values.clone()
+        } else {
+            anyhow::bail!("Missing 'key'/'value' or 'values' parameter");
+        };
+
+        // Validate everything before touching the file so a bad entry doesn't cause a partial write
+        let mut warnings = Vec::new();
+        for (key, value) in &updates {
+            validate_setting(key, value)?;
+            if !value.is_null() && known_setting(key).is_none() {
+                warnings.push(format!("'{}' is not a known setting; stored without type checking", key));
+            }
+        }
+
+        // Held until the rename so concurrent writers in other processes can't interleave
+        let _lock = SettingsStore::lock(&file)?;
+        let mut store = SettingsStore::load(&file)?;
+        if let Some(expected) = args["expected_version"].as_u64() {
+            if store.version != expected {
+                anyhow::bail!(
+                    "Settings changed concurrently: expected version {}, found {}. Re-read and retry",
+                    expected, store.version
+                );
+            }
+        }
+
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+        for (key, value) in updates {
+            if value.is_null() {
+                if store.settings.remove(&key).is_some() {
+                    removed.push(key);
+                }
+            } else if store.settings.get(&key) != Some(&value) {
+                store.settings.insert(key.clone(), value);
+                changed.push(key);
+            }
+        }
+
+        if !changed.is_empty() || !removed.is_empty() {
+            store.version += 1;
+            store.save(&file)?;
+        }
+
+        Ok(json!({
+            "success": true,
+            "file": file,
+            "version": store.version,
+            "changed": changed,
+            "removed": removed,
+            "warnings": warnings
+        }))
+    }
+
+    pub async fn list(&self, args: Value) -> Result<Value> {
+        let file = settings_path(&args);
+        let store = SettingsStore::load(&file)?;
+        let prefix = args["prefix"].as_str().unwrap_or("");
+
+        let settings: Map<String, Value> = store
+            .settings
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let known: Vec<Value> = KNOWN_SETTINGS
+            .iter()
+            .filter(|s| s.key.starts_with(prefix))
+            .map(|s| json!({
+                "key": s.key,
+                "type": s.kind.describe(),
+                "description": s.description,
+                "set": store.settings.contains_key(s.key)
+            }))
+            .collect();
+
+        Ok(json!({
+            "file": file,
+            "exists": file.exists(),
+            "version": store.version,
+            "updated_at": store.updated_at,
+            "count": settings.len(),
+            "settings": settings,
+            "known_keys": known
+        }))
+    }
+
+    pub async fn watch(&self, args: Value) -> Result<Value> {
+        let file = settings_path(&args);
+        let timeout = args["timeout"].as_u64().unwrap_or(30);
+        let keys: Option<Vec<String>> = args["keys"]
+            .as_array()
+            .map(|k| k.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect());
+
+        let initial = SettingsStore::load(&file)?;
+        let mut baseline = initial.clone();
+
+        // A caller that already saw an older version gets the pending changes right away
+        if let Some(since) = args["since_version"].as_u64() {
+            if initial.version > since {
+                return Ok(json!({
+                    "changed": true,
+                    "version": initial.version,
+                    "settings": initial.settings
+                }));
+            }
+        }
+
+        let start = Instant::now();
+        while start.elapsed().as_secs() < timeout {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+
+            let current = match SettingsStore::load(&file) {
+                Ok(current) => current,
+                // The file may be mid-write by another agent; try again on the next tick
+                Err(_) => continue,
+            };
+            if current.version == baseline.version {
+                continue;
+            }
+
+            let changes = diff_settings(&baseline.settings, &current.settings, keys.as_deref());
+            if changes.is_empty() {
+                baseline = current;
+                continue;
+            }
+
+            if let Some(ref notifier) = self.notifier {
+                let _ = notifier.send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/settings_changed",
+                    "params": {
+                        "file": file,
+                        "version": current.version,
+                        "changes": changes
+                    }
+                }));
+            }
+
+            return Ok(json!({
+                "changed": true,
+                "version": current.version,
+                "changes": changes,
+                "waited_seconds": start.elapsed().as_secs_f64()
+            }));
+        }
+
+        Ok(json!({
+            "changed": false,
+            "version": baseline.version,
+            "waited_seconds": start.elapsed().as_secs_f64()
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for SettingsModule {
+    fn name(&self) -> &str {
+        "settings"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "settings_get" => self.get(args).await,
+            "settings_set" => self.set(args).await,
+            "settings_list" => self.list(args).await,
+            "settings_watch" => self.watch(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct SettingsStore {
+    version: u64,
+    updated_at: Option<String>,
+    settings: Map<String, Value>,
+}
+
+impl SettingsStore {
+    fn load(file: &Path) -> Result<Self> {
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read settings: {}", file.display()))?;
+        let data: Value = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid settings file: {}", file.display()))?;
+
+        Ok(Self {
+            version: data["version"].as_u64().unwrap_or(0),
+            updated_at: data["updated_at"].as_str().map(|s| s.to_string()),
+            settings: data["settings"].as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Take an exclusive advisory lock on a sidecar file; released when the handle drops
+    fn lock(file: &Path) -> Result<File> {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let path = file.with_extension("json.lock");
+        let handle = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open settings lock: {}", path.display()))?;
+        handle.lock()
+            .with_context(|| format!("Failed to lock settings: {}", path.display()))?;
+        Ok(handle)
+    }
+
+    /// Write through a temporary file and rename so readers never see a half-written file
+    fn save(&mut self, file: &Path) -> Result<()> {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        self.updated_at = Some(Utc::now().to_rfc3339());
+        let data = json!({
+            "version": self.version,
+            "updated_at": self.updated_at,
+            "settings": self.settings
+        });
+
+        let tmp = file.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_string_pretty(&data)? + "\n")
+            .with_context(|| format!("Failed to write settings: {}", tmp.display()))?;
+        fs::rename(&tmp, file)
+            .with_context(|| format!("Failed to write settings: {}", file.display()))?;
+        Ok(())
+    }
+}
+
+fn settings_path(args: &Value) -> PathBuf {
+    let project = args["project"].as_str().unwrap_or(".");
+    Path::new(project).join(SETTINGS_DIR).join(SETTINGS_FILE)
+}
+
+fn known_setting(key: &str) -> Option<&'static KnownSetting> {
+    KNOWN_SETTINGS.iter().find(|s| s.key == key)
+}
+
+fn validate_setting(key: &str, value: &Value) -> Result<()> {
+    if !KEY_RE.is_match(key) {
+        anyhow::bail!("Invalid setting key '{}': use dot-separated lowercase segments, e.g. 'build.command'", key);
+    }
+
+    if value.is_null() {
+        return Ok(());
+    }
+
+    if let Some(known) = known_setting(key) {
+        if !known.kind.accepts(value) {
+            anyhow::bail!("Invalid value for '{}': expected {}, got {}", key, known.kind.describe(), value);
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_settings(old: &Map<String, Value>, new: &Map<String, Value>, keys: Option<&[String]>) -> Vec<Value> {
+    let mut all_keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    all_keys.sort();
+    all_keys.dedup();
+
+    all_keys
+        .into_iter()
+        .filter(|key| keys.is_none_or(|k| k.contains(key)))
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| json!({
+            "key": key,
+            "old": old.get(key),
+            "new": new.get(key)
+        }))
+        .collect()
+}
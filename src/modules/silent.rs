@@ -1,10 +1,23 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::children;
+use crate::progress;
+use crate::registry::{Lazy, ToolModule};
+use crate::telemetry;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::process::Command;
 use sysinfo::System;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead};
+use tracing::Instrument;
 
 pub struct SilentModule {
-    system: System,
+    // The full process and hardware scan, done on the first resource query
+    system: Lazy<Mutex<System>>,
+    // Script files of running silent_script calls, removed at shutdown if still there
+    scripts: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
 impl Default for SilentModule {
@@ -16,7 +29,8 @@ impl Default for SilentModule {
 impl SilentModule {
     pub fn new() -> Self {
         Self {
-            system: System::new_all(),
+            system: Lazy::new(|| Mutex::new(System::new_all())),
+            scripts: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
@@ -24,7 +38,7 @@ impl SilentModule {
         vec![
             json!({
                 "name": "silent_script",
-                "description": "Execute bash scripts (silent scripting language)",
+                "description": "Execute bash scripts (silent scripting language). When the call carries a progress token, stdout and stderr lines are streamed as progress notifications while the script runs",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -94,6 +108,8 @@ impl SilentModule {
 
         std::fs::write(&script_path, script)
             .context("Failed to write script to temp file")?;
+        // Removed on drop, so the file is cleaned up even if the call is cancelled
+        let _script_file = TempFile::new(script_path.clone(), self.scripts.clone());
 
         // Make script executable
         #[cfg(unix)]
@@ -107,6 +123,9 @@ impl SilentModule {
         // Build command
         let mut cmd = tokio::process::Command::new("bash");
         cmd.arg(&script_path);
+        cmd.stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
 
         for arg in script_args {
             cmd.arg(arg);
@@ -128,11 +147,25 @@ impl SilentModule {
         let start = std::time::Instant::now();
         let timeout_dur = tokio::time::Duration::from_secs(timeout_secs);
 
-        let result = tokio::time::timeout(timeout_dur, cmd.output()).await;
-        let duration = start.elapsed();
+        let span = telemetry::command_span("bash");
+        // In a process group of its own, killed with the script's children if it
+        // times out or the call is cancelled
+        let (child, tracked) = children::spawn_async(&mut cmd).context("Failed to execute script")?;
 
-        // Clean up temp file
-        let _ = std::fs::remove_file(&script_path);
+        let result = tokio::time::timeout(timeout_dur, collect_output(child))
+            .instrument(span.clone())
+            .await;
+        let duration = start.elapsed();
+        let status = match &result {
+            Ok(Ok(output)) => Some(&output.status),
+            _ => None,
+        };
+        telemetry::record_command(&span, "bash", status, duration);
+        // Finished normally: leave anything it deliberately backgrounded alone
+        let result = match result {
+            Ok(output) => Ok(tracked.finish(output)),
+            Err(elapsed) => Err(elapsed),
+        };
 
         match result {
             Ok(Ok(output)) => {
@@ -149,7 +182,8 @@ impl SilentModule {
                 }))
             }
             Ok(Err(e)) => {
-                Err(anyhow::anyhow!("Failed to execute script: {}", e))
+                // Keeps the kind, which says whether the supervisor killed it
+                Err(std::io::Error::new(e.kind(), format!("Failed to execute script: {}", e)).into())
             }
             Err(_) => {
                 Ok(json!({
@@ -164,16 +198,17 @@ impl SilentModule {
         }
     }
 
-    pub async fn resources(&mut self, args: Value) -> Result<Value> {
+    pub async fn resources(&self, args: Value) -> Result<Value> {
         let detailed = args["detailed"].as_bool().unwrap_or(false);
         let process_filter = args["process_filter"].as_str();
 
         // Refresh system information
-        self.system.refresh_all();
+        let mut system = self.system.get().lock().unwrap();
+        system.refresh_all();
 
         // CPU information
         let mut cpu_usage = Vec::new();
-        for cpu in self.system.cpus() {
+        for cpu in system.cpus() {
             cpu_usage.push(json!({
                 "name": cpu.name(),
                 "usage": cpu.cpu_usage(),
@@ -181,17 +216,17 @@ impl SilentModule {
             }));
         }
 
-        let global_cpu_usage = self.system.global_cpu_info().cpu_usage();
+        let global_cpu_usage = system.global_cpu_info().cpu_usage();
 
         // Memory information
-        let total_memory = self.system.total_memory();
-        let used_memory = self.system.used_memory();
-        let available_memory = self.system.available_memory();
+        let total_memory = system.total_memory();
+        let used_memory = system.used_memory();
+        let available_memory = system.available_memory();
         let memory_usage_percent = (used_memory as f64 / total_memory as f64) * 100.0;
 
         // Swap information
-        let total_swap = self.system.total_swap();
-        let used_swap = self.system.used_swap();
+        let total_swap = system.total_swap();
+        let used_swap = system.used_swap();
         let swap_usage_percent = if total_swap > 0 {
             (used_swap as f64 / total_swap as f64) * 100.0
         } else {
@@ -205,7 +240,7 @@ impl SilentModule {
             "cpu": {
                 "global_usage": global_cpu_usage,
                 "cores": cpu_usage,
-                "core_count": self.system.cpus().len()
+                "core_count": system.cpus().len()
             },
             "memory": {
                 "total_bytes": total_memory,
@@ -227,7 +262,7 @@ impl SilentModule {
         if detailed {
             let mut processes = Vec::new();
 
-            for (pid, process) in self.system.processes() {
+            for (pid, process) in system.processes() {
                 let name = process.name();
 
                 // Filter by process name if specified
@@ -266,13 +301,10 @@ impl SilentModule {
 
     fn get_gpu_info(&self) -> Value {
         // Try to get GPU info from nvidia-smi
-        if let Ok(output) = Command::new("nvidia-smi")
-            .args([
-                "--query-gpu=index,name,temperature.gpu,utilization.gpu,utilization.memory,memory.total,memory.used,memory.free",
-                "--format=csv,noheader,nounits"
-            ])
-            .output()
-        {
+        if let Ok(output) = telemetry::run_command(Command::new("nvidia-smi").args([
+            "--query-gpu=index,name,temperature.gpu,utilization.gpu,utilization.memory,memory.total,memory.used,memory.free",
+            "--format=csv,noheader,nounits",
+        ])) {
             if output.status.success() {
                 let stdout = String::from_utf8_lossy(&output.stdout);
                 let mut gpus = Vec::new();
@@ -310,3 +342,83 @@ impl SilentModule {
         })
     }
 }
+
+#[async_trait]
+impl ToolModule for SilentModule {
+    fn name(&self) -> &str {
+        "silent"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "silent_script" => self.script(args).await,
+            "silent_resources" => self.resources(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        for script in self.scripts.lock().unwrap().drain() {
+            let _ = std::fs::remove_file(script);
+        }
+        Ok(())
+    }
+
+    fn warm_up(&self) {
+        self.system.get();
+    }
+}
+
+/// Wait for the script while reading its output line by line, so each line can be
+/// streamed to the client as it is printed
+async fn collect_output(mut child: tokio::process::Child) -> std::io::Result<std::process::Output> {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let (stdout, stderr, status) = tokio::try_join!(
+        read_stream(stdout, "stdout"),
+        read_stream(stderr, "stderr"),
+        child.wait(),
+    )?;
+    Ok(std::process::Output { status, stdout, stderr })
+}
+
+async fn read_stream(pipe: Option<impl AsyncRead + Unpin>, name: &str) -> std::io::Result<Vec<u8>> {
+    let mut collected = Vec::new();
+    let Some(pipe) = pipe else {
+        return Ok(collected);
+    };
+
+    let mut reader = tokio::io::BufReader::new(pipe);
+    loop {
+        let start = collected.len();
+        if reader.read_until(b'\n', &mut collected).await? == 0 {
+            break;
+        }
+        progress::output(name, &String::from_utf8_lossy(&collected[start..]));
+    }
+    Ok(collected)
+}
+
+/// A script file, registered with the module until it is removed on drop
+struct TempFile {
+    path: PathBuf,
+    live: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl TempFile {
+    fn new(path: PathBuf, live: Arc<Mutex<HashSet<PathBuf>>>) -> Self {
+        live.lock().unwrap().insert(path.clone());
+        Self { path, live }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        self.live.lock().unwrap().remove(&self.path);
+    }
+}
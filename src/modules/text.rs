@@ -0,0 +1,431 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::patch;
+use crate::registry::ToolModule;
+use similar::{Algorithm, ChangeTag, DiffTag, TextDiff};
+use std::ops::Range;
+
+pub struct TextModule;
+
+impl Default for TextModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextModule {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "text_diff",
+                "description": "Diff two strings in memory, without touching files. Returns a unified diff, or a structured list of hunks (line granularity) or changed segments (word/char granularity).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "old": {
+                            "type": "string",
+                            "description": "Original text"
+                        },
+                        "new": {
+                            "type": "string",
+                            "description": "Changed text"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["unified", "structured"],
+                            "description": "Output format (default: unified)"
+                        },
+                        "granularity": {
+                            "type": "string",
+                            "enum": ["line", "word", "char"],
+                            "description": "Unit of comparison for structured output (default: line)"
+                        },
+                        "context_lines": {
+                            "type": "integer",
+                            "description": "Unchanged lines around each hunk (default: 3)"
+                        },
+                        "old_label": {
+                            "type": "string",
+                            "description": "Name for the old text in the unified diff header (default: a)"
+                        },
+                        "new_label": {
+                            "type": "string",
+                            "description": "Name for the new text in the unified diff header (default: b)"
+                        }
+                    },
+                    "required": ["old", "new"]
+                }
+            }),
+            json!({
+                "name": "text_patch",
+                "description": "Apply a unified diff to a string and return the patched text. Hunks are located by their context, so they still apply when line numbers have drifted; each hunk is reported as applied, already_applied or failed.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "text": {
+                            "type": "string",
+                            "description": "Text to patch"
+                        },
+                        "patch": {
+                            "type": "string",
+                            "description": "Unified diff for a single file"
+                        },
+                        "reverse": {
+                            "type": "boolean",
+                            "description": "Undo the patch instead of applying it (default: false)"
+                        },
+                        "partial": {
+                            "type": "boolean",
+                            "description": "Return the result even if some hunks fail (default: false)"
+                        },
+                        "max_offset": {
+                            "type": "integer",
+                            "description": "How many lines away from its stated position a hunk may be found (default: unlimited)"
+                        }
+                    },
+                    "required": ["text", "patch"]
+                }
+            }),
+            json!({
+                "name": "text_merge3",
+                "description": "Three-way merge of two edited versions of a common base text. Non-overlapping changes are combined; overlapping ones become conflicts marked with <<<<<<< / ======= / >>>>>>>.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "base": {
+                            "type": "string",
+                            "description": "Common ancestor text"
+                        },
+                        "ours": {
+                            "type": "string",
+                            "description": "First edited version"
+                        },
+                        "theirs": {
+                            "type": "string",
+                            "description": "Second edited version"
+                        },
+                        "style": {
+                            "type": "string",
+                            "enum": ["merge", "diff3"],
+                            "description": "Conflict style; diff3 also shows the base text between ||||||| and ======= (default: merge)"
+                        },
+                        "ours_label": {
+                            "type": "string",
+                            "description": "Label after <<<<<<< (default: ours)"
+                        },
+                        "theirs_label": {
+                            "type": "string",
+                            "description": "Label after >>>>>>> (default: theirs)"
+                        },
+                        "base_label": {
+                            "type": "string",
+                            "description": "Label after ||||||| in diff3 style (default: base)"
+                        }
+                    },
+                    "required": ["base", "ours", "theirs"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn diff(&self, args: Value) -> Result<Value> {
+        let old = args["old"].as_str().context("Missing 'old' parameter")?;
+        let new = args["new"].as_str().context("Missing 'new' parameter")?;
+        let format = args["format"].as_str().unwrap_or("unified");
+        let granularity = args["granularity"].as_str().unwrap_or("line");
+        let context_lines = args["context_lines"].as_u64().unwrap_or(3) as usize;
+        let old_label = args["old_label"].as_str().unwrap_or("a");
+        let new_label = args["new_label"].as_str().unwrap_or("b");
+
+        let line_diff = TextDiff::from_lines(old, new);
+        let mut additions = 0;
+        let mut deletions = 0;
+        for change in line_diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => additions += 1,
+                ChangeTag::Delete => deletions += 1,
+                ChangeTag::Equal => {}
+            }
+        }
+
+        let mut result = json!({
+            "identical": old == new,
+            "additions": additions,
+            "deletions": deletions,
+            "format": format
+        });
+
+        match (format, granularity) {
+            ("unified", _) => {
+                result["diff"] = json!(line_diff
+                    .unified_diff()
+                    .context_radius(context_lines)
+                    .header(old_label, new_label)
+                    .to_string());
+            }
+            ("structured", "line") => {
+                let hunks: Vec<Value> = line_diff
+                    .grouped_ops(context_lines)
+                    .iter()
+                    .map(|group| {
+                        let (first, last) = (&group[0], &group[group.len() - 1]);
+                        let old_range = first.old_range().start..last.old_range().end;
+                        let new_range = first.new_range().start..last.new_range().end;
+                        let changes: Vec<Value> = group
+                            .iter()
+                            .flat_map(|op| line_diff.iter_changes(op))
+                            .map(|change| {
+                                json!({
+                                    "tag": tag_name(change.tag()),
+                                    "old_line": change.old_index().map(|i| i + 1),
+                                    "new_line": change.new_index().map(|i| i + 1),
+                                    "text": patch::strip_eol(change.value())
+                                })
+                            })
+                            .collect();
+                        json!({
+                            "old_start": old_range.start + 1,
+                            "old_lines": old_range.len(),
+                            "new_start": new_range.start + 1,
+                            "new_lines": new_range.len(),
+                            "changes": changes
+                        })
+                    })
+                    .collect();
+                result["hunks"] = json!(hunks);
+            }
+            ("structured", "word") | ("structured", "char") => {
+                let diff = if granularity == "word" {
+                    TextDiff::from_words(old, new)
+                } else {
+                    TextDiff::from_chars(old, new)
+                };
+
+                // Merge consecutive tokens with the same tag into one segment
+                let mut segments: Vec<(ChangeTag, String)> = Vec::new();
+                for change in diff.iter_all_changes() {
+                    match segments.last_mut() {
+                        Some((tag, text)) if *tag == change.tag() => text.push_str(change.value()),
+                        _ => segments.push((change.tag(), change.value().to_string())),
+                    }
+                }
+                result["segments"] = json!(segments
+                    .iter()
+                    .map(|(tag, text)| json!({ "tag": tag_name(*tag), "text": text }))
+                    .collect::<Vec<_>>());
+            }
+            ("structured", other) => {
+                return Err(anyhow::anyhow!("Unsupported granularity: {}. Use line, word or char", other))
+            }
+            (other, _) => return Err(anyhow::anyhow!("Unsupported format: {}. Use unified or structured", other)),
+        }
+
+        result["granularity"] = json!(granularity);
+        Ok(result)
+    }
+
+    pub async fn patch(&self, args: Value) -> Result<Value> {
+        let text = args["text"].as_str().context("Missing 'text' parameter")?;
+        let patch = args["patch"].as_str().context("Missing 'patch' parameter")?;
+        let reverse = args["reverse"].as_bool().unwrap_or(false);
+        let partial = args["partial"].as_bool().unwrap_or(false);
+        let max_offset = args["max_offset"].as_u64().map(|n| n as usize);
+
+        let mut hunks = patch::parse(patch)?;
+        if hunks.is_empty() {
+            anyhow::bail!("Patch contains no hunks (expected lines starting with @@)");
+        }
+        if reverse {
+            for hunk in &mut hunks {
+                hunk.reverse();
+            }
+        }
+
+        let patched = patch::apply(text, &hunks, max_offset, false);
+        let success = patched.failed == 0;
+        let result = if success || partial { Some(patched.text) } else { None };
+
+        Ok(json!({
+            "success": success,
+            "result": result,
+            "applied": patched.applied,
+            "already_applied": patched.already_applied,
+            "failed": patched.failed,
+            "reversed": reverse,
+            "hunks": patched.hunks
+        }))
+    }
+
+    pub async fn merge3(&self, args: Value) -> Result<Value> {
+        let base = args["base"].as_str().context("Missing 'base' parameter")?;
+        let ours = args["ours"].as_str().context("Missing 'ours' parameter")?;
+        let theirs = args["theirs"].as_str().context("Missing 'theirs' parameter")?;
+        let style = args["style"].as_str().unwrap_or("merge");
+        let ours_label = args["ours_label"].as_str().unwrap_or("ours");
+        let theirs_label = args["theirs_label"].as_str().unwrap_or("theirs");
+        let base_label = args["base_label"].as_str().unwrap_or("base");
+
+        if style != "merge" && style != "diff3" {
+            anyhow::bail!("Unsupported style: {}. Use merge or diff3", style);
+        }
+
+        let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+        let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+        let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+        let ours_edits = edits(&base_lines, &ours_lines);
+        let theirs_edits = edits(&base_lines, &theirs_lines);
+
+        let mut merged = String::new();
+        let mut conflicts = Vec::new();
+        let mut cursor = 0;
+        let (mut i, mut j) = (0, 0);
+
+        while i < ours_edits.len() || j < theirs_edits.len() {
+            // Start a cluster at the earliest edit, then pull in every edit from either
+            // side that overlaps or touches it
+            let start = match (ours_edits.get(i), theirs_edits.get(j)) {
+                (Some(a), Some(b)) => a.base.start.min(b.base.start),
+                (Some(a), None) => a.base.start,
+                (None, Some(b)) => b.base.start,
+                (None, None) => break,
+            };
+            let mut end = start;
+            let (ours_from, theirs_from) = (i, j);
+            loop {
+                if let Some(edit) = ours_edits.get(i).filter(|e| e.base.start <= end) {
+                    end = end.max(edit.base.end);
+                    i += 1;
+                } else if let Some(edit) = theirs_edits.get(j).filter(|e| e.base.start <= end) {
+                    end = end.max(edit.base.end);
+                    j += 1;
+                } else {
+                    break;
+                }
+            }
+
+            merged.push_str(&base_lines[cursor..start].concat());
+            cursor = end;
+
+            let ours_version = apply_edits(&base_lines, &ours_lines, start, end, &ours_edits[ours_from..i]);
+            let theirs_version = apply_edits(&base_lines, &theirs_lines, start, end, &theirs_edits[theirs_from..j]);
+
+            if ours_from == i {
+                merged.push_str(&theirs_version);
+            } else if theirs_from == j || ours_version == theirs_version {
+                merged.push_str(&ours_version);
+            } else {
+                let base_version = base_lines[start..end].concat();
+                ensure_newline(&mut merged);
+                merged.push_str(&format!("<<<<<<< {}\n", ours_label));
+                merged.push_str(&ours_version);
+                ensure_newline(&mut merged);
+                if style == "diff3" {
+                    merged.push_str(&format!("||||||| {}\n", base_label));
+                    merged.push_str(&base_version);
+                    ensure_newline(&mut merged);
+                }
+                merged.push_str("=======\n");
+                merged.push_str(&theirs_version);
+                ensure_newline(&mut merged);
+                merged.push_str(&format!(">>>>>>> {}\n", theirs_label));
+
+                conflicts.push(json!({
+                    "base_start": start + 1,
+                    "base_end": end,
+                    "base": base_version,
+                    "ours": ours_version,
+                    "theirs": theirs_version
+                }));
+            }
+        }
+        merged.push_str(&base_lines[cursor..].concat());
+
+        Ok(json!({
+            "merged": merged,
+            "clean": conflicts.is_empty(),
+            "conflict_count": conflicts.len(),
+            "conflicts": conflicts,
+            "style": style
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for TextModule {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "text_diff" => self.diff(args).await,
+            "text_patch" => self.patch(args).await,
+            "text_merge3" => self.merge3(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Three-way merge ─────────────────────────────────────────────────────
+
+/// A change relative to the base: `base` lines replaced by `new` lines of the other version
+struct Edit {
+    base: Range<usize>,
+    new: Range<usize>,
+}
+
+fn edits(base: &[&str], other: &[&str]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    for op in similar::capture_diff_slices(Algorithm::Myers, base, other) {
+        let (tag, old, new) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        // Adjacent delete + insert ops form one replacement
+        match edits.last_mut() {
+            Some(last) if last.base.end == old.start && last.new.end == new.start => {
+                last.base.end = old.end;
+                last.new.end = new.end;
+            }
+            _ => edits.push(Edit { base: old, new }),
+        }
+    }
+    edits
+}
+
+/// Base lines `start..end` with `edits` (all inside that range) applied
+fn apply_edits(base: &[&str], other: &[&str], start: usize, end: usize, edits: &[Edit]) -> String {
+    let mut out = String::new();
+    let mut cursor = start;
+    for edit in edits {
+        out.push_str(&base[cursor..edit.base.start].concat());
+        out.push_str(&other[edit.new.clone()].concat());
+        cursor = edit.base.end;
+    }
+    out.push_str(&base[cursor..end].concat());
+    out
+}
+
+fn ensure_newline(text: &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn tag_name(tag: ChangeTag) -> &'static str {
+    match tag {
+        ChangeTag::Equal => "equal",
+        ChangeTag::Insert => "insert",
+        ChangeTag::Delete => "delete",
+    }
+}
@@ -1,18 +1,24 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
 use chrono::{Local, Utc, DateTime, Duration as ChronoDuration};
 use chrono_tz::Tz;
+use crate::storage::{keyspaces, MemoryStorage, Storage};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use tokio::time::{sleep as tokio_sleep, Duration};
 
 pub struct TimeModule {
-    scheduled_tasks: Arc<Mutex<HashMap<String, ScheduledTask>>>,
+    // time_schedule tasks, by id
+    storage: Arc<dyn Storage>,
     stopwatches: Arc<Mutex<HashMap<String, Stopwatch>>>,
     timers: Arc<Mutex<HashMap<String, TimerEntry>>>,
     alarms: Arc<Mutex<HashMap<String, Alarm>>>,
 }
 
+#[derive(Serialize, Deserialize)]
 struct ScheduledTask {
     id: String,
     execute_at: DateTime<Utc>,
@@ -63,8 +69,13 @@ impl Default for TimeModule {
 
 impl TimeModule {
     pub fn new() -> Self {
+        Self::with_storage(Arc::new(MemoryStorage::new()))
+    }
+
+    /// Keep time_schedule tasks in `storage`
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
         Self {
-            scheduled_tasks: Arc::new(Mutex::new(HashMap::new())),
+            storage,
             stopwatches: Arc::new(Mutex::new(HashMap::new())),
             timers: Arc::new(Mutex::new(HashMap::new())),
             alarms: Arc::new(Mutex::new(HashMap::new())),
@@ -117,7 +128,7 @@ impl TimeModule {
             }),
             json!({
                 "name": "time_schedule",
-                "description": "Schedule a task for future execution (in-memory, process lifetime)",
+                "description": "Schedule a task for future execution (kept in memory for the process lifetime, or across restarts with a persistent [storage] backend)",
                 "inputSchema": {
                     "type": "object",
                     "properties": {
@@ -363,24 +374,26 @@ impl TimeModule {
             executed: false,
         };
 
-        let mut tasks = self.scheduled_tasks.lock().unwrap();
-        tasks.insert(task_id.clone(), task);
+        self.storage.put_json(keyspaces::SCHEDULE, &task_id, &task)?;
 
+        let message = if self.storage.is_persistent() {
+            "Task scheduled (kept in storage across restarts)"
+        } else {
+            "Task scheduled (in-memory, will be lost on process restart)"
+        };
         Ok(json!({
             "success": true,
             "task_id": task_id,
             "execute_at": execute_at.to_rfc3339(),
             "callback": callback,
-            "message": "Task scheduled (in-memory, will be lost on process restart)"
+            "message": message
         }))
     }
 
     async fn schedule_cancel(&self, args: Value) -> Result<Value> {
         let task_id = args["task_id"].as_str().context("Missing 'task_id' parameter")?;
 
-        let mut tasks = self.scheduled_tasks.lock().unwrap();
-
-        if tasks.remove(task_id).is_some() {
+        if self.storage.delete(keyspaces::SCHEDULE, task_id)? {
             Ok(json!({
                 "success": true,
                 "task_id": task_id,
@@ -392,10 +405,10 @@ impl TimeModule {
     }
 
     async fn schedule_list(&self, _args: Value) -> Result<Value> {
-        let tasks = self.scheduled_tasks.lock().unwrap();
+        let tasks: Vec<(String, ScheduledTask)> = self.storage.scan_json(keyspaces::SCHEDULE, "")?;
         let now = Utc::now();
 
-        let task_list: Vec<Value> = tasks.values().map(|task| {
+        let task_list: Vec<Value> = tasks.iter().map(|(_, task)| {
             let time_until = task.execute_at.signed_duration_since(now);
 
             json!({
@@ -418,9 +431,7 @@ impl TimeModule {
     async fn schedule_status(&self, args: Value) -> Result<Value> {
         let task_id = args["task_id"].as_str().context("Missing 'task_id' parameter")?;
 
-        let tasks = self.scheduled_tasks.lock().unwrap();
-
-        if let Some(task) = tasks.get(task_id) {
+        if let Some(task) = self.storage.get_json::<ScheduledTask>(keyspaces::SCHEDULE, task_id)? {
             let now = Utc::now();
             let time_until = task.execute_at.signed_duration_since(now);
 
@@ -923,6 +934,61 @@ impl TimeModule {
     }
 }
 
+#[async_trait]
+impl ToolModule for TimeModule {
+    fn name(&self) -> &str {
+        "time"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "time_now" => self.now(args).await,
+            "time_sleep" => self.sleep(args).await,
+            "time_schedule" => self.schedule(args).await,
+            "time_timezone" => self.timezone(args).await,
+            "time_stopwatch" => self.stopwatch(args).await,
+            "time_timer" => self.timer(args).await,
+            "time_alarm" => self.alarm(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        // Alarms, and scheduled tasks without persistent storage, only live in
+        // memory, so say what is being dropped
+        let tasks: Vec<String> = if self.storage.is_persistent() {
+            Vec::new()
+        } else {
+            self.storage
+                .scan_json::<ScheduledTask>(keyspaces::SCHEDULE, "")?
+                .into_iter()
+                .filter(|(_, task)| !task.executed)
+                .map(|(id, task)| format!("{} at {}", id, task.execute_at.to_rfc3339()))
+                .collect()
+        };
+        let alarms: Vec<String> = self
+            .alarms
+            .lock()
+            .unwrap()
+            .drain()
+            .filter(|(_, alarm)| !alarm.triggered)
+            .map(|(name, alarm)| format!("{} at {}", name, alarm.alarm_time.to_rfc3339()))
+            .collect();
+
+        if !tasks.is_empty() {
+            tracing::warn!("Discarding {} pending scheduled task(s): {}", tasks.len(), tasks.join(", "));
+        }
+        if !alarms.is_empty() {
+            tracing::warn!("Discarding {} pending alarm(s): {}", alarms.len(), alarms.join(", "));
+        }
+        Ok(())
+    }
+}
+
 fn format_duration_ms(ms: i64) -> String {
     let total_secs = ms / 1000;
     let millis = ms % 1000;
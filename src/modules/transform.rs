@@ -1,5 +1,8 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::provenance;
+use crate::registry::ToolModule;
 use std::fs;
 use std::io::Write as IoWrite;
 use std::path::Path;
@@ -224,6 +227,8 @@ impl TransformModule {
         let context_lines = args["context_lines"].as_u64().unwrap_or(3) as usize;
 
         let (text_a, text_b, label_a, label_b) = if from_file {
+            provenance::read(a_raw);
+            provenance::read(b_raw);
             let a = fs::read_to_string(a_raw)
                 .with_context(|| format!("Failed to read file: {}", a_raw))?;
             let b = fs::read_to_string(b_raw)
@@ -369,6 +374,7 @@ impl TransformModule {
         let from_file = args["from_file"].as_bool().unwrap_or(false);
 
         let data = if from_file {
+            provenance::read(input);
             fs::read(input)
                 .with_context(|| format!("Failed to read file: {}", input))?
         } else {
@@ -729,13 +735,16 @@ impl TransformModule {
         let file_paths: Vec<&str> = files.iter()
             .filter_map(|v| v.as_str())
             .collect();
+        for file_path in &file_paths {
+            provenance::read(file_path);
+        }
 
         match format {
             "zip" => {
                 let file = fs::File::create(path)
                     .with_context(|| format!("Failed to create archive: {}", path))?;
                 let mut archive = zip::ZipWriter::new(file);
-                let options: zip::write::FileOptions = zip::write::FileOptions::default()
+                let options = zip::write::SimpleFileOptions::default()
                     .compression_method(zip::CompressionMethod::Deflated);
 
                 for file_path in &file_paths {
@@ -787,6 +796,7 @@ impl TransformModule {
 
     async fn archive_extract(&self, path: &str, args: &Value, format: &str) -> Result<Value> {
         let destination = args["destination"].as_str().unwrap_or(".");
+        provenance::read(path);
 
         fs::create_dir_all(destination)
             .with_context(|| format!("Failed to create destination: {}", destination))?;
@@ -849,6 +859,7 @@ impl TransformModule {
     }
 
     async fn archive_list(&self, path: &str, format: &str) -> Result<Value> {
+        provenance::read(path);
         let mut entries = Vec::new();
 
         match format {
@@ -897,6 +908,30 @@ impl TransformModule {
     }
 }
 
+#[async_trait]
+impl ToolModule for TransformModule {
+    fn name(&self) -> &str {
+        "transform"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "transform_diff" => self.diff(args).await,
+            "transform_encode" => self.encode(args).await,
+            "transform_hash" => self.hash(args).await,
+            "transform_regex" => self.regex_op(args).await,
+            "transform_json" => self.json_op(args).await,
+            "transform_text" => self.text(args).await,
+            "transform_archive" => self.archive(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
 // ── Helper functions ────────────────────────────────────────────────────
 
 fn json_query(value: &Value, query: &str) -> Value {
@@ -1055,7 +1090,7 @@ fn add_dir_to_zip<W: IoWrite + std::io::Seek>(
     archive: &mut zip::ZipWriter<W>,
     dir: &Path,
     base: &Path,
-    options: zip::write::FileOptions,
+    options: zip::write::SimpleFileOptions,
 ) -> Result<()> {
     for entry in walkdir::WalkDir::new(dir) {
         let entry = entry?;
@@ -0,0 +1,625 @@
+use serde_json::{json, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::modules::diagnostics::DiagnosticsModule;
+use crate::paths::resolve_path;
+use crate::registry::ToolModule;
+use crate::telemetry;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// At most this many diagnostics are quoted when validation fails
+const MAX_REPORTED_DIAGNOSTICS: usize = 20;
+/// Tail of a failed check command's output quoted in the error
+const MAX_REPORTED_OUTPUT_CHARS: usize = 4000;
+
+/// A file as staged in a transaction
+#[derive(Clone)]
+struct StagedFile {
+    // Disk content when the transaction first touched the file; None if it did not exist
+    original: Option<Vec<u8>>,
+    // New content, or None to delete the file
+    content: Option<String>,
+}
+
+struct Transaction {
+    description: Option<String>,
+    created: DateTime<Utc>,
+    files: BTreeMap<PathBuf, StagedFile>,
+}
+
+/// Pending multi-file edits, shared between the txn tools and the filesystem
+/// tools that stage changes into them. Nothing reaches the disk until commit.
+#[derive(Default)]
+pub struct Transactions {
+    open: Mutex<HashMap<String, Transaction>>,
+}
+
+impl Transactions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The content `path` has within the transaction: `Some(Some(text))` if
+    /// written, `Some(None)` if deleted, `None` if the transaction has not touched it
+    pub fn read(&self, txn: &str, path: &Path) -> Result<Option<Option<String>>> {
+        let open = self.open.lock().unwrap();
+        let transaction = open.get(txn).with_context(|| unknown_txn(txn))?;
+        Ok(transaction.files.get(&resolve_path(path)).map(|file| file.content.clone()))
+    }
+
+    /// Stage new content for `path`
+    pub fn write(&self, txn: &str, path: &Path, content: String) -> Result<()> {
+        self.stage(txn, path, Some(content))
+    }
+
+    /// Stage the deletion of `path`
+    pub fn delete(&self, txn: &str, path: &Path) -> Result<()> {
+        self.stage(txn, path, None)
+    }
+
+    fn stage(&self, txn: &str, path: &Path, content: Option<String>) -> Result<()> {
+        let resolved = resolve_path(path);
+        if resolved.is_dir() {
+            anyhow::bail!("{} is a directory; transactions only stage file edits", path.display());
+        }
+
+        let mut open = self.open.lock().unwrap();
+        let transaction = open.get_mut(txn).with_context(|| unknown_txn(txn))?;
+        match transaction.files.get_mut(&resolved) {
+            Some(file) => file.content = content,
+            None => {
+                // Remembered so commit can detect outside changes and roll back
+                let original = match std::fs::read(&resolved) {
+                    Ok(bytes) => Some(bytes),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+                };
+                transaction.files.insert(resolved, StagedFile { original, content });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TxnModule {
+    transactions: Arc<Transactions>,
+}
+
+impl Default for TxnModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxnModule {
+    pub fn new() -> Self {
+        Self::with_transactions(Arc::new(Transactions::new()))
+    }
+
+    /// Share `transactions` with the filesystem module that stages edits into them
+    pub fn with_transactions(transactions: Arc<Transactions>) -> Self {
+        Self { transactions }
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "txn_begin",
+                "description": "Start a transaction for all-or-nothing edits across files. Pass the returned txn id to fs_write, fs_replace, fs_create, fs_delete and fs_move to stage changes (fs_read with the id sees them), then txn_commit or txn_abort",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "description": {
+                            "type": "string",
+                            "description": "What the transaction is for, shown by txn_status"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "txn_status",
+                "description": "List open transactions, or show the files staged in one with optional diffs",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id (default: list all open transactions)"
+                        },
+                        "diff": {
+                            "type": "boolean",
+                            "description": "Include a unified diff per file (default: false)"
+                        }
+                    }
+                }
+            }),
+            json!({
+                "name": "txn_commit",
+                "description": "Apply every staged change at once. Fails without touching anything if a file changed on disk since it was staged. With diagnostics or command, the result is validated after applying and rolled back if the check fails; the transaction then stays open for fixes",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin"
+                        },
+                        "diagnostics": {
+                            "type": "string",
+                            "description": "Project or file to run diagnostics_get on; any error rolls the commit back"
+                        },
+                        "command": {
+                            "type": "string",
+                            "description": "Shell command that must exit 0, e.g. 'cargo test'; otherwise the commit is rolled back. Refused where silent_script is denied, and needs approval where silent_script does"
+                        },
+                        "cwd": {
+                            "type": "string",
+                            "description": "Working directory for command (default: the server's)"
+                        }
+                    },
+                    "required": ["txn"]
+                }
+            }),
+            json!({
+                "name": "txn_abort",
+                "description": "Discard a transaction and everything staged in it. Nothing was written to disk",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "txn": {
+                            "type": "string",
+                            "description": "Transaction id from txn_begin"
+                        }
+                    },
+                    "required": ["txn"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn begin(&self, args: Value) -> Result<Value> {
+        let id = format!("txn-{}", uuid::Uuid::new_v4().simple());
+        let created = Utc::now();
+        self.transactions.open.lock().unwrap().insert(
+            id.clone(),
+            Transaction {
+                description: args["description"].as_str().map(String::from),
+                created,
+                files: BTreeMap::new(),
+            },
+        );
+
+        Ok(json!({
+            "txn": id,
+            "created": created.to_rfc3339(),
+        }))
+    }
+
+    pub async fn status(&self, args: Value) -> Result<Value> {
+        let open = self.transactions.open.lock().unwrap();
+
+        let Some(id) = args["txn"].as_str() else {
+            let mut list: Vec<Value> = open
+                .iter()
+                .map(|(id, transaction)| {
+                    json!({
+                        "txn": id,
+                        "description": transaction.description,
+                        "created": transaction.created.to_rfc3339(),
+                        "files": transaction.files.len(),
+                    })
+                })
+                .collect();
+            list.sort_by(|a, b| a["created"].as_str().cmp(&b["created"].as_str()));
+            return Ok(json!({ "count": list.len(), "transactions": list }));
+        };
+
+        let transaction = open.get(id).with_context(|| unknown_txn(id))?;
+        let with_diff = args["diff"].as_bool().unwrap_or(false);
+        let files: Vec<Value> = transaction
+            .files
+            .iter()
+            .map(|(path, file)| {
+                let mut entry = json!({
+                    "path": path.display().to_string(),
+                    "action": action(file),
+                });
+                if with_diff {
+                    let before = file.original.as_deref().map(String::from_utf8_lossy).unwrap_or_default();
+                    let after = file.content.as_deref().unwrap_or_default();
+                    let name = path.display().to_string();
+                    entry["diff"] = json!(similar::TextDiff::from_lines(before.as_ref(), after)
+                        .unified_diff()
+                        .header(&name, &name)
+                        .to_string());
+                }
+                entry
+            })
+            .collect();
+
+        Ok(json!({
+            "txn": id,
+            "description": transaction.description,
+            "created": transaction.created.to_rfc3339(),
+            "files": files,
+        }))
+    }
+
+    pub async fn commit(&self, args: Value) -> Result<Value> {
+        let id = args["txn"].as_str().context("Missing 'txn' parameter")?;
+        // Taken out while committing so nothing is staged into it halfway through
+        let transaction = self
+            .transactions
+            .open
+            .lock()
+            .unwrap()
+            .remove(id)
+            .with_context(|| unknown_txn(id))?;
+
+        let mut commit = Commit {
+            transactions: &self.transactions,
+            id: id.to_string(),
+            transaction: Some(transaction),
+            applied: Vec::new(),
+            created_dirs: Vec::new(),
+        };
+        let result = commit.apply(&args).await;
+        if result.is_ok() {
+            commit.transaction = None;
+        }
+        result
+    }
+
+    pub async fn abort(&self, args: Value) -> Result<Value> {
+        let id = args["txn"].as_str().context("Missing 'txn' parameter")?;
+        let transaction = self
+            .transactions
+            .open
+            .lock()
+            .unwrap()
+            .remove(id)
+            .with_context(|| unknown_txn(id))?;
+
+        Ok(json!({
+            "aborted": true,
+            "txn": id,
+            "discarded_files": transaction.files.len(),
+        }))
+    }
+}
+
+#[async_trait]
+impl ToolModule for TxnModule {
+    fn name(&self) -> &str {
+        "txn"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "txn_begin" => self.begin(args).await,
+            "txn_status" => self.status(args).await,
+            "txn_commit" => self.commit(args).await,
+            "txn_abort" => self.abort(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        // Nothing staged has been written, so dropping open transactions leaves the files as they were
+        let open = self.transactions.open.lock().unwrap();
+        if !open.is_empty() {
+            tracing::warn!("Discarding {} uncommitted transaction(s): {}", open.len(), open.keys().cloned().collect::<Vec<_>>().join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// A transaction taken out of the open ones while it commits. Dropped before the
+/// commit completes, including when the call is timed out or cancelled during its
+/// checks, it puts the files it applied back and reopens the transaction.
+struct Commit<'a> {
+    transactions: &'a Transactions,
+    id: String,
+    // None once committed
+    transaction: Option<Transaction>,
+    // Files already changed on disk, and directories created for them
+    applied: Vec<PathBuf>,
+    created_dirs: Vec<PathBuf>,
+}
+
+impl Commit<'_> {
+    async fn apply(&mut self, args: &Value) -> Result<Value> {
+        let transaction = self.transaction.as_ref().expect("not yet committed");
+
+        // Refuse to overwrite edits made outside the transaction
+        let conflicts: Vec<String> = transaction
+            .files
+            .iter()
+            .filter(|(path, file)| std::fs::read(path).ok() != file.original)
+            .map(|(path, _)| path.display().to_string())
+            .collect();
+        if !conflicts.is_empty() {
+            anyhow::bail!(
+                "Nothing was committed: these files changed on disk after the transaction staged them: {}",
+                conflicts.join(", ")
+            );
+        }
+
+        // Write everything next to its target first, so a failure here leaves the files untouched
+        let mut prepared = Vec::new();
+        for (path, file) in &transaction.files {
+            let Some(content) = &file.content else { continue };
+            match prepare(path, content, &mut self.created_dirs) {
+                Ok(temp) => prepared.push(temp),
+                Err(e) => {
+                    for temp in &prepared {
+                        let _ = std::fs::remove_file(temp);
+                    }
+                    remove_dirs(&std::mem::take(&mut self.created_dirs));
+                    anyhow::bail!("Nothing was committed: {:#}", e);
+                }
+            }
+        }
+
+        // Then swap them in; renames within a directory replace each file in one step
+        let mut temps = prepared.iter();
+        let mut failure = None;
+        for (path, file) in &transaction.files {
+            let result = match &file.content {
+                Some(_) => {
+                    let temp = temps.next().expect("one prepared file per write");
+                    std::fs::rename(temp, path)
+                }
+                None if file.original.is_some() => std::fs::remove_file(path),
+                None => Ok(()),
+            };
+            match result {
+                Ok(()) => self.applied.push(path.clone()),
+                Err(e) => {
+                    failure = Some(anyhow::Error::new(e).context(format!("Failed to update {}", path.display())));
+                    break;
+                }
+            }
+        }
+        if let Some(e) = failure {
+            for temp in temps {
+                let _ = std::fs::remove_file(temp);
+            }
+            return Err(self.roll_back(e));
+        }
+
+        let mut checks = Vec::new();
+        if let Some(path) = args["diagnostics"].as_str() {
+            match run_diagnostics(path).await {
+                Ok(summary) => checks.push(summary),
+                Err(e) => return Err(self.roll_back(e)),
+            }
+        }
+        if let Some(command) = args["command"].as_str() {
+            match run_check_command(command, args["cwd"].as_str()).await {
+                Ok(summary) => checks.push(summary),
+                Err(e) => return Err(self.roll_back(e)),
+            }
+        }
+
+        let transaction = self.transaction.as_ref().expect("not yet committed");
+        let files: Vec<Value> = transaction
+            .files
+            .iter()
+            .map(|(path, file)| json!({ "path": path.display().to_string(), "action": action(file) }))
+            .collect();
+        Ok(json!({
+            "committed": true,
+            "files": files,
+            "checks": checks,
+        }))
+    }
+
+    /// Put the applied files back, returning the error that caused it
+    fn roll_back(&mut self, cause: anyhow::Error) -> anyhow::Error {
+        let applied = std::mem::take(&mut self.applied);
+        let created_dirs = std::mem::take(&mut self.created_dirs);
+        let files = &self.transaction.as_ref().expect("not yet committed").files;
+        rollback(files, &applied, &created_dirs, cause)
+    }
+}
+
+impl Drop for Commit<'_> {
+    fn drop(&mut self) {
+        if self.transaction.is_none() {
+            return;
+        }
+        if !self.applied.is_empty() {
+            let e = self.roll_back(anyhow::anyhow!("the call ended before its checks finished"));
+            tracing::warn!("Transaction {}: {:#}", self.id, e);
+        }
+        if let Some(transaction) = self.transaction.take() {
+            self.transactions.open.lock().unwrap().insert(self.id.clone(), transaction);
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn unknown_txn(id: &str) -> String {
+    format!("Unknown transaction '{}'; it may have been committed or aborted (see txn_status)", id)
+}
+
+fn action(file: &StagedFile) -> &'static str {
+    match (&file.original, &file.content) {
+        (None, Some(_)) => "create",
+        (Some(_), Some(_)) => "write",
+        (Some(_), None) => "delete",
+        (None, None) => "none",
+    }
+}
+
+/// Write `content` to a temporary file beside `path`, with the permissions of the existing file.
+/// Missing parent directories are created and added to `created_dirs`.
+fn prepare(path: &Path, content: &str, created_dirs: &mut Vec<PathBuf>) -> Result<PathBuf> {
+    let parent = path.parent().context("File has no parent directory")?;
+    let missing: Vec<PathBuf> = parent
+        .ancestors()
+        .take_while(|dir| !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    created_dirs.extend(missing.into_iter().rev());
+
+    let name = path.file_name().context("File has no name")?.to_string_lossy();
+    let temp = parent.join(format!(".{}.txn-{}", name, uuid::Uuid::new_v4().simple()));
+    std::fs::write(&temp, content).with_context(|| format!("Failed to stage {}", path.display()))?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        let _ = std::fs::set_permissions(&temp, metadata.permissions());
+    }
+    Ok(temp)
+}
+
+/// Put every applied file back the way it was before the commit, returning
+/// the error that caused it
+fn rollback(files: &BTreeMap<PathBuf, StagedFile>, applied: &[PathBuf], created_dirs: &[PathBuf], cause: anyhow::Error) -> anyhow::Error {
+    let mut failed = Vec::new();
+    for path in applied {
+        let Some(file) = files.get(path) else { continue };
+        let restored = match &file.original {
+            Some(bytes) => std::fs::write(path, bytes),
+            None => std::fs::remove_file(path),
+        };
+        if let Err(e) = restored {
+            failed.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    remove_dirs(created_dirs);
+
+    if failed.is_empty() {
+        anyhow::anyhow!("The commit was rolled back: {:#}", cause)
+    } else {
+        anyhow::anyhow!(
+            "The commit failed ({:#}) and rolling it back failed too; these files may be left with the new content: {}",
+            cause,
+            failed.join("; ")
+        )
+    }
+}
+
+/// Remove directories created for the commit, deepest first, if they are empty again
+fn remove_dirs(created_dirs: &[PathBuf]) {
+    for dir in created_dirs.iter().rev() {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+/// Fails when diagnostics_get reports any errors for `path`
+async fn run_diagnostics(path: &str) -> Result<Value> {
+    let report = DiagnosticsModule::new().get(json!({ "path": path })).await?;
+    let diagnostics = report["diagnostics"].as_array().cloned().unwrap_or_default();
+    let errors: Vec<&Value> = diagnostics
+        .iter()
+        .filter(|d| d["level"].as_str().is_some_and(|level| level.starts_with("error") || level == "fatal"))
+        .collect();
+
+    if !errors.is_empty() {
+        let listed: Vec<String> = errors
+            .iter()
+            .take(MAX_REPORTED_DIAGNOSTICS)
+            .map(|d| {
+                format!(
+                    "{}:{}: {}",
+                    d["file"].as_str().unwrap_or("?"),
+                    d["line"].as_u64().map(|l| l.to_string()).unwrap_or_default(),
+                    d["message"].as_str().unwrap_or_default()
+                )
+            })
+            .collect();
+        anyhow::bail!(
+            "Diagnostics ({}) found {} error(s):\n{}",
+            report["tool"].as_str().unwrap_or("?"),
+            errors.len(),
+            listed.join("\n")
+        );
+    }
+
+    Ok(json!({
+        "check": "diagnostics",
+        "tool": report["tool"],
+        "warnings": diagnostics.len(),
+    }))
+}
+
+/// Fails when `command` exits non-zero, quoting the end of its output
+async fn run_check_command(command: &str, cwd: Option<&str>) -> Result<Value> {
+    let mut process = if cfg!(windows) {
+        let mut process = tokio::process::Command::new("cmd");
+        process.args(["/C", command]);
+        process
+    } else {
+        let mut process = tokio::process::Command::new("sh");
+        process.args(["-c", command]);
+        process
+    };
+    if let Some(cwd) = cwd {
+        process.current_dir(cwd);
+    }
+    process.kill_on_drop(true);
+
+    let output = telemetry::run_command_async(&mut process)
+        .await
+        .with_context(|| format!("Failed to run check command: {}", command))?;
+    if !output.status.success() {
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let count = text.chars().count();
+        let tail: String = text.chars().skip(count.saturating_sub(MAX_REPORTED_OUTPUT_CHARS)).collect();
+        anyhow::bail!("Check command '{}' failed ({}):\n{}", command, output.status, tail.trim());
+    }
+
+    Ok(json!({
+        "check": "command",
+        "command": command,
+        "exit_code": output.status.code(),
+    }))
+}
+
+#[cfg(all(test, feature = "filesystem", unix))]
+mod tests {
+    use crate::config::Config;
+    use crate::server::PolyMcp;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn a_commit_timed_out_in_its_checks_is_rolled_back() {
+        let dir = std::env::temp_dir().join(format!("poly-txn-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "before").unwrap();
+
+        let mut config = Config::default();
+        config.timeouts.tools.insert("txn_commit".to_string(), 1);
+        let server = PolyMcp::builder().config(config).build().unwrap();
+        let txn = server.call_tool("txn_begin", None).await.unwrap()["txn"].as_str().unwrap().to_string();
+        let write = json!({ "path": file.display().to_string(), "content": "after", "txn": txn });
+        server.call_tool("fs_write", Some(write)).await.unwrap();
+
+        let commit = json!({ "txn": txn, "command": "sleep 5" });
+        assert!(server.call_tool("txn_commit", Some(commit)).await.is_err());
+        // The aborted task rolls back as it is dropped
+        for _ in 0..50 {
+            if std::fs::read_to_string(&file).unwrap() == "before" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "before");
+        let status = server.call_tool("txn_status", Some(json!({ "txn": txn }))).await.unwrap();
+        assert_eq!(status["files"].as_array().unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
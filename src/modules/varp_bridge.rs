@@ -1,5 +1,7 @@
 use serde_json::{json, Value};
 use anyhow::{Result, Context};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
 use std::io::{BufRead, Write};
 use std::process::{Child, Command, Stdio};
 use std::sync::Mutex;
@@ -108,3 +110,18 @@ impl Drop for VarpModule {
         }
     }
 }
+
+#[async_trait]
+impl ToolModule for VarpModule {
+    fn name(&self) -> &str {
+        "varp"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        self.call_tool(tool, args).await
+    }
+}
@@ -0,0 +1,600 @@
+use serde_json::{json, Map, Value};
+use anyhow::{Result, Context as _};
+use async_trait::async_trait;
+use crate::registry::ToolModule;
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use rust_xlsxwriter::{Format, Formula, Workbook, Worksheet};
+use std::path::Path;
+
+const DEFAULT_MAX_ROWS: u64 = 1000;
+
+/// Excel's hard limits
+const MAX_ROWS: u32 = 1_048_576;
+const MAX_COLS: u32 = 16_384;
+
+pub struct XlsxModule;
+
+impl Default for XlsxModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XlsxModule {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn get_tools(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "name": "xlsx_read",
+                "description": "Read a spreadsheet (.xlsx, .xlsm, .xls, .xlsb, .ods). Lists the sheets with their dimensions and returns the cells of one sheet (or an A1-style range of it) as JSON rows. Dates are returned as ISO 8601 strings and empty cells as null.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the spreadsheet"
+                        },
+                        "sheet": {
+                            "type": "string",
+                            "description": "Sheet name (default: first sheet)"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Cell range such as 'A1:D20' or a single cell such as 'B3' (default: the sheet's used range)"
+                        },
+                        "header": {
+                            "type": "boolean",
+                            "description": "Treat the first row as column names and return the remaining rows as objects (default: false)"
+                        },
+                        "formulas": {
+                            "type": "boolean",
+                            "description": "Also return the formulas of cells that have one (default: false)"
+                        },
+                        "max_rows": {
+                            "type": "integer",
+                            "description": "Maximum data rows to return (default: 1000)"
+                        }
+                    },
+                    "required": ["path"]
+                }
+            }),
+            json!({
+                "name": "xlsx_write",
+                "description": "Write JSON rows to a sheet of an .xlsx workbook. Rows are arrays of cell values, or objects whose keys become a bold header row. Strings starting with '=' are written as formulas; their results are calculated when the file is opened in a spreadsheet application. Existing workbooks are rewritten keeping the values and formulas of other sheets, but not their formatting or charts.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the .xlsx file"
+                        },
+                        "sheet": {
+                            "type": "string",
+                            "description": "Sheet name (default: Sheet1)"
+                        },
+                        "rows": {
+                            "type": "array",
+                            "description": "Rows to write: arrays of values, or objects keyed by column name"
+                        },
+                        "columns": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Column order for object rows (default: keys in sorted order)"
+                        },
+                        "mode": {
+                            "type": "string",
+                            "enum": ["create", "replace_sheet", "append"],
+                            "description": "'create' starts a new workbook, 'replace_sheet' replaces or adds the sheet in an existing workbook, 'append' adds rows below the sheet's existing data (default: replace_sheet)"
+                        },
+                        "overwrite": {
+                            "type": "boolean",
+                            "description": "Allow 'create' to replace an existing file (default: false)"
+                        },
+                        "autofit": {
+                            "type": "boolean",
+                            "description": "Size columns to fit their contents (default: true)"
+                        }
+                    },
+                    "required": ["path", "rows"]
+                }
+            }),
+        ]
+    }
+
+    pub async fn read(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        let header = args["header"].as_bool().unwrap_or(false);
+        let with_formulas = args["formulas"].as_bool().unwrap_or(false);
+        let max_rows = args["max_rows"].as_u64().unwrap_or(DEFAULT_MAX_ROWS) as usize;
+
+        let mut workbook = open_workbook_auto(path)
+            .with_context(|| format!("Failed to open spreadsheet: {}", path))?;
+
+        let names = workbook.sheet_names();
+        let mut sheets = Vec::with_capacity(names.len());
+        for name in &names {
+            let range = workbook
+                .worksheet_range(name)
+                .with_context(|| format!("Failed to read sheet '{}'", name))?;
+            sheets.push(json!({
+                "name": name,
+                "dimensions": range_name(&range),
+                "rows": range.height(),
+                "columns": range.width(),
+            }));
+        }
+
+        let sheet = match args["sheet"].as_str() {
+            Some(sheet) => {
+                if !names.iter().any(|n| n == sheet) {
+                    anyhow::bail!("Sheet '{}' not found. Available sheets: {}", sheet, names.join(", "));
+                }
+                sheet.to_string()
+            }
+            None => names.first().cloned().context("Workbook has no sheets")?,
+        };
+
+        let used = workbook.worksheet_range(&sheet)?;
+        let range = match args["range"].as_str() {
+            Some(spec) => {
+                let (start, end) = parse_range(spec)?;
+                used.range(start, end)
+            }
+            None => used,
+        };
+
+        let origin = range.start().unwrap_or((0, 0));
+        let mut rows = range.rows();
+        let mut result = json!({
+            "path": path,
+            "sheets": sheets,
+            "sheet": sheet,
+            "range": range_name(&range),
+        });
+
+        let data: Vec<Value> = if header {
+            let columns: Vec<String> = rows
+                .next()
+                .map(|row| {
+                    row.iter()
+                        .enumerate()
+                        .map(|(i, cell)| match cell {
+                            Data::Empty => format!("column_{}", i + 1),
+                            other => other.to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let objects = rows
+                .by_ref()
+                .take(max_rows)
+                .map(|row| {
+                    let object: Map<String, Value> = columns
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().map(cell_to_json))
+                        .collect();
+                    Value::Object(object)
+                })
+                .collect();
+            result["columns"] = json!(columns);
+            objects
+        } else {
+            rows.by_ref()
+                .take(max_rows)
+                .map(|row| Value::Array(row.iter().map(cell_to_json).collect()))
+                .collect()
+        };
+
+        let remaining = rows.count();
+        result["row_count"] = json!(data.len());
+        result["rows"] = Value::Array(data);
+        if remaining > 0 {
+            result["truncated"] = json!(true);
+            result["remaining_rows"] = json!(remaining);
+        }
+
+        if with_formulas {
+            let formulas = workbook
+                .worksheet_formula(&sheet)
+                .with_context(|| format!("Failed to read formulas of sheet '{}'", sheet))?;
+            let (top, left) = origin;
+            let (bottom, right) = range.end().unwrap_or(origin);
+            let list: Vec<Value> = formulas
+                .used_cells()
+                .filter(|(_, _, formula)| !formula.is_empty())
+                .map(|(r, c, formula)| {
+                    let (start_row, start_col) = formulas.start().unwrap_or((0, 0));
+                    (start_row + r as u32, start_col + c as u32, formula)
+                })
+                .filter(|(r, c, _)| (top..=bottom).contains(r) && (left..=right).contains(c))
+                .map(|(r, c, formula)| json!({ "cell": cell_name(r, c), "formula": format!("={}", formula) }))
+                .collect();
+            result["formulas"] = json!(list);
+        }
+
+        Ok(result)
+    }
+
+    pub async fn write(&self, args: Value) -> Result<Value> {
+        let path = args["path"].as_str().context("Missing 'path' parameter")?;
+        let sheet = args["sheet"].as_str().unwrap_or("Sheet1");
+        let rows = args["rows"].as_array().context("Missing 'rows' parameter")?;
+        let mode = args["mode"].as_str().unwrap_or("replace_sheet");
+        let overwrite = args["overwrite"].as_bool().unwrap_or(false);
+        let autofit = args["autofit"].as_bool().unwrap_or(true);
+        let columns: Option<Vec<String>> = args["columns"].as_array().map(|cols| {
+            cols.iter()
+                .map(|c| c.as_str().map(String::from).unwrap_or_else(|| c.to_string()))
+                .collect()
+        });
+
+        let is_xlsx = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("xlsx"));
+        if !is_xlsx {
+            anyhow::bail!("xlsx_write only writes .xlsx files: {}", path);
+        }
+
+        let exists = Path::new(path).exists();
+        let existing = match mode {
+            "create" => {
+                if exists && !overwrite {
+                    anyhow::bail!("File already exists: {}. Pass overwrite: true or use mode 'replace_sheet'", path);
+                }
+                Vec::new()
+            }
+            "replace_sheet" | "append" if exists => load_sheets(path)?,
+            "replace_sheet" | "append" => Vec::new(),
+            _ => anyhow::bail!("Unsupported mode: {}. Use create, replace_sheet or append", mode),
+        };
+
+        let mut workbook = Workbook::new();
+        let bold = Format::new().set_bold();
+        let date_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+        let mut preserved = Vec::new();
+        let mut written = None;
+
+        let target_exists = existing.iter().any(|s| s.name == sheet);
+        for stored in &existing {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(&stored.name)?;
+            if stored.name == sheet && mode == "replace_sheet" {
+                written = Some(write_rows(worksheet, rows, columns.as_deref(), None, &bold)?);
+            } else {
+                copy_sheet(worksheet, stored, &date_format)?;
+                if stored.name == sheet {
+                    written = Some(write_rows(worksheet, rows, columns.as_deref(), Some(stored), &bold)?);
+                } else {
+                    preserved.push(stored.name.clone());
+                }
+            }
+            if autofit {
+                worksheet.autofit();
+            }
+        }
+        if !target_exists {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(sheet)?;
+            written = Some(write_rows(worksheet, rows, columns.as_deref(), None, &bold)?);
+            if autofit {
+                worksheet.autofit();
+            }
+        }
+
+        workbook
+            .save(path)
+            .with_context(|| format!("Failed to save workbook: {}", path))?;
+
+        let written = written.unwrap_or_default();
+        let mut result = json!({
+            "success": true,
+            "path": path,
+            "sheet": sheet,
+            "mode": mode,
+            "rows_written": written.rows,
+            "range": written.range,
+        });
+        if let Some(header) = written.header {
+            result["columns"] = json!(header);
+        }
+        if !existing.is_empty() {
+            result["preserved_sheets"] = json!(preserved);
+            result["note"] = json!("The workbook was rewritten: cell values and formulas were kept, formatting, charts and other objects were not");
+        }
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl ToolModule for XlsxModule {
+    fn name(&self) -> &str {
+        "xlsx"
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.get_tools()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match tool {
+            "xlsx_read" => self.read(args).await,
+            "xlsx_write" => self.write(args).await,
+            _ => Err(anyhow::anyhow!("Unknown tool: {}", tool)),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// A sheet loaded from an existing workbook, kept so it can be rewritten
+struct StoredSheet {
+    name: String,
+    values: Range<Data>,
+    formulas: Range<String>,
+}
+
+impl StoredSheet {
+    /// Row index just past the last used row
+    fn next_row(&self) -> u32 {
+        let values_end = self.values.end().map(|(r, _)| r + 1).unwrap_or(0);
+        let formulas_end = self.formulas.end().map(|(r, _)| r + 1).unwrap_or(0);
+        values_end.max(formulas_end)
+    }
+
+    /// Strings in the first row, used as the header when appending objects
+    fn header(&self) -> Vec<String> {
+        let Some((top, left)) = self.values.start() else {
+            return Vec::new();
+        };
+        if top != 0 {
+            return Vec::new();
+        }
+        let (_, right) = self.values.end().unwrap_or((0, left));
+        let mut header: Vec<String> = (0..=right)
+            .map(|c| match self.values.get_value((0, c)) {
+                Some(Data::String(s)) => s.clone(),
+                _ => String::new(),
+            })
+            .collect();
+        while header.last().is_some_and(|h| h.is_empty()) {
+            header.pop();
+        }
+        header
+    }
+}
+
+#[derive(Default)]
+struct Written {
+    rows: usize,
+    range: Option<String>,
+    header: Option<Vec<String>>,
+}
+
+fn load_sheets(path: &str) -> Result<Vec<StoredSheet>> {
+    let mut workbook = open_workbook_auto(path)
+        .with_context(|| format!("Failed to open existing workbook: {}", path))?;
+    let mut sheets = Vec::new();
+    for name in workbook.sheet_names() {
+        let values = workbook
+            .worksheet_range(&name)
+            .with_context(|| format!("Failed to read sheet '{}'", name))?;
+        let formulas = workbook
+            .worksheet_formula(&name)
+            .with_context(|| format!("Failed to read formulas of sheet '{}'", name))?;
+        sheets.push(StoredSheet { name, values, formulas });
+    }
+    Ok(sheets)
+}
+
+fn copy_sheet(worksheet: &mut Worksheet, sheet: &StoredSheet, date_format: &Format) -> Result<()> {
+    let (row0, col0) = sheet.values.start().unwrap_or((0, 0));
+    for (r, c, cell) in sheet.values.used_cells() {
+        let (row, col) = (row0 + r as u32, (col0 + c as u32) as u16);
+        match cell {
+            Data::Int(i) => worksheet.write_number(row, col, *i as f64)?,
+            Data::Float(f) => worksheet.write_number(row, col, *f)?,
+            Data::Bool(b) => worksheet.write_boolean(row, col, *b)?,
+            Data::String(s) => worksheet.write_string(row, col, s)?,
+            Data::DateTime(dt) => worksheet.write_number_with_format(row, col, dt.as_f64(), date_format)?,
+            Data::DateTimeIso(s) | Data::DurationIso(s) => worksheet.write_string(row, col, s)?,
+            Data::Error(e) => worksheet.write_string(row, col, e.to_string())?,
+            Data::Empty => continue,
+        };
+    }
+
+    // Formulas are written after values so they take precedence
+    let (row0, col0) = sheet.formulas.start().unwrap_or((0, 0));
+    for (r, c, formula) in sheet.formulas.used_cells() {
+        if formula.is_empty() {
+            continue;
+        }
+        let (row, col) = (row0 + r as u32, (col0 + c as u32) as u16);
+        worksheet.write_formula(row, col, Formula::new(format!("={}", formula)))?;
+    }
+    Ok(())
+}
+
+fn write_rows(
+    worksheet: &mut Worksheet,
+    rows: &[Value],
+    columns: Option<&[String]>,
+    existing: Option<&StoredSheet>,
+    bold: &Format,
+) -> Result<Written> {
+    let mut row = existing.map(StoredSheet::next_row).unwrap_or(0);
+    let first_row = row;
+    let objects = rows.iter().any(Value::is_object);
+    if objects && rows.iter().any(|r| !r.is_object()) {
+        anyhow::bail!("Rows must be all arrays or all objects");
+    }
+
+    let mut header = None;
+    if objects {
+        let mut names: Vec<String> = match (existing.map(StoredSheet::header), columns) {
+            (Some(existing), _) if !existing.is_empty() => existing,
+            (_, Some(columns)) => columns.to_vec(),
+            _ => Vec::new(),
+        };
+        let existing_width = if row > 0 { names.len() } else { 0 };
+        for object in rows.iter().filter_map(Value::as_object) {
+            for key in object.keys() {
+                if columns.is_none() && !names.contains(key) {
+                    names.push(key.clone());
+                }
+            }
+        }
+
+        // Write the header row for a new sheet, or the new columns of an existing one
+        if row == 0 || existing_width > 0 {
+            for (col, name) in names.iter().enumerate().skip(existing_width) {
+                worksheet.write_string_with_format(0, col_num(col)?, name, bold)?;
+            }
+            row = row.max(1);
+        }
+
+        for object in rows.iter().filter_map(Value::as_object) {
+            for (col, name) in names.iter().enumerate() {
+                if let Some(value) = object.get(name) {
+                    write_value(worksheet, row, col_num(col)?, value)?;
+                }
+            }
+            row = next_row(row)?;
+        }
+        header = Some(names);
+    } else {
+        for values in rows {
+            let values = values.as_array().context("Rows must be arrays or objects")?;
+            for (col, value) in values.iter().enumerate() {
+                write_value(worksheet, row, col_num(col)?, value)?;
+            }
+            row = next_row(row)?;
+        }
+    }
+
+    let width = match &header {
+        Some(names) => names.len(),
+        None => rows.iter().filter_map(Value::as_array).map(Vec::len).max().unwrap_or(0),
+    };
+    let range = (row > first_row && width > 0)
+        .then(|| format!("{}:{}", cell_name(first_row, 0), cell_name(row - 1, width as u32 - 1)));
+
+    Ok(Written { rows: rows.len(), range, header })
+}
+
+fn write_value(worksheet: &mut Worksheet, row: u32, col: u16, value: &Value) -> Result<()> {
+    match value {
+        Value::Null => {}
+        Value::Bool(b) => {
+            worksheet.write_boolean(row, col, *b)?;
+        }
+        Value::Number(n) => {
+            let number = n.as_f64().context("Number out of range")?;
+            worksheet.write_number(row, col, number)?;
+        }
+        Value::String(s) if s.starts_with('=') && s.len() > 1 => {
+            worksheet.write_formula(row, col, Formula::new(s))?;
+        }
+        Value::String(s) => {
+            worksheet.write_string(row, col, s)?;
+        }
+        other => {
+            worksheet.write_string(row, col, other.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn next_row(row: u32) -> Result<u32> {
+    if row + 1 >= MAX_ROWS {
+        anyhow::bail!("Too many rows: worksheets hold at most {} rows", MAX_ROWS);
+    }
+    Ok(row + 1)
+}
+
+fn col_num(col: usize) -> Result<u16> {
+    if col as u32 >= MAX_COLS {
+        anyhow::bail!("Too many columns: worksheets hold at most {} columns", MAX_COLS);
+    }
+    Ok(col as u16)
+}
+
+fn cell_to_json(cell: &Data) -> Value {
+    match cell {
+        Data::Int(i) => json!(i),
+        // Excel stores every number as a double; report whole ones as integers
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 9.0e15 => json!(*f as i64),
+        Data::Float(f) => json!(f),
+        Data::String(s) => json!(s),
+        Data::Bool(b) => json!(b),
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(datetime) if dt.is_datetime() => json!(datetime.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            _ => json!(dt.as_f64()),
+        },
+        Data::DateTimeIso(s) | Data::DurationIso(s) => json!(s),
+        Data::Error(e) => json!(e.to_string()),
+        Data::Empty => Value::Null,
+    }
+}
+
+/// Zero-based (row, column) to an A1-style reference
+fn cell_name(row: u32, col: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = col + 1;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        letters.push(b'A' + rem as u8);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    format!("{}{}", String::from_utf8_lossy(&letters), row + 1)
+}
+
+fn range_name<T: calamine::CellType>(range: &Range<T>) -> Option<String> {
+    let (start, end) = (range.start()?, range.end()?);
+    Some(format!("{}:{}", cell_name(start.0, start.1), cell_name(end.0, end.1)))
+}
+
+/// Parse an A1-style reference into a zero-based (row, column)
+fn parse_cell(reference: &str) -> Result<(u32, u32)> {
+    let reference = reference.trim().replace('$', "");
+    let split = reference
+        .find(|c: char| c.is_ascii_digit())
+        .with_context(|| format!("Invalid cell reference: {}", reference))?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        anyhow::bail!("Invalid cell reference: {}", reference);
+    }
+
+    let col = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0u32, |acc, b| acc.saturating_mul(26).saturating_add((b - b'A' + 1) as u32));
+    let row: u32 = digits
+        .parse()
+        .with_context(|| format!("Invalid cell reference: {}", reference))?;
+    if row == 0 || row > MAX_ROWS || col > MAX_COLS {
+        anyhow::bail!("Cell reference out of range: {}", reference);
+    }
+    Ok((row - 1, col - 1))
+}
+
+fn parse_range(spec: &str) -> Result<((u32, u32), (u32, u32))> {
+    match spec.split_once(':') {
+        Some((start, end)) => {
+            let (start, end) = (parse_cell(start)?, parse_cell(end)?);
+            Ok((
+                (start.0.min(end.0), start.1.min(end.1)),
+                (start.0.max(end.0), start.1.max(end.1)),
+            ))
+        }
+        None => {
+            let cell = parse_cell(spec)?;
+            Ok((cell, cell))
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// Tool names as clients see them: a shared prefix and per-tool renames from `[server]`
+
+use crate::config::ServerConfig;
+use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+/// Maps the built-in names tools are registered under to the names exposed in
+/// tools/list and accepted by tools/call, so several servers can sit behind one
+/// MCP proxy without their tool names colliding. Everything inside the server
+/// (policy patterns, timeouts, audit entries) keeps using the built-in names.
+#[derive(Debug, Default)]
+pub struct ToolNames {
+    prefix: String,
+    // Built-in name to exposed name, from `[server.tool_names]`
+    renames: BTreeMap<String, String>,
+    // Exposed name to built-in name, for the renamed tools only
+    reverse: HashMap<String, String>,
+}
+
+impl ToolNames {
+    pub fn new(config: &ServerConfig) -> Result<Self> {
+        if !config.tool_prefix.chars().all(valid_char) {
+            anyhow::bail!(
+                "[server] tool_prefix '{}' may only contain letters, digits, '_', '-' and '.'",
+                config.tool_prefix
+            );
+        }
+        let mut reverse = HashMap::new();
+        for (tool, exposed) in &config.tool_names {
+            if exposed.is_empty() || !exposed.chars().all(valid_char) {
+                anyhow::bail!(
+                    "[server.tool_names] {} = '{}': names may only contain letters, digits, '_', '-' and '.'",
+                    tool,
+                    exposed
+                );
+            }
+            if let Some(other) = reverse.insert(exposed.clone(), tool.clone()) {
+                anyhow::bail!("[server.tool_names] maps both {} and {} to '{}'", other, tool, exposed);
+            }
+        }
+        Ok(Self {
+            prefix: config.tool_prefix.clone(),
+            renames: config.tool_names.clone(),
+            reverse,
+        })
+    }
+
+    /// The name clients see for `tool`: its rename if it has one, otherwise the
+    /// built-in name behind the prefix
+    pub fn expose<'a>(&self, tool: &'a str) -> Cow<'a, str> {
+        match self.renames.get(tool) {
+            Some(exposed) => Cow::Owned(exposed.clone()),
+            None if self.prefix.is_empty() => Cow::Borrowed(tool),
+            None => Cow::Owned(format!("{}{}", self.prefix, tool)),
+        }
+    }
+
+    /// The built-in name behind a name a client sent, or `None` if no tool is
+    /// exposed under it. Built-in names of renamed or prefixed tools are not accepted.
+    pub fn resolve<'a>(&self, exposed: &'a str) -> Option<Cow<'a, str>> {
+        if let Some(tool) = self.reverse.get(exposed) {
+            return Some(Cow::Owned(tool.clone()));
+        }
+        let tool = exposed.strip_prefix(self.prefix.as_str())?;
+        (!self.renames.contains_key(tool)).then_some(Cow::Borrowed(tool))
+    }
+
+    /// Check the mapping against the tools the server actually has: every renamed
+    /// tool must exist, and no two tools may end up with the same exposed name
+    pub fn check<'a>(&self, tools: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let tools: Vec<&str> = tools.into_iter().collect();
+        for tool in self.renames.keys() {
+            if !tools.contains(&tool.as_str()) {
+                anyhow::bail!("[server.tool_names] renames unknown tool '{}'", tool);
+            }
+        }
+        let mut seen: HashMap<Cow<str>, &str> = HashMap::new();
+        for tool in tools {
+            if let Some(other) = seen.insert(self.expose(tool), tool) {
+                anyhow::bail!("Tools {} and {} would both be exposed as '{}'", other, tool, self.expose(tool));
+            }
+        }
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn valid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
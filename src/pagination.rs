@@ -0,0 +1,97 @@
+// Server-side pages of large tool results, fetched with a cursor
+
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Results are dropped this long after they were computed
+const TTL: Duration = Duration::from_secs(600);
+
+/// At most this many results are kept; the oldest goes first
+const MAX_RESULTS: usize = 16;
+
+struct Stored {
+    items: Vec<Value>,
+    page_size: usize,
+    created: Instant,
+}
+
+/// Items of results too large for one response, kept so later pages do not have
+/// to recompute them (re-running `cargo check` or re-diffing the repository).
+/// Each module instance has its own, so cursors do not cross sessions.
+#[derive(Default)]
+pub struct Pages {
+    results: Mutex<HashMap<String, Stored>>,
+}
+
+impl Pages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `items` and return the first `page_size` of them, with the cursor of the
+    /// next page if there are more
+    pub fn first(&self, items: Vec<Value>, page_size: usize) -> (Vec<Value>, Option<String>) {
+        let page_size = page_size.max(1);
+        if items.len() <= page_size {
+            return (items, None);
+        }
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let page = items[..page_size].to_vec();
+        let mut results = self.results.lock().unwrap();
+        prune(&mut results);
+        results.insert(
+            id.clone(),
+            Stored {
+                items,
+                page_size,
+                created: Instant::now(),
+            },
+        );
+        (page, Some(make_cursor(&id, page_size)))
+    }
+
+    /// The page a cursor from [`Pages::first`] or an earlier page points at, and
+    /// the cursor after it
+    pub fn next(&self, cursor: &str) -> Result<(Vec<Value>, Option<String>)> {
+        let (id, offset) = cursor
+            .rsplit_once(':')
+            .and_then(|(id, offset)| Some((id, offset.parse::<usize>().ok()?)))
+            .context("Invalid cursor")?;
+
+        let mut results = self.results.lock().unwrap();
+        results.retain(|_, stored| stored.created.elapsed() < TTL);
+        let stored = results
+            .get(id)
+            .context("Cursor expired; request the result again without a cursor")?;
+        let end = stored.items.len().min(offset.saturating_add(stored.page_size));
+        let page = stored.items.get(offset..end).context("Invalid cursor")?.to_vec();
+        // Kept after the last page too, in case the client retries it
+        let next = (end < stored.items.len()).then(|| make_cursor(id, end));
+        Ok((page, next))
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn make_cursor(id: &str, offset: usize) -> String {
+    format!("{}:{}", id, offset)
+}
+
+/// Drop expired results and make room for one more
+fn prune(results: &mut HashMap<String, Stored>) {
+    results.retain(|_, stored| stored.created.elapsed() < TTL);
+    while results.len() >= MAX_RESULTS {
+        let oldest = results
+            .iter()
+            .min_by_key(|(_, stored)| stored.created)
+            .map(|(id, _)| id.clone());
+        match oldest {
+            Some(id) => results.remove(&id),
+            None => break,
+        };
+    }
+}
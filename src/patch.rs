@@ -0,0 +1,282 @@
+// Unified diffs applied to text by content rather than line numbers, for text_patch and fs_edit
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Text with a patch applied, and what became of each hunk
+pub struct Patched {
+    pub text: String,
+    pub applied: usize,
+    pub already_applied: usize,
+    pub failed: usize,
+    /// Per hunk: "applied" with the line it landed on, "already_applied", or "failed"
+    pub hunks: Vec<Value>,
+}
+
+/// Apply `hunks` to `text`. Each hunk is found by its context and removed lines, searching
+/// outward from its stated position up to `max_offset` lines away; `fuzzy` also lets
+/// lines match that differ only in whitespace. Failed hunks are skipped.
+pub fn apply(text: &str, hunks: &[Hunk], max_offset: Option<usize>, fuzzy: bool) -> Patched {
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(str::to_string).collect();
+
+    // Lines added or removed by earlier hunks shift where later ones should land
+    let mut delta: isize = 0;
+    // Hunks must not land inside text produced by an earlier hunk
+    let mut min_pos = 0;
+    let mut report = Vec::new();
+    let (mut applied, mut already_applied, mut failed) = (0, 0, 0);
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old: Vec<&str> = hunk.old_lines().map(|line| line.text.as_str()).collect();
+        let new: Vec<&str> = hunk.new_lines().map(|line| line.text.as_str()).collect();
+
+        let stated = match hunk.old_start {
+            Some(start) if old.is_empty() => start,
+            Some(start) => start.saturating_sub(1),
+            None => min_pos,
+        };
+        let expected = (stated as isize + delta).max(0) as usize;
+
+        if let Some(pos) = find_block(&lines, &old, expected, min_pos, max_offset, fuzzy) {
+            let replacement = hunk.render_new(&lines[pos..pos + old.len()], eol);
+            let inserted = replacement.len();
+            lines.splice(pos..pos + old.len(), replacement);
+
+            delta += inserted as isize - old.len() as isize;
+            min_pos = pos + inserted;
+            applied += 1;
+            report.push(json!({
+                "hunk": index + 1,
+                "status": "applied",
+                "line": pos + 1,
+                "offset": pos as isize - expected as isize
+            }));
+        } else if !new.is_empty() && old != new && find_block(&lines, &new, expected, min_pos, max_offset, fuzzy).is_some() {
+            already_applied += 1;
+            report.push(json!({ "hunk": index + 1, "status": "already_applied" }));
+        } else {
+            failed += 1;
+            report.push(json!({
+                "hunk": index + 1,
+                "status": "failed",
+                "expected_line": expected + 1,
+                "reason": "context lines not found in text"
+            }));
+        }
+    }
+
+    Patched {
+        text: lines.concat(),
+        applied,
+        already_applied,
+        failed,
+        hunks: report,
+    }
+}
+
+/// Whether line `have` (with its line ending) is `want`; `fuzzy` ignores differences in whitespace
+pub fn same_line(have: &str, want: &str, fuzzy: bool) -> bool {
+    match fuzzy {
+        true => have.split_whitespace().eq(want.split_whitespace()),
+        false => strip_eol(have) == want,
+    }
+}
+
+pub fn strip_eol(line: &str) -> &str {
+    line.strip_suffix('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)).unwrap_or(line)
+}
+
+// ── Patch parsing ───────────────────────────────────────────────────────
+
+struct PatchLine {
+    kind: char,
+    text: String,
+    // Followed by "\ No newline at end of file"
+    no_newline: bool,
+}
+
+/// One `@@` hunk of a unified diff
+pub struct Hunk {
+    // 1-based start line in the old text; None for a bare "@@" header
+    old_start: Option<usize>,
+    new_start: Option<usize>,
+    lines: Vec<PatchLine>,
+}
+
+impl Hunk {
+    fn old_lines(&self) -> impl Iterator<Item = &PatchLine> {
+        self.lines.iter().filter(|line| line.kind != '+')
+    }
+
+    fn new_lines(&self) -> impl Iterator<Item = &PatchLine> {
+        self.lines.iter().filter(|line| line.kind != '-')
+    }
+
+    /// Swap what the hunk removes and adds, to undo it
+    pub fn reverse(&mut self) {
+        std::mem::swap(&mut self.old_start, &mut self.new_start);
+        for line in &mut self.lines {
+            line.kind = match line.kind {
+                '+' => '-',
+                '-' => '+',
+                other => other,
+            };
+        }
+    }
+
+    /// The replacement for `matched` (the old lines as found in the text). Context
+    /// lines are copied from the text so their original line endings survive.
+    fn render_new(&self, matched: &[String], eol: &str) -> Vec<String> {
+        let mut old = matched.iter();
+        let mut out = Vec::new();
+        for line in &self.lines {
+            match line.kind {
+                '-' => {
+                    old.next();
+                }
+                '+' => {
+                    let ending = if line.no_newline { "" } else { eol };
+                    out.push(format!("{}{}", line.text, ending));
+                }
+                _ => {
+                    if let Some(original) = old.next() {
+                        out.push(original.clone());
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The hunks of a unified diff for a single file. File headers are optional, and so
+/// are the line counts in `@@` headers.
+pub fn parse(patch: &str) -> Result<Vec<Hunk>> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut files = 0;
+    // Remaining old/new line counts from the hunk header, when it has them
+    let mut remaining: Option<(usize, usize)> = None;
+    let mut in_hunk = false;
+
+    for raw in patch.lines() {
+        if raw.starts_with("@@") {
+            let header = parse_hunk_header(raw);
+            hunks.push(Hunk {
+                old_start: header.map(|h| h.0),
+                new_start: header.map(|h| h.2),
+                lines: Vec::new(),
+            });
+            remaining = header.map(|h| (h.1, h.3));
+            in_hunk = true;
+            continue;
+        }
+
+        if let Some(last) = raw.strip_prefix('\\') {
+            if last.trim_start().starts_with("No newline") {
+                if let Some(line) = hunks.last_mut().and_then(|h| h.lines.last_mut()) {
+                    line.no_newline = true;
+                }
+            }
+            continue;
+        }
+
+        let exhausted = matches!(remaining, Some((0, 0)));
+        if !in_hunk || exhausted {
+            in_hunk = false;
+            if raw.starts_with("--- ") {
+                files += 1;
+            }
+            continue;
+        }
+
+        // Without counts, a file header ends the hunk
+        if remaining.is_none() && (raw.starts_with("--- ") || raw.starts_with("+++ ") || raw.starts_with("diff ")) {
+            in_hunk = false;
+            if raw.starts_with("--- ") {
+                files += 1;
+            }
+            continue;
+        }
+
+        // Editors often strip the single space from blank context lines
+        let (kind, text) = match raw.chars().next() {
+            Some(c @ (' ' | '-' | '+')) => (c, &raw[1..]),
+            None => (' ', ""),
+            Some(_) if remaining.is_none() => (' ', raw),
+            Some(_) => anyhow::bail!("Malformed hunk line: {:?}", raw),
+        };
+
+        if let Some((old, new)) = remaining.as_mut() {
+            match kind {
+                '-' => *old = old.saturating_sub(1),
+                '+' => *new = new.saturating_sub(1),
+                _ => {
+                    *old = old.saturating_sub(1);
+                    *new = new.saturating_sub(1);
+                }
+            }
+        }
+
+        if let Some(hunk) = hunks.last_mut() {
+            hunk.lines.push(PatchLine {
+                kind,
+                text: text.trim_end_matches('\r').to_string(),
+                no_newline: false,
+            });
+        }
+    }
+
+    if files > 1 {
+        anyhow::bail!("Patch touches {} files; only a single-file diff can be applied", files);
+    }
+
+    // Hunks without counts may pick up trailing blank lines from the end of the patch
+    for hunk in hunks.iter_mut().filter(|hunk| hunk.old_start.is_none()) {
+        while hunk.lines.last().is_some_and(|line| line.kind == ' ' && line.text.is_empty()) {
+            hunk.lines.pop();
+        }
+    }
+
+    Ok(hunks)
+}
+
+/// "@@ -12,3 +12,4 @@" → (12, 3, 12, 4); counts default to 1 when omitted
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize, usize)> {
+    let mut parts = header.trim_start_matches('@').split_whitespace();
+    let old = parts.next()?.strip_prefix('-')?;
+    let new = parts.next()?.strip_prefix('+')?;
+
+    let range = |s: &str| -> Option<(usize, usize)> {
+        match s.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((s.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (new_start, new_count) = range(new)?;
+    Some((old_start, old_count, new_start, new_count))
+}
+
+/// Position where `block` matches `lines` (ignoring line endings, and with `fuzzy`
+/// differences in whitespace), searching outward from `expected`
+pub fn find_block(lines: &[String], block: &[&str], expected: usize, min_pos: usize, max_offset: Option<usize>, fuzzy: bool) -> Option<usize> {
+    let last_start = lines.len().checked_sub(block.len())?;
+    let matches_at = |pos: usize| {
+        pos >= min_pos
+            && pos <= last_start
+            && block.iter().zip(&lines[pos..]).all(|(want, have)| same_line(have, want, fuzzy))
+    };
+
+    let expected = expected.min(last_start);
+    let limit = max_offset.unwrap_or(lines.len());
+    (0..=limit).find_map(|offset| {
+        if matches_at(expected + offset) {
+            Some(expected + offset)
+        } else if offset > 0 && offset <= expected && matches_at(expected - offset) {
+            Some(expected - offset)
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,165 @@
+// Path resolution and glob matching shared by the policy, sessions and the filesystem tools
+
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Arguments of built-in tools that hold filesystem paths, and the one (if any)
+/// that defaults to the current directory when omitted
+pub fn path_args(tool: &str, module: &str, args: &Map<String, Value>) -> (&'static [&'static str], Option<&'static str>) {
+    let from_file = args.get("from_file").and_then(Value::as_bool).unwrap_or(false);
+    match (module, tool) {
+        // Desktop-relative, not cwd-relative
+        ("filesystem", "fs_move_desktop") => (&[], None),
+        // A relative symlink target is relative to the link, not the working directory
+        ("filesystem", "fs_link") if args.get("type").and_then(Value::as_str) == Some("hard") => (&["path", "target"], None),
+        ("filesystem", _) => (&["path", "paths", "source", "destination", "old", "new"], None),
+        ("diagnostics", _) => (&["path"], Some("path")),
+        ("silent", "silent_script") => (&["cwd"], Some("cwd")),
+        // `file` arguments are paths inside the repository
+        ("git", _) => (&["path", "patch_file", "ignore_revs_file"], Some("path")),
+        // Other gitent paths are relative to the tracked root
+        ("gitent", "gitent_init") => (&["path", "db_path"], Some("path")),
+        ("clipboard", _) => (&["path"], None),
+        ("fixtures", _) => (&["path"], None),
+        ("transform", "transform_archive") if args.get("action").and_then(Value::as_str) == Some("extract") => {
+            (&["path", "destination"], Some("destination"))
+        }
+        // `files` are the ones create packs
+        ("transform", "transform_archive") => (&["path", "destination", "files"], None),
+        ("transform", _) if from_file => (&["a", "b", "input"], None),
+        ("settings", _) => (&["project"], Some("project")),
+        ("xlsx", _) => (&["path"], None),
+        ("md", _) => (&["path", "base_dir", "output_path"], None),
+        ("media", _) => (&["path", "model"], None),
+        ("browser", _) => (&["path", "dest_dir"], None),
+        ("apps", "apps_open") if args.get("target").and_then(Value::as_str).is_some_and(|t| !t.contains(':')) => {
+            (&["target"], None)
+        }
+        ("txn", "txn_commit") => (&["cwd", "diagnostics"], Some("cwd")),
+        _ => (&[], None),
+    }
+}
+
+/// Simple glob matching: supports * (any chars) and ? (single char)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_recursive(&pattern.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>(), 0, 0)
+}
+
+fn glob_match_recursive(pattern: &[char], text: &[char], pi: usize, ti: usize) -> bool {
+    if pi == pattern.len() && ti == text.len() {
+        return true;
+    }
+    if pi == pattern.len() {
+        return false;
+    }
+
+    match pattern[pi] {
+        '*' => {
+            // * matches zero or more characters
+            for i in ti..=text.len() {
+                if glob_match_recursive(pattern, text, pi + 1, i) {
+                    return true;
+                }
+            }
+            false
+        }
+        '?' => {
+            if ti < text.len() {
+                glob_match_recursive(pattern, text, pi + 1, ti + 1)
+            } else {
+                false
+            }
+        }
+        c => {
+            if ti < text.len() && (c == text[ti] || c.to_lowercase().next() == text[ti].to_lowercase().next()) {
+                glob_match_recursive(pattern, text, pi + 1, ti + 1)
+            } else {
+                false
+            }
+        }
+    }
+}
+
+/// Most symlinks followed while resolving one path, as in the kernel's limit
+const MAX_SYMLINKS: usize = 40;
+
+/// Absolute, symlink-free form of `path`, resolved one component at a time so
+/// `..` is applied to the real parent even when the tail doesn't exist yet.
+/// Dangling symlinks are followed to where they point, so a link inside an
+/// allowed root can't be used to create a file outside it.
+/// On Windows the result is in the plain form, see [`simplify`].
+pub fn resolve_path(path: &Path) -> PathBuf {
+    simplify(&resolve_following(path, 0))
+}
+
+fn resolve_following(path: &Path, depth: usize) -> PathBuf {
+    use std::path::Component;
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => {
+                resolved.push(other);
+                if let Ok(canonical) = resolved.canonicalize() {
+                    resolved = canonical;
+                } else if depth < MAX_SYMLINKS {
+                    // canonicalize fails on a link whose target is missing; resolve the target itself
+                    let target = std::fs::symlink_metadata(&resolved)
+                        .ok()
+                        .filter(|meta| meta.file_type().is_symlink())
+                        .and_then(|_| std::fs::read_link(&resolved).ok());
+                    if let Some(target) = target {
+                        let target = match resolved.parent() {
+                            Some(parent) if target.is_relative() => parent.join(target),
+                            _ => target,
+                        };
+                        resolved = resolve_following(&target, depth + 1);
+                    }
+                }
+            }
+        }
+    }
+    resolved
+}
+
+/// `path` without the `\\?\` prefix that canonicalizing adds on Windows: `\\?\C:\x`
+/// becomes `C:\x` and `\\?\UNC\server\share\x` becomes `\\server\share\x`, the
+/// forms users write and external programs accept. The standard library puts the
+/// prefix back by itself when a path is too long for the plain form, so deep trees
+/// still work. Other paths, and every path elsewhere, are returned unchanged.
+pub fn simplify(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    if let Some(text) = path.to_str() {
+        if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+            return PathBuf::from(format!(r"\\{}", share));
+        }
+        // Only drive paths; other verbatim paths (\\?\Volume{...}) have no plain form
+        if let Some(disk) = text.strip_prefix(r"\\?\").filter(|rest| rest.get(1..2) == Some(":")) {
+            return PathBuf::from(disk);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Whether `path` is `root` or inside it, both as returned by [`resolve_path`].
+/// Windows file systems ignore case, and so does the comparison there.
+pub fn within(path: &Path, root: &Path) -> bool {
+    if cfg!(windows) {
+        let mut components = path.components();
+        root.components().all(|part| {
+            components.next().is_some_and(|other| other.as_os_str().eq_ignore_ascii_case(part.as_os_str()))
+        })
+    } else {
+        path.starts_with(root)
+    }
+}
@@ -0,0 +1,474 @@
+// External tool providers found at startup: subprocess plugins and native libraries
+
+use crate::config::{PluginsConfig, MODULE_NAMES};
+use crate::registry::ToolModule;
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Version of the C ABI native plugins implement, returned by their `poly_plugin_abi_version`
+pub const ABI_VERSION: u32 = 1;
+
+/// How long a subprocess plugin has to answer `tools/list` while the server loads
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a call waiting on a plugin checks whether it was abandoned
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Every plugin in the `[plugins] dirs`, in file name order. A plugin that fails to
+/// load, or whose name is taken, is skipped with a warning so it cannot keep the
+/// server from starting.
+pub fn load(config: &PluginsConfig) -> Vec<Arc<dyn ToolModule>> {
+    let mut plugins: Vec<Arc<dyn ToolModule>> = Vec::new();
+    for dir in &config.dirs {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+            Err(e) => {
+                tracing::warn!("Skipping plugin directory {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        paths.sort();
+
+        for path in paths {
+            let plugin = match kind(&path) {
+                Some(Kind::Process) => ProcessPlugin::from_manifest(&path).map(|p| Arc::new(p) as Arc<dyn ToolModule>),
+                Some(Kind::Native) => load_native(&path),
+                None => continue,
+            };
+            let plugin = match plugin {
+                Ok(plugin) => plugin,
+                Err(e) => {
+                    tracing::warn!("Skipping plugin {}: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+            let name = plugin.name();
+            if MODULE_NAMES.contains(&name) || plugins.iter().any(|other| other.name() == name) {
+                tracing::warn!("Skipping plugin {}: a module named '{}' already exists", path.display(), name);
+                continue;
+            }
+            tracing::info!("Loaded plugin '{}' with {} tools from {}", name, plugin.tools().len(), path.display());
+            plugins.push(plugin);
+        }
+    }
+    plugins
+}
+
+/// `*.toml` in a plugin directory: how to start a subprocess plugin
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    cwd: Option<PathBuf>,
+}
+
+/// A plugin running as a child process that reads JSON-RPC 2.0 requests on stdin
+/// and writes one response per line on stdout: `tools/list` returns `{"tools": [...]}`
+/// and `tools/call` (`{"name", "arguments"}`) returns the tool's result. Its stderr
+/// goes to the server's. The process is shared by every session and restarted on
+/// the next call if it exits, or if it stops answering: at startup after
+/// [`STARTUP_TIMEOUT`], and for a call once the server stops waiting for it.
+pub struct ProcessPlugin {
+    name: String,
+    tools: Vec<Value>,
+    connection: Arc<Connection>,
+}
+
+impl ProcessPlugin {
+    pub fn from_manifest(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut manifest: Manifest = toml::from_str(&text).with_context(|| format!("Invalid plugin manifest {}", path.display()))?;
+        // Relative commands and directories are taken from the manifest's directory
+        let base = path.parent().unwrap_or(Path::new("."));
+        if manifest.command.contains(['/', '\\']) && Path::new(&manifest.command).is_relative() {
+            manifest.command = base.join(&manifest.command).display().to_string();
+        }
+        manifest.cwd = Some(match manifest.cwd.take() {
+            Some(cwd) => base.join(cwd),
+            None => base.to_path_buf(),
+        });
+
+        let connection = Arc::new(Connection {
+            manifest,
+            process: Mutex::new(None),
+        });
+        let listed = connection.request("tools/list", json!({}), Some(Instant::now() + STARTUP_TIMEOUT), &AtomicBool::new(false))?;
+        let tools = listed["tools"]
+            .as_array()
+            .cloned()
+            .context("The plugin's tools/list result has no tools array")?;
+        Ok(Self {
+            name: connection.manifest.name.clone(),
+            tools,
+            connection,
+        })
+    }
+}
+
+#[async_trait]
+impl ToolModule for ProcessPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.tools.clone()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        let connection = self.connection.clone();
+        let params = json!({ "name": tool, "arguments": args });
+        // Set when the server stops waiting (timeout, cancellation), so the blocked
+        // thread gives up, restarts the plugin and frees it for the next call
+        let abandoned = SetOnDrop(Arc::new(AtomicBool::new(false)));
+        let flag = abandoned.0.clone();
+        tokio::task::spawn_blocking(move || connection.request("tools/call", params, None, &flag)).await?
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        if let Some(mut process) = self.connection.process.lock().unwrap().take() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+        Ok(())
+    }
+}
+
+struct Connection {
+    manifest: Manifest,
+    process: Mutex<Option<Process>>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    // Lines of stdout, read on a thread of their own so waiting for them can time out
+    lines: Receiver<std::io::Result<String>>,
+    next_id: u64,
+}
+
+/// Sets its flag when dropped
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Connection {
+    /// Send one request and wait for its response, starting the process if it is not
+    /// running. Waiting ends at `deadline` or once `abandoned` is set, and then the
+    /// process is stopped, since its next line could be the late response.
+    fn request(&self, method: &str, params: Value, deadline: Option<Instant>, abandoned: &AtomicBool) -> Result<Value> {
+        let mut guard = self.process.lock().unwrap();
+        // Given up on while it waited for an earlier call
+        if abandoned.load(Ordering::Relaxed) {
+            anyhow::bail!("Plugin '{}' was busy with an earlier call", self.manifest.name);
+        }
+        if guard.is_none() {
+            *guard = Some(self.spawn()?);
+        }
+        let process = guard.as_mut().unwrap();
+        process.next_id += 1;
+        let id = process.next_id;
+
+        let response = match exchange(process, id, method, &params, deadline, abandoned) {
+            Ok(response) => response,
+            Err(e) => {
+                // Gone or out of step; start afresh next time
+                if let Some(mut process) = guard.take() {
+                    let _ = process.child.kill();
+                    let _ = process.child.wait();
+                }
+                return Err(e.context(format!("Plugin '{}' stopped responding", self.manifest.name)));
+            }
+        };
+        match response.get("error").filter(|error| !error.is_null()) {
+            Some(error) => anyhow::bail!("{}", error["message"].as_str().unwrap_or("unknown plugin error")),
+            None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    fn spawn(&self) -> Result<Process> {
+        let manifest = &self.manifest;
+        let mut command = Command::new(&manifest.command);
+        command
+            .args(&manifest.args)
+            .envs(&manifest.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        if let Some(cwd) = &manifest.cwd {
+            command.current_dir(cwd);
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start plugin '{}' ({})", manifest.name, manifest.command))?;
+        let stdin = child.stdin.take().context("Plugin stdin not available")?;
+        let stdout = BufReader::new(child.stdout.take().context("Plugin stdout not available")?);
+        // Ends when the process closes stdout, which killing it does
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in stdout.lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Process {
+            child,
+            stdin,
+            lines,
+            next_id: 0,
+        })
+    }
+}
+
+/// Native plugins: shared libraries exporting the C ABI below. Every string is
+/// NUL-terminated UTF-8 JSON, and strings the library returns are handed back to
+/// `poly_plugin_free`.
+///
+/// ```c
+/// uint32_t poly_plugin_abi_version(void);          // must return 1
+/// char *poly_plugin_tools(void);                   // a JSON array of tool definitions
+/// char *poly_plugin_call(const char *name, const char *arguments);
+///                                                  // {"result": ...} or {"error": "message"}
+/// void poly_plugin_free(char *s);
+/// ```
+///
+/// The module is named after the file, without a `lib` prefix and the extension.
+#[cfg(feature = "native-plugins")]
+pub struct NativePlugin {
+    name: String,
+    tools: Vec<Value>,
+    functions: native::Functions,
+}
+
+#[cfg(feature = "native-plugins")]
+impl NativePlugin {
+    pub fn load(path: &Path) -> Result<Self> {
+        let stem = path.file_stem().and_then(|s| s.to_str()).context("Plugin file name is not UTF-8")?;
+        let name = stem.strip_prefix("lib").unwrap_or(stem).to_string();
+        let functions = native::Functions::load(path)?;
+        let tools = functions.tools()?;
+        Ok(Self { name, tools, functions })
+    }
+}
+
+#[cfg(feature = "native-plugins")]
+#[async_trait]
+impl ToolModule for NativePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tools(&self) -> Vec<Value> {
+        self.tools.clone()
+    }
+
+    async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        let functions = self.functions.clone();
+        let tool = tool.to_string();
+        tokio::task::spawn_blocking(move || functions.call(&tool, &args)).await?
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+enum Kind {
+    Process,
+    Native,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    match path.extension()?.to_str()? {
+        "toml" => Some(Kind::Process),
+        "so" | "dylib" | "dll" => Some(Kind::Native),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "native-plugins")]
+fn load_native(path: &Path) -> Result<Arc<dyn ToolModule>> {
+    Ok(Arc::new(NativePlugin::load(path)?))
+}
+
+#[cfg(not(feature = "native-plugins"))]
+fn load_native(_path: &Path) -> Result<Arc<dyn ToolModule>> {
+    anyhow::bail!("This build has no native plugin support; rebuild with --features native-plugins")
+}
+
+/// Write a request and read lines until the response with `id`, `deadline` passes
+/// or `abandoned` is set; responses to requests an abandoned call left behind are skipped
+fn exchange(process: &mut Process, id: u64, method: &str, params: &Value, deadline: Option<Instant>, abandoned: &AtomicBool) -> Result<Value> {
+    let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    let mut line = serde_json::to_string(&request)?;
+    line.push('\n');
+    process.stdin.write_all(line.as_bytes())?;
+    process.stdin.flush()?;
+
+    loop {
+        if abandoned.load(Ordering::Relaxed) {
+            anyhow::bail!("the call was abandoned before the plugin answered");
+        }
+        let wait = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => left.min(POLL_INTERVAL),
+                None => anyhow::bail!("no answer to {} in time", method),
+            },
+            None => POLL_INTERVAL,
+        };
+        let line = match process.lines.recv_timeout(wait) {
+            Ok(line) => line?,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("the process exited"),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response: Value = serde_json::from_str(&line).context("the plugin wrote a line that is not JSON")?;
+        if response["id"] == json!(id) {
+            return Ok(response);
+        }
+    }
+}
+
+#[cfg(feature = "native-plugins")]
+mod native {
+    use super::ABI_VERSION;
+    use anyhow::{Context as _, Result};
+    use serde_json::Value;
+    use std::ffi::{c_char, CStr, CString};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    type VersionFn = unsafe extern "C" fn() -> u32;
+    type ToolsFn = unsafe extern "C" fn() -> *mut c_char;
+    type CallFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char;
+    type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+    /// The library's entry points, valid for as long as `_library` is loaded
+    #[derive(Clone)]
+    pub struct Functions {
+        tools: ToolsFn,
+        call: CallFn,
+        free: FreeFn,
+        _library: Arc<libloading::Library>,
+    }
+
+    impl Functions {
+        pub fn load(path: &Path) -> Result<Self> {
+            // SAFETY: loading runs the library's initializers; plugins are trusted code
+            // the operator placed in a plugin directory
+            let library = unsafe { libloading::Library::new(path) }
+                .with_context(|| format!("Failed to load {}", path.display()))?;
+            // SAFETY: the signatures are the documented plugin ABI
+            unsafe {
+                let version: VersionFn = *library
+                    .get::<VersionFn>(b"poly_plugin_abi_version\0")
+                    .context("Missing poly_plugin_abi_version")?;
+                if version() != ABI_VERSION {
+                    anyhow::bail!("Plugin ABI version {} is not supported (expected {})", version(), ABI_VERSION);
+                }
+                Ok(Self {
+                    tools: *library.get::<ToolsFn>(b"poly_plugin_tools\0").context("Missing poly_plugin_tools")?,
+                    call: *library.get::<CallFn>(b"poly_plugin_call\0").context("Missing poly_plugin_call")?,
+                    free: *library.get::<FreeFn>(b"poly_plugin_free\0").context("Missing poly_plugin_free")?,
+                    _library: Arc::new(library),
+                })
+            }
+        }
+
+        pub fn tools(&self) -> Result<Vec<Value>> {
+            // SAFETY: takes no arguments and returns a string for take_string
+            let tools = self.take_string(unsafe { (self.tools)() })?;
+            match tools {
+                Value::Array(tools) => Ok(tools),
+                _ => anyhow::bail!("poly_plugin_tools did not return a JSON array"),
+            }
+        }
+
+        pub fn call(&self, tool: &str, args: &Value) -> Result<Value> {
+            let name = CString::new(tool)?;
+            let args = CString::new(args.to_string())?;
+            // SAFETY: both pointers are valid NUL-terminated strings for the duration of the call
+            let response = self.take_string(unsafe { (self.call)(name.as_ptr(), args.as_ptr()) })?;
+            if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+                anyhow::bail!("{}", error.as_str().map(String::from).unwrap_or_else(|| error.to_string()));
+            }
+            Ok(response.get("result").cloned().unwrap_or(Value::Null))
+        }
+
+        /// Parse a string the library returned, then give it back to be freed
+        fn take_string(&self, ptr: *mut c_char) -> Result<Value> {
+            if ptr.is_null() {
+                anyhow::bail!("The plugin returned no data");
+            }
+            // SAFETY: the plugin returned a NUL-terminated string it owns until poly_plugin_free
+            let parsed = unsafe { CStr::from_ptr(ptr) }
+                .to_str()
+                .context("The plugin returned invalid UTF-8")
+                .and_then(|text| serde_json::from_str(text).context("The plugin returned invalid JSON"));
+            // SAFETY: ptr came from this library and is freed exactly once
+            unsafe { (self.free)(ptr) };
+            parsed
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn silent_plugin() -> Connection {
+        Connection {
+            manifest: Manifest {
+                name: "silent".to_string(),
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), "sleep 30".to_string()],
+                env: BTreeMap::new(),
+                cwd: None,
+            },
+            process: Mutex::new(None),
+        }
+    }
+
+    #[test]
+    fn stops_a_plugin_that_does_not_answer_in_time() {
+        let connection = silent_plugin();
+        let started = Instant::now();
+        let deadline = Some(started + Duration::from_millis(300));
+        assert!(connection.request("tools/list", json!({}), deadline, &AtomicBool::new(false)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Restarted on the next call
+        assert!(connection.process.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn an_abandoned_call_frees_the_plugin() {
+        let connection = silent_plugin();
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let flag = abandoned.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            flag.store(true, Ordering::Relaxed);
+        });
+        assert!(connection.request("tools/call", json!({}), None, &abandoned).is_err());
+        assert!(connection.process.lock().unwrap().is_none());
+    }
+}
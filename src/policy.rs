@@ -0,0 +1,493 @@
+// Policy checks applied to every tool call before it runs
+
+use crate::audit::Caller;
+use crate::config::{PolicyConfig, RateLimitConfig};
+use crate::error::PolyError;
+use crate::paths::{glob_match, path_args, resolve_path, within};
+#[cfg(feature = "input")]
+use crate::modules::input::InputModule;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Approval requests show at most this much of the call's arguments
+const MAX_APPROVAL_ARGS_CHARS: usize = 600;
+
+/// The tool and module that run shell scripts. Other calls that run a shell command
+/// are denied and need approval wherever this tool is and does.
+const SCRIPT_TOOL: (&str, &str) = ("silent_script", "silent");
+
+/// The built-in tools a `read_only` server offers: those that only inspect files,
+/// repositories, the system or the web. Tools that change anything, the session
+/// clipboard included, are left out, save timers, watches and other bookkeeping of
+/// the caller's own; so are plugin tools, and tools added later until they are listed
+/// here. Tools listed with some calls that do change things are checked by
+/// [`changes_state`]. `diagnostics_get` is the exception: `cargo check` writes build
+/// output to `target/` and runs the project's build scripts and procedural macros.
+const READ_ONLY_TOOLS: &[&str] = &[
+    "fs_read", "fs_read_many", "fs_find", "fs_ld", "fs_stat", "fs_permissions", "fs_watch", "fs_watch_start",
+    "fs_watch_poll", "fs_watch_stop", "fs_tree", "fs_grep",
+    "fs_tail", "fs_du", "fs_filetype", "fs_loc", "fs_hash", "fs_diff", "fs_snapshot_list", "fs_snapshot_diff", "fs_extract", "fs_trash_list",
+    "fs_recent", "fs_largest",
+    "diagnostics_get",
+    "silent_resources",
+    "time_now", "time_sleep", "time_timezone", "time_stopwatch", "time_timer", "time_alarm",
+    "net_fetch", "net_cargo", "net_node", "net_python", "net_apt", "net_ping", "net_session_close",
+    "ctx_context", "ctx_compact", "ctx_token_count", "ctx_memory_recall", "ctx_estimate_cost",
+    "git_status", "git_diff", "git_branch", "git_blame", "git_log", "git_tag", "git_stats",
+    "git_changelog", "git_ignore", "git_discover",
+    "gitent_status", "gitent_log", "gitent_diff",
+    "input_notify", "input_prompt", "input_select", "input_progress", "input_clipboard_read",
+    "clip_paste",
+    "transform_diff", "transform_encode", "transform_hash", "transform_regex", "transform_json",
+    "transform_text", "transform_archive",
+    "settings_get", "settings_list", "settings_watch",
+    "calc_eval", "calc_convert", "calc_stats",
+    "crypto_uuid", "crypto_random", "crypto_hash", "crypto_hmac", "crypto_encode",
+    "regex_test", "regex_replace", "regex_validate",
+    "text_diff", "text_patch", "text_merge3",
+    "xlsx_read",
+    "md_toc", "md_links", "md_lint", "md_render", "md_frontmatter",
+    "media_transcribe",
+    "browser_navigate", "browser_wait", "browser_text", "browser_screenshot", "browser_pages",
+    "browser_close",
+    "llm_generate", "llm_embed", "llm_models",
+    "apps_list",
+    "txn_status",
+    "session_cwd", "session_profile", "server_children", "audit_verify", "tool_help",
+];
+
+/// Tool allow/deny lists, path and URL restrictions, call rate limits, and human
+/// approval, from `[policy]`
+pub struct Policy {
+    allow_tools: Vec<String>,
+    deny_tools: Vec<String>,
+    // Resolved like filesystem allowed roots so `..` and symlinks cannot escape them
+    allowed_paths: Vec<PathBuf>,
+    allowed_urls: Vec<String>,
+    require_approval: Vec<String>,
+    approval_mode: String,
+    approval_timeout: Duration,
+    max_calls_per_minute: usize,
+    // Start times of the calls made in the last minute, oldest first
+    recent_calls: Mutex<VecDeque<Instant>>,
+    rate_limits: Vec<(String, RateLimitConfig)>,
+    // By index into rate_limits and client ("" for limits shared by all clients)
+    buckets: Arc<Mutex<HashMap<(usize, String), Bucket>>>,
+    read_only: bool,
+}
+
+/// Calls left in a `[policy.rate_limits]` bucket, and how many of its calls are running
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    running: u32,
+}
+
+/// Held while a call that `[policy.rate_limits]` applies to runs, so it counts toward
+/// their `concurrent` limits until it finishes
+pub struct Permit {
+    buckets: Arc<Mutex<HashMap<(usize, String), Bucket>>>,
+    keys: Vec<(usize, String)>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut buckets = self.buckets.lock().unwrap();
+        for key in &self.keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.running = bucket.running.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl Policy {
+    pub fn new(config: &PolicyConfig) -> Result<Self> {
+        if !matches!(config.approval_mode.as_str(), "dialog" | "terminal" | "notification") {
+            anyhow::bail!(
+                "Unknown approval_mode '{}' in [policy] (expected dialog, terminal or notification)",
+                config.approval_mode
+            );
+        }
+        for (pattern, limit) in &config.rate_limits {
+            if !(limit.per_second >= 0.0 && limit.per_second.is_finite()) {
+                anyhow::bail!("[policy.rate_limits.\"{}\"] per_second must be a number of 0 or more", pattern);
+            }
+            if limit.per_second == 0.0 && limit.concurrent == 0 {
+                anyhow::bail!("[policy.rate_limits.\"{}\"] sets neither per_second nor concurrent", pattern);
+            }
+        }
+
+        Ok(Self {
+            allow_tools: config.allow_tools.clone(),
+            deny_tools: config.deny_tools.clone(),
+            allowed_paths: config.allowed_paths.iter().map(|p| resolve_path(p)).collect(),
+            allowed_urls: config.allowed_urls.clone(),
+            require_approval: config.require_approval.clone(),
+            approval_mode: config.approval_mode.clone(),
+            approval_timeout: Duration::from_secs(config.approval_timeout_secs),
+            max_calls_per_minute: config.max_calls_per_minute as usize,
+            recent_calls: Mutex::new(VecDeque::new()),
+            rate_limits: config.rate_limits.clone().into_iter().collect(),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            read_only: config.read_only,
+        })
+    }
+
+    /// Whether the tool may be called at all; denied tools are also left out of tools/list
+    pub fn allows_tool(&self, tool: &str, module: &str) -> bool {
+        let allowed = self.allow_tools.is_empty() || matches_any(&self.allow_tools, tool, module);
+        allowed && !matches_any(&self.deny_tools, tool, module) && (!self.read_only || READ_ONLY_TOOLS.contains(&tool))
+    }
+
+    /// Reject the call if the tool is denied, an argument is outside the allowed paths
+    /// or URLs, a `rate_limits` entry has no call left for `caller`, or the server has
+    /// had `max_calls_per_minute` calls in the last minute. Keep the permit until the
+    /// call finishes.
+    pub fn check(&self, tool: &str, module: &str, args: &Value, caller: &Caller) -> Result<Permit> {
+        if self.read_only && !READ_ONLY_TOOLS.contains(&tool) {
+            return Err(PolyError::PolicyDenied(format!("Policy denied: tool '{}' can make changes, and this server is read-only", tool)).into());
+        }
+        if !self.allows_tool(tool, module) {
+            return Err(PolyError::PolicyDenied(format!("Policy denied: tool '{}' is not allowed on this server", tool)).into());
+        }
+        if runs_shell(tool, args) && !self.allows_tool(SCRIPT_TOOL.0, SCRIPT_TOOL.1) {
+            return Err(PolyError::PolicyDenied(format!(
+                "Policy denied: this call to {} runs a shell command, and {} is not allowed on this server",
+                tool, SCRIPT_TOOL.0
+            ))
+            .into());
+        }
+        if self.read_only && changes_state(tool, args) {
+            return Err(PolyError::PolicyDenied(format!(
+                "Policy denied: this call to {} would make changes, and this server is read-only",
+                tool
+            ))
+            .into());
+        }
+
+        if !self.allowed_paths.is_empty() {
+            for path in checked_paths(tool, module, args) {
+                let resolved = resolve_path(&path);
+                if !self.allowed_paths.iter().any(|root| within(&resolved, root)) {
+                    return Err(PolyError::PolicyDenied(format!(
+                        "Policy denied: {} is outside the allowed paths ({})",
+                        path.display(),
+                        self.allowed_paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        if !self.allowed_urls.is_empty() {
+            for url in url_args(module, args) {
+                if !self.allowed_urls.iter().any(|pattern| url_matches(pattern, url)) {
+                    return Err(PolyError::PolicyDenied(format!("Policy denied: {} is not in the allowed URLs", url)).into());
+                }
+            }
+        }
+
+        let permit = self.take_permit(tool, module, caller)?;
+        if let Err(e) = self.count_call() {
+            // Refused calls do not spend their rate_limits tokens either
+            self.return_tokens(&permit);
+            return Err(e);
+        }
+        Ok(permit)
+    }
+
+    /// Take a call from every `rate_limits` bucket that applies, or none of them if
+    /// one is empty or at its concurrency limit
+    fn take_permit(&self, tool: &str, module: &str, caller: &Caller) -> Result<Permit> {
+        let client = client_of(caller);
+        let keys: Vec<(usize, String)> = self
+            .rate_limits
+            .iter()
+            .enumerate()
+            .filter(|(_, (pattern, _))| matches_any(std::slice::from_ref(pattern), tool, module))
+            .map(|(i, (_, limit))| (i, if limit.per_client { client.clone() } else { String::new() }))
+            .collect();
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        for key in &keys {
+            let (pattern, limit) = &self.rate_limits[key.0];
+            let capacity = burst(limit);
+            let bucket = buckets.entry(key.clone()).or_insert(Bucket { tokens: capacity, refilled: now, running: 0 });
+            bucket.tokens = (bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * limit.per_second).min(capacity);
+            bucket.refilled = now;
+
+            if limit.concurrent > 0 && bucket.running >= limit.concurrent {
+                return Err(PolyError::RateLimited(format!(
+                    "Rate limited: {} may only run {} at a time ([policy.rate_limits.\"{}\"]); retry when a running call finishes",
+                    tool, limit.concurrent, pattern
+                ))
+                .with_data(json!({ "retry_after_secs": null, "limit": pattern })));
+            }
+            if limit.per_second > 0.0 && bucket.tokens < 1.0 {
+                // Rounded up to the millisecond
+                let retry = ((1.0 - bucket.tokens) / limit.per_second * 1000.0).ceil() / 1000.0;
+                return Err(PolyError::RateLimited(format!(
+                    "Rate limited: {} is limited to {} calls per second ([policy.rate_limits.\"{}\"]); retry in {:.1}s",
+                    tool, limit.per_second, pattern, retry
+                ))
+                .with_data(json!({ "retry_after_secs": retry, "limit": pattern })));
+            }
+        }
+        for key in &keys {
+            let (_, limit) = &self.rate_limits[key.0];
+            let bucket = buckets.get_mut(key).expect("bucket created above");
+            if limit.per_second > 0.0 {
+                bucket.tokens -= 1.0;
+            }
+            bucket.running += 1;
+        }
+
+        Ok(Permit { buckets: self.buckets.clone(), keys })
+    }
+
+    /// Give back the tokens `permit` took from the `rate_limits` buckets, for a call
+    /// refused after taking it. Its running calls are released when it is dropped.
+    fn return_tokens(&self, permit: &Permit) {
+        let mut buckets = self.buckets.lock().unwrap();
+        for key in &permit.keys {
+            let (_, limit) = &self.rate_limits[key.0];
+            if let Some(bucket) = buckets.get_mut(key).filter(|_| limit.per_second > 0.0) {
+                bucket.tokens = (bucket.tokens + 1.0).min(burst(limit));
+            }
+        }
+    }
+
+    /// Count a call against the per-minute limit; checked last, so refused calls do not count
+    fn count_call(&self) -> Result<()> {
+        if self.max_calls_per_minute == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut recent = self.recent_calls.lock().unwrap();
+        while recent.front().is_some_and(|start| now.duration_since(*start) >= Duration::from_secs(60)) {
+            recent.pop_front();
+        }
+        if recent.len() >= self.max_calls_per_minute {
+            let retry = Duration::from_secs(60).saturating_sub(now.duration_since(recent[0])).as_secs() + 1;
+            return Err(PolyError::RateLimited(format!(
+                "Rate limited: this server allows {} tool calls per minute; retry in {}s",
+                self.max_calls_per_minute, retry
+            ))
+            .with_data(json!({ "retry_after_secs": retry, "limit": "max_calls_per_minute" })));
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+
+    /// Whether the user must allow the call first. Calls that run a shell command
+    /// need approval wherever silent_script does, and a power_shutdown call that would
+    /// take the machine down always does.
+    pub fn needs_approval(&self, tool: &str, module: &str, args: &Value) -> bool {
+        let (script, script_module) = SCRIPT_TOOL;
+        matches_any(&self.require_approval, tool, module)
+            || (runs_shell(tool, args) && matches_any(&self.require_approval, script, script_module))
+            || schedules_shutdown(tool, args)
+    }
+
+    /// Ask the user to allow this call, failing if they deny it or do not answer in time
+    pub async fn approve(&self, tool: &str, args: &Value, caller: &Caller) -> Result<()> {
+        let mut arguments = args.to_string();
+        if arguments.chars().count() > MAX_APPROVAL_ARGS_CHARS {
+            arguments = arguments.chars().take(MAX_APPROVAL_ARGS_CHARS).collect::<String>() + "...";
+        }
+        let who = match &caller.name {
+            Some(name) => format!("{} (via {})", name, caller.transport),
+            None => format!("An agent (via {})", caller.transport),
+        };
+        let message = format!("{} wants to run {} with arguments:\n{}\n\nAllow this call?", who, tool, arguments);
+
+        let approved = ask("Poly MCP approval", &message, &self.approval_mode, self.approval_timeout)
+            .await
+            .map_err(|e| PolyError::PolicyDenied(format!("Policy denied: '{}' needs approval, but the user could not be asked: {}", tool, e)))?;
+        if !approved {
+            return Err(PolyError::PolicyDenied(format!("Policy denied: the user did not approve this call to '{}'", tool)).into());
+        }
+        Ok(())
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// Ask the user a yes/no question with the input module's dialog or terminal prompt
+#[cfg(feature = "input")]
+async fn ask(title: &str, message: &str, mode: &str, timeout: Duration) -> Result<bool> {
+    InputModule::new().confirm(title, message, mode, timeout).await
+}
+
+#[cfg(not(feature = "input"))]
+async fn ask(_title: &str, _message: &str, _mode: &str, _timeout: Duration) -> Result<bool> {
+    anyhow::bail!("this build has no input module to ask with (rebuild with --features input)")
+}
+
+/// Who a `per_client` rate limit counts calls for: the authenticated caller, else
+/// their HTTP session, else the transport (there is one stdio client)
+fn client_of(caller: &Caller) -> String {
+    caller
+        .name
+        .clone()
+        .or_else(|| caller.session.clone())
+        .unwrap_or_else(|| caller.transport.to_string())
+}
+
+/// Calls a rate limit's bucket holds when full
+fn burst(limit: &RateLimitConfig) -> f64 {
+    match limit.burst {
+        Some(burst) => f64::from(burst.max(1)),
+        None => limit.per_second.ceil().max(1.0),
+    }
+}
+
+/// Whether a call runs a shell command it was given, as silent_script does:
+/// txn_commit's check `command`
+fn runs_shell(tool: &str, args: &Value) -> bool {
+    tool == "txn_commit" && !args["command"].is_null()
+}
+
+/// Whether a power_shutdown call redeems an approval token, scheduling a shutdown
+/// or restart. The token only guards against replays; the user confirms these.
+fn schedules_shutdown(tool: &str, args: &Value) -> bool {
+    tool == "power_shutdown"
+        && matches!(args["action"].as_str().unwrap_or("shutdown"), "shutdown" | "restart")
+        && !args["approval_token"].is_null()
+}
+
+/// Whether a call to one of the [`READ_ONLY_TOOLS`] that can also make changes would
+/// make some: write a file, change a repository, or set the system clipboard or a schedule
+fn changes_state(tool: &str, args: &Value) -> bool {
+    let action = args["action"].as_str().unwrap_or_default();
+    let set = |key: &str| !args[key].is_null();
+    match tool {
+        "fs_permissions" => set("mode"),
+        "fs_extract" => !args["dry_run"].as_bool().unwrap_or(false),
+        "net_fetch" => args["method"].as_str().is_some_and(|method| !method.eq_ignore_ascii_case("GET")),
+        "git_branch" | "git_tag" => matches!(action, "create" | "delete"),
+        "git_ignore" => matches!(action, "add" | "remove"),
+        "transform_archive" => matches!(action, "create" | "extract"),
+        "md_toc" => args["insert"].as_bool().unwrap_or(false),
+        "md_frontmatter" => set("path") && matches!(action, "set" | "replace" | "remove"),
+        "md_render" => set("output_path"),
+        "browser_screenshot" => set("path"),
+        "server_children" => action == "kill",
+        _ => false,
+    }
+}
+
+/// A pattern matches a tool name glob, or names the tool's module
+fn matches_any(patterns: &[String], tool: &str, module: &str) -> bool {
+    patterns.iter().any(|pattern| pattern == module || glob_match(pattern, tool))
+}
+
+/// The paths a call names, for every module with path arguments: each one given,
+/// the working directory for an omitted one that defaults to it, and where an
+/// fs_link symlink points, which is relative to the link
+fn checked_paths(tool: &str, module: &str, args: &Value) -> Vec<PathBuf> {
+    let empty = serde_json::Map::new();
+    let map = args.as_object().unwrap_or(&empty);
+    let (keys, default) = path_args(tool, module, map);
+
+    let mut paths: Vec<PathBuf> = keys
+        .iter()
+        .flat_map(|key| match map.get(*key) {
+            // Lists of paths, such as fs_read_many's
+            Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).map(PathBuf::from).collect(),
+            value => value.and_then(Value::as_str).map(PathBuf::from).into_iter().collect::<Vec<_>>(),
+        })
+        .collect();
+    if let Some(key) = default {
+        if map.get(key).is_none_or(Value::is_null) {
+            paths.push(PathBuf::from("."));
+        }
+    }
+    if tool == "fs_link" && map.get("type").and_then(Value::as_str) != Some("hard") {
+        if let (Some(link), Some(target)) = (map.get("path").and_then(Value::as_str), map.get("target").and_then(Value::as_str)) {
+            let dir = Path::new(link).parent().unwrap_or(Path::new(""));
+            paths.push(dir.join(target));
+        }
+    }
+    paths
+}
+
+/// URL arguments of network and browser tools
+fn url_args<'a>(module: &str, args: &'a Value) -> Vec<&'a str> {
+    match module {
+        "network" | "browser" => args["url"].as_str().into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Patterns with a scheme are globs over the whole URL; others are globs over the host
+fn url_matches(pattern: &str, url: &str) -> bool {
+    if pattern.contains("://") {
+        return glob_match(pattern, url);
+    }
+    let host = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(url)
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    // Drop any credentials and port
+    let host = host.rsplit('@').next().unwrap_or(host);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    glob_match(pattern, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(config: PolicyConfig) -> Policy {
+        Policy::new(&config).unwrap()
+    }
+
+    #[test]
+    fn checks_files_transform_archive_packs_against_allowed_paths() {
+        let root = std::env::temp_dir().join(format!("poly-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let policy = policy(PolicyConfig { allowed_paths: vec![root.clone()], ..PolicyConfig::default() });
+        let caller = Caller::new("library");
+        let archive = root.join("out.zip").display().to_string();
+
+        let outside = json!({ "action": "create", "path": archive, "files": ["/etc/passwd"] });
+        assert!(policy.check("transform_archive", "transform", &outside, &caller).is_err());
+        let inside = json!({ "action": "create", "path": archive, "files": [root.join("a.txt").display().to_string()] });
+        assert!(policy.check("transform_archive", "transform", &inside, &caller).is_ok());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_only_refuses_md_render_to_a_file() {
+        let policy = policy(PolicyConfig { read_only: true, ..PolicyConfig::default() });
+        let caller = Caller::new("library");
+
+        assert!(policy.check("md_render", "md", &json!({ "content": "# Hi" }), &caller).is_ok());
+        let to_file = json!({ "content": "# Hi", "output_path": "/tmp/ro_out.html" });
+        assert!(policy.check("md_render", "md", &to_file, &caller).is_err());
+    }
+
+    #[test]
+    fn scheduling_a_shutdown_always_needs_approval() {
+        let policy = policy(PolicyConfig::default());
+
+        assert!(!policy.needs_approval("power_shutdown", "power", &json!({})));
+        assert!(!policy.needs_approval("power_shutdown", "power", &json!({ "action": "cancel", "approval_token": "t" })));
+        assert!(policy.needs_approval("power_shutdown", "power", &json!({ "approval_token": "t" })));
+        assert!(policy.needs_approval("power_shutdown", "power", &json!({ "action": "restart", "approval_token": "t" })));
+    }
+}
@@ -0,0 +1,170 @@
+// Built-in execution profiles: policy presets selected with --profile-level or [policy] profile
+
+use crate::config::Config;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Hosts the safe profile lets network and browser tools reach: package registries
+/// and documentation the version-lookup tools need, and GitHub
+const SAFE_URLS: &[&str] = &[
+    "crates.io",
+    "*.crates.io",
+    "docs.rs",
+    "pypi.org",
+    "files.pythonhosted.org",
+    "registry.npmjs.org",
+    "www.npmjs.com",
+    "github.com",
+    "*.github.com",
+    "raw.githubusercontent.com",
+];
+
+/// A preset bundle of sandbox roots, denied tools, approvals, URL allowlist and
+/// call rate. Presets only fill in: settings from the file and the command line
+/// are kept, and their lists are extended rather than replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Confined to the working directory, no scripts, power or app control,
+    /// approval for destructive changes, registry and GitHub URLs only, 60 calls a minute
+    Safe,
+    /// Confined to the working directory, approval for scripts, deletes, power and
+    /// quitting apps, 600 calls a minute
+    Standard,
+    /// No restrictions beyond what the config sets
+    Yolo,
+}
+
+impl Profile {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "safe" => Ok(Self::Safe),
+            "standard" => Ok(Self::Standard),
+            "yolo" => Ok(Self::Yolo),
+            other => anyhow::bail!("Unknown profile '{}' (expected safe, standard or yolo)", other),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Safe => "safe",
+            Self::Standard => "standard",
+            Self::Yolo => "yolo",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Safe => "Read and edit files in the working directory; scripts, power and app control are off, destructive changes need approval, and only package registries and GitHub can be fetched",
+            Self::Standard => "Work anywhere in the working directory; scripts, deletes, power actions and quitting apps need approval",
+            Self::Yolo => "Everything the server offers, with no preset restrictions",
+        }
+    }
+
+    fn deny_tools(&self) -> &'static [&'static str] {
+        match self {
+            Self::Safe => &["silent_script", "power", "apps", "settings_set"],
+            Self::Standard | Self::Yolo => &[],
+        }
+    }
+
+    fn require_approval(&self) -> &'static [&'static str] {
+        match self {
+            Self::Safe => &[
+                "fs_delete",
+                "fs_move",
+                "git_commit",
+                "git_checkout",
+                "git_apply",
+                "gitent_rollback",
+                "txn_commit",
+            ],
+            Self::Standard => &["silent_script", "fs_delete", "power", "apps_quit"],
+            Self::Yolo => &[],
+        }
+    }
+
+    fn max_calls_per_minute(&self) -> u64 {
+        match self {
+            Self::Safe => 60,
+            Self::Standard => 600,
+            Self::Yolo => 0,
+        }
+    }
+}
+
+/// Fill in the preset of the profile `config` selects, if any. Safe to apply twice.
+pub fn apply(config: &mut Config) -> Result<Option<Profile>> {
+    let Some(profile) = config.policy.profile.as_deref().map(Profile::parse).transpose()? else {
+        return Ok(None);
+    };
+
+    if profile != Profile::Yolo {
+        if config.filesystem.allowed_roots.is_empty() {
+            config.filesystem.allowed_roots = vec![working_dir(config)];
+        }
+        if config.policy.allowed_paths.is_empty() {
+            config.policy.allowed_paths = config.filesystem.allowed_roots.clone();
+        }
+    }
+    extend(&mut config.policy.deny_tools, profile.deny_tools());
+    extend(&mut config.policy.require_approval, profile.require_approval());
+    if profile == Profile::Safe && config.policy.allowed_urls.is_empty() {
+        config.policy.allowed_urls = SAFE_URLS.iter().map(|url| url.to_string()).collect();
+    }
+    if config.policy.max_calls_per_minute == 0 {
+        config.policy.max_calls_per_minute = profile.max_calls_per_minute();
+    }
+    Ok(Some(profile))
+}
+
+/// What a session may do under `config`, as reported by `session_profile`
+pub fn describe(config: &Config) -> Value {
+    let profile = config.policy.profile.as_deref().and_then(|name| Profile::parse(name).ok());
+    let policy = &config.policy;
+    json!({
+        "profile": profile.map(|p| p.name()).unwrap_or("custom"),
+        "description": profile
+            .map(|p| p.description())
+            .unwrap_or("No built-in profile; the server's [policy] applies as written"),
+        "allowed_roots": config.filesystem.allowed_roots,
+        "allowed_urls": policy.allowed_urls,
+        "allow_tools": policy.allow_tools,
+        "deny_tools": policy.deny_tools,
+        "require_approval": policy.require_approval,
+        "read_only": policy.read_only,
+        "max_calls_per_minute": policy.max_calls_per_minute,
+        "rate_limits": policy
+            .rate_limits
+            .iter()
+            .map(|(pattern, limit)| {
+                (pattern.clone(), json!({
+                    "per_second": limit.per_second,
+                    "burst": limit.burst,
+                    "concurrent": limit.concurrent,
+                    "per_client": limit.per_client,
+                }))
+            })
+            .collect::<serde_json::Map<_, _>>(),
+    })
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn extend(list: &mut Vec<String>, preset: &[&str]) {
+    for entry in preset {
+        if !list.iter().any(|existing| existing == entry) {
+            list.push(entry.to_string());
+        }
+    }
+}
+
+/// The directory sessions start in, which the presets confine them to
+fn working_dir(config: &Config) -> PathBuf {
+    config
+        .server
+        .cwd
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
@@ -0,0 +1,131 @@
+// Progress reporting and streamed partial output for a running tool call
+
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+tokio::task_local! {
+    static CONTEXT: ProgressContext;
+}
+
+/// Where a tool call's progress goes: the client's progress token and the
+/// server's notification channel. Set for the duration of one tools/call.
+#[derive(Clone)]
+struct ProgressContext {
+    token: Value,
+    notifier: broadcast::Sender<Value>,
+    // MCP requires progress to increase with every notification for a token
+    last: Arc<AtomicU64>,
+    // Last whole percentage reported, so `report` only speaks when it changes
+    percent: Arc<AtomicU64>,
+}
+
+impl ProgressContext {
+    fn report(&self, progress: u64, total: Option<u64>, message: &str) {
+        if progress <= self.last.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Some(total) = total.filter(|&total| total > 0) {
+            let percent = progress.min(total) * 100 / total;
+            if self.percent.swap(percent, Ordering::SeqCst) == percent && progress < total {
+                return;
+            }
+        }
+        self.last.store(progress, Ordering::SeqCst);
+        self.send(progress, total, message, None);
+    }
+
+    fn send(&self, progress: u64, total: Option<u64>, message: &str, stream: Option<&str>) {
+        let mut params = json!({
+            "progressToken": self.token,
+            "progress": progress,
+            "message": message,
+        });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if let Some(stream) = stream {
+            params["_meta"] = json!({ "stream": stream });
+        }
+
+        // No subscribers is not an error; the final result carries everything
+        let _ = self.notifier.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": params
+        }));
+    }
+}
+
+/// Run a tool call with progress reporting. Without a token (the client did not
+/// pass `_meta.progressToken`) the call runs as is and reports nothing.
+pub async fn scope<F: Future>(
+    token: Option<Value>,
+    notifier: broadcast::Sender<Value>,
+    call: F,
+) -> F::Output {
+    match token {
+        Some(token) => {
+            let context = ProgressContext {
+                token,
+                notifier,
+                last: Arc::new(AtomicU64::new(0)),
+                percent: Arc::new(AtomicU64::new(u64::MAX)),
+            };
+            CONTEXT.scope(context, call).await
+        }
+        None => call.await,
+    }
+}
+
+/// Whether the current tool call has a client listening for its progress.
+/// Tools can use this to skip work that only matters when reporting, like
+/// measuring the total size of a copy up front.
+pub fn is_streaming() -> bool {
+    CONTEXT.try_with(|_| ()).is_ok()
+}
+
+/// Report how far a long operation has got, e.g. bytes copied out of `total`.
+/// Reports that do not move the whole percentage are dropped, so calling this
+/// for every file of a large tree does not flood the client.
+pub fn report(progress: u64, total: Option<u64>, message: &str) {
+    let _ = CONTEXT.try_with(|context| context.report(progress, total, message));
+}
+
+/// A handle for reporting progress from outside the tool call's task, such as
+/// a blocking thread doing the actual work. `None` when nobody is listening.
+pub fn reporter() -> Option<Reporter> {
+    CONTEXT.try_with(|context| Reporter(context.clone())).ok()
+}
+
+/// See [`reporter`]
+#[derive(Clone)]
+pub struct Reporter(ProgressContext);
+
+impl Reporter {
+    /// Same as the free function [`report`]
+    pub fn report(&self, progress: u64, total: Option<u64>, message: &str) {
+        self.0.report(progress, total, message);
+    }
+}
+
+/// Send a chunk of partial output, e.g. a line a script printed on `stream`
+/// ("stdout", "stderr", ...). The full output is still part of the final result.
+pub fn output(stream: &str, text: &str) {
+    let _ = CONTEXT.try_with(|context| {
+        let progress = context.last.fetch_add(1, Ordering::SeqCst) + 1;
+        context.send(progress, None, text, Some(stream));
+    });
+}
+
+/// Send a chunk of partial output together with how far the operation has got,
+/// e.g. a piece of a download and the bytes received so far out of `total`.
+pub fn output_at(stream: &str, text: &str, progress: u64, total: Option<u64>) {
+    let _ = CONTEXT.try_with(|context| {
+        let progress = progress.max(context.last.load(Ordering::SeqCst) + 1);
+        context.last.store(progress, Ordering::SeqCst);
+        context.send(progress, total, text, Some(stream));
+    });
+}
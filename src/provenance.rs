@@ -0,0 +1,111 @@
+// Where a tool result's data came from: the files read, URLs fetched and commands run, attached to results as `_provenance`
+
+use crate::registry::ToolOutput;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Sources of one kind kept per call; the rest are only counted
+const MAX_SOURCES: usize = 100;
+
+tokio::task_local! {
+    static CURRENT: Arc<Mutex<Sources>>;
+}
+
+/// What one tool call read, fetched and ran, each in the order it first did so
+#[derive(Debug, Default)]
+struct Sources {
+    paths: Vec<String>,
+    urls: Vec<String>,
+    commands: Vec<String>,
+    omitted: usize,
+}
+
+/// The provenance of one tool call, collected while it runs inside [`Record::scope`]
+pub struct Record {
+    tool: String,
+    started: DateTime<Utc>,
+    sources: Arc<Mutex<Sources>>,
+}
+
+impl Record {
+    pub fn new(tool: &str) -> Self {
+        Self {
+            tool: tool.to_string(),
+            started: Utc::now(),
+            sources: Arc::default(),
+        }
+    }
+
+    /// Run `call` with what it reads, fetches and runs noted here
+    pub fn scope<F: Future>(&self, call: F) -> impl Future<Output = F::Output> {
+        CURRENT.scope(self.sources.clone(), call)
+    }
+
+    /// Add the `_provenance` field to `result`. Results that are not JSON objects
+    /// have nowhere to put it and are left as they are.
+    pub fn attach(self, result: &mut ToolOutput) {
+        let sources = self.sources.lock().unwrap();
+        let mut provenance = json!({
+            "tool": self.tool,
+            "server": format!("poly-mcp {}", env!("CARGO_PKG_VERSION")),
+            "started": self.started.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "finished": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        });
+        for (key, list) in [("paths", &sources.paths), ("urls", &sources.urls), ("commands", &sources.commands)] {
+            if !list.is_empty() {
+                provenance[key] = json!(list);
+            }
+        }
+        if sources.omitted > 0 {
+            provenance["omitted"] = json!(sources.omitted);
+        }
+        result.insert("_provenance", provenance);
+    }
+}
+
+/// Note that the current call read the file or directory at `path`
+pub fn read(path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    note(|sources| &mut sources.paths, path.display().to_string());
+}
+
+/// Note that the current call fetched `url`. Credentials in it are left out.
+pub fn fetched(url: &str) {
+    let url = without_userinfo(url).unwrap_or_else(|| url.to_string());
+    note(|sources| &mut sources.urls, url);
+}
+
+/// Note that the current call ran `command`
+pub fn ran(command: &str) {
+    note(|sources| &mut sources.commands, command.to_string());
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+fn note(list: fn(&mut Sources) -> &mut Vec<String>, source: String) {
+    // Outside a tool call there is nothing to note it for
+    let _ = CURRENT.try_with(|sources| {
+        let mut sources = sources.lock().unwrap();
+        let entries = list(&mut sources);
+        if entries.contains(&source) {
+            return;
+        }
+        if entries.len() < MAX_SOURCES {
+            entries.push(source);
+        } else {
+            sources.omitted += 1;
+        }
+    });
+}
+
+/// `url` without its `user:password@` part, if it has one
+fn without_userinfo(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (_, host) = rest[..authority_end].rsplit_once('@')?;
+    Some(format!("{}://{}{}", scheme, host, &rest[authority_end..]))
+}
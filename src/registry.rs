@@ -0,0 +1,241 @@
+// Tool modules and the registry that routes tool calls to them
+
+use crate::config::Config;
+use crate::error::PolyError;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+/// A group of related tools. Implement this to add tools to the server,
+/// either for the built-in modules or from a downstream crate.
+#[async_trait]
+pub trait ToolModule: Send + Sync {
+    /// Module name used for enabling/disabling, e.g. "filesystem"
+    fn name(&self) -> &str;
+
+    /// Tool definitions (name, description, inputSchema) as returned by tools/list
+    fn tools(&self) -> Vec<Value>;
+
+    /// Run one of this module's tools
+    async fn call(&self, tool: &str, args: Value) -> Result<Value>;
+
+    /// Run one of this module's tools for a client. The server calls this rather than
+    /// `call`, so modules can serialize large results themselves (see [`ToolOutput`]).
+    async fn call_output(&self, tool: &str, args: Value) -> Result<ToolOutput> {
+        Ok(self.call(tool, args).await?.into())
+    }
+
+    /// Persist state and release resources before the server exits. Called once
+    /// per server instance, after its in-flight tool calls have finished.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Take on settings from a reloaded config (config/reload or SIGHUP) while
+    /// running. An error keeps the module's current settings and is logged.
+    async fn reload(&self, _config: &Config) -> Result<()> {
+        Ok(())
+    }
+
+    /// Build whatever the module would otherwise build on its first call, such as
+    /// an HTTP client or a system scan. Called at startup with `--preload`.
+    fn warm_up(&self) {}
+}
+
+/// What a tool call returns: a JSON value, or JSON text the tool serialized itself.
+///
+/// Clients get a tool's result as JSON text, so a `Value` tree is built only to be
+/// serialized once more. Tools with large results, such as file contents or diffs,
+/// serialize them straight to text with [`ToolOutput::serialize`] instead, saving
+/// the tree's allocations and a copy of every string in it. The text is sent as it
+/// is, unless a middleware needs the result as a `Value`.
+#[derive(Debug, Clone)]
+pub struct ToolOutput(Output);
+
+#[derive(Debug, Clone)]
+enum Output {
+    Value(Value),
+    // Always valid JSON, made by serde_json
+    Json(String),
+}
+
+impl ToolOutput {
+    /// `result` serialized to JSON text now, without building a `Value`
+    pub fn serialize(result: &impl Serialize) -> Result<Self> {
+        Ok(Self(Output::Json(serde_json::to_string(result)?)))
+    }
+
+    /// Whether this is JSON text rather than a `Value`
+    pub fn is_serialized(&self) -> bool {
+        matches!(self.0, Output::Json(_))
+    }
+
+    /// The result as a `Value`, parsing it if it was serialized
+    pub fn into_value(self) -> Result<Value> {
+        match self.0 {
+            Output::Value(value) => Ok(value),
+            Output::Json(text) => Ok(serde_json::from_str(&text)?),
+        }
+    }
+
+    /// The result as JSON text, for a text content block
+    pub fn into_text(self) -> String {
+        match self.0 {
+            Output::Value(value) => value.to_string(),
+            Output::Json(text) => text,
+        }
+    }
+
+    /// Add the field `key` to a result that is a JSON object. Other results have
+    /// nowhere to put it and are left as they are.
+    pub fn insert(&mut self, key: &str, value: Value) {
+        match &mut self.0 {
+            Output::Value(Value::Object(fields)) => {
+                fields.insert(key.to_string(), value);
+            }
+            Output::Value(_) => {}
+            // serde_json writes objects without whitespace around their braces
+            Output::Json(text) if text.starts_with('{') && text.ends_with('}') => {
+                let field = format!("{}:{}", Value::from(key), value);
+                text.pop();
+                if text.len() > 1 {
+                    text.push(',');
+                }
+                text.push_str(&field);
+                text.push('}');
+            }
+            Output::Json(_) => {}
+        }
+    }
+}
+
+impl From<Value> for ToolOutput {
+    fn from(value: Value) -> Self {
+        Self(Output::Value(value))
+    }
+}
+
+/// A value built on first use rather than when its module is constructed, so
+/// modules that are never called cost nothing at startup. Clones share the value.
+pub struct Lazy<T> {
+    cell: Arc<OnceLock<T>>,
+    init: Arc<dyn Fn() -> T + Send + Sync>,
+}
+
+impl<T> Lazy<T> {
+    pub fn new(init: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        Self {
+            cell: Arc::new(OnceLock::new()),
+            init: Arc::new(init),
+        }
+    }
+
+    /// The value, built now if this is the first use
+    pub fn get(&self) -> &T {
+        self.cell.get_or_init(|| (self.init)())
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+            init: self.init.clone(),
+        }
+    }
+}
+
+/// Modules in registration order, plus an index from tool name to the module that owns it
+#[derive(Default)]
+pub struct ToolRegistry {
+    modules: Vec<Arc<dyn ToolModule>>,
+    routes: HashMap<String, usize>,
+    // Each tool's parameter names, from its inputSchema
+    parameters: HashMap<String, Vec<String>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a module. Fails if one of its tools is already provided by another module.
+    pub fn register(&mut self, module: impl ToolModule + 'static) -> Result<()> {
+        self.register_boxed(Box::new(module))
+    }
+
+    pub fn register_boxed(&mut self, module: Box<dyn ToolModule>) -> Result<()> {
+        self.register_shared(Arc::from(module))
+    }
+
+    /// Add a module that is also registered elsewhere, e.g. in every HTTP session
+    pub fn register_shared(&mut self, module: Arc<dyn ToolModule>) -> Result<()> {
+        let index = self.modules.len();
+        let tools = module.tools();
+        let names: Vec<String> = tools
+            .iter()
+            .filter_map(|tool| tool["name"].as_str().map(|s| s.to_string()))
+            .collect();
+
+        for name in &names {
+            if let Some(&existing) = self.routes.get(name) {
+                anyhow::bail!(
+                    "Tool '{}' from module '{}' is already registered by module '{}'",
+                    name,
+                    module.name(),
+                    self.modules[existing].name()
+                );
+            }
+        }
+
+        for name in names {
+            self.routes.insert(name, index);
+        }
+        for tool in &tools {
+            if let (Some(name), Some(properties)) = (tool["name"].as_str(), tool["inputSchema"]["properties"].as_object()) {
+                self.parameters.insert(name.to_string(), properties.keys().cloned().collect());
+            }
+        }
+        self.modules.push(module);
+        Ok(())
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &dyn ToolModule> {
+        self.modules.iter().map(|m| m.as_ref())
+    }
+
+    /// Owned handles to every module, in registration order
+    pub fn handles(&self) -> impl Iterator<Item = &Arc<dyn ToolModule>> {
+        self.modules.iter()
+    }
+
+    /// The module that provides `tool`, if any
+    pub fn module_for(&self, tool: &str) -> Option<&dyn ToolModule> {
+        self.routes.get(tool).map(|&index| self.modules[index].as_ref())
+    }
+
+    /// Owned handle to the module that provides `tool`, for running a call on its own task
+    pub fn get(&self, tool: &str) -> Option<Arc<dyn ToolModule>> {
+        self.routes.get(tool).map(|&index| self.modules[index].clone())
+    }
+
+    /// The parameter names `tool` declares in its inputSchema, if it declares any
+    pub fn parameters(&self, tool: &str) -> Option<&[String]> {
+        self.parameters.get(tool).map(Vec::as_slice)
+    }
+
+    /// Tool definitions from every registered module
+    pub fn tools(&self) -> Vec<Value> {
+        self.modules.iter().flat_map(|m| m.tools()).collect()
+    }
+
+    pub async fn call(&self, tool: &str, args: Value) -> Result<Value> {
+        match self.module_for(tool) {
+            Some(module) => module.call(tool, args).await,
+            None => Err(PolyError::UnknownTool(format!("Unknown tool: {}", tool)).into()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Requests from a running tool call back to the client: sampling and elicitation
+
+use anyhow::{Context as _, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+
+/// How long a tool waits for the client to answer, unless it asks for another limit
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+tokio::task_local! {
+    static CURRENT: Arc<ClientBridge>;
+}
+
+type Answer = Result<Value, String>;
+
+/// One server instance's outstanding requests to its client. Requests go out on
+/// the notification channel, so they reach the client over whichever transport it
+/// listens on (stdout, SSE or WebSocket); its responses come back through
+/// [`ClientBridge::respond`].
+pub struct ClientBridge {
+    notifier: broadcast::Sender<Value>,
+    // Declared by the client in its initialize request
+    capabilities: Mutex<Value>,
+    pending: Mutex<HashMap<String, oneshot::Sender<Answer>>>,
+    next_id: AtomicU64,
+}
+
+impl ClientBridge {
+    pub fn new(notifier: broadcast::Sender<Value>) -> Arc<Self> {
+        Arc::new(Self {
+            notifier,
+            capabilities: Mutex::new(Value::Null),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Remember the `capabilities` from the client's initialize request
+    pub fn set_capabilities(&self, capabilities: Value) {
+        *self.capabilities.lock().unwrap() = capabilities;
+    }
+
+    /// Whether the client declared a capability, e.g. "sampling" or "elicitation"
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.lock().unwrap().get(capability).is_some_and(|c| !c.is_null())
+    }
+
+    /// Send a request to the client and wait up to `timeout` for its result
+    pub async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value> {
+        // Prefixed so our ids never look like ones the client uses for its own requests
+        let id = format!("poly-mcp-{}", self.next_id.fetch_add(1, Ordering::SeqCst));
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), tx);
+        // Forgets the request if the tool call is cancelled while waiting
+        let _pending = Pending { bridge: self, id: &id };
+
+        self.notifier
+            .send(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params
+            }))
+            .ok()
+            .context("No client is listening for server requests")?;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(e))) => anyhow::bail!("The client rejected {}: {}", method, e),
+            Ok(Err(_)) => anyhow::bail!("The server stopped before the client answered {}", method),
+            Err(_) => anyhow::bail!("The client did not answer {} within {}s", method, timeout.as_secs()),
+        }
+    }
+
+    /// Deliver the client's response to the request with `id`. Returns false if no
+    /// request is waiting for it (unknown id, or it already timed out).
+    pub fn respond(&self, id: &Value, answer: Result<Value, String>) -> bool {
+        let Some(id) = id.as_str() else {
+            return false;
+        };
+        match self.pending.lock().unwrap().remove(id) {
+            Some(tx) => tx.send(answer).is_ok(),
+            None => false,
+        }
+    }
+}
+
+struct Pending<'a> {
+    bridge: &'a ClientBridge,
+    id: &'a str,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.bridge.pending.lock().unwrap().remove(self.id);
+    }
+}
+
+/// Run a tool call so it can make requests to its own client
+pub async fn scope<F: Future>(bridge: Arc<ClientBridge>, call: F) -> F::Output {
+    CURRENT.scope(bridge, call).await
+}
+
+/// The client of the current tool call
+pub fn current() -> Result<Arc<ClientBridge>> {
+    CURRENT
+        .try_with(|bridge| bridge.clone())
+        .ok()
+        .context("Not inside a tool call, so there is no client to ask")
+}
+
+/// Have the client's model complete `prompt` with `sampling/createMessage`
+pub async fn create_message(
+    bridge: &ClientBridge,
+    prompt: &str,
+    system_prompt: Option<&str>,
+    max_tokens: u64,
+    timeout: Duration,
+) -> Result<Sampled> {
+    let mut params = json!({
+        "messages": [{
+            "role": "user",
+            "content": { "type": "text", "text": prompt }
+        }],
+        "maxTokens": max_tokens
+    });
+    if let Some(system_prompt) = system_prompt {
+        params["systemPrompt"] = json!(system_prompt);
+    }
+
+    let result = bridge.request("sampling/createMessage", params, timeout).await?;
+    let text = result["content"]["text"]
+        .as_str()
+        .context("The client's sampling result has no text content")?;
+    Ok(Sampled {
+        text: text.to_string(),
+        model: result["model"].as_str().map(String::from),
+    })
+}
+
+/// A completion returned by [`create_message`]
+pub struct Sampled {
+    pub text: String,
+    pub model: Option<String>,
+}
+
+/// Ask the user for the fields in `schema` (a flat JSON Schema object) with
+/// `elicitation/create`. Returns the action the user took ("accept", "decline" or
+/// "cancel") and, when accepted, the content they entered.
+pub async fn elicit(bridge: &ClientBridge, message: &str, schema: Value, timeout: Duration) -> Result<(String, Option<Value>)> {
+    let params = json!({
+        "message": message,
+        "requestedSchema": schema
+    });
+    let result = bridge.request("elicitation/create", params, timeout).await?;
+    let action = result["action"]
+        .as_str()
+        .context("The client's elicitation result has no action")?;
+    let content = result.get("content").filter(|c| !c.is_null()).cloned();
+    Ok((action.to_string(), content))
+}
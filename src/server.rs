@@ -0,0 +1,2115 @@
+// The MCP server: JSON-RPC dispatch, transports, and a builder for embedding it
+
+use anyhow::{Context as _, Result};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, RawQuery, State,
+    },
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::convert::Infallible;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::task::JoinSet;
+use tokio_stream::{wrappers::{BroadcastStream, WatchStream}, Stream, StreamExt};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::Instrument;
+
+use crate::audit::{self, AuditLog, Caller, Outcome};
+use crate::auth::{self, Authenticator, Principal};
+use crate::children;
+use crate::error::{Detailed, PolyError};
+use crate::hints;
+use crate::idempotency::IdempotencyCache;
+use crate::logging::{self, LogSink};
+use crate::middleware::{Call, Middleware};
+use crate::naming::ToolNames;
+use crate::plugins;
+use crate::policy::{Permit, Policy};
+use crate::profiles;
+use crate::provenance;
+use crate::sampling::{self, ClientBridge};
+use crate::session::{self, SessionError, SessionManager};
+use crate::storage::{self, MemoryStorage, Namespaced, Storage};
+use crate::telemetry;
+use crate::tenants::{Tenant, Tenants};
+use crate::usage::{self, UsageStats};
+
+use crate::config::{module_built, Config, ModuleSelection, MODULE_NAMES};
+use crate::progress;
+use crate::modules::{prompts::PromptsModule, session::{self as session_tools, SessionCwd, SessionModule, ToolEntry}};
+#[cfg(feature = "apps")]
+use crate::modules::apps::AppsModule;
+#[cfg(feature = "calc")]
+use crate::modules::calc::CalcModule;
+#[cfg(feature = "clipboard")]
+use crate::modules::clipboard::ClipboardModule;
+#[cfg(feature = "context")]
+use crate::modules::context::ContextModule;
+#[cfg(feature = "crypto")]
+use crate::modules::crypto::CryptoModule;
+#[cfg(feature = "diagnostics")]
+use crate::modules::diagnostics::DiagnosticsModule;
+#[cfg(feature = "filesystem")]
+use crate::modules::filesystem::FilesystemModule;
+#[cfg(feature = "git")]
+use crate::modules::git::GitModule;
+#[cfg(feature = "input")]
+use crate::modules::input::InputModule;
+#[cfg(feature = "llm")]
+use crate::modules::llm::LlmModule;
+#[cfg(feature = "md")]
+use crate::modules::markdown::MarkdownModule;
+#[cfg(feature = "network")]
+use crate::modules::network::NetworkModule;
+#[cfg(feature = "power")]
+use crate::modules::power::PowerModule;
+#[cfg(feature = "regex")]
+use crate::modules::regex_lab::RegexModule;
+#[cfg(feature = "settings")]
+use crate::modules::settings::SettingsModule;
+#[cfg(feature = "silent")]
+use crate::modules::silent::SilentModule;
+#[cfg(feature = "text")]
+use crate::modules::text::TextModule;
+#[cfg(feature = "time")]
+use crate::modules::time::TimeModule;
+#[cfg(feature = "transform")]
+use crate::modules::transform::TransformModule;
+#[cfg(feature = "txn")]
+use crate::modules::txn::{Transactions, TxnModule};
+#[cfg(feature = "xlsx")]
+use crate::modules::xlsx::XlsxModule;
+#[cfg(feature = "gitent")]
+use crate::modules::gitent::GitentModule;
+#[cfg(feature = "whisper")]
+use crate::modules::media::MediaModule;
+#[cfg(feature = "browser")]
+use crate::modules::browser::BrowserModule;
+#[cfg(feature = "testing")]
+use crate::modules::fixtures::FixturesModule;
+use crate::registry::{ToolModule, ToolOutput, ToolRegistry};
+
+/// The JSON-RPC method that re-reads the config file. `[policy]` tool patterns
+/// apply to it as if it were a tool of the `server` module.
+const RELOAD_METHOD: &str = "config/reload";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcResponse {
+    fn parse_error(e: serde_json::Error) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            result: None,
+            error: Some(JsonRpcError {
+                code: -32700,
+                message: format!("Parse error: {}", e),
+                data: None,
+            }),
+        }
+    }
+}
+
+/// Composes a [`PolyMcp`] server from built-in and custom modules.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let server = poly_mcp::PolyMcpBuilder::new()
+///     .enable(["filesystem", "git"])
+///     .build()?;
+/// let app = server.router();
+/// # let _ = app; Ok(())
+/// # }
+/// ```
+pub struct PolyMcpBuilder {
+    config: Config,
+    default_modules: bool,
+    modules: Vec<Box<dyn ToolModule>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    notifications: broadcast::Sender<Value>,
+    storage: Option<Arc<dyn Storage>>,
+    loader: Option<ConfigLoader>,
+}
+
+/// Produces a fresh [`Config`] for `config/reload`, see [`PolyMcpBuilder::config_loader`]
+pub type ConfigLoader = Arc<dyn Fn() -> Result<Config> + Send + Sync>;
+
+impl Default for PolyMcpBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PolyMcpBuilder {
+    pub fn new() -> Self {
+        let (notifications, _) = broadcast::channel(256);
+        Self {
+            config: Config::default(),
+            default_modules: true,
+            modules: Vec::new(),
+            middlewares: Vec::new(),
+            notifications,
+            storage: None,
+            loader: None,
+        }
+    }
+
+    /// Settings for the built-in modules and the module selection
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Only serve tools from these modules (built-in or custom)
+    pub fn enable<I, S>(mut self, modules: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.modules.enable = modules.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Hide tools from these modules
+    pub fn disable<I, S>(mut self, modules: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.modules.disable.extend(modules.into_iter().map(Into::into));
+        self
+    }
+
+    /// Start from an empty registry instead of the built-in modules
+    pub fn without_default_modules(mut self) -> Self {
+        self.default_modules = false;
+        self
+    }
+
+    /// Register a custom module alongside the built-in ones
+    pub fn module(mut self, module: impl ToolModule + 'static) -> Self {
+        self.modules.push(Box::new(module));
+        self
+    }
+
+    /// Run `middleware` around every tool call, after the middlewares added before it
+    /// (see [`Middleware`] for the order hooks run in)
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Keep persistent state (snapshot history, scheduled tasks, memory values,
+    /// the token cache, and the audit log with `format = "storage"`) in `storage`
+    /// instead of the `[storage]` backend from the config. It is shared by every session.
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Where `config/reload` (and SIGHUP in `serve` and `run_stdio`) gets the new
+    /// config, typically by reading the config file again and re-applying any
+    /// overrides. Without a loader, reloading fails.
+    pub fn config_loader(mut self, loader: impl Fn() -> Result<Config> + Send + Sync + 'static) -> Self {
+        self.loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Sender for server-initiated notifications, for custom modules that push
+    /// messages to clients. Everything sent here reaches stdio, SSE and WebSocket clients.
+    pub fn notifier(&self) -> broadcast::Sender<Value> {
+        self.notifications.clone()
+    }
+
+    /// Fails if two modules provide the same tool or the selection names an unknown module
+    pub fn build(mut self) -> Result<PolyMcp> {
+        profiles::apply(&mut self.config)?;
+        let mut custom_modules: Vec<Arc<dyn ToolModule>> = self.modules.into_iter().map(Arc::from).collect();
+        custom_modules.extend(plugins::load(&self.config.plugins));
+        let enabled_modules = select_modules(&self.config, self.default_modules, &custom_modules)?;
+        let storage = match self.storage {
+            Some(storage) => Some(storage),
+            None => storage::open(&self.config.storage)?,
+        };
+        let auth = Arc::new(match &storage {
+            Some(storage) => Authenticator::with_storage(&self.config.auth, storage.clone())?,
+            None => Authenticator::new(&self.config.auth)?,
+        });
+        let cors = cors_layer(&self.config.server.cors_origins)?;
+        let audit = AuditLog::open(&self.config.audit, storage.as_ref())?.map(Arc::new);
+        let usage = UsageStats::open(&self.config.usage_stats)?;
+        let policy = Arc::new(Policy::new(&self.config.policy)?);
+        let tenants = Tenants::new(&self.config.tenants, &self.config.audit)?;
+        let tenant_policies = tenant_policies(&self.config, &tenants)?;
+        let tool_names = ToolNames::new(&self.config.server)?;
+        let idempotency = IdempotencyCache::new(Duration::from_secs(self.config.server.idempotency_ttl_secs));
+        children::configure(&self.config.children);
+
+        let factory = Arc::new(ServerFactory {
+            startup: Arc::new(self.config.clone()),
+            config: RwLock::new(Arc::new(self.config)),
+            loader: self.loader,
+            default_modules: self.default_modules,
+            custom_modules,
+            middlewares: self.middlewares,
+            enabled_modules: RwLock::new(enabled_modules),
+            auth,
+            cors,
+            audit,
+            usage,
+            policy: RwLock::new(policy),
+            storage,
+            tenants,
+            tenant_policies: RwLock::new(tenant_policies),
+            tool_names,
+            instances: std::sync::Mutex::new(Vec::new()),
+            idempotency,
+        });
+        let server = factory.build(self.notifications, None)?;
+        let tools: Vec<Value> = server.registry.modules().flat_map(|module| module.tools()).collect();
+        factory
+            .tool_names
+            .check(tools.iter().filter_map(|tool| tool["name"].as_str()))?;
+        Ok(server)
+    }
+}
+
+/// Everything needed to build another server instance with fresh module state,
+/// as each HTTP client session gets
+struct ServerFactory {
+    // The latest config; replaced by config/reload, and new instances are built from it
+    config: RwLock<Arc<Config>>,
+    // The config the server started with, for settings a reload cannot change
+    startup: Arc<Config>,
+    loader: Option<ConfigLoader>,
+    default_modules: bool,
+    // Custom modules are shared by every instance
+    custom_modules: Vec<Arc<dyn ToolModule>>,
+    // Shared by every instance too, in the order they were added
+    middlewares: Vec<Arc<dyn Middleware>>,
+    enabled_modules: RwLock<ModuleSelection>,
+    auth: Arc<Authenticator>,
+    cors: CorsLayer,
+    // Shared by every instance so all sessions append to the same log
+    audit: Option<Arc<AuditLog>>,
+    // [usage_stats] counts, when opted in
+    usage: Option<Arc<UsageStats>>,
+    policy: RwLock<Arc<Policy>>,
+    // Persistent state shared by every instance; None keeps each instance's state in memory
+    storage: Option<Arc<dyn Storage>>,
+    // From [tenants]; fixed at startup
+    tenants: Tenants,
+    // Each tenant's [policy], confined to its roots, by tenant name
+    tenant_policies: RwLock<HashMap<String, Arc<Policy>>>,
+    // Names clients see for the tools; fixed at startup
+    tool_names: ToolNames,
+    // Built-in modules of every instance, to hand a reloaded config to
+    instances: std::sync::Mutex<Vec<BuiltModule>>,
+    // Results of calls made with an idempotency_key, for replaying to retries from
+    // any session of the same caller
+    idempotency: IdempotencyCache,
+}
+
+/// A built-in module of some instance, and the tenant that instance serves
+struct BuiltModule {
+    tenant: Option<Arc<Tenant>>,
+    module: Weak<dyn ToolModule>,
+}
+
+impl ServerFactory {
+    fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    fn enabled_modules(&self) -> ModuleSelection {
+        self.enabled_modules.read().unwrap().clone()
+    }
+
+    /// The policy for calls made by `tenant`'s sessions, or by everyone when `None`
+    fn policy(&self, tenant: Option<&Tenant>) -> Arc<Policy> {
+        match tenant.and_then(|tenant| self.tenant_policies.read().unwrap().get(tenant.name()).cloned()) {
+            Some(policy) => policy,
+            None => self.policy.read().unwrap().clone(),
+        }
+    }
+
+    /// Load the config again and apply it to every instance: the module selection,
+    /// `[policy]`, and whatever each module takes from it (see [`ToolModule::reload`]).
+    /// New sessions are built from it entirely. Settings that only take effect at
+    /// startup are reported back in `restart_required`.
+    async fn reload(&self) -> Result<Value> {
+        let loader = self
+            .loader
+            .as_ref()
+            .context("This server has no config source to reload from")?;
+        let mut config = loader()?;
+        profiles::apply(&mut config)?;
+        // Checked before anything is applied, so a broken file changes nothing
+        let enabled_modules = select_modules(&config, self.default_modules, &self.custom_modules)?;
+        let policy = Arc::new(Policy::new(&config.policy)?);
+        let tenant_policies = tenant_policies(&config, &self.tenants)?;
+
+        let mut modules: Vec<_> = {
+            let mut instances = self.instances.lock().unwrap();
+            instances.retain(|built| built.module.strong_count() > 0);
+            instances
+                .iter()
+                .filter_map(|built| Some((built.tenant.clone(), built.module.upgrade()?)))
+                .collect()
+        };
+        modules.extend(self.custom_modules.iter().map(|module| (None, module.clone())));
+        for (tenant, module) in &modules {
+            let result = match tenant {
+                Some(tenant) => module.reload(&tenant.apply(&config)).await,
+                None => module.reload(&config).await,
+            };
+            if let Err(e) = result {
+                tracing::warn!("Module {} kept its old settings: {:#}", module.name(), e);
+            }
+        }
+
+        *self.config.write().unwrap() = Arc::new(config);
+        let (old, new) = (&self.startup, self.config());
+        let mut restart_required = Vec::new();
+        let bind = |server: &crate::config::ServerConfig| {
+            format!(
+                "{:?}",
+                (
+                    &server.host,
+                    server.port,
+                    &server.cors_origins,
+                    server.session_idle_secs,
+                    server.max_sessions,
+                    server.idempotency_ttl_secs,
+                    &server.tool_prefix,
+                    &server.tool_names,
+                )
+            )
+        };
+        if bind(&old.server) != bind(&new.server) {
+            restart_required.push("server");
+        }
+        for (section, changed) in [
+            ("auth", format!("{:?}", old.auth) != format!("{:?}", new.auth)),
+            ("storage", format!("{:?}", old.storage) != format!("{:?}", new.storage)),
+            ("audit", format!("{:?}", old.audit) != format!("{:?}", new.audit)),
+            ("tenants", format!("{:?}", old.tenants) != format!("{:?}", new.tenants)),
+            ("telemetry", format!("{:?}", old.telemetry) != format!("{:?}", new.telemetry)),
+            ("usage_stats", format!("{:?}", old.usage_stats) != format!("{:?}", new.usage_stats)),
+            ("plugins", format!("{:?}", old.plugins) != format!("{:?}", new.plugins)),
+        ] {
+            if changed {
+                restart_required.push(section);
+            }
+        }
+
+        let modules: Vec<&str> = MODULE_NAMES
+            .iter()
+            .copied()
+            .filter(|module| self.default_modules && module_built(module))
+            .chain(self.custom_modules.iter().map(|module| module.name()))
+            .filter(|module| enabled_modules.is_enabled(module))
+            .collect();
+        let result = json!({
+            "reloaded": true,
+            "modules": modules,
+            "restart_required": restart_required,
+        });
+        *self.enabled_modules.write().unwrap() = enabled_modules;
+        *self.policy.write().unwrap() = policy;
+        *self.tenant_policies.write().unwrap() = tenant_policies;
+        children::configure(&self.config().children);
+
+        if restart_required.is_empty() {
+            tracing::info!("Configuration reloaded");
+        } else {
+            tracing::warn!(
+                "Configuration reloaded; changes to [{}] take effect after a restart",
+                restart_required.join("], [")
+            );
+        }
+        Ok(result)
+    }
+
+    /// A server instance with fresh module state. A tenant's instance sees the
+    /// config through [`Tenant::apply`] and its own part of the shared storage.
+    fn build(self: &Arc<Self>, notifications: broadcast::Sender<Value>, tenant: Option<Arc<Tenant>>) -> Result<PolyMcp> {
+        let config = match &tenant {
+            Some(tenant) => Arc::new(tenant.apply(&self.config())),
+            None => self.config(),
+        };
+        let cwd = Arc::new(SessionCwd::with_dir(config.server.cwd.as_deref())?);
+        let storage: Arc<dyn Storage> = match (&self.storage, &tenant) {
+            (Some(storage), Some(tenant)) => Arc::new(Namespaced::new(storage.clone(), tenant.name())),
+            (Some(storage), None) => storage.clone(),
+            (None, _) => Arc::new(MemoryStorage::new()),
+        };
+        let mut registry = ToolRegistry::new();
+        let mut init_times = Vec::new();
+        if self.default_modules {
+            init_times = register_builtin_modules(&mut registry, &config, &notifications, &cwd, &storage)?;
+            let mut instances = self.instances.lock().unwrap();
+            // Forget the modules of sessions that have ended, or the list grows with every session
+            instances.retain(|built| built.module.strong_count() > 0);
+            instances.extend(registry.handles().map(|module| BuiltModule {
+                tenant: tenant.clone(),
+                module: Arc::downgrade(module),
+            }));
+        }
+        for module in &self.custom_modules {
+            registry.register_shared(module.clone())?;
+        }
+        if config.server.preload {
+            let enabled_modules = self.enabled_modules();
+            for module in registry.modules().filter(|module| enabled_modules.is_enabled(module.name())) {
+                let started = Instant::now();
+                module.warm_up();
+                let elapsed = started.elapsed();
+                match init_times.iter_mut().find(|(name, _)| name == module.name()) {
+                    Some((_, time)) => *time += elapsed,
+                    None => init_times.push((module.name().to_string(), elapsed)),
+                }
+            }
+        }
+
+        Ok(PolyMcp {
+            registry,
+            prompts: PromptsModule::new(),
+            log: LogSink::register(notifications.clone()),
+            client: ClientBridge::new(notifications.clone()),
+            notifications,
+            initialized: Arc::new(AtomicBool::new(false)),
+            in_flight: std::sync::Mutex::new(HashMap::new()),
+            cwd,
+            tenant,
+            init_times,
+            factory: self.clone(),
+        })
+    }
+}
+
+/// The module selection `config` asks for, among the built-in modules (unless
+/// left out) and the custom ones
+fn select_modules(
+    config: &Config,
+    default_modules: bool,
+    custom_modules: &[Arc<dyn ToolModule>],
+) -> Result<ModuleSelection> {
+    let mut known: Vec<&str> = Vec::new();
+    if default_modules {
+        known.extend(MODULE_NAMES);
+    }
+    known.extend(custom_modules.iter().map(|module| module.name()));
+    ModuleSelection::new(&config.modules.enable, &config.modules.disable, &known)
+}
+
+/// Every tenant's policy: `[policy]` seen through the tenant's config
+fn tenant_policies(config: &Config, tenants: &Tenants) -> Result<HashMap<String, Arc<Policy>>> {
+    tenants
+        .iter()
+        .map(|tenant| {
+            let policy = Policy::new(&tenant.apply(config).policy)?;
+            Ok((tenant.name().to_string(), Arc::new(policy)))
+        })
+        .collect()
+}
+
+/// Any origin when `origins` is empty or contains "*", otherwise only those listed
+fn cors_layer(origins: &[String]) -> Result<CorsLayer> {
+    if origins.is_empty() || origins.iter().any(|o| o == "*") {
+        return Ok(CorsLayer::permissive());
+    }
+
+    let origins = origins
+        .iter()
+        .map(|o| {
+            HeaderValue::from_str(o.trim_end_matches('/'))
+                .with_context(|| format!("Invalid CORS origin: {}", o))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static(auth::API_KEY_HEADER),
+            HeaderName::from_static(session::SESSION_HEADER),
+        ])
+        .expose_headers([HeaderName::from_static(session::SESSION_HEADER)]))
+}
+
+fn register_builtin_modules(
+    registry: &mut ToolRegistry,
+    config: &Config,
+    notifications: &broadcast::Sender<Value>,
+    cwd: &Arc<SessionCwd>,
+    storage: &Arc<dyn Storage>,
+) -> Result<Vec<(String, Duration)>> {
+    #[cfg(feature = "txn")]
+    let transactions = Arc::new(Transactions::new());
+    // Only some modules take these
+    #[cfg(not(any(feature = "filesystem", feature = "settings")))]
+    let _ = notifications;
+    #[cfg(not(any(feature = "filesystem", feature = "time", feature = "context")))]
+    let _ = storage;
+    let mut init_times = Vec::new();
+    let times = &mut init_times;
+
+    let builtin: Vec<Box<dyn ToolModule>> = vec![
+        #[cfg(feature = "filesystem")]
+        timed(times, || {
+            let mut filesystem = FilesystemModule::with_config(&config.filesystem);
+            filesystem.set_notifier(notifications.clone());
+            #[cfg(feature = "txn")]
+            filesystem.set_transactions(transactions.clone());
+            filesystem.set_storage(storage.clone());
+            filesystem
+        }),
+        #[cfg(feature = "diagnostics")]
+        timed(times, DiagnosticsModule::new),
+        #[cfg(feature = "silent")]
+        timed(times, SilentModule::new),
+        #[cfg(feature = "time")]
+        timed(times, || TimeModule::with_storage(storage.clone())),
+        #[cfg(feature = "network")]
+        timed(times, || NetworkModule::with_config(&config.network)),
+        #[cfg(feature = "context")]
+        timed(times, || ContextModule::with_storage(storage.clone())),
+        #[cfg(feature = "git")]
+        timed(times, || GitModule::with_config(&config.git)),
+        #[cfg(feature = "input")]
+        timed(times, || InputModule::with_config(&config.input)),
+        #[cfg(feature = "gitent")]
+        timed(times, || GitentModule::with_config(&config.gitent)),
+        #[cfg(feature = "clipboard")]
+        timed(times, ClipboardModule::new),
+        #[cfg(feature = "transform")]
+        timed(times, TransformModule::new),
+        #[cfg(feature = "settings")]
+        timed(times, || {
+            let mut settings = SettingsModule::new();
+            settings.set_notifier(notifications.clone());
+            settings
+        }),
+        #[cfg(feature = "calc")]
+        timed(times, CalcModule::new),
+        #[cfg(feature = "crypto")]
+        timed(times, CryptoModule::new),
+        #[cfg(feature = "regex")]
+        timed(times, RegexModule::new),
+        #[cfg(feature = "text")]
+        timed(times, TextModule::new),
+        #[cfg(feature = "xlsx")]
+        timed(times, XlsxModule::new),
+        #[cfg(feature = "md")]
+        timed(times, || MarkdownModule::with_config(&config.network)),
+        #[cfg(feature = "whisper")]
+        timed(times, || MediaModule::with_config(&config.media)),
+        #[cfg(feature = "browser")]
+        timed(times, || BrowserModule::with_config(&config.browser)),
+        #[cfg(feature = "llm")]
+        timed(times, || LlmModule::with_config(&config.llm)),
+        #[cfg(feature = "power")]
+        timed(times, || PowerModule::with_config(&config.power)),
+        #[cfg(feature = "apps")]
+        timed(times, || AppsModule::with_config(&config.apps)),
+        #[cfg(feature = "txn")]
+        timed(times, || TxnModule::with_transactions(transactions.clone())),
+        timed(times, || {
+            let mut session = SessionModule::with_config(cwd.clone(), config);
+            session.set_storage(storage.clone());
+            session
+        }),
+        #[cfg(feature = "testing")]
+        timed(times, FixturesModule::new),
+    ];
+    for module in builtin {
+        registry.register_boxed(module)?;
+    }
+
+    // VARP premium tools (plan, task, iteration, vaca, workspace)
+    #[cfg(feature = "premium")]
+    match crate::modules::varp_bridge::VarpModule::new() {
+        Ok(Some(v)) => {
+            eprintln!("  VARP premium tools enabled ({} tools)", v.get_tools().len());
+            if let Err(e) = registry.register(v) {
+                eprintln!("  VARP premium: {}", e);
+            }
+        }
+        Ok(None) => {
+            eprintln!("  VARP premium: no license key (set VARP_LICENSE_KEY to enable)");
+        }
+        Err(e) => {
+            eprintln!("  VARP premium: {}", e);
+        }
+    }
+
+    Ok(init_times)
+}
+
+/// Construct a module, noting how long it took under its name
+fn timed<M: ToolModule + 'static>(init_times: &mut Vec<(String, Duration)>, build: impl FnOnce() -> M) -> Box<dyn ToolModule> {
+    let started = Instant::now();
+    let module = build();
+    init_times.push((module.name().to_string(), started.elapsed()));
+    Box::new(module)
+}
+
+pub struct PolyMcp {
+    // Every tool module, routed by tool name
+    registry: ToolRegistry,
+    prompts: PromptsModule,
+    // Server-initiated JSON-RPC notifications, fanned out to stdio, SSE and WebSocket clients
+    notifications: broadcast::Sender<Value>,
+    // Log messages for the client, at the level it set with logging/setLevel
+    log: Arc<LogSink>,
+    // Sampling and elicitation requests that tool calls send to the client
+    client: Arc<ClientBridge>,
+    // Set once the client sends notifications/initialized
+    initialized: Arc<AtomicBool>,
+    // Cancellation handles for running tools/call requests, keyed by request id
+    in_flight: std::sync::Mutex<HashMap<String, oneshot::Sender<()>>>,
+    // Working directory that relative path arguments resolve against, set with session_cwd
+    cwd: Arc<SessionCwd>,
+    // The tenant this instance serves, on servers with [tenants]
+    tenant: Option<Arc<Tenant>>,
+    // How long each module took to construct, plus warming up with [server] preload
+    init_times: Vec<(String, Duration)>,
+    // Builds the per-session instances in HTTP mode, and holds the [auth] and CORS settings
+    factory: Arc<ServerFactory>,
+}
+
+/// Where a tools/list page starts, and the filter it was asked with. Clients get
+/// it as an opaque base64 cursor.
+#[derive(Serialize, Deserialize)]
+struct ToolsPage {
+    offset: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    prefix: Vec<String>,
+}
+
+impl ToolsPage {
+    fn encode(&self) -> String {
+        use base64::Engine;
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(cursor: &str) -> Result<Self> {
+        use base64::Engine;
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(cursor)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+/// Why a tool call produced no result
+#[derive(Debug, thiserror::Error)]
+enum ToolError {
+    #[error(transparent)]
+    Failed(#[from] anyhow::Error),
+    #[error("Tool '{tool}' timed out after {} seconds", .limit.as_secs())]
+    TimedOut { tool: String, limit: Duration },
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+impl ToolError {
+    fn code(&self) -> i32 {
+        match self {
+            ToolError::Failed(e) => PolyError::of(e).code(),
+            ToolError::TimedOut { .. } => PolyError::Timeout(String::new()).code(),
+            ToolError::Cancelled => PolyError::Cancelled(String::new()).code(),
+        }
+    }
+}
+
+impl PolyMcp {
+    pub fn builder() -> PolyMcpBuilder {
+        PolyMcpBuilder::new()
+    }
+
+    pub fn registry(&self) -> &ToolRegistry {
+        &self.registry
+    }
+
+    /// The modules whose tools are served, as of the last config reload
+    pub fn enabled_modules(&self) -> ModuleSelection {
+        self.factory.enabled_modules()
+    }
+
+    /// How long each module of this instance took to start, in registration order:
+    /// construction, plus warming up when `[server] preload` is set
+    pub fn module_init_times(&self) -> &[(String, Duration)] {
+        &self.init_times
+    }
+
+    /// Receive server-initiated notifications
+    pub fn subscribe(&self) -> broadcast::Receiver<Value> {
+        self.notifications.subscribe()
+    }
+
+    /// A new instance with the same modules and settings but its own module state
+    /// and notification channel. Custom modules are shared with this instance.
+    pub fn new_session(&self) -> Result<PolyMcp> {
+        let (notifications, _) = broadcast::channel(256);
+        self.factory.build(notifications, self.tenant.clone())
+    }
+
+    /// The tenant `caller` belongs to, see [`Tenants::of`]
+    pub(crate) fn tenant_of(&self, caller: Option<&str>) -> Result<Option<Arc<Tenant>>> {
+        self.factory.tenants.of(caller)
+    }
+
+    /// Whether the server has `[tenants]`, whose callers must each use a session of their own
+    pub(crate) fn has_tenants(&self) -> bool {
+        !self.factory.tenants.is_empty()
+    }
+
+    /// A new instance serving `tenant`
+    pub(crate) fn new_tenant_session(&self, tenant: Option<Arc<Tenant>>) -> Result<PolyMcp> {
+        let (notifications, _) = broadcast::channel(256);
+        self.factory.build(notifications, tenant)
+    }
+
+    /// Name of the tenant this instance serves
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref().map(Tenant::name)
+    }
+
+    pub(crate) fn server_config(&self) -> crate::config::ServerConfig {
+        self.factory.config().server.clone()
+    }
+
+    /// Read the config again and apply it without dropping clients; what `config/reload`
+    /// and SIGHUP do. Needs [`PolyMcpBuilder::config_loader`].
+    pub async fn reload_config(&self) -> Result<Value> {
+        self.factory.reload().await
+    }
+
+    /// Whether this client may send config/reload, which changes every session: not
+    /// from a tenant's session, and only where `[policy]` allows the method as if it
+    /// were a tool of the `server` module
+    fn authorize_reload(&self) -> std::result::Result<(), PolyError> {
+        if let Some(tenant) = &self.tenant {
+            return Err(PolyError::PolicyDenied(format!(
+                "Policy denied: {} applies to the whole server, and tenant '{}' may not send it",
+                RELOAD_METHOD,
+                tenant.name()
+            )));
+        }
+        if !self.factory.policy(None).allows_tool(RELOAD_METHOD, "server") {
+            return Err(PolyError::PolicyDenied(format!("Policy denied: {} is not allowed on this server", RELOAD_METHOD)));
+        }
+        Ok(())
+    }
+
+    /// Let every module persist its state and release what it holds: gitent storage
+    /// is closed, browsers stopped, leftover script files removed, and child processes
+    /// still running are killed. Call it once no more tool calls will be made;
+    /// `run_stdio` and `serve` do so on exit.
+    pub async fn shutdown(&self) {
+        self.shutdown_modules(true).await;
+        if let Some(usage) = &self.factory.usage {
+            if let Err(e) = usage.write() {
+                tracing::warn!("Failed to write usage report: {:#}", e);
+            }
+        }
+        let killed = children::kill_all();
+        if killed > 0 {
+            tracing::info!("Killed {} child processes still running", killed);
+        }
+    }
+
+    /// Shut down a session's modules, leaving the custom modules it shares with every other instance
+    pub(crate) async fn shutdown_session(&self) {
+        self.shutdown_modules(false).await;
+    }
+
+    async fn shutdown_modules(&self, include_custom: bool) {
+        for module in self.registry.modules() {
+            if self.is_custom(module) && !include_custom {
+                continue;
+            }
+            if let Err(e) = module.shutdown().await {
+                tracing::warn!("Module {} did not shut down cleanly: {:#}", module.name(), e);
+            }
+        }
+    }
+
+    /// Whether `module` is one of the custom modules or plugins every instance shares
+    fn is_custom(&self, module: &dyn ToolModule) -> bool {
+        self.factory
+            .custom_modules
+            .iter()
+            .any(|custom| std::ptr::addr_eq(Arc::as_ptr(custom), module as *const dyn ToolModule))
+    }
+
+    fn get_server_info(&self) -> Value {
+        json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": {
+                "tools": {},
+                "prompts": {},
+                "logging": {}
+            },
+            "serverInfo": {
+                "name": "poly-mcp",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        })
+    }
+
+    /// tools/list, one page at a time when `[server] tools_page_size` is set. `prefix`
+    /// (a string or an array of strings) keeps only tools whose names start with one
+    /// of them, e.g. "fs_". The returned `nextCursor` carries the filter along, so
+    /// later pages only need the cursor.
+    fn list_tools(&self, params: &Value) -> Result<Value> {
+        let page = match params.get("cursor").and_then(Value::as_str) {
+            Some(cursor) => ToolsPage::decode(cursor).context("Invalid cursor")?,
+            None => ToolsPage {
+                offset: 0,
+                prefix: match &params["prefix"] {
+                    Value::Null => Vec::new(),
+                    Value::String(prefix) => vec![prefix.clone()],
+                    Value::Array(prefixes) => prefixes
+                        .iter()
+                        .map(|p| p.as_str().map(str::to_string).context("'prefix' must be a string or an array of strings"))
+                        .collect::<Result<_>>()?,
+                    _ => anyhow::bail!("'prefix' must be a string or an array of strings"),
+                },
+            },
+        };
+
+        // Prefix filters match the names clients see
+        let tools: Vec<Value> = self
+            .visible_tools()
+            .into_iter()
+            .filter(|tool| {
+                let name = tool["name"].as_str().unwrap_or_default();
+                page.prefix.is_empty() || page.prefix.iter().any(|prefix| name.starts_with(prefix.as_str()))
+            })
+            .collect();
+
+        let size = match self.factory.config().server.tools_page_size {
+            0 => tools.len(),
+            size => size,
+        };
+        let end = tools.len().min(page.offset.saturating_add(size));
+        let mut result = json!({ "tools": tools.get(page.offset..end).unwrap_or_default() });
+        if end < tools.len() {
+            let next = ToolsPage { offset: end, ..page };
+            result["nextCursor"] = json!(next.encode());
+        }
+        Ok(result)
+    }
+
+    /// Definitions of the tools this instance's client may call, under the names the
+    /// client knows them by. Tools from disabled modules, and tools the policy denies,
+    /// are hidden.
+    fn visible_tools(&self) -> Vec<Value> {
+        self.visible_entries().into_iter().map(|entry| entry.tool).collect()
+    }
+
+    /// The tools of [`PolyMcp::visible_tools`], with the module and built-in name of each
+    fn visible_entries(&self) -> Vec<ToolEntry> {
+        let policy = self.factory.policy(self.tenant.as_deref());
+        let enabled_modules = self.factory.enabled_modules();
+        self.registry
+            .modules()
+            .filter(|module| enabled_modules.is_enabled(module.name()))
+            .flat_map(|module| {
+                let policy = &policy;
+                module
+                    .tools()
+                    .into_iter()
+                    .filter(move |tool| policy.allows_tool(tool["name"].as_str().unwrap_or_default(), module.name()))
+                    .map(move |tool| (module.name().to_string(), tool))
+            })
+            .map(|(module, mut tool)| {
+                let name = tool["name"].as_str().unwrap_or_default().to_string();
+                tool["name"] = json!(self.factory.tool_names.expose(&name));
+                ToolEntry { module, name, tool }
+            })
+            .collect()
+    }
+
+    /// Names of the visible tools closest to `name`, a tool the client called that does not exist
+    fn closest_tools(&self, name: &str) -> Vec<String> {
+        let tools = self.visible_tools();
+        hints::closest(name, tools.iter().filter_map(|tool| tool["name"].as_str()))
+    }
+
+    /// The error for a call to `name`, which is not a tool, naming the closest real ones
+    fn unknown_tool(&self, name: &str) -> anyhow::Error {
+        let message = match self.closest_tools(name).as_slice() {
+            [] => format!("Unknown tool: {}", name),
+            [only] => format!("Unknown tool: {}. Did you mean '{}'?", name, only),
+            several => format!("Unknown tool: {}. Did you mean one of '{}'?", name, several.join("', '")),
+        };
+        PolyError::UnknownTool(message).into()
+    }
+
+    /// Rename arguments of a call to `tool` that match one of its parameters but for
+    /// case and separators (`filePath` for `file_path`), returning what was renamed.
+    /// Arguments that look like misspellings of a parameter the call lacks fail it,
+    /// naming the likely parameters, rather than let the tool run without it.
+    fn correct_arguments(&self, tool: &str, args: &mut Value) -> Result<Map<String, Value>> {
+        let mut corrected = Map::new();
+        let (Some(params), Some(object)) = (self.registry.parameters(tool), args.as_object_mut()) else {
+            return Ok(corrected);
+        };
+        let unknown: Vec<String> = object.keys().filter(|key| !params.contains(key)).cloned().collect();
+
+        let mut misspelled = Map::new();
+        for key in unknown {
+            let absent = params.iter().filter(|param| !object.contains_key(*param)).map(String::as_str);
+            let candidates = hints::closest_params(&key, absent);
+            match candidates.first() {
+                Some(param) if hints::normalize(param) == hints::normalize(&key) => {
+                    if let Some(value) = object.remove(&key) {
+                        object.insert(param.clone(), value);
+                    }
+                    corrected.insert(key, json!(param));
+                }
+                Some(_) => {
+                    misspelled.insert(key, json!(candidates));
+                }
+                None => {}
+            }
+        }
+        if misspelled.is_empty() {
+            return Ok(corrected);
+        }
+
+        let guesses: Vec<String> = misspelled
+            .iter()
+            .map(|(key, candidates)| {
+                let candidates: Vec<&str> = candidates.as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+                format!("'{}' (did you mean '{}'?)", key, candidates.join("' or '"))
+            })
+            .collect();
+        // With the corrections above already made
+        let mut retry = object.clone();
+        for (key, candidates) in &misspelled {
+            if let (Some(value), Some(param)) = (retry.remove(key), candidates[0].as_str()) {
+                retry.insert(param.to_string(), value);
+            }
+        }
+        Err(PolyError::InvalidArguments(format!("Unknown argument {} for {}", guesses.join(", "), tool)).with_data(json!({
+            "did_you_mean": misspelled,
+            "suggestions": [{ "tool": tool, "arguments": retry }],
+        })))
+    }
+
+    /// Run a tool directly, bypassing JSON-RPC. The configured timeout still applies.
+    pub async fn call_tool(&self, name: &str, arguments: Option<Value>) -> Result<Value> {
+        match self.execute_tool(name, arguments, None, None, None).await {
+            Ok(output) => output.into_value(),
+            Err(ToolError::Failed(e)) => Err(e),
+            Err(other) => Err(anyhow::anyhow!(other)),
+        }
+    }
+
+    /// Run a tool on its own task so a timeout or cancellation can abandon it even
+    /// when the tool is blocked, and so a panicking tool does not take the server down.
+    ///
+    /// Every call, including failed ones, is recorded in the audit log if one is configured,
+    /// and runs in a span whose outcome also goes to the tool-call metrics (see [`telemetry`]).
+    /// The audit log gets the arguments as the middlewares left them.
+    async fn execute_tool(
+        &self,
+        name: &str,
+        arguments: Option<Value>,
+        cancel: Option<oneshot::Receiver<()>>,
+        progress_token: Option<Value>,
+        request_id: Option<&Value>,
+    ) -> Result<ToolOutput, ToolError> {
+        // A tenant with its own audit log gets its calls recorded there only
+        let audit = self.tenant.as_ref().and_then(|tenant| tenant.audit()).or(self.factory.audit.as_ref());
+        let module = self.registry.module_for(name).map(|module| module.name());
+        let span = telemetry::tool_span(name, module, self.tenant.as_ref().map(|tenant| tenant.name()));
+
+        let mut args = arguments.unwrap_or(json!({}));
+        let call = Call {
+            tool: name,
+            module: self.registry.module_for(name),
+            tenant: self.tenant.as_ref().map(|tenant| tenant.name()),
+            started: Instant::now(),
+        };
+        let result = self
+            .run_middlewares(&call, &mut args, cancel, progress_token)
+            .instrument(span.clone())
+            .await;
+        let elapsed = call.elapsed();
+
+        let message = result.as_ref().err().map(ToString::to_string);
+        let outcome = match &result {
+            Ok(_) => Outcome::Ok,
+            Err(ToolError::TimedOut { .. }) => Outcome::TimedOut,
+            Err(ToolError::Cancelled) => Outcome::Cancelled,
+            Err(ToolError::Failed(_)) => Outcome::Error(message.as_deref().unwrap_or_default()),
+        };
+        telemetry::record_call(&span, name, module, outcome.label(), message.as_deref(), elapsed);
+        if let Some(usage) = &self.factory.usage {
+            let counted = match self.registry.module_for(name) {
+                None => usage::UNKNOWN_TOOLS,
+                Some(module) if self.is_custom(module) => usage::CUSTOM_TOOLS,
+                Some(_) => name,
+            };
+            usage.record(counted, outcome.label(), elapsed);
+        }
+        if let Some(audit) = audit {
+            audit.record(name, module, &args, request_id, elapsed, outcome);
+        }
+        result
+    }
+
+    /// Run the call through the middlewares' `before_call` hooks, the tool, then the
+    /// `after_call` or `on_error` hooks of the middlewares that let it through
+    async fn run_middlewares(
+        &self,
+        call: &Call<'_>,
+        args: &mut Value,
+        cancel: Option<oneshot::Receiver<()>>,
+        progress_token: Option<Value>,
+    ) -> Result<ToolOutput, ToolError> {
+        let middlewares = &self.factory.middlewares;
+        let mut passed = 0;
+        let mut answer = None;
+        for middleware in middlewares {
+            match middleware.before_call(call, args).await {
+                Ok(None) => passed += 1,
+                Ok(Some(result)) => answer = Some(Ok(result)),
+                Err(e) => answer = Some(Err(ToolError::Failed(e))),
+            }
+            if answer.is_some() {
+                break;
+            }
+        }
+        let mut result = match answer {
+            Some(answer) => answer,
+            None => match self.run_tool(call.tool, Some(args.clone()), cancel, progress_token).await {
+                // after_call hooks take the result as a Value
+                Ok(output) if passed > 0 => output.into_value().map_err(ToolError::Failed),
+                Ok(output) => return Ok(output),
+                Err(e) => Err(e),
+            },
+        };
+
+        for middleware in middlewares[..passed].iter().rev() {
+            result = match result {
+                Ok(mut value) => match middleware.after_call(call, args, &mut value).await {
+                    Ok(()) => Ok(value),
+                    Err(e) => Err(ToolError::Failed(e)),
+                },
+                Err(ToolError::Failed(e)) => {
+                    middleware.on_error(call, args, &e).await;
+                    Err(ToolError::Failed(e))
+                }
+                Err(other) => {
+                    middleware.on_error(call, args, &anyhow::anyhow!(other.to_string())).await;
+                    Err(other)
+                }
+            };
+        }
+        result.map(ToolOutput::from)
+    }
+
+    /// A call with an `idempotency_key` that already succeeded returns the stored result
+    /// instead of running again; a retry sent while the first call runs waits for it.
+    /// Retries are matched by their arguments after session-cwd resolution, and pass
+    /// the policy, rate limit and quota checks like any call before a result is replayed.
+    async fn run_tool(
+        &self,
+        name: &str,
+        arguments: Option<Value>,
+        cancel: Option<oneshot::Receiver<()>>,
+        progress_token: Option<Value>,
+    ) -> Result<ToolOutput, ToolError> {
+        let mut args = arguments.unwrap_or(json!({}));
+        let key = IdempotencyCache::take_key(&mut args)?;
+        let call = self.check_call(name, args)?;
+        let Some(key) = key else {
+            return self.invoke_tool(name, call, cancel, progress_token).await;
+        };
+
+        // Keys belong to an authenticated caller rather than the session, which a retry
+        // may not share; anonymous callers only have their session to tell them apart
+        let owner = match (&call.caller.name, &call.caller.session) {
+            (Some(name), _) => format!("{}\n{}", call.caller.tenant.as_deref().unwrap_or_default(), name),
+            (None, Some(session)) => format!("{}\nsession {}", call.caller.tenant.as_deref().unwrap_or_default(), session),
+            (None, None) => format!("{}\n", call.caller.tenant.as_deref().unwrap_or_default()),
+        };
+        let idempotency = &self.factory.idempotency;
+        let slot = idempotency.slot(&owner, &key, name, &call.args)?;
+        // Waiting for the first call is bound by the same timeout and cancellation as running
+        let mut cancel = cancel;
+        let limit = self.factory.config().timeouts.for_tool(name);
+        let mut stored = tokio::select! {
+            stored = slot.lock() => stored,
+            _ = deadline(limit) => {
+                return Err(ToolError::TimedOut { tool: name.to_string(), limit: limit.unwrap_or_default() });
+            }
+            _ = cancelled(&mut cancel) => return Err(ToolError::Cancelled),
+        };
+        if let Some(result) = &*stored {
+            tracing::debug!("Replaying the result of '{}' for idempotency key '{}'", name, key);
+            return Ok(result.clone());
+        }
+        let result = self.invoke_tool(name, call, cancel, progress_token).await;
+        match &result {
+            Ok(result) => *stored = Some(result.clone()),
+            Err(_) => idempotency.forget(&owner, &key, &slot),
+        }
+        result
+    }
+
+    /// Find the tool, count the call against the tenant's quota, correct and resolve
+    /// its arguments, and check them against the policy
+    fn check_call(&self, name: &str, mut args: Value) -> Result<CheckedCall, ToolError> {
+        let module = self
+            .registry
+            .get(name)
+            .ok_or_else(|| self.unknown_tool(name))?;
+        if let Some(tenant) = &self.tenant {
+            tenant.count_call()?;
+        }
+        self.factory.enabled_modules().check_tool(name, module.name())?;
+        let corrected = self.correct_arguments(name, &mut args)?;
+        self.cwd.resolve_args(name, module.name(), &mut args);
+        let policy = self.factory.policy(self.tenant.as_deref());
+        // Read here because the caller does not carry over to the spawned task
+        let caller = audit::current_caller();
+        let permit = policy.check(name, module.name(), &args, &caller)?;
+        Ok(CheckedCall { module, args, corrected, policy, caller, permit })
+    }
+
+    async fn invoke_tool(
+        &self,
+        name: &str,
+        call: CheckedCall,
+        cancel: Option<oneshot::Receiver<()>>,
+        progress_token: Option<Value>,
+    ) -> Result<ToolOutput, ToolError> {
+        let CheckedCall { module, args, corrected, policy, caller, permit } = call;
+        // Held until the call ends, however it ends
+        let _permit = permit;
+
+        // Kills the child processes the call leaves running when it ends, however it ends
+        let call = children::Call::new(name);
+        let record = provenance::Record::new(name);
+
+        let tool = name.to_string();
+        let notifier = self.notifications.clone();
+        let log = self.log.clone();
+        let client = self.client.clone();
+        // Only tool_help reads it, and listing the tools is not free
+        let catalog = (name == session_tools::TOOL_HELP).then(|| Arc::new(self.visible_entries()));
+        let mut task = tokio::spawn(progress::scope(progress_token, notifier, logging::scope(log, sampling::scope(client, call.scope(record.scope(session_tools::catalog_scope(catalog, async move {
+            // Waiting for approval counts toward the timeout and can be cancelled
+            if policy.needs_approval(&tool, module.name(), &args) {
+                policy.approve(&tool, &args, &caller).await?;
+            }
+            let mut result = module.call_output(&tool, args).await?;
+            if !corrected.is_empty() {
+                result.insert("_corrected_arguments", Value::Object(corrected));
+            }
+            Ok(result)
+        })))))).in_current_span());
+        // Abandoned along with this call, e.g. when the server stops waiting for it at shutdown
+        let _abort = AbortOnDrop(task.abort_handle());
+
+        let limit = self.factory.config().timeouts.for_tool(name);
+        let mut cancel = cancel;
+
+        tokio::select! {
+            joined = &mut task => match joined {
+                Ok(Ok(mut result)) => {
+                    if self.factory.config().server.provenance {
+                        record.attach(&mut result);
+                    }
+                    Ok(result)
+                }
+                Ok(Err(e)) => Err(ToolError::Failed(e)),
+                Err(e) => Err(anyhow::anyhow!("Tool '{}' panicked: {}", name, e).into()),
+            },
+            _ = deadline(limit) => {
+                task.abort();
+                Err(ToolError::TimedOut {
+                    tool: name.to_string(),
+                    limit: limit.unwrap_or_default(),
+                })
+            }
+            _ = cancelled(&mut cancel) => {
+                task.abort();
+                Err(ToolError::Cancelled)
+            }
+        }
+    }
+
+    /// The failure's category as `code` (see [`PolyError`]), with remediation hints
+    /// for the failures [`hints::enrich`] knows how to explain
+    fn error_data(&self, name: &str, arguments: Option<&Value>, error: &ToolError) -> Value {
+        let error = match error {
+            ToolError::Failed(e) => e,
+            ToolError::TimedOut { tool, .. } => {
+                let mut data = hints::timed_out(tool);
+                data["code"] = json!(PolyError::Timeout(String::new()).name());
+                return data;
+            }
+            ToolError::Cancelled => return json!({ "code": PolyError::Cancelled(String::new()).name() }),
+        };
+        let category = PolyError::of(error);
+        let code = category.name();
+        if let PolyError::UnknownTool(_) = category {
+            let mut data = hints::unknown_tool(name, arguments.unwrap_or(&json!({})), &self.closest_tools(name));
+            data["code"] = json!(code);
+            return data;
+        }
+        // Hints refer to the paths the tool actually saw
+        let mut args = arguments.cloned().unwrap_or(json!({}));
+        if let Some(module) = self.registry.module_for(name) {
+            self.cwd.resolve_args(name, module.name(), &mut args);
+        }
+        let enabled_modules = self.factory.enabled_modules();
+        let policy = self.factory.policy(self.tenant.as_deref());
+        let hint = hints::enrich(name, &args, error, |tool| {
+            self.registry.module_for(tool).is_some_and(|module| {
+                enabled_modules.is_enabled(module.name()) && policy.allows_tool(tool, module.name())
+            })
+        });
+        let mut data = hint.unwrap_or_else(|| json!({}));
+        data["code"] = json!(code);
+        if let (Some(fields), Some(data)) = (Detailed::data_of(error).and_then(Value::as_object), data.as_object_mut()) {
+            data.extend(fields.clone());
+        }
+        // Suggested calls must use the names the client knows the tools by
+        if let Some(suggestions) = data.get_mut("suggestions").and_then(Value::as_array_mut) {
+            for suggestion in suggestions {
+                if let Some(tool) = suggestion["tool"].as_str() {
+                    suggestion["tool"] = json!(self.factory.tool_names.expose(tool));
+                }
+            }
+        }
+        data
+    }
+
+    /// Cancel a running tools/call request. Unknown or finished ids are ignored.
+    fn cancel_request(&self, id: &Value) {
+        let handle = self.in_flight.lock().unwrap().remove(&id.to_string());
+        if let Some(handle) = handle {
+            let _ = handle.send(());
+        }
+    }
+
+    /// Entry point for raw messages from a transport: requests and notifications for
+    /// the server, and the client's responses to requests the server sent it (see
+    /// [`sampling`]), which never get a response themselves.
+    pub async fn handle_json(&self, message: Value) -> Option<JsonRpcResponse> {
+        if message.get("method").is_none() && (message.get("result").is_some() || message.get("error").is_some()) {
+            let id = message.get("id").cloned().unwrap_or(Value::Null);
+            let answer = match message.get("error").filter(|error| !error.is_null()) {
+                Some(error) => Err(error["message"].as_str().unwrap_or("unknown error").to_string()),
+                None => Ok(message["result"].clone()),
+            };
+            if !self.client.respond(&id, answer) {
+                tracing::debug!("Ignoring a response to unknown or expired request {}", id);
+            }
+            return None;
+        }
+
+        match serde_json::from_value::<JsonRpcRequest>(message) {
+            Ok(request) => self.handle_message(request).await,
+            Err(e) => Some(JsonRpcResponse::parse_error(e)),
+        }
+    }
+
+    /// Entry point for every incoming request. Requests without an id are JSON-RPC
+    /// notifications and never get a response.
+    pub async fn handle_message(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        if request.id.is_some() {
+            return Some(self.handle_request(request).await);
+        }
+
+        match request.method.as_str() {
+            "notifications/initialized" => {
+                self.initialized.store(true, Ordering::SeqCst);
+            }
+            // MCP cancellation, plus the LSP-style $/cancelRequest some clients send
+            "notifications/cancelled" | "$/cancelRequest" => {
+                let params = request.params.unwrap_or(json!({}));
+                if let Some(id) = params.get("requestId").or_else(|| params.get("id")) {
+                    self.cancel_request(id);
+                }
+            }
+            // Other client notifications (roots/list_changed, progress, ...) need no action yet
+            method if method.starts_with("notifications/") => {}
+            // A request sent without an id still runs, but its result is discarded
+            _ => {
+                let _ = self.handle_request(request).await;
+            }
+        }
+
+        None
+    }
+
+    async fn handle_request(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let id = request.id.clone();
+
+        match request.method.as_str() {
+            "initialize" => {
+                // Decides whether tools may ask the client for sampling or elicitation
+                if let Some(capabilities) = request.params.as_ref().and_then(|params| params.get("capabilities")) {
+                    self.client.set_capabilities(capabilities.clone());
+                }
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: Some(self.get_server_info()),
+                    error: None,
+                }
+            }
+            "tools/list" => match self.list_tools(request.params.as_ref().unwrap_or(&Value::Null)) {
+                Ok(result) => JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: Some(result),
+                    error: None,
+                },
+                Err(e) => JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: None,
+                    error: Some(JsonRpcError {
+                        code: -32602,
+                        message: e.to_string(),
+                        data: None,
+                    }),
+                },
+            },
+            "tools/describe" => {
+                let name = request.params.as_ref().and_then(|params| params["name"].as_str()).unwrap_or_default();
+                match session_tools::describe_tool(name, &self.visible_entries()) {
+                    Ok(result) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(e) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: -32602,
+                            message: e.to_string(),
+                            data: None,
+                        }),
+                    },
+                }
+            }
+            "logging/setLevel" => {
+                let level = request.params.as_ref().and_then(|params| params["level"].as_str()).unwrap_or_default();
+                match self.log.set_level(level) {
+                    Ok(()) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(json!({})),
+                        error: None,
+                    },
+                    Err(e) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: -32602,
+                            message: e.to_string(),
+                            data: None,
+                        }),
+                    },
+                }
+            }
+            RELOAD_METHOD => match self.authorize_reload() {
+                Err(e) => JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: None,
+                    error: Some(JsonRpcError {
+                        code: e.code(),
+                        message: e.to_string(),
+                        data: None,
+                    }),
+                },
+                Ok(()) => match self.reload_config().await {
+                    Ok(result) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(e) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: -32000,
+                            message: format!("Config reload failed; keeping the current config: {:#}", e),
+                            data: None,
+                        }),
+                    },
+                },
+            },
+            "prompts/list" => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: Some(json!({ "prompts": self.prompts.get_prompts() })),
+                error: None,
+            },
+            "prompts/get" => {
+                let params = request.params.unwrap_or(json!({}));
+                let name = params["name"].as_str().unwrap_or("");
+                let arguments = params.get("arguments").cloned();
+
+                match self.prompts.get_prompt(name, arguments) {
+                    Ok(result) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(e) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: -32602,
+                            message: e.to_string(),
+                            data: None,
+                        }),
+                    },
+                }
+            }
+            "tools/call" => {
+                let params = request.params.unwrap_or(json!({}));
+                let exposed = params["name"].as_str().unwrap_or("");
+                let name = self.factory.tool_names.resolve(exposed);
+                let arguments = params.get("arguments").cloned();
+                // Clients opt into streamed partial output by passing a progress token
+                let progress_token = params["_meta"].get("progressToken").cloned();
+
+                let key = id.as_ref().map(|id| id.to_string());
+                let cancel = key.as_ref().map(|key| {
+                    let (tx, rx) = oneshot::channel();
+                    self.in_flight.lock().unwrap().insert(key.clone(), tx);
+                    rx
+                });
+
+                let result = match &name {
+                    Some(name) => self.execute_tool(name, arguments, cancel, progress_token, id.as_ref()).await,
+                    // Includes the built-in names of prefixed and renamed tools
+                    None => Err(self.unknown_tool(exposed).into()),
+                };
+                if let Some(key) = key {
+                    self.in_flight.lock().unwrap().remove(&key);
+                }
+
+                match result {
+                    Ok(result) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: Some(json!({
+                            "content": [
+                                {
+                                    "type": "text",
+                                    "text": result.into_text()
+                                }
+                            ]
+                        })),
+                        error: None,
+                    },
+                    Err(e) => JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: e.code(),
+                            message: e.to_string(),
+                            data: Some(self.error_data(name.as_deref().unwrap_or(exposed), params.get("arguments"), &e)),
+                        }),
+                    },
+                }
+            }
+            _ => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: -32601,
+                    message: format!("Method not found: {}", request.method),
+                    data: None,
+                }),
+            },
+        }
+    }
+
+    /// HTTP transport: JSON-RPC over POST `/` and `/jsonrpc`, WebSocket on `/ws`,
+    /// notifications over SSE on `/events`, and `/health`. Mount it under a prefix
+    /// with `Router::nest` to embed it in a larger application.
+    ///
+    /// Requests are handled concurrently; a slow tool call does not hold up others.
+    /// When `[auth]` configures credentials, every route except `/health` answers
+    /// 401 without them.
+    ///
+    /// An `initialize` request without an `Mcp-Session-Id` header starts a client
+    /// session with its own module state, and the id comes back in that header.
+    /// Requests carrying it use the session; `DELETE /` ends it. Requests without
+    /// it share this instance. Each WebSocket connection is its own session.
+    pub fn router(self) -> Router {
+        self.into_router().0
+    }
+
+    /// HTTP transport on `listener`, until SIGINT or SIGTERM. Shutdown stops accepting
+    /// connections and requests, ends SSE streams, and closes WebSockets once their
+    /// pending responses are sent. In-flight calls get `[timeouts] shutdown_grace_secs`
+    /// (or until a second signal) to finish, then every session's modules are shut down.
+    pub async fn serve(self, listener: tokio::net::TcpListener) -> Result<()> {
+        let grace = Duration::from_secs(self.factory.config().timeouts.shutdown_grace_secs);
+        let _reload = AbortOnDrop(tokio::spawn(reload_on_hangup(self.factory.clone())).abort_handle());
+        let (app, sessions) = self.into_router();
+
+        let closing = sessions.clone();
+        let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("Shutting down: finishing in-flight requests");
+            closing.close();
+        });
+        let deadline = async {
+            let _ = sessions.closing().wait_for(|closing| *closing).await;
+            tokio::select! {
+                _ = tokio::time::sleep(grace) => {}
+                _ = shutdown_signal() => {}
+            }
+        };
+
+        tokio::select! {
+            result = server => result?,
+            _ = deadline => tracing::warn!("In-flight requests did not finish in time; abandoning them"),
+        }
+
+        sessions.shutdown().await;
+        Ok(())
+    }
+
+    fn into_router(self) -> (Router, SharedState) {
+        let auth = self.factory.auth.clone();
+        let cors = self.factory.cors.clone();
+        let state: SharedState = Arc::new(SessionManager::new(self));
+
+        let mut routes = Router::new()
+            .route("/", post(handle_jsonrpc).delete(handle_end_session))
+            .route("/jsonrpc", post(handle_jsonrpc).delete(handle_end_session))
+            .route("/ws", get(handle_ws))
+            .route("/events", get(handle_sse));
+        if auth.is_enabled() {
+            routes = routes.route_layer(middleware::from_fn_with_state(auth, auth::require_auth));
+        }
+
+        let router = routes
+            .route("/health", get(health_check))
+            .layer(cors)
+            .with_state(state.clone());
+        (router, state)
+    }
+
+    /// Whether HTTP mode requires credentials
+    pub fn requires_auth(&self) -> bool {
+        self.factory.auth.is_enabled()
+    }
+
+    /// Where tool calls are audited, if anywhere
+    pub fn audit_log_path(&self) -> Option<&std::path::Path> {
+        self.factory.audit.as_deref().map(AuditLog::path)
+    }
+
+    /// stdio transport: reads one JSON-RPC message per line from stdin and writes
+    /// responses and notifications to stdout until stdin closes or the process gets
+    /// SIGINT or SIGTERM.
+    ///
+    /// Each request runs on its own task, so responses may arrive out of order;
+    /// clients match them up by id. In-flight requests finish before this returns;
+    /// after a signal they get `[timeouts] shutdown_grace_secs` (or until a second
+    /// signal). The modules are then shut down.
+    pub async fn run_stdio(self) -> Result<()> {
+        let server = Arc::new(self);
+        let grace = Duration::from_secs(server.factory.config().timeouts.shutdown_grace_secs);
+        let _reload = AbortOnDrop(tokio::spawn(reload_on_hangup(server.factory.clone())).abort_handle());
+
+        // A single writer owns stdout. Notifications (once the client has finished
+        // initializing) are written before responses that are ready at the same time,
+        // so a tool's streamed output always precedes its result.
+        let mut notifications = server.subscribe();
+        let initialized = server.initialized.clone();
+        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<String>();
+        let writer = tokio::spawn(async move {
+            loop {
+                let line = tokio::select! {
+                    biased;
+                    notification = notifications.recv() => match notification {
+                        Ok(notification) if initialized.load(Ordering::SeqCst) => notification.to_string(),
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    response = response_rx.recv() => match response {
+                        Some(response) => response,
+                        None => break,
+                    },
+                };
+                let mut out = io::stdout().lock();
+                writeln!(out, "{}", line)?;
+                out.flush()?;
+            }
+            Ok::<_, io::Error>(())
+        });
+
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut in_flight = JoinSet::new();
+        let signal = shutdown_signal();
+        tokio::pin!(signal);
+        let mut signalled = false;
+
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => match line? {
+                    Some(line) => line,
+                    None => break,
+                },
+                _ = &mut signal => {
+                    tracing::info!("Shutting down: finishing {} in-flight request(s)", in_flight.len());
+                    signalled = true;
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let server = server.clone();
+            let response_tx = response_tx.clone();
+            in_flight.spawn(audit::with_caller(Caller::new("stdio"), async move {
+                let response = match serde_json::from_str::<Value>(&line) {
+                    Ok(message) => match server.handle_json(message).await {
+                        Some(response) => response,
+                        None => return Ok(()),
+                    },
+                    Err(e) => JsonRpcResponse::parse_error(e),
+                };
+
+                let response_json = serde_json::to_string(&response)?;
+                // Fails only if the writer stopped because stdout is gone
+                response_tx.send(response_json).context("stdout writer stopped")?;
+                Ok::<_, anyhow::Error>(())
+            }));
+
+            // Reap finished requests so the set does not grow without bound
+            while let Some(done) = in_flight.try_join_next() {
+                done??;
+            }
+        }
+
+        let drained = async {
+            while let Some(done) = in_flight.join_next().await {
+                done??;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        let result = if signalled {
+            tokio::select! {
+                result = drained => result,
+                _ = tokio::time::sleep(grace) => {
+                    tracing::warn!("In-flight requests did not finish in {}s; abandoning them", grace.as_secs());
+                    Ok(())
+                }
+                _ = shutdown_signal() => {
+                    tracing::warn!("Second signal; abandoning in-flight requests");
+                    Ok(())
+                }
+            }
+        } else {
+            drained.await
+        };
+        in_flight.shutdown().await;
+        server.shutdown().await;
+
+        // Let the writer flush the remaining responses, then stop
+        drop(response_tx);
+        writer.await??;
+
+        result
+    }
+}
+
+// Shared state type for HTTP server
+type SharedState = Arc<SessionManager>;
+
+// Name of the authenticated caller, which sessions are tied to
+type Authenticated = Option<Extension<Principal>>;
+
+fn caller_name(principal: &Authenticated) -> Option<&str> {
+    principal.as_ref().map(|Extension(principal)| principal.name.as_str())
+}
+
+// HTTP handler for JSON-RPC requests
+async fn handle_jsonrpc(
+    State(sessions): State<SharedState>,
+    principal: Authenticated,
+    headers: HeaderMap,
+    Json(message): Json<Value>,
+) -> Response {
+    if sessions.is_closing() {
+        return SessionError::ShuttingDown.into_response();
+    }
+    let owner = caller_name(&principal);
+    let session_id = session::session_id(&headers, None);
+    let (server, new_session) = match &session_id {
+        Some(id) => match sessions.get(id, owner) {
+            Ok(server) => (server, None),
+            Err(e) => return e.into_response(),
+        },
+        None if message["method"] == "initialize" => match sessions.create(owner) {
+            Ok((id, server)) => (server, Some(id)),
+            Err(e) => return e.into_response(),
+        },
+        None => match sessions.shared_for(owner) {
+            Ok(server) => (server, None),
+            Err(e) => return e.into_response(),
+        },
+    };
+
+    let audit_caller = Caller {
+        name: owner.map(String::from),
+        transport: "http",
+        session: session_id.or_else(|| new_session.clone()),
+        tenant: server.tenant().map(String::from),
+    };
+    let mut response = match audit::with_caller(audit_caller, server.handle_json(message)).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    };
+    if let Some(id) = new_session.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(session::SESSION_HEADER, id);
+    }
+    response
+}
+
+// HTTP handler ending the session named in the Mcp-Session-Id header
+async fn handle_end_session(State(sessions): State<SharedState>, principal: Authenticated, headers: HeaderMap) -> Response {
+    let Some(id) = session::session_id(&headers, None) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response();
+    };
+    match sessions.remove(&id, caller_name(&principal)).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+// SSE handler: streams server-initiated notifications to HTTP clients
+async fn handle_sse(
+    State(sessions): State<SharedState>,
+    principal: Authenticated,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, SessionError> {
+    if sessions.is_closing() {
+        return Err(SessionError::ShuttingDown);
+    }
+    let server = match session::session_id(&headers, query.as_deref()) {
+        Some(id) => sessions.get(&id, caller_name(&principal))?,
+        None => sessions.shared_for(caller_name(&principal))?,
+    };
+    let notifications = server.subscribe();
+
+    // Lagged receivers skip the notifications they missed. The stream holds on to
+    // the session so it does not expire while the client is listening, and ends
+    // when the server shuts down.
+    let closing = WatchStream::new(sessions.closing())
+        .filter(|closing| *closing)
+        .map(|_| None);
+    let stream = BroadcastStream::new(notifications)
+        .filter_map(|notification| notification.ok())
+        .map(Some)
+        .merge(closing)
+        .take_while(Option::is_some)
+        .filter_map(move |notification| {
+            let _session = &server;
+            notification.map(|notification| Ok(Event::default().event("message").data(notification.to_string())))
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// WebSocket handler: JSON-RPC over a persistent socket, with server push. The
+// connection gets a session of its own unless it names an existing one.
+async fn handle_ws(
+    ws: WebSocketUpgrade,
+    State(sessions): State<SharedState>,
+    principal: Authenticated,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Response {
+    if sessions.is_closing() {
+        return SessionError::ShuttingDown.into_response();
+    }
+    // A connection without a session id gets an instance that lives as long as the socket
+    let owned = session::session_id(&headers, query.as_deref()).is_none();
+    let server = match session::session_id(&headers, query.as_deref()) {
+        Some(id) => sessions.get(&id, caller_name(&principal)),
+        None => sessions.connection(caller_name(&principal)).map(Arc::new),
+    };
+    let audit_caller = Caller {
+        name: caller_name(&principal).map(String::from),
+        transport: "ws",
+        // A connection of its own is a session of its own, e.g. for idempotency keys
+        session: session::session_id(&headers, query.as_deref())
+            .or_else(|| owned.then(|| uuid::Uuid::new_v4().simple().to_string())),
+        tenant: server.as_ref().ok().and_then(|server| server.tenant()).map(String::from),
+    };
+    match server {
+        Ok(server) => {
+            let closing = sessions.closing();
+            ws.on_upgrade(move |socket| run_ws_session(socket, server, audit_caller, closing, owned))
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn run_ws_session(
+    mut socket: WebSocket,
+    state: Arc<PolyMcp>,
+    caller: Caller,
+    mut closing: watch::Receiver<bool>,
+    owned: bool,
+) {
+    let mut notifications = state.subscribe();
+    let closed = async move {
+        let _ = closing.wait_for(|closing| *closing).await;
+    };
+    tokio::pin!(closed);
+    let mut shutting_down = false;
+
+    // Requests are handled on their own tasks so notifications keep flowing
+    // while a long-running tool call is in progress
+    let (response_tx, mut response_rx) = mpsc::channel::<String>(64);
+
+    loop {
+        // Biased towards notifications so a tool's streamed output is sent before its result
+        tokio::select! {
+            biased;
+            notification = notifications.recv() => {
+                match notification {
+                    Ok(notification) => {
+                        if socket.send(Message::Text(notification.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Slow client: drop the missed notifications and keep going
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+            Some(response_json) = response_rx.recv() => {
+                if socket.send(Message::Text(response_json)).await.is_err() {
+                    break;
+                }
+            }
+            _ = &mut closed => {
+                shutting_down = true;
+                break;
+            }
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                if text.trim().is_empty() {
+                    continue;
+                }
+
+                let state = state.clone();
+                let response_tx = response_tx.clone();
+                tokio::spawn(audit::with_caller(caller.clone(), async move {
+                    let response = match serde_json::from_str::<Value>(&text) {
+                        Ok(message) => match state.handle_json(message).await {
+                            Some(response) => response,
+                            None => return,
+                        },
+                        Err(e) => JsonRpcResponse::parse_error(e),
+                    };
+                    if let Ok(response_json) = serde_json::to_string(&response) {
+                        let _ = response_tx.send(response_json).await;
+                    }
+                }));
+            }
+        }
+    }
+
+    if shutting_down {
+        // Answer the requests already received, then close. The channel ends once
+        // every request task has finished.
+        drop(response_tx);
+        while let Some(response_json) = response_rx.recv().await {
+            if socket.send(Message::Text(response_json)).await.is_err() {
+                break;
+            }
+        }
+        let _ = socket.send(Message::Close(None)).await;
+    }
+    // The connection's own instance ends with it, whether the client or the server
+    // closed it, so its watches, child processes and transactions do not outlive it
+    if owned {
+        state.shutdown_session().await;
+    }
+}
+
+// HTTP handler for health check
+async fn health_check(State(sessions): State<SharedState>) -> Response {
+    Json(json!({
+        "status": "healthy",
+        "service": "poly-mcp",
+        "version": env!("CARGO_PKG_VERSION"),
+        "sessions": sessions.len()
+    }))
+    .into_response()
+}
+
+/// Resolves on Ctrl+C (SIGINT) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Cannot listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Reload the config on every SIGHUP (Unix only)
+async fn reload_on_hangup(factory: Arc<ServerFactory>) {
+    #[cfg(unix)]
+    {
+        let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            if let Err(e) = factory.reload().await {
+                tracing::error!("Config reload failed; keeping the current config: {:#}", e);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = factory;
+}
+
+/// Resolves once a call has run for `limit`, or never without one
+async fn deadline(limit: Option<Duration>) {
+    match limit {
+        Some(limit) => tokio::time::sleep(limit).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves when the client cancels the request. A dropped sender means the request
+/// finished, not that it was cancelled, and the receiver is not polled again.
+async fn cancelled(cancel: &mut Option<oneshot::Receiver<()>>) {
+    if let Some(receiver) = cancel.as_mut() {
+        if receiver.await.is_ok() {
+            return;
+        }
+        *cancel = None;
+    }
+    std::future::pending::<()>().await;
+}
+
+/// A tool call that passed its checks, ready to run
+struct CheckedCall {
+    module: Arc<dyn ToolModule>,
+    args: Value,
+    corrected: Map<String, Value>,
+    policy: Arc<Policy>,
+    caller: Caller,
+    // Held until the call ends
+    permit: Permit,
+}
+
+/// Aborts a spawned task when dropped
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -0,0 +1,267 @@
+// Per-client sessions for HTTP mode, each with its own module state
+
+use crate::server::PolyMcp;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Header carrying the session id, as in MCP's streamable HTTP transport
+pub const SESSION_HEADER: &str = "mcp-session-id";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Session not found or expired; send initialize without the Mcp-Session-Id header to start a new one")]
+    NotFound,
+    #[error("Too many active sessions ({0}); try again later or end an unused session")]
+    Full(usize),
+    #[error("Failed to create session: {0}")]
+    Failed(#[from] anyhow::Error),
+    #[error("Server is shutting down")]
+    ShuttingDown,
+    #[error("{0}")]
+    NoTenant(anyhow::Error),
+    #[error("Tenant '{0}' already has its maximum of {1} sessions; end an unused session first")]
+    TenantFull(String, usize),
+    #[error("This server hosts several tenants; send initialize without the Mcp-Session-Id header to start a session")]
+    SessionRequired,
+}
+
+impl IntoResponse for SessionError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            SessionError::NotFound => StatusCode::NOT_FOUND,
+            SessionError::Full(_) | SessionError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            SessionError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SessionError::NoTenant(_) => StatusCode::FORBIDDEN,
+            SessionError::TenantFull(..) => StatusCode::TOO_MANY_REQUESTS,
+            SessionError::SessionRequired => StatusCode::BAD_REQUEST,
+        };
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32000, "message": self.to_string() }
+        });
+        (status, Json(body)).into_response()
+    }
+}
+
+struct Session {
+    server: Arc<PolyMcp>,
+    // Name of the authenticated caller that created the session; only they may use it
+    owner: Option<String>,
+    tenant: Option<String>,
+    last_seen: Instant,
+}
+
+/// Hands out a separate [`PolyMcp`] per client so gitent sessions, snapshots,
+/// clipboard entries and memory are not shared. Requests without a session id
+/// use the shared instance.
+pub struct SessionManager {
+    shared: Arc<PolyMcp>,
+    sessions: Mutex<HashMap<String, Session>>,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    // Set when the server starts shutting down
+    closing: watch::Sender<bool>,
+}
+
+impl SessionManager {
+    pub fn new(shared: PolyMcp) -> Self {
+        let config = shared.server_config();
+        let idle_timeout = Duration::from_secs(config.session_idle_secs);
+        let max_sessions = config.max_sessions;
+        Self {
+            shared: Arc::new(shared),
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+            max_sessions,
+            closing: watch::Sender::new(false),
+        }
+    }
+
+    /// The instance used by clients that do not send a session id
+    pub fn shared(&self) -> &Arc<PolyMcp> {
+        &self.shared
+    }
+
+    /// The shared instance for a request without a session id. Tenants are confined
+    /// to their own sessions, so with `[tenants]` there is none.
+    pub fn shared_for(&self, owner: Option<&str>) -> Result<Arc<PolyMcp>, SessionError> {
+        if self.shared.has_tenants() {
+            self.shared.tenant_of(owner).map_err(SessionError::NoTenant)?;
+            return Err(SessionError::SessionRequired);
+        }
+        Ok(self.shared.clone())
+    }
+
+    /// An instance for one WebSocket connection that did not name a session
+    pub fn connection(&self, owner: Option<&str>) -> Result<PolyMcp, SessionError> {
+        let tenant = self.shared.tenant_of(owner).map_err(SessionError::NoTenant)?;
+        Ok(self.shared.new_tenant_session(tenant)?)
+    }
+
+    /// Start a new session, returning its id
+    pub fn create(&self, owner: Option<&str>) -> Result<(String, Arc<PolyMcp>), SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire_idle(&mut sessions);
+        if sessions.len() >= self.max_sessions {
+            return Err(SessionError::Full(self.max_sessions));
+        }
+
+        let tenant = self.shared.tenant_of(owner).map_err(SessionError::NoTenant)?;
+        if let Some(tenant) = tenant.as_ref().filter(|tenant| tenant.max_sessions() > 0) {
+            let open = sessions
+                .values()
+                .filter(|session| session.tenant.as_deref() == Some(tenant.name()))
+                .count();
+            if open >= tenant.max_sessions() {
+                return Err(SessionError::TenantFull(tenant.name().to_string(), tenant.max_sessions()));
+            }
+        }
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let server = Arc::new(self.shared.new_tenant_session(tenant)?);
+        sessions.insert(
+            id.clone(),
+            Session {
+                tenant: server.tenant().map(String::from),
+                server: server.clone(),
+                owner: owner.map(String::from),
+                last_seen: Instant::now(),
+            },
+        );
+        tracing::info!("Started session {} ({} active)", id, sessions.len());
+        Ok((id, server))
+    }
+
+    /// The session's server instance, marking the session as used
+    pub fn get(&self, id: &str, owner: Option<&str>) -> Result<Arc<PolyMcp>, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.expire_idle(&mut sessions);
+        match sessions.get_mut(id) {
+            // Another caller's session is reported as missing rather than forbidden
+            Some(session) if session.owner.as_deref() == owner => {
+                session.last_seen = Instant::now();
+                Ok(session.server.clone())
+            }
+            _ => Err(SessionError::NotFound),
+        }
+    }
+
+    /// End a session, shutting down its modules
+    pub async fn remove(&self, id: &str, owner: Option<&str>) -> Result<(), SessionError> {
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions.get(id) {
+                Some(session) if session.owner.as_deref() == owner => {
+                    let session = sessions.remove(id);
+                    tracing::info!("Ended session {} ({} active)", id, sessions.len());
+                    session
+                }
+                _ => return Err(SessionError::NotFound),
+            }
+        };
+        if let Some(session) = session {
+            session.server.shutdown_session().await;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Refuse new requests from now on and tell SSE streams and WebSockets to finish
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        *self.closing.borrow()
+    }
+
+    /// Changes to `true` when [`Self::close`] is called
+    pub fn closing(&self) -> watch::Receiver<bool> {
+        self.closing.subscribe()
+    }
+
+    /// Shut down the modules of every session, then of the shared instance
+    pub async fn shutdown(&self) {
+        let sessions: Vec<Session> = self.sessions.lock().unwrap().drain().map(|(_, session)| session).collect();
+        for session in sessions {
+            session.server.shutdown_session().await;
+        }
+        self.shared.shutdown().await;
+    }
+
+    /// Drop sessions idle for longer than the timeout, shutting down their modules
+    /// on a task of their own since the caller holds the lock
+    fn expire_idle(&self, sessions: &mut HashMap<String, Session>) {
+        let now = Instant::now();
+        let expired: Vec<String> = sessions
+            .iter()
+            // A session is busy while a request, SSE stream or socket still holds it
+            .filter(|(_, session)| Arc::strong_count(&session.server) == 1 && now.duration_since(session.last_seen) >= self.idle_timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        let expired: Vec<Session> = expired
+            .into_iter()
+            .filter_map(|id| {
+                tracing::info!("Session {} expired after {}s idle", id, self.idle_timeout.as_secs());
+                sessions.remove(&id)
+            })
+            .collect();
+        tokio::spawn(async move {
+            for session in expired {
+                session.server.shutdown_session().await;
+            }
+        });
+    }
+}
+
+/// The session id from the `Mcp-Session-Id` header, or a `session_id` query
+/// parameter for browser EventSource and WebSocket clients
+pub fn session_id(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .or_else(|| {
+            query?.split('&').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                (name == "session_id").then(|| value.to_string())
+            })
+        })
+        .filter(|id| !id.is_empty())
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn ending_a_session_removes_its_fixtures() {
+        let manager = SessionManager::new(PolyMcp::builder().build().unwrap());
+        let (id, server) = manager.create(None).unwrap();
+        let fixture = server.call_tool("fixture_create", Some(json!({ "template": "rust-crate" }))).await.unwrap();
+        let dir = PathBuf::from(fixture["path"].as_str().unwrap());
+        assert!(dir.exists());
+        drop(server);
+
+        manager.remove(&id, None).await.unwrap();
+        assert!(!dir.exists());
+    }
+}
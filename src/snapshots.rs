@@ -0,0 +1,699 @@
+// Incremental fs_snapshot storage: file contents kept once as blobs named by their hash, and a manifest per snapshot,
+// with older snapshots packed into tar.zst archives
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use walkdir::WalkDir;
+
+use crate::modules::filesystem::tree_size;
+use crate::progress;
+
+/// Extension of a compressed snapshot, in place of `.json`
+pub const ARCHIVE_EXTENSION: &str = ".tar.zst";
+
+// Names inside a compressed snapshot: the manifest, the hashes of the blobs the
+// archive holds, then the blobs. The first two come first so they can be read
+// without decompressing the rest.
+const ARCHIVE_MANIFEST: &str = "manifest.json";
+const ARCHIVE_BLOBS: &str = "blobs.json";
+
+// Taking a snapshot and collecting unreferenced blobs must not overlap, or a blob
+// reused by a snapshot still being taken could be removed under it
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// What one snapshot contains: every file, directory and symlink under the
+/// snapshotted path, with files referring to their content by hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub source: PathBuf,
+    pub timestamp: String,
+    /// Whether `source` was a single file rather than a directory
+    pub file: bool,
+    pub entries: Vec<Entry>,
+}
+
+/// One thing in a snapshot. Paths are relative to the snapshotted directory and
+/// use '/'; a single-file snapshot has one file named after it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Entry {
+    File {
+        path: String,
+        hash: String,
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mtime_ms: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    Dir {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+    },
+    Symlink {
+        path: String,
+        target: PathBuf,
+    },
+}
+
+impl Entry {
+    /// Where the entry is, relative to the snapshotted directory
+    pub fn path(&self) -> &str {
+        match self {
+            Entry::File { path, .. } | Entry::Dir { path, .. } | Entry::Symlink { path, .. } => path,
+        }
+    }
+}
+
+/// How much of a snapshot was already in the store
+#[derive(Debug, Default, Serialize)]
+pub struct Dedup {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files whose content was not in the store yet, and their size
+    pub new_files: usize,
+    pub new_bytes: u64,
+    /// Files whose content an earlier snapshot (or another file) had already stored
+    pub reused_files: usize,
+    pub reused_bytes: u64,
+}
+
+/// What restoring a snapshot changed
+#[derive(Debug, Default, Serialize)]
+pub struct Restored {
+    /// Files written because they were missing or differed, and their size
+    pub written: usize,
+    pub bytes: u64,
+    /// Files that already held the snapshot's content
+    pub unchanged: usize,
+    pub directories: usize,
+    pub symlinks: usize,
+    /// With `delete_extra`: what was removed because the snapshot does not have it
+    pub deleted: Vec<String>,
+    /// Symlinks that cannot be created on this platform
+    pub skipped: Vec<String>,
+}
+
+/// Disk space taken by the snapshots of one path
+#[derive(Debug, Default, Serialize)]
+pub struct DiskUsage {
+    pub snapshots: usize,
+    pub compressed: usize,
+    /// Manifests of the uncompressed snapshots
+    pub manifest_bytes: u64,
+    /// Compressed snapshots, with the blobs only they use
+    pub archive_bytes: u64,
+    /// Blobs shared by the uncompressed snapshots (or by compressed ones with them)
+    pub objects: usize,
+    pub object_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// The snapshots of one path: `objects/` holds the blobs and each snapshot is a
+/// `<name>.json` manifest next to it, or once compressed a `<name>.tar.zst` archive
+/// holding the manifest and the blobs no other snapshot uses
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Snapshot `source` as `name`, storing only the contents the store does not have
+    /// yet. Files whose size and modification time match `previous` are taken to be
+    /// unchanged and not read again.
+    pub fn take(&self, source: &Path, name: &str, timestamp: &str, previous: Option<&Manifest>) -> Result<(PathBuf, Dedup)> {
+        let _lock = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        fs::create_dir_all(self.root.join("objects"))?;
+
+        let known: HashMap<&str, (&str, u64, Option<u64>)> = previous
+            .into_iter()
+            .flat_map(|manifest| &manifest.entries)
+            .filter_map(|entry| match entry {
+                Entry::File { path, hash, size, mtime_ms, .. } => Some((path.as_str(), (hash.as_str(), *size, *mtime_ms))),
+                _ => None,
+            })
+            .collect();
+        let total = progress::is_streaming().then(|| tree_size(source));
+
+        let mut dedup = Dedup::default();
+        let mut entries = Vec::new();
+        for entry in WalkDir::new(source).sort_by_file_name() {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            let path = match entry.path().strip_prefix(source)? {
+                relative if relative.as_os_str().is_empty() && metadata.is_dir() => continue,
+                relative if relative.as_os_str().is_empty() => entry.file_name().to_string_lossy().into_owned(),
+                relative => relative.to_string_lossy().replace('\\', "/"),
+            };
+
+            if metadata.file_type().is_symlink() {
+                entries.push(Entry::Symlink { path, target: fs::read_link(entry.path())? });
+            } else if metadata.is_dir() {
+                entries.push(Entry::Dir { path, mode: mode(&metadata) });
+            } else {
+                let size = metadata.len();
+                let mtime_ms = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|since| since.as_millis() as u64);
+                let unchanged = known
+                    .get(path.as_str())
+                    .filter(|(hash, known_size, known_mtime)| {
+                        *known_size == size && known_mtime.is_some() && *known_mtime == mtime_ms && self.blob(hash).exists()
+                    })
+                    .map(|(hash, ..)| hash.to_string());
+                let (hash, new) = match unchanged {
+                    Some(hash) => (hash, false),
+                    None => self.put(entry.path())?,
+                };
+
+                dedup.files += 1;
+                dedup.bytes += size;
+                if new {
+                    dedup.new_files += 1;
+                    dedup.new_bytes += size;
+                } else {
+                    dedup.reused_files += 1;
+                    dedup.reused_bytes += size;
+                }
+                if let Some(total) = total {
+                    progress::report(dedup.bytes, Some(total.max(dedup.bytes)), &format!("Stored {}", entry.path().display()));
+                }
+                entries.push(Entry::File { path, hash, size, mtime_ms, mode: mode(&metadata) });
+            }
+        }
+
+        let manifest = Manifest {
+            source: source.to_path_buf(),
+            timestamp: timestamp.to_string(),
+            file: !source.is_dir(),
+            entries,
+        };
+        let manifest_path = self.root.join(format!("{}.json", name));
+        write_atomically(&manifest_path, &serde_json::to_vec_pretty(&manifest)?)?;
+        Ok((manifest_path, dedup))
+    }
+
+    /// The manifests in the store and their timestamps, oldest first
+    pub fn manifests(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut manifests = Vec::new();
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.root.display())),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let Some((timestamp, n)) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json").or_else(|| name.strip_suffix(ARCHIVE_EXTENSION)))
+                .and_then(parse_name)
+            else {
+                continue;
+            };
+            manifests.push((path, timestamp, n));
+        }
+        manifests.sort_by(|a, b| (&a.1, a.2).cmp(&(&b.1, b.2)));
+        Ok(manifests.into_iter().map(|(path, timestamp, _)| (path, timestamp)).collect())
+    }
+
+    /// Put `destination` back the way `manifest` recorded it. Files that already hold
+    /// the recorded content are left alone; with `delete_extra`, whatever the snapshot
+    /// does not have is removed.
+    pub fn restore(&self, manifest: &Manifest, destination: &Path, delete_extra: bool) -> Result<Restored> {
+        // The blobs being copied back must not be collected meanwhile
+        let _lock = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // A damaged store fails before anything is changed
+        for entry in &manifest.entries {
+            if let Entry::File { path, hash, .. } = entry {
+                if !self.blob(hash).is_file() {
+                    anyhow::bail!("The snapshot is damaged: the content of {} ({}) is missing from {}", path, hash, self.root.display());
+                }
+            }
+        }
+
+        let target = |path: &str| if manifest.file { destination.to_path_buf() } else { destination.join(path) };
+        let mut restored = Restored::default();
+        if !manifest.file && !destination.is_dir() {
+            remove_any(destination)?;
+            fs::create_dir_all(destination).with_context(|| format!("Failed to create {}", destination.display()))?;
+        }
+        let total: u64 = manifest
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::File { size, .. } => *size,
+                _ => 0,
+            })
+            .sum();
+        let mut done = 0;
+
+        // Entries are in walk order, so a directory comes before what is in it
+        for entry in &manifest.entries {
+            let path = target(entry.path());
+            match entry {
+                Entry::Dir { .. } => {
+                    // Also replaces a symlink, which writes below could otherwise follow
+                    if path.symlink_metadata().is_ok_and(|metadata| !metadata.is_dir()) {
+                        remove_any(&path)?;
+                    }
+                    if !path.exists() {
+                        fs::create_dir(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+                        restored.directories += 1;
+                    }
+                }
+                Entry::File { hash, size, mtime_ms, mode, .. } => {
+                    done += size;
+                    if progress::is_streaming() {
+                        progress::report(done, Some(total), &format!("Restored {}", path.display()));
+                    }
+                    let unchanged = path.symlink_metadata().is_ok_and(|metadata| metadata.is_file() && metadata.len() == *size)
+                        && hash_file(&path)? == *hash;
+                    if unchanged {
+                        restored.unchanged += 1;
+                        set_mode(&path, *mode)?;
+                        continue;
+                    }
+                    if path.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                        fs::remove_dir_all(&path)?;
+                    }
+                    self.copy_out(hash, &path, *mtime_ms, *mode)?;
+                    restored.written += 1;
+                    restored.bytes += size;
+                }
+                Entry::Symlink { target: link, .. } => {
+                    if fs::read_link(&path).is_ok_and(|existing| existing == *link) {
+                        continue;
+                    }
+                    remove_any(&path)?;
+                    if symlink(link, &path).with_context(|| format!("Failed to create symlink {}", path.display()))? {
+                        restored.symlinks += 1;
+                    } else {
+                        restored.skipped.push(entry.path().to_string());
+                    }
+                }
+            }
+        }
+
+        // Last, as a read-only directory could not have been written into
+        for entry in manifest.entries.iter().rev() {
+            if let Entry::Dir { path, mode } = entry {
+                set_mode(&target(path), *mode)?;
+            }
+        }
+
+        if delete_extra && !manifest.file {
+            let keep: HashSet<PathBuf> = manifest.entries.iter().map(|entry| target(entry.path())).collect();
+            let mut walk = WalkDir::new(destination).min_depth(1).sort_by_file_name().into_iter();
+            while let Some(entry) = walk.next() {
+                let entry = entry?;
+                let is_dir = entry.file_type().is_dir();
+                // Never the store itself, should it be inside the destination
+                if keep.contains(entry.path()) || entry.path().starts_with(&self.root) {
+                    if is_dir && !keep.contains(entry.path()) {
+                        walk.skip_current_dir();
+                    }
+                    continue;
+                }
+                remove_any(entry.path())?;
+                if is_dir {
+                    walk.skip_current_dir();
+                }
+                let relative = entry.path().strip_prefix(destination).unwrap_or(entry.path());
+                restored.deleted.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Remove the blobs no manifest in the store refers to; returns how many were
+    /// removed and their total size. Manifests of every session count, not only
+    /// the snapshots one session's history lists.
+    pub fn collect_garbage(&self) -> Result<(usize, u64)> {
+        let _lock = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // Without every manifest's blobs known, removing any could break one
+        let Some(referenced) = self.loose_references(None)? else {
+            return Ok((0, 0));
+        };
+
+        let (mut removed, mut freed) = (0, 0);
+        for entry in WalkDir::new(self.root.join("objects")).min_depth(2).into_iter().filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || referenced.contains(name.as_ref()) {
+                continue;
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+                freed += size;
+            }
+        }
+        Ok((removed, freed))
+    }
+
+    /// Pack the snapshot with manifest `manifest_path` into an archive, moving in
+    /// the blobs no other snapshot uses. Returns the archive's path.
+    pub fn compress(&self, manifest_path: &Path) -> Result<PathBuf> {
+        let _lock = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let content = fs::read(manifest_path).with_context(|| format!("Failed to read snapshot manifest: {}", manifest_path.display()))?;
+        let manifest: Manifest = serde_json::from_slice(&content)
+            .with_context(|| format!("Invalid snapshot manifest: {}", manifest_path.display()))?;
+        // Unknown references keep every blob loose, which is always safe
+        let elsewhere = self.loose_references(Some(manifest_path))?;
+        let mut blobs: Vec<String> = file_hashes(&manifest)
+            .filter(|hash| elsewhere.as_ref().is_some_and(|elsewhere| !elsewhere.contains(*hash)))
+            .filter(|hash| self.blob(hash).is_file())
+            .map(str::to_string)
+            .collect();
+        blobs.sort();
+        blobs.dedup();
+
+        let name = manifest_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let archive = self.root.join(format!("{}{}", name, ARCHIVE_EXTENSION));
+        let temp = self.root.join(format!("{}{}.tmp", name, ARCHIVE_EXTENSION));
+        let written = (|| -> Result<()> {
+            let mut builder = tar::Builder::new(zstd::Encoder::new(fs::File::create(&temp)?, 0)?);
+            append_bytes(&mut builder, ARCHIVE_MANIFEST, &content)?;
+            append_bytes(&mut builder, ARCHIVE_BLOBS, &serde_json::to_vec(&blobs)?)?;
+            for hash in &blobs {
+                builder.append_path_with_name(self.blob(hash), format!("objects/{}", hash))?;
+            }
+            builder.into_inner()?.finish()?.sync_all()?;
+            Ok(())
+        })();
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(e).with_context(|| format!("Failed to compress {}", manifest_path.display()));
+        }
+        fs::rename(&temp, &archive)?;
+        fs::remove_file(manifest_path)?;
+        for hash in &blobs {
+            let _ = fs::remove_file(self.blob(hash));
+        }
+        Ok(archive)
+    }
+
+    /// Turn the compressed snapshot `archive` back into a manifest and loose blobs,
+    /// so it can be restored or compared. Returns the manifest's path.
+    pub fn expand(&self, archive: &Path) -> Result<PathBuf> {
+        let _lock = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let file = fs::File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+        let mut content = None;
+        let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if name == ARCHIVE_MANIFEST {
+                let mut bytes = Vec::new();
+                io::Read::read_to_end(&mut entry, &mut bytes)?;
+                content = Some(bytes);
+            } else if let Some(hash) = name.strip_prefix("objects/") {
+                // A blob's name is its hash; anything else is not one of ours
+                if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    continue;
+                }
+                let blob = self.blob(hash);
+                if blob.exists() {
+                    continue;
+                }
+                fs::create_dir_all(blob.parent().unwrap_or(&self.root))?;
+                let temp = self.root.join("objects").join(format!(".incoming-{}-{}", std::process::id(), hash));
+                io::copy(&mut entry, &mut fs::File::create(&temp)?)?;
+                fs::rename(&temp, &blob)?;
+            }
+        }
+        let content = content.with_context(|| format!("Invalid compressed snapshot, no manifest: {}", archive.display()))?;
+
+        let name = archive_name(archive).with_context(|| format!("Not a compressed snapshot: {}", archive.display()))?;
+        let manifest_path = self.root.join(format!("{}.json", name));
+        write_atomically(&manifest_path, &content)?;
+        fs::remove_file(archive)?;
+        Ok(manifest_path)
+    }
+
+    /// How much space the snapshots take
+    pub fn disk_usage(&self) -> Result<DiskUsage> {
+        let mut usage = DiskUsage::default();
+        for (path, _) in self.manifests()? {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            usage.snapshots += 1;
+            if is_archive(&path) {
+                usage.compressed += 1;
+                usage.archive_bytes += size;
+            } else {
+                usage.manifest_bytes += size;
+            }
+        }
+        for entry in WalkDir::new(self.root.join("objects")).min_depth(2).into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() {
+                usage.objects += 1;
+                usage.object_bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        usage.total_bytes = usage.manifest_bytes + usage.archive_bytes + usage.object_bytes;
+        Ok(usage)
+    }
+
+    /// The hashes of the blobs the snapshots other than `except` need from `objects/`:
+    /// every blob of an uncompressed one, and those a compressed one does not hold
+    /// itself. None when a manifest cannot be read.
+    fn loose_references(&self, except: Option<&Path>) -> Result<Option<HashSet<String>>> {
+        let mut referenced = HashSet::new();
+        for (path, _) in self.manifests()? {
+            if except == Some(path.as_path()) {
+                continue;
+            }
+            let head = if is_archive(&path) {
+                read_archive_head(&path)
+            } else {
+                load(&path).map(|manifest| (manifest, Vec::new()))
+            };
+            let Ok((manifest, held)) = head else {
+                return Ok(None);
+            };
+            let held: HashSet<String> = held.into_iter().collect();
+            referenced.extend(file_hashes(&manifest).filter(|hash| !held.contains(*hash)).map(str::to_string));
+        }
+        Ok(Some(referenced))
+    }
+
+    /// Where the blob with `hash` is kept
+    pub fn blob(&self, hash: &str) -> PathBuf {
+        self.root.join("objects").join(&hash[..2.min(hash.len())]).join(hash)
+    }
+
+    /// Store the content of `file` unless it is already there. Returns its hash and
+    /// whether it was new.
+    fn put(&self, file: &Path) -> Result<(String, bool)> {
+        let hash = hash_file(file)?;
+        if self.blob(&hash).exists() {
+            return Ok((hash, false));
+        }
+
+        // Hash what is actually copied, in case the file changes in between
+        let temp = self.root.join("objects").join(format!(".incoming-{}-{}", std::process::id(), hash));
+        let mut tee = Tee { file: fs::File::create(&temp)?, hasher: blake3::Hasher::new() };
+        let copied = io::copy(&mut fs::File::open(file)?, &mut tee).and_then(|_| tee.file.sync_all());
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&temp);
+            return Err(e).with_context(|| format!("Failed to store {}", file.display()));
+        }
+        let hash = tee.hasher.finalize().to_hex().to_string();
+        let blob = self.blob(&hash);
+        if blob.exists() {
+            fs::remove_file(&temp)?;
+            return Ok((hash, false));
+        }
+        fs::create_dir_all(blob.parent().unwrap_or(&self.root))?;
+        fs::rename(&temp, &blob)?;
+        Ok((hash, true))
+    }
+
+    /// Write the blob with `hash` to `path`, replacing what is there in one step
+    fn copy_out(&self, hash: &str, path: &Path, mtime_ms: Option<u64>, mode: Option<u32>) -> Result<()> {
+        let file_name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let temp = path.with_file_name(format!(".{}.restore-{}", file_name, std::process::id()));
+        let copied = (|| -> Result<()> {
+            let mut file = fs::File::create(&temp)?;
+            io::copy(&mut fs::File::open(self.blob(hash))?, &mut file)?;
+            // So the next snapshot sees the file as unchanged
+            if let Some(mtime_ms) = mtime_ms {
+                file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_millis(mtime_ms))?;
+            }
+            file.sync_all()?;
+            drop(file);
+            set_mode(&temp, mode)?;
+            fs::rename(&temp, path)?;
+            Ok(())
+        })();
+        if copied.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        copied.with_context(|| format!("Failed to restore {}", path.display()))
+    }
+}
+
+/// Read the manifest at `path`, or inside the compressed snapshot there
+pub fn load(path: &Path) -> Result<Manifest> {
+    if is_archive(path) {
+        return read_archive_head(path).map(|(manifest, _)| manifest);
+    }
+    let content = fs::read(path).with_context(|| format!("Failed to read snapshot manifest: {}", path.display()))?;
+    serde_json::from_slice(&content).with_context(|| format!("Invalid snapshot manifest: {}", path.display()))
+}
+
+/// Whether `path` is a compressed snapshot
+pub fn is_archive(path: &Path) -> bool {
+    archive_name(path).is_some()
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// The snapshot name of archive `path`, without the extension
+fn archive_name(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.strip_suffix(ARCHIVE_EXTENSION)
+}
+
+/// The manifest and held blob hashes at the start of a compressed snapshot
+fn read_archive_head(path: &Path) -> Result<(Manifest, Vec<String>)> {
+    if !is_archive(path) {
+        anyhow::bail!("Not a compressed snapshot: {}", path.display());
+    }
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut tar = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = tar.entries()?;
+    let mut next = |name: &str| -> Result<Vec<u8>> {
+        let mut entry = entries.next().with_context(|| format!("Invalid compressed snapshot, no {}: {}", name, path.display()))??;
+        if entry.path()?.to_str() != Some(name) {
+            anyhow::bail!("Invalid compressed snapshot, {} is not first: {}", name, path.display());
+        }
+        let mut bytes = Vec::new();
+        io::Read::read_to_end(&mut entry, &mut bytes)?;
+        Ok(bytes)
+    };
+    let manifest = serde_json::from_slice(&next(ARCHIVE_MANIFEST)?)
+        .with_context(|| format!("Invalid snapshot manifest in {}", path.display()))?;
+    let held = serde_json::from_slice(&next(ARCHIVE_BLOBS)?)
+        .with_context(|| format!("Invalid blob list in {}", path.display()))?;
+    Ok((manifest, held))
+}
+
+/// The content hashes of the files in `manifest`
+fn file_hashes(manifest: &Manifest) -> impl Iterator<Item = &str> {
+    manifest.entries.iter().filter_map(|entry| match entry {
+        Entry::File { hash, .. } => Some(hash.as_str()),
+        _ => None,
+    })
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, name: &str, content: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)
+}
+
+/// The timestamp and number of a manifest named `snapshot_<timestamp>` or
+/// `snapshot_<timestamp>_<n>`, for ordering snapshots taken in the same second
+fn parse_name(name: &str) -> Option<(String, u64)> {
+    let rest = name.strip_prefix("snapshot_")?;
+    // %Y%m%d_%H%M%S
+    let timestamp = rest.get(..15)?;
+    let n = match rest.get(15..)? {
+        "" => 1,
+        suffix => suffix.strip_prefix('_')?.parse().ok()?,
+    };
+    Some((timestamp.to_string(), n))
+}
+
+/// BLAKE3 hash of the content of `file`, as blobs are named
+fn hash_file(file: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut fs::File::open(file).with_context(|| format!("Failed to read {}", file.display()))?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Remove whatever is at `path`, if anything, without following symlinks
+fn remove_any(path: &Path) -> Result<()> {
+    let removed = match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+    removed.with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+/// Copies into a file and a hasher at once
+struct Tee {
+    file: fs::File,
+    hasher: blake3::Hasher,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set the permissions of {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> Result<()> {
+    Ok(())
+}
+
+/// Create `link` pointing at `target`; false where symlinks cannot be created
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<bool> {
+    std::os::unix::fs::symlink(target, link).map(|_| true)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<bool> {
+    Ok(false)
+}
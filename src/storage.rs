@@ -0,0 +1,322 @@
+// Key-value storage for state that may outlive the process: in memory, SQLite or sled
+
+use crate::config::StorageConfig;
+use anyhow::{Context as _, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Keyspaces used by the built-in modules and the server
+pub mod keyspaces {
+    /// `fs_snapshot` history, by snapshotted path
+    pub const SNAPSHOTS: &str = "fs_snapshots";
+    /// `time_schedule` tasks, by task id
+    pub const SCHEDULE: &str = "time_schedule";
+    /// `ctx_memory_store` values, by key
+    pub const MEMORY: &str = "ctx_memory";
+    /// Audit log entries with `[audit] format = "storage"`, by timestamp
+    pub const AUDIT: &str = "audit";
+    /// Active OAuth tokens from introspection, by token digest
+    pub const TOKEN_CACHE: &str = "auth_tokens";
+}
+
+/// Named keyspaces of string keys and byte values. Implement it to keep the
+/// server's state in your own database; see [`crate::PolyMcpBuilder::storage`].
+pub trait Storage: Send + Sync {
+    fn get(&self, keyspace: &str, key: &str) -> Result<Option<Vec<u8>>>;
+
+    fn put(&self, keyspace: &str, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Remove a key; returns whether it existed
+    fn delete(&self, keyspace: &str, key: &str) -> Result<bool>;
+
+    /// Every entry of `keyspace` whose key starts with `prefix`, in key order
+    fn scan(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+
+    /// Whether the data survives a restart
+    fn is_persistent(&self) -> bool;
+}
+
+impl dyn Storage {
+    pub fn get_json<T: DeserializeOwned>(&self, keyspace: &str, key: &str) -> Result<Option<T>> {
+        match self.get(keyspace, key)? {
+            Some(bytes) => Ok(Some(
+                serde_json::from_slice(&bytes).with_context(|| format!("Corrupt value for {} in {}", key, keyspace))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_json<T: Serialize + ?Sized>(&self, keyspace: &str, key: &str, value: &T) -> Result<()> {
+        self.put(keyspace, key, &serde_json::to_vec(value)?)
+    }
+
+    /// Every value of `keyspace` whose key starts with `prefix`, in key order
+    pub fn scan_json<T: DeserializeOwned>(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, T)>> {
+        self.scan(keyspace, prefix)?
+            .into_iter()
+            .map(|(key, bytes)| {
+                let value = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Corrupt value for {} in {}", key, keyspace))?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    /// Remove every key of `keyspace`; returns how many there were
+    pub fn clear(&self, keyspace: &str) -> Result<usize> {
+        let entries = self.scan(keyspace, "")?;
+        for (key, _) in &entries {
+            self.delete(keyspace, key)?;
+        }
+        Ok(entries.len())
+    }
+}
+
+/// Open the backend configured in `[storage]`. `None` means in memory, which is
+/// not shared: each server instance (stdio run or HTTP session) gets its own.
+pub fn open(config: &StorageConfig) -> Result<Option<Arc<dyn Storage>>> {
+    let storage: Arc<dyn Storage> = match config.backend.as_str() {
+        "memory" => return Ok(None),
+        "sqlite" => Arc::new(SqliteStorage::open(&path_or(config, ".poly-mcp/state.db"))?),
+        "sled" => open_sled(&path_or(config, ".poly-mcp/state.sled"))?,
+        other => anyhow::bail!("Unknown storage backend '{}' (expected memory, sqlite or sled)", other),
+    };
+    Ok(Some(storage))
+}
+
+/// State kept for the life of the process only
+#[derive(Default)]
+pub struct MemoryStorage {
+    keyspaces: Mutex<BTreeMap<String, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, keyspace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let keyspaces = self.keyspaces.lock().unwrap();
+        Ok(keyspaces.get(keyspace).and_then(|entries| entries.get(key)).cloned())
+    }
+
+    fn put(&self, keyspace: &str, key: &str, value: &[u8]) -> Result<()> {
+        let mut keyspaces = self.keyspaces.lock().unwrap();
+        keyspaces
+            .entry(keyspace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, keyspace: &str, key: &str) -> Result<bool> {
+        let mut keyspaces = self.keyspaces.lock().unwrap();
+        Ok(keyspaces
+            .get_mut(keyspace)
+            .is_some_and(|entries| entries.remove(key).is_some()))
+    }
+
+    fn scan(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let keyspaces = self.keyspaces.lock().unwrap();
+        let Some(entries) = keyspaces.get(keyspace) else {
+            return Ok(Vec::new());
+        };
+        Ok(entries
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// All keyspaces in one table of a SQLite database
+pub struct SqliteStorage {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        create_parent(path)?;
+        let connection = rusqlite::Connection::open(path)
+            .with_context(|| format!("Failed to open storage database {}", path.display()))?;
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                CREATE TABLE IF NOT EXISTS poly_mcp_state (
+                    keyspace TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    value BLOB NOT NULL,
+                    PRIMARY KEY (keyspace, key)
+                );",
+            )
+            .context("Failed to create poly_mcp_state table")?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, keyspace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare_cached("SELECT value FROM poly_mcp_state WHERE keyspace = ?1 AND key = ?2")?;
+        let mut rows = statement.query(rusqlite::params![keyspace, key])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&self, keyspace: &str, key: &str, value: &[u8]) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO poly_mcp_state (keyspace, key, value) VALUES (?1, ?2, ?3)",
+            rusqlite::params![keyspace, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, keyspace: &str, key: &str) -> Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let deleted = connection.execute(
+            "DELETE FROM poly_mcp_state WHERE keyspace = ?1 AND key = ?2",
+            rusqlite::params![keyspace, key],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    fn scan(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let connection = self.connection.lock().unwrap();
+        // substr rather than LIKE, which would treat % and _ in the prefix as wildcards
+        let mut statement = connection.prepare_cached(
+            "SELECT key, value FROM poly_mcp_state
+             WHERE keyspace = ?1 AND substr(key, 1, length(?2)) = ?2
+             ORDER BY key",
+        )?;
+        let rows = statement.query_map(rusqlite::params![keyspace, prefix], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+/// One sled tree per keyspace
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = sled::open(path).with_context(|| format!("Failed to open sled database {}", path.display()))?;
+        Ok(Self { db })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    fn get(&self, keyspace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.open_tree(keyspace)?.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn put(&self, keyspace: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.db.open_tree(keyspace)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, keyspace: &str, key: &str) -> Result<bool> {
+        Ok(self.db.open_tree(keyspace)?.remove(key)?.is_some())
+    }
+
+    fn scan(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.db
+            .open_tree(keyspace)?
+            .scan_prefix(prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+/// Another storage with every keyspace moved under `namespace`, so tenants sharing
+/// one database do not see each other's state
+pub struct Namespaced {
+    inner: Arc<dyn Storage>,
+    namespace: String,
+}
+
+impl Namespaced {
+    pub fn new(inner: Arc<dyn Storage>, namespace: &str) -> Self {
+        Self {
+            inner,
+            namespace: namespace.to_string(),
+        }
+    }
+
+    fn keyspace(&self, keyspace: &str) -> String {
+        format!("{}/{}", self.namespace, keyspace)
+    }
+}
+
+impl Storage for Namespaced {
+    fn get(&self, keyspace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get(&self.keyspace(keyspace), key)
+    }
+
+    fn put(&self, keyspace: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.inner.put(&self.keyspace(keyspace), key, value)
+    }
+
+    fn delete(&self, keyspace: &str, key: &str) -> Result<bool> {
+        self.inner.delete(&self.keyspace(keyspace), key)
+    }
+
+    fn scan(&self, keyspace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        self.inner.scan(&self.keyspace(keyspace), prefix)
+    }
+
+    fn is_persistent(&self) -> bool {
+        self.inner.is_persistent()
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+#[cfg(feature = "sled")]
+fn open_sled(path: &Path) -> Result<Arc<dyn Storage>> {
+    Ok(Arc::new(SledStorage::open(path)?))
+}
+
+#[cfg(not(feature = "sled"))]
+fn open_sled(_path: &Path) -> Result<Arc<dyn Storage>> {
+    anyhow::bail!("This build has no sled support; rebuild with --features sled, or use the sqlite backend")
+}
+
+fn path_or(config: &StorageConfig, default: &str) -> PathBuf {
+    config.path.clone().unwrap_or_else(|| PathBuf::from(default))
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,290 @@
+// Spans and metrics for tool calls and the external commands they run, exported over OTLP
+
+use crate::children;
+use crate::config::TelemetryConfig;
+use anyhow::Result;
+use std::process::{ExitStatus, Output};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::Span;
+
+/// The span a tool call runs in. Its fields follow the tool call: the outcome is
+/// recorded by [`record_call`].
+pub fn tool_span(tool: &str, module: Option<&str>, tenant: Option<&str>) -> Span {
+    tracing::info_span!(
+        "tool_call",
+        otel.name = format!("tools/call {}", tool),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        otel.status_message = Empty,
+        mcp.tool = tool,
+        mcp.module = module,
+        mcp.tenant = tenant,
+        mcp.outcome = Empty,
+    )
+}
+
+/// Record how a tool call ended ("ok", "error", "timeout" or "cancelled") on its
+/// span and in the `poly_mcp.tool.calls` and `poly_mcp.tool.duration` metrics
+pub fn record_call(span: &Span, tool: &str, module: Option<&str>, outcome: &str, error: Option<&str>, elapsed: Duration) {
+    span.record("mcp.outcome", outcome);
+    if let Some(error) = error {
+        span.record("otel.status_code", "ERROR");
+        span.record("otel.status_message", error);
+    }
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::KeyValue;
+        let attributes = [
+            KeyValue::new("mcp.tool", tool.to_string()),
+            KeyValue::new("mcp.module", module.unwrap_or_default().to_string()),
+            KeyValue::new("mcp.outcome", outcome.to_string()),
+        ];
+        let instruments = otel::instruments();
+        instruments.calls.add(1, &attributes);
+        instruments.call_duration.record(elapsed.as_secs_f64(), &attributes);
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (tool, module, elapsed);
+}
+
+/// The span an external command runs in, named after the program
+pub fn command_span(program: &str) -> Span {
+    tracing::info_span!(
+        "command",
+        otel.name = format!("exec {}", program),
+        otel.status_code = Empty,
+        process.executable.name = program,
+        process.exit.code = Empty,
+    )
+}
+
+/// Record how an external command ended on its span and in the
+/// `poly_mcp.command.duration` metric. `status` is `None` when it never finished.
+pub fn record_command(span: &Span, program: &str, status: Option<&ExitStatus>, elapsed: Duration) {
+    if let Some(code) = status.and_then(ExitStatus::code) {
+        span.record("process.exit.code", code);
+    }
+    let success = status.is_some_and(ExitStatus::success);
+    if !success {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::KeyValue;
+        let attributes = [
+            KeyValue::new("process.executable.name", program.to_string()),
+            KeyValue::new("success", success),
+        ];
+        otel::instruments().command_duration.record(elapsed.as_secs_f64(), &attributes);
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (program, elapsed);
+}
+
+/// Run `command` to completion inside a [`command_span`], as a supervised child (see [`children::run`])
+pub fn run_command(command: &mut std::process::Command) -> std::io::Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let span = command_span(&program);
+    let started = Instant::now();
+    let output = span.in_scope(|| children::run(command));
+    record_command(&span, &program, output.as_ref().ok().map(|output| &output.status), started.elapsed());
+    output
+}
+
+/// [`run_command`] for commands run on the async runtime
+pub async fn run_command_async(command: &mut tokio::process::Command) -> std::io::Result<Output> {
+    use tracing::Instrument;
+    let program = command.as_std().get_program().to_string_lossy().into_owned();
+    let span = command_span(&program);
+    let started = Instant::now();
+    let output = children::run_async(command).instrument(span.clone()).await;
+    record_command(&span, &program, output.as_ref().ok().map(|output| &output.status), started.elapsed());
+    output
+}
+
+/// Whether `[telemetry]` or the `OTEL_*` environment asks for export
+pub fn wanted(config: &TelemetryConfig) -> bool {
+    let disabled = std::env::var("OTEL_SDK_DISABLED").is_ok_and(|v| v.eq_ignore_ascii_case("true"));
+    let from_env = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()));
+    (config.enabled || from_env) && !disabled
+}
+
+/// Running OTLP exporters. Dropping it flushes what is still buffered.
+#[cfg(feature = "otel")]
+pub struct Telemetry {
+    tracer_provider: opentelemetry_sdk::trace::TracerProvider,
+    meter_provider: opentelemetry_sdk::metrics::SdkMeterProvider,
+}
+
+#[cfg(feature = "otel")]
+impl Telemetry {
+    /// Start exporting, or `None` when [`wanted`] says not to. Must be called
+    /// inside the Tokio runtime, which the exporters run on.
+    pub fn init(config: &TelemetryConfig) -> Result<Option<Self>> {
+        if !wanted(config) {
+            return Ok(None);
+        }
+        let (tracer_provider, meter_provider) = otel::providers(config)?;
+        opentelemetry::global::set_meter_provider(meter_provider.clone());
+        Ok(Some(Self {
+            tracer_provider,
+            meter_provider,
+        }))
+    }
+
+    /// A tracing layer that turns spans into OTLP traces; add it to the subscriber
+    pub fn layer<S>(&self) -> impl tracing_subscriber::Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        use opentelemetry::trace::TracerProvider as _;
+        tracing_opentelemetry::layer().with_tracer(self.tracer_provider.tracer("poly-mcp"))
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            eprintln!("Failed to flush metrics: {}", e);
+        }
+    }
+}
+
+/// Without the `otel` feature there is nothing to export; asking for it in the
+/// config is an error, while `OTEL_*` variables meant for other programs are ignored.
+#[cfg(not(feature = "otel"))]
+pub struct Telemetry {
+    _private: (),
+}
+
+#[cfg(not(feature = "otel"))]
+impl Telemetry {
+    pub fn init(config: &TelemetryConfig) -> Result<Option<Self>> {
+        if config.enabled {
+            anyhow::bail!("This build has no OpenTelemetry support; rebuild with --features otel, or remove [telemetry] enabled");
+        }
+        Ok(None)
+    }
+
+    pub fn layer<S: tracing::Subscriber>(&self) -> impl tracing_subscriber::Layer<S> {
+        tracing_subscriber::layer::Identity::new()
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+#[cfg(feature = "otel")]
+mod otel {
+    use crate::config::TelemetryConfig;
+    use anyhow::{Context as _, Result};
+    use opentelemetry::metrics::{Counter, Histogram};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::TracerProvider;
+    use opentelemetry_sdk::{runtime, Resource};
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    pub struct Instruments {
+        pub calls: Counter<u64>,
+        pub call_duration: Histogram<f64>,
+        pub command_duration: Histogram<f64>,
+    }
+
+    /// Created on first use, from whichever meter provider is global by then
+    pub fn instruments() -> &'static Instruments {
+        static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+        INSTRUMENTS.get_or_init(|| {
+            let meter = opentelemetry::global::meter("poly-mcp");
+            Instruments {
+                calls: meter
+                    .u64_counter("poly_mcp.tool.calls")
+                    .with_description("Tool calls, by tool, module and outcome")
+                    .build(),
+                call_duration: meter
+                    .f64_histogram("poly_mcp.tool.duration")
+                    .with_description("How long tool calls take")
+                    .with_unit("s")
+                    .build(),
+                command_duration: meter
+                    .f64_histogram("poly_mcp.command.duration")
+                    .with_description("How long external commands run by tools take")
+                    .with_unit("s")
+                    .build(),
+            }
+        })
+    }
+
+    pub fn providers(config: &TelemetryConfig) -> Result<(TracerProvider, SdkMeterProvider)> {
+        let protocol = config
+            .protocol
+            .clone()
+            .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_PROTOCOL").ok())
+            .unwrap_or_else(|| "grpc".to_string());
+        let (spans, metrics) = match protocol.as_str() {
+            "grpc" => {
+                let mut spans = SpanExporter::builder().with_tonic();
+                let mut metrics = MetricExporter::builder().with_tonic();
+                if let Some(endpoint) = &config.endpoint {
+                    spans = spans.with_endpoint(endpoint);
+                    metrics = metrics.with_endpoint(endpoint);
+                }
+                (spans.build(), metrics.build())
+            }
+            "http/protobuf" | "http" => {
+                let mut spans = SpanExporter::builder().with_http();
+                let mut metrics = MetricExporter::builder().with_http();
+                // A base URL, like OTEL_EXPORTER_OTLP_ENDPOINT, so each signal gets its path
+                if let Some(endpoint) = &config.endpoint {
+                    let endpoint = endpoint.trim_end_matches('/');
+                    spans = spans.with_endpoint(format!("{}/v1/traces", endpoint));
+                    metrics = metrics.with_endpoint(format!("{}/v1/metrics", endpoint));
+                }
+                (spans.build(), metrics.build())
+            }
+            other => anyhow::bail!("Unknown OTLP protocol '{}' (expected grpc or http/protobuf)", other),
+        };
+        let spans = spans.context("Failed to create the OTLP trace exporter")?;
+        let metrics = metrics.context("Failed to create the OTLP metric exporter")?;
+
+        let resource = resource(config);
+        let tracer_provider = TracerProvider::builder()
+            .with_batch_exporter(spans, runtime::Tokio)
+            .with_resource(resource.clone())
+            .build();
+        let mut reader = PeriodicReader::builder(metrics, runtime::Tokio);
+        if let Some(secs) = config.metrics_interval_secs {
+            reader = reader.with_interval(Duration::from_secs(secs));
+        }
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(reader.build())
+            .with_resource(resource)
+            .build();
+        Ok((tracer_provider, meter_provider))
+    }
+
+    /// The SDK's resource (which reads OTEL_SERVICE_NAME and OTEL_RESOURCE_ATTRIBUTES),
+    /// named poly-mcp unless the config or the environment names it
+    fn resource(config: &TelemetryConfig) -> Resource {
+        let resource = Resource::default();
+        let unnamed = resource
+            .get(opentelemetry::Key::from_static_str("service.name"))
+            .is_none_or(|name| name.as_str().starts_with("unknown_service"));
+        let name = match &config.service_name {
+            Some(name) => name.clone(),
+            None if unnamed => "poly-mcp".to_string(),
+            None => return resource,
+        };
+        resource.merge(&Resource::new([KeyValue::new("service.name", name)]))
+    }
+}
@@ -0,0 +1,161 @@
+// Tenants of a shared HTTP server: per-tenant sandboxes, quotas and audit logs
+
+use crate::audit::AuditLog;
+use crate::config::{AuditConfig, Config, TenantConfig};
+use crate::error::PolyError;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// One tenant from `[tenants.<name>]`, shared by all of its sessions
+pub struct Tenant {
+    name: String,
+    config: TenantConfig,
+    // Its own partition of the audit log, when `audit_path` is set
+    audit: Option<Arc<AuditLog>>,
+    // Calls made on the current UTC day, and which day that is
+    calls: Mutex<(chrono::NaiveDate, u64)>,
+}
+
+impl Tenant {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.config.max_sessions
+    }
+
+    pub fn audit(&self) -> Option<&Arc<AuditLog>> {
+        self.audit.as_ref()
+    }
+
+    /// The server config as this tenant's sessions see it: confined to its roots,
+    /// without shell commands unless `allow_shell` is set, starting in its working
+    /// directory, with its own gitent database and audit log
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        let first_root = self.config.allowed_roots.first().cloned().unwrap_or_default();
+        config.filesystem.allowed_roots = self.config.allowed_roots.clone();
+        config.policy.allowed_paths = self.config.allowed_roots.clone();
+        config.policy.deny_tools.extend(self.config.deny_tools.iter().cloned());
+        // Denying silent_script also refuses txn_commit calls with a check command
+        if !self.config.allow_shell {
+            config.policy.deny_tools.push("silent_script".to_string());
+        }
+        config.server.cwd = Some(self.config.cwd.clone().unwrap_or_else(|| first_root.clone()));
+        config.gitent.db_path = Some(
+            self.config
+                .gitent_db
+                .clone()
+                .unwrap_or_else(|| first_root.join(".gitent").join("gitent.db")),
+        );
+        // audit_verify checks the tenant's own log, and never the shared one
+        config.audit = match &self.config.audit_path {
+            Some(path) => partition(&config.audit, path.clone()),
+            None => AuditConfig {
+                path: None,
+                format: None,
+                ..config.audit.clone()
+            },
+        };
+        config
+    }
+
+    /// Count a tool call against the daily quota, failing once it is used up
+    pub fn count_call(&self) -> Result<()> {
+        let today = chrono::Utc::now().date_naive();
+        let mut calls = self.calls.lock().unwrap();
+        if calls.0 != today {
+            *calls = (today, 0);
+        }
+        let limit = self.config.max_calls_per_day;
+        if limit > 0 && calls.1 >= limit {
+            return Err(PolyError::RateLimited(format!(
+                "Tenant '{}' has used its quota of {} tool calls for today (resets at 00:00 UTC)",
+                self.name,
+                limit
+            ))
+            .into());
+        }
+        calls.1 += 1;
+        Ok(())
+    }
+}
+
+/// Every configured tenant, and which tenant each caller belongs to
+#[derive(Default)]
+pub struct Tenants {
+    tenants: HashMap<String, Arc<Tenant>>,
+    // Member (API key name or token subject) to tenant name
+    members: HashMap<String, String>,
+}
+
+impl Tenants {
+    /// Check and set up `[tenants]`. Each tenant needs at least one root, and a
+    /// caller may belong to only one tenant.
+    pub fn new(configs: &BTreeMap<String, TenantConfig>, audit: &AuditConfig) -> Result<Self> {
+        let mut tenants = Self::default();
+        for (name, config) in configs {
+            if config.allowed_roots.is_empty() {
+                anyhow::bail!("[tenants.{}] needs at least one entry in allowed_roots", name);
+            }
+            for member in &config.members {
+                if let Some(other) = tenants.members.insert(member.clone(), name.clone()) {
+                    anyhow::bail!("'{}' is a member of both [tenants.{}] and [tenants.{}]", member, other, name);
+                }
+            }
+
+            let audit = match &config.audit_path {
+                Some(path) => AuditLog::open(&partition(audit, path.clone()), None)?.map(Arc::new),
+                None => None,
+            };
+            let tenant = Tenant {
+                name: name.clone(),
+                config: config.clone(),
+                audit,
+                calls: Mutex::new((chrono::Utc::now().date_naive(), 0)),
+            };
+            tenants.tenants.insert(name.clone(), Arc::new(tenant));
+        }
+        Ok(tenants)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        self.tenants.values()
+    }
+
+    /// The tenant `caller` belongs to. Without tenants everyone is served as before;
+    /// with them, callers outside every tenant are refused.
+    pub fn of(&self, caller: Option<&str>) -> Result<Option<Arc<Tenant>>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let tenant = caller
+            .and_then(|caller| self.members.get(caller))
+            .and_then(|name| self.tenants.get(name));
+        match tenant {
+            Some(tenant) => Ok(Some(tenant.clone())),
+            None => anyhow::bail!(
+                "'{}' does not belong to any tenant on this server",
+                caller.unwrap_or("anonymous")
+            ),
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// The `[audit]` settings for a tenant's own log at `path`
+fn partition(audit: &AuditConfig, path: PathBuf) -> AuditConfig {
+    AuditConfig {
+        path: Some(path),
+        format: None,
+        ..audit.clone()
+    }
+}
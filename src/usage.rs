@@ -0,0 +1,284 @@
+// Opt-in anonymous usage statistics: tool call counts and outcomes in a local report that can also be sent
+
+use crate::config::UsageStatsConfig;
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Where the report is kept without `[usage_stats] path`
+pub const DEFAULT_PATH: &str = ".poly-mcp/usage-stats.json";
+
+/// Calls to tools of custom modules and plugins are counted under this name, since
+/// their names are the embedder's own business
+pub const CUSTOM_TOOLS: &str = "(custom)";
+
+/// Calls to tools that do not exist are counted under this name, since the name
+/// could be anything a client sent
+pub const UNKNOWN_TOOLS: &str = "(unknown)";
+
+/// How often the report file is rewritten while calls come in
+const WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// After a failed send, the next attempt waits this long
+const RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Version of the report's layout
+const SCHEMA: u32 = 1;
+
+/// Counts for one tool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    pub cancelled: u64,
+    pub total_ms: u64,
+}
+
+/// What is written to the report file and, with `report_url`, sent. Nothing in it
+/// identifies the user or the machine: no arguments, paths, error messages, caller
+/// names or host names.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub schema: u32,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    /// When counting started; moves on each time a report is sent
+    pub period_start: String,
+    pub period_end: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub tools: BTreeMap<String, ToolStats>,
+}
+
+/// The parts of an earlier report that counting continues from
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Saved {
+    period_start: Option<String>,
+    tools: BTreeMap<String, ToolStats>,
+}
+
+struct State {
+    period_start: DateTime<Utc>,
+    tools: BTreeMap<String, ToolStats>,
+    // Calls counted since the file was last written
+    dirty: bool,
+    // No send before this, after one failed
+    retry_at: Option<Instant>,
+}
+
+/// Counts tool calls for the `[usage_stats]` report. The file is rewritten in the
+/// background every minute while calls come in, and when the server shuts down.
+pub struct UsageStats {
+    path: PathBuf,
+    report_url: Option<String>,
+    report_interval: Duration,
+    state: Mutex<State>,
+}
+
+impl UsageStats {
+    /// Start counting when `[usage_stats] enabled` is set, continuing the report
+    /// already at its path
+    pub fn open(config: &UsageStatsConfig) -> Result<Option<Arc<Self>>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        if config.report_url.is_some() && !cfg!(feature = "usage-report") {
+            anyhow::bail!(
+                "This build cannot send usage reports; rebuild with --features usage-report, or remove [usage_stats] report_url"
+            );
+        }
+
+        let path = config.path.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_PATH));
+        let saved = load(&path);
+        let period_start = saved
+            .period_start
+            .and_then(|start| DateTime::parse_from_rfc3339(&start).ok())
+            .map(|start| start.with_timezone(&Utc))
+            .unwrap_or_else(Utc::now);
+        let stats = Arc::new(Self {
+            path,
+            report_url: config.report_url.clone(),
+            report_interval: Duration::from_secs(config.report_interval_secs.max(60)),
+            state: Mutex::new(State {
+                period_start,
+                tools: saved.tools,
+                dirty: false,
+                retry_at: None,
+            }),
+        });
+
+        let background = Arc::downgrade(&stats);
+        std::thread::Builder::new()
+            .name("usage-stats".to_string())
+            .spawn(move || run(background))
+            .context("Failed to start usage stats writer")?;
+        Ok(Some(stats))
+    }
+
+    /// Count a finished call to `tool` ("ok", "error", "timeout" or "cancelled").
+    /// Pass [`CUSTOM_TOOLS`] or [`UNKNOWN_TOOLS`] rather than names that are not built in.
+    pub fn record(&self, tool: &str, outcome: &str, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.tools.entry(tool.to_string()).or_default();
+        stats.calls += 1;
+        stats.total_ms += elapsed.as_millis() as u64;
+        match outcome {
+            "error" => stats.errors += 1,
+            "timeout" => stats.timeouts += 1,
+            "cancelled" => stats.cancelled += 1,
+            _ => {}
+        }
+        state.dirty = true;
+    }
+
+    /// The report as it stands
+    pub fn report(&self) -> Report {
+        let state = self.state.lock().unwrap();
+        let calls: u64 = state.tools.values().map(|stats| stats.calls).sum();
+        let errors: u64 = state.tools.values().map(|stats| stats.errors + stats.timeouts).sum();
+        Report {
+            schema: SCHEMA,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            period_start: state.period_start.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            period_end: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            calls,
+            errors,
+            error_rate: if calls == 0 { 0.0 } else { errors as f64 / calls as f64 },
+            tools: state.tools.clone(),
+        }
+    }
+
+    /// Write the report file now
+    pub fn write(&self) -> Result<()> {
+        let report = self.report();
+        self.state.lock().unwrap().dirty = false;
+        save(&self.path, &report)
+    }
+
+    fn dirty(&self) -> bool {
+        self.state.lock().unwrap().dirty
+    }
+
+    /// Send the report when a period is over, then start the next one
+    fn send_due(&self) {
+        let Some(url) = &self.report_url else {
+            return;
+        };
+        if do_not_track() {
+            return;
+        }
+        {
+            let state = self.state.lock().unwrap();
+            let age = (Utc::now() - state.period_start).to_std().unwrap_or_default();
+            if age < self.report_interval
+                || state.tools.is_empty()
+                || state.retry_at.is_some_and(|retry_at| Instant::now() < retry_at)
+            {
+                return;
+            }
+        }
+
+        let report = self.report();
+        match send(url, &report) {
+            Ok(()) => {
+                tracing::info!("Sent usage report for {} calls to {}", report.calls, url);
+                let mut state = self.state.lock().unwrap();
+                state.period_start = Utc::now();
+                state.tools.clear();
+                state.dirty = true;
+                state.retry_at = None;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to send usage report to {}: {:#}", url, e);
+                self.state.lock().unwrap().retry_at = Some(Instant::now() + RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+impl Drop for UsageStats {
+    fn drop(&mut self) {
+        if self.dirty() {
+            if let Err(e) = self.write() {
+                tracing::warn!("Failed to write usage report {}: {:#}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+// ── Helper functions ────────────────────────────────────────────────────
+
+/// The background writer; ends once the stats are dropped
+fn run(stats: Weak<UsageStats>) {
+    loop {
+        std::thread::sleep(WRITE_INTERVAL);
+        let Some(stats) = stats.upgrade() else {
+            return;
+        };
+        stats.send_due();
+        if stats.dirty() {
+            if let Err(e) = stats.write() {
+                tracing::warn!("Failed to write usage report {}: {:#}", stats.path.display(), e);
+            }
+        }
+    }
+}
+
+/// The `DO_NOT_TRACK` convention: set to anything but "0" or "false", nothing is sent
+fn do_not_track() -> bool {
+    std::env::var("DO_NOT_TRACK").is_ok_and(|value| !matches!(value.trim(), "" | "0" | "false"))
+}
+
+/// An earlier report, or nothing when it is missing or unreadable
+fn load(path: &Path) -> Saved {
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Replace the report file, so readers never see it half written
+fn save(path: &Path, report: &Report) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(report)?)
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    std::fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+#[cfg(feature = "usage-report")]
+fn send(url: &str, report: &Report) -> Result<()> {
+    // The writer thread is outside the server's runtime
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(async {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("poly-mcp/", env!("CARGO_PKG_VERSION")))
+            .build()?
+            .post(url)
+            .json(report)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    })
+}
+
+// UsageStats::open refuses report_url in these builds
+#[cfg(not(feature = "usage-report"))]
+fn send(_url: &str, _report: &Report) -> Result<()> {
+    anyhow::bail!("This build cannot send usage reports")
+}
@@ -0,0 +1,181 @@
+// fs_watch_start subscriptions: file system events buffered per watch until fs_watch_poll drains them
+
+use anyhow::{Context as _, Result};
+use notify::{RecursiveMode, Watcher};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+
+/// Events a watch keeps between polls; past this the oldest are dropped and counted
+pub const MAX_BUFFERED: usize = 1000;
+
+/// Watches one session may have running at once
+pub const MAX_WATCHES: usize = 32;
+
+/// Longest fs_watch_poll may wait for an event
+pub const MAX_WAIT: Duration = Duration::from_secs(300);
+
+/// Events of one watch that no poll has taken yet
+#[derive(Default)]
+struct Buffer {
+    events: VecDeque<Value>,
+    // Dropped since the last poll
+    dropped: u64,
+    total: u64,
+}
+
+struct Watch {
+    path: String,
+    recursive: bool,
+    started: String,
+    buffer: Arc<Mutex<Buffer>>,
+    // Wakes a poll waiting for events
+    arrived: Arc<Notify>,
+    // Dropping it ends the OS watch
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl Watch {
+    /// The buffered events, emptying the buffer
+    fn drain(&self) -> (Vec<Value>, u64, u64) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let events = buffer.events.drain(..).collect();
+        let dropped = std::mem::take(&mut buffer.dropped);
+        (events, dropped, buffer.total)
+    }
+}
+
+/// One session's running watches, by id. They stop when the session ends.
+#[derive(Default)]
+pub struct Watches {
+    watches: Mutex<HashMap<String, Arc<Watch>>>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching `path`. Events are buffered for [`Watches::poll`] and, with a
+    /// `notifier`, also pushed to clients as `notifications/fs_watch` messages.
+    pub fn start(&self, path: &str, recursive: bool, notifier: Option<broadcast::Sender<Value>>) -> Result<Value> {
+        if self.watches.lock().unwrap().len() >= MAX_WATCHES {
+            anyhow::bail!("At most {} watches can run at once; stop one with fs_watch_stop", MAX_WATCHES);
+        }
+
+        let id = format!("watch-{}", uuid::Uuid::new_v4().simple());
+        let buffer = Arc::new(Mutex::new(Buffer::default()));
+        let arrived = Arc::new(Notify::new());
+        let mut watcher = {
+            let (buffer, arrived) = (buffer.clone(), arrived.clone());
+            let (id, path) = (id.clone(), path.to_string());
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let event = json!({
+                    "kind": format!("{:?}", event.kind),
+                    "paths": event.paths.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>(),
+                    "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                });
+                if let Some(notifier) = &notifier {
+                    // No subscribers is not an error; the event is still buffered
+                    let _ = notifier.send(json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/fs_watch",
+                        "params": {
+                            "path": path,
+                            "watch_id": id,
+                            "event": event
+                        }
+                    }));
+                }
+                let mut buffer = buffer.lock().unwrap();
+                if buffer.events.len() >= MAX_BUFFERED {
+                    buffer.events.pop_front();
+                    buffer.dropped += 1;
+                }
+                buffer.events.push_back(event);
+                buffer.total += 1;
+                drop(buffer);
+                arrived.notify_one();
+            })?
+        };
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        watcher
+            .watch(Path::new(path), mode)
+            .with_context(|| format!("Failed to watch {}", path))?;
+
+        let watch = Watch {
+            path: path.to_string(),
+            recursive,
+            started: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            buffer,
+            arrived,
+            _watcher: watcher,
+        };
+        let result = json!({
+            "id": id,
+            "path": watch.path,
+            "recursive": watch.recursive,
+            "started": watch.started,
+        });
+        self.watches.lock().unwrap().insert(id, Arc::new(watch));
+        Ok(result)
+    }
+
+    /// Take the events buffered for watch `id`, waiting up to `wait` for the first
+    /// one when there are none yet
+    pub async fn poll(&self, id: &str, wait: Duration) -> Result<Value> {
+        let watch = self.get(id)?;
+        let deadline = tokio::time::Instant::now() + wait.min(MAX_WAIT);
+        // A wake-up can be left over from events an earlier poll already took
+        while watch.buffer.lock().unwrap().events.is_empty() && tokio::time::Instant::now() < deadline {
+            let _ = tokio::time::timeout_at(deadline, watch.arrived.notified()).await;
+        }
+
+        let (events, dropped, total) = watch.drain();
+        Ok(json!({
+            "id": id,
+            "path": watch.path,
+            "event_count": events.len(),
+            "events": events,
+            "dropped": dropped,
+            "total_events": total,
+        }))
+    }
+
+    /// End watch `id`, returning the events no poll has taken
+    pub fn stop(&self, id: &str) -> Result<Value> {
+        let watch = self.watches.lock().unwrap().remove(id).with_context(|| self.unknown(id))?;
+        let (events, dropped, total) = watch.drain();
+        Ok(json!({
+            "id": id,
+            "stopped": true,
+            "path": watch.path,
+            "event_count": events.len(),
+            "events": events,
+            "dropped": dropped,
+            "total_events": total,
+        }))
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Watch>> {
+        let watch = self.watches.lock().unwrap().get(id).cloned();
+        watch.with_context(|| self.unknown(id))
+    }
+
+    fn unknown(&self, id: &str) -> String {
+        let watches = self.watches.lock().unwrap();
+        let mut running: Vec<String> = watches.iter().map(|(id, watch)| format!("{} ({})", id, watch.path)).collect();
+        running.sort();
+        if running.is_empty() {
+            format!("No watch with id {}; none is running (start one with fs_watch_start)", id)
+        } else {
+            format!("No watch with id {}; running: {}", id, running.join(", "))
+        }
+    }
+}
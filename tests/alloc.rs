@@ -0,0 +1,114 @@
+//! Allocations and peak heap of a large `git_diff`, sent to the client through
+//! `call_output` (serialized once) and through `call` (a `Value` tree).
+//!
+//! cargo test --release --test alloc -- --ignored --nocapture
+#![cfg(feature = "git")]
+
+use poly_mcp::{GitModule, ToolModule};
+use serde_json::json;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            let current = if new_size >= layout.size() {
+                CURRENT.fetch_add(new_size - layout.size(), Ordering::Relaxed) + new_size - layout.size()
+            } else {
+                CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed) - (layout.size() - new_size)
+            };
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// Every line changes, so the diff has twice as many lines
+const LINES: usize = 100_000;
+
+fn git(repo: &Path, args: &[&str]) {
+    let status = Command::new("git").arg("-C").arg(repo).args(args).status().unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+/// A repository whose working tree changes every line of a LINES-line file
+fn large_diff_repo() -> std::path::PathBuf {
+    let repo = std::env::temp_dir().join(format!("poly-mcp-alloc-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&repo);
+    std::fs::create_dir_all(&repo).unwrap();
+    git(&repo, &["init", "-q"]);
+
+    let file = repo.join("data.txt");
+    let lines = |tag: &str| (0..LINES).map(|i| format!("{} line {}\n", tag, i)).collect::<String>();
+    std::fs::write(&file, lines("old")).unwrap();
+    git(&repo, &["add", "data.txt"]);
+    git(&repo, &["-c", "user.name=bench", "-c", "user.email=bench@localhost", "commit", "-qm", "old"]);
+    std::fs::write(&file, lines("new")).unwrap();
+    repo
+}
+
+/// Allocations made and peak bytes above the starting heap while `f` runs
+async fn measure<F: std::future::Future<Output = String>>(f: F) -> (usize, usize, usize) {
+    let start = CURRENT.load(Ordering::Relaxed);
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    PEAK.store(start, Ordering::Relaxed);
+    let text = f.await;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let peak = PEAK.load(Ordering::Relaxed) - start;
+    (allocations, peak, text.len())
+}
+
+#[tokio::test(flavor = "current_thread")]
+#[ignore = "builds a 200,000-line diff; run with --ignored --nocapture"]
+async fn git_diff_allocations() {
+    let repo = large_diff_repo();
+    let args = json!({ "path": repo });
+    let git = GitModule::new();
+
+    let (output_allocs, output_peak, output_len) = measure(async {
+        git.call_output("git_diff", args.clone()).await.unwrap().into_text()
+    })
+    .await;
+    let (value_allocs, value_peak, value_len) = measure(async {
+        git.call("git_diff", args.clone()).await.unwrap().to_string()
+    })
+    .await;
+    std::fs::remove_dir_all(&repo).unwrap();
+
+    let mb = |bytes: usize| bytes as f64 / 1_000_000.0;
+    println!("{}-line git_diff, {:.1} MB of JSON", LINES * 2, mb(output_len));
+    println!("call_output: {:>10} allocations, peak {:>7.1} MB", output_allocs, mb(output_peak));
+    println!("call:        {:>10} allocations, peak {:>7.1} MB", value_allocs, mb(value_peak));
+
+    assert_eq!(output_len, value_len);
+    assert!(output_allocs < value_allocs);
+    assert!(output_peak < value_peak);
+}